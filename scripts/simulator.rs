@@ -0,0 +1,592 @@
+//! 不需要 Mattermost 伺服器的手動測試工具
+//!
+//! 啟動一個假的 Mattermost API（bot 設定的 `mattermost.url` 指向這裡），並代替 Mattermost
+//! 對執行中的 bot 送出 slash command、按鈕 action 與 dialog submission。bot 開啟的對話框、
+//! 發出的貼文與臨時訊息都會記錄下來，下一步直接點擊貼文上的按鈕或送出對話框。
+//!
+//! ```bash
+//! cargo run --bin simulator -- group-buy
+//! cargo run --bin simulator -- slash /sticker 貓
+//! ```
+
+use anyhow::{Context, Result, bail};
+use clap::{Parser, Subcommand};
+use serde_json::{Value, json};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use warp::Filter;
+use warp::http::{Method, StatusCode};
+use warp::hyper::body::Bytes;
+
+/// 等待 bot 開啟對話框或發文的時間上限
+const WAIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// 假 Mattermost 上的 bot 帳號
+const BOT_USER_ID: &str = "simbot";
+
+/// 回報給 bot 的權限，讓 bot 啟用所有功能
+const PERMISSIONS: &[&str] = &[
+    "create_post",
+    "upload_file",
+    "edit_post",
+    "add_reaction",
+    "view_members",
+];
+
+#[derive(Parser)]
+#[command(name = "simulator", about = "模擬 Mattermost 對執行中的 bot 送出請求")]
+struct Args {
+    /// bot 的 HTTP 位址
+    #[arg(long, default_value = "http://127.0.0.1:3000")]
+    bot: String,
+
+    /// 假 Mattermost API 的監聽位址，bot 的 `mattermost.url` 需指向這裡
+    #[arg(long, default_value = "127.0.0.1:8065")]
+    listen: SocketAddr,
+
+    /// Slash command token（對應 `mattermost.slash_command_tokens`）
+    #[arg(long)]
+    token: Option<String>,
+
+    /// 模擬的使用者 ID
+    #[arg(long, default_value = "simuser")]
+    user_id: String,
+
+    /// 模擬的使用者名稱
+    #[arg(long, default_value = "simuser")]
+    user_name: String,
+
+    /// 模擬的頻道 ID
+    #[arg(long, default_value = "simchannel")]
+    channel_id: String,
+
+    /// 模擬的團隊 ID
+    #[arg(long, default_value = "simteam")]
+    team_id: String,
+
+    #[command(subcommand)]
+    command: SimCommand,
+}
+
+#[derive(Subcommand)]
+enum SimCommand {
+    /// 只啟動假 Mattermost API，搭配 curl 手動操作
+    Serve,
+    /// 送出 slash command，例如 `slash /sticker 貓`
+    Slash { command: String, text: Vec<String> },
+    /// 依序建立團購、編輯商品、登記、截止
+    GroupBuy {
+        /// 商家名稱
+        #[arg(long, default_value = "模擬商家")]
+        merchant: String,
+        /// 商品列表 (YAML 格式)
+        #[arg(long, default_value = "紅茶: 30\n綠茶: 35")]
+        items: String,
+    },
+}
+
+/// 假 Mattermost 收到的對話框與貼文
+#[derive(Default)]
+struct Recorder {
+    channel_id: String,
+    dialogs: Vec<Value>,
+    posts: Vec<Value>,
+    next_id: u64,
+}
+
+impl Recorder {
+    fn new_id(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{}{}", prefix, self.next_id)
+    }
+
+    fn add_post(&mut self, mut post: Value) -> Value {
+        let id = self.new_id("simpost");
+        let now = chrono::Utc::now().timestamp_millis();
+        post["id"] = json!(id);
+        if post["channel_id"].is_null() {
+            post["channel_id"] = json!(self.channel_id);
+        }
+        if post["user_id"].is_null() {
+            post["user_id"] = json!(BOT_USER_ID);
+        }
+        if post["props"].is_null() {
+            post["props"] = json!({});
+        }
+        post["root_id"] = json!(post["root_id"].as_str().unwrap_or_default());
+        post["metadata"] = json!({});
+        post["create_at"] = json!(now);
+        post["update_at"] = json!(now);
+        post["delete_at"] = json!(0);
+        print_post("📝 新貼文", &post);
+        self.posts.push(post.clone());
+        post
+    }
+
+    fn post_mut(&mut self, id: &str) -> Option<&mut Value> {
+        self.posts
+            .iter_mut()
+            .find(|p| p["id"] == id && p["delete_at"] == 0)
+    }
+
+    /// 以按鈕 action 或 PUT 的內容更新貼文，props 整個取代（與 Mattermost 相同）
+    fn update_post(&mut self, id: &str, update: &Value) -> Option<Value> {
+        let post = self.post_mut(id)?;
+        if let Some(message) = update.get("message") {
+            post["message"] = message.clone();
+        }
+        if let Some(props) = update.get("props") {
+            post["props"] = props.clone();
+        }
+        post["update_at"] = json!(chrono::Utc::now().timestamp_millis());
+        let post = post.clone();
+        print_post("✏️ 更新貼文", &post);
+        Some(post)
+    }
+}
+
+type Shared = Arc<Mutex<Recorder>>;
+
+fn print_post(label: &str, post: &Value) {
+    println!(
+        "{} {}\n{}",
+        label,
+        post["id"].as_str().unwrap_or_default(),
+        post["message"].as_str().unwrap_or_default()
+    );
+    let buttons: Vec<&str> = buttons(post)
+        .filter_map(|action| action["name"].as_str())
+        .collect();
+    if !buttons.is_empty() {
+        println!("按鈕：{}", buttons.join(" | "));
+    }
+}
+
+fn buttons(post: &Value) -> impl Iterator<Item = &Value> {
+    post["props"]["attachments"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|attachment| attachment["actions"].as_array().into_iter().flatten())
+}
+
+fn user(id: &str, username: &str) -> Value {
+    json!({
+        "id": id,
+        "username": username,
+        "first_name": "",
+        "last_name": "",
+        "roles": "system_user",
+    })
+}
+
+/// 假 Mattermost API：回應 bot 會呼叫的端點，其他請求一律回傳 `{}`
+fn fake_api(recorder: &Shared, method: &Method, path: &str, body: &Value) -> (StatusCode, Value) {
+    let mut recorder = recorder.lock().unwrap();
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    match (method.as_str(), segments.as_slice()) {
+        ("GET", ["api", "v4", "users", "me"]) => (StatusCode::OK, user(BOT_USER_ID, "leko-bot")),
+        ("GET", ["api", "v4", "users", id]) => (StatusCode::OK, user(id, id)),
+        ("POST", ["api", "v4", "roles", "names"]) => {
+            let roles: Vec<Value> = body
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|name| json!({ "name": name, "permissions": PERMISSIONS }))
+                .collect();
+            (StatusCode::OK, json!(roles))
+        }
+        ("POST", ["api", "v4", "channels", "direct"]) => {
+            let id = recorder.new_id("simdm");
+            (StatusCode::CREATED, json!({ "id": id, "type": "D" }))
+        }
+        ("GET", ["api", "v4", "channels", id, "posts"]) => {
+            let posts: Vec<&Value> = recorder
+                .posts
+                .iter()
+                .rev()
+                .filter(|p| p["channel_id"] == *id && p["delete_at"] == 0)
+                .collect();
+            let order: Vec<&Value> = posts.iter().map(|p| &p["id"]).collect();
+            let posts: serde_json::Map<String, Value> = posts
+                .iter()
+                .map(|p| {
+                    (
+                        p["id"].as_str().unwrap_or_default().to_string(),
+                        (*p).clone(),
+                    )
+                })
+                .collect();
+            (StatusCode::OK, json!({ "order": order, "posts": posts }))
+        }
+        ("GET", ["api", "v4", "channels", id]) => (
+            StatusCode::OK,
+            json!({ "id": id, "type": "O", "name": id, "display_name": "模擬頻道" }),
+        ),
+        ("POST", ["api", "v4", "posts", "ephemeral"]) => {
+            let post = body["post"].clone();
+            println!(
+                "💬 臨時訊息給 {}：{}",
+                body["user_id"].as_str().unwrap_or_default(),
+                post["message"].as_str().unwrap_or_default()
+            );
+            (StatusCode::CREATED, post)
+        }
+        ("POST", ["api", "v4", "posts"]) => (StatusCode::CREATED, recorder.add_post(body.clone())),
+        ("GET", ["api", "v4", "posts", id]) => match recorder.post_mut(id) {
+            Some(post) => (StatusCode::OK, post.clone()),
+            None => (StatusCode::NOT_FOUND, json!({ "message": "找不到貼文" })),
+        },
+        ("PUT", ["api", "v4", "posts", id]) => match recorder.update_post(id, body) {
+            Some(post) => (StatusCode::OK, post),
+            None => (StatusCode::NOT_FOUND, json!({ "message": "找不到貼文" })),
+        },
+        ("DELETE", ["api", "v4", "posts", id]) => match recorder.post_mut(id) {
+            Some(post) => {
+                post["delete_at"] = json!(chrono::Utc::now().timestamp_millis());
+                println!("🗑️ 刪除貼文 {}", id);
+                (StatusCode::OK, json!({ "status": "OK" }))
+            }
+            None => (StatusCode::NOT_FOUND, json!({ "message": "找不到貼文" })),
+        },
+        ("POST", ["api", "v4", "actions", "dialogs", "open"]) => {
+            let dialog = &body["dialog"];
+            let fields: Vec<&str> = dialog["elements"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|e| e["name"].as_str())
+                .collect();
+            println!(
+                "🗂️ 開啟對話框「{}」：{}",
+                dialog["title"].as_str().unwrap_or_default(),
+                fields.join(", ")
+            );
+            recorder.dialogs.push(body.clone());
+            (StatusCode::OK, json!({}))
+        }
+        // slash command 的 response_url
+        ("POST", ["hooks", _]) => {
+            if body["response_type"] == "in_channel" {
+                let mut props = body["props"].as_object().cloned().unwrap_or_default();
+                props.insert("attachments".to_string(), body["attachments"].clone());
+                props.insert("from_webhook".to_string(), json!("true"));
+                recorder.add_post(json!({
+                    "user_id": "",
+                    "message": body["text"],
+                    "props": props,
+                }));
+            } else {
+                println!(
+                    "💬 response_url 臨時訊息：{}",
+                    body["text"].as_str().unwrap_or_default()
+                );
+            }
+            (StatusCode::OK, json!({}))
+        }
+        _ => {
+            println!("（未模擬的 API：{} {}）", method, path);
+            (StatusCode::OK, json!({}))
+        }
+    }
+}
+
+async fn serve_fake_api(recorder: Shared, listen: SocketAddr) {
+    let api = warp::method()
+        .and(warp::path::full())
+        .and(warp::body::bytes())
+        .map(
+            move |method: Method, path: warp::path::FullPath, body: Bytes| {
+                let body = serde_json::from_slice(&body).unwrap_or(Value::Null);
+                let (status, reply) = fake_api(&recorder, &method, path.as_str(), &body);
+                warp::reply::with_status(warp::reply::json(&reply), status)
+            },
+        );
+    warp::serve(api).run(listen).await;
+}
+
+/// 依對話框欄位產生送出的值：優先使用 `overrides`，其次是欄位預設值，
+/// 選填欄位留空，必填欄位依類型填入看起來合理的值
+fn fill_dialog(dialog: &Value, user_id: &str, overrides: &[(&str, Value)]) -> Value {
+    let mut submission = serde_json::Map::new();
+    for element in dialog["dialog"]["elements"]
+        .as_array()
+        .into_iter()
+        .flatten()
+    {
+        let name = element["name"].as_str().unwrap_or_default();
+        let value = if let Some((_, value)) = overrides.iter().find(|(n, _)| *n == name) {
+            value.clone()
+        } else if let Some(default) = element["default"].as_str()
+            && !default.is_empty()
+        {
+            json!(default)
+        } else if element["optional"] == true {
+            continue;
+        } else {
+            match element["type"].as_str().unwrap_or_default() {
+                "bool" => json!(false),
+                "select" if element["data_source"] == "users" => json!(user_id),
+                "select" => element["options"][0]["value"].clone(),
+                _ if element["subtype"] == "number" => json!("1"),
+                _ => json!(format!(
+                    "模擬{}",
+                    element["display_name"].as_str().unwrap_or(name)
+                )),
+            }
+        };
+        submission.insert(name.to_string(), value);
+    }
+    Value::Object(submission)
+}
+
+struct Simulator {
+    args: Args,
+    http: reqwest::Client,
+    recorder: Shared,
+}
+
+impl Simulator {
+    fn trigger_id(&self) -> String {
+        format!("simtrigger{}", uuid::Uuid::new_v4().simple())
+    }
+
+    /// 送出 slash command（form 格式），回傳 bot 的 JSON 回應
+    async fn slash(&self, command: &str, text: &str) -> Result<Value> {
+        let path = command.trim_start_matches('/');
+        let response_url = format!(
+            "http://{}/hooks/{}",
+            self.args.listen,
+            uuid::Uuid::new_v4().simple()
+        );
+        let trigger_id = self.trigger_id();
+        let mut form = vec![
+            ("team_id", self.args.team_id.as_str()),
+            ("team_domain", "sim"),
+            ("channel_id", self.args.channel_id.as_str()),
+            ("channel_name", "sim"),
+            ("user_id", self.args.user_id.as_str()),
+            ("user_name", self.args.user_name.as_str()),
+            ("command", command),
+            ("text", text),
+            ("response_url", response_url.as_str()),
+            ("trigger_id", trigger_id.as_str()),
+        ];
+        if let Some(token) = &self.args.token {
+            form.push(("token", token.as_str()));
+        }
+
+        println!("➡️ {} {}", command, text);
+        let response = self
+            .http
+            .post(format!("{}/{}", self.args.bot.trim_end_matches('/'), path))
+            .form(&form)
+            .send()
+            .await
+            .context("送出 slash command 失敗")?;
+        let reply = read_reply(response).await?;
+        if let Some(text) = reply["text"].as_str()
+            && !text.is_empty()
+        {
+            println!("⬅️ {}", text);
+        }
+        Ok(reply)
+    }
+
+    /// 點擊貼文上的按鈕，回應中的 `update` 會套用到貼文
+    async fn click(&self, post_id: &str, label: &str) -> Result<Value> {
+        let action = {
+            let mut recorder = self.recorder.lock().unwrap();
+            let post = recorder
+                .post_mut(post_id)
+                .with_context(|| format!("找不到貼文 {}", post_id))?;
+            buttons(post)
+                .find(|action| action["name"] == label)
+                .cloned()
+                .with_context(|| format!("貼文 {} 上沒有「{}」按鈕", post_id, label))?
+        };
+        let url = action["integration"]["url"]
+            .as_str()
+            .context("按鈕沒有 integration URL")?;
+
+        println!("➡️ 點擊「{}」", label);
+        let response = self
+            .http
+            .post(url)
+            .json(&json!({
+                "user_id": self.args.user_id,
+                "user_name": self.args.user_name,
+                "channel_id": self.args.channel_id,
+                "team_id": self.args.team_id,
+                "team_domain": "sim",
+                "post_id": post_id,
+                "trigger_id": self.trigger_id(),
+                "type": "button",
+                "context": action["integration"]["context"],
+            }))
+            .send()
+            .await
+            .context("送出 action 失敗")?;
+        let reply = read_reply(response).await?;
+
+        if let Some(text) = reply["ephemeral_text"].as_str() {
+            println!("⬅️ {}", text);
+        }
+        if let Some(update) = reply.get("update") {
+            self.recorder.lock().unwrap().update_post(post_id, update);
+        }
+        Ok(reply)
+    }
+
+    /// 填寫並送出 bot 開啟的對話框，bot 回傳欄位錯誤時視為失敗
+    async fn submit(&self, dialog: &Value, overrides: &[(&str, Value)]) -> Result<Value> {
+        let submission = fill_dialog(dialog, &self.args.user_id, overrides);
+        let url = dialog["url"].as_str().context("對話框沒有 URL")?;
+
+        println!("➡️ 送出對話框：{}", submission);
+        let response = self
+            .http
+            .post(url)
+            .json(&json!({
+                "type": "dialog_submission",
+                "callback_id": dialog["dialog"]["callback_id"],
+                "state": dialog["dialog"]["state"],
+                "user_id": self.args.user_id,
+                "channel_id": self.args.channel_id,
+                "team_id": self.args.team_id,
+                "submission": submission,
+                "cancelled": false,
+            }))
+            .send()
+            .await
+            .context("送出對話框失敗")?;
+        let reply = read_reply(response).await?;
+
+        if !reply["error"].is_null() || !reply["errors"].is_null() {
+            bail!("bot 拒絕了對話框：{}", reply);
+        }
+        Ok(reply)
+    }
+
+    /// 等待 bot 開啟第 `count + 1` 個對話框
+    async fn wait_dialog(&self, count: usize) -> Result<Value> {
+        self.wait(|recorder| recorder.dialogs.get(count).cloned())
+            .await
+            .context("bot 沒有開啟對話框")
+    }
+
+    /// 等待第一則符合條件的貼文
+    async fn wait_post(&self, predicate: impl Fn(&Value) -> bool) -> Result<Value> {
+        self.wait(|recorder| recorder.posts.iter().find(|p| predicate(p)).cloned())
+            .await
+            .context("bot 沒有發出預期的貼文")
+    }
+
+    async fn wait<T>(&self, check: impl Fn(&Recorder) -> Option<T>) -> Option<T> {
+        let deadline = tokio::time::Instant::now() + WAIT_TIMEOUT;
+        loop {
+            if let Some(found) = check(&self.recorder.lock().unwrap()) {
+                return Some(found);
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return None;
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    fn dialog_count(&self) -> usize {
+        self.recorder.lock().unwrap().dialogs.len()
+    }
+
+    /// 點擊按鈕並送出它開啟的對話框
+    async fn click_and_submit(
+        &self,
+        post_id: &str,
+        label: &str,
+        overrides: &[(&str, Value)],
+    ) -> Result<()> {
+        let count = self.dialog_count();
+        self.click(post_id, label).await?;
+        let dialog = self.wait_dialog(count).await?;
+        self.submit(&dialog, overrides).await?;
+        Ok(())
+    }
+
+    async fn group_buy(&self, merchant: &str, items: &str) -> Result<()> {
+        let count = self.dialog_count();
+        self.slash("/group_buy", "").await?;
+        let dialog = self.wait_dialog(count).await?;
+        self.submit(
+            &dialog,
+            &[("merchant_name", json!(merchant)), ("draft", json!(false))],
+        )
+        .await?;
+
+        let post = self
+            .wait_post(|p| p["props"]["leko_group_buy"].is_object())
+            .await?;
+        let post_id = post["id"].as_str().unwrap_or_default().to_string();
+
+        self.click_and_submit(&post_id, "編輯商品", &[("items", json!(items))])
+            .await?;
+        self.click_and_submit(&post_id, "登記", &[]).await?;
+        self.click(&post_id, "截止").await?;
+
+        println!("✅ 團購流程完成");
+        Ok(())
+    }
+}
+
+/// 讀取 bot 的回應，非 2xx 視為失敗；空的 body 回傳 Null
+async fn read_reply(response: reqwest::Response) -> Result<Value> {
+    let status = response.status();
+    let text = response.text().await.unwrap_or_default();
+    if !status.is_success() {
+        bail!("bot 回應 {}：{}", status, text);
+    }
+    Ok(serde_json::from_str(&text).unwrap_or(Value::Null))
+}
+
+#[tokio::main]
+async fn main() {
+    if let Err(e) = run().await {
+        eprintln!("Error: {:#}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run() -> Result<()> {
+    let args = Args::parse();
+    let recorder: Shared = Arc::new(Mutex::new(Recorder {
+        channel_id: args.channel_id.clone(),
+        ..Default::default()
+    }));
+
+    println!("假 Mattermost API：http://{}", args.listen);
+    let server = tokio::spawn(serve_fake_api(recorder.clone(), args.listen));
+
+    let simulator = Simulator {
+        http: reqwest::Client::new(),
+        recorder,
+        args,
+    };
+    match &simulator.args.command {
+        SimCommand::Serve => {
+            server.await?;
+        }
+        SimCommand::Slash { command, text } => {
+            simulator.slash(command, &text.join(" ")).await?;
+            // 等 bot 經 response_url 回覆或開啟對話框
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        SimCommand::GroupBuy { merchant, items } => {
+            simulator.group_buy(merchant, items).await?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,146 @@
+//! Bot token 權限偵測
+//!
+//! 啟動時依 bot 帳號的角色查詢實際擁有的權限，缺少權限的功能會被停用，
+//! 結果同時顯示在 `/health`。
+
+use serde::Serialize;
+use std::collections::HashSet;
+use tracing::{info, warn};
+
+use crate::mattermost::MattermostClient;
+
+/// 頻道成員都會有的角色，發文等權限通常來自這裡而非 system 角色
+const MEMBER_ROLES: &[&str] = &["team_user", "channel_user"];
+
+/// Bot token 擁有的功能權限
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Capabilities {
+    /// 是否成功查詢到權限。查詢失敗時所有功能視為可用，避免誤停
+    pub probed: bool,
+    /// 發文（create_post）
+    pub post: bool,
+    /// 上傳檔案（upload_file）
+    pub upload: bool,
+    /// 釘選貼文。Mattermost 沒有獨立的釘選權限，以 edit_post 判斷
+    pub pin: bool,
+    /// 加上表情反應（add_reaction）
+    pub reactions: bool,
+    /// 讀取使用者資訊（view_members）
+    pub user_read: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            probed: false,
+            post: true,
+            upload: true,
+            pin: true,
+            reactions: true,
+            user_read: true,
+        }
+    }
+}
+
+impl Capabilities {
+    fn from_permissions(permissions: &HashSet<String>) -> Self {
+        let has = |p: &str| permissions.contains(p);
+        Self {
+            probed: true,
+            post: has("create_post"),
+            upload: has("upload_file"),
+            pin: has("edit_post"),
+            reactions: has("add_reaction"),
+            user_read: has("view_members"),
+        }
+    }
+
+    /// 查詢 bot 角色（`roles` 為 Mattermost 回傳的空白分隔字串）的權限
+    pub async fn probe(client: &MattermostClient, roles: &str) -> Self {
+        let mut names: Vec<&str> = roles.split_whitespace().collect();
+        names.extend_from_slice(MEMBER_ROLES);
+
+        match client.get_roles_by_names(&names).await {
+            Ok(roles) => {
+                let permissions: HashSet<String> =
+                    roles.into_iter().flat_map(|r| r.permissions).collect();
+                let capabilities = Self::from_permissions(&permissions);
+                if capabilities.missing().is_empty() {
+                    info!("Bot token 擁有所有需要的權限");
+                } else {
+                    warn!("Bot token 缺少權限: {:?}", capabilities.missing());
+                }
+                capabilities
+            }
+            Err(e) => {
+                warn!("無法查詢 bot 權限，假設全部可用: {}", e);
+                Self::default()
+            }
+        }
+    }
+
+    /// 缺少的權限名稱
+    pub fn missing(&self) -> Vec<&'static str> {
+        [
+            ("post", self.post),
+            ("upload", self.upload),
+            ("pin", self.pin),
+            ("reactions", self.reactions),
+            ("user_read", self.user_read),
+        ]
+        .into_iter()
+        .filter(|(_, ok)| !ok)
+        .map(|(name, _)| name)
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_probe_merges_role_permissions() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/v4/roles/names")
+            .match_body(mockito::Matcher::Json(serde_json::json!([
+                "system_user",
+                "team_user",
+                "channel_user"
+            ])))
+            .with_body(
+                r#"[
+                    {"name":"system_user","permissions":["view_members"]},
+                    {"name":"team_user","permissions":[]},
+                    {"name":"channel_user","permissions":["create_post","add_reaction"]}
+                ]"#,
+            )
+            .create_async()
+            .await;
+
+        let client = MattermostClient::new(server.url(), "test_token".to_string()).unwrap();
+        let capabilities = Capabilities::probe(&client, "system_user").await;
+        mock.assert_async().await;
+
+        assert!(capabilities.probed);
+        assert!(capabilities.post && capabilities.reactions && capabilities.user_read);
+        assert_eq!(capabilities.missing(), vec!["upload", "pin"]);
+    }
+
+    #[tokio::test]
+    async fn test_probe_failure_keeps_features_enabled() {
+        let mut server = mockito::Server::new_async().await;
+        let _mock = server
+            .mock("POST", "/api/v4/roles/names")
+            .with_status(500)
+            .create_async()
+            .await;
+
+        let client = MattermostClient::new(server.url(), "test_token".to_string()).unwrap();
+        let capabilities = Capabilities::probe(&client, "system_user").await;
+
+        assert!(!capabilities.probed);
+        assert!(capabilities.missing().is_empty());
+    }
+}
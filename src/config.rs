@@ -1,5 +1,6 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::env;
 use std::path::PathBuf;
 
@@ -11,12 +12,767 @@ pub struct Config {
     pub admin: Vec<String>,
     #[serde(default = "default_database_url")]
     pub database_url: String,
+    #[serde(default)]
+    pub database: DatabaseConfig,
+    #[serde(default)]
+    pub group_buy: GroupBuyConfig,
+    #[serde(default)]
+    pub error_reporting: ErrorReportingConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub features: FeaturesConfig,
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    #[serde(default)]
+    pub feeds: Vec<FeedConfig>,
+    #[serde(default)]
+    pub shared_store: SharedStoreConfig,
 }
 
 fn default_database_url() -> String {
     "sqlite::memory:".to_string()
 }
 
+/// SQLite 連線與 WAL 調校
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatabaseConfig {
+    /// 連接池最大連線數
+    #[serde(default = "default_max_connections")]
+    pub max_connections: u32,
+    /// 等待資料庫鎖的秒數
+    #[serde(default = "default_busy_timeout_secs")]
+    pub busy_timeout_secs: u64,
+    /// journal mode：delete、truncate、persist、memory、wal、off
+    #[serde(default = "default_journal_mode")]
+    pub journal_mode: String,
+    /// synchronous 等級：off、normal、full、extra
+    #[serde(default = "default_synchronous")]
+    pub synchronous: String,
+    /// 定期執行 WAL checkpoint 的間隔秒數，0 代表停用（僅在 WAL 模式下生效）
+    #[serde(default = "default_checkpoint_interval_secs")]
+    pub checkpoint_interval_secs: u64,
+    /// 定期執行完整性檢查與 VACUUM 的間隔小時數，0 代表停用。多實例部署時只由 leader 執行
+    #[serde(default)]
+    pub maintenance_interval_hours: u64,
+    /// 統計等大量讀取使用的唯讀資料庫（例如定期複製出的副本），未設定時以唯讀連線開啟主資料庫
+    #[serde(default)]
+    pub read_replica_url: Option<String>,
+    /// 唯讀連接池最大連線數
+    #[serde(default = "default_read_max_connections")]
+    pub read_max_connections: u32,
+}
+
+fn default_max_connections() -> u32 {
+    5
+}
+
+fn default_busy_timeout_secs() -> u64 {
+    5
+}
+
+fn default_journal_mode() -> String {
+    "wal".to_string()
+}
+
+fn default_synchronous() -> String {
+    "full".to_string()
+}
+
+fn default_checkpoint_interval_secs() -> u64 {
+    300
+}
+
+fn default_read_max_connections() -> u32 {
+    2
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: default_max_connections(),
+            busy_timeout_secs: default_busy_timeout_secs(),
+            journal_mode: default_journal_mode(),
+            synchronous: default_synchronous(),
+            checkpoint_interval_secs: default_checkpoint_interval_secs(),
+            maintenance_interval_hours: 0,
+            read_replica_url: None,
+            read_max_connections: default_read_max_connections(),
+        }
+    }
+}
+
+/// 團購相關設定
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupBuyConfig {
+    #[serde(default)]
+    pub buttons: GroupBuyButtonsConfig,
+    /// 團購貼文的登記名單格式
+    #[serde(default)]
+    pub layout: MessageLayout,
+    /// 貼文中最多列出幾筆登記，超過時截斷並提供「完整名單」按鈕，避免超過訊息長度上限
+    #[serde(default = "default_max_listed_orders")]
+    pub max_listed_orders: usize,
+    /// 每個團購最多的商品數
+    #[serde(default = "default_max_items")]
+    pub max_items: usize,
+    /// 編輯商品對話框每個文字框的字數上限，列表較長時會拆成多個文字框
+    #[serde(default = "default_items_textarea_length")]
+    pub items_textarea_length: usize,
+    /// bot 對團購的公開回覆（編輯商品、自動截止等）發在討論串或頻道
+    #[serde(default)]
+    pub replies: ReplyMode,
+    /// 孤兒團購（貼文已被刪除、長期沒有登記）的偵測
+    #[serde(default)]
+    pub orphans: OrphanCheckConfig,
+    /// 商品名稱以 `|` 附上的翻譯依序對應的語系（Mattermost 的使用者語系，例如 `en`、`ja`）。
+    /// `珍珠奶茶|Bubble tea: 50` 在預設設定下，英文介面的使用者會看到 Bubble tea
+    #[serde(default = "default_item_locales")]
+    pub item_locales: Vec<String>,
+    /// 個人應付金額（小計、服務費）的進位方式
+    #[serde(default)]
+    pub rounding: RoundingConfig,
+    /// 頻道管理員可以管理頻道中任何人建立的團購（截止、調整缺貨、復原登記等）
+    #[serde(default)]
+    pub channel_admins_can_manage: bool,
+    /// 採購列表、個人小計與封存摘要的日期與數字格式（例如 `zh-TW`、`en-US`、`de`），
+    /// 未設定時為年/月/日、不加千分位
+    #[serde(default)]
+    pub locale: Option<String>,
+}
+
+/// Mattermost 對話框 textarea 允許的最大長度
+pub const MATTERMOST_TEXTAREA_MAX_LENGTH: usize = 3000;
+
+fn default_max_listed_orders() -> usize {
+    30
+}
+
+fn default_max_items() -> usize {
+    200
+}
+
+fn default_items_textarea_length() -> usize {
+    MATTERMOST_TEXTAREA_MAX_LENGTH
+}
+
+fn default_item_locales() -> Vec<String> {
+    vec!["en".to_string()]
+}
+
+impl GroupBuyConfig {
+    /// 公開回覆的 root_id：回覆發在討論串時為團購貼文，否則不指定
+    pub fn reply_root_id(&self, post_id: Option<&str>) -> Option<String> {
+        match self.replies {
+            ReplyMode::Thread => post_id.map(str::to_string),
+            ReplyMode::Channel => None,
+        }
+    }
+
+    /// 使用者語系對應的商品翻譯位置。`en` 也符合 `en-US`；沒有對應時顯示原本的商品名稱
+    pub fn item_locale_index(&self, locale: Option<&str>) -> Option<usize> {
+        let locale = locale?.to_ascii_lowercase().replace('_', "-");
+        self.item_locales.iter().position(|candidate| {
+            let candidate = candidate.to_ascii_lowercase().replace('_', "-");
+            locale == candidate
+                || locale
+                    .strip_prefix(&candidate)
+                    .is_some_and(|rest| rest.starts_with('-'))
+        })
+    }
+
+    /// 設定的語系對應的日期與數字格式
+    pub fn locale_format(&self) -> crate::locale::LocaleFormat {
+        self.locale
+            .as_deref()
+            .and_then(crate::locale::LocaleFormat::for_locale)
+            .unwrap_or_default()
+    }
+
+    fn validate(&self) -> Result<()> {
+        self.buttons.validate()?;
+        if self.max_items == 0 {
+            anyhow::bail!("group_buy.max_items 必須大於 0");
+        }
+        if !(1..=MATTERMOST_TEXTAREA_MAX_LENGTH).contains(&self.items_textarea_length) {
+            anyhow::bail!(
+                "group_buy.items_textarea_length 必須介於 1 到 {}（Mattermost 的上限）",
+                MATTERMOST_TEXTAREA_MAX_LENGTH
+            );
+        }
+        if self.rounding.decimal_places > MAX_ROUNDING_DECIMAL_PLACES {
+            anyhow::bail!(
+                "group_buy.rounding.decimal_places 最多 {}",
+                MAX_ROUNDING_DECIMAL_PLACES
+            );
+        }
+        if let Some(locale) = &self.locale
+            && crate::locale::LocaleFormat::for_locale(locale).is_none()
+        {
+            anyhow::bail!(
+                "不支援的 group_buy.locale「{}」，可用的語系: {}",
+                locale,
+                crate::locale::SUPPORTED_LOCALES.join(", ")
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Default for GroupBuyConfig {
+    fn default() -> Self {
+        Self {
+            buttons: GroupBuyButtonsConfig::default(),
+            layout: MessageLayout::default(),
+            max_listed_orders: default_max_listed_orders(),
+            max_items: default_max_items(),
+            items_textarea_length: default_items_textarea_length(),
+            replies: ReplyMode::default(),
+            orphans: OrphanCheckConfig::default(),
+            item_locales: default_item_locales(),
+            rounding: RoundingConfig::default(),
+            channel_admins_can_manage: false,
+            locale: None,
+        }
+    }
+}
+
+/// 金額進位方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundingMode {
+    /// 四捨六入五成雙（銀行家捨入）
+    #[default]
+    Bankers,
+    /// 四捨五入
+    HalfUp,
+    /// 無條件進位
+    Ceil,
+    /// 無條件捨去
+    Floor,
+}
+
+/// `group_buy.rounding.decimal_places` 的上限
+pub const MAX_ROUNDING_DECIMAL_PLACES: u32 = 4;
+
+/// 個人應付金額的進位設定，例如只收整數現金時設為 `{ mode: ceil, decimal_places: 0 }`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoundingConfig {
+    #[serde(default)]
+    pub mode: RoundingMode,
+    /// 保留的小數位數
+    #[serde(default = "default_rounding_decimal_places")]
+    pub decimal_places: u32,
+}
+
+fn default_rounding_decimal_places() -> u32 {
+    2
+}
+
+impl Default for RoundingConfig {
+    fn default() -> Self {
+        Self {
+            mode: RoundingMode::default(),
+            decimal_places: default_rounding_decimal_places(),
+        }
+    }
+}
+
+/// 團購貼文的登記名單格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageLayout {
+    /// 列出每位購買人
+    #[default]
+    Full,
+    /// 只顯示各商品的總數與人數，適合手機閱讀大量登記
+    Compact,
+}
+
+/// bot 對團購的公開回覆發在哪裡
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplyMode {
+    /// 回覆在團購貼文的討論串中（沒有貼文時發在頻道）
+    #[default]
+    Thread,
+    /// 直接發在頻道
+    Channel,
+}
+
+/// 孤兒團購偵測設定
+///
+/// ```yaml
+/// group_buy:
+///   orphans:
+///     check_interval_secs: 3600
+///     idle_days: 7
+///     action: archive
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrphanCheckConfig {
+    /// 檢查間隔秒數，0 代表停用
+    #[serde(default = "default_orphan_check_interval_secs")]
+    pub check_interval_secs: u64,
+    /// 進行中超過幾天仍沒有任何登記視為孤兒，0 代表不檢查
+    #[serde(default = "default_orphan_idle_days")]
+    pub idle_days: i64,
+    /// 發現孤兒團購時的處理方式
+    #[serde(default)]
+    pub action: OrphanAction,
+}
+
+fn default_orphan_check_interval_secs() -> u64 {
+    3600
+}
+
+fn default_orphan_idle_days() -> i64 {
+    7
+}
+
+impl Default for OrphanCheckConfig {
+    fn default() -> Self {
+        Self {
+            check_interval_secs: default_orphan_check_interval_secs(),
+            idle_days: default_orphan_idle_days(),
+            action: OrphanAction::default(),
+        }
+    }
+}
+
+/// 發現孤兒團購時的處理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrphanAction {
+    /// 私訊提醒建立者（每個團購只提醒一次）
+    #[default]
+    Notify,
+    /// 直接截止團購並私訊告知建立者
+    Archive,
+}
+
+/// 日誌設定。filter 使用 `RUST_LOG` 的語法，可以分別指定各模組的等級
+///
+/// ```yaml
+/// logging:
+///   level: info
+///   file:
+///     directory: logs
+///     rotation: daily
+///     max_files: 7
+///     level: info,leko_mattermost_bot::mattermost=debug
+///   routes:
+///     /health: off
+///     /api/v1/group_buy: full
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggingConfig {
+    /// 標準輸出的 filter，有設定 `RUST_LOG` 時以環境變數為準
+    #[serde(default = "default_log_level")]
+    pub level: String,
+    /// 另外寫入輪替的日誌檔
+    #[serde(default)]
+    pub file: Option<LogFileConfig>,
+    /// 各路由的日誌詳細程度，以路徑前綴比對（最長者優先），未列出的路由為 `summary`
+    #[serde(default)]
+    pub routes: BTreeMap<String, RouteLogLevel>,
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: default_log_level(),
+            file: None,
+            routes: BTreeMap::new(),
+        }
+    }
+}
+
+/// 路由的日誌詳細程度
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RouteLogLevel {
+    /// 不記錄 access log 與請求內容
+    Off,
+    /// 只記錄 access log
+    #[default]
+    Summary,
+    /// 另外記錄請求內容（token、response_url、trigger_id 等欄位會被遮蔽）
+    Full,
+}
+
+/// 日誌檔設定
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogFileConfig {
+    /// 日誌檔目錄
+    #[serde(default = "default_log_directory")]
+    pub directory: PathBuf,
+    /// 檔名前綴，輪替時加上日期，例如 `bot.log.2026-10-16`
+    #[serde(default = "default_log_file_prefix")]
+    pub prefix: String,
+    /// 輪替週期
+    #[serde(default)]
+    pub rotation: LogRotation,
+    /// 保留的日誌檔數量，0 代表全部保留
+    #[serde(default = "default_log_max_files")]
+    pub max_files: usize,
+    /// 日誌檔的 filter，與標準輸出分開設定
+    #[serde(default = "default_log_level")]
+    pub level: String,
+}
+
+fn default_log_directory() -> PathBuf {
+    PathBuf::from("logs")
+}
+
+fn default_log_file_prefix() -> String {
+    "bot.log".to_string()
+}
+
+fn default_log_max_files() -> usize {
+    7
+}
+
+impl Default for LogFileConfig {
+    fn default() -> Self {
+        Self {
+            directory: default_log_directory(),
+            prefix: default_log_file_prefix(),
+            rotation: LogRotation::default(),
+            max_files: default_log_max_files(),
+            level: default_log_level(),
+        }
+    }
+}
+
+/// 日誌檔輪替週期
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Minutely,
+    Hourly,
+    #[default]
+    Daily,
+    /// 不輪替，一直寫入同一個檔案
+    Never,
+}
+
+impl LoggingConfig {
+    fn validate(&self) -> Result<()> {
+        tracing_subscriber::EnvFilter::try_new(&self.level)
+            .with_context(|| format!("logging.level 格式錯誤: {}", self.level))?;
+        if let Some(file) = &self.file {
+            tracing_subscriber::EnvFilter::try_new(&file.level)
+                .with_context(|| format!("logging.file.level 格式錯誤: {}", file.level))?;
+        }
+        Ok(())
+    }
+}
+
+/// 錯誤回報設定
+///
+/// ```yaml
+/// error_reporting:
+///   notify_admins_on_panic: true
+///   sentry_dsn: https://<key>@o0.ingest.sentry.io/<project_id>
+///   webhook_url: https://example.com/hooks/errors
+///   environment: production
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ErrorReportingConfig {
+    /// handler panic 時私訊管理員（附上 backtrace 片段）
+    #[serde(default)]
+    pub notify_admins_on_panic: bool,
+    /// ERROR 日誌送往的 Sentry 專案（可 reload）
+    #[serde(default)]
+    pub sentry_dsn: Option<String>,
+    /// ERROR 日誌以 JSON POST 到此網址（可 reload）
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+    /// 回報時附上的環境名稱，例如 production、staging
+    #[serde(default)]
+    pub environment: Option<String>,
+}
+
+/// 一次性按鈕（發送貼圖、重試）已使用的 nonce 存放的位置
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SharedStoreBackend {
+    /// 資料庫的 `used_nonces`，共用同一個資料庫檔案的實例彼此可見
+    #[default]
+    Database,
+    /// 只存在本機記憶體，適合單一實例
+    Memory,
+}
+
+/// 多實例共用的狀態存放位置（啟動時讀取，變更需重新啟動）
+///
+/// ```yaml
+/// shared_store:
+///   backend: database
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SharedStoreConfig {
+    #[serde(default)]
+    pub backend: SharedStoreBackend,
+}
+
+/// 把外部系統（CI、監控）送到 `POST /webhook/<name>` 的 JSON 依範本轉成貼文，重新載入配置後生效
+///
+/// ```yaml
+/// webhooks:
+///   - name: ci
+///     channel_id: abc123
+///     token: random-secret
+///     template: "**{{repository.name}}** 建置{{status}}：{{build.url}}"
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    /// 網址中的名稱
+    pub name: String,
+    /// 發文的頻道
+    pub channel_id: String,
+    /// 呼叫端以 `Authorization: Bearer <token>` header 或 `?token=` 帶上
+    pub token: String,
+    /// 貼文範本，`{{a.b.0}}` 代入 JSON 中對應的欄位
+    pub template: String,
+    /// 覆蓋貼文顯示的名稱（選填）
+    #[serde(default)]
+    pub username: Option<String>,
+    /// 覆蓋貼文顯示的頭像（選填）
+    #[serde(default)]
+    pub icon_url: Option<String>,
+}
+
+fn default_feed_interval_minutes() -> u64 {
+    30
+}
+
+fn default_feed_template() -> String {
+    "📰 **{{feed_title}}**：[{{title}}]({{link}})".to_string()
+}
+
+/// 定期讀取 RSS／Atom feed，把新的項目依範本貼到頻道，重新載入配置後生效
+///
+/// ```yaml
+/// feeds:
+///   - name: rust-blog
+///     url: https://blog.rust-lang.org/feed.xml
+///     channel_id: abc123
+///     interval_minutes: 60
+///     template: "📰 [{{title}}]({{link}})"
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeedConfig {
+    /// 識別 feed 的名稱，記錄已貼過的項目時使用，變更後會重新從目前的項目開始
+    pub name: String,
+    pub url: String,
+    /// 發文的頻道
+    pub channel_id: String,
+    /// 讀取間隔（分鐘）
+    #[serde(default = "default_feed_interval_minutes")]
+    pub interval_minutes: u64,
+    /// 貼文範本，可用 `{{feed}}`、`{{feed_title}}`、`{{title}}`、`{{link}}`、`{{summary}}`、
+    /// `{{published}}` 與 `{{id}}`
+    #[serde(default = "default_feed_template")]
+    pub template: String,
+    /// 請求時附加的 HTTP header（例如需要驗證的 feed）
+    #[serde(default)]
+    pub headers: std::collections::HashMap<String, String>,
+}
+
+/// 各子系統的開關，預設全部啟用。重新載入配置後立即生效：
+/// 停用功能的路由回覆 `FEATURE_DISABLED`，對應的背景工作暫停
+///
+/// ```yaml
+/// features:
+///   group_buy: false
+///   websocket: true
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeaturesConfig {
+    /// `/sticker`、`/leko sticker` 與貼圖面板的按鈕
+    #[serde(default = "default_feature_enabled")]
+    pub stickers: bool,
+    /// 團購的指令、按鈕、對話框，以及自動截止、孤兒團購檢查等背景工作
+    #[serde(default = "default_feature_enabled")]
+    pub group_buy: bool,
+    /// 管理員私訊 bot 下達的管理指令
+    #[serde(default = "default_feature_enabled")]
+    pub dm_admin: bool,
+    /// WebSocket 連線；停用時不會收到私訊，管理員私訊指令也無法使用
+    #[serde(default = "default_feature_enabled")]
+    pub websocket: bool,
+    /// 給外部應用程式使用的管理 API（`/api/v1/admin`）
+    #[serde(default = "default_feature_enabled")]
+    pub apps: bool,
+    /// 轉發外部系統的 webhook（`/webhook/<name>`）
+    #[serde(default = "default_feature_enabled")]
+    pub webhooks: bool,
+    /// 定期讀取 `feeds` 並把新項目貼到頻道
+    #[serde(default = "default_feature_enabled")]
+    pub feeds: bool,
+}
+
+fn default_feature_enabled() -> bool {
+    true
+}
+
+impl Default for FeaturesConfig {
+    fn default() -> Self {
+        Self {
+            stickers: true,
+            group_buy: true,
+            dm_admin: true,
+            websocket: true,
+            apps: true,
+            webhooks: true,
+            feeds: true,
+        }
+    }
+}
+
+/// 可以在 `features` 停用的子系統
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    Stickers,
+    GroupBuy,
+    DmAdmin,
+    Websocket,
+    Apps,
+    Webhooks,
+    Feeds,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 7] = [
+        Feature::Stickers,
+        Feature::GroupBuy,
+        Feature::DmAdmin,
+        Feature::Websocket,
+        Feature::Apps,
+        Feature::Webhooks,
+        Feature::Feeds,
+    ];
+
+    /// `features` 中的設定名稱
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::Stickers => "stickers",
+            Feature::GroupBuy => "group_buy",
+            Feature::DmAdmin => "dm_admin",
+            Feature::Websocket => "websocket",
+            Feature::Apps => "apps",
+            Feature::Webhooks => "webhooks",
+            Feature::Feeds => "feeds",
+        }
+    }
+
+    /// 回覆使用者時的名稱
+    pub fn label(self) -> &'static str {
+        match self {
+            Feature::Stickers => "貼圖",
+            Feature::GroupBuy => "團購",
+            Feature::DmAdmin => "管理員私訊指令",
+            Feature::Websocket => "WebSocket",
+            Feature::Apps => "管理 API",
+            Feature::Webhooks => "Webhook 轉發",
+            Feature::Feeds => "RSS 訂閱",
+        }
+    }
+}
+
+impl FeaturesConfig {
+    pub fn is_enabled(&self, feature: Feature) -> bool {
+        match feature {
+            Feature::Stickers => self.stickers,
+            Feature::GroupBuy => self.group_buy,
+            Feature::DmAdmin => self.dm_admin,
+            Feature::Websocket => self.websocket,
+            Feature::Apps => self.apps,
+            Feature::Webhooks => self.webhooks,
+            Feature::Feeds => self.feeds,
+        }
+    }
+}
+
+/// 團購按鈕的 action 名稱
+pub const GROUP_BUY_BUTTON_ACTIONS: &[&str] = &[
+    "edit_items",
+    "publish",
+    "register",
+    "cancel_register",
+    "close",
+    "reopen",
+    "mark_ordered",
+    "archive",
+    "adjust_shortage",
+    "adjustment_history",
+    "payments",
+    "shopping_list",
+    "subtotal",
+    "my_registrations",
+    "my_subtotal",
+];
+
+/// 各狀態要顯示的按鈕（action 名稱，依列出的順序排列）。未設定的狀態使用預設按鈕。
+///
+/// ```yaml
+/// group_buy:
+///   buttons:
+///     closed: [adjust_shortage, mark_ordered, shopping_list, subtotal]
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GroupBuyButtonsConfig {
+    #[serde(default)]
+    pub draft: Option<Vec<String>>,
+    #[serde(default)]
+    pub active: Option<Vec<String>>,
+    #[serde(default)]
+    pub closed: Option<Vec<String>>,
+    #[serde(default)]
+    pub ordered: Option<Vec<String>>,
+}
+
+impl GroupBuyButtonsConfig {
+    /// 依狀態名稱（`GroupBuyStatus` 的字串形式）取得設定
+    pub fn for_status(&self, status: &str) -> Option<&[String]> {
+        match status {
+            "draft" => self.draft.as_deref(),
+            "active" => self.active.as_deref(),
+            "closed" => self.closed.as_deref(),
+            "ordered" => self.ordered.as_deref(),
+            _ => None,
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (status, buttons) in [
+            ("draft", &self.draft),
+            ("active", &self.active),
+            ("closed", &self.closed),
+            ("ordered", &self.ordered),
+        ] {
+            for action in buttons.iter().flatten() {
+                if !GROUP_BUY_BUTTON_ACTIONS.contains(&action.as_str()) {
+                    anyhow::bail!("group_buy.buttons.{} 含有未知的按鈕: {}", status, action);
+                }
+            }
+        }
+        // 草稿沒有「發布」就永遠無法公開
+        if let Some(draft) = &self.draft
+            && !draft.iter().any(|a| a == "publish")
+        {
+            anyhow::bail!("group_buy.buttons.draft 必須包含 publish");
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MattermostConfig {
     pub url: String,
@@ -25,6 +781,39 @@ pub struct MattermostConfig {
     pub slash_command_tokens: SlashCommandTokens,
     #[serde(default)]
     pub bot_callback_url: Option<String>, // Bot 服務器的公開 URL，用於 dialog callback
+    /// 未設定 bot_callback_url 時，從 Mattermost 請求的這個 header 取得 bot 的對外位址
+    /// （例如 `Host` 或反向代理的 `X-Forwarded-Host`），需搭配 callback_allowlist
+    #[serde(default)]
+    pub callback_host_header: Option<String>,
+    /// 簽署 dialog state 用的金鑰，未設定時使用 bot_token
+    #[serde(default)]
+    pub signing_secret: Option<String>,
+    /// 允許呼叫 callback 端點（/action、dialog）的來源 IP 或 CIDR，通常是 Mattermost 伺服器的位址。
+    /// 留空時不限制來源。
+    #[serde(default)]
+    pub callback_allowlist: Vec<String>,
+    /// 除錯用：記錄完整的 Mattermost API 請求與回應（token 會被遮蔽）
+    #[serde(default)]
+    pub trace_api: bool,
+    /// DM `selftest` 指令用來發文、編輯與刪除的沙盒頻道 ID，bot 必須是頻道成員
+    #[serde(default)]
+    pub sandbox_channel_id: Option<String>,
+}
+
+impl MattermostConfig {
+    /// 解析 callback 允許清單，單一 IP 視為 /32（IPv6 為 /128）
+    pub fn callback_networks(&self) -> Result<Vec<ipnet::IpNet>> {
+        self.callback_allowlist
+            .iter()
+            .map(|entry| {
+                let entry = entry.trim();
+                entry
+                    .parse::<ipnet::IpNet>()
+                    .or_else(|_| entry.parse::<std::net::IpAddr>().map(ipnet::IpNet::from))
+                    .with_context(|| format!("無效的 callback_allowlist 項目: {}", entry))
+            })
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -37,18 +826,90 @@ pub struct SlashCommandTokens {
     pub stickers: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct StickersConfig {
     pub categories: Vec<CategoryConfig>,
+    /// 貼圖選擇器超過幾分鐘沒有操作就自動刪除，0 代表不清除
+    #[serde(default = "default_picker_cleanup_minutes")]
+    pub picker_cleanup_minutes: u64,
+    /// 以臨時訊息顯示貼圖選擇器，只有發送時才公開貼出貼圖
+    #[serde(default)]
+    pub ephemeral_picker: bool,
+    /// 發送時下載貼圖圖片並上傳成 Mattermost 檔案，原圖床失效後貼圖仍看得到
+    #[serde(default)]
+    pub send_as_file: bool,
+    /// 下載過的貼圖圖片存放的目錄，以檔案發送時優先使用，未設定時每次重新下載
+    #[serde(default)]
+    pub image_cache_dir: Option<String>,
+    /// 啟動時預先下載最常使用的幾張貼圖到 image_cache_dir，0 代表不預先下載
+    #[serde(default)]
+    pub prewarm_count: usize,
+    /// 每隔幾分鐘重新讀取貼圖來源（HTTP 來源的新貼圖不必手動 reload），0 代表不重新讀取
+    #[serde(default)]
+    pub refresh_interval_minutes: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+fn default_picker_cleanup_minutes() -> u64 {
+    30
+}
+
+impl Default for StickersConfig {
+    fn default() -> Self {
+        Self {
+            categories: Vec::new(),
+            picker_cleanup_minutes: default_picker_cleanup_minutes(),
+            ephemeral_picker: false,
+            send_as_file: false,
+            image_cache_dir: None,
+            prewarm_count: 0,
+            refresh_interval_minutes: 0,
+        }
+    }
+}
+
+impl StickersConfig {
+    /// 是否有從 HTTP 取得的貼圖來源
+    pub fn has_http_sources(&self) -> bool {
+        self.categories.iter().any(|c| {
+            c.sources
+                .iter()
+                .any(|s| matches!(s, SourceConfig::HttpGet { .. }))
+        })
+    }
+
+    /// 檢查貼圖來源設定
+    fn validate(&self) -> Result<()> {
+        for category in &self.categories {
+            for source in &category.sources {
+                if let SourceConfig::Line { pack, .. } = source
+                    && crate::sticker::line::parse_pack_id(pack).is_none()
+                {
+                    anyhow::bail!(
+                        "分類「{}」的 LINE 貼圖包無效: {}（需要 LINE STORE 網址或貼圖包 ID）",
+                        category.name,
+                        pack
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CategoryConfig {
     pub name: String,
+    #[serde(default)]
     pub sources: Vec<SourceConfig>,
+    /// 分類別名（例如改名前的舊名稱），搜尋時視同本分類
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// 上層分類（例如 海綿寶寶 → 卡通），搜尋上層分類時會包含本分類
+    #[serde(default)]
+    pub parent: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum SourceConfig {
     File {
@@ -61,9 +922,16 @@ pub enum SourceConfig {
         #[serde(default)]
         headers: std::collections::HashMap<String, String>,
     },
+    /// LINE 貼圖包，`pack` 為 LINE STORE 網址或貼圖包 ID
+    Line {
+        pack: String,
+        /// 貼圖名稱的前綴，預設為貼圖包的標題
+        #[serde(default)]
+        name: Option<String>,
+    },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum FileFormat {
     Csv,
@@ -79,9 +947,64 @@ impl Config {
         let config: Config = serde_yaml::from_str(&content)
             .with_context(|| format!("無法解析配置檔案: {}", path.display()))?;
 
+        config.mattermost.callback_networks()?;
+        config.group_buy.validate()?;
+        config.stickers.validate()?;
+        config.validate_webhooks()?;
+        config.validate_feeds()?;
+        config.error_reporting.validate()?;
+        config.logging.validate()?;
+
         Ok(config)
     }
 
+    /// 檢查 webhook 設定：名稱只能有英數字、`-` 與 `_` 且不重複，token 不可為空
+    fn validate_webhooks(&self) -> Result<()> {
+        let mut names = std::collections::HashSet::new();
+        for webhook in &self.webhooks {
+            if webhook.name.is_empty()
+                || !webhook
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                anyhow::bail!("webhooks 的名稱只能包含英數字、- 與 _: {:?}", webhook.name);
+            }
+            if !names.insert(webhook.name.as_str()) {
+                anyhow::bail!("webhooks 的名稱重複: {}", webhook.name);
+            }
+            if webhook.token.trim().is_empty() {
+                anyhow::bail!("webhook {} 必須設定 token", webhook.name);
+            }
+        }
+        Ok(())
+    }
+
+    /// 檢查 feed 設定：名稱不可為空且不重複，網址必須是 HTTP(S)，讀取間隔至少 1 分鐘
+    fn validate_feeds(&self) -> Result<()> {
+        let mut names = std::collections::HashSet::new();
+        for feed in &self.feeds {
+            if feed.name.trim().is_empty() {
+                anyhow::bail!("feeds 的名稱不可為空");
+            }
+            if !names.insert(feed.name.as_str()) {
+                anyhow::bail!("feeds 的名稱重複: {}", feed.name);
+            }
+            if !feed.url.starts_with("http://") && !feed.url.starts_with("https://") {
+                anyhow::bail!("feed {} 的網址必須是 http(s): {}", feed.name, feed.url);
+            }
+            if feed.interval_minutes == 0 {
+                anyhow::bail!("feed {} 的 interval_minutes 必須大於 0", feed.name);
+            }
+        }
+        Ok(())
+    }
+
+    /// 依名稱取得 webhook 設定
+    pub fn webhook(&self, name: &str) -> Option<&WebhookConfig> {
+        self.webhooks.iter().find(|w| w.name == name)
+    }
+
     /// 從命令列參數、環境變數或預設位置載入配置
     pub fn load(config_path: Option<PathBuf>) -> Result<Self> {
         let path = config_path
@@ -112,6 +1035,87 @@ mod tests {
     use std::fs;
     use tempfile::TempDir;
 
+    #[test]
+    fn test_group_buy_buttons_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("buttons_config.yaml");
+
+        let base = r#"
+mattermost:
+  url: https://example.com
+  bot_token: test_token
+stickers:
+  categories: []
+"#;
+        fs::write(
+            &config_path,
+            format!(
+                "{}group_buy:\n  buttons:\n    closed: [adjust_shortage, subtotal]\n",
+                base
+            ),
+        )
+        .unwrap();
+        let config = Config::from_path(&config_path).unwrap();
+        assert_eq!(
+            config.group_buy.buttons.for_status("closed"),
+            Some(&["adjust_shortage".to_string(), "subtotal".to_string()][..])
+        );
+        assert_eq!(config.group_buy.buttons.for_status("active"), None);
+        assert_eq!(config.group_buy.layout, MessageLayout::Full);
+        assert_eq!(config.group_buy.max_listed_orders, 30);
+        assert_eq!(config.group_buy.max_items, 200);
+        assert_eq!(config.group_buy.items_textarea_length, 3000);
+        assert_eq!(config.group_buy.replies, ReplyMode::Thread);
+        assert_eq!(
+            config.group_buy.reply_root_id(Some("post-1")).as_deref(),
+            Some("post-1")
+        );
+        let channel_replies = GroupBuyConfig {
+            replies: ReplyMode::Channel,
+            ..Default::default()
+        };
+        assert_eq!(channel_replies.reply_root_id(Some("post-1")), None);
+
+        let locales = GroupBuyConfig {
+            item_locales: vec!["en".to_string(), "ja".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(locales.item_locale_index(Some("en")), Some(0));
+        assert_eq!(locales.item_locale_index(Some("en_US")), Some(0));
+        assert_eq!(locales.item_locale_index(Some("ja")), Some(1));
+        assert_eq!(locales.item_locale_index(Some("zh-TW")), None);
+        assert_eq!(locales.item_locale_index(Some("eng")), None);
+        assert_eq!(locales.item_locale_index(None), None);
+        assert_eq!(config.group_buy.orphans.check_interval_secs, 3600);
+        assert_eq!(config.group_buy.orphans.idle_days, 7);
+        assert_eq!(config.group_buy.orphans.action, OrphanAction::Notify);
+        assert_eq!(config.stickers.picker_cleanup_minutes, 30);
+        assert!(!config.stickers.ephemeral_picker);
+        assert!(config.features.is_enabled(Feature::GroupBuy));
+        assert!(config.features.is_enabled(Feature::Apps));
+
+        fs::write(
+            &config_path,
+            format!("{}group_buy:\n  buttons:\n    closed: [refund]\n", base),
+        )
+        .unwrap();
+        assert!(Config::from_path(&config_path).is_err());
+
+        fs::write(
+            &config_path,
+            format!("{}group_buy:\n  buttons:\n    draft: [edit_items]\n", base),
+        )
+        .unwrap();
+        assert!(Config::from_path(&config_path).is_err());
+
+        fs::write(
+            &config_path,
+            format!("{}group_buy:\n  items_textarea_length: 5000\n", base),
+        )
+        .unwrap();
+        assert!(Config::from_path(&config_path).is_err());
+    }
+
     #[test]
     fn test_load_config_from_file() {
         let temp_dir = TempDir::new().unwrap();
@@ -153,6 +1157,255 @@ admin:
         assert!(!config.is_admin("otherid", "otheruser"));
     }
 
+    #[test]
+    fn test_load_config_with_category_aliases_and_parent() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("alias_config.yaml");
+
+        let yaml_content = r#"
+mattermost:
+  url: https://example.com
+  bot_token: test_token
+stickers:
+  categories:
+    - name: 卡通
+    - name: 海綿寶寶
+      parent: 卡通
+      aliases:
+        - SpongeBob
+      sources:
+        - type: file
+          format: csv
+          path: data/sb.csv
+"#;
+
+        fs::write(&config_path, yaml_content).unwrap();
+
+        let config = Config::from_path(&config_path).unwrap();
+        let categories = &config.stickers.categories;
+
+        assert_eq!(categories.len(), 2);
+        assert!(categories[0].sources.is_empty());
+        assert!(categories[0].parent.is_none());
+        assert_eq!(categories[1].parent.as_deref(), Some("卡通"));
+        assert_eq!(categories[1].aliases, vec!["SpongeBob".to_string()]);
+    }
+
+    #[test]
+    fn test_load_config_with_line_source() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("line_config.yaml");
+
+        let yaml = |pack: &str| {
+            format!(
+                r#"
+mattermost:
+  url: https://example.com
+  bot_token: test_token
+stickers:
+  categories:
+    - name: 熊大
+      sources:
+        - type: line
+          pack: "{}"
+"#,
+                pack
+            )
+        };
+
+        fs::write(
+            &config_path,
+            yaml("https://store.line.me/stickershop/product/1234/zh-Hant"),
+        )
+        .unwrap();
+        let config = Config::from_path(&config_path).unwrap();
+        assert_eq!(
+            config.stickers.categories[0].sources,
+            vec![SourceConfig::Line {
+                pack: "https://store.line.me/stickershop/product/1234/zh-Hant".to_string(),
+                name: None,
+            }]
+        );
+        assert!(!config.stickers.has_http_sources());
+
+        fs::write(&config_path, yaml("https://store.line.me/home")).unwrap();
+        assert!(Config::from_path(&config_path).is_err());
+    }
+
+    #[test]
+    fn test_callback_allowlist_parsing() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("allowlist_config.yaml");
+
+        let yaml_content = r#"
+mattermost:
+  url: https://example.com
+  bot_token: test_token
+  callback_allowlist:
+    - 10.0.0.0/8
+    - 192.168.1.20
+    - "fd00::/64"
+stickers:
+  categories: []
+"#;
+
+        fs::write(&config_path, yaml_content).unwrap();
+
+        let config = Config::from_path(&config_path).unwrap();
+        let networks = config.mattermost.callback_networks().unwrap();
+        assert_eq!(networks.len(), 3);
+        assert_eq!(networks[1].to_string(), "192.168.1.20/32");
+
+        // 無效的項目在載入配置時就會報錯
+        fs::write(
+            &config_path,
+            yaml_content.replace("192.168.1.20", "not-an-ip"),
+        )
+        .unwrap();
+        assert!(Config::from_path(&config_path).is_err());
+    }
+
+    #[test]
+    fn test_database_config_defaults_and_overrides() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("db_config.yaml");
+
+        let yaml_content = r#"
+mattermost:
+  url: https://example.com
+  bot_token: test_token
+stickers:
+  categories: []
+database:
+  max_connections: 10
+  synchronous: normal
+  maintenance_interval_hours: 168
+"#;
+
+        fs::write(&config_path, yaml_content).unwrap();
+
+        let config = Config::from_path(&config_path).unwrap();
+        assert_eq!(config.database.max_connections, 10);
+        assert_eq!(config.database.synchronous, "normal");
+        // 未指定的欄位使用預設值
+        assert_eq!(config.database.journal_mode, "wal");
+        assert_eq!(config.database.busy_timeout_secs, 5);
+        assert_eq!(config.database.checkpoint_interval_secs, 300);
+        assert_eq!(config.database.maintenance_interval_hours, 168);
+    }
+
+    #[test]
+    fn test_features_config() {
+        let features: FeaturesConfig =
+            serde_yaml::from_str("group_buy: false\nwebsocket: false\n").unwrap();
+        assert!(!features.is_enabled(Feature::GroupBuy));
+        assert!(!features.is_enabled(Feature::Websocket));
+        // 未列出的功能維持啟用
+        assert!(features.is_enabled(Feature::Stickers));
+        assert!(features.is_enabled(Feature::DmAdmin));
+        assert!(features.is_enabled(Feature::Apps));
+        assert!(features.is_enabled(Feature::Webhooks));
+        assert!(features.is_enabled(Feature::Feeds));
+    }
+
+    #[test]
+    fn test_webhooks_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("webhooks_config.yaml");
+
+        let yaml = |name: &str, token: &str| {
+            format!(
+                r#"
+mattermost:
+  url: https://example.com
+  bot_token: test_token
+stickers:
+  categories: []
+webhooks:
+  - name: {}
+    channel_id: c1
+    token: "{}"
+    template: "{{{{status}}}}"
+"#,
+                name, token
+            )
+        };
+
+        fs::write(&config_path, yaml("ci-build", "secret")).unwrap();
+        let config = Config::from_path(&config_path).unwrap();
+        let webhook = config.webhook("ci-build").unwrap();
+        assert_eq!(webhook.channel_id, "c1");
+        assert_eq!(webhook.template, "{{status}}");
+        assert!(webhook.username.is_none());
+        assert!(config.webhook("other").is_none());
+
+        fs::write(&config_path, yaml("ci/build", "secret")).unwrap();
+        assert!(Config::from_path(&config_path).is_err());
+        fs::write(&config_path, yaml("ci", "")).unwrap();
+        assert!(Config::from_path(&config_path).is_err());
+    }
+
+    #[test]
+    fn test_shared_store_config() {
+        let store: SharedStoreConfig = serde_yaml::from_str("{}").unwrap();
+        assert_eq!(store.backend, SharedStoreBackend::Database);
+        let store: SharedStoreConfig = serde_yaml::from_str("backend: memory\n").unwrap();
+        assert_eq!(store.backend, SharedStoreBackend::Memory);
+        assert!(serde_yaml::from_str::<SharedStoreConfig>("backend: redis\n").is_err());
+    }
+
+    #[test]
+    fn test_feeds_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("feeds_config.yaml");
+
+        let yaml = |feeds: &str| {
+            format!(
+                r#"
+mattermost:
+  url: https://example.com
+  bot_token: test_token
+stickers:
+  categories: []
+feeds:
+{}
+"#,
+                feeds
+            )
+        };
+
+        fs::write(
+            &config_path,
+            yaml("  - name: blog\n    url: https://example.com/feed.xml\n    channel_id: c1"),
+        )
+        .unwrap();
+        let config = Config::from_path(&config_path).unwrap();
+        assert_eq!(config.feeds.len(), 1);
+        // 未指定的欄位使用預設值
+        assert_eq!(config.feeds[0].interval_minutes, 30);
+        assert!(config.feeds[0].template.contains("{{title}}"));
+        assert!(config.feeds[0].headers.is_empty());
+
+        fs::write(
+            &config_path,
+            yaml("  - name: blog\n    url: https://example.com/a\n    channel_id: c1\n  - name: blog\n    url: https://example.com/b\n    channel_id: c1"),
+        )
+        .unwrap();
+        assert!(Config::from_path(&config_path).is_err());
+        fs::write(
+            &config_path,
+            yaml("  - name: blog\n    url: ftp://example.com/feed\n    channel_id: c1"),
+        )
+        .unwrap();
+        assert!(Config::from_path(&config_path).is_err());
+        fs::write(
+            &config_path,
+            yaml("  - name: blog\n    url: https://example.com/feed\n    channel_id: c1\n    interval_minutes: 0"),
+        )
+        .unwrap();
+        assert!(Config::from_path(&config_path).is_err());
+    }
+
     #[test]
     fn test_load_config_with_env_var() {
         let temp_dir = TempDir::new().unwrap();
@@ -1,3 +1,5 @@
+use crate::config::DatabaseConfig;
+use crate::error_code::{CodedError, ErrorCode};
 use crate::sticker::Sticker;
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
@@ -5,15 +7,70 @@ use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use sqlx::Acquire;
 use sqlx::Row;
-use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
-use std::collections::HashMap;
+use sqlx::sqlite::{
+    SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions, SqliteSynchronous,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::str::FromStr;
-use tracing::info;
+use tracing::{debug, info, warn};
+
+/// `sqlite::memory:` 或 `mode=memory` 的連線字串
+fn is_memory_url(database_url: &str) -> bool {
+    database_url.contains(":memory:") || database_url.contains("mode=memory")
+}
+
+/// 演練模式（`--dry-run`）使用的資料庫：把正式資料庫連同尚未 checkpoint 的 WAL
+/// 複製到暫存目錄並回傳副本的連線字串，寫入不會影響正式資料。
+/// in-memory 資料庫原樣回傳；正式資料庫還不存在時回傳空的暫存資料庫。
+pub fn dry_run_database_url(database_url: &str) -> Result<String> {
+    if is_memory_url(database_url) {
+        return Ok(database_url.to_string());
+    }
+
+    let options = SqliteConnectOptions::from_str(database_url)?;
+    let source = options.get_filename();
+    let target = std::env::temp_dir().join(format!("leko-dry-run-{}.db", std::process::id()));
+    let with_suffix = |path: &std::path::Path, suffix: &str| {
+        let mut path = path.as_os_str().to_owned();
+        path.push(suffix);
+        std::path::PathBuf::from(path)
+    };
+
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(with_suffix(&target, suffix));
+    }
+    for suffix in ["", "-wal"] {
+        let from = with_suffix(source, suffix);
+        if from.exists() {
+            std::fs::copy(&from, with_suffix(&target, suffix))
+                .with_context(|| format!("複製資料庫 {} 失敗", from.display()))?;
+        }
+    }
+
+    Ok(format!("sqlite://{}", target.display()))
+}
+
+/// 連接池的使用狀況
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStatus {
+    /// 目前開啟的連線數
+    pub size: u32,
+    /// 其中閒置的連線數
+    pub idle: u32,
+    /// 連線數上限
+    pub max: u32,
+}
 
 /// 資料庫連接池
 #[derive(Clone, Debug)]
 pub struct Database {
     pool: SqlitePool,
+    /// 統計等大量讀取使用的唯讀連接池，避免與互動中的寫入搶連線
+    read_pool: SqlitePool,
+    /// 避免同時執行多個 VACUUM / integrity_check
+    maintenance: std::sync::Arc<tokio::sync::Mutex<()>>,
+    /// 查詢耗時記錄到的統計，由 `with_metrics` 設定
+    metrics: std::sync::Arc<crate::metrics::Metrics>,
 }
 
 // Embedded canonical schema at compile time. This guarantees the running
@@ -26,7 +83,8 @@ const EMBEDDED_SCHEMA: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "
 mod tests {
     use super::*;
     use crate::test_utils::utils::{
-        close_group_buy, create_and_insert_order, insert_group_buy, make_group_buy, setup_db,
+        close_group_buy, create_and_insert_order, insert_group_buy, make_group_buy, make_order_for,
+        setup_db,
     };
     use rust_decimal::Decimal;
     use uuid::Uuid;
@@ -54,18 +112,29 @@ mod tests {
         new_items.insert("banana".to_string(), Decimal::new(500, 2));
 
         // success with correct version
-        db.update_items(&gb.id, &new_items, 1, "u1", "u1")
+        let menu = ItemList {
+            items: new_items,
+            ..Default::default()
+        };
+        db.update_items(&gb.id, &menu, 1, "u1", "u1")
             .await
             .expect("update items");
         let fetched = db.get_group_buy(&gb.id).await.unwrap().unwrap();
         assert_eq!(fetched.version, 2);
 
         // conflict when using old version
-        let mut another = std::collections::HashMap::new();
-        another.insert("pear".to_string(), Decimal::new(300, 2));
+        let mut another = ItemList::default();
+        another
+            .items
+            .insert("pear".to_string(), Decimal::new(300, 2));
 
         let res = db.update_items(&gb.id, &another, 1, "u1", "u1").await;
-        assert!(res.is_err());
+        assert_eq!(ErrorCode::of(&res.unwrap_err()), ErrorCode::GbConflict);
+
+        // 截止後以最新版本更新也會失敗
+        close_group_buy(&db, &gb.id, 2).await;
+        let res = db.update_items(&gb.id, &another, 3, "u1", "u1").await;
+        assert_eq!(ErrorCode::of(&res.unwrap_err()), ErrorCode::GbClosed);
     }
 
     #[tokio::test]
@@ -90,6 +159,163 @@ mod tests {
         assert_eq!(fetched2.version, 2);
     }
 
+    #[test]
+    fn test_group_buy_status_transitions() {
+        use GroupBuyStatus::*;
+        assert!(Draft.can_transition_to(&Active));
+        assert!(Active.can_transition_to(&Closed));
+        assert!(Closed.can_transition_to(&Active));
+        assert!(Closed.can_transition_to(&Ordered));
+        assert!(!Draft.can_transition_to(&Closed));
+        assert!(!Active.can_transition_to(&Ordered));
+        assert!(!Ordered.can_transition_to(&Active));
+        assert!(!Ordered.can_transition_to(&Closed));
+        assert!(Closed.can_transition_to(&Archived));
+        assert!(Ordered.can_transition_to(&Archived));
+        assert!(!Active.can_transition_to(&Archived));
+        assert!(!Archived.can_transition_to(&Active));
+
+        assert!(Active.accepts_registrations());
+        assert!(!Ordered.accepts_registrations());
+        assert!(Ordered.accepts_adjustments());
+        assert!(!Draft.accepts_adjustments());
+        assert!(!Archived.accepts_adjustments());
+        assert!(!Archived.accepts_registrations());
+
+        for status in [Draft, Active, Closed, Ordered, Archived] {
+            assert_eq!(GroupBuyStatus::from_string(&status.to_string()), status);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_update_status_rejects_invalid_transition() {
+        let db = setup_db().await;
+        let gb = insert_group_buy(&db, 1).await;
+
+        // 進行中不能直接標記為已下單
+        let res = db
+            .update_status(&gb.id, GroupBuyStatus::Ordered, 1, "u1", "u1")
+            .await;
+        assert!(res.is_err());
+
+        close_group_buy(&db, &gb.id, 1).await;
+        db.update_status(&gb.id, GroupBuyStatus::Ordered, 2, "u1", "u1")
+            .await
+            .expect("closed -> ordered");
+        let fetched = db.get_group_buy(&gb.id).await.unwrap().unwrap();
+        assert_eq!(fetched.status, GroupBuyStatus::Ordered);
+
+        // 已下單後不能再登記
+        let order = crate::test_utils::utils::make_order_for(gb.id.clone(), "late", "late");
+        assert!(db.create_order(&order).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_migrate_group_buy_status_check_keeps_orders() {
+        let db = setup_db().await;
+        let gb = insert_group_buy(&db, 1).await;
+        let _order = create_and_insert_order(&db, &gb.id, "buyer1", "reg1", 2).await;
+
+        // 模擬舊版只允許 active / closed 的資料表
+        let mut conn = db.pool.acquire().await.unwrap();
+        for stmt in [
+            "PRAGMA foreign_keys = OFF",
+            "CREATE TABLE group_buys_old (
+                id TEXT PRIMARY KEY, creator_id TEXT NOT NULL, creator_username TEXT NOT NULL,
+                channel_id TEXT NOT NULL, post_id TEXT, merchant_name TEXT NOT NULL,
+                description TEXT, metadata TEXT, items TEXT NOT NULL, item_icons TEXT,
+                status TEXT NOT NULL CHECK(status IN ('active', 'closed')),
+                version INTEGER NOT NULL DEFAULT 1, created_at TEXT NOT NULL, updated_at TEXT NOT NULL
+            )",
+            "INSERT INTO group_buys_old SELECT id, creator_id, creator_username, channel_id, post_id,
+                merchant_name, description, metadata, items, item_icons, status, version,
+                created_at, updated_at FROM group_buys",
+            "DROP TABLE group_buys",
+            "ALTER TABLE group_buys_old RENAME TO group_buys",
+            "CREATE INDEX idx_group_buys_post_id ON group_buys(post_id)",
+            "PRAGMA foreign_keys = ON",
+            "PRAGMA user_version = 4",
+        ] {
+            sqlx::query(stmt).execute(&mut *conn).await.unwrap();
+        }
+        drop(conn);
+
+        db.run_migrations().await.expect("migrate");
+
+        let table_sql: String = sqlx::query_scalar(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'group_buys'",
+        )
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+        assert!(table_sql.contains("'ordered'"));
+        assert_eq!(db.get_orders_by_group_buy(&gb.id).await.unwrap().len(), 1);
+        assert!(db.get_group_buy(&gb.id).await.unwrap().is_some());
+
+        // 重建後索引仍在，不必等下次啟動
+        let indexes: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM sqlite_master
+             WHERE type = 'index' AND name = 'idx_group_buys_post_id' AND tbl_name = 'group_buys'",
+        )
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!(indexes, 1);
+    }
+
+    #[tokio::test]
+    async fn test_migrate_group_buy_archived_status() {
+        let db = setup_db().await;
+        let gb = insert_group_buy(&db, 1).await;
+        let _order = create_and_insert_order(&db, &gb.id, "buyer1", "reg1", 2).await;
+
+        // 模擬 v8 的資料表：status 還不允許 archived
+        let table_sql: String = sqlx::query_scalar(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'group_buys'",
+        )
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+        let columns = &table_sql[table_sql.find('(').unwrap()..];
+        let old_sql = format!(
+            "CREATE TABLE group_buys_old {}",
+            columns.replace(", 'archived'", "")
+        );
+        let mut conn = db.pool.acquire().await.unwrap();
+        for stmt in [
+            "PRAGMA foreign_keys = OFF",
+            old_sql.as_str(),
+            "INSERT INTO group_buys_old SELECT * FROM group_buys",
+            "DROP TABLE group_buys",
+            "ALTER TABLE group_buys_old RENAME TO group_buys",
+            "PRAGMA foreign_keys = ON",
+            "PRAGMA user_version = 8",
+        ] {
+            sqlx::query(stmt).execute(&mut *conn).await.unwrap();
+        }
+        drop(conn);
+
+        db.run_migrations().await.expect("migrate");
+
+        db.update_status(&gb.id, GroupBuyStatus::Closed, 1, "u1", "u1")
+            .await
+            .unwrap();
+        db.update_status(&gb.id, GroupBuyStatus::Archived, 2, "u1", "u1")
+            .await
+            .unwrap();
+        let fetched = db.get_group_buy(&gb.id).await.unwrap().unwrap();
+        assert_eq!(fetched.status, GroupBuyStatus::Archived);
+        assert_eq!(fetched.currency, gb.currency);
+        assert_eq!(db.get_orders_by_group_buy(&gb.id).await.unwrap().len(), 1);
+
+        // 封存後不能再回到其他狀態
+        assert!(
+            db.update_status(&gb.id, GroupBuyStatus::Active, 3, "u1", "u1")
+                .await
+                .is_err()
+        );
+    }
+
     #[tokio::test]
     async fn test_create_order_and_queries() {
         let db = setup_db().await;
@@ -129,14 +355,181 @@ mod tests {
             .delete_buyer_item_orders(&gb.id, "buyer1", "apple", "actor1", "actor1")
             .await
             .expect("delete buyer1 item");
-        assert!(rows >= 1);
+        assert!(rows.count >= 1);
 
         // delete all orders for buyer2
         let rows2 = db
-            .delete_orders_for_buyer(&gb.id, "buyer2", "actor2", "actor2")
+            .delete_orders_for_buyers(&gb.id, &["buyer2".to_string()], "actor2", "actor2")
             .await
             .expect("delete buyer2 all");
-        assert!(rows2 >= 1);
+        assert!(rows2.count >= 1);
+    }
+
+    #[tokio::test]
+    async fn test_delete_orders_for_several_buyers_shares_batch() {
+        let db = setup_db().await;
+        let gb = insert_group_buy(&db, 1).await;
+        let _o1 = create_and_insert_order(&db, &gb.id, "buyer1", "reg1", 2).await;
+        let _o2 = create_and_insert_order(&db, &gb.id, "buyer1", "reg1", 1).await;
+        let _o3 = create_and_insert_order(&db, &gb.id, "buyer2", "reg2", 1).await;
+        let _o4 = create_and_insert_order(&db, &gb.id, "buyer3", "reg3", 1).await;
+
+        let deleted = db
+            .delete_orders_for_buyers(
+                &gb.id,
+                &["buyer1".to_string(), "buyer2".to_string()],
+                "actor",
+                "actor",
+            )
+            .await
+            .unwrap();
+        assert_eq!(deleted.count, 3);
+        let remaining = db.get_orders_by_group_buy(&gb.id).await.unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].buyer_id, "buyer3");
+
+        // 同一批刪除可以一次復原
+        let restored = db
+            .restore_deleted_orders(
+                &gb.id,
+                &deleted.batch_id,
+                chrono::Duration::minutes(ORDER_RESTORE_GRACE_MINUTES),
+                "actor",
+                "actor",
+            )
+            .await
+            .unwrap();
+        assert_eq!(restored, 3);
+    }
+
+    #[tokio::test]
+    async fn test_delete_single_order_checks_buyer() {
+        let db = setup_db().await;
+        let gb = insert_group_buy(&db, 1).await;
+        let order = create_and_insert_order(&db, &gb.id, "buyer1", "reg1", 2).await;
+        let _other = create_and_insert_order(&db, &gb.id, "buyer1", "reg1", 1).await;
+
+        // 別人不能刪除這筆訂單
+        let deleted = db
+            .delete_order(&gb.id, &order.id, "buyer2", "buyer2")
+            .await
+            .unwrap();
+        assert_eq!(deleted.count, 0);
+
+        let deleted = db
+            .delete_order(&gb.id, &order.id, "buyer1", "buyer1")
+            .await
+            .unwrap();
+        assert_eq!(deleted.count, 1);
+        assert_eq!(
+            db.get_buyer_orders(&gb.id, "buyer1").await.unwrap().len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_order_fields_roundtrip() {
+        let db = setup_db().await;
+        let mut gb = make_group_buy(uuid::Uuid::new_v4().to_string(), 1);
+        gb.order_fields = vec![OrderField {
+            name: "內用/外帶".to_string(),
+            options: vec!["內用".to_string(), "外帶".to_string()],
+        }];
+        db.create_group_buy(&gb).await.expect("create gb");
+        let fetched = db.get_group_buy(&gb.id).await.unwrap().unwrap();
+        assert_eq!(fetched.order_fields, gb.order_fields);
+
+        let mut order = crate::test_utils::utils::make_order_for(gb.id.clone(), "buyer1", "buyer1");
+        order
+            .custom_fields
+            .insert("內用/外帶".to_string(), "外帶".to_string());
+        db.create_order(&order).await.expect("create order");
+        let orders = db.get_buyer_orders(&gb.id, "buyer1").await.unwrap();
+        assert_eq!(orders[0].custom_fields, order.custom_fields);
+    }
+
+    #[tokio::test]
+    async fn test_merge_order_quantity() {
+        let db = setup_db().await;
+        let gb = insert_group_buy(&db, 1).await;
+        let order = create_and_insert_order(&db, &gb.id, "buyer1", "reg1", 2).await;
+
+        db.merge_order_quantity(&gb.id, &order.id, 3, "reg2", "reg2")
+            .await
+            .unwrap();
+        let orders = db.get_buyer_orders(&gb.id, "buyer1").await.unwrap();
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].quantity, 5);
+
+        close_group_buy(&db, &gb.id, 1).await;
+        assert!(
+            db.merge_order_quantity(&gb.id, &order.id, 1, "reg2", "reg2")
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_restore_deleted_orders() {
+        let db = setup_db().await;
+        let gb = insert_group_buy(&db, 1).await;
+        let _o1 = create_and_insert_order(&db, &gb.id, "buyer1", "reg1", 2).await;
+        let _o2 = create_and_insert_order(&db, &gb.id, "buyer2", "reg2", 1).await;
+
+        let deleted = db
+            .delete_orders_for_buyers(&gb.id, &["buyer1".to_string()], "actor", "actor")
+            .await
+            .expect("soft delete");
+        assert_eq!(deleted.count, 1);
+
+        // 軟刪除的訂單不再出現在查詢結果中
+        let orders = db.get_orders_by_group_buy(&gb.id).await.unwrap();
+        assert_eq!(orders.len(), 1);
+        assert!(
+            db.get_buyer_orders(&gb.id, "buyer1")
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        // 超過期限不能復原
+        assert!(
+            db.restore_deleted_orders(
+                &gb.id,
+                &deleted.batch_id,
+                chrono::Duration::zero(),
+                "creator",
+                "creator"
+            )
+            .await
+            .is_err()
+        );
+
+        let restored = db
+            .restore_deleted_orders(
+                &gb.id,
+                &deleted.batch_id,
+                chrono::Duration::minutes(ORDER_RESTORE_GRACE_MINUTES),
+                "creator",
+                "creator",
+            )
+            .await
+            .expect("restore");
+        assert_eq!(restored, 1);
+        assert_eq!(db.get_orders_by_group_buy(&gb.id).await.unwrap().len(), 2);
+
+        // 同一批只能復原一次
+        assert!(
+            db.restore_deleted_orders(
+                &gb.id,
+                &deleted.batch_id,
+                chrono::Duration::minutes(ORDER_RESTORE_GRACE_MINUTES),
+                "creator",
+                "creator"
+            )
+            .await
+            .is_err()
+        );
     }
 
     #[tokio::test]
@@ -175,128 +568,2101 @@ mod tests {
                 .await
                 .expect("count adjustments");
         assert!(cnt >= 1);
+
+        let history = db.get_shortage_adjustments(&gb.id).await.unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].buyer_username, "alice");
+        assert_eq!((history[0].old_quantity, history[0].new_quantity), (3, 1));
+        assert_eq!(history[1].adjuster_username, "adj2");
     }
 
     #[tokio::test]
-    async fn test_sticker_bulk_insert_and_search() {
-        use crate::sticker::Sticker;
-
+    async fn test_adjust_order_quantity_fills_earliest_orders_first() {
         let db = setup_db().await;
+        let gb = insert_group_buy(&db, 1).await;
+        let first = create_and_insert_order(&db, &gb.id, "alice", "alice", 2).await;
+        let mut second = make_order_for(gb.id.clone(), "alice", "alice");
+        second.quantity = 3;
+        second.created_at = first.created_at + chrono::Duration::seconds(1);
+        db.create_order(&second).await.unwrap();
+        close_group_buy(&db, &gb.id, 1).await;
 
-        let stickers = vec![
-            Sticker {
-                name: "apple smile".to_string(),
-                image_url: "https://example.com/a1.png".to_string(),
-                category: "fruit".to_string(),
-            },
-            Sticker {
-                name: "banana happy".to_string(),
-                image_url: "https://example.com/b1.png".to_string(),
-                category: "fruit".to_string(),
-            },
-            Sticker {
-                name: "carrot".to_string(),
-                image_url: "https://example.com/c1.png".to_string(),
-                category: "veg".to_string(),
-            },
-        ];
+        let adjustments = HashMap::from([("alice".to_string(), 3)]);
+        let records = db
+            .adjust_order_quantity(&gb.id, "apple", &adjustments, "adj", "adj")
+            .await
+            .unwrap();
+        // 第一筆維持 2 份，只調整第二筆
+        assert_eq!(records.len(), 1);
+        let orders = db.get_buyer_orders(&gb.id, "alice").await.unwrap();
+        let quantity_of = |id: &str| orders.iter().find(|o| o.id == id).unwrap().quantity;
+        assert_eq!(quantity_of(&first.id), 2);
+        assert_eq!(quantity_of(&second.id), 1);
+    }
 
-        let inserted = db
-            .bulk_insert_stickers(&stickers)
+    #[tokio::test]
+    async fn test_migrate_stable_sticker_hashes_keeps_aliases() {
+        let db = setup_db().await;
+
+        // 模擬舊版資料：使用舊演算法產生的 hash，且尚未套用遷移
+        sqlx::query(
+            "INSERT INTO stickers (name, image_url, category, url_hash, created_at) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind("legacy")
+        .bind("https://example.com/legacy.png")
+        .bind("old")
+        .bind("deadbeef")
+        .bind(Utc::now().to_rfc3339())
+        .execute(&db.pool)
+        .await
+        .expect("insert legacy sticker");
+        sqlx::query("PRAGMA user_version = 0")
+            .execute(&db.pool)
             .await
-            .expect("bulk insert");
-        assert!(inserted >= 3);
+            .expect("reset user_version");
 
-        let cnt = db.count_stickers().await.expect("count");
-        assert!(cnt >= 3);
+        db.run_migrations().await.expect("migrate");
 
-        // search for "apple"
-        let res = db
-            .search_stickers(None, &vec!["apple".to_string()], &vec![], None, 10)
+        let new_hash = crate::sticker::url_hash("https://example.com/legacy.png");
+        let stored: String = sqlx::query_scalar("SELECT url_hash FROM stickers WHERE name = ?")
+            .bind("legacy")
+            .fetch_one(&db.pool)
             .await
-            .expect("search");
-        assert_eq!(res.len(), 1);
-        assert_eq!(res[0].name, "apple smile");
+            .expect("stored hash");
+        assert_eq!(stored, new_hash);
+
+        // 新舊 hash 都能找到同一張貼圖
+        let by_new = db.find_sticker_by_hash(&new_hash).await.unwrap();
+        assert_eq!(by_new.map(|s| s.name), Some("legacy".to_string()));
+        let by_old = db.find_sticker_by_hash("deadbeef").await.unwrap();
+        assert_eq!(by_old.map(|s| s.name), Some("legacy".to_string()));
+
+        // 遷移只會執行一次
+        let version: i64 = sqlx::query_scalar("PRAGMA user_version")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(version, 8);
     }
-}
 
-impl Database {
-    /// 初始化資料庫連接
-    pub async fn new(database_url: &str) -> Result<Self> {
-        // 解析 connection string
-        let options = SqliteConnectOptions::from_str(database_url)?
-            .create_if_missing(true)
-            .journal_mode(sqlx::sqlite::SqliteJournalMode::Wal)
-            .busy_timeout(std::time::Duration::from_secs(5))
-            .auto_vacuum(sqlx::sqlite::SqliteAutoVacuum::Full)
-            .foreign_keys(true);
+    #[tokio::test]
+    async fn test_log_details_are_json_and_queryable() {
+        let db = setup_db().await;
+        let gb = insert_group_buy(&db, 3).await;
 
-        // 建立連接池
-        let pool = SqlitePoolOptions::new()
-            .max_connections(5)
-            .connect_with(options)
+        // 非 JSON 的 details 會被包成物件
+        db.log_action(&gb.id, "u1", "user1", "note", Some("純文字備註"))
             .await
-            .with_context(|| format!("無法連接到資料庫: {}", database_url))?;
+            .unwrap();
+
+        let notes = db
+            .query_logs(&LogFilter {
+                group_buy_id: Some(gb.id.clone()),
+                action: Some("note".to_string()),
+                ..LogFilter::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(
+            notes[0].details,
+            serde_json::json!({ "message": "純文字備註" })
+        );
 
-        let db = Database { pool };
+        // 依 details 中的 version 查詢
+        let created = db
+            .query_logs(&LogFilter {
+                group_buy_id: Some(gb.id.clone()),
+                version: Some(3),
+                ..LogFilter::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(created.len(), 1);
+        assert_eq!(created[0].action, "create");
+        assert_eq!(created[0].details["merchant_name"], gb.merchant_name);
+    }
 
-        // 初始化資料表
-        db.init_schema().await?;
+    #[tokio::test]
+    async fn test_query_group_buys_and_orders() {
+        let db = setup_db().await;
+        let first = insert_group_buy(&db, 1).await;
+        let second = insert_group_buy(&db, 1).await;
+        close_group_buy(&db, &second.id, 1).await;
+        create_and_insert_order(&db, &first.id, "alice", "alice", 1).await;
+        create_and_insert_order(&db, &first.id, "bob", "alice", 2).await;
+        let cancelled = create_and_insert_order(&db, &second.id, "alice", "alice", 3).await;
+        db.delete_order(&second.id, &cancelled.id, "alice", "alice")
+            .await
+            .unwrap();
 
-        info!("資料庫初始化成功: {}", database_url);
+        let closed = db
+            .query_group_buys(&GroupBuyQuery {
+                status: Some("closed".to_string()),
+                ..GroupBuyQuery::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].id, second.id);
 
-        Ok(db)
+        // 分頁
+        let all = db
+            .query_group_buys(&GroupBuyQuery::default())
+            .await
+            .unwrap();
+        assert_eq!(all.len(), 2);
+        let page = db
+            .query_group_buys(&GroupBuyQuery {
+                limit: Some(1),
+                offset: Some(1),
+                ..GroupBuyQuery::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].id, all[1].id);
+
+        // 已取消的登記不列入
+        let alice = db
+            .query_orders(&OrderQuery {
+                buyer_id: Some("alice".to_string()),
+                ..OrderQuery::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(alice.len(), 1);
+        assert_eq!(alice[0].group_buy_id, first.id);
+        let registered = db
+            .query_orders(&OrderQuery {
+                group_buy_id: Some(first.id.clone()),
+                registrar_id: Some("alice".to_string()),
+                ..OrderQuery::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(registered.len(), 2);
+        assert!(
+            db.query_orders(&OrderQuery {
+                until: Some(Utc::now() - chrono::Duration::days(1)),
+                ..OrderQuery::default()
+            })
+            .await
+            .unwrap()
+            .is_empty()
+        );
     }
 
-    /// 建立資料表結構
-    async fn init_schema(&self) -> Result<()> {
-        // Prefer a single source-of-truth schema file when explicitly set via
-        // `DB_SCHEMA_FILE`. Otherwise use the embedded schema that is baked
-        // into the binary at compile time (see `EMBEDDED_SCHEMA`). This keeps
-        // runtime self-contained.
-        if let Ok(schema_path) = std::env::var("DB_SCHEMA_FILE") {
-            if let Ok(schema) = std::fs::read_to_string(&schema_path) {
-                for stmt in schema.split(';') {
-                    let s = stmt.trim();
-                    if s.is_empty() {
-                        continue;
-                    }
-                    sqlx::query(s).execute(&self.pool).await?;
-                }
-                info!("資料表結構初始化完成 (from {})", schema_path);
-                return Ok(());
-            } else {
-                info!(
-                    "DB_SCHEMA_FILE set but not readable: {}. Falling back to embedded schema",
-                    schema_path
-                );
-            }
-        }
-
-        // No external schema provided or readable — apply the embedded schema.
-        self.apply_embedded_schema().await?;
+    #[tokio::test]
+    async fn test_migrate_legacy_text_log_details() {
+        let db = setup_db().await;
+        let gb = insert_group_buy(&db, 1).await;
 
-        Ok(())
-    }
+        // 舊版直接寫入的純文字 details
+        sqlx::query(
+            "INSERT INTO group_buy_logs (group_buy_id, user_id, username, action, details, created_at)
+             VALUES (?, 'u1', 'user1', 'adjust_shortage', '調整 珍奶 的數量，影響 2 位用戶', ?)",
+        )
+        .bind(&gb.id)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&db.pool)
+        .await
+        .unwrap();
+        sqlx::query("PRAGMA user_version = 2")
+            .execute(&db.pool)
+            .await
+            .unwrap();
 
-    /// Apply the embedded schema (EMBEDDED_SCHEMA) to the database. This is
-    /// the fallback path and is also the recommended runtime behavior so the
-    /// binary does not require external SQL files.
-    async fn apply_embedded_schema(&self) -> Result<()> {
-        for stmt in EMBEDDED_SCHEMA.split(';') {
-            let s = stmt.trim();
-            if s.is_empty() {
-                continue;
-            }
-            sqlx::query(s).execute(&self.pool).await?;
-        }
-        info!("資料表結構初始化完成 (embedded)");
+        db.run_migrations().await.expect("migrate");
 
-        Ok(())
+        let logs = db
+            .query_logs(&LogFilter {
+                action: Some("adjust_shortage".to_string()),
+                ..LogFilter::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(
+            logs[0].details,
+            serde_json::json!({ "message": "調整 珍奶 的數量，影響 2 位用戶" })
+        );
+        let invalid: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM group_buy_logs WHERE json_valid(details) = 0")
+                .fetch_one(&db.pool)
+                .await
+                .unwrap();
+        assert_eq!(invalid, 0);
+    }
+
+    #[tokio::test]
+    async fn test_item_icons_roundtrip_and_legacy_column() {
+        let db = setup_db().await;
+
+        let mut gb = make_group_buy(Uuid::new_v4().to_string(), 1);
+        gb.item_icons.insert("apple".to_string(), "🍎".to_string());
+        db.create_group_buy(&gb).await.expect("create gb");
+
+        let fetched = db.get_group_buy(&gb.id).await.unwrap().unwrap();
+        assert_eq!(
+            fetched.item_icons.get("apple").map(String::as_str),
+            Some("🍎")
+        );
+
+        let items: HashMap<String, Decimal> = [("bubble tea".to_string(), Decimal::new(50, 0))]
+            .into_iter()
+            .collect();
+        let icons: HashMap<String, String> = [(
+            "bubble tea".to_string(),
+            "https://example.com/tea.png".to_string(),
+        )]
+        .into_iter()
+        .collect();
+        let sections: HashMap<String, String> = [("bubble tea".to_string(), "飲料".to_string())]
+            .into_iter()
+            .collect();
+        let translations: HashMap<String, Vec<String>> =
+            [("bubble tea".to_string(), vec!["珍珠奶茶".to_string()])]
+                .into_iter()
+                .collect();
+        let menu = ItemList {
+            items,
+            item_icons: icons,
+            item_sections: sections,
+            item_translations: translations,
+        };
+        db.update_items(&gb.id, &menu, 1, "u1", "u1")
+            .await
+            .expect("update items");
+        let fetched = db.get_group_buy(&gb.id).await.unwrap().unwrap();
+        assert_eq!(fetched.item_icons, menu.item_icons);
+        assert_eq!(fetched.item_sections, menu.item_sections);
+        assert_eq!(fetched.item_translations, menu.item_translations);
+
+        // 舊資料庫沒有 item_icons 欄位時補上，重複執行不會出錯
+        sqlx::query("CREATE TABLE legacy_items (id TEXT PRIMARY KEY)")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        db.add_column_if_missing("legacy_items", "item_icons", "TEXT")
+            .await
+            .expect("add column");
+        db.add_column_if_missing("legacy_items", "item_icons", "TEXT")
+            .await
+            .expect("add column twice");
+        let cols: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM pragma_table_info('legacy_items') WHERE name = 'item_icons'",
+        )
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!(cols, 1);
+    }
+
+    #[tokio::test]
+    async fn test_group_buy_deadlines() {
+        let db = setup_db().await;
+        let soon = make_group_buy(Uuid::new_v4().to_string(), 1);
+        let later = make_group_buy(Uuid::new_v4().to_string(), 1);
+        db.create_group_buy(&soon).await.unwrap();
+        db.create_group_buy(&later).await.unwrap();
+
+        let now = Utc::now();
+        db.set_deadline(&later.id, now + chrono::Duration::minutes(1))
+            .await
+            .unwrap();
+        db.set_deadline(&soon.id, now + chrono::Duration::minutes(30))
+            .await
+            .unwrap();
+        // 重新設定時覆蓋原本的時間
+        db.set_deadline(&soon.id, now - chrono::Duration::minutes(1))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            db.get_due_deadlines(now).await.unwrap(),
+            vec![soon.id.clone()]
+        );
+        assert_eq!(
+            db.get_due_deadlines(now + chrono::Duration::minutes(5))
+                .await
+                .unwrap(),
+            vec![soon.id.clone(), later.id.clone()]
+        );
+
+        db.clear_deadline(&soon.id).await.unwrap();
+        assert!(db.get_due_deadlines(now).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_group_buy_reminders() {
+        let db = setup_db().await;
+        let with_deadline = insert_group_buy(&db, 1).await;
+        let closed = insert_group_buy(&db, 1).await;
+
+        let now = Utc::now();
+        let close_at = now + chrono::Duration::minutes(30);
+        db.set_deadline(&with_deadline.id, close_at).await.unwrap();
+        db.set_reminder(&with_deadline.id, now - chrono::Duration::minutes(1))
+            .await
+            .unwrap();
+        db.set_reminder(&closed.id, now + chrono::Duration::minutes(5))
+            .await
+            .unwrap();
+
+        let due = db.get_due_reminders(now).await.unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].0, with_deadline.id);
+        assert_eq!(due[0].1.map(|t| t.timestamp()), Some(close_at.timestamp()));
+
+        // 沒有截止時間的提醒仍會列出，由呼叫端略過
+        let due = db
+            .get_due_reminders(now + chrono::Duration::minutes(10))
+            .await
+            .unwrap();
+        assert_eq!(due.len(), 2);
+        assert_eq!(due[1], (closed.id.clone(), None));
+
+        db.clear_reminder(&with_deadline.id).await.unwrap();
+        db.clear_reminder(&closed.id).await.unwrap();
+        assert!(
+            db.get_due_reminders(now + chrono::Duration::minutes(10))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_paid_buyers() {
+        let db = setup_db().await;
+        let group_buy = insert_group_buy(&db, 1).await;
+        assert!(db.get_paid_buyers(&group_buy.id).await.unwrap().is_empty());
+
+        db.set_paid_buyers(
+            &group_buy.id,
+            &["u1".to_string(), "u2".to_string()],
+            "creator",
+        )
+        .await
+        .unwrap();
+        let paid = db.get_paid_buyers(&group_buy.id).await.unwrap();
+        assert_eq!(paid.len(), 2);
+        assert!(paid.contains("u1") && paid.contains("u2"));
+
+        // 沒有列出的購買人改回未付款
+        db.set_paid_buyers(&group_buy.id, &["u2".to_string()], "creator")
+            .await
+            .unwrap();
+        let paid = db.get_paid_buyers(&group_buy.id).await.unwrap();
+        assert_eq!(paid, HashSet::from(["u2".to_string()]));
+
+        db.set_paid_buyers(&group_buy.id, &[], "creator")
+            .await
+            .unwrap();
+        assert!(db.get_paid_buyers(&group_buy.id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reassign_buyer_orders() {
+        let db = setup_db().await;
+        let group_buy = insert_group_buy(&db, 1).await;
+        create_and_insert_order(&db, &group_buy.id, "left", "left", 2).await;
+        create_and_insert_order(&db, &group_buy.id, "left", "bob", 1).await;
+        create_and_insert_order(&db, &group_buy.id, "carol", "carol", 3).await;
+        db.set_paid_buyers(&group_buy.id, &["left".to_string()], "creator")
+            .await
+            .unwrap();
+
+        let moved = db
+            .reassign_buyer_orders(&group_buy.id, "left", "dave", "dave", "creator", "creator")
+            .await
+            .unwrap();
+        assert_eq!(moved, 2);
+        let orders = db.get_orders_by_group_buy(&group_buy.id).await.unwrap();
+        assert!(orders.iter().all(|o| o.buyer_id != "left"));
+        assert_eq!(
+            orders
+                .iter()
+                .filter(|o| o.buyer_id == "dave" && o.buyer_username == "dave")
+                .count(),
+            2
+        );
+        // 付款狀態跟著移過去
+        assert_eq!(
+            db.get_paid_buyers(&group_buy.id).await.unwrap(),
+            HashSet::from(["dave".to_string()])
+        );
+
+        assert_eq!(
+            db.get_open_group_buy_ids_by_buyer("dave").await.unwrap(),
+            vec![group_buy.id.clone()]
+        );
+        assert!(
+            db.get_open_group_buy_ids_by_buyer("left")
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_deactivated_users() {
+        let db = setup_db().await;
+        assert!(db.set_user_deactivated("u1", true).await.unwrap());
+        assert!(!db.set_user_deactivated("u1", true).await.unwrap());
+        assert!(db.set_user_deactivated("u2", true).await.unwrap());
+        assert_eq!(db.get_deactivated_users().await.unwrap(), vec!["u1", "u2"]);
+
+        assert!(db.set_user_deactivated("u1", false).await.unwrap());
+        assert!(!db.set_user_deactivated("u1", false).await.unwrap());
+        assert_eq!(db.get_deactivated_users().await.unwrap(), vec!["u2"]);
+    }
+
+    #[tokio::test]
+    async fn test_cross_posts() {
+        let db = setup_db().await;
+        let group_buy = insert_group_buy(&db, 1).await;
+        let channels = ["c2".to_string(), "c3".to_string()];
+        db.add_cross_post_channels(&group_buy.id, &channels)
+            .await
+            .unwrap();
+        // 重複轉發到同一個頻道不會多一則
+        db.add_cross_post_channels(&group_buy.id, &channels[..1])
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get_cross_posts(&group_buy.id).await.unwrap(),
+            vec![("c2".to_string(), None), ("c3".to_string(), None)]
+        );
+
+        db.set_cross_post_id(&group_buy.id, "c2", "copy-1")
+            .await
+            .unwrap();
+        assert!(db.is_cross_post("copy-1").await.unwrap());
+        let found = db
+            .get_group_buy_by_post_id("copy-1")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.id, group_buy.id);
+
+        db.delete_cross_post("copy-1").await.unwrap();
+        assert!(!db.is_cross_post("copy-1").await.unwrap());
+        assert_eq!(
+            db.get_cross_posts(&group_buy.id).await.unwrap(),
+            vec![("c3".to_string(), None)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_buyer_spend_since() {
+        let db = setup_db().await;
+        let first = insert_group_buy(&db, 1).await;
+        let second = insert_group_buy(&db, 1).await;
+
+        let mut old = crate::test_utils::utils::make_order_for(first.id.clone(), "u1", "u1");
+        old.unit_price = Decimal::new(1000, 0);
+        old.created_at = Utc::now() - chrono::Duration::days(40);
+        db.create_order(&old).await.unwrap();
+        for (group_buy, price, quantity) in [(&first, 50, 2), (&second, 35, 1)] {
+            let mut order =
+                crate::test_utils::utils::make_order_for(group_buy.id.clone(), "u1", "u1");
+            order.unit_price = Decimal::new(price, 0);
+            order.quantity = quantity;
+            db.create_order(&order).await.unwrap();
+        }
+        let other = crate::test_utils::utils::make_order_for(first.id.clone(), "u2", "u2");
+        db.create_order(&other).await.unwrap();
+
+        let since = Utc::now() - chrono::Duration::days(30);
+        // 跨團購合計，不含期間外與其他人的訂單
+        assert_eq!(
+            db.get_buyer_spend_since("u1", since).await.unwrap(),
+            Decimal::new(135, 0)
+        );
+        assert_eq!(
+            db.get_buyer_spend_since("u3", since).await.unwrap(),
+            Decimal::ZERO
+        );
+    }
+
+    #[tokio::test]
+    async fn test_group_buy_templates() {
+        let db = setup_db().await;
+        let mut template = GroupBuyTemplate {
+            channel_id: "chan".to_string(),
+            name: "週五飲料".to_string(),
+            merchant_name: "五十嵐".to_string(),
+            description: Some("每週五下午".to_string()),
+            metadata: [("取貨".to_string(), "大廳".to_string())].into(),
+            items: [("紅茶".to_string(), Decimal::new(30, 0))].into(),
+            item_icons: [("紅茶".to_string(), "🍵".to_string())].into(),
+            ..Default::default()
+        };
+        db.save_group_buy_template(&template, "u1").await.unwrap();
+        assert_eq!(
+            db.get_group_buy_template("chan", "週五飲料").await.unwrap(),
+            Some(template.clone())
+        );
+        // 其他頻道看不到
+        assert_eq!(
+            db.get_group_buy_template("other", "週五飲料")
+                .await
+                .unwrap(),
+            None
+        );
+
+        // 同名時覆蓋
+        template.merchant_name = "清心".to_string();
+        template.description = None;
+        db.save_group_buy_template(&template, "u2").await.unwrap();
+        assert_eq!(
+            db.list_group_buy_templates("chan").await.unwrap(),
+            vec![("週五飲料".to_string(), "清心".to_string())]
+        );
+
+        assert!(
+            db.delete_group_buy_template("chan", "週五飲料")
+                .await
+                .unwrap()
+        );
+        assert!(
+            !db.delete_group_buy_template("chan", "週五飲料")
+                .await
+                .unwrap()
+        );
+        assert!(
+            db.list_group_buy_templates("chan")
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_group_buy_by_post_id() {
+        let db = setup_db().await;
+        let gb = insert_group_buy(&db, 1).await;
+        assert!(
+            db.get_group_buy_by_post_id("post-1")
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        db.update_post_id(&gb.id, "post-1").await.unwrap();
+        let found = db
+            .get_group_buy_by_post_id("post-1")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.id, gb.id);
+    }
+
+    #[tokio::test]
+    async fn test_orphan_candidates() {
+        let db = setup_db().await;
+        let idle = insert_group_buy(&db, 1).await;
+        let busy = insert_group_buy(&db, 1).await;
+        let closed = insert_group_buy(&db, 1).await;
+        close_group_buy(&db, &closed.id, 1).await;
+        create_and_insert_order(&db, &busy.id, "buyer", "buyer", 1).await;
+        db.log_action(&idle.id, "bot", "bot", "orphan_notice", None)
+            .await
+            .unwrap();
+
+        let candidates = db.get_orphan_candidates().await.unwrap();
+        assert_eq!(candidates.len(), 2);
+        let idle_candidate = candidates
+            .iter()
+            .find(|c| c.group_buy_id == idle.id)
+            .unwrap();
+        assert_eq!(idle_candidate.order_count, 0);
+        assert!(idle_candidate.notified);
+        let busy_candidate = candidates
+            .iter()
+            .find(|c| c.group_buy_id == busy.id)
+            .unwrap();
+        assert_eq!(busy_candidate.order_count, 1);
+        assert!(!busy_candidate.notified);
+    }
+
+    #[tokio::test]
+    async fn test_delete_group_buy_cascades() {
+        let db = setup_db().await;
+        let gb = insert_group_buy(&db, 1).await;
+        create_and_insert_order(&db, &gb.id, "buyer", "buyer", 1).await;
+        db.set_deadline(&gb.id, Utc::now()).await.unwrap();
+
+        assert!(db.delete_group_buy(&gb.id).await.unwrap());
+        assert!(db.get_group_buy(&gb.id).await.unwrap().is_none());
+        assert!(db.get_orders_by_group_buy(&gb.id).await.unwrap().is_empty());
+        assert!(db.get_due_deadlines(Utc::now()).await.unwrap().is_empty());
+        assert!(!db.delete_group_buy(&gb.id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_channel_settings_roundtrip() {
+        let db = setup_db().await;
+
+        let defaults = db.get_channel_settings("chan").await.unwrap();
+        assert_eq!(defaults.currency(), DEFAULT_CURRENCY);
+        assert!(defaults.can_create_group_buy("anyone"));
+
+        let settings = ChannelSettings {
+            channel_id: "chan".to_string(),
+            currency: Some("US$".to_string()),
+            quiet_hours: Some("22:00-08:00".to_string()),
+            default_deadline_minutes: Some(90),
+            allowed_creators: vec!["alice".to_string()],
+        };
+        db.save_channel_settings(&settings, "admin").await.unwrap();
+        let fetched = db.get_channel_settings("chan").await.unwrap();
+        assert_eq!(fetched, settings);
+        assert!(!fetched.can_create_group_buy("bob"));
+
+        // 再次儲存時覆蓋
+        let cleared = ChannelSettings {
+            channel_id: "chan".to_string(),
+            ..Default::default()
+        };
+        db.save_channel_settings(&cleared, "admin").await.unwrap();
+        assert_eq!(db.get_channel_settings("chan").await.unwrap(), cleared);
+    }
+
+    #[tokio::test]
+    async fn test_user_preferences() {
+        let db = setup_db().await;
+        assert_eq!(
+            db.get_user_preference("u1", "sticker_categories")
+                .await
+                .unwrap(),
+            None
+        );
+
+        db.set_user_preference("u1", "sticker_categories", "貓")
+            .await
+            .unwrap();
+        db.set_user_preference("u1", "sticker_categories", "貓,狗")
+            .await
+            .unwrap();
+        db.set_user_preference("u1", "favorite_item:50嵐", "紅茶")
+            .await
+            .unwrap();
+        db.set_user_preference("u2", "favorite_item:50嵐", "綠茶")
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get_user_preference("u1", "sticker_categories")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("貓,狗")
+        );
+
+        // 只刪除該使用者、符合前綴的設定
+        assert_eq!(
+            db.delete_user_preferences("u1", "favorite_item:")
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            db.list_user_preferences("u1").await.unwrap(),
+            vec![("sticker_categories".to_string(), "貓,狗".to_string())]
+        );
+        assert_eq!(db.list_user_preferences("u2").await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_recent_group_buys_by_merchant() {
+        let db = setup_db().await;
+
+        let mut ids = Vec::new();
+        for days_ago in [3, 1, 2] {
+            let mut gb = make_group_buy(Uuid::new_v4().to_string(), 1);
+            gb.created_at = Utc::now() - chrono::Duration::days(days_ago);
+            db.create_group_buy(&gb).await.unwrap();
+            ids.push(gb.id);
+        }
+        // 其他商家與只有範例商品的團購不列入
+        let mut other = make_group_buy(Uuid::new_v4().to_string(), 1);
+        other.merchant_name = "other".to_string();
+        db.create_group_buy(&other).await.unwrap();
+        let mut placeholder = make_group_buy(Uuid::new_v4().to_string(), 1);
+        placeholder.items = [("範例商品".to_string(), Decimal::new(10, 0))]
+            .into_iter()
+            .collect();
+        db.create_group_buy(&placeholder).await.unwrap();
+
+        let current = insert_group_buy(&db, 1).await;
+        let recent = db
+            .get_recent_group_buys_by_merchant("shop", &current.id, 2)
+            .await
+            .unwrap();
+        let recent_ids: Vec<&str> = recent.iter().map(|gb| gb.id.as_str()).collect();
+        assert_eq!(recent_ids, vec![ids[1].as_str(), ids[2].as_str()]);
+
+        let names = db.get_merchant_names(10).await.unwrap();
+        assert_eq!(names, vec!["shop".to_string(), "other".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_api_token_lifecycle() {
+        let db = setup_db().await;
+
+        let (token, secret) = db
+            .create_api_token("ci", ApiTokenScope::ReadOnly, "admin")
+            .await
+            .expect("create token");
+        assert!(secret.starts_with("lmb_"));
+
+        // 只儲存 hash
+        let stored: String = sqlx::query_scalar("SELECT token_hash FROM api_tokens WHERE id = ?")
+            .bind(&token.id)
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_ne!(stored, secret);
+
+        let authed = db.authenticate_api_token(&secret).await.unwrap().unwrap();
+        assert_eq!(authed.id, token.id);
+        assert_eq!(authed.scope, ApiTokenScope::ReadOnly);
+        assert!(
+            db.authenticate_api_token("lmb_wrong")
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        db.log_api_token_request(&token.id, "GET", "/api/v1/admin/stickers/stats", 200)
+            .await
+            .unwrap();
+        let logs = db.get_api_token_logs(&token.id, 10).await.unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].status, 200);
+
+        let tokens = db.list_api_tokens().await.unwrap();
+        assert!(tokens[0].last_used_at.is_some());
+
+        assert!(db.revoke_api_token(&token.id).await.unwrap());
+        assert!(!db.revoke_api_token(&token.id).await.unwrap());
+        assert!(db.authenticate_api_token(&secret).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_api_token_scope_allows() {
+        use ApiTokenScope::*;
+        assert!(ReadOnly.allows(ReadOnly));
+        assert!(!ReadOnly.allows(StickerAdmin));
+        assert!(StickerAdmin.allows(ReadOnly));
+        assert!(StickerAdmin.allows(StickerAdmin));
+        assert!(!StickerAdmin.allows(GbAdmin));
+        assert!(GbAdmin.allows(GbAdmin));
+        assert!(!GbAdmin.allows(StickerAdmin));
+    }
+
+    #[tokio::test]
+    async fn test_sticker_bulk_insert_and_search() {
+        use crate::sticker::Sticker;
+
+        let db = setup_db().await;
+
+        let stickers = vec![
+            Sticker {
+                name: "apple smile".to_string(),
+                image_url: "https://example.com/a1.png".to_string(),
+                category: "fruit".to_string(),
+            },
+            Sticker {
+                name: "banana happy".to_string(),
+                image_url: "https://example.com/b1.png".to_string(),
+                category: "fruit".to_string(),
+            },
+            Sticker {
+                name: "carrot".to_string(),
+                image_url: "https://example.com/c1.png".to_string(),
+                category: "veg".to_string(),
+            },
+        ];
+
+        let inserted = db
+            .bulk_insert_stickers(&stickers)
+            .await
+            .expect("bulk insert");
+        assert!(inserted >= 3);
+
+        let cnt = db.count_stickers().await.expect("count");
+        assert!(cnt >= 3);
+
+        // search for "apple"
+        let res = db
+            .search_stickers(None, &vec!["apple".to_string()], &vec![], None, 10)
+            .await
+            .expect("search");
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].name, "apple smile");
+    }
+
+    #[tokio::test]
+    async fn test_connect_with_tuning_and_wal_checkpoint() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("bot.db").display());
+        let config = DatabaseConfig {
+            max_connections: 2,
+            synchronous: "normal".to_string(),
+            ..DatabaseConfig::default()
+        };
+
+        let db = Database::connect(&url, &config).await.expect("connect");
+
+        let journal_mode: String = sqlx::query_scalar("PRAGMA journal_mode")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(journal_mode, "wal");
+        let synchronous: i64 = sqlx::query_scalar("PRAGMA synchronous")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(synchronous, 1); // NORMAL
+
+        insert_group_buy(&db, 1).await;
+        let (busy, _, _) = db.wal_checkpoint().await.expect("checkpoint");
+        assert_eq!(busy, 0);
+
+        // 無效的設定值
+        let invalid = DatabaseConfig {
+            journal_mode: "fast".to_string(),
+            ..DatabaseConfig::default()
+        };
+        assert!(Database::connect(&url, &invalid).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_vacuum_and_integrity_check() {
+        let db = setup_db().await;
+        insert_group_buy(&db, 1).await;
+
+        assert_eq!(db.integrity_check().await.unwrap(), vec!["ok".to_string()]);
+        db.vacuum().await.expect("vacuum");
+        assert!(db.database_size().await.unwrap() > 0);
+
+        // 維護作業進行中時拒絕再次執行
+        let _guard = db.maintenance.lock().await;
+        assert!(db.vacuum().await.is_err());
+        assert!(db.integrity_check().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_consume_nonce_once_until_expired() {
+        let db = setup_db().await;
+        let future = (Utc::now() + chrono::Duration::minutes(5)).timestamp();
+        assert!(db.consume_nonce("n1", future).await.unwrap());
+        assert!(!db.consume_nonce("n1", future).await.unwrap());
+
+        // 過期的紀錄會被清除
+        let past = (Utc::now() - chrono::Duration::minutes(5)).timestamp();
+        assert!(db.consume_nonce("n2", past).await.unwrap());
+        assert!(db.consume_nonce("n2", future).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_lease_has_single_holder_until_expired() {
+        let db = setup_db().await;
+        let ttl = chrono::Duration::seconds(30);
+        assert!(db.try_acquire_lease("jobs", "a", ttl).await.unwrap());
+        assert!(!db.try_acquire_lease("jobs", "b", ttl).await.unwrap());
+        // 持有者可以續約
+        assert!(db.try_acquire_lease("jobs", "a", ttl).await.unwrap());
+
+        // 過期後其他實例可以接手
+        sqlx::query("UPDATE leader_leases SET expires_at = 0")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        assert!(db.try_acquire_lease("jobs", "b", ttl).await.unwrap());
+        assert!(!db.try_acquire_lease("jobs", "a", ttl).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_count_group_buys_by_status() {
+        let db = setup_db().await;
+        insert_group_buy(&db, 1).await;
+        insert_group_buy(&db, 1).await;
+        let closed = insert_group_buy(&db, 1).await;
+        close_group_buy(&db, &closed.id, 1).await;
+
+        let mut counts = db.count_group_buys_by_status().await.unwrap();
+        counts.sort_by_key(|(_, count)| *count);
+        assert_eq!(
+            counts,
+            vec![(GroupBuyStatus::Closed, 1), (GroupBuyStatus::Active, 2)]
+        );
+
+        let pool = db.pool_status();
+        assert!(pool.size <= pool.max);
+        assert!(pool.idle <= pool.size);
+    }
+
+    #[tokio::test]
+    async fn test_custom_stickers_survive_replace() {
+        let db = setup_db().await;
+        let sticker = |name: &str, category: &str| Sticker {
+            name: name.to_string(),
+            image_url: format!("https://example.com/{}.png", name),
+            category: category.to_string(),
+        };
+        db.replace_stickers(&[sticker("apple", "fruit")])
+            .await
+            .expect("replace");
+
+        // 已存在的網址不重複新增
+        let added = db
+            .add_custom_stickers(
+                &[sticker("apple", "custom"), sticker("cat", "animal")],
+                "admin",
+            )
+            .await
+            .expect("add custom");
+        assert_eq!(added, 1);
+        assert_eq!(db.count_stickers().await.unwrap(), 2);
+
+        // 重新載入配置後仍保留
+        db.replace_stickers(&[sticker("banana", "fruit")])
+            .await
+            .expect("replace again");
+        let stats = db.get_sticker_category_stats().await.unwrap();
+        assert_eq!(stats.get("fruit"), Some(&1));
+        assert_eq!(stats.get("animal"), Some(&1));
+        assert_eq!(stats.get("custom"), None);
+    }
+
+    #[tokio::test]
+    async fn test_update_and_delete_sticker() {
+        let db = setup_db().await;
+        let sticker = |name: &str| Sticker {
+            name: name.to_string(),
+            image_url: format!("https://example.com/{}.png", name),
+            category: "animal".to_string(),
+        };
+        db.replace_stickers(&[sticker("cat")])
+            .await
+            .expect("replace");
+        db.add_custom_stickers(&[sticker("dog")], "admin")
+            .await
+            .expect("add custom");
+        assert!(
+            !db.is_custom_sticker("https://example.com/cat.png")
+                .await
+                .unwrap()
+        );
+        assert!(
+            db.is_custom_sticker("https://example.com/dog.png")
+                .await
+                .unwrap()
+        );
+
+        // 管理員新增的貼圖改名後，重新載入配置仍保留新名稱
+        assert!(
+            db.update_sticker("https://example.com/dog.png", "柴犬")
+                .await
+                .unwrap()
+        );
+        db.replace_stickers(&[sticker("cat")])
+            .await
+            .expect("replace");
+        let dog = db
+            .find_sticker_by_hash(&crate::sticker::url_hash("https://example.com/dog.png"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(dog.name, "柴犬");
+
+        assert!(
+            db.delete_sticker("https://example.com/dog.png")
+                .await
+                .unwrap()
+        );
+        assert!(
+            !db.delete_sticker("https://example.com/dog.png")
+                .await
+                .unwrap()
+        );
+        assert!(
+            !db.is_custom_sticker("https://example.com/dog.png")
+                .await
+                .unwrap()
+        );
+        db.replace_stickers(&[sticker("cat")])
+            .await
+            .expect("replace");
+        assert_eq!(db.count_stickers().await.unwrap(), 1);
+        assert!(
+            !db.update_sticker("https://example.com/missing.png", "x")
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sync_stickers_skips_unchanged_list() {
+        let db = setup_db().await;
+        let sticker = |name: &str| Sticker {
+            name: name.to_string(),
+            image_url: format!("https://example.com/{}.png", name),
+            category: "animal".to_string(),
+        };
+
+        assert_eq!(db.sync_stickers(&[sticker("cat")]).await.unwrap(), Some(1));
+        assert_eq!(db.sync_stickers(&[sticker("cat")]).await.unwrap(), None);
+        assert_eq!(
+            db.sync_stickers(&[sticker("cat"), sticker("dog")])
+                .await
+                .unwrap(),
+            Some(2)
+        );
+
+        // 從私訊刪除配置中的貼圖後，重新載入時補回
+        db.delete_sticker("https://example.com/dog.png")
+            .await
+            .unwrap();
+        assert_eq!(
+            db.sync_stickers(&[sticker("cat"), sticker("dog")])
+                .await
+                .unwrap(),
+            Some(2)
+        );
+        assert_eq!(db.count_stickers().await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_sticker_sources() {
+        let db = setup_db().await;
+        let now = Utc::now();
+        let record = StickerSourceRecord {
+            category: "animal".to_string(),
+            url: "https://example.com/animals.csv".to_string(),
+            etag: Some("\"v1\"".to_string()),
+            last_modified: None,
+            content: Some("名稱,圖片".to_string()),
+            status: StickerSourceStatus::Updated,
+            sticker_count: 3,
+            error: None,
+            checked_at: now,
+            changed_at: Some(now),
+        };
+        db.save_sticker_source(&record).await.unwrap();
+        db.save_sticker_source(&StickerSourceRecord {
+            status: StickerSourceStatus::Failed,
+            error: Some("timeout".to_string()),
+            ..record.clone()
+        })
+        .await
+        .unwrap();
+
+        let saved = db
+            .get_sticker_source("animal", "https://example.com/animals.csv")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(saved.status, StickerSourceStatus::Failed);
+        assert_eq!(saved.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(saved.content.as_deref(), Some("名稱,圖片"));
+
+        // 列出狀態時不讀取內容
+        let all = db.get_sticker_sources().await.unwrap();
+        assert_eq!(all.len(), 1);
+        assert!(all[0].content.is_none());
+        assert_eq!(all[0].error.as_deref(), Some("timeout"));
+
+        assert_eq!(db.prune_sticker_sources(&[]).await.unwrap(), 1);
+        assert!(db.get_sticker_sources().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_feed_state_and_items() {
+        let db = setup_db().await;
+        assert!(db.get_feed_state("blog").await.unwrap().is_none());
+
+        let now = Utc::now();
+        db.save_feed_state(&FeedStateRecord {
+            name: "blog".to_string(),
+            etag: Some("\"v1\"".to_string()),
+            last_modified: None,
+            error: Some("timeout".to_string()),
+            checked_at: now,
+            succeeded_at: None,
+        })
+        .await
+        .unwrap();
+        let state = db.get_feed_state("blog").await.unwrap().unwrap();
+        assert_eq!(state.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(state.error.as_deref(), Some("timeout"));
+        assert!(state.succeeded_at.is_none());
+
+        let ids: Vec<String> = ["a", "b", "c"].iter().map(|s| s.to_string()).collect();
+        db.mark_feed_items_seen("blog", &ids[1..2]).await.unwrap();
+        // 重複記錄不會出錯
+        db.mark_feed_items_seen("blog", &ids[1..2]).await.unwrap();
+        assert_eq!(
+            db.unseen_feed_items("blog", &ids).await.unwrap(),
+            vec!["a".to_string(), "c".to_string()]
+        );
+        // 不同 feed 的項目分開記錄
+        assert_eq!(db.unseen_feed_items("other", &ids).await.unwrap(), ids);
+    }
+
+    #[tokio::test]
+    async fn test_trending_stickers_counts_recent_usage() {
+        let db = setup_db().await;
+        let sticker = |name: &str| Sticker {
+            name: name.to_string(),
+            image_url: format!("https://example.com/{}.png", name),
+            category: "fruit".to_string(),
+        };
+        db.bulk_insert_stickers(&[sticker("apple"), sticker("banana"), sticker("cherry")])
+            .await
+            .unwrap();
+
+        for (name, times) in [("apple", 1), ("banana", 3), ("removed", 5)] {
+            for _ in 0..times {
+                db.record_sticker_usage(&format!("https://example.com/{}.png", name), "u1")
+                    .await
+                    .unwrap();
+            }
+        }
+        // 超過統計範圍的紀錄不列入
+        sqlx::query("INSERT INTO sticker_usage (image_url, user_id, used_at) VALUES (?, ?, ?)")
+            .bind("https://example.com/cherry.png")
+            .bind("u1")
+            .bind((Utc::now() - chrono::Duration::days(30)).to_rfc3339())
+            .execute(&db.pool)
+            .await
+            .unwrap();
+
+        let trending = db
+            .get_trending_stickers(Utc::now() - chrono::Duration::days(7), 5)
+            .await
+            .unwrap();
+        let names: Vec<(&str, i64)> = trending
+            .iter()
+            .map(|(s, uses)| (s.name.as_str(), *uses))
+            .collect();
+        assert_eq!(names, vec![("banana", 3), ("apple", 1)]);
+    }
+
+    #[tokio::test]
+    async fn test_sticker_usage_by_category_and_user() {
+        let db = setup_db().await;
+        let sticker = |name: &str, category: &str| Sticker {
+            name: name.to_string(),
+            image_url: format!("https://example.com/{}.png", name),
+            category: category.to_string(),
+        };
+        db.bulk_insert_stickers(&[
+            sticker("apple", "fruit"),
+            sticker("banana", "fruit"),
+            sticker("cat", "animal"),
+        ])
+        .await
+        .unwrap();
+
+        for (name, user) in [
+            ("apple", "u1"),
+            ("banana", "u1"),
+            ("cat", "u2"),
+            ("removed", "u2"),
+            ("removed", "u2"),
+        ] {
+            db.record_sticker_usage(&format!("https://example.com/{}.png", name), user)
+                .await
+                .unwrap();
+        }
+
+        let since = Utc::now() - chrono::Duration::days(7);
+        assert_eq!(
+            db.get_sticker_usage_by_category(since).await.unwrap(),
+            vec![("fruit".to_string(), 2), ("animal".to_string(), 1)]
+        );
+        assert_eq!(
+            db.get_sticker_usage_by_user(since, 10).await.unwrap(),
+            vec![("u2".to_string(), 3), ("u1".to_string(), 2)]
+        );
+        assert_eq!(
+            db.get_sticker_usage_by_user(since, 1).await.unwrap().len(),
+            1
+        );
+        assert!(
+            db.get_sticker_usage_by_category(Utc::now() + chrono::Duration::minutes(1))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_sticker_usage() {
+        let db = setup_db().await;
+        db.bulk_insert_stickers(&[Sticker {
+            name: "apple".to_string(),
+            image_url: "https://example.com/apple.png".to_string(),
+            category: "fruit".to_string(),
+        }])
+        .await
+        .unwrap();
+        for (name, user) in [("apple", "u1"), ("removed", "u1"), ("apple", "u2")] {
+            db.record_sticker_usage(&format!("https://example.com/{}.png", name), user)
+                .await
+                .unwrap();
+        }
+
+        let u1 = db
+            .query_sticker_usage(&StickerUsageQuery {
+                user_id: Some("u1".to_string()),
+                ..StickerUsageQuery::default()
+            })
+            .await
+            .unwrap();
+        // 新到舊，已移除的貼圖沒有名稱與分類
+        assert_eq!(u1.len(), 2);
+        assert_eq!(u1[0].image_url, "https://example.com/removed.png");
+        assert_eq!(u1[0].name, None);
+        assert_eq!(u1[1].name.as_deref(), Some("apple"));
+        assert_eq!(u1[1].category.as_deref(), Some("fruit"));
+
+        let fruit = db
+            .query_sticker_usage(&StickerUsageQuery {
+                category: Some("fruit".to_string()),
+                limit: Some(1),
+                ..StickerUsageQuery::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(fruit.len(), 1);
+        assert_eq!(fruit[0].user_id, "u2");
+    }
+
+    #[tokio::test]
+    async fn test_recent_stickers_per_user() {
+        let db = setup_db().await;
+        let sticker = |name: &str| Sticker {
+            name: name.to_string(),
+            image_url: format!("https://example.com/{}.png", name),
+            category: "fruit".to_string(),
+        };
+        db.bulk_insert_stickers(&[sticker("apple"), sticker("banana"), sticker("cherry")])
+            .await
+            .unwrap();
+
+        let now = Utc::now();
+        for (name, user, minutes_ago) in [
+            ("apple", "u1", 30),
+            ("banana", "u1", 20),
+            ("apple", "u1", 10),
+            ("cherry", "u2", 5),
+            ("removed", "u1", 1),
+        ] {
+            sqlx::query("INSERT INTO sticker_usage (image_url, user_id, used_at) VALUES (?, ?, ?)")
+                .bind(format!("https://example.com/{}.png", name))
+                .bind(user)
+                .bind((now - chrono::Duration::minutes(minutes_ago)).to_rfc3339())
+                .execute(&db.pool)
+                .await
+                .unwrap();
+        }
+
+        let recent = db.get_recent_stickers("u1", 10).await.unwrap();
+        let names: Vec<&str> = recent.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["apple", "banana"]);
+        assert_eq!(db.get_recent_stickers("u1", 1).await.unwrap().len(), 1);
+        assert!(db.get_recent_stickers("u3", 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sticker_picker_tracking() {
+        let db = setup_db().await;
+        db.create_sticker_picker("p1", "c1", "u1").await.unwrap();
+        db.create_sticker_picker("p2", "c1", "u2").await.unwrap();
+
+        let later = Utc::now() + chrono::Duration::minutes(1);
+        let stale = db.get_stale_sticker_pickers(later).await.unwrap();
+        assert_eq!(stale.len(), 2);
+        assert!(stale.iter().all(|p| p.post_id.is_none()));
+        assert!(
+            db.get_stale_sticker_pickers(Utc::now() - chrono::Duration::minutes(1))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        // 操作後記下貼文 ID；之後沒有貼文 ID 的操作不會覆蓋
+        db.touch_sticker_picker("p1", "post-1").await.unwrap();
+        db.touch_sticker_picker("p1", "").await.unwrap();
+        db.delete_sticker_picker("p2").await.unwrap();
+
+        let stale = db.get_stale_sticker_pickers(later).await.unwrap();
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0].id, "p1");
+        assert_eq!(stale[0].channel_id, "c1");
+        assert_eq!(stale[0].post_id.as_deref(), Some("post-1"));
+    }
+
+    #[tokio::test]
+    async fn test_favorite_stickers_per_user() {
+        let db = setup_db().await;
+        let sticker = |name: &str| Sticker {
+            name: name.to_string(),
+            image_url: format!("https://example.com/{}.png", name),
+            category: "fruit".to_string(),
+        };
+        db.bulk_insert_stickers(&[sticker("apple"), sticker("banana")])
+            .await
+            .unwrap();
+
+        let apple = "https://example.com/apple.png";
+        db.add_favorite_sticker("u1", apple).await.unwrap();
+        // 重複加入不會出錯也不會重複列出
+        db.add_favorite_sticker("u1", apple).await.unwrap();
+        db.add_favorite_sticker("u1", "https://example.com/removed.png")
+            .await
+            .unwrap();
+
+        assert!(db.is_favorite_sticker("u1", apple).await.unwrap());
+        assert!(!db.is_favorite_sticker("u2", apple).await.unwrap());
+
+        let favorites = db.get_favorite_stickers("u1").await.unwrap();
+        let names: Vec<&str> = favorites.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["apple"]);
+        assert!(db.get_favorite_stickers("u2").await.unwrap().is_empty());
+
+        db.remove_favorite_sticker("u1", apple).await.unwrap();
+        assert!(!db.is_favorite_sticker("u1", apple).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_database_is_a_copy() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("bot.db").display());
+        let db = Database::connect(&url, &DatabaseConfig::default())
+            .await
+            .unwrap();
+        let existing = insert_group_buy(&db, 1).await;
+
+        let copy_url = dry_run_database_url(&url).unwrap();
+        assert_ne!(copy_url, url);
+        let copy = Database::connect(&copy_url, &DatabaseConfig::default())
+            .await
+            .unwrap();
+        assert!(copy.get_group_buy(&existing.id).await.unwrap().is_some());
+
+        // 寫入副本不影響正式資料庫
+        let rehearsal = insert_group_buy(&copy, 1).await;
+        assert!(db.get_group_buy(&rehearsal.id).await.unwrap().is_none());
+
+        assert_eq!(
+            dry_run_database_url("sqlite::memory:").unwrap(),
+            "sqlite::memory:"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_pool_is_read_only_and_can_use_replica() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("bot.db").display());
+        let sticker = |name: &str| Sticker {
+            name: name.to_string(),
+            image_url: format!("https://example.com/{}.png", name),
+            category: "fruit".to_string(),
+        };
+
+        let db = Database::connect(&url, &DatabaseConfig::default())
+            .await
+            .expect("connect");
+        db.bulk_insert_stickers(&[sticker("apple")]).await.unwrap();
+
+        // 唯讀連接池看得到主資料庫已提交的資料，但不能寫入
+        let stats = db.get_sticker_category_stats().await.unwrap();
+        assert_eq!(stats.get("fruit"), Some(&1));
+        assert!(
+            sqlx::query("DELETE FROM stickers")
+                .execute(db.read_pool())
+                .await
+                .is_err()
+        );
+
+        // 指定副本時，統計讀取的是副本內容
+        let replica_path = dir.path().join("replica.db");
+        sqlx::query(&format!("VACUUM INTO '{}'", replica_path.display()))
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        let config = DatabaseConfig {
+            read_replica_url: Some(format!("sqlite://{}", replica_path.display())),
+            ..DatabaseConfig::default()
+        };
+        let db = Database::connect(&url, &config).await.expect("connect");
+        db.bulk_insert_stickers(&[sticker("banana")]).await.unwrap();
+
+        assert_eq!(db.count_stickers().await.unwrap(), 2);
+        let stats = db.get_sticker_category_stats().await.unwrap();
+        assert_eq!(stats.get("fruit"), Some(&1));
+    }
+}
+
+impl Database {
+    /// 初始化資料庫連接
+    pub async fn new(database_url: &str) -> Result<Self> {
+        Self::connect(database_url, &DatabaseConfig::default()).await
+    }
+
+    /// 依照設定的連線參數建立資料庫連線
+    pub async fn connect(database_url: &str, config: &DatabaseConfig) -> Result<Self> {
+        let journal_mode = SqliteJournalMode::from_str(&config.journal_mode)
+            .with_context(|| format!("無效的 journal_mode: {}", config.journal_mode))?;
+        let synchronous = SqliteSynchronous::from_str(&config.synchronous)
+            .with_context(|| format!("無效的 synchronous: {}", config.synchronous))?;
+
+        // 解析 connection string
+        let options = SqliteConnectOptions::from_str(database_url)?
+            .create_if_missing(true)
+            .journal_mode(journal_mode)
+            .synchronous(synchronous)
+            .busy_timeout(std::time::Duration::from_secs(config.busy_timeout_secs))
+            .auto_vacuum(sqlx::sqlite::SqliteAutoVacuum::Full)
+            .foreign_keys(true);
+
+        // 建立連接池
+        let pool = SqlitePoolOptions::new()
+            .max_connections(config.max_connections)
+            .connect_with(options)
+            .await
+            .with_context(|| format!("無法連接到資料庫: {}", database_url))?;
+
+        let mut db = Database {
+            read_pool: pool.clone(),
+            pool,
+            maintenance: Default::default(),
+            metrics: Default::default(),
+        };
+
+        // 初始化資料表
+        db.init_schema().await?;
+
+        // 套用資料遷移
+        db.run_migrations().await?;
+
+        // 資料表建立後才開啟唯讀連接池（唯讀連線無法建立檔案）
+        let read_url = match &config.read_replica_url {
+            Some(url) => Some(url.as_str()),
+            // in-memory 資料庫每個連線各自獨立，只能共用主連接池
+            None if is_memory_url(database_url) => None,
+            None => Some(database_url),
+        };
+        if let Some(read_url) = read_url {
+            let read_options = SqliteConnectOptions::from_str(read_url)?
+                .read_only(true)
+                .busy_timeout(std::time::Duration::from_secs(config.busy_timeout_secs));
+            db.read_pool = SqlitePoolOptions::new()
+                .max_connections(config.read_max_connections)
+                .connect_with(read_options)
+                .await
+                .with_context(|| format!("無法連接到唯讀資料庫: {}", read_url))?;
+            info!("唯讀連接池: {}", read_url);
+        }
+
+        info!("資料庫初始化成功: {}", database_url);
+
+        Ok(db)
+    }
+
+    /// 查詢耗時記錄到指定的統計（預設為各自獨立的一份）
+    pub fn with_metrics(mut self, metrics: std::sync::Arc<crate::metrics::Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// 查詢耗時記錄到的統計
+    pub fn metrics(&self) -> &std::sync::Arc<crate::metrics::Metrics> {
+        &self.metrics
+    }
+
+    /// 建立資料表結構
+    async fn init_schema(&self) -> Result<()> {
+        // Prefer a single source-of-truth schema file when explicitly set via
+        // `DB_SCHEMA_FILE`. Otherwise use the embedded schema that is baked
+        // into the binary at compile time (see `EMBEDDED_SCHEMA`). This keeps
+        // runtime self-contained.
+        if let Ok(schema_path) = std::env::var("DB_SCHEMA_FILE") {
+            if let Ok(schema) = std::fs::read_to_string(&schema_path) {
+                for stmt in schema.split(';') {
+                    let s = stmt.trim();
+                    if s.is_empty() {
+                        continue;
+                    }
+                    sqlx::query(s).execute(&self.pool).await?;
+                }
+                info!("資料表結構初始化完成 (from {})", schema_path);
+                return Ok(());
+            } else {
+                info!(
+                    "DB_SCHEMA_FILE set but not readable: {}. Falling back to embedded schema",
+                    schema_path
+                );
+            }
+        }
+
+        // No external schema provided or readable — apply the embedded schema.
+        self.apply_embedded_schema().await?;
+
+        Ok(())
+    }
+
+    /// Apply the embedded schema (EMBEDDED_SCHEMA) to the database. This is
+    /// the fallback path and is also the recommended runtime behavior so the
+    /// binary does not require external SQL files.
+    async fn apply_embedded_schema(&self) -> Result<()> {
+        for stmt in EMBEDDED_SCHEMA.split(';') {
+            let s = stmt.trim();
+            if s.is_empty() {
+                continue;
+            }
+            sqlx::query(s).execute(&self.pool).await?;
+        }
+        info!("資料表結構初始化完成 (embedded)");
+
+        Ok(())
+    }
+
+    /// 依序套用資料遷移，已套用的版本記錄在 `PRAGMA user_version`。
+    /// 只處理無法用 `CREATE ... IF NOT EXISTS` 表達的資料變更。
+    async fn run_migrations(&self) -> Result<()> {
+        let version: i64 = sqlx::query_scalar("PRAGMA user_version")
+            .fetch_one(&self.pool)
+            .await?;
+
+        if version < 1 {
+            self.migrate_stable_sticker_hashes().await?;
+            info!("資料遷移完成: v1 (穩定貼圖 hash)");
+        }
+
+        if version < 2 {
+            self.add_column_if_missing("group_buys", "item_icons", "TEXT")
+                .await?;
+            sqlx::query("PRAGMA user_version = 2")
+                .execute(&self.pool)
+                .await?;
+            info!("資料遷移完成: v2 (商品圖示)");
+        }
+
+        if version < 3 {
+            self.migrate_structured_log_details().await?;
+            sqlx::query("PRAGMA user_version = 3")
+                .execute(&self.pool)
+                .await?;
+            info!("資料遷移完成: v3 (結構化操作日誌)");
+        }
+
+        if version < 4 {
+            self.add_column_if_missing("group_buy_orders", "deleted_at", "TEXT")
+                .await?;
+            self.add_column_if_missing("group_buy_orders", "deleted_batch", "TEXT")
+                .await?;
+            sqlx::query(
+                "CREATE INDEX IF NOT EXISTS idx_orders_deleted_batch ON group_buy_orders(deleted_batch)",
+            )
+            .execute(&self.pool)
+            .await?;
+            sqlx::query("PRAGMA user_version = 4")
+                .execute(&self.pool)
+                .await?;
+            info!("資料遷移完成: v4 (訂單軟刪除)");
+        }
+
+        if version < 5 {
+            self.migrate_group_buy_status_check().await?;
+            sqlx::query("PRAGMA user_version = 5")
+                .execute(&self.pool)
+                .await?;
+            info!("資料遷移完成: v5 (草稿與已下單狀態)");
+        }
+
+        if version < 6 {
+            // v5 重建資料表時不含此欄位，必須在 v5 之後補上
+            self.add_column_if_missing("group_buys", "item_sections", "TEXT")
+                .await?;
+            sqlx::query("PRAGMA user_version = 6")
+                .execute(&self.pool)
+                .await?;
+            info!("資料遷移完成: v6 (商品分類)");
+        }
+
+        if version < 7 {
+            self.add_column_if_missing("group_buys", "order_fields", "TEXT")
+                .await?;
+            self.add_column_if_missing("group_buy_orders", "custom_fields", "TEXT")
+                .await?;
+            sqlx::query("PRAGMA user_version = 7")
+                .execute(&self.pool)
+                .await?;
+            info!("資料遷移完成: v7 (訂單自訂欄位)");
+        }
+
+        if version < 8 {
+            self.add_column_if_missing("group_buys", "currency", "TEXT")
+                .await?;
+            sqlx::query("PRAGMA user_version = 8")
+                .execute(&self.pool)
+                .await?;
+            info!("資料遷移完成: v8 (團購幣別)");
+        }
+
+        if version < 9 {
+            self.migrate_group_buy_archived_status().await?;
+            sqlx::query("PRAGMA user_version = 9")
+                .execute(&self.pool)
+                .await?;
+            info!("資料遷移完成: v9 (封存狀態)");
+        }
+
+        if version < 10 {
+            self.add_column_if_missing("group_buys", "item_translations", "TEXT")
+                .await?;
+            sqlx::query("PRAGMA user_version = 10")
+                .execute(&self.pool)
+                .await?;
+            info!("資料遷移完成: v10 (商品翻譯)");
+        }
+
+        Ok(())
+    }
+
+    /// 統計、匯出等大量讀取應使用的連接池。
+    /// 設定 `read_replica_url` 時資料可能落後主資料庫，不應用於互動流程。
+    pub fn read_pool(&self) -> &SqlitePool {
+        &self.read_pool
+    }
+
+    /// 資料庫大小（位元組）
+    pub async fn database_size(&self) -> Result<i64> {
+        let page_count: i64 = sqlx::query_scalar("PRAGMA page_count")
+            .fetch_one(&self.pool)
+            .await?;
+        let page_size: i64 = sqlx::query_scalar("PRAGMA page_size")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(page_count * page_size)
+    }
+
+    /// 將一次性 nonce 標記為已使用，第一次使用時回傳 `true`。
+    /// 記錄在資料庫中，多個 bot 實例共用同一份紀錄；過期的 nonce 會順便清除。
+    pub async fn consume_nonce(&self, nonce: &str, expires_at: i64) -> Result<bool> {
+        sqlx::query("DELETE FROM used_nonces WHERE expires_at < ?")
+            .bind(Utc::now().timestamp())
+            .execute(&self.pool)
+            .await?;
+        let result =
+            sqlx::query("INSERT OR IGNORE INTO used_nonces (nonce, expires_at) VALUES (?, ?)")
+                .bind(nonce)
+                .bind(expires_at)
+                .execute(&self.pool)
+                .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// 取得或續約名為 `name` 的 lease，成功時回傳 `true`。
+    /// lease 由 `holder` 持有或已過期時才會寫入，同一時間只有一個 holder 能取得。
+    pub async fn try_acquire_lease(
+        &self,
+        name: &str,
+        holder: &str,
+        ttl: chrono::Duration,
+    ) -> Result<bool> {
+        let now = Utc::now();
+        let result = sqlx::query(
+            "INSERT INTO leader_leases (name, holder, expires_at) VALUES (?, ?, ?)
+             ON CONFLICT(name) DO UPDATE SET holder = excluded.holder, expires_at = excluded.expires_at
+             WHERE leader_leases.holder = excluded.holder OR leader_leases.expires_at < ?",
+        )
+        .bind(name)
+        .bind(holder)
+        .bind((now + ttl).timestamp())
+        .bind(now.timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// 主連接池的使用狀況
+    pub fn pool_status(&self) -> PoolStatus {
+        PoolStatus {
+            size: self.pool.size(),
+            idle: self.pool.num_idle() as u32,
+            max: self.pool.options().get_max_connections(),
+        }
+    }
+
+    /// 各狀態的團購數量，不含已封存的團購
+    pub async fn count_group_buys_by_status(&self) -> Result<Vec<(GroupBuyStatus, i64)>> {
+        let rows = sqlx::query_as::<_, (String, i64)>(
+            "SELECT status, COUNT(*) FROM group_buys WHERE status != 'archived' GROUP BY status",
+        )
+        .fetch_all(self.read_pool())
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(status, count)| (GroupBuyStatus::from_string(&status), count))
+            .collect())
+    }
+
+    /// 執行 VACUUM 重整資料庫檔案。同一時間只允許一個維護作業。
+    pub async fn vacuum(&self) -> Result<()> {
+        let _guard = self
+            .maintenance
+            .try_lock()
+            .map_err(|_| anyhow::anyhow!("已有資料庫維護作業進行中"))?;
+
+        sqlx::query("VACUUM").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    /// 執行 `PRAGMA integrity_check`，資料庫正常時回傳 `["ok"]`，否則回傳問題列表
+    pub async fn integrity_check(&self) -> Result<Vec<String>> {
+        let _guard = self
+            .maintenance
+            .try_lock()
+            .map_err(|_| anyhow::anyhow!("已有資料庫維護作業進行中"))?;
+
+        let problems: Vec<String> = sqlx::query_scalar("PRAGMA integrity_check")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(problems)
+    }
+
+    /// 執行 WAL checkpoint 並截斷 WAL 檔，回傳 (busy, WAL 頁數, 已寫回頁數)
+    pub async fn wal_checkpoint(&self) -> Result<(i64, i64, i64)> {
+        let row = sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok((row.try_get(0)?, row.try_get(1)?, row.try_get(2)?))
+    }
+
+    /// 在背景定期執行 WAL checkpoint，避免長時間有讀取進行時 WAL 檔無限成長
+    pub fn spawn_wal_checkpoint(
+        &self,
+        interval: std::time::Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        let db = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            // 第一次 tick 會立即完成，跳過
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                match db.wal_checkpoint().await {
+                    Ok((0, pages, checkpointed)) => {
+                        debug!("WAL checkpoint 完成: {}/{} 頁", checkpointed, pages)
+                    }
+                    Ok((_, pages, checkpointed)) => warn!(
+                        "WAL checkpoint 未完成（資料庫忙碌中）: {}/{} 頁",
+                        checkpointed, pages
+                    ),
+                    Err(e) => warn!("WAL checkpoint 失敗: {}", e),
+                }
+            }
+        })
+    }
+
+    /// 將 group_buy_logs.details 統一為 JSON 物件，並建立常用欄位的索引
+    async fn migrate_structured_log_details(&self) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        // 舊版的缺貨調整等紀錄是純文字，包成 {"message": ...}
+        sqlx::query(
+            "UPDATE group_buy_logs SET details = '{}' WHERE details IS NULL OR TRIM(details) = ''",
+        )
+        .execute(&mut *tx)
+        .await?;
+        let wrapped = sqlx::query(
+            "UPDATE group_buy_logs SET details = json_object('message', details)
+             WHERE CASE WHEN json_valid(details) THEN json_type(details) != 'object' ELSE 1 END",
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        self.add_column_if_missing(
+            "group_buy_logs",
+            "details_version",
+            "INTEGER GENERATED ALWAYS AS (json_extract(details, '$.version')) VIRTUAL",
+        )
+        .await?;
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_logs_group_buy_action ON group_buy_logs(group_buy_id, action)",
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_logs_group_buy_version ON group_buy_logs(group_buy_id, details_version)",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        if wrapped.rows_affected() > 0 {
+            info!("已將 {} 筆純文字日誌轉為 JSON", wrapped.rows_affected());
+        }
+        Ok(())
+    }
+
+    /// group_buys.status 的 CHECK 限制無法用 ALTER TABLE 修改，
+    /// 依 SQLite 建議的步驟重建資料表以允許 draft / ordered
+    async fn migrate_group_buy_status_check(&self) -> Result<()> {
+        let table_sql: String = sqlx::query_scalar(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'group_buys'",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        if table_sql.contains("'draft'") {
+            return Ok(());
+        }
+        // DROP TABLE 會一併刪除索引，記下來在同一個交易中重建
+        let index_sqls: Vec<String> = sqlx::query_scalar(
+            "SELECT sql FROM sqlite_master
+             WHERE type = 'index' AND tbl_name = 'group_buys' AND sql IS NOT NULL",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        // 重建期間必須關閉外鍵，否則 DROP TABLE 會連帶刪除訂單與日誌
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query("PRAGMA foreign_keys = OFF")
+            .execute(&mut *conn)
+            .await?;
+
+        let result = async {
+            let mut tx = conn.begin().await?;
+            sqlx::query(
+                "CREATE TABLE group_buys_new (
+                    id TEXT PRIMARY KEY,
+                    creator_id TEXT NOT NULL,
+                    creator_username TEXT NOT NULL,
+                    channel_id TEXT NOT NULL,
+                    post_id TEXT,
+                    merchant_name TEXT NOT NULL,
+                    description TEXT,
+                    metadata TEXT,
+                    items TEXT NOT NULL,
+                    item_icons TEXT,
+                    status TEXT NOT NULL CHECK(status IN ('draft', 'active', 'closed', 'ordered')),
+                    version INTEGER NOT NULL DEFAULT 1,
+                    created_at TEXT NOT NULL,
+                    updated_at TEXT NOT NULL
+                )",
+            )
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query(
+                "INSERT INTO group_buys_new (
+                    id, creator_id, creator_username, channel_id, post_id, merchant_name,
+                    description, metadata, items, item_icons, status, version, created_at, updated_at
+                 )
+                 SELECT id, creator_id, creator_username, channel_id, post_id, merchant_name,
+                    description, metadata, items, item_icons, status, version, created_at, updated_at
+                 FROM group_buys",
+            )
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query("DROP TABLE group_buys")
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("ALTER TABLE group_buys_new RENAME TO group_buys")
+                .execute(&mut *tx)
+                .await?;
+            for index_sql in &index_sqls {
+                sqlx::query(index_sql).execute(&mut *tx).await?;
+            }
+            let violations = sqlx::query("PRAGMA foreign_key_check")
+                .fetch_all(&mut *tx)
+                .await?;
+            if !violations.is_empty() {
+                anyhow::bail!("重建 group_buys 後有 {} 筆外鍵錯誤", violations.len());
+            }
+            tx.commit().await?;
+            Ok::<_, anyhow::Error>(())
+        }
+        .await;
+
+        sqlx::query("PRAGMA foreign_keys = ON")
+            .execute(&mut *conn)
+            .await?;
+        result
+    }
+
+    /// v9: group_buys.status 的 CHECK 加上 archived。
+    /// v5 之後陸續加了欄位，這裡以目前的建表語句替換 CHECK 後重建，保留所有欄位與索引
+    async fn migrate_group_buy_archived_status(&self) -> Result<()> {
+        const OLD_CHECK: &str = "CHECK(status IN ('draft', 'active', 'closed', 'ordered'))";
+        const NEW_CHECK: &str =
+            "CHECK(status IN ('draft', 'active', 'closed', 'ordered', 'archived'))";
+
+        let table_sql: String = sqlx::query_scalar(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'group_buys'",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        if table_sql.contains("'archived'") {
+            return Ok(());
+        }
+        let Some(columns_start) = table_sql.find('(') else {
+            anyhow::bail!("無法解析 group_buys 的建表語句");
+        };
+        if !table_sql.contains(OLD_CHECK) {
+            anyhow::bail!("group_buys 的 status 限制與預期不同，無法加上 archived");
+        }
+        let create_sql = format!(
+            "CREATE TABLE group_buys_new {}",
+            table_sql[columns_start..].replacen(OLD_CHECK, NEW_CHECK, 1)
+        );
+        let index_sqls: Vec<String> = sqlx::query_scalar(
+            "SELECT sql FROM sqlite_master
+             WHERE type = 'index' AND tbl_name = 'group_buys' AND sql IS NOT NULL",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        // 重建期間必須關閉外鍵，否則 DROP TABLE 會連帶刪除訂單與日誌
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query("PRAGMA foreign_keys = OFF")
+            .execute(&mut *conn)
+            .await?;
+
+        let result = async {
+            let mut tx = conn.begin().await?;
+            sqlx::query(&create_sql).execute(&mut *tx).await?;
+            sqlx::query("INSERT INTO group_buys_new SELECT * FROM group_buys")
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DROP TABLE group_buys")
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("ALTER TABLE group_buys_new RENAME TO group_buys")
+                .execute(&mut *tx)
+                .await?;
+            for index_sql in &index_sqls {
+                sqlx::query(index_sql).execute(&mut *tx).await?;
+            }
+            let violations = sqlx::query("PRAGMA foreign_key_check")
+                .fetch_all(&mut *tx)
+                .await?;
+            if !violations.is_empty() {
+                anyhow::bail!("重建 group_buys 後有 {} 筆外鍵錯誤", violations.len());
+            }
+            tx.commit().await?;
+            Ok::<_, anyhow::Error>(())
+        }
+        .await;
+
+        sqlx::query("PRAGMA foreign_keys = ON")
+            .execute(&mut *conn)
+            .await?;
+        result
+    }
+
+    /// 舊資料庫補上新欄位；新資料庫已由 schema.sql 建立則略過
+    async fn add_column_if_missing(&self, table: &str, column: &str, decl: &str) -> Result<()> {
+        // table_xinfo 才會列出 generated column
+        let exists: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM pragma_table_xinfo(?) WHERE name = ?")
+                .bind(table)
+                .bind(column)
+                .fetch_one(&self.pool)
+                .await?;
+
+        if exists == 0 {
+            sqlx::query(&format!(
+                "ALTER TABLE {} ADD COLUMN {} {}",
+                table, column, decl
+            ))
+            .execute(&self.pool)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// v1: 以穩定演算法重新計算 url_hash，舊 hash 保留為別名
+    async fn migrate_stable_sticker_hashes(&self) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let rows = sqlx::query("SELECT id, image_url, url_hash FROM stickers")
+            .fetch_all(&mut *tx)
+            .await?;
+
+        let now = Utc::now().to_rfc3339();
+        for r in rows {
+            let id: i64 = r.try_get("id")?;
+            let image_url: String = r.try_get("image_url")?;
+            let old_hash: Option<String> = r.try_get("url_hash")?;
+            let new_hash = crate::sticker::url_hash(&image_url);
+
+            if old_hash.as_deref() == Some(new_hash.as_str()) {
+                continue;
+            }
+
+            if let Some(old_hash) = old_hash {
+                sqlx::query(
+                    "INSERT OR IGNORE INTO sticker_hash_aliases (old_hash, url_hash, created_at) VALUES (?, ?, ?)",
+                )
+                .bind(&old_hash)
+                .bind(&new_hash)
+                .bind(&now)
+                .execute(&mut *tx)
+                .await?;
+            }
+
+            sqlx::query("UPDATE stickers SET url_hash = ? WHERE id = ?")
+                .bind(&new_hash)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        sqlx::query("PRAGMA user_version = 1")
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
     }
 
     /* ---------- Sticker helpers ---------- */
 
+    /// 以 url_hash 取得貼圖；舊演算法的 hash 會透過別名表對應到目前的 hash
+    pub async fn find_sticker_by_hash(&self, hash: &str) -> Result<Option<Sticker>> {
+        let row = sqlx::query(
+            "SELECT name, image_url, category FROM stickers
+             WHERE url_hash = ?1
+                OR url_hash = (SELECT url_hash FROM sticker_hash_aliases WHERE old_hash = ?1)
+             ORDER BY id LIMIT 1",
+        )
+        .bind(hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match row {
+            Some(r) => Ok(Some(Sticker {
+                name: r.try_get("name")?,
+                image_url: r.try_get("image_url")?,
+                category: r.try_get("category")?,
+            })),
+            None => Ok(None),
+        }
+    }
+
     /// Bulk insert stickers into the stickers table (INSERT OR IGNORE to avoid duplicates)
     pub async fn bulk_insert_stickers(&self, stickers: &[Sticker]) -> Result<usize> {
         let mut inserted: usize = 0;
@@ -331,15 +2697,92 @@ impl Database {
     /// Replace all stickers atomically: delete existing rows and insert the provided list.
     /// Returns number of inserted rows.
     pub async fn replace_stickers(&self, stickers: &[Sticker]) -> Result<usize> {
-        let mut inserted: usize = 0;
         let mut conn = self.pool.acquire().await?;
         let mut tx = conn.begin().await?;
+        let inserted = Self::replace_stickers_in(&mut tx, stickers).await?;
+        tx.commit().await?;
+        Ok(inserted)
+    }
+
+    /// 與 `replace_stickers` 相同，但貼圖列表與上次寫入的相同時不做任何事（回傳 None），
+    /// 來源沒有變更時重新載入不必刪除再寫入整個貼圖表
+    pub async fn sync_stickers(&self, stickers: &[Sticker]) -> Result<Option<usize>> {
+        let fingerprint = sticker_list_fingerprint(stickers);
+        let mut conn = self.pool.acquire().await?;
+        let mut tx = conn.begin().await?;
+
+        let current: Option<String> =
+            sqlx::query_scalar("SELECT fingerprint FROM sticker_snapshot WHERE id = 1")
+                .fetch_optional(&mut *tx)
+                .await?;
+        if current.as_deref() == Some(fingerprint.as_str()) {
+            return Ok(None);
+        }
+
+        let inserted = Self::replace_stickers_in(&mut tx, stickers).await?;
+        tx.commit().await?;
+        Ok(Some(inserted))
+    }
+
+    async fn replace_stickers_in(
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        stickers: &[Sticker],
+    ) -> Result<usize> {
+        let mut inserted: usize = 0;
 
         // Clear existing stickers
         sqlx::query("DELETE FROM stickers")
-            .execute(&mut *tx)
+            .execute(&mut **tx)
+            .await?;
+
+        for s in stickers {
+            let url_hash = s.get_url_hash();
+            let created_at = Utc::now().to_rfc3339();
+            let res = sqlx::query(
+                "INSERT OR IGNORE INTO stickers (name, image_url, category, url_hash, created_at) VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(&s.name)
+            .bind(&s.image_url)
+            .bind(&s.category)
+            .bind(&url_hash)
+            .bind(&created_at)
+            .execute(&mut **tx)
             .await?;
 
+            if res.rows_affected() > 0 {
+                inserted += 1;
+            }
+        }
+
+        // 管理員以私訊新增的貼圖不在配置中，替換後補回（與配置重複的網址以配置為準）
+        sqlx::query(
+            "INSERT OR IGNORE INTO stickers (name, image_url, category, url_hash, created_at)
+             SELECT name, image_url, category, url_hash, created_at FROM custom_stickers",
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        sqlx::query(
+            "INSERT INTO sticker_snapshot (id, fingerprint, updated_at) VALUES (1, ?, ?)
+             ON CONFLICT(id) DO UPDATE SET fingerprint = excluded.fingerprint, updated_at = excluded.updated_at",
+        )
+        .bind(sticker_list_fingerprint(stickers))
+        .bind(Utc::now().to_rfc3339())
+        .execute(&mut **tx)
+        .await?;
+
+        // no FTS population during replace — using LIKE-based searches instead
+
+        Ok(inserted)
+    }
+
+    /// 新增管理員上傳的貼圖，重新載入配置後仍會保留。
+    /// 回傳實際新增的數量（網址已存在的貼圖略過）
+    pub async fn add_custom_stickers(&self, stickers: &[Sticker], added_by: &str) -> Result<usize> {
+        let mut inserted: usize = 0;
+        let mut conn = self.pool.acquire().await?;
+        let mut tx = conn.begin().await?;
+
         for s in stickers {
             let url_hash = s.get_url_hash();
             let created_at = Utc::now().to_rfc3339();
@@ -353,39 +2796,503 @@ impl Database {
             .bind(&created_at)
             .execute(&mut *tx)
             .await?;
+            if res.rows_affected() == 0 {
+                continue;
+            }
+            inserted += 1;
+
+            sqlx::query(
+                "INSERT OR IGNORE INTO custom_stickers (name, image_url, category, url_hash, added_by, created_at) VALUES (?, ?, ?, ?, ?, ?)",
+            )
+            .bind(&s.name)
+            .bind(&s.image_url)
+            .bind(&s.category)
+            .bind(&url_hash)
+            .bind(added_by)
+            .bind(&created_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(inserted)
+    }
+
+    /// 貼圖是否為管理員新增（不在配置的來源中）
+    pub async fn is_custom_sticker(&self, image_url: &str) -> Result<bool> {
+        let found: Option<i64> =
+            sqlx::query_scalar("SELECT 1 FROM custom_stickers WHERE image_url = ?")
+                .bind(image_url)
+                .fetch_optional(self.read_pool())
+                .await?;
+        Ok(found.is_some())
+    }
+
+    /// 刪除貼圖（連同管理員新增的紀錄），回傳是否有刪除
+    pub async fn delete_sticker(&self, image_url: &str) -> Result<bool> {
+        let mut conn = self.pool.acquire().await?;
+        let mut tx = conn.begin().await?;
+
+        let res = sqlx::query("DELETE FROM stickers WHERE image_url = ?")
+            .bind(image_url)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM custom_stickers WHERE image_url = ?")
+            .bind(image_url)
+            .execute(&mut *tx)
+            .await?;
+        // 配置中的貼圖被修改後，下次重新載入要重新寫入
+        sqlx::query("DELETE FROM sticker_snapshot")
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// 修改貼圖名稱（連同管理員新增的紀錄），回傳是否有修改
+    pub async fn update_sticker(&self, image_url: &str, name: &str) -> Result<bool> {
+        let mut conn = self.pool.acquire().await?;
+        let mut tx = conn.begin().await?;
+
+        let res = sqlx::query("UPDATE stickers SET name = ? WHERE image_url = ?")
+            .bind(name)
+            .bind(image_url)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE custom_stickers SET name = ? WHERE image_url = ?")
+            .bind(name)
+            .bind(image_url)
+            .execute(&mut *tx)
+            .await?;
+        // 配置中的貼圖被修改後，下次重新載入要重新寫入
+        sqlx::query("DELETE FROM sticker_snapshot")
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// 取得 HTTP 貼圖來源上次讀取的紀錄（包含內容）
+    pub async fn get_sticker_source(
+        &self,
+        category: &str,
+        url: &str,
+    ) -> Result<Option<StickerSourceRecord>> {
+        let row = sqlx::query(
+            "SELECT category, url, etag, last_modified, content, status, sticker_count, error, checked_at, changed_at
+             FROM sticker_sources WHERE category = ? AND url = ?",
+        )
+        .bind(category)
+        .bind(url)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(|row| StickerSourceRecord::from_row(&row, true))
+            .transpose()
+    }
+
+    /// 所有 HTTP 貼圖來源的讀取狀態（不包含內容），依分類與網址排序
+    pub async fn get_sticker_sources(&self) -> Result<Vec<StickerSourceRecord>> {
+        let rows = sqlx::query(
+            "SELECT category, url, etag, last_modified, NULL AS content, status, sticker_count, error, checked_at, changed_at
+             FROM sticker_sources ORDER BY category, url",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|row| StickerSourceRecord::from_row(row, false))
+            .collect()
+    }
+
+    /// 寫入 HTTP 貼圖來源這次讀取的結果
+    pub async fn save_sticker_source(&self, record: &StickerSourceRecord) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO sticker_sources
+                (category, url, etag, last_modified, content, status, sticker_count, error, checked_at, changed_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT(category, url) DO UPDATE SET
+                etag = excluded.etag,
+                last_modified = excluded.last_modified,
+                content = excluded.content,
+                status = excluded.status,
+                sticker_count = excluded.sticker_count,
+                error = excluded.error,
+                checked_at = excluded.checked_at,
+                changed_at = excluded.changed_at",
+        )
+        .bind(&record.category)
+        .bind(&record.url)
+        .bind(&record.etag)
+        .bind(&record.last_modified)
+        .bind(&record.content)
+        .bind(record.status.to_string())
+        .bind(record.sticker_count)
+        .bind(&record.error)
+        .bind(record.checked_at.to_rfc3339())
+        .bind(record.changed_at.map(|t| t.to_rfc3339()))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// 刪除已不在配置中的 HTTP 貼圖來源紀錄，`keep` 為（分類, 網址），回傳刪除的數量
+    pub async fn prune_sticker_sources(&self, keep: &[(String, String)]) -> Result<usize> {
+        let existing: Vec<(String, String)> =
+            sqlx::query_as("SELECT category, url FROM sticker_sources")
+                .fetch_all(&self.pool)
+                .await?;
+        let mut removed = 0;
+        for (category, url) in existing {
+            if keep.iter().any(|(c, u)| *c == category && *u == url) {
+                continue;
+            }
+            sqlx::query("DELETE FROM sticker_sources WHERE category = ? AND url = ?")
+                .bind(&category)
+                .bind(&url)
+                .execute(&self.pool)
+                .await?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    /// 取得 feed 上次讀取的紀錄
+    pub async fn get_feed_state(&self, name: &str) -> Result<Option<FeedStateRecord>> {
+        let row = sqlx::query(
+            "SELECT name, etag, last_modified, error, checked_at, succeeded_at
+             FROM feed_state WHERE name = ?",
+        )
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(|row| FeedStateRecord::from_row(&row)).transpose()
+    }
+
+    /// 寫入 feed 這次讀取的結果
+    pub async fn save_feed_state(&self, record: &FeedStateRecord) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO feed_state (name, etag, last_modified, error, checked_at, succeeded_at)
+             VALUES (?, ?, ?, ?, ?, ?)
+             ON CONFLICT(name) DO UPDATE SET
+                etag = excluded.etag,
+                last_modified = excluded.last_modified,
+                error = excluded.error,
+                checked_at = excluded.checked_at,
+                succeeded_at = excluded.succeeded_at",
+        )
+        .bind(&record.name)
+        .bind(&record.etag)
+        .bind(&record.last_modified)
+        .bind(&record.error)
+        .bind(record.checked_at.to_rfc3339())
+        .bind(record.succeeded_at.map(|t| t.to_rfc3339()))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// 從 `item_ids` 中挑出尚未貼過的項目，保持原本的順序
+    pub async fn unseen_feed_items(&self, feed: &str, item_ids: &[String]) -> Result<Vec<String>> {
+        let seen: HashSet<String> =
+            sqlx::query_scalar::<_, String>("SELECT item_id FROM feed_items WHERE feed = ?")
+                .bind(feed)
+                .fetch_all(&self.pool)
+                .await?
+                .into_iter()
+                .collect();
+        Ok(item_ids
+            .iter()
+            .filter(|id| !seen.contains(*id))
+            .cloned()
+            .collect())
+    }
+
+    /// 記錄 feed 項目已貼過（或略過）
+    pub async fn mark_feed_items_seen(&self, feed: &str, item_ids: &[String]) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let mut tx = conn.begin().await?;
+        let now = Utc::now().to_rfc3339();
+        for item_id in item_ids {
+            sqlx::query(
+                "INSERT OR IGNORE INTO feed_items (feed, item_id, seen_at) VALUES (?, ?, ?)",
+            )
+            .bind(feed)
+            .bind(item_id)
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Count total stickers
+    pub async fn count_stickers(&self) -> Result<i64> {
+        let cnt: i64 = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM stickers")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(cnt)
+    }
+
+    /// Get category statistics (category -> count)
+    pub async fn get_sticker_category_stats(&self) -> Result<HashMap<String, i64>> {
+        let rows = sqlx::query("SELECT category, COUNT(*) as cnt FROM stickers GROUP BY category")
+            .fetch_all(self.read_pool())
+            .await?;
+
+        let mut map = HashMap::new();
+        for r in rows {
+            let category: String = r.try_get("category")?;
+            let cnt: i64 = r.try_get("cnt")?;
+            map.insert(category, cnt);
+        }
+        Ok(map)
+    }
+
+    /// 記錄一次貼圖發送
+    pub async fn record_sticker_usage(&self, image_url: &str, user_id: &str) -> Result<()> {
+        sqlx::query("INSERT INTO sticker_usage (image_url, user_id, used_at) VALUES (?, ?, ?)")
+            .bind(image_url)
+            .bind(user_id)
+            .bind(Utc::now().to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// 指定時間之後最常發送的貼圖，已從貼圖庫移除的不列入
+    pub async fn get_trending_stickers(
+        &self,
+        since: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<(Sticker, i64)>> {
+        let rows = sqlx::query(
+            "SELECT s.name, s.image_url, s.category, COUNT(*) AS uses
+             FROM sticker_usage u
+             JOIN stickers s ON s.image_url = u.image_url
+             WHERE u.used_at >= ?
+             GROUP BY s.image_url
+             ORDER BY uses DESC, s.name
+             LIMIT ?",
+        )
+        .bind(since.to_rfc3339())
+        .bind(limit)
+        .fetch_all(self.read_pool())
+        .await?;
+
+        rows.into_iter()
+            .map(|r| {
+                Ok((
+                    Sticker {
+                        name: r.try_get("name")?,
+                        image_url: r.try_get("image_url")?,
+                        category: r.try_get("category")?,
+                    },
+                    r.try_get("uses")?,
+                ))
+            })
+            .collect()
+    }
+
+    /// 指定時間之後各分類的貼圖發送次數，由多到少。已從貼圖庫移除的貼圖不列入
+    pub async fn get_sticker_usage_by_category(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<(String, i64)>> {
+        let rows = sqlx::query(
+            "SELECT s.category, COUNT(*) AS uses
+             FROM sticker_usage u
+             JOIN stickers s ON s.image_url = u.image_url
+             WHERE u.used_at >= ?
+             GROUP BY s.category
+             ORDER BY uses DESC, s.category",
+        )
+        .bind(since.to_rfc3339())
+        .fetch_all(self.read_pool())
+        .await?;
+
+        rows.into_iter()
+            .map(|r| Ok((r.try_get("category")?, r.try_get("uses")?)))
+            .collect()
+    }
+
+    /// 指定時間之後發送最多貼圖的使用者（user_id 與次數），包含已移除的貼圖
+    pub async fn get_sticker_usage_by_user(
+        &self,
+        since: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<(String, i64)>> {
+        let rows = sqlx::query(
+            "SELECT user_id, COUNT(*) AS uses
+             FROM sticker_usage
+             WHERE used_at >= ?
+             GROUP BY user_id
+             ORDER BY uses DESC, user_id
+             LIMIT ?",
+        )
+        .bind(since.to_rfc3339())
+        .bind(limit)
+        .fetch_all(self.read_pool())
+        .await?;
+
+        rows.into_iter()
+            .map(|r| Ok((r.try_get("user_id")?, r.try_get("uses")?)))
+            .collect()
+    }
+
+    /// 使用者最近發送過的貼圖，同一張只列一次、最近發送的在前，已從貼圖庫移除的不列入
+    pub async fn get_recent_stickers(&self, user_id: &str, limit: i64) -> Result<Vec<Sticker>> {
+        let rows = sqlx::query(
+            "SELECT s.name, s.image_url, s.category, MAX(u.used_at) AS last_used
+             FROM sticker_usage u
+             JOIN stickers s ON s.image_url = u.image_url
+             WHERE u.user_id = ?
+             GROUP BY s.image_url
+             ORDER BY last_used DESC, s.name
+             LIMIT ?",
+        )
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(self.read_pool())
+        .await?;
+
+        rows.into_iter()
+            .map(|r| {
+                Ok(Sticker {
+                    name: r.try_get("name")?,
+                    image_url: r.try_get("image_url")?,
+                    category: r.try_get("category")?,
+                })
+            })
+            .collect()
+    }
+
+    /// 記錄剛發出的貼圖選擇器
+    pub async fn create_sticker_picker(
+        &self,
+        id: &str,
+        channel_id: &str,
+        user_id: &str,
+    ) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        sqlx::query(
+            "INSERT INTO sticker_pickers (id, channel_id, user_id, created_at, last_active_at)
+             VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(id)
+        .bind(channel_id)
+        .bind(user_id)
+        .bind(&now)
+        .bind(&now)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// 選擇器有操作時更新最後活動時間，並記下貼文 ID
+    pub async fn touch_sticker_picker(&self, id: &str, post_id: &str) -> Result<()> {
+        sqlx::query(
+            "UPDATE sticker_pickers SET last_active_at = ?, post_id = COALESCE(NULLIF(?, ''), post_id)
+             WHERE id = ?",
+        )
+        .bind(Utc::now().to_rfc3339())
+        .bind(post_id)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// 選擇器已發送、取消或清除，不再追蹤
+    pub async fn delete_sticker_picker(&self, id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM sticker_pickers WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
 
-            if res.rows_affected() > 0 {
-                inserted += 1;
-            }
-        }
+    /// 最後活動時間早於 `before` 的選擇器
+    pub async fn get_stale_sticker_pickers(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<StickerPickerRecord>> {
+        let rows = sqlx::query(
+            "SELECT id, channel_id, user_id, post_id FROM sticker_pickers
+             WHERE last_active_at < ? ORDER BY last_active_at",
+        )
+        .bind(before.to_rfc3339())
+        .fetch_all(&self.pool)
+        .await?;
 
-        // no FTS population during replace — using LIKE-based searches instead
+        rows.iter()
+            .map(|row| {
+                Ok(StickerPickerRecord {
+                    id: row.try_get("id")?,
+                    channel_id: row.try_get("channel_id")?,
+                    user_id: row.try_get("user_id")?,
+                    post_id: row.try_get("post_id")?,
+                })
+            })
+            .collect()
+    }
 
-        tx.commit().await?;
-        Ok(inserted)
+    /// 將貼圖加入使用者的最愛，已加入時不做任何事
+    pub async fn add_favorite_sticker(&self, user_id: &str, image_url: &str) -> Result<()> {
+        sqlx::query(
+            "INSERT OR IGNORE INTO user_favorites (user_id, image_url, created_at) VALUES (?, ?, ?)",
+        )
+        .bind(user_id)
+        .bind(image_url)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
     }
 
-    /// Count total stickers
-    pub async fn count_stickers(&self) -> Result<i64> {
-        let cnt: i64 = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM stickers")
-            .fetch_one(&self.pool)
+    /// 將貼圖從使用者的最愛移除
+    pub async fn remove_favorite_sticker(&self, user_id: &str, image_url: &str) -> Result<()> {
+        sqlx::query("DELETE FROM user_favorites WHERE user_id = ? AND image_url = ?")
+            .bind(user_id)
+            .bind(image_url)
+            .execute(&self.pool)
             .await?;
-        Ok(cnt)
+        Ok(())
     }
 
-    /// Get category statistics (category -> count)
-    pub async fn get_sticker_category_stats(&self) -> Result<HashMap<String, i64>> {
-        let rows = sqlx::query("SELECT category, COUNT(*) as cnt FROM stickers GROUP BY category")
-            .fetch_all(&self.pool)
+    /// 貼圖是否在使用者的最愛中
+    pub async fn is_favorite_sticker(&self, user_id: &str, image_url: &str) -> Result<bool> {
+        let row = sqlx::query("SELECT 1 FROM user_favorites WHERE user_id = ? AND image_url = ?")
+            .bind(user_id)
+            .bind(image_url)
+            .fetch_optional(self.read_pool())
             .await?;
+        Ok(row.is_some())
+    }
 
-        let mut map = HashMap::new();
-        for r in rows {
-            let category: String = r.try_get("category")?;
-            let cnt: i64 = r.try_get("cnt")?;
-            map.insert(category, cnt);
-        }
-        Ok(map)
+    /// 使用者的最愛貼圖，最近加入的在前，已從貼圖庫移除的不列入
+    pub async fn get_favorite_stickers(&self, user_id: &str) -> Result<Vec<Sticker>> {
+        let rows = sqlx::query(
+            "SELECT s.name, s.image_url, s.category
+             FROM user_favorites f
+             JOIN stickers s ON s.image_url = f.image_url
+             WHERE f.user_id = ?
+             ORDER BY f.created_at DESC, s.name",
+        )
+        .bind(user_id)
+        .fetch_all(self.read_pool())
+        .await?;
+
+        rows.into_iter()
+            .map(|r| {
+                Ok(Sticker {
+                    name: r.try_get("name")?,
+                    image_url: r.try_get("image_url")?,
+                    category: r.try_get("category")?,
+                })
+            })
+            .collect()
     }
 
     /// Search stickers with include/exclude keywords and optional category filters.
@@ -397,6 +3304,29 @@ impl Database {
         categories_filter: Option<&[String]>,
         limit: i64,
     ) -> Result<Vec<Sticker>> {
+        let include_groups: Vec<Vec<String>> =
+            include_keywords.iter().map(|kw| vec![kw.clone()]).collect();
+        self.search_stickers_grouped(
+            opt_category,
+            &include_groups,
+            exclude_keywords,
+            categories_filter,
+            limit,
+        )
+        .await
+    }
+
+    /// Search stickers where `include_groups` are AND-ed together and the
+    /// keywords inside each group are OR-ed (e.g. `a|b c` -> `[[a, b], [c]]`).
+    pub async fn search_stickers_grouped(
+        &self,
+        opt_category: Option<&str>,
+        include_groups: &[Vec<String>],
+        exclude_keywords: &[String],
+        categories_filter: Option<&[String]>,
+        limit: i64,
+    ) -> Result<Vec<Sticker>> {
+        let _timer = self.metrics.timer("db", "search_stickers_grouped");
         let mut sql = String::from("SELECT name, image_url, category FROM stickers");
         let mut where_clauses: Vec<String> = Vec::new();
         let mut binds: Vec<String> = Vec::new();
@@ -414,9 +3344,16 @@ impl Database {
             }
         }
 
-        for kw in include_keywords.iter() {
-            where_clauses.push("LOWER(name) LIKE LOWER(?)".to_string());
-            binds.push(format!("%{}%", kw));
+        for group in include_groups.iter().filter(|g| !g.is_empty()) {
+            let alternatives = group
+                .iter()
+                .map(|_| "LOWER(name) LIKE LOWER(?)")
+                .collect::<Vec<_>>()
+                .join(" OR ");
+            where_clauses.push(format!("({})", alternatives));
+            for kw in group.iter() {
+                binds.push(format!("%{}%", kw));
+            }
         }
 
         if !exclude_keywords.is_empty() {
@@ -457,134 +3394,663 @@ impl Database {
             });
         }
 
-        Ok(stickers_out)
+        Ok(stickers_out)
+    }
+
+    /// 記錄操作日誌
+    pub async fn log_action(
+        &self,
+        group_buy_id: &str,
+        user_id: &str,
+        username: &str,
+        action: &str,
+        details: Option<&str>,
+    ) -> Result<()> {
+        // Callers should supply a JSON object that includes a "version" key.
+        // Anything else is wrapped so `details` is always a JSON object.
+        let details_min = normalize_log_details(details);
+
+        let created = Utc::now().to_rfc3339();
+        sqlx::query!(
+            "INSERT INTO group_buy_logs (group_buy_id, user_id, username, action, details, created_at)
+             VALUES (?, ?, ?, ?, ?, ?)",
+            group_buy_id,
+            user_id,
+            username,
+            action,
+            details_min,
+            created
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// 建立新團購
+    pub async fn create_group_buy(&self, group_buy: &GroupBuy) -> Result<()> {
+        let _timer = self.metrics.timer("db", "create_group_buy");
+        let metadata_json = serde_json::to_string(&group_buy.metadata)?;
+        let items_json = serde_json::to_string(&group_buy.items)?;
+        let item_icons_json = serde_json::to_string(&group_buy.item_icons)?;
+        let item_sections_json = serde_json::to_string(&group_buy.item_sections)?;
+        let item_translations_json = serde_json::to_string(&group_buy.item_translations)?;
+        let order_fields_json = serde_json::to_string(&group_buy.order_fields)?;
+        let gb_currency = group_buy.currency.clone();
+
+        // materialize owned values for sqlx macros
+        let gb_id = group_buy.id.clone();
+        let gb_creator_id = group_buy.creator_id.clone();
+        let gb_creator_username = group_buy.creator_username.clone();
+        let gb_channel_id = group_buy.channel_id.clone();
+        let gb_post_id = group_buy.post_id.clone();
+        let gb_merchant_name = group_buy.merchant_name.clone();
+        let gb_description = group_buy.description.clone();
+        let gb_status = group_buy.status.to_string();
+        let gb_created_at = group_buy.created_at.to_rfc3339();
+        let gb_updated_at = group_buy.updated_at.to_rfc3339();
+
+        sqlx::query!(
+            "INSERT INTO group_buys (
+                id, creator_id, creator_username, channel_id, post_id,
+                merchant_name, description, metadata, items, item_icons, item_sections,
+                item_translations, order_fields, currency, status, version, created_at, updated_at
+             ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            gb_id,
+            gb_creator_id,
+            gb_creator_username,
+            gb_channel_id,
+            gb_post_id,
+            gb_merchant_name,
+            gb_description,
+            metadata_json,
+            items_json,
+            item_icons_json,
+            item_sections_json,
+            item_translations_json,
+            order_fields_json,
+            gb_currency,
+            gb_status,
+            group_buy.version,
+            gb_created_at,
+            gb_updated_at
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // details must be JSON with version key (minified)
+        let details_json = serde_json::json!({
+            "merchant_name": group_buy.merchant_name,
+            "action": "create",
+            "version": group_buy.version,
+        });
+        let details = serde_json::to_string(&details_json)?;
+        self.log_action(
+            &group_buy.id,
+            &group_buy.creator_id,
+            &group_buy.creator_username,
+            "create",
+            Some(&details),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// 取得團購資料
+    pub async fn get_group_buy(&self, id: &str) -> Result<Option<GroupBuy>> {
+        let _timer = self.metrics.timer("db", "get_group_buy");
+        let result = sqlx::query_as!(
+            GroupBuyRow,
+            "SELECT id, creator_id, creator_username, channel_id, post_id,
+                    merchant_name, description, metadata, items, item_icons, item_sections,
+                    item_translations, order_fields, currency, status, version, created_at, updated_at
+             FROM group_buys WHERE id = ?",
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(result.map(|row| row.into()))
+    }
+
+    /// 以團購貼文（包含轉發到其他頻道的貼文）的 ID 找出團購，例如處理貼文上的表情回應
+    pub async fn get_group_buy_by_post_id(&self, post_id: &str) -> Result<Option<GroupBuy>> {
+        let row = sqlx::query_as::<_, GroupBuyRow>(
+            "SELECT id, creator_id, creator_username, channel_id, post_id,
+                    merchant_name, description, metadata, items, item_icons, item_sections,
+                    item_translations, order_fields, currency, status, version, created_at, updated_at
+             FROM group_buys
+             WHERE post_id = ?
+                OR id IN (SELECT group_buy_id FROM group_buy_posts WHERE post_id = ?)",
+        )
+        .bind(post_id)
+        .bind(post_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(GroupBuy::from))
+    }
+
+    /// 使用者在頻道中最近建立、有實際商品的團購，用來存成範本
+    pub async fn get_latest_group_buy_by_creator(
+        &self,
+        channel_id: &str,
+        creator_id: &str,
+    ) -> Result<Option<GroupBuy>> {
+        let rows = sqlx::query_as::<_, GroupBuyRow>(
+            "SELECT id, creator_id, creator_username, channel_id, post_id,
+                    merchant_name, description, metadata, items, item_icons, item_sections,
+                    item_translations, order_fields, currency, status, version, created_at, updated_at
+             FROM group_buys
+             WHERE channel_id = ? AND creator_id = ?
+             ORDER BY created_at DESC",
+        )
+        .bind(channel_id)
+        .bind(creator_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(GroupBuy::from)
+            .find(|gb| !gb.items.is_empty() && !gb.items.contains_key("範例商品")))
+    }
+
+    /// 既有的商家名稱，依最近一次建立團購的時間排序
+    pub async fn get_merchant_names(&self, limit: i64) -> Result<Vec<String>> {
+        let names = sqlx::query_scalar::<_, String>(
+            "SELECT merchant_name FROM group_buys
+             GROUP BY merchant_name
+             ORDER BY MAX(created_at) DESC
+             LIMIT ?",
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(names)
+    }
+
+    /// 同商家最近的團購（不含 `exclude_id`），編輯商品時用來載入之前的菜單。
+    /// 只有範例商品的團購不列入。
+    pub async fn get_recent_group_buys_by_merchant(
+        &self,
+        merchant_name: &str,
+        exclude_id: &str,
+        limit: usize,
+    ) -> Result<Vec<GroupBuy>> {
+        let _timer = self
+            .metrics
+            .timer("db", "get_recent_group_buys_by_merchant");
+        let rows = sqlx::query_as::<_, GroupBuyRow>(
+            "SELECT id, creator_id, creator_username, channel_id, post_id,
+                    merchant_name, description, metadata, items, item_icons, item_sections,
+                    item_translations, order_fields, currency, status, version, created_at, updated_at
+             FROM group_buys
+             WHERE merchant_name = ? AND id != ?
+             ORDER BY created_at DESC",
+        )
+        .bind(merchant_name)
+        .bind(exclude_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(GroupBuy::from)
+            .filter(|gb| !gb.items.is_empty() && !gb.items.contains_key("範例商品"))
+            .take(limit)
+            .collect())
+    }
+
+    /// 設定團購的自動截止時間，已設定時覆蓋
+    pub async fn set_deadline(&self, group_buy_id: &str, close_at: DateTime<Utc>) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO group_buy_deadlines (group_buy_id, close_at) VALUES (?, ?)
+             ON CONFLICT(group_buy_id) DO UPDATE SET close_at = excluded.close_at",
+        )
+        .bind(group_buy_id)
+        .bind(close_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// 截止時間已到（`close_at <= now`）的團購 ID，依截止時間排序
+    pub async fn get_due_deadlines(&self, now: DateTime<Utc>) -> Result<Vec<String>> {
+        let ids = sqlx::query_scalar::<_, String>(
+            "SELECT group_buy_id FROM group_buy_deadlines WHERE close_at <= ? ORDER BY close_at",
+        )
+        .bind(now.to_rfc3339())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(ids)
+    }
+
+    /// 移除團購的自動截止時間
+    pub async fn clear_deadline(&self, group_buy_id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM group_buy_deadlines WHERE group_buy_id = ?")
+            .bind(group_buy_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// 設定團購的截止前提醒時間，已設定時覆蓋
+    pub async fn set_reminder(&self, group_buy_id: &str, remind_at: DateTime<Utc>) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO group_buy_reminders (group_buy_id, remind_at) VALUES (?, ?)
+             ON CONFLICT(group_buy_id) DO UPDATE SET remind_at = excluded.remind_at",
+        )
+        .bind(group_buy_id)
+        .bind(remind_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// 提醒時間已到的團購 ID 與截止時間（截止時間已移除時為 None），依提醒時間排序
+    pub async fn get_due_reminders(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<(String, Option<DateTime<Utc>>)>> {
+        let rows = sqlx::query(
+            "SELECT r.group_buy_id, d.close_at
+             FROM group_buy_reminders r
+             LEFT JOIN group_buy_deadlines d ON d.group_buy_id = r.group_buy_id
+             WHERE r.remind_at <= ? ORDER BY r.remind_at",
+        )
+        .bind(now.to_rfc3339())
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let close_at: Option<String> = row.try_get("close_at")?;
+                let close_at = close_at
+                    .map(|s| DateTime::parse_from_rfc3339(&s).map(|t| t.with_timezone(&Utc)))
+                    .transpose()?;
+                Ok((row.try_get("group_buy_id")?, close_at))
+            })
+            .collect()
+    }
+
+    /// 移除團購的截止前提醒
+    pub async fn clear_reminder(&self, group_buy_id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM group_buy_reminders WHERE group_buy_id = ?")
+            .bind(group_buy_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// 已標記為已付款的購買人 ID
+    pub async fn get_paid_buyers(&self, group_buy_id: &str) -> Result<HashSet<String>> {
+        let buyers: Vec<String> =
+            sqlx::query_scalar("SELECT buyer_id FROM payments WHERE group_buy_id = ?")
+                .bind(group_buy_id)
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(buyers.into_iter().collect())
+    }
+
+    /// 以 `paid_buyer_ids` 取代團購的付款狀態：列出的購買人為已付款，其他人為未付款。
+    /// 原本已付款的購買人保留原本的付款時間
+    pub async fn set_paid_buyers(
+        &self,
+        group_buy_id: &str,
+        paid_buyer_ids: &[String],
+        marked_by: &str,
+    ) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        let mut tx = self.pool.begin().await?;
+        let paid_json = serde_json::to_string(paid_buyer_ids)?;
+        sqlx::query(
+            "DELETE FROM payments WHERE group_buy_id = ?
+             AND buyer_id NOT IN (SELECT value FROM json_each(?))",
+        )
+        .bind(group_buy_id)
+        .bind(&paid_json)
+        .execute(&mut *tx)
+        .await?;
+        for buyer_id in paid_buyer_ids {
+            sqlx::query(
+                "INSERT INTO payments (group_buy_id, buyer_id, marked_by, paid_at)
+                 VALUES (?, ?, ?, ?)
+                 ON CONFLICT(group_buy_id, buyer_id) DO NOTHING",
+            )
+            .bind(group_buy_id)
+            .bind(buyer_id)
+            .bind(marked_by)
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// 儲存團購範本，頻道中已有同名範本時覆蓋
+    pub async fn save_group_buy_template(
+        &self,
+        template: &GroupBuyTemplate,
+        saved_by: &str,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO group_buy_templates
+                (channel_id, name, merchant_name, description, metadata, items, item_icons,
+                 item_sections, item_translations, saved_by, updated_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT(channel_id, name) DO UPDATE SET
+                merchant_name = excluded.merchant_name,
+                description = excluded.description,
+                metadata = excluded.metadata,
+                items = excluded.items,
+                item_icons = excluded.item_icons,
+                item_sections = excluded.item_sections,
+                item_translations = excluded.item_translations,
+                saved_by = excluded.saved_by,
+                updated_at = excluded.updated_at",
+        )
+        .bind(&template.channel_id)
+        .bind(&template.name)
+        .bind(&template.merchant_name)
+        .bind(&template.description)
+        .bind(serde_json::to_string(&template.metadata)?)
+        .bind(serde_json::to_string(&template.items)?)
+        .bind(serde_json::to_string(&template.item_icons)?)
+        .bind(serde_json::to_string(&template.item_sections)?)
+        .bind(serde_json::to_string(&template.item_translations)?)
+        .bind(saved_by)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// 取得頻道中的團購範本
+    pub async fn get_group_buy_template(
+        &self,
+        channel_id: &str,
+        name: &str,
+    ) -> Result<Option<GroupBuyTemplate>> {
+        let row = sqlx::query(
+            "SELECT channel_id, name, merchant_name, description, metadata, items, item_icons,
+                    item_sections, item_translations
+             FROM group_buy_templates WHERE channel_id = ? AND name = ?",
+        )
+        .bind(channel_id)
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| -> Result<GroupBuyTemplate> {
+            let json = |column: &str| -> Result<String> { Ok(row.try_get(column)?) };
+            Ok(GroupBuyTemplate {
+                channel_id: row.try_get("channel_id")?,
+                name: row.try_get("name")?,
+                merchant_name: row.try_get("merchant_name")?,
+                description: row.try_get("description")?,
+                metadata: serde_json::from_str(&json("metadata")?)?,
+                items: serde_json::from_str(&json("items")?)?,
+                item_icons: serde_json::from_str(&json("item_icons")?)?,
+                item_sections: serde_json::from_str(&json("item_sections")?)?,
+                item_translations: serde_json::from_str(&json("item_translations")?)?,
+            })
+        })
+        .transpose()
+    }
+
+    /// 頻道中的團購範本名稱與商家名稱，依名稱排序
+    pub async fn list_group_buy_templates(
+        &self,
+        channel_id: &str,
+    ) -> Result<Vec<(String, String)>> {
+        let rows = sqlx::query_as::<_, (String, String)>(
+            "SELECT name, merchant_name FROM group_buy_templates
+             WHERE channel_id = ? ORDER BY name",
+        )
+        .bind(channel_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    /// 刪除團購範本，回傳是否有刪除
+    pub async fn delete_group_buy_template(&self, channel_id: &str, name: &str) -> Result<bool> {
+        let result =
+            sqlx::query("DELETE FROM group_buy_templates WHERE channel_id = ? AND name = ?")
+                .bind(channel_id)
+                .bind(name)
+                .execute(&self.pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
     }
 
-    /// 記錄操作日誌
-    pub async fn log_action(
+    /// 記下要轉發團購貼文的頻道，已記下的頻道略過。貼文發出後以 `set_cross_post_id` 補上 ID
+    pub async fn add_cross_post_channels(
         &self,
         group_buy_id: &str,
-        user_id: &str,
-        username: &str,
-        action: &str,
-        details: Option<&str>,
+        channel_ids: &[String],
     ) -> Result<()> {
-        // Use the provided `details` string as-is. Callers are responsible for
-        // supplying a minified JSON string that includes a "version" key.
-        // If None is provided, record an empty JSON object.
-        let details_min = details
-            .map(|s| s.to_string())
-            .unwrap_or_else(|| "{}".to_string());
+        let now = Utc::now().to_rfc3339();
+        let mut tx = self.pool.begin().await?;
+        for channel_id in channel_ids {
+            sqlx::query(
+                "INSERT INTO group_buy_posts (group_buy_id, channel_id, post_id, created_at)
+                 VALUES (?, ?, NULL, ?)
+                 ON CONFLICT(group_buy_id, channel_id) DO NOTHING",
+            )
+            .bind(group_buy_id)
+            .bind(channel_id)
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
 
-        let created = Utc::now().to_rfc3339();
-        sqlx::query!(
-            "INSERT INTO group_buy_logs (group_buy_id, user_id, username, action, details, created_at)
-             VALUES (?, ?, ?, ?, ?, ?)",
-            group_buy_id,
-            user_id,
-            username,
-            action,
-            details_min,
-            created
+    /// 團購轉發到其他頻道的貼文（channel_id, post_id），還沒發出的貼文 post_id 為 None
+    pub async fn get_cross_posts(
+        &self,
+        group_buy_id: &str,
+    ) -> Result<Vec<(String, Option<String>)>> {
+        let rows = sqlx::query_as::<_, (String, Option<String>)>(
+            "SELECT channel_id, post_id FROM group_buy_posts
+             WHERE group_buy_id = ? ORDER BY created_at, channel_id",
+        )
+        .bind(group_buy_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    /// 記下轉發到頻道的貼文 ID
+    pub async fn set_cross_post_id(
+        &self,
+        group_buy_id: &str,
+        channel_id: &str,
+        post_id: &str,
+    ) -> Result<()> {
+        sqlx::query(
+            "UPDATE group_buy_posts SET post_id = ? WHERE group_buy_id = ? AND channel_id = ?",
         )
+        .bind(post_id)
+        .bind(group_buy_id)
+        .bind(channel_id)
         .execute(&self.pool)
         .await?;
+        Ok(())
+    }
 
+    /// 是否為轉發到其他頻道的團購貼文
+    pub async fn is_cross_post(&self, post_id: &str) -> Result<bool> {
+        let count: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM group_buy_posts WHERE post_id = ?")
+                .bind(post_id)
+                .fetch_one(&self.pool)
+                .await?;
+        Ok(count > 0)
+    }
+
+    /// 不再更新轉發的貼文（貼文已被刪除）
+    pub async fn delete_cross_post(&self, post_id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM group_buy_posts WHERE post_id = ?")
+            .bind(post_id)
+            .execute(&self.pool)
+            .await?;
         Ok(())
     }
 
-    /// 建立新團購
-    pub async fn create_group_buy(&self, group_buy: &GroupBuy) -> Result<()> {
-        let metadata_json = serde_json::to_string(&group_buy.metadata)?;
-        let items_json = serde_json::to_string(&group_buy.items)?;
+    /// 刪除團購，訂單、紀錄與截止時間一併刪除（外鍵 ON DELETE CASCADE）。
+    /// 只用於建立失敗時撤銷剛寫入的資料，回傳是否有刪除
+    pub async fn delete_group_buy(&self, group_buy_id: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM group_buys WHERE id = ?")
+            .bind(group_buy_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
 
-        // materialize owned values for sqlx macros
-        let gb_id = group_buy.id.clone();
-        let gb_creator_id = group_buy.creator_id.clone();
-        let gb_creator_username = group_buy.creator_username.clone();
-        let gb_channel_id = group_buy.channel_id.clone();
-        let gb_post_id = group_buy.post_id.clone();
-        let gb_merchant_name = group_buy.merchant_name.clone();
-        let gb_description = group_buy.description.clone();
-        let gb_status = group_buy.status.to_string();
-        let gb_created_at = group_buy.created_at.to_rfc3339();
-        let gb_updated_at = group_buy.updated_at.to_rfc3339();
+    /// 取得頻道設定，沒有設定過時回傳預設值
+    pub async fn get_channel_settings(&self, channel_id: &str) -> Result<ChannelSettings> {
+        let row = sqlx::query(
+            "SELECT currency, quiet_hours, default_deadline_minutes, allowed_creators
+             FROM channel_settings WHERE channel_id = ?",
+        )
+        .bind(channel_id)
+        .fetch_optional(&self.pool)
+        .await?;
 
-        sqlx::query!(
-            "INSERT INTO group_buys (
-                id, creator_id, creator_username, channel_id, post_id,
-                merchant_name, description, metadata, items, status,
-                version, created_at, updated_at
-             ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
-            gb_id,
-            gb_creator_id,
-            gb_creator_username,
-            gb_channel_id,
-            gb_post_id,
-            gb_merchant_name,
-            gb_description,
-            metadata_json,
-            items_json,
-            gb_status,
-            group_buy.version,
-            gb_created_at,
-            gb_updated_at
+        let Some(row) = row else {
+            return Ok(ChannelSettings {
+                channel_id: channel_id.to_string(),
+                ..Default::default()
+            });
+        };
+        let allowed_creators: Option<String> = row.try_get("allowed_creators")?;
+        Ok(ChannelSettings {
+            channel_id: channel_id.to_string(),
+            currency: row.try_get("currency")?,
+            quiet_hours: row.try_get("quiet_hours")?,
+            default_deadline_minutes: row.try_get("default_deadline_minutes")?,
+            allowed_creators: allowed_creators
+                .as_deref()
+                .and_then(|s| serde_json::from_str(s).ok())
+                .unwrap_or_default(),
+        })
+    }
+
+    /// 儲存頻道設定（整筆覆蓋）
+    pub async fn save_channel_settings(
+        &self,
+        settings: &ChannelSettings,
+        updated_by: &str,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO channel_settings (
+                channel_id, currency, quiet_hours, default_deadline_minutes,
+                allowed_creators, updated_by, updated_at
+             ) VALUES (?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT(channel_id) DO UPDATE SET
+                currency = excluded.currency,
+                quiet_hours = excluded.quiet_hours,
+                default_deadline_minutes = excluded.default_deadline_minutes,
+                allowed_creators = excluded.allowed_creators,
+                updated_by = excluded.updated_by,
+                updated_at = excluded.updated_at",
         )
+        .bind(&settings.channel_id)
+        .bind(&settings.currency)
+        .bind(&settings.quiet_hours)
+        .bind(settings.default_deadline_minutes)
+        .bind(serde_json::to_string(&settings.allowed_creators)?)
+        .bind(updated_by)
+        .bind(Utc::now().to_rfc3339())
         .execute(&self.pool)
         .await?;
+        Ok(())
+    }
 
-        // details must be JSON with version key (minified)
-        let details_json = serde_json::json!({
-            "merchant_name": group_buy.merchant_name,
-            "action": "create",
-            "version": group_buy.version,
-        });
-        let details = serde_json::to_string(&details_json)?;
-        self.log_action(
-            &group_buy.id,
-            &group_buy.creator_id,
-            &group_buy.creator_username,
-            "create",
-            Some(&details),
+    /// 取得使用者偏好設定
+    pub async fn get_user_preference(&self, user_id: &str, key: &str) -> Result<Option<String>> {
+        let value = sqlx::query_scalar::<_, String>(
+            "SELECT value FROM user_preferences WHERE user_id = ? AND key = ?",
         )
+        .bind(user_id)
+        .bind(key)
+        .fetch_optional(&self.pool)
         .await?;
+        Ok(value)
+    }
 
-        Ok(())
+    /// 使用者所有的偏好設定，依名稱排序
+    pub async fn list_user_preferences(&self, user_id: &str) -> Result<Vec<(String, String)>> {
+        let rows = sqlx::query_as::<_, (String, String)>(
+            "SELECT key, value FROM user_preferences WHERE user_id = ? ORDER BY key",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
     }
 
-    /// 取得團購資料
-    pub async fn get_group_buy(&self, id: &str) -> Result<Option<GroupBuy>> {
-        let result = sqlx::query_as!(
-            GroupBuyRow,
-            "SELECT id, creator_id, creator_username, channel_id, post_id,
-                    merchant_name, description, metadata, items, status,
-                    version, created_at, updated_at
-             FROM group_buys WHERE id = ?",
-            id
+    /// 設定使用者偏好，已存在時覆蓋
+    pub async fn set_user_preference(&self, user_id: &str, key: &str, value: &str) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO user_preferences (user_id, key, value, updated_at) VALUES (?, ?, ?, ?)
+             ON CONFLICT(user_id, key) DO UPDATE SET
+                value = excluded.value,
+                updated_at = excluded.updated_at",
         )
-        .fetch_optional(&self.pool)
+        .bind(user_id)
+        .bind(key)
+        .bind(value)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
         .await?;
+        Ok(())
+    }
 
-        Ok(result.map(|row| row.into()))
+    /// 刪除名稱以 `prefix` 開頭的偏好設定，回傳刪除的數量
+    pub async fn delete_user_preferences(&self, user_id: &str, prefix: &str) -> Result<u64> {
+        let result = sqlx::query(
+            "DELETE FROM user_preferences WHERE user_id = ? AND substr(key, 1, length(?)) = ?",
+        )
+        .bind(user_id)
+        .bind(prefix)
+        .bind(prefix)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
     }
 
     /// 更新團購商品列表
     pub async fn update_items(
         &self,
         id: &str,
-        items: &HashMap<String, Decimal>,
+        menu: &ItemList,
         expected_version: i32,
         user_id: &str,
         username: &str,
     ) -> Result<()> {
-        let items_json = serde_json::to_string(items)?;
+        let _timer = self.metrics.timer("db", "update_items");
+        let items_json = serde_json::to_string(&menu.items)?;
+        let item_icons_json = serde_json::to_string(&menu.item_icons)?;
+        let item_sections_json = serde_json::to_string(&menu.item_sections)?;
+        let item_translations_json = serde_json::to_string(&menu.item_translations)?;
 
         let updated_at = Utc::now().to_rfc3339();
         let result = sqlx::query!(
             "UPDATE group_buys 
-             SET items = ?, version = version + 1, updated_at = ?
-             WHERE id = ? AND version = ? AND status = 'active'",
+             SET items = ?, item_icons = ?, item_sections = ?, item_translations = ?,
+                 version = version + 1, updated_at = ?
+             WHERE id = ? AND version = ? AND status IN ('draft', 'active')",
             items_json,
+            item_icons_json,
+            item_sections_json,
+            item_translations_json,
             updated_at,
             id,
             expected_version
@@ -593,11 +4059,11 @@ impl Database {
         .await?;
 
         if result.rows_affected() == 0 {
-            anyhow::bail!("更新失敗：團購已被修改或已截止，請重新整理");
+            return Err(self.stale_update_error(id).await?.into());
         }
 
         let details_json = serde_json::json!({
-            "items_count": items.len(),
+            "items_count": menu.items.len(),
             "action": "update_items",
             "version": expected_version,
         });
@@ -608,6 +4074,24 @@ impl Database {
         Ok(())
     }
 
+    /// 條件更新沒有影響任何資料時，依團購目前的狀態判斷原因：
+    /// 已不存在、已截止，或是版本已被其他人更新
+    async fn stale_update_error(&self, id: &str) -> Result<CodedError> {
+        let status: Option<String> =
+            sqlx::query_scalar("SELECT status FROM group_buys WHERE id = ?")
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(match status.map(|s| GroupBuyStatus::from_string(&s)) {
+            None => CodedError::new(ErrorCode::GbNotFound, "更新失敗：找不到該團購"),
+            Some(GroupBuyStatus::Draft | GroupBuyStatus::Active) => CodedError::new(
+                ErrorCode::GbConflict,
+                "更新失敗：團購已被其他人修改，請重新整理",
+            ),
+            Some(_) => CodedError::new(ErrorCode::GbClosed, "更新失敗：團購已截止"),
+        })
+    }
+
     /// 更新團購的 post_id（第一次按鈕點擊時使用）
     pub async fn update_post_id(&self, id: &str, post_id: &str) -> Result<()> {
         let updated_at = Utc::now().to_rfc3339();
@@ -638,6 +4122,23 @@ impl Database {
         user_id: &str,
         username: &str,
     ) -> Result<()> {
+        let _timer = self.metrics.timer("db", "update_status");
+        let current: String = sqlx::query_scalar!("SELECT status FROM group_buys WHERE id = ?", id)
+            .fetch_one(&self.pool)
+            .await?;
+        let current = GroupBuyStatus::from_string(&current);
+        if !current.can_transition_to(&status) {
+            return Err(CodedError::new(
+                ErrorCode::GbInvalidStatus,
+                format!(
+                    "無法將團購從「{}」變更為「{}」",
+                    current.label(),
+                    status.label()
+                ),
+            )
+            .into());
+        }
+
         let status_str = status.to_string();
         let updated_at = Utc::now().to_rfc3339();
         let result = sqlx::query!(
@@ -653,7 +4154,11 @@ impl Database {
         .await?;
 
         if result.rows_affected() == 0 {
-            anyhow::bail!("更新失敗：團購狀態已變更，請重新整理");
+            return Err(CodedError::new(
+                ErrorCode::GbConflict,
+                "更新失敗：團購狀態已變更，請重新整理",
+            )
+            .into());
         }
 
         let details_json = serde_json::json!({
@@ -676,6 +4181,7 @@ impl Database {
 
     /// 新增訂單
     pub async fn create_order(&self, order: &GroupBuyOrder) -> Result<()> {
+        let _timer = self.metrics.timer("db", "create_order");
         // 檢查團購狀態
         let status: String = sqlx::query_scalar!(
             "SELECT status FROM group_buys WHERE id = ?",
@@ -684,8 +4190,8 @@ impl Database {
         .fetch_one(&self.pool)
         .await?;
 
-        if status != "active" {
-            anyhow::bail!("團購已截止，無法登記");
+        if !GroupBuyStatus::from_string(&status).accepts_registrations() {
+            return Err(CodedError::new(ErrorCode::GbClosed, "團購目前不開放登記").into());
         }
 
         // Materialize temporary values as locals so they live long enough for
@@ -701,14 +4207,15 @@ impl Database {
         let quantity = order.quantity as i64;
         let original_quantity = order.original_quantity.map(|v| v as i64);
         let unit_price = order.unit_price.to_string(); // 將 Decimal 轉為字串儲存
+        let custom_fields = serde_json::to_string(&order.custom_fields)?;
         let created_at = order.created_at.to_rfc3339();
 
         sqlx::query!(
             "INSERT INTO group_buy_orders (
                 id, group_buy_id, registrar_id, registrar_username,
                 buyer_id, buyer_username, item_name, quantity,
-                original_quantity, unit_price, created_at
-             ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                original_quantity, unit_price, custom_fields, created_at
+             ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
             id,
             group_buy_id,
             registrar_id,
@@ -719,6 +4226,7 @@ impl Database {
             quantity,
             original_quantity,
             unit_price,
+            custom_fields,
             created_at
         )
         .execute(&self.pool)
@@ -753,15 +4261,73 @@ impl Database {
         Ok(())
     }
 
+    /// 將數量合併到購買人既有的訂單（重複登記同一商品時選擇「合併」）
+    pub async fn merge_order_quantity(
+        &self,
+        group_buy_id: &str,
+        order_id: &str,
+        additional_quantity: i32,
+        actor_id: &str,
+        actor_username: &str,
+    ) -> Result<()> {
+        let _timer = self.metrics.timer("db", "merge_order_quantity");
+        let status: String = sqlx::query_scalar("SELECT status FROM group_buys WHERE id = ?")
+            .bind(group_buy_id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        if !GroupBuyStatus::from_string(&status).accepts_registrations() {
+            return Err(CodedError::new(ErrorCode::GbClosed, "團購目前不開放登記").into());
+        }
+
+        let result = sqlx::query(
+            "UPDATE group_buy_orders SET quantity = quantity + ?
+             WHERE id = ? AND group_buy_id = ? AND deleted_at IS NULL",
+        )
+        .bind(additional_quantity as i64)
+        .bind(order_id)
+        .bind(group_buy_id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(CodedError::new(ErrorCode::OrderNotFound, "原本的登記已被取消").into());
+        }
+
+        let version: i64 = sqlx::query_scalar("SELECT version FROM group_buys WHERE id = ?")
+            .bind(group_buy_id)
+            .fetch_one(&self.pool)
+            .await
+            .unwrap_or(0i64);
+        let details = serde_json::json!({
+            "order_id": order_id,
+            "quantity": additional_quantity,
+            "action": "merge_registration",
+            "version": version as i32,
+        })
+        .to_string();
+        self.log_action(
+            group_buy_id,
+            actor_id,
+            actor_username,
+            "merge_registration",
+            Some(&details),
+        )
+        .await?;
+
+        Ok(())
+    }
+
     /// 取得團購的所有訂單
     pub async fn get_orders_by_group_buy(&self, group_buy_id: &str) -> Result<Vec<GroupBuyOrder>> {
+        let _timer = self.metrics.timer("db", "get_orders_by_group_buy");
         let rows = sqlx::query_as!(
             GroupBuyOrderRow,
             "SELECT id, group_buy_id, registrar_id, registrar_username,
                     buyer_id, buyer_username, item_name, quantity,
-                    original_quantity, unit_price, created_at
+                    original_quantity, unit_price, custom_fields, created_at
              FROM group_buy_orders
-             WHERE group_buy_id = ?
+             WHERE group_buy_id = ? AND deleted_at IS NULL
              ORDER BY created_at ASC",
             group_buy_id
         )
@@ -771,7 +4337,8 @@ impl Database {
         Ok(rows.into_iter().map(|row| row.into()).collect())
     }
 
-    /// 刪除特定買家在特定商品的所有訂單（用於數量為 0 的情況）
+    /// 軟刪除特定買家在特定商品的所有訂單（用於數量為 0 的情況），
+    /// 可在 [`ORDER_RESTORE_GRACE_MINUTES`] 內以 `restore_deleted_orders` 復原
     pub async fn delete_buyer_item_orders(
         &self,
         group_buy_id: &str,
@@ -779,9 +4346,15 @@ impl Database {
         item_name: &str,
         actor_id: &str,
         actor_username: &str,
-    ) -> Result<u64> {
+    ) -> Result<DeletedOrders> {
+        let _timer = self.metrics.timer("db", "delete_buyer_item_orders");
+        let batch_id = uuid::Uuid::new_v4().to_string();
+        let now = Utc::now().to_rfc3339();
         let result = sqlx::query!(
-            "DELETE FROM group_buy_orders WHERE group_buy_id = ? AND buyer_id = ? AND item_name = ?",
+            "UPDATE group_buy_orders SET deleted_at = ?, deleted_batch = ?
+             WHERE group_buy_id = ? AND buyer_id = ? AND item_name = ? AND deleted_at IS NULL",
+            now,
+            batch_id,
             group_buy_id,
             buyer_id,
             item_name
@@ -789,66 +4362,306 @@ impl Database {
         .execute(&self.pool)
         .await?;
 
-        // 記錄日誌，使用操作人的資訊（details 為 JSON，含 version）
-        let version: i64 =
-            sqlx::query_scalar!("SELECT version FROM group_buys WHERE id = ?", group_buy_id)
-                .fetch_one(&self.pool)
-                .await
-                .unwrap_or(0i64);
-        let details_json = serde_json::json!({
-            "buyer_id": buyer_id,
-            "item_name": item_name,
-            "action": "delete_registration",
+        // 記錄日誌，使用操作人的資訊（details 為 JSON，含 version）
+        let version: i64 =
+            sqlx::query_scalar!("SELECT version FROM group_buys WHERE id = ?", group_buy_id)
+                .fetch_one(&self.pool)
+                .await
+                .unwrap_or(0i64);
+        let details_json = serde_json::json!({
+            "buyer_id": buyer_id,
+            "item_name": item_name,
+            "batch_id": batch_id,
+            "action": "delete_registration",
+            "version": version as i32,
+        });
+        let details = serde_json::to_string(&details_json).unwrap_or_else(|_| "{}".to_string());
+        let _ = self
+            .log_action(
+                group_buy_id,
+                actor_id,
+                actor_username,
+                "delete_registration",
+                Some(&details),
+            )
+            .await;
+
+        Ok(DeletedOrders {
+            count: result.rows_affected(),
+            batch_id,
+        })
+    }
+
+    /// 軟刪除單一訂單（購買人取消別人代登記的訂單），訂單不屬於 `buyer_id` 時不會刪除
+    pub async fn delete_order(
+        &self,
+        group_buy_id: &str,
+        order_id: &str,
+        buyer_id: &str,
+        actor_username: &str,
+    ) -> Result<DeletedOrders> {
+        let _timer = self.metrics.timer("db", "delete_order");
+        let batch_id = uuid::Uuid::new_v4().to_string();
+        let result = sqlx::query(
+            "UPDATE group_buy_orders SET deleted_at = ?, deleted_batch = ?
+             WHERE id = ? AND group_buy_id = ? AND buyer_id = ? AND deleted_at IS NULL",
+        )
+        .bind(Utc::now().to_rfc3339())
+        .bind(&batch_id)
+        .bind(order_id)
+        .bind(group_buy_id)
+        .bind(buyer_id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() > 0 {
+            let version: i64 = sqlx::query_scalar("SELECT version FROM group_buys WHERE id = ?")
+                .bind(group_buy_id)
+                .fetch_one(&self.pool)
+                .await
+                .unwrap_or(0i64);
+            let details = serde_json::json!({
+                "order_id": order_id,
+                "batch_id": batch_id,
+                "action": "reject_registration",
+                "version": version as i32,
+            })
+            .to_string();
+            let _ = self
+                .log_action(
+                    group_buy_id,
+                    buyer_id,
+                    actor_username,
+                    "reject_registration",
+                    Some(&details),
+                )
+                .await;
+        }
+
+        Ok(DeletedOrders {
+            count: result.rows_affected(),
+            batch_id,
+        })
+    }
+
+    /// 在同一個交易中軟刪除多位買家的所有訂單（用於取消登記功能），
+    /// 共用同一個 batch，可在 [`ORDER_RESTORE_GRACE_MINUTES`] 內以 `restore_deleted_orders` 一次復原
+    pub async fn delete_orders_for_buyers(
+        &self,
+        group_buy_id: &str,
+        buyer_ids: &[String],
+        actor_id: &str,
+        actor_username: &str,
+    ) -> Result<DeletedOrders> {
+        let _timer = self.metrics.timer("db", "delete_orders_for_buyers");
+        let batch_id = uuid::Uuid::new_v4().to_string();
+        let now = Utc::now().to_rfc3339();
+
+        let mut tx = self.pool.begin().await?;
+        let mut count = 0;
+        for buyer_id in buyer_ids {
+            let result = sqlx::query(
+                "UPDATE group_buy_orders SET deleted_at = ?, deleted_batch = ?
+                 WHERE group_buy_id = ? AND buyer_id = ? AND deleted_at IS NULL",
+            )
+            .bind(&now)
+            .bind(&batch_id)
+            .bind(group_buy_id)
+            .bind(buyer_id)
+            .execute(&mut *tx)
+            .await?;
+            count += result.rows_affected();
+        }
+        let version: i64 = sqlx::query_scalar("SELECT version FROM group_buys WHERE id = ?")
+            .bind(group_buy_id)
+            .fetch_one(&mut *tx)
+            .await
+            .unwrap_or(0i64);
+        tx.commit().await?;
+
+        // 記錄日誌（details 為 JSON，含 version）
+        let details_json = serde_json::json!({
+            "buyer_ids": buyer_ids,
+            "batch_id": batch_id,
+            "action": "cancel_all_registrations",
+            "version": version as i32,
+        });
+        let details = serde_json::to_string(&details_json).unwrap_or_else(|_| "{}".to_string());
+        let _ = self
+            .log_action(
+                group_buy_id,
+                actor_id,
+                actor_username,
+                "cancel_all_registrations",
+                Some(&details),
+            )
+            .await;
+
+        Ok(DeletedOrders { count, batch_id })
+    }
+
+    /// 把購買人的所有訂單改到另一位使用者名下（例如原本的帳號已停用），付款狀態一併移過去。
+    /// 回傳移動的訂單數
+    pub async fn reassign_buyer_orders(
+        &self,
+        group_buy_id: &str,
+        from_buyer_id: &str,
+        to_buyer_id: &str,
+        to_buyer_username: &str,
+        actor_id: &str,
+        actor_username: &str,
+    ) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        let result = sqlx::query(
+            "UPDATE group_buy_orders SET buyer_id = ?, buyer_username = ?
+             WHERE group_buy_id = ? AND buyer_id = ? AND deleted_at IS NULL",
+        )
+        .bind(to_buyer_id)
+        .bind(to_buyer_username)
+        .bind(group_buy_id)
+        .bind(from_buyer_id)
+        .execute(&mut *tx)
+        .await?;
+        // 新的購買人已付款時保留他的紀錄
+        sqlx::query(
+            "UPDATE OR IGNORE payments SET buyer_id = ? WHERE group_buy_id = ? AND buyer_id = ?",
+        )
+        .bind(to_buyer_id)
+        .bind(group_buy_id)
+        .bind(from_buyer_id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM payments WHERE group_buy_id = ? AND buyer_id = ?")
+            .bind(group_buy_id)
+            .bind(from_buyer_id)
+            .execute(&mut *tx)
+            .await?;
+        let version: i64 = sqlx::query_scalar("SELECT version FROM group_buys WHERE id = ?")
+            .bind(group_buy_id)
+            .fetch_one(&mut *tx)
+            .await
+            .unwrap_or(0i64);
+        tx.commit().await?;
+
+        let details = serde_json::json!({
+            "from_buyer_id": from_buyer_id,
+            "to_buyer_id": to_buyer_id,
+            "count": result.rows_affected(),
             "version": version as i32,
         });
-        let details = serde_json::to_string(&details_json).unwrap_or_else(|_| "{}".to_string());
         let _ = self
             .log_action(
                 group_buy_id,
                 actor_id,
                 actor_username,
-                "delete_registration",
-                Some(&details),
+                "reassign_orders",
+                Some(&details.to_string()),
             )
             .await;
 
         Ok(result.rows_affected())
     }
 
-    /// 刪除特定買家的所有訂單（用於取消登記功能）
-    pub async fn delete_orders_for_buyer(
+    /// 記錄帳號是否已停用（或已被刪除），回傳狀態是否有改變
+    pub async fn set_user_deactivated(&self, user_id: &str, deactivated: bool) -> Result<bool> {
+        let result = if deactivated {
+            sqlx::query(
+                "INSERT INTO deactivated_users (user_id, detected_at) VALUES (?, ?)
+                 ON CONFLICT(user_id) DO NOTHING",
+            )
+            .bind(user_id)
+            .bind(Utc::now().to_rfc3339())
+            .execute(&self.pool)
+            .await?
+        } else {
+            sqlx::query("DELETE FROM deactivated_users WHERE user_id = ?")
+                .bind(user_id)
+                .execute(&self.pool)
+                .await?
+        };
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// 已停用的帳號
+    pub async fn get_deactivated_users(&self) -> Result<Vec<String>> {
+        let users = sqlx::query_scalar("SELECT user_id FROM deactivated_users ORDER BY user_id")
+            .fetch_all(self.read_pool())
+            .await?;
+        Ok(users)
+    }
+
+    /// 購買人有登記且尚未封存的團購 ID
+    pub async fn get_open_group_buy_ids_by_buyer(&self, buyer_id: &str) -> Result<Vec<String>> {
+        let ids = sqlx::query_scalar(
+            "SELECT DISTINCT o.group_buy_id FROM group_buy_orders o
+             JOIN group_buys g ON g.id = o.group_buy_id
+             WHERE o.buyer_id = ? AND o.deleted_at IS NULL AND g.status != 'archived'
+             ORDER BY o.group_buy_id",
+        )
+        .bind(buyer_id)
+        .fetch_all(self.read_pool())
+        .await?;
+        Ok(ids)
+    }
+
+    /// 復原同一批被軟刪除的訂單。超過 `grace` 或已復原時回傳錯誤。
+    pub async fn restore_deleted_orders(
         &self,
         group_buy_id: &str,
-        buyer_id: &str,
+        batch_id: &str,
+        grace: chrono::Duration,
         actor_id: &str,
         actor_username: &str,
     ) -> Result<u64> {
-        let result = sqlx::query!(
-            "DELETE FROM group_buy_orders WHERE group_buy_id = ? AND buyer_id = ?",
-            group_buy_id,
-            buyer_id
+        let _timer = self.metrics.timer("db", "restore_deleted_orders");
+        let deleted_at: Option<String> = sqlx::query_scalar(
+            "SELECT MIN(deleted_at) FROM group_buy_orders
+             WHERE group_buy_id = ? AND deleted_batch = ? AND deleted_at IS NOT NULL",
+        )
+        .bind(group_buy_id)
+        .bind(batch_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        let Some(deleted_at) = deleted_at else {
+            return Err(CodedError::new(
+                ErrorCode::OrderNotFound,
+                "找不到可復原的登記，可能已經復原過了",
+            )
+            .into());
+        };
+        let deleted_at = DateTime::parse_from_rfc3339(&deleted_at)?.with_timezone(&Utc);
+        if Utc::now() - deleted_at > grace {
+            anyhow::bail!("已超過 {} 分鐘的復原期限", grace.num_minutes());
+        }
+
+        let result = sqlx::query(
+            "UPDATE group_buy_orders SET deleted_at = NULL, deleted_batch = NULL
+             WHERE group_buy_id = ? AND deleted_batch = ?",
         )
+        .bind(group_buy_id)
+        .bind(batch_id)
         .execute(&self.pool)
         .await?;
 
-        // 記錄日誌（details 為 JSON，含 version）
-        let version: i64 =
-            sqlx::query_scalar!("SELECT version FROM group_buys WHERE id = ?", group_buy_id)
-                .fetch_one(&self.pool)
-                .await
-                .unwrap_or(0i64);
-        let details_json = serde_json::json!({
-            "buyer_id": buyer_id,
-            "action": "cancel_all_registrations",
+        let version: i64 = sqlx::query_scalar("SELECT version FROM group_buys WHERE id = ?")
+            .bind(group_buy_id)
+            .fetch_one(&self.pool)
+            .await
+            .unwrap_or(0i64);
+        let details = serde_json::json!({
+            "batch_id": batch_id,
+            "count": result.rows_affected(),
+            "action": "restore_registrations",
             "version": version as i32,
-        });
-        let details = serde_json::to_string(&details_json).unwrap_or_else(|_| "{}".to_string());
+        })
+        .to_string();
         let _ = self
             .log_action(
                 group_buy_id,
                 actor_id,
                 actor_username,
-                "cancel_all_registrations",
+                "restore_registrations",
                 Some(&details),
             )
             .await;
@@ -862,13 +4675,14 @@ impl Database {
         group_buy_id: &str,
         buyer_id: &str,
     ) -> Result<Vec<GroupBuyOrder>> {
+        let _timer = self.metrics.timer("db", "get_buyer_orders");
         let orders = sqlx::query_as!(
             GroupBuyOrderRow,
             "SELECT id, group_buy_id, registrar_id, registrar_username,
                     buyer_id, buyer_username, item_name, quantity,
-                    original_quantity, unit_price, created_at
+                    original_quantity, unit_price, custom_fields, created_at
              FROM group_buy_orders
-             WHERE group_buy_id = ? AND buyer_id = ?",
+             WHERE group_buy_id = ? AND buyer_id = ? AND deleted_at IS NULL",
             group_buy_id,
             buyer_id
         )
@@ -878,15 +4692,42 @@ impl Database {
         Ok(orders.into_iter().map(|row| row.into()).collect())
     }
 
+    /// 購買人自 `since` 起在所有團購登記的金額合計（不含已取消的訂單）
+    pub async fn get_buyer_spend_since(
+        &self,
+        buyer_id: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Decimal> {
+        let rows = sqlx::query(
+            "SELECT unit_price, quantity FROM group_buy_orders
+             WHERE buyer_id = ? AND created_at >= ? AND deleted_at IS NULL",
+        )
+        .bind(buyer_id)
+        .bind(since.to_rfc3339())
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let unit_price: String = row.try_get("unit_price")?;
+                let quantity: i64 = row.try_get("quantity")?;
+                Ok(crate::money::line_total(
+                    Decimal::from_str(&unit_price).unwrap_or(Decimal::ZERO),
+                    quantity,
+                ))
+            })
+            .sum()
+    }
+
     /// 取得所有訂單
     pub async fn get_all_orders(&self, group_buy_id: &str) -> Result<Vec<GroupBuyOrder>> {
         let orders = sqlx::query_as!(
             GroupBuyOrderRow,
             "SELECT id, group_buy_id, registrar_id, registrar_username,
                     buyer_id, buyer_username, item_name, quantity,
-                    original_quantity, unit_price, created_at
+                    original_quantity, unit_price, custom_fields, created_at
              FROM group_buy_orders
-             WHERE group_buy_id = ?",
+             WHERE group_buy_id = ? AND deleted_at IS NULL",
             group_buy_id
         )
         .fetch_all(&self.pool)
@@ -903,6 +4744,7 @@ impl Database {
         adjuster_id: &str,
         adjuster_username: &str,
     ) -> Result<()> {
+        let _timer = self.metrics.timer("db", "adjust_single_order");
         let mut tx = self.pool.begin().await?;
 
         // 取得訂單資訊
@@ -910,9 +4752,9 @@ impl Database {
             GroupBuyOrderRow,
             "SELECT id, group_buy_id, registrar_id, registrar_username,
             buyer_id, buyer_username, item_name, quantity,
-            original_quantity, unit_price, created_at
+            original_quantity, unit_price, custom_fields, created_at
          FROM group_buy_orders
-         WHERE id = ?",
+         WHERE id = ? AND deleted_at IS NULL",
             order_id
         )
         .fetch_one(&mut *tx)
@@ -927,8 +4769,10 @@ impl Database {
         .fetch_one(&mut *tx)
         .await?;
 
-        if status != "closed" {
-            anyhow::bail!("只能在團購截止後調整缺貨");
+        if !GroupBuyStatus::from_string(&status).accepts_adjustments() {
+            return Err(
+                CodedError::new(ErrorCode::GbInvalidStatus, "只能在團購截止後調整缺貨").into(),
+            );
         }
 
         let old_qty = order.quantity;
@@ -976,11 +4820,24 @@ impl Database {
         .execute(&mut *tx)
         .await?;
 
-        // 記錄日誌
-        let msg = format!(
-            "調整 @{} 的 {} 數量：{} → {}",
-            order_buyer_username, order_item_name, old_qty, new_quantity
-        );
+        // 記錄日誌（details 為 JSON，含 version）
+        let version: i64 = sqlx::query_scalar("SELECT version FROM group_buys WHERE id = ?")
+            .bind(&order_group_buy_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        let msg = serde_json::json!({
+            "message": format!(
+                "調整 @{} 的 {} 數量：{} → {}",
+                order_buyer_username, order_item_name, old_qty, new_quantity
+            ),
+            "buyer_id": order_buyer_id,
+            "item_name": order_item_name,
+            "old_quantity": old_qty,
+            "new_quantity": new_quantity,
+            "action": "adjust_shortage",
+            "version": version,
+        })
+        .to_string();
         sqlx::query!(
             "INSERT INTO group_buy_logs (group_buy_id, user_id, username, action, details, created_at)
              VALUES (?, ?, ?, ?, ?, ?)",
@@ -998,7 +4855,65 @@ impl Database {
         Ok(())
     }
 
+    /// 團購的缺貨調整紀錄，依時間先後排序
+    pub async fn get_shortage_adjustments(
+        &self,
+        group_buy_id: &str,
+    ) -> Result<Vec<ShortageAdjustment>> {
+        let rows = sqlx::query(
+            "SELECT adjuster_username, item_name, buyer_username, old_quantity, new_quantity, created_at
+             FROM shortage_adjustments WHERE group_buy_id = ? ORDER BY created_at, id",
+        )
+        .bind(group_buy_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let created_at: String = row.try_get("created_at")?;
+                Ok(ShortageAdjustment {
+                    adjuster_username: row.try_get("adjuster_username")?,
+                    item_name: row.try_get("item_name")?,
+                    buyer_username: row.try_get("buyer_username")?,
+                    old_quantity: row.try_get::<i64, _>("old_quantity")? as i32,
+                    new_quantity: row.try_get::<i64, _>("new_quantity")? as i32,
+                    created_at: DateTime::parse_from_rfc3339(&created_at)?.with_timezone(&Utc),
+                })
+            })
+            .collect()
+    }
+
+    /// 進行中團購的孤兒檢查資料：貼文、有效登記數，以及是否已提醒過建立者
+    pub async fn get_orphan_candidates(&self) -> Result<Vec<OrphanCandidate>> {
+        let rows = sqlx::query(
+            "SELECT g.id, g.post_id, g.created_at,
+                    (SELECT COUNT(*) FROM group_buy_orders o
+                      WHERE o.group_buy_id = g.id AND o.deleted_at IS NULL) AS order_count,
+                    EXISTS (SELECT 1 FROM group_buy_logs l
+                      WHERE l.group_buy_id = g.id AND l.action = 'orphan_notice') AS notified
+             FROM group_buys g WHERE g.status = 'active' ORDER BY g.created_at, g.id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let created_at: String = row.try_get("created_at")?;
+                Ok(OrphanCandidate {
+                    group_buy_id: row.try_get("id")?,
+                    post_id: row.try_get("post_id")?,
+                    order_count: row.try_get("order_count")?,
+                    notified: row.try_get("notified")?,
+                    created_at: DateTime::parse_from_rfc3339(&created_at)?.with_timezone(&Utc),
+                })
+            })
+            .collect()
+    }
+
     /// 調整訂單數量（缺貨調整）
+    ///
+    /// `adjustments` 為 購買人 → 該商品調整後的總數量；購買人有多筆同商品訂單時，
+    /// 依登記先後由先登記的訂單先分配。
     pub async fn adjust_order_quantity(
         &self,
         group_buy_id: &str,
@@ -1007,6 +4922,7 @@ impl Database {
         adjuster_id: &str,
         adjuster_username: &str,
     ) -> Result<Vec<AdjustmentRecord>> {
+        let _timer = self.metrics.timer("db", "adjust_order_quantity");
         let mut tx = self.pool.begin().await?;
 
         // 檢查團購狀態必須是 closed
@@ -1015,107 +4931,552 @@ impl Database {
                 .fetch_one(&mut *tx)
                 .await?;
 
-        if status != "closed" {
-            anyhow::bail!("只能在團購截止後調整缺貨");
+        if !GroupBuyStatus::from_string(&status).accepts_adjustments() {
+            return Err(
+                CodedError::new(ErrorCode::GbInvalidStatus, "只能在團購截止後調整缺貨").into(),
+            );
+        }
+
+        // 取得所有相關訂單
+        let orders = sqlx::query_as!(
+            OrderAdjustmentRow,
+            "SELECT id, buyer_id, buyer_username, quantity, original_quantity
+             FROM group_buy_orders
+             WHERE group_buy_id = ? AND item_name = ? AND deleted_at IS NULL
+             ORDER BY created_at, id",
+            group_buy_id,
+            item_name
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut records = Vec::new();
+        let mut remaining: HashMap<String, i32> = adjustments.clone();
+
+        for order in orders {
+            // Skip orders without a buyer_username (shouldn't normally happen)
+            let buyer_username = match order.buyer_username.clone() {
+                Some(s) => s,
+                None => continue,
+            };
+
+            if let Some(left) = remaining.get_mut(&buyer_username) {
+                let old_qty = order.quantity;
+                let new_qty = (*left).min(old_qty as i32).max(0);
+                *left -= new_qty;
+                if new_qty as i64 == old_qty {
+                    continue;
+                }
+                let orig_qty = order.original_quantity.unwrap_or(old_qty);
+
+                // Ensure we have an order id to update; skip otherwise
+                let order_id_clone = match order.id.clone() {
+                    Some(s) => s,
+                    None => continue,
+                };
+
+                // buyer_id may be absent in edge cases; use empty string if missing
+                let order_buyer_id = order.buyer_id.clone().unwrap_or_default();
+                let order_buyer_username = buyer_username.clone();
+                let new_qty_i64 = new_qty as i64;
+
+                sqlx::query!(
+                    "UPDATE group_buy_orders 
+                     SET quantity = ?, original_quantity = ?
+                     WHERE id = ?",
+                    new_qty_i64,
+                    orig_qty,
+                    order_id_clone
+                )
+                .execute(&mut *tx)
+                .await?;
+
+                // 記錄調整歷史
+                let now = Utc::now().to_rfc3339();
+                let now_for_insert = now.clone();
+                sqlx::query!(
+                    "INSERT INTO shortage_adjustments (
+                        group_buy_id, order_id, adjuster_id, adjuster_username,
+                        item_name, buyer_id, buyer_username, old_quantity, new_quantity, created_at
+                     ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                    group_buy_id,
+                    order_id_clone,
+                    adjuster_id,
+                    adjuster_username,
+                    item_name,
+                    order_buyer_id,
+                    order_buyer_username,
+                    old_qty,
+                    new_qty_i64,
+                    now_for_insert
+                )
+                .execute(&mut *tx)
+                .await?;
+
+                records.push(AdjustmentRecord {
+                    buyer_username: order_buyer_username.clone(),
+                    old_quantity: old_qty as i32,
+                    new_quantity: new_qty,
+                });
+            }
+        }
+
+        // 記錄日誌（在同一交易中插入以避免連線/鎖定問題，details 為 JSON，含 version）
+        let now2 = Utc::now().to_rfc3339();
+        let version: i64 = sqlx::query_scalar("SELECT version FROM group_buys WHERE id = ?")
+            .bind(group_buy_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        let details = serde_json::json!({
+            "message": format!("調整 {} 的數量，影響 {} 位用戶", item_name, records.len()),
+            "item_name": item_name,
+            "affected_orders": records.len(),
+            "action": "adjust_shortage",
+            "version": version,
+        })
+        .to_string();
+        sqlx::query!(
+            "INSERT INTO group_buy_logs (group_buy_id, user_id, username, action, details, created_at)
+             VALUES (?, ?, ?, ?, ?, ?)",
+            group_buy_id,
+            adjuster_id,
+            adjuster_username,
+            "adjust_shortage",
+            details,
+            now2
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(records)
+    }
+
+    /// 建立 API token，回傳 token 資料與明文 token（明文只會出現這一次）
+    pub async fn create_api_token(
+        &self,
+        name: &str,
+        scope: ApiTokenScope,
+        created_by: &str,
+    ) -> Result<(ApiToken, String)> {
+        let id = uuid::Uuid::new_v4().simple().to_string()[..8].to_string();
+        let secret = format!(
+            "lmb_{}{}",
+            uuid::Uuid::new_v4().simple(),
+            uuid::Uuid::new_v4().simple()
+        );
+        let created_at = Utc::now();
+
+        sqlx::query(
+            "INSERT INTO api_tokens (id, name, token_hash, scope, created_by, created_at)
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&id)
+        .bind(name)
+        .bind(hash_api_token(&secret))
+        .bind(scope.to_string())
+        .bind(created_by)
+        .bind(created_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        let token = ApiToken {
+            id,
+            name: name.to_string(),
+            scope,
+            created_by: created_by.to_string(),
+            created_at,
+            last_used_at: None,
+            revoked_at: None,
+        };
+
+        Ok((token, secret))
+    }
+
+    /// 列出所有 API token（含已撤銷）
+    pub async fn list_api_tokens(&self) -> Result<Vec<ApiToken>> {
+        let rows = sqlx::query(
+            "SELECT id, name, scope, created_by, created_at, last_used_at, revoked_at
+             FROM api_tokens ORDER BY created_at",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(ApiToken::from_row).collect()
+    }
+
+    /// 撤銷 API token，找不到或已撤銷時回傳 false
+    pub async fn revoke_api_token(&self, id: &str) -> Result<bool> {
+        let result =
+            sqlx::query("UPDATE api_tokens SET revoked_at = ? WHERE id = ? AND revoked_at IS NULL")
+                .bind(Utc::now().to_rfc3339())
+                .bind(id)
+                .execute(&self.pool)
+                .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// 以明文 token 查詢有效的 API token，並更新最後使用時間
+    pub async fn authenticate_api_token(&self, secret: &str) -> Result<Option<ApiToken>> {
+        let row = sqlx::query(
+            "SELECT id, name, scope, created_by, created_at, last_used_at, revoked_at
+             FROM api_tokens WHERE token_hash = ? AND revoked_at IS NULL",
+        )
+        .bind(hash_api_token(secret))
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let token = ApiToken::from_row(&row)?;
+
+        sqlx::query("UPDATE api_tokens SET last_used_at = ? WHERE id = ?")
+            .bind(Utc::now().to_rfc3339())
+            .bind(&token.id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(Some(token))
+    }
+
+    /// 記錄 API token 的請求
+    pub async fn log_api_token_request(
+        &self,
+        token_id: &str,
+        method: &str,
+        path: &str,
+        status: u16,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO api_token_logs (token_id, method, path, status, created_at)
+             VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(token_id)
+        .bind(method)
+        .bind(path)
+        .bind(status as i64)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// 取得 API token 最近的請求紀錄（新到舊）
+    pub async fn get_api_token_logs(&self, token_id: &str, limit: i64) -> Result<Vec<ApiTokenLog>> {
+        let rows = sqlx::query(
+            "SELECT method, path, status, created_at FROM api_token_logs
+             WHERE token_id = ? ORDER BY id DESC LIMIT ?",
+        )
+        .bind(token_id)
+        .bind(limit)
+        .fetch_all(self.read_pool())
+        .await?;
+
+        rows.iter()
+            .map(|r| {
+                Ok(ApiTokenLog {
+                    method: r.try_get("method")?,
+                    path: r.try_get("path")?,
+                    status: r.try_get::<i64, _>("status")? as u16,
+                    created_at: parse_rfc3339(&r.try_get::<String, _>("created_at")?)?,
+                })
+            })
+            .collect()
+    }
+
+    /// 依條件查詢團購操作日誌（新到舊）。使用唯讀連接池，供歷史紀錄與匯出使用。
+    pub async fn query_logs(&self, filter: &LogFilter) -> Result<Vec<GroupBuyLog>> {
+        let mut sql = String::from(
+            "SELECT id, group_buy_id, user_id, username, action, details, created_at
+             FROM group_buy_logs",
+        );
+        let mut where_clauses: Vec<&str> = Vec::new();
+
+        if filter.group_buy_id.is_some() {
+            where_clauses.push("group_buy_id = ?");
+        }
+        if filter.action.is_some() {
+            where_clauses.push("action = ?");
+        }
+        if filter.user_id.is_some() {
+            where_clauses.push("user_id = ?");
+        }
+        if filter.version.is_some() {
+            where_clauses.push("details_version = ?");
+        }
+        if filter.since.is_some() {
+            where_clauses.push("created_at >= ?");
+        }
+        if filter.until.is_some() {
+            where_clauses.push("created_at < ?");
+        }
+        if !where_clauses.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&where_clauses.join(" AND "));
+        }
+        sql.push_str(" ORDER BY id DESC LIMIT ? OFFSET ?");
+
+        let mut query = sqlx::query(&sql);
+        if let Some(group_buy_id) = &filter.group_buy_id {
+            query = query.bind(group_buy_id);
+        }
+        if let Some(action) = &filter.action {
+            query = query.bind(action);
+        }
+        if let Some(user_id) = &filter.user_id {
+            query = query.bind(user_id);
+        }
+        if let Some(version) = filter.version {
+            query = query.bind(version);
+        }
+        if let Some(since) = filter.since {
+            query = query.bind(since.to_rfc3339());
+        }
+        if let Some(until) = filter.until {
+            query = query.bind(until.to_rfc3339());
+        }
+        query = query
+            .bind(filter.limit.unwrap_or(100))
+            .bind(filter.offset.unwrap_or(0));
+
+        let rows = query.fetch_all(self.read_pool()).await?;
+        rows.iter()
+            .map(|r| {
+                let details: String = r.try_get("details")?;
+                Ok(GroupBuyLog {
+                    id: r.try_get("id")?,
+                    group_buy_id: r.try_get("group_buy_id")?,
+                    user_id: r.try_get("user_id")?,
+                    username: r.try_get("username")?,
+                    action: r.try_get("action")?,
+                    details: serde_json::from_str(&details)
+                        .unwrap_or_else(|_| serde_json::json!({ "message": details })),
+                    created_at: parse_rfc3339(&r.try_get::<String, _>("created_at")?)?,
+                })
+            })
+            .collect()
+    }
+
+    /// 依條件查詢團購（新到舊），供管理 API 的查詢端點使用
+    pub async fn query_group_buys(&self, filter: &GroupBuyQuery) -> Result<Vec<GroupBuy>> {
+        let mut sql = String::from(
+            "SELECT id, creator_id, creator_username, channel_id, post_id,
+                    merchant_name, description, metadata, items, item_icons, item_sections,
+                    item_translations, order_fields, currency, status, version, created_at, updated_at
+             FROM group_buys",
+        );
+        let mut where_clauses: Vec<&str> = Vec::new();
+
+        if filter.status.is_some() {
+            where_clauses.push("status = ?");
+        }
+        if filter.channel_id.is_some() {
+            where_clauses.push("channel_id = ?");
+        }
+        if filter.creator_id.is_some() {
+            where_clauses.push("creator_id = ?");
+        }
+        if filter.merchant_name.is_some() {
+            where_clauses.push("merchant_name = ?");
+        }
+        if filter.since.is_some() {
+            where_clauses.push("created_at >= ?");
+        }
+        if filter.until.is_some() {
+            where_clauses.push("created_at < ?");
+        }
+        if !where_clauses.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&where_clauses.join(" AND "));
+        }
+        sql.push_str(" ORDER BY created_at DESC, id LIMIT ? OFFSET ?");
+
+        let mut query = sqlx::query_as::<_, GroupBuyRow>(&sql);
+        if let Some(status) = &filter.status {
+            query = query.bind(status);
+        }
+        if let Some(channel_id) = &filter.channel_id {
+            query = query.bind(channel_id);
+        }
+        if let Some(creator_id) = &filter.creator_id {
+            query = query.bind(creator_id);
         }
+        if let Some(merchant_name) = &filter.merchant_name {
+            query = query.bind(merchant_name);
+        }
+        if let Some(since) = filter.since {
+            query = query.bind(since.to_rfc3339());
+        }
+        if let Some(until) = filter.until {
+            query = query.bind(until.to_rfc3339());
+        }
+        query = query
+            .bind(filter.limit.unwrap_or(100))
+            .bind(filter.offset.unwrap_or(0));
 
-        // 取得所有相關訂單
-        let orders = sqlx::query_as!(
-            OrderAdjustmentRow,
-            "SELECT id, buyer_id, buyer_username, quantity, original_quantity
-             FROM group_buy_orders
-             WHERE group_buy_id = ? AND item_name = ?",
-            group_buy_id,
-            item_name
-        )
-        .fetch_all(&mut *tx)
-        .await?;
-
-        let mut records = Vec::new();
+        let rows = query.fetch_all(self.read_pool()).await?;
+        Ok(rows.into_iter().map(|row| row.into()).collect())
+    }
 
-        for order in orders {
-            // Skip orders without a buyer_username (shouldn't normally happen)
-            let buyer_username = match order.buyer_username.clone() {
-                Some(s) => s,
-                None => continue,
-            };
+    /// 依條件查詢登記（新到舊），不包含已取消的登記
+    pub async fn query_orders(&self, filter: &OrderQuery) -> Result<Vec<GroupBuyOrder>> {
+        let mut sql = String::from(
+            "SELECT id, group_buy_id, registrar_id, registrar_username,
+                    buyer_id, buyer_username, item_name, quantity,
+                    original_quantity, unit_price, custom_fields, created_at
+             FROM group_buy_orders
+             WHERE deleted_at IS NULL",
+        );
 
-            if let Some(&new_qty) = adjustments.get(&buyer_username) {
-                let old_qty = order.quantity;
-                let orig_qty = order.original_quantity.unwrap_or(old_qty);
+        if filter.group_buy_id.is_some() {
+            sql.push_str(" AND group_buy_id = ?");
+        }
+        if filter.buyer_id.is_some() {
+            sql.push_str(" AND buyer_id = ?");
+        }
+        if filter.registrar_id.is_some() {
+            sql.push_str(" AND registrar_id = ?");
+        }
+        if filter.item_name.is_some() {
+            sql.push_str(" AND item_name = ?");
+        }
+        if filter.since.is_some() {
+            sql.push_str(" AND created_at >= ?");
+        }
+        if filter.until.is_some() {
+            sql.push_str(" AND created_at < ?");
+        }
+        sql.push_str(" ORDER BY created_at DESC, id LIMIT ? OFFSET ?");
 
-                // Ensure we have an order id to update; skip otherwise
-                let order_id_clone = match order.id.clone() {
-                    Some(s) => s,
-                    None => continue,
-                };
+        let mut query = sqlx::query_as::<_, GroupBuyOrderRow>(&sql);
+        if let Some(group_buy_id) = &filter.group_buy_id {
+            query = query.bind(group_buy_id);
+        }
+        if let Some(buyer_id) = &filter.buyer_id {
+            query = query.bind(buyer_id);
+        }
+        if let Some(registrar_id) = &filter.registrar_id {
+            query = query.bind(registrar_id);
+        }
+        if let Some(item_name) = &filter.item_name {
+            query = query.bind(item_name);
+        }
+        if let Some(since) = filter.since {
+            query = query.bind(since.to_rfc3339());
+        }
+        if let Some(until) = filter.until {
+            query = query.bind(until.to_rfc3339());
+        }
+        query = query
+            .bind(filter.limit.unwrap_or(100))
+            .bind(filter.offset.unwrap_or(0));
 
-                // buyer_id may be absent in edge cases; use empty string if missing
-                let order_buyer_id = order.buyer_id.clone().unwrap_or_default();
-                let order_buyer_username = buyer_username.clone();
-                let new_qty_i64 = new_qty as i64;
+        let rows = query.fetch_all(self.read_pool()).await?;
+        Ok(rows.into_iter().map(|row| row.into()).collect())
+    }
 
-                sqlx::query!(
-                    "UPDATE group_buy_orders 
-                     SET quantity = ?, original_quantity = ?
-                     WHERE id = ?",
-                    new_qty_i64,
-                    orig_qty,
-                    order_id_clone
-                )
-                .execute(&mut *tx)
-                .await?;
+    /// 依條件查詢貼圖發送紀錄（新到舊）。已從貼圖庫移除的貼圖也會列出，名稱與分類為 None
+    pub async fn query_sticker_usage(
+        &self,
+        filter: &StickerUsageQuery,
+    ) -> Result<Vec<StickerUsageRecord>> {
+        let mut sql = String::from(
+            "SELECT u.id, u.image_url, s.name, s.category, u.user_id, u.used_at
+             FROM sticker_usage u
+             LEFT JOIN stickers s ON s.image_url = u.image_url",
+        );
+        let mut where_clauses: Vec<&str> = Vec::new();
 
-                // 記錄調整歷史
-                let now = Utc::now().to_rfc3339();
-                let now_for_insert = now.clone();
-                sqlx::query!(
-                    "INSERT INTO shortage_adjustments (
-                        group_buy_id, order_id, adjuster_id, adjuster_username,
-                        item_name, buyer_id, buyer_username, old_quantity, new_quantity, created_at
-                     ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
-                    group_buy_id,
-                    order_id_clone,
-                    adjuster_id,
-                    adjuster_username,
-                    item_name,
-                    order_buyer_id,
-                    order_buyer_username,
-                    old_qty,
-                    new_qty_i64,
-                    now_for_insert
-                )
-                .execute(&mut *tx)
-                .await?;
+        if filter.user_id.is_some() {
+            where_clauses.push("u.user_id = ?");
+        }
+        if filter.category.is_some() {
+            where_clauses.push("s.category = ?");
+        }
+        if filter.image_url.is_some() {
+            where_clauses.push("u.image_url = ?");
+        }
+        if filter.since.is_some() {
+            where_clauses.push("u.used_at >= ?");
+        }
+        if filter.until.is_some() {
+            where_clauses.push("u.used_at < ?");
+        }
+        if !where_clauses.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&where_clauses.join(" AND "));
+        }
+        sql.push_str(" ORDER BY u.id DESC LIMIT ? OFFSET ?");
 
-                records.push(AdjustmentRecord {
-                    buyer_username: order_buyer_username.clone(),
-                    old_quantity: old_qty as i32,
-                    new_quantity: new_qty,
-                });
-            }
+        let mut query = sqlx::query(&sql);
+        if let Some(user_id) = &filter.user_id {
+            query = query.bind(user_id);
+        }
+        if let Some(category) = &filter.category {
+            query = query.bind(category);
+        }
+        if let Some(image_url) = &filter.image_url {
+            query = query.bind(image_url);
+        }
+        if let Some(since) = filter.since {
+            query = query.bind(since.to_rfc3339());
+        }
+        if let Some(until) = filter.until {
+            query = query.bind(until.to_rfc3339());
         }
+        query = query
+            .bind(filter.limit.unwrap_or(100))
+            .bind(filter.offset.unwrap_or(0));
+
+        let rows = query.fetch_all(self.read_pool()).await?;
+        rows.iter()
+            .map(|r| {
+                Ok(StickerUsageRecord {
+                    id: r.try_get("id")?,
+                    image_url: r.try_get("image_url")?,
+                    name: r.try_get("name")?,
+                    category: r.try_get("category")?,
+                    user_id: r.try_get("user_id")?,
+                    used_at: parse_rfc3339(&r.try_get::<String, _>("used_at")?)?,
+                })
+            })
+            .collect()
+    }
+}
 
-        // 記錄日誌（在同一交易中插入以避免連線/鎖定問題）
-        let now2 = Utc::now().to_rfc3339();
-        let details = format!("調整 {} 的數量，影響 {} 位用戶", item_name, records.len());
-        sqlx::query!(
-            "INSERT INTO group_buy_logs (group_buy_id, user_id, username, action, details, created_at)
-             VALUES (?, ?, ?, ?, ?, ?)",
-            group_buy_id,
-            adjuster_id,
-            adjuster_username,
-            "adjust_shortage",
-            details,
-            now2
-        )
-        .execute(&mut *tx)
-        .await?;
+/// 日誌的 details 一律存成 JSON 物件；非物件的內容包成 `{"message": ...}`
+fn normalize_log_details(details: Option<&str>) -> String {
+    let Some(details) = details.map(str::trim).filter(|d| !d.is_empty()) else {
+        return "{}".to_string();
+    };
+    match serde_json::from_str::<serde_json::Value>(details) {
+        Ok(value @ serde_json::Value::Object(_)) => value.to_string(),
+        Ok(value) => serde_json::json!({ "message": value }).to_string(),
+        Err(_) => serde_json::json!({ "message": details }).to_string(),
+    }
+}
 
-        tx.commit().await?;
+fn hash_api_token(secret: &str) -> String {
+    use sha2::{Digest, Sha256};
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
 
-        Ok(records)
+/// 貼圖列表（依順序）的指紋，判斷重新載入後是否有變更
+fn sticker_list_fingerprint(stickers: &[Sticker]) -> String {
+    use sha2::{Digest, Sha256};
+    let mut hasher = Sha256::new();
+    for s in stickers {
+        hasher.update(format!("{}\t{}\t{}\n", s.category, s.name, s.image_url).as_bytes());
     }
+    hex::encode(hasher.finalize())
+}
+
+fn parse_rfc3339(s: &str) -> Result<DateTime<Utc>> {
+    Ok(DateTime::parse_from_rfc3339(s)?.with_timezone(&Utc))
 }
 
 // 資料結構定義
@@ -1131,16 +5492,39 @@ pub struct GroupBuy {
     pub description: Option<String>,
     pub metadata: HashMap<String, String>,
     pub items: HashMap<String, Decimal>, // 改用 Decimal 存儲價格
+    pub item_icons: HashMap<String, String>, // 商品名稱 -> emoji 或縮圖網址
+    pub item_sections: HashMap<String, String>, // 商品名稱 -> 分類（飲料、炸物…），未分類的商品不列入
+    pub item_translations: HashMap<String, Vec<String>>, // 商品名稱 -> 其他語言的名稱，依 group_buy.item_locales 的順序
+    pub order_fields: Vec<OrderField>,                   // 建立者定義的訂單欄位（內用/外帶…）
+    pub currency: String,                                // 幣別符號，建立時依頻道設定決定
     pub status: GroupBuyStatus,
     pub version: i32,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+/// 團購的商品列表：編輯商品時解析後整組寫入
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ItemList {
+    pub items: HashMap<String, Decimal>,
+    pub item_icons: HashMap<String, String>,
+    /// 商品名稱 -> 分類
+    pub item_sections: HashMap<String, String>,
+    /// 商品名稱 -> 其他語言的名稱
+    pub item_translations: HashMap<String, Vec<String>>,
+}
+
+/// 團購狀態：草稿 → 進行中 ⇄ 已截止 → 已下單 → 已封存
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum GroupBuyStatus {
+    /// 已建立但尚未公開，建立者可以先填好商品
+    Draft,
     Active,
     Closed,
+    /// 建立者已向商家下單：不能再登記，但仍可調整缺貨
+    Ordered,
+    /// 已結束並封存：貼文不再有按鈕，也不能再調整
+    Archived,
 }
 
 use std::fmt;
@@ -1148,8 +5532,11 @@ use std::fmt;
 impl fmt::Display for GroupBuyStatus {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            GroupBuyStatus::Draft => write!(f, "draft"),
             GroupBuyStatus::Active => write!(f, "active"),
             GroupBuyStatus::Closed => write!(f, "closed"),
+            GroupBuyStatus::Ordered => write!(f, "ordered"),
+            GroupBuyStatus::Archived => write!(f, "archived"),
         }
     }
 }
@@ -1157,10 +5544,45 @@ impl fmt::Display for GroupBuyStatus {
 impl GroupBuyStatus {
     pub fn from_string(s: &str) -> Self {
         match s {
+            "draft" => GroupBuyStatus::Draft,
             "closed" => GroupBuyStatus::Closed,
+            "ordered" => GroupBuyStatus::Ordered,
+            "archived" => GroupBuyStatus::Archived,
             _ => GroupBuyStatus::Active,
         }
     }
+
+    /// 顯示給使用者的名稱
+    pub fn label(&self) -> &'static str {
+        match self {
+            GroupBuyStatus::Draft => "草稿",
+            GroupBuyStatus::Active => "進行中",
+            GroupBuyStatus::Closed => "已截止",
+            GroupBuyStatus::Ordered => "已下單",
+            GroupBuyStatus::Archived => "已封存",
+        }
+    }
+
+    /// 允許的狀態轉換
+    pub fn can_transition_to(&self, next: &GroupBuyStatus) -> bool {
+        matches!(
+            (self, next),
+            (GroupBuyStatus::Draft, GroupBuyStatus::Active)
+                | (GroupBuyStatus::Active, GroupBuyStatus::Closed)
+                | (GroupBuyStatus::Closed, GroupBuyStatus::Active)
+                | (GroupBuyStatus::Closed, GroupBuyStatus::Ordered)
+                | (GroupBuyStatus::Closed, GroupBuyStatus::Archived)
+                | (GroupBuyStatus::Ordered, GroupBuyStatus::Archived)
+        )
+    }
+
+    pub fn accepts_registrations(&self) -> bool {
+        *self == GroupBuyStatus::Active
+    }
+
+    pub fn accepts_adjustments(&self) -> bool {
+        matches!(self, GroupBuyStatus::Closed | GroupBuyStatus::Ordered)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1174,7 +5596,201 @@ pub struct GroupBuyOrder {
     pub item_name: String,
     pub quantity: i32,
     pub original_quantity: Option<i32>,
-    pub unit_price: Decimal, // 改用 Decimal 存儲單價
+    pub unit_price: Decimal,                     // 改用 Decimal 存儲單價
+    pub custom_fields: BTreeMap<String, String>, // 欄位名稱 -> 填寫的值
+    pub created_at: DateTime<Utc>,
+}
+
+/// 團購建立者定義的訂單欄位，登記時填寫
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OrderField {
+    pub name: String,
+    /// 可選的值，空白表示自由填寫
+    #[serde(default)]
+    pub options: Vec<String>,
+}
+
+/// 取消登記後可復原的時間
+pub const ORDER_RESTORE_GRACE_MINUTES: i64 = 10;
+
+/// 頻道沒有設定幣別時使用的幣別符號
+pub const DEFAULT_CURRENCY: &str = "NT$";
+
+/// 團購範本（`/leko group_buy template`）：建立團購時預先填入的商家、描述、其他資訊與商品
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GroupBuyTemplate {
+    pub channel_id: String,
+    pub name: String,
+    pub merchant_name: String,
+    pub description: Option<String>,
+    pub metadata: HashMap<String, String>,
+    pub items: HashMap<String, Decimal>,
+    pub item_icons: HashMap<String, String>,
+    pub item_sections: HashMap<String, String>,
+    pub item_translations: HashMap<String, Vec<String>>,
+}
+
+/// 頻道層級的團購設定（`/leko settings`），沒有設定的項目沿用預設值
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChannelSettings {
+    pub channel_id: String,
+    /// 幣別符號，例如 `NT$`、`US$`
+    pub currency: Option<String>,
+    /// 靜音時段，例如 `22:00-08:00`，期間 bot 不在頻道發通知
+    pub quiet_hours: Option<String>,
+    /// 建立團購時預設的截止時間（分鐘）
+    pub default_deadline_minutes: Option<i64>,
+    /// 可以建立團購的使用者名稱，空白表示所有人都可以
+    pub allowed_creators: Vec<String>,
+}
+
+impl ChannelSettings {
+    pub fn currency(&self) -> &str {
+        self.currency.as_deref().unwrap_or(DEFAULT_CURRENCY)
+    }
+
+    pub fn can_create_group_buy(&self, username: &str) -> bool {
+        self.allowed_creators.is_empty() || self.allowed_creators.iter().any(|u| u == username)
+    }
+}
+
+/// 軟刪除的結果，`batch_id` 用於復原同一次取消的所有訂單
+#[derive(Debug, Clone)]
+pub struct DeletedOrders {
+    pub count: u64,
+    pub batch_id: String,
+}
+
+/// `shortage_adjustments` 中的一筆缺貨調整紀錄
+#[derive(Debug, Clone)]
+pub struct ShortageAdjustment {
+    pub adjuster_username: String,
+    pub item_name: String,
+    pub buyer_username: String,
+    pub old_quantity: i32,
+    pub new_quantity: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// HTTP 貼圖來源最後一次讀取的結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StickerSourceStatus {
+    /// 回應新的內容
+    Updated,
+    /// 回應 304，沿用上次的內容
+    NotModified,
+    /// 連線、HTTP 狀態或解析失敗
+    Failed,
+}
+
+impl fmt::Display for StickerSourceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StickerSourceStatus::Updated => write!(f, "updated"),
+            StickerSourceStatus::NotModified => write!(f, "not_modified"),
+            StickerSourceStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+impl StickerSourceStatus {
+    pub fn from_string(s: &str) -> Self {
+        match s {
+            "updated" => StickerSourceStatus::Updated,
+            "not_modified" => StickerSourceStatus::NotModified,
+            _ => StickerSourceStatus::Failed,
+        }
+    }
+}
+
+/// HTTP 貼圖來源的驗證資訊（ETag、Last-Modified）、上次成功取得的內容與讀取狀態
+#[derive(Debug, Clone)]
+pub struct StickerSourceRecord {
+    pub category: String,
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// 上次成功取得的內容，來源回應 304 時重新解析；列出所有來源時不讀取
+    pub content: Option<String>,
+    pub status: StickerSourceStatus,
+    /// 上次成功解析出的貼圖數量
+    pub sticker_count: i64,
+    pub error: Option<String>,
+    pub checked_at: DateTime<Utc>,
+    /// 最後一次取得新內容的時間
+    pub changed_at: Option<DateTime<Utc>>,
+}
+
+impl StickerSourceRecord {
+    fn from_row(row: &sqlx::sqlite::SqliteRow, with_content: bool) -> Result<Self> {
+        let status: String = row.try_get("status")?;
+        let checked_at: String = row.try_get("checked_at")?;
+        let changed_at: Option<String> = row.try_get("changed_at")?;
+        Ok(Self {
+            category: row.try_get("category")?,
+            url: row.try_get("url")?,
+            etag: row.try_get("etag")?,
+            last_modified: row.try_get("last_modified")?,
+            content: if with_content {
+                row.try_get("content")?
+            } else {
+                None
+            },
+            status: StickerSourceStatus::from_string(&status),
+            sticker_count: row.try_get("sticker_count")?,
+            error: row.try_get("error")?,
+            checked_at: parse_rfc3339(&checked_at)?,
+            changed_at: changed_at.as_deref().map(parse_rfc3339).transpose()?,
+        })
+    }
+}
+
+/// Feed 的驗證資訊（ETag、Last-Modified）與讀取狀態
+#[derive(Debug, Clone)]
+pub struct FeedStateRecord {
+    pub name: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub error: Option<String>,
+    pub checked_at: DateTime<Utc>,
+    /// 最後一次成功讀取的時間，None 代表還沒有成功讀取過
+    pub succeeded_at: Option<DateTime<Utc>>,
+}
+
+impl FeedStateRecord {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self> {
+        let checked_at: String = row.try_get("checked_at")?;
+        let succeeded_at: Option<String> = row.try_get("succeeded_at")?;
+        Ok(Self {
+            name: row.try_get("name")?,
+            etag: row.try_get("etag")?,
+            last_modified: row.try_get("last_modified")?,
+            error: row.try_get("error")?,
+            checked_at: parse_rfc3339(&checked_at)?,
+            succeeded_at: succeeded_at.as_deref().map(parse_rfc3339).transpose()?,
+        })
+    }
+}
+
+/// 尚未發送或取消的貼圖選擇器
+#[derive(Debug, Clone)]
+pub struct StickerPickerRecord {
+    pub id: String,
+    pub channel_id: String,
+    pub user_id: String,
+    /// 第一次操作前不知道貼文 ID
+    pub post_id: Option<String>,
+}
+
+/// 孤兒團購檢查所需的進行中團購資料
+#[derive(Debug, Clone)]
+pub struct OrphanCandidate {
+    pub group_buy_id: String,
+    pub post_id: Option<String>,
+    /// 未取消的登記數
+    pub order_count: i64,
+    /// 是否已記錄過 `orphan_notice`
+    pub notified: bool,
     pub created_at: DateTime<Utc>,
 }
 
@@ -1185,6 +5801,192 @@ pub struct AdjustmentRecord {
     pub new_quantity: i32,
 }
 
+/// 管理 API token 的權限範圍
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ApiTokenScope {
+    /// 只能呼叫唯讀端點
+    ReadOnly,
+    /// 唯讀 + 貼圖管理
+    StickerAdmin,
+    /// 唯讀 + 團購管理
+    GbAdmin,
+}
+
+impl fmt::Display for ApiTokenScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiTokenScope::ReadOnly => write!(f, "read-only"),
+            ApiTokenScope::StickerAdmin => write!(f, "sticker-admin"),
+            ApiTokenScope::GbAdmin => write!(f, "gb-admin"),
+        }
+    }
+}
+
+impl ApiTokenScope {
+    pub fn from_string(s: &str) -> Option<Self> {
+        match s {
+            "read-only" => Some(ApiTokenScope::ReadOnly),
+            "sticker-admin" => Some(ApiTokenScope::StickerAdmin),
+            "gb-admin" => Some(ApiTokenScope::GbAdmin),
+            _ => None,
+        }
+    }
+
+    /// 此範圍是否可呼叫需要 `required` 權限的端點
+    pub fn allows(self, required: ApiTokenScope) -> bool {
+        self == required || required == ApiTokenScope::ReadOnly
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiToken {
+    pub id: String,
+    pub name: String,
+    pub scope: ApiTokenScope,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl ApiToken {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self> {
+        let scope: String = row.try_get("scope")?;
+        let last_used_at: Option<String> = row.try_get("last_used_at")?;
+        let revoked_at: Option<String> = row.try_get("revoked_at")?;
+
+        Ok(ApiToken {
+            id: row.try_get("id")?,
+            name: row.try_get("name")?,
+            scope: ApiTokenScope::from_string(&scope)
+                .ok_or_else(|| anyhow::anyhow!("未知的 token 權限範圍: {}", scope))?,
+            created_by: row.try_get("created_by")?,
+            created_at: parse_rfc3339(&row.try_get::<String, _>("created_at")?)?,
+            last_used_at: last_used_at.as_deref().map(parse_rfc3339).transpose()?,
+            revoked_at: revoked_at.as_deref().map(parse_rfc3339).transpose()?,
+        })
+    }
+}
+
+/// 團購操作日誌查詢條件，未設定的欄位不篩選
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LogFilter {
+    #[serde(default)]
+    pub group_buy_id: Option<String>,
+    #[serde(default)]
+    pub action: Option<String>,
+    #[serde(default)]
+    pub user_id: Option<String>,
+    /// details 中的團購版本
+    #[serde(default)]
+    pub version: Option<i64>,
+    #[serde(default)]
+    pub since: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub until: Option<DateTime<Utc>>,
+    /// 最多回傳筆數，預設 100
+    #[serde(default)]
+    pub limit: Option<i64>,
+    /// 略過的筆數，用於分頁
+    #[serde(default)]
+    pub offset: Option<i64>,
+}
+
+/// 團購查詢條件，未設定的欄位不篩選；`since`、`until` 比對建立時間
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct GroupBuyQuery {
+    /// 狀態（`draft`、`active`、`closed`、`ordered`、`archived`）
+    #[serde(default)]
+    pub status: Option<String>,
+    #[serde(default)]
+    pub channel_id: Option<String>,
+    #[serde(default)]
+    pub creator_id: Option<String>,
+    #[serde(default)]
+    pub merchant_name: Option<String>,
+    #[serde(default)]
+    pub since: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub until: Option<DateTime<Utc>>,
+    /// 最多回傳筆數，預設 100
+    #[serde(default)]
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub offset: Option<i64>,
+}
+
+/// 登記查詢條件，未設定的欄位不篩選；`since`、`until` 比對登記時間
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct OrderQuery {
+    #[serde(default)]
+    pub group_buy_id: Option<String>,
+    #[serde(default)]
+    pub buyer_id: Option<String>,
+    #[serde(default)]
+    pub registrar_id: Option<String>,
+    #[serde(default)]
+    pub item_name: Option<String>,
+    #[serde(default)]
+    pub since: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub until: Option<DateTime<Utc>>,
+    /// 最多回傳筆數，預設 100
+    #[serde(default)]
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub offset: Option<i64>,
+}
+
+/// 貼圖發送紀錄查詢條件，未設定的欄位不篩選；`since`、`until` 比對發送時間
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct StickerUsageQuery {
+    #[serde(default)]
+    pub user_id: Option<String>,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub image_url: Option<String>,
+    #[serde(default)]
+    pub since: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub until: Option<DateTime<Utc>>,
+    /// 最多回傳筆數，預設 100
+    #[serde(default)]
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub offset: Option<i64>,
+}
+
+/// 一次貼圖發送。貼圖已從貼圖庫移除時沒有名稱與分類
+#[derive(Debug, Clone, Serialize)]
+pub struct StickerUsageRecord {
+    pub id: i64,
+    pub image_url: String,
+    pub name: Option<String>,
+    pub category: Option<String>,
+    pub user_id: String,
+    pub used_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupBuyLog {
+    pub id: i64,
+    pub group_buy_id: String,
+    pub user_id: String,
+    pub username: String,
+    pub action: String,
+    pub details: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiTokenLog {
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    pub created_at: DateTime<Utc>,
+}
+
 // SQLx Row 映射結構
 
 #[derive(sqlx::FromRow)]
@@ -1198,6 +6000,11 @@ struct GroupBuyRow {
     description: Option<String>,
     metadata: Option<String>,
     items: String,
+    item_icons: Option<String>,
+    item_sections: Option<String>,
+    item_translations: Option<String>,
+    order_fields: Option<String>,
+    currency: Option<String>,
     status: String,
     version: i64,
     created_at: String,
@@ -1220,6 +6027,27 @@ impl From<GroupBuyRow> for GroupBuy {
                 .and_then(|s| serde_json::from_str(s).ok())
                 .unwrap_or_default(),
             items: serde_json::from_str(&row.items).unwrap_or_default(),
+            item_icons: row
+                .item_icons
+                .as_deref()
+                .and_then(|s| serde_json::from_str(s).ok())
+                .unwrap_or_default(),
+            item_sections: row
+                .item_sections
+                .as_deref()
+                .and_then(|s| serde_json::from_str(s).ok())
+                .unwrap_or_default(),
+            item_translations: row
+                .item_translations
+                .as_deref()
+                .and_then(|s| serde_json::from_str(s).ok())
+                .unwrap_or_default(),
+            order_fields: row
+                .order_fields
+                .as_deref()
+                .and_then(|s| serde_json::from_str(s).ok())
+                .unwrap_or_default(),
+            currency: row.currency.unwrap_or_else(|| DEFAULT_CURRENCY.to_string()),
             status: GroupBuyStatus::from_string(&row.status),
             version: row.version as i32,
             created_at: DateTime::parse_from_rfc3339(&row.created_at)
@@ -1244,6 +6072,7 @@ struct GroupBuyOrderRow {
     quantity: i64,
     original_quantity: Option<i64>,
     unit_price: String, // 從資料庫讀取為字串
+    custom_fields: Option<String>,
     created_at: String,
 }
 
@@ -1260,6 +6089,11 @@ impl From<GroupBuyOrderRow> for GroupBuyOrder {
             quantity: row.quantity as i32,
             original_quantity: row.original_quantity.map(|v| v as i32),
             unit_price: Decimal::from_str(&row.unit_price).unwrap_or(Decimal::ZERO), // 從字串解析回 Decimal
+            custom_fields: row
+                .custom_fields
+                .as_deref()
+                .and_then(|s| serde_json::from_str(s).ok())
+                .unwrap_or_default(),
             created_at: DateTime::parse_from_rfc3339(&row.created_at)
                 .unwrap()
                 .with_timezone(&Utc),
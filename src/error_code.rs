@@ -0,0 +1,227 @@
+//! 錯誤代碼
+//!
+//! 回覆使用者的錯誤訊息（臨時訊息、dialog 錯誤）與 JSON 錯誤都帶上固定的代碼，
+//! 使用者回報問題時提供代碼即可查到原因，日誌也能依 `error_code` 欄位彙整。
+//! 代碼一覽見 DEV.md 的「錯誤代碼」。
+
+use serde::{Serialize, Serializer};
+use std::fmt;
+
+/// 錯誤代碼。代碼字串是對外的介面，新增可以，既有的不要改名
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// 找不到團購
+    GbNotFound,
+    /// 團購在操作期間被其他人修改（版本衝突）
+    GbConflict,
+    /// 團購已截止，不接受登記或修改
+    GbClosed,
+    /// 團購目前的狀態不允許此操作（例如尚未截止就調整缺貨）
+    GbInvalidStatus,
+    /// 沒有權限操作這個團購或這筆登記
+    GbForbidden,
+    /// 團購尚未設定商品
+    GbNoItems,
+    /// 找不到登記，或登記已被取消
+    OrderNotFound,
+    /// 找不到貼圖
+    StickerNotFound,
+    /// 按鈕無效、過期或已使用過
+    InvalidAction,
+    /// 對話框內容無法解析或驗證失敗
+    InvalidDialog,
+    /// slash command token 錯誤
+    InvalidToken,
+    /// 來源 IP 不在 callback 允許清單
+    ForbiddenSource,
+    /// 管理 API token 錯誤
+    InvalidApiToken,
+    /// 管理 API token 的權限範圍不包含此端點
+    InsufficientScope,
+    /// Webhook token 錯誤
+    InvalidWebhookToken,
+    /// 請求內容不是有效的 JSON
+    InvalidPayload,
+    /// 找不到端點
+    NotFound,
+    /// 功能已在 `features` 設定中停用
+    FeatureDisabled,
+    /// 呼叫 Mattermost API 失敗
+    MattermostError,
+    /// 資料庫錯誤
+    DatabaseError,
+    /// 未預期的錯誤
+    Internal,
+}
+
+impl ErrorCode {
+    /// 所有代碼，依 DEV.md 的順序
+    #[allow(dead_code)]
+    pub const ALL: &[ErrorCode] = &[
+        ErrorCode::GbNotFound,
+        ErrorCode::GbConflict,
+        ErrorCode::GbClosed,
+        ErrorCode::GbInvalidStatus,
+        ErrorCode::GbForbidden,
+        ErrorCode::GbNoItems,
+        ErrorCode::OrderNotFound,
+        ErrorCode::StickerNotFound,
+        ErrorCode::InvalidAction,
+        ErrorCode::InvalidDialog,
+        ErrorCode::InvalidToken,
+        ErrorCode::ForbiddenSource,
+        ErrorCode::InvalidApiToken,
+        ErrorCode::InsufficientScope,
+        ErrorCode::InvalidWebhookToken,
+        ErrorCode::InvalidPayload,
+        ErrorCode::NotFound,
+        ErrorCode::FeatureDisabled,
+        ErrorCode::MattermostError,
+        ErrorCode::DatabaseError,
+        ErrorCode::Internal,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::GbNotFound => "GB_NOT_FOUND",
+            ErrorCode::GbConflict => "GB_CONFLICT",
+            ErrorCode::GbClosed => "GB_CLOSED",
+            ErrorCode::GbInvalidStatus => "GB_INVALID_STATUS",
+            ErrorCode::GbForbidden => "GB_FORBIDDEN",
+            ErrorCode::GbNoItems => "GB_NO_ITEMS",
+            ErrorCode::OrderNotFound => "ORDER_NOT_FOUND",
+            ErrorCode::StickerNotFound => "STICKER_NOT_FOUND",
+            ErrorCode::InvalidAction => "INVALID_ACTION",
+            ErrorCode::InvalidDialog => "INVALID_DIALOG",
+            ErrorCode::InvalidToken => "INVALID_TOKEN",
+            ErrorCode::ForbiddenSource => "FORBIDDEN_SOURCE",
+            ErrorCode::InvalidApiToken => "INVALID_API_TOKEN",
+            ErrorCode::InsufficientScope => "INSUFFICIENT_SCOPE",
+            ErrorCode::InvalidWebhookToken => "INVALID_WEBHOOK_TOKEN",
+            ErrorCode::InvalidPayload => "INVALID_PAYLOAD",
+            ErrorCode::NotFound => "NOT_FOUND",
+            ErrorCode::FeatureDisabled => "FEATURE_DISABLED",
+            ErrorCode::MattermostError => "MATTERMOST_ERROR",
+            ErrorCode::DatabaseError => "DATABASE_ERROR",
+            ErrorCode::Internal => "INTERNAL",
+        }
+    }
+
+    /// 從錯誤鏈找出代碼：以 `CodedError` 標記的代碼優先，其次依錯誤來源判斷
+    pub fn of(err: &anyhow::Error) -> ErrorCode {
+        if let Some(coded) = err.chain().find_map(|e| e.downcast_ref::<CodedError>()) {
+            return coded.code;
+        }
+        if err.chain().any(|e| e.is::<sqlx::Error>()) {
+            ErrorCode::DatabaseError
+        } else if err.chain().any(|e| e.is::<reqwest::Error>()) {
+            ErrorCode::MattermostError
+        } else {
+            ErrorCode::Internal
+        }
+    }
+
+    /// 在訊息後附上代碼，例如 `⚠️ 此團購已截止（GB_CLOSED）`，並以 `error_code` 欄位記錄
+    pub fn user_message(self, message: impl fmt::Display) -> String {
+        tracing::warn!(error_code = self.as_str(), "{}", message);
+        format!("{}（{}）", message, self.as_str())
+    }
+
+    /// Interactive Message 的錯誤回應
+    pub fn ephemeral(self, message: impl fmt::Display) -> serde_json::Value {
+        serde_json::json!({
+            "ephemeral_text": self.user_message(message),
+            "error_code": self,
+        })
+    }
+
+    /// JSON API 的錯誤內容
+    pub fn json(self, message: impl fmt::Display) -> serde_json::Value {
+        tracing::warn!(error_code = self.as_str(), "{}", message);
+        serde_json::json!({
+            "error": message.to_string(),
+            "code": self,
+        })
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for ErrorCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// 帶有代碼的錯誤。以 `anyhow::Error` 傳遞，之後用 `ErrorCode::of` 取回代碼
+#[derive(Debug, thiserror::Error)]
+#[error("{message}")]
+pub struct CodedError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl CodedError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_codes_are_unique_and_uppercase() {
+        let codes: HashSet<&str> = ErrorCode::ALL.iter().map(|c| c.as_str()).collect();
+        assert_eq!(codes.len(), ErrorCode::ALL.len());
+        for code in codes {
+            assert!(
+                code.chars().all(|c| c.is_ascii_uppercase() || c == '_'),
+                "{}",
+                code
+            );
+        }
+        assert_eq!(
+            serde_json::to_value(ErrorCode::GbClosed).unwrap(),
+            serde_json::json!("GB_CLOSED")
+        );
+    }
+
+    #[test]
+    fn test_code_of_error_chain() {
+        let err = anyhow::Error::from(CodedError::new(ErrorCode::GbConflict, "團購已被修改"))
+            .context("更新失敗");
+        assert_eq!(ErrorCode::of(&err), ErrorCode::GbConflict);
+        assert_eq!(format!("{:#}", err), "更新失敗: 團購已被修改");
+
+        let err = Err::<(), _>(sqlx::Error::RowNotFound)
+            .context("查詢失敗")
+            .unwrap_err();
+        assert_eq!(ErrorCode::of(&err), ErrorCode::DatabaseError);
+
+        assert_eq!(
+            ErrorCode::of(&anyhow::anyhow!("其他錯誤")),
+            ErrorCode::Internal
+        );
+    }
+
+    #[test]
+    fn test_user_message() {
+        assert_eq!(
+            ErrorCode::GbClosed.user_message("⚠️ 此團購已截止"),
+            "⚠️ 此團購已截止（GB_CLOSED）"
+        );
+        let reply = ErrorCode::StickerNotFound.ephemeral("找不到指定的貼圖");
+        assert_eq!(reply["error_code"], "STICKER_NOT_FOUND");
+    }
+}
@@ -0,0 +1,440 @@
+//! 錯誤回報
+//!
+//! 以 tracing layer 收集 ERROR 等級的日誌（handler 錯誤、panic、WebSocket 斷線等），
+//! 連同所在 span 的欄位（request_id、method、path）送到 Sentry 或自訂的 webhook。
+//! 送出在背景 task 進行，佇列滿時直接丟棄，不會拖慢請求。
+//!
+//! `ErrorReporter` 存在 `AppState.error_reporter`，`reload` 時依新的配置重新建立送出的 task。
+
+use crate::config::ErrorReportingConfig;
+use crate::metrics::Metrics;
+use anyhow::{Context as _, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber, span, warn};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// 等待送出的錯誤上限，超過時丟棄新的錯誤
+const QUEUE_SIZE: usize = 100;
+
+/// 送出錯誤的逾時
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 一筆要回報的錯誤
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorEvent {
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub target: String,
+    pub message: String,
+    /// 日誌本身的欄位（例如 `error_code`）
+    pub fields: BTreeMap<String, String>,
+    /// 所在 span 的欄位，外層在前、內層覆蓋外層
+    pub context: BTreeMap<String, String>,
+    /// 所在 span 的名稱，由外而內
+    pub spans: Vec<String>,
+}
+
+impl ErrorEvent {
+    /// 自訂 webhook 的內容
+    pub fn webhook_payload(&self, environment: Option<&str>) -> serde_json::Value {
+        serde_json::json!({
+            "service": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+            "environment": environment,
+            "timestamp": self.timestamp.to_rfc3339(),
+            "level": "error",
+            "target": self.target,
+            "message": self.message,
+            "fields": self.fields,
+            "context": self.context,
+            "spans": self.spans,
+        })
+    }
+
+    /// Sentry event
+    pub fn sentry_event(&self, event_id: &str, environment: Option<&str>) -> serde_json::Value {
+        let mut tags = BTreeMap::new();
+        for key in ["error_code", "method", "path"] {
+            if let Some(value) = self.fields.get(key).or_else(|| self.context.get(key)) {
+                tags.insert(key, value.clone());
+            }
+        }
+        let mut extra = self.context.clone();
+        extra.extend(self.fields.clone());
+        if !self.spans.is_empty() {
+            extra.insert("spans".to_string(), self.spans.join(" > "));
+        }
+
+        serde_json::json!({
+            "event_id": event_id,
+            "timestamp": self.timestamp.to_rfc3339(),
+            "platform": "other",
+            "level": "error",
+            "logger": self.target,
+            "message": { "formatted": self.message },
+            "release": concat!(env!("CARGO_PKG_NAME"), "@", env!("CARGO_PKG_VERSION")),
+            "environment": environment,
+            "tags": tags,
+            "extra": extra,
+        })
+    }
+}
+
+/// Sentry DSN，例如 `https://<key>@o0.ingest.sentry.io/<project_id>`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SentryDsn {
+    pub public_key: String,
+    /// envelope 端點
+    pub envelope_url: String,
+    dsn: String,
+}
+
+impl SentryDsn {
+    pub fn parse(dsn: &str) -> Result<Self> {
+        let url = url::Url::parse(dsn).with_context(|| format!("無效的 Sentry DSN: {}", dsn))?;
+        let public_key = url.username();
+        if public_key.is_empty() {
+            anyhow::bail!("Sentry DSN 缺少 public key: {}", dsn);
+        }
+        let host = url
+            .host_str()
+            .with_context(|| format!("Sentry DSN 缺少主機: {}", dsn))?;
+        let path = url.path().trim_matches('/');
+        let (prefix, project_id) = match path.rsplit_once('/') {
+            Some((prefix, project_id)) => (format!("/{}", prefix), project_id),
+            None => (String::new(), path),
+        };
+        if project_id.is_empty() {
+            anyhow::bail!("Sentry DSN 缺少 project ID: {}", dsn);
+        }
+        let port = url.port().map(|p| format!(":{}", p)).unwrap_or_default();
+
+        Ok(Self {
+            public_key: public_key.to_string(),
+            envelope_url: format!(
+                "{}://{}{}{}/api/{}/envelope/",
+                url.scheme(),
+                host,
+                port,
+                prefix,
+                project_id
+            ),
+            dsn: dsn.to_string(),
+        })
+    }
+
+    fn auth_header(&self) -> String {
+        format!(
+            "Sentry sentry_version=7, sentry_client={}/{}, sentry_key={}",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+            self.public_key
+        )
+    }
+
+    /// 單一 event 的 envelope
+    fn envelope(&self, event_id: &str, event: &serde_json::Value) -> String {
+        format!(
+            "{}\n{}\n{}\n",
+            serde_json::json!({ "event_id": event_id, "dsn": self.dsn }),
+            serde_json::json!({ "type": "event" }),
+            event
+        )
+    }
+}
+
+/// 錯誤送往的目的地
+enum Sink {
+    Sentry(SentryDsn),
+    Webhook(String),
+}
+
+impl Sink {
+    async fn send(
+        &self,
+        client: &reqwest::Client,
+        event: &ErrorEvent,
+        environment: Option<&str>,
+    ) -> Result<()> {
+        let request = match self {
+            Sink::Sentry(dsn) => {
+                let event_id = uuid::Uuid::new_v4().simple().to_string();
+                let body = dsn.envelope(&event_id, &event.sentry_event(&event_id, environment));
+                client
+                    .post(&dsn.envelope_url)
+                    .header("X-Sentry-Auth", dsn.auth_header())
+                    .header("Content-Type", "application/x-sentry-envelope")
+                    .body(body)
+            }
+            Sink::Webhook(url) => client.post(url).json(&event.webhook_payload(environment)),
+        };
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            anyhow::bail!("回應 {}", response.status());
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Sink::Sentry(_) => "Sentry",
+            Sink::Webhook(_) => "webhook",
+        }
+    }
+}
+
+impl ErrorReportingConfig {
+    /// 載入配置時檢查 DSN 格式
+    pub fn validate(&self) -> Result<()> {
+        if let Some(dsn) = &self.sentry_dsn {
+            SentryDsn::parse(dsn)?;
+        }
+        Ok(())
+    }
+}
+
+/// 錯誤回報的佇列與 ERROR 日誌的計數，`ErrorReportLayer` 與 `AppState` 共用
+pub struct ErrorReporter {
+    metrics: Arc<Metrics>,
+    /// 目前送出 task 的佇列，未設定回報目的地時為 `None`
+    sender: RwLock<Option<mpsc::Sender<ErrorEvent>>>,
+}
+
+impl ErrorReporter {
+    /// 尚未設定回報目的地的 reporter，ERROR 日誌只計入 `metrics`
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self {
+            metrics,
+            sender: RwLock::new(None),
+        }
+    }
+
+    /// 依配置啟動回報的背景 task，取代先前的 task（舊的 task 送完佇列中的錯誤後結束）。
+    /// 未設定 `sentry_dsn` 與 `webhook_url` 時停止回報
+    pub fn configure(&self, config: &ErrorReportingConfig) -> Result<()> {
+        let mut sinks = Vec::new();
+        if let Some(dsn) = &config.sentry_dsn {
+            sinks.push(Sink::Sentry(SentryDsn::parse(dsn)?));
+        }
+        if let Some(url) = &config.webhook_url {
+            sinks.push(Sink::Webhook(url.clone()));
+        }
+        if sinks.is_empty() {
+            self.set_sender(None);
+            return Ok(());
+        }
+
+        let client = reqwest::Client::builder()
+            .timeout(SEND_TIMEOUT)
+            .build()
+            .context("建立錯誤回報用的 HTTP 客戶端失敗")?;
+        let environment = config.environment.clone();
+        let (sender, mut receiver) = mpsc::channel::<ErrorEvent>(QUEUE_SIZE);
+
+        tokio::spawn(async move {
+            while let Some(event) = receiver.recv().await {
+                for sink in &sinks {
+                    // 只記 warn：記成 error 會再被回報一次
+                    if let Err(e) = sink.send(&client, &event, environment.as_deref()).await {
+                        warn!("錯誤回報送往 {} 失敗: {}", sink.name(), e);
+                    }
+                }
+            }
+        });
+
+        self.set_sender(Some(sender));
+        Ok(())
+    }
+
+    fn set_sender(&self, sender: Option<mpsc::Sender<ErrorEvent>>) {
+        *self.sender.write().unwrap_or_else(|e| e.into_inner()) = sender;
+    }
+
+    fn sender(&self) -> Option<mpsc::Sender<ErrorEvent>> {
+        self.sender
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// 是否有設定回報目的地
+    pub fn is_enabled(&self) -> bool {
+        self.sender
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_some()
+    }
+}
+
+/// 收集 ERROR 日誌的 tracing layer
+pub struct ErrorReportLayer {
+    reporter: Arc<ErrorReporter>,
+}
+
+impl ErrorReportLayer {
+    pub fn new(reporter: Arc<ErrorReporter>) -> Self {
+        Self { reporter }
+    }
+}
+
+/// span 建立時記下的欄位
+struct SpanFields(BTreeMap<String, String>);
+
+#[derive(Default)]
+struct FieldVisitor(BTreeMap<String, String>);
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value));
+    }
+}
+
+impl<S> Layer<S> for ErrorReportLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        // 未啟用回報時不必保存欄位
+        if !self.reporter.is_enabled() {
+            return;
+        }
+        let mut visitor = FieldVisitor::default();
+        attrs.record(&mut visitor);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanFields(visitor.0));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if *event.metadata().level() != Level::ERROR {
+            return;
+        }
+        // 未啟用回報時也計入次數，DM `status` 指令會顯示
+        self.reporter.metrics.record_error();
+        let Some(sender) = self.reporter.sender() else {
+            return;
+        };
+
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        let mut fields = visitor.0;
+        let message = fields.remove("message").unwrap_or_default();
+
+        let mut context = BTreeMap::new();
+        let mut spans = Vec::new();
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                spans.push(span.name().to_string());
+                if let Some(span_fields) = span.extensions().get::<SpanFields>() {
+                    context.extend(span_fields.0.clone());
+                }
+            }
+        }
+
+        // 佇列滿時丟棄，避免錯誤大量發生時佔用記憶體
+        let _ = sender.try_send(ErrorEvent {
+            timestamp: chrono::Utc::now(),
+            target: event.metadata().target().to_string(),
+            message,
+            fields,
+            context,
+            spans,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::prelude::*;
+
+    #[test]
+    fn test_parse_sentry_dsn() {
+        let dsn = SentryDsn::parse("https://abc123@o42.ingest.sentry.io/4505").unwrap();
+        assert_eq!(dsn.public_key, "abc123");
+        assert_eq!(
+            dsn.envelope_url,
+            "https://o42.ingest.sentry.io/api/4505/envelope/"
+        );
+
+        let dsn = SentryDsn::parse("http://key@sentry.local:9000/sentry/7").unwrap();
+        assert_eq!(
+            dsn.envelope_url,
+            "http://sentry.local:9000/sentry/api/7/envelope/"
+        );
+
+        assert!(SentryDsn::parse("https://o42.ingest.sentry.io/4505").is_err());
+        assert!(SentryDsn::parse("https://key@o42.ingest.sentry.io/").is_err());
+        assert!(SentryDsn::parse("not a dsn").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_configure_replaces_sender() {
+        let reporter = ErrorReporter::new(Arc::default());
+        assert!(!reporter.is_enabled());
+
+        let mut config = ErrorReportingConfig {
+            webhook_url: Some("http://127.0.0.1:1/errors".to_string()),
+            ..Default::default()
+        };
+        reporter.configure(&config).unwrap();
+        let first = reporter.sender().unwrap();
+        assert!(reporter.is_enabled());
+
+        // 重新載入配置後換成新的 task，舊的佇列不再使用
+        config.environment = Some("staging".to_string());
+        reporter.configure(&config).unwrap();
+        assert!(!reporter.sender().unwrap().same_channel(&first));
+
+        config.webhook_url = None;
+        reporter.configure(&config).unwrap();
+        assert!(!reporter.is_enabled());
+    }
+
+    #[test]
+    fn test_layer_captures_errors_with_span_context() {
+        let (sender, mut receiver) = mpsc::channel(10);
+        let metrics = Arc::new(Metrics::default());
+        let reporter = ErrorReporter::new(metrics.clone());
+        reporter.set_sender(Some(sender));
+        let subscriber =
+            tracing_subscriber::registry().with(ErrorReportLayer::new(Arc::new(reporter)));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("request", request_id = "abcd1234", path = "/action");
+            let _entered = span.enter();
+            tracing::info!("一般日誌不回報");
+            tracing::error!(error_code = "GB_CONFLICT", "更新失敗: {}", "版本衝突");
+        });
+
+        let event = receiver.try_recv().unwrap();
+        assert_eq!(event.message, "更新失敗: 版本衝突");
+        assert_eq!(event.fields["error_code"], "GB_CONFLICT");
+        assert_eq!(event.context["request_id"], "abcd1234");
+        assert_eq!(event.context["path"], "/action");
+        assert_eq!(event.spans, vec!["request".to_string()]);
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(metrics.error_count(), 1);
+
+        let sentry = event.sentry_event("e1", Some("production"));
+        assert_eq!(sentry["message"]["formatted"], "更新失敗: 版本衝突");
+        assert_eq!(sentry["tags"]["error_code"], "GB_CONFLICT");
+        assert_eq!(sentry["tags"]["path"], "/action");
+        assert_eq!(sentry["extra"]["request_id"], "abcd1234");
+        assert_eq!(sentry["environment"], "production");
+
+        let webhook = event.webhook_payload(None);
+        assert_eq!(webhook["context"]["request_id"], "abcd1234");
+        assert_eq!(webhook["level"], "error");
+    }
+}
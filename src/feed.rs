@@ -0,0 +1,333 @@
+//! RSS／Atom feed 解析
+//!
+//! 只取出 feed watcher 需要的欄位：feed 標題，以及每個項目的 ID、標題、連結、摘要與發布時間。
+//! RSS 2.0 讀 `<item>`（`guid`、`link`、`description`、`pubDate`），Atom 讀 `<entry>`
+//! （`id`、`<link rel="alternate" href>`、`summary`／`content`、`published`／`updated`）。
+//! 不是完整的 XML parser：不處理 DTD 與巢狀的同名元素，但支援 CDATA、字元實體與命名空間前綴
+//! 以外的常見寫法，足以讀取一般網站與 GitHub releases 的 feed。
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+
+/// 摘要的長度上限（字元）
+const MAX_SUMMARY_CHARS: usize = 300;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Feed {
+    pub title: String,
+    pub items: Vec<FeedItem>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedItem {
+    /// 用來判斷是否已經貼過：guid／id，沒有時依序以連結、標題代替
+    pub id: String,
+    pub title: String,
+    pub link: String,
+    /// 去除 HTML 標籤後的摘要
+    pub summary: String,
+    pub published: Option<DateTime<Utc>>,
+}
+
+/// 一個元素：開始標籤中的屬性與內容（自我結束的元素沒有內容）
+struct Element<'a> {
+    attrs: &'a str,
+    inner: Option<&'a str>,
+}
+
+/// 依序找出名稱為 `tag` 的元素
+fn elements<'a>(xml: &'a str, tag: &str) -> Vec<Element<'a>> {
+    let open = format!("<{}", tag);
+    let close = format!("</{}>", tag);
+    let mut found = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        let after = &rest[start + open.len()..];
+        // 排除名稱只是開頭相同的元素（例如 <title> 與 <titles>）
+        if !after.starts_with(|c: char| c.is_whitespace() || c == '>' || c == '/') {
+            rest = after;
+            continue;
+        }
+        let Some(tag_end) = after.find('>') else {
+            break;
+        };
+        let attrs = &after[..tag_end];
+        let body = &after[tag_end + 1..];
+        if let Some(attrs) = attrs.strip_suffix('/') {
+            found.push(Element { attrs, inner: None });
+            rest = body;
+            continue;
+        }
+        let Some(end) = body.find(&close) else {
+            break;
+        };
+        found.push(Element {
+            attrs,
+            inner: Some(&body[..end]),
+        });
+        rest = &body[end + close.len()..];
+    }
+    found
+}
+
+/// 第一個名稱為 `tag` 的元素的文字內容
+fn child_text(xml: &str, tag: &str) -> Option<String> {
+    elements(xml, tag)
+        .into_iter()
+        .find_map(|e| e.inner)
+        .map(text)
+        .filter(|t| !t.is_empty())
+}
+
+/// 開始標籤中的屬性值
+fn attr(attrs: &str, name: &str) -> Option<String> {
+    for quote in ['"', '\''] {
+        let pattern = format!("{}={}", name, quote);
+        let mut rest = attrs;
+        while let Some(pos) = rest.find(&pattern) {
+            let preceded_by_space = rest[..pos].ends_with(char::is_whitespace) || pos == 0;
+            let value = &rest[pos + pattern.len()..];
+            if preceded_by_space && let Some(end) = value.find(quote) {
+                return Some(decode_entities(&value[..end]));
+            }
+            rest = value;
+        }
+    }
+    None
+}
+
+/// 元素內容的文字：CDATA 原樣保留，其他部分解開字元實體
+fn text(inner: &str) -> String {
+    let mut out = String::new();
+    let mut rest = inner;
+    while let Some(start) = rest.find("<![CDATA[") {
+        out.push_str(&decode_entities(&rest[..start]));
+        let cdata = &rest[start + "<![CDATA[".len()..];
+        let end = cdata.find("]]>").unwrap_or(cdata.len());
+        out.push_str(&cdata[..end]);
+        rest = cdata.get(end + "]]>".len()..).unwrap_or("");
+    }
+    out.push_str(&decode_entities(rest));
+    out.trim().to_string()
+}
+
+/// 解開 XML 的字元實體（`&amp;`、`&#39;`、`&#x27;` 等）
+fn decode_entities(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let decoded = after.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &after[..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|n| n.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// 去除 HTML 標籤並合併空白，超過長度上限時截斷
+fn summarize(html: &str) -> String {
+    let mut plain = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => {
+                in_tag = true;
+                plain.push(' ');
+            }
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => plain.push(c),
+            _ => {}
+        }
+    }
+    let collapsed = plain.split_whitespace().collect::<Vec<_>>().join(" ");
+    if collapsed.chars().count() > MAX_SUMMARY_CHARS {
+        let truncated: String = collapsed.chars().take(MAX_SUMMARY_CHARS).collect();
+        format!("{}…", truncated.trim_end())
+    } else {
+        collapsed
+    }
+}
+
+/// RSS 的 pubDate 是 RFC 2822，Atom 是 RFC 3339
+fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(value)
+        .or_else(|_| DateTime::parse_from_rfc3339(value))
+        .ok()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// Atom 項目的連結：`rel="alternate"` 或沒有 rel 的 `<link href>`
+fn atom_link(entry: &str) -> Option<String> {
+    elements(entry, "link")
+        .into_iter()
+        .filter(|link| attr(link.attrs, "rel").is_none_or(|rel| rel == "alternate"))
+        .find_map(|link| attr(link.attrs, "href"))
+}
+
+fn parse_item(xml: &str) -> FeedItem {
+    let title = child_text(xml, "title").unwrap_or_default();
+    let link = elements(xml, "link")
+        .into_iter()
+        .find_map(|e| e.inner.map(text).filter(|t| !t.is_empty()))
+        .or_else(|| atom_link(xml))
+        .unwrap_or_default();
+    let summary = ["description", "summary", "content", "content:encoded"]
+        .iter()
+        .find_map(|tag| child_text(xml, tag))
+        .map(|html| summarize(&html))
+        .unwrap_or_default();
+    let published = ["pubDate", "published", "updated", "dc:date"]
+        .iter()
+        .find_map(|tag| child_text(xml, tag).and_then(|d| parse_date(&d)));
+    let id = child_text(xml, "guid")
+        .or_else(|| child_text(xml, "id"))
+        .unwrap_or_else(|| {
+            if link.is_empty() {
+                title.clone()
+            } else {
+                link.clone()
+            }
+        });
+
+    FeedItem {
+        id,
+        title,
+        link,
+        summary,
+        published,
+    }
+}
+
+/// 解析 RSS 或 Atom feed，項目依 feed 中的順序排列。不是 RSS 或 Atom 時回傳錯誤
+pub fn parse_feed(content: &str) -> Result<Feed> {
+    let (header_end, items) = if content.contains("<rss") || content.contains("<rdf:RDF") {
+        let items = elements(content, "item");
+        (content.find("<item"), items)
+    } else if content.contains("<feed") {
+        let entries = elements(content, "entry");
+        (content.find("<entry"), entries)
+    } else {
+        anyhow::bail!("不是 RSS 或 Atom feed");
+    };
+
+    let header = &content[..header_end.unwrap_or(content.len())];
+    Ok(Feed {
+        title: child_text(header, "title").unwrap_or_default(),
+        items: items
+            .into_iter()
+            .filter_map(|e| e.inner)
+            .map(parse_item)
+            .filter(|item| !item.id.is_empty())
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rss() {
+        let rss = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0">
+  <channel>
+    <title>Leko &amp; Friends</title>
+    <link>https://example.com/</link>
+    <item>
+      <title><![CDATA[v1.2.0 <released>]]></title>
+      <link>https://example.com/releases/1.2.0</link>
+      <guid isPermaLink="false">release-1.2.0</guid>
+      <description>&lt;p&gt;新增 &lt;b&gt;LINE&lt;/b&gt; 貼圖&lt;/p&gt;</description>
+      <pubDate>Tue, 06 Oct 2026 08:00:00 +0800</pubDate>
+    </item>
+    <item>
+      <title>v1.1.0</title>
+      <link>https://example.com/releases/1.1.0</link>
+    </item>
+  </channel>
+</rss>"#;
+
+        let feed = parse_feed(rss).unwrap();
+        assert_eq!(feed.title, "Leko & Friends");
+        assert_eq!(feed.items.len(), 2);
+        let first = &feed.items[0];
+        assert_eq!(first.id, "release-1.2.0");
+        assert_eq!(first.title, "v1.2.0 <released>");
+        assert_eq!(first.link, "https://example.com/releases/1.2.0");
+        assert_eq!(first.summary, "新增 LINE 貼圖");
+        assert_eq!(
+            first.published,
+            Some("2026-10-06T00:00:00Z".parse().unwrap())
+        );
+        // 沒有 guid 時以連結代替
+        assert_eq!(feed.items[1].id, "https://example.com/releases/1.1.0");
+        assert_eq!(feed.items[1].published, None);
+    }
+
+    #[test]
+    fn test_parse_atom() {
+        let atom = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title type="text">Release notes</title>
+  <link rel="self" href="https://example.com/feed.atom"/>
+  <entry>
+    <id>tag:example.com,2026:42</id>
+    <title>Build #42</title>
+    <link rel="self" href="https://example.com/api/42"/>
+    <link rel="alternate" type="text/html" href="https://example.com/builds/42?a=1&amp;b=2"/>
+    <updated>2026-10-06T01:02:03Z</updated>
+    <summary type="html">All &#x2705; passed</summary>
+  </entry>
+</feed>"#;
+
+        let feed = parse_feed(atom).unwrap();
+        assert_eq!(feed.title, "Release notes");
+        assert_eq!(feed.items.len(), 1);
+        let entry = &feed.items[0];
+        assert_eq!(entry.id, "tag:example.com,2026:42");
+        assert_eq!(entry.link, "https://example.com/builds/42?a=1&b=2");
+        assert_eq!(entry.summary, "All ✅ passed");
+        assert_eq!(
+            entry.published,
+            Some("2026-10-06T01:02:03Z".parse().unwrap())
+        );
+
+        assert!(parse_feed("<html><body>404</body></html>").is_err());
+    }
+
+    #[test]
+    fn test_summarize_truncates() {
+        let long = "字".repeat(MAX_SUMMARY_CHARS + 10);
+        let summary = summarize(&long);
+        assert_eq!(summary.chars().count(), MAX_SUMMARY_CHARS + 1);
+        assert!(summary.ends_with('…'));
+        assert_eq!(decode_entities("a &unknown; &#65;"), "a &unknown; A");
+    }
+}
@@ -2,21 +2,47 @@
 
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
+use super::sticker::load_panel_stickers;
+use super::sticker_panel::{PanelSource, StickerPanel};
 use crate::AppState;
-use crate::mattermost::{Action, ActionOption, ActionRequest, Attachment, Integration};
+use crate::database::Database;
+use crate::error_code::ErrorCode;
+use crate::mattermost::{ActionRequest, Post};
+use crate::signing::ContextError;
+
+/// 貼圖面板過期時回覆的訊息
+const PANEL_EXPIRED_TEXT: &str = "此選擇器已過期，請重新輸入 /sticker";
 
 /// 處理 Interactive Message Action callback
 pub async fn handle_action(
     action_req: ActionRequest,
     state: Arc<RwLock<AppState>>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    info!("收到 Action 請求: {:?}", action_req);
-    info!(
-        "Context 內容: {}",
-        serde_json::to_string_pretty(&action_req.context).unwrap_or_default()
-    );
+    info!("收到 Action 請求");
+    crate::logging::log_request(&state.read().await.config.logging.routes, "/action", || {
+        format!("{:?}", action_req)
+    });
+
+    let action_type = action_req
+        .context
+        .get("action")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+
+    // 驗證 context 簽章，確保 user_id、貼圖網址等欄位由本 bot 產生
+    let (signer, database, nonce_store) = {
+        let app_state = state.read().await;
+        (
+            app_state.mattermost_client.signer().clone(),
+            app_state.database.clone(),
+            app_state.nonce_store.clone(),
+        )
+    };
+    if let Err(e) = signer.verify_context(&action_req.context) {
+        return Ok(reject_context(e, &action_req, &state).await);
+    }
 
     // 權限檢查：只有觸發指令的使用者才能操作
     let original_user_id = action_req
@@ -35,25 +61,216 @@ pub async fn handle_action(
         })));
     }
 
-    let action_type = action_req
-        .context
-        .get("action")
-        .and_then(|v| v.as_str())
-        .unwrap_or("");
+    // 發送貼圖只能成功一次，避免重放
+    if action_type == "send_sticker"
+        && let Err(e) = signer
+            .consume_context(&action_req.context, &nonce_store)
+            .await
+    {
+        return Ok(reject_context(e, &action_req, &state).await);
+    }
+
+    track_picker(&action_req, action_type, &database).await;
 
     match action_type {
         "cancel" => handle_cancel(),
         "select_sticker" => handle_select_sticker(&action_req, state).await,
+        "sticker_page" => handle_sticker_page(&action_req, state).await,
         "send_sticker" => handle_send_sticker(&action_req, state).await,
+        "toggle_favorite" => handle_toggle_favorite(&action_req, state).await,
         _ => {
             error!("未知的 action 類型: {}", action_type);
-            Ok(warp::reply::json(&serde_json::json!({
-                "ephemeral_text": "未知的操作"
-            })))
+            Ok(warp::reply::json(
+                &ErrorCode::InvalidAction.ephemeral("未知的操作"),
+            ))
+        }
+    }
+}
+
+/// context 驗證失敗時的回覆。過期（或簽章功能上線前產生）的面板一併清除，
+/// 避免貼圖庫重新載入後舊面板的索引對應到別張貼圖
+async fn reject_context(
+    e: ContextError,
+    action_req: &ActionRequest,
+    state: &Arc<RwLock<AppState>>,
+) -> warp::reply::Json {
+    match e {
+        ContextError::Expired | ContextError::Unsigned => {
+            info!("貼圖面板已過期（{}），清除訊息 {}", e, action_req.post_id);
+            expired_panel_reply(action_req, state).await
+        }
+        _ => context_error_reply(e),
+    }
+}
+
+/// 刪除過期的面板訊息並提示重新搜尋；無法刪除時改為清掉訊息上的按鈕
+async fn expired_panel_reply(
+    action_req: &ActionRequest,
+    state: &Arc<RwLock<AppState>>,
+) -> warp::reply::Json {
+    let client = state.read().await.mattermost_client.clone();
+    let deleted = !action_req.post_id.is_empty()
+        && match client.delete_post(&action_req.post_id).await {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("刪除過期的貼圖面板失敗，改為清除按鈕: {}", e);
+                false
+            }
+        };
+
+    if deleted {
+        warp::reply::json(&serde_json::json!({
+            "ephemeral_text": PANEL_EXPIRED_TEXT
+        }))
+    } else {
+        warp::reply::json(&serde_json::json!({
+            "update": {
+                "message": "",
+                "props": {}
+            },
+            "ephemeral_text": PANEL_EXPIRED_TEXT
+        }))
+    }
+}
+
+/// context 驗證失敗時回覆給使用者的訊息
+fn context_error_reply(e: ContextError) -> warp::reply::Json {
+    error!("拒絕 Action 請求（{}）", e);
+    let message = match e {
+        ContextError::Expired | ContextError::Unsigned => PANEL_EXPIRED_TEXT,
+        ContextError::Replayed => "⚠️ 此貼圖已經發送過了",
+        ContextError::Unavailable => "⚠️ 暫時無法發送貼圖，請稍後再試",
+        ContextError::Invalid => "⚠️ 無效的操作",
+    };
+    warp::reply::json(&serde_json::json!({
+        "ephemeral_text": message
+    }))
+}
+
+/// context 中的頁碼，沒有時為第一頁
+fn context_page(action_req: &ActionRequest) -> usize {
+    action_req
+        .context
+        .get("page")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(0) as usize
+}
+
+/// context 中的選擇器 ID，沒有時為空字串（不追蹤）
+fn context_picker(action_req: &ActionRequest) -> &str {
+    action_req
+        .context
+        .get("picker")
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+}
+
+/// 選擇器是否為臨時訊息
+fn context_ephemeral(action_req: &ActionRequest) -> bool {
+    action_req
+        .context
+        .get("ephemeral")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
+/// 更新選擇器的追蹤狀態：發送或取消後不再追蹤，其他操作延後自動清除的時間
+async fn track_picker(action_req: &ActionRequest, action_type: &str, database: &Database) {
+    let picker_id = context_picker(action_req);
+    if picker_id.is_empty() {
+        return;
+    }
+    let result = match action_type {
+        "send_sticker" | "cancel" => database.delete_sticker_picker(picker_id).await,
+        _ => {
+            database
+                .touch_sticker_picker(picker_id, &action_req.post_id)
+                .await
         }
+    };
+    if let Err(e) = result {
+        warn!("更新貼圖選擇器 {} 的追蹤狀態失敗: {}", picker_id, e);
     }
 }
 
+/// 換頁：重新搜尋並顯示指定頁的下拉選單
+async fn handle_sticker_page(
+    action_req: &ActionRequest,
+    state: Arc<RwLock<AppState>>,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    let user_id = action_req
+        .context
+        .get("user_id")
+        .and_then(|v| v.as_str())
+        .unwrap_or(&action_req.user_id);
+    let user_name = action_req
+        .context
+        .get("user_name")
+        .and_then(|v| v.as_str())
+        .or(action_req.user_name.as_deref())
+        .unwrap_or("Unknown");
+    let keyword = action_req
+        .context
+        .get("keyword")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+    let page = context_page(action_req);
+    let source = PanelSource::from_context(&action_req.context);
+
+    let app_state = state.read().await;
+    let sticker_db = app_state.sticker_database.clone();
+    let database = app_state.database.clone();
+    let callback_url = app_state
+        .config
+        .mattermost
+        .bot_callback_url
+        .as_ref()
+        .map(|url| format!("{}/action", url.trim_end_matches('/')))
+        .unwrap_or_else(|| "http://localhost/action".to_string());
+    let signer = app_state.mattermost_client.signer().clone();
+    drop(app_state);
+
+    let stickers = match load_panel_stickers(&sticker_db, &database, user_id, keyword, source).await
+    {
+        Ok(v) => v,
+        Err(e) => {
+            error!("重新搜尋貼圖失敗: {}", e);
+            return Ok(warp::reply::json(&serde_json::json!({
+                "ephemeral_text": "搜尋貼圖失敗，請稍後再試"
+            })));
+        }
+    };
+    if stickers.is_empty() {
+        return Ok(warp::reply::json(
+            &ErrorCode::StickerNotFound.ephemeral("找不到符合的貼圖，請重新搜尋"),
+        ));
+    }
+
+    info!("貼圖面板換到第 {} 頁", page + 1);
+
+    let attachment = StickerPanel {
+        callback_url: &callback_url,
+        signer: &signer,
+        user_id,
+        user_name,
+        keyword,
+        source,
+        picker_id: context_picker(action_req),
+        ephemeral: context_ephemeral(action_req),
+        page,
+    }
+    .picker(&stickers);
+
+    Ok(warp::reply::json(&serde_json::json!({
+        "update": {
+            "message": "",
+            "props": {
+                "attachments": [attachment]
+            }
+        }
+    })))
+}
+
 /// 取消：清空訊息
 fn handle_cancel() -> Result<warp::reply::Json, warp::Rejection> {
     info!("使用者取消了貼圖選擇");
@@ -102,9 +319,12 @@ async fn handle_select_sticker(
         .get("keyword")
         .and_then(|v| v.as_str())
         .unwrap_or("");
+    let page = context_page(action_req);
+    let source = PanelSource::from_context(&action_req.context);
 
     let app_state = state.read().await;
     let sticker_db = app_state.sticker_database.clone();
+    let database = app_state.database.clone();
     let callback_url = app_state
         .config
         .mattermost
@@ -113,10 +333,12 @@ async fn handle_select_sticker(
         .map(|url| format!("{}/action", url.trim_end_matches('/')))
         .unwrap_or_else(|| "http://localhost/action".to_string());
     let mattermost_url = app_state.config.mattermost.url.clone();
+    let signer = app_state.mattermost_client.signer().clone();
     drop(app_state);
 
-    let stickers = match sticker_db.search_async(keyword, None).await {
-        Ok(v) => v.into_iter().take(25).collect::<Vec<_>>(),
+    let stickers = match load_panel_stickers(&sticker_db, &database, user_id, keyword, source).await
+    {
+        Ok(v) => v,
         Err(e) => {
             error!("重新搜尋貼圖失敗: {}", e);
             return Ok(warp::reply::json(&serde_json::json!({
@@ -127,9 +349,9 @@ async fn handle_select_sticker(
 
     let Some(sticker) = stickers.get(sticker_index) else {
         error!("找不到貼圖索引: {}", sticker_index);
-        return Ok(warp::reply::json(&serde_json::json!({
-            "ephemeral_text": "找不到指定的貼圖"
-        })));
+        return Ok(warp::reply::json(
+            &ErrorCode::StickerNotFound.ephemeral("找不到指定的貼圖"),
+        ));
     };
 
     info!(
@@ -137,80 +359,151 @@ async fn handle_select_sticker(
         sticker.name, sticker_index
     );
 
-    let sticker_options: Vec<ActionOption> = stickers
-        .iter()
-        .enumerate()
-        .map(|(idx, s)| ActionOption {
-            text: s.get_display_name(),
-            value: idx.to_string(),
-        })
-        .collect();
-
-    // 克隆需要的資料
-    let sticker_name = sticker.name.clone();
-    let sticker_display_name = sticker.get_display_name();
-    let sticker_image_url = sticker.image_url.clone();
-
-    // 建立包含預覽的 Interactive Message
-    let attachment = Attachment {
-        fallback: Some(format!("已選擇: {}", sticker_name)),
-        color: Some("#36a64f".to_string()),
-        pretext: None,
-        text: Some(format!("已選擇: **{}**", sticker_display_name)),
-        author_name: Some(user_name.to_string()),
-        author_icon: Some(format!("{}/api/v4/users/{}/image", mattermost_url, user_id)),
-        title: Some("🎨 貼圖預覽".to_string()),
-        image_url: Some(sticker_image_url.clone()),
-        thumb_url: None,
-        actions: Some(vec![
-            Action {
-                id: "stickerselect".to_string(),
-                name: "選擇貼圖".to_string(),
-                action_type: "select".to_string(),
-                style: None,
-                integration: Some(Integration {
-                    url: callback_url.clone(),
-                    context: Some(serde_json::json!({
-                        "action": "select_sticker",
-                        "user_id": user_id,
-                        "user_name": user_name,
-                        "keyword": keyword,
-                    })),
-                }),
-                options: Some(sticker_options),
-            },
-            Action {
-                id: "send".to_string(),
-                name: "✅ 發送".to_string(),
-                action_type: "button".to_string(),
-                style: Some("primary".to_string()),
-                integration: Some(Integration {
-                    url: callback_url.clone(),
-                    context: Some(serde_json::json!({
-                        "action": "send_sticker",
-                        "sticker_name": sticker_name,
-                        "sticker_image_url": sticker_image_url,
-                        "user_id": user_id,
-                        "user_name": user_name,
-                    })),
-                }),
-                options: None,
-            },
-            Action {
-                id: "cancel".to_string(),
-                name: "❌ 取消".to_string(),
-                action_type: "button".to_string(),
-                style: Some("danger".to_string()),
-                integration: Some(Integration {
-                    url: callback_url.clone(),
-                    context: Some(serde_json::json!({
-                        "action": "cancel",
-                        "user_id": user_id,
-                    })),
-                }),
-                options: None,
-            },
-        ]),
+    // 最愛狀態只影響按鈕文字，查詢失敗時當作尚未加入
+    let favorite = sticker_db
+        .is_favorite(user_id, &sticker.image_url)
+        .await
+        .unwrap_or_else(|e| {
+            error!("查詢最愛貼圖失敗: {}", e);
+            false
+        });
+
+    let attachment = StickerPanel {
+        callback_url: &callback_url,
+        signer: &signer,
+        user_id,
+        user_name,
+        keyword,
+        source,
+        picker_id: context_picker(action_req),
+        ephemeral: context_ephemeral(action_req),
+        page,
+    }
+    .preview(
+        &stickers,
+        sticker,
+        format!("{}/api/v4/users/{}/image", mattermost_url, user_id),
+        favorite,
+    );
+
+    Ok(warp::reply::json(&serde_json::json!({
+        "update": {
+            "message": "",
+            "props": {
+                "attachments": [attachment]
+            }
+        }
+    })))
+}
+
+/// 加入或移除最愛：更新後重新顯示同一張貼圖的預覽。
+/// 在最愛清單中移除的貼圖不會再出現在清單裡，這時改回顯示下拉選單
+async fn handle_toggle_favorite(
+    action_req: &ActionRequest,
+    state: Arc<RwLock<AppState>>,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    let image_url = action_req
+        .context
+        .get("sticker_image_url")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+    let favorite = action_req
+        .context
+        .get("favorite")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let user_id = action_req
+        .context
+        .get("user_id")
+        .and_then(|v| v.as_str())
+        .unwrap_or(&action_req.user_id);
+    let user_name = action_req
+        .context
+        .get("user_name")
+        .and_then(|v| v.as_str())
+        .or(action_req.user_name.as_deref())
+        .unwrap_or("Unknown");
+    let keyword = action_req
+        .context
+        .get("keyword")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+    let page = context_page(action_req);
+    let source = PanelSource::from_context(&action_req.context);
+
+    if image_url.is_empty() {
+        error!("toggle_favorite 缺少貼圖網址");
+        return Ok(warp::reply::json(
+            &ErrorCode::InvalidAction.ephemeral("無效的操作"),
+        ));
+    }
+
+    let app_state = state.read().await;
+    let sticker_db = app_state.sticker_database.clone();
+    let database = app_state.database.clone();
+    let callback_url = app_state
+        .config
+        .mattermost
+        .bot_callback_url
+        .as_ref()
+        .map(|url| format!("{}/action", url.trim_end_matches('/')))
+        .unwrap_or_else(|| "http://localhost/action".to_string());
+    let mattermost_url = app_state.config.mattermost.url.clone();
+    let signer = app_state.mattermost_client.signer().clone();
+    drop(app_state);
+
+    if let Err(e) = sticker_db.set_favorite(user_id, image_url, favorite).await {
+        error!("更新最愛貼圖失敗: {}", e);
+        return Ok(warp::reply::json(&serde_json::json!({
+            "ephemeral_text": "更新最愛失敗，請稍後再試"
+        })));
+    }
+    info!(
+        "使用者 {} {}最愛貼圖: {}",
+        user_id,
+        if favorite { "加入" } else { "移除" },
+        image_url
+    );
+
+    let stickers = match load_panel_stickers(&sticker_db, &database, user_id, keyword, source).await
+    {
+        Ok(v) => v,
+        Err(e) => {
+            error!("重新取得貼圖清單失敗: {}", e);
+            return Ok(warp::reply::json(&serde_json::json!({
+                "ephemeral_text": "取得貼圖失敗，請稍後再試"
+            })));
+        }
+    };
+
+    if stickers.is_empty() {
+        return Ok(warp::reply::json(&serde_json::json!({
+            "update": {
+                "message": "你的最愛已經沒有貼圖了",
+                "props": {}
+            }
+        })));
+    }
+
+    let panel = StickerPanel {
+        callback_url: &callback_url,
+        signer: &signer,
+        user_id,
+        user_name,
+        keyword,
+        source,
+        picker_id: context_picker(action_req),
+        ephemeral: context_ephemeral(action_req),
+        page,
+    };
+    let attachment = match stickers.iter().find(|s| s.image_url == image_url) {
+        Some(sticker) => panel.preview(
+            &stickers,
+            sticker,
+            format!("{}/api/v4/users/{}/image", mattermost_url, user_id),
+            favorite,
+        ),
+        None => panel.picker(&stickers),
     };
 
     Ok(warp::reply::json(&serde_json::json!({
@@ -252,27 +545,145 @@ async fn handle_send_sticker(
 
     if sticker_image_url.is_empty() {
         error!("sticker_image_url 為空");
-        return Ok(warp::reply::json(&serde_json::json!({
-            "ephemeral_text": "找不到指定的貼圖"
-        })));
+        return Ok(warp::reply::json(
+            &ErrorCode::StickerNotFound.ephemeral("找不到指定的貼圖"),
+        ));
     }
 
     info!("發送貼圖: {} 由 {}", sticker_name, user_name);
 
     let app_state = state.read().await;
     let mattermost_url = app_state.config.mattermost.url.clone();
+    // bot 沒有上傳檔案的權限時照常以圖片連結發送
+    let send_as_file = app_state.config.stickers.send_as_file && app_state.capabilities.upload;
+    let image_cache = app_state
+        .config
+        .stickers
+        .image_cache_dir
+        .as_deref()
+        .map(crate::sticker_cache::ImageCache::new);
+    let sticker_db = app_state.sticker_database.clone();
+    let client = app_state.mattermost_client.clone();
+    // 讀取失敗時照常送出圖片
+    let text_only = match app_state
+        .database
+        .get_user_preference(user_id, super::preferences::STICKER_TEXT_ONLY_KEY)
+        .await
+    {
+        Ok(value) => value.as_deref() == Some("on"),
+        Err(e) => {
+            error!("取得純文字貼圖設定失敗: {}", e);
+            false
+        }
+    };
     drop(app_state);
 
+    // 熱門統計失敗不影響發送
+    if let Err(e) = sticker_db.record_usage(sticker_image_url, user_id).await {
+        error!("記錄貼圖使用失敗: {}", e);
+    }
+
     // 替換訊息為貼圖，並設定 override_username 和 override_icon_url
-    let sticker_message = format!("![{}]({})", sticker_name, sticker_image_url);
+    let sticker_message = if text_only {
+        super::preferences::text_only_sticker_message(sticker_name, sticker_image_url)
+    } else {
+        format!("![{}]({})", sticker_name, sticker_image_url)
+    };
+    let props = serde_json::json!({
+        "override_username": user_name,
+        "override_icon_url": format!("{}/api/v4/users/{}/image", mattermost_url, user_id)
+    });
 
-    Ok(warp::reply::json(&serde_json::json!({
-        "update": {
-            "message": sticker_message,
-            "props": {
-                "override_username": user_name,
-                "override_icon_url": format!("{}/api/v4/users/{}/image", mattermost_url, user_id)
+    let ephemeral = context_ephemeral(action_req);
+
+    // 以檔案發送時另外建立附帶檔案的貼文（既有貼文無法加上檔案），下載或上傳失敗時改用圖片連結
+    let file_id = if send_as_file && !text_only {
+        match upload_sticker(
+            &client,
+            image_cache.as_ref(),
+            &action_req.channel_id,
+            sticker_image_url,
+        )
+        .await
+        {
+            Ok(file_id) => Some(file_id),
+            Err(e) => {
+                warn!("以檔案發送貼圖失敗，改用圖片連結: {:#}", e);
+                None
             }
         }
-    })))
+    } else {
+        None
+    };
+
+    if file_id.is_none() && !ephemeral {
+        return Ok(warp::reply::json(&serde_json::json!({
+            "update": {
+                "message": sticker_message,
+                "props": props
+            }
+        })));
+    }
+
+    // 臨時訊息的選擇器只有使用者看得到，另外公開貼出貼圖，再收起選擇器
+    let result = match file_id {
+        Some(file_id) => {
+            client
+                .create_post_with_files(&action_req.channel_id, "", &[file_id], props)
+                .await
+        }
+        None => {
+            let post = Post {
+                id: None,
+                channel_id: action_req.channel_id.clone(),
+                message: sticker_message,
+                root_id: None,
+                props: Some(props),
+            };
+            client.create_post(&post).await
+        }
+    };
+    if let Err(e) = result {
+        error!("公開發送貼圖失敗: {}", e);
+        return Ok(warp::reply::json(&serde_json::json!({
+            "ephemeral_text": "發送貼圖失敗，請確認 bot 已加入此頻道"
+        })));
+    }
+
+    if ephemeral {
+        return Ok(warp::reply::json(&serde_json::json!({
+            "update": {
+                "message": format!("已發送貼圖：{}", sticker_name),
+                "props": {}
+            }
+        })));
+    }
+
+    // 公開的選擇器已被新的貼文取代，刪除它；無法刪除時改為清空訊息
+    if let Err(e) = client.delete_post(&action_req.post_id).await {
+        warn!("刪除貼圖選擇器失敗，改為清空訊息: {}", e);
+        return Ok(warp::reply::json(&serde_json::json!({
+            "update": {
+                "message": "",
+                "props": {}
+            }
+        })));
+    }
+    Ok(warp::reply::json(&serde_json::json!({})))
+}
+
+/// 下載貼圖圖片（有設定快取時優先使用快取）並上傳到頻道，回傳 file ID
+async fn upload_sticker(
+    client: &crate::mattermost::MattermostClient,
+    image_cache: Option<&crate::sticker_cache::ImageCache>,
+    channel_id: &str,
+    image_url: &str,
+) -> anyhow::Result<String> {
+    let image = match image_cache {
+        Some(cache) => cache.fetch(image_url).await?,
+        None => crate::sticker::download_sticker_image(image_url).await?,
+    };
+    client
+        .upload_file(channel_id, &image.filename, image.data)
+        .await
 }
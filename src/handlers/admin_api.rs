@@ -0,0 +1,634 @@
+//! 管理用 REST API（以 scoped API token 驗證）
+//!
+//! Token 只接受 `Authorization: Bearer <token>` header，不讀取 cookie 或 query string，
+//! 瀏覽器不會在跨站請求中自動帶上憑證，因此不受 CSRF 影響。
+//! Token 由管理員透過 DM 指令 `token create` 建立，資料庫只保存 hash。
+//!
+//! `/api/v1/admin/query/...` 是給報表與 BI 工具使用的唯讀查詢端點，以 query string 篩選團購、
+//! 登記、貼圖發送紀錄與操作日誌，並以 `limit`、`offset` 分頁。查詢使用資料庫的唯讀連接池。
+
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{error, info, warn};
+use warp::Filter;
+use warp::http::StatusCode;
+use warp::reply::{Json, WithStatus};
+
+use crate::AppState;
+use crate::database::{
+    ApiToken, ApiTokenScope, GroupBuy, GroupBuyQuery, GroupBuyStatus, LogFilter, OrderQuery,
+    StickerUsageQuery,
+};
+use crate::error_code::ErrorCode;
+
+/// 缺少或無效的 API token
+#[derive(Debug)]
+pub struct InvalidApiToken;
+impl warp::reject::Reject for InvalidApiToken {}
+
+/// API token 權限不足
+#[derive(Debug)]
+pub struct InsufficientScope;
+impl warp::reject::Reject for InsufficientScope {}
+
+/// 通過驗證的 API 請求
+#[derive(Debug, Clone)]
+pub struct ApiRequest {
+    pub token: ApiToken,
+    method: String,
+    path: String,
+}
+
+fn with_state(
+    state: Arc<RwLock<AppState>>,
+) -> impl Filter<Extract = (Arc<RwLock<AppState>>,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || state.clone())
+}
+
+/// 驗證 API token 並檢查權限範圍
+fn with_api_token(
+    state: Arc<RwLock<AppState>>,
+    required: ApiTokenScope,
+) -> impl Filter<Extract = (ApiRequest,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and(warp::method())
+        .and(warp::path::full())
+        .and(with_state(state))
+        .and_then(
+            move |authorization: Option<String>,
+                  method: warp::http::Method,
+                  path: warp::path::FullPath,
+                  state: Arc<RwLock<AppState>>| async move {
+                let Some(secret) = authorization
+                    .as_deref()
+                    .and_then(|v| v.strip_prefix("Bearer "))
+                    .map(str::trim)
+                else {
+                    return Err(warp::reject::custom(InvalidApiToken));
+                };
+
+                let app_state = state.read().await;
+                let token = match app_state.database.authenticate_api_token(secret).await {
+                    Ok(Some(token)) => token,
+                    Ok(None) => {
+                        warn!("無效的 API token: {} {}", method, path.as_str());
+                        return Err(warp::reject::custom(InvalidApiToken));
+                    }
+                    Err(e) => {
+                        error!("驗證 API token 失敗: {}", e);
+                        return Err(warp::reject::custom(InvalidApiToken));
+                    }
+                };
+
+                let request = ApiRequest {
+                    token,
+                    method: method.to_string(),
+                    path: path.as_str().to_string(),
+                };
+
+                if !request.token.scope.allows(required) {
+                    warn!(
+                        "API token {} ({}) 權限不足，需要 {}",
+                        request.token.id, request.token.scope, required
+                    );
+                    log_request(&app_state, &request, StatusCode::FORBIDDEN).await;
+                    return Err(warp::reject::custom(InsufficientScope));
+                }
+
+                Ok(request)
+            },
+        )
+}
+
+async fn log_request(app_state: &AppState, request: &ApiRequest, status: StatusCode) {
+    if let Err(e) = app_state
+        .database
+        .log_api_token_request(
+            &request.token.id,
+            &request.method,
+            &request.path,
+            status.as_u16(),
+        )
+        .await
+    {
+        error!("記錄 API token 請求失敗: {}", e);
+    }
+}
+
+/// 記錄請求後回傳 JSON
+async fn respond(
+    app_state: &AppState,
+    request: &ApiRequest,
+    status: StatusCode,
+    body: serde_json::Value,
+) -> Result<WithStatus<Json>, warp::Rejection> {
+    log_request(app_state, request, status).await;
+    Ok(warp::reply::with_status(warp::reply::json(&body), status))
+}
+
+/// 管理 API 路由（/api/v1/admin/...）
+pub fn admin_api_routes(
+    state: Arc<RwLock<AppState>>,
+) -> impl Filter<Extract = (warp::reply::Response,), Error = warp::Rejection> + Clone {
+    let admin = warp::path("api")
+        .and(warp::path("v1"))
+        .and(warp::path("admin"))
+        .and(super::require_feature(
+            state.clone(),
+            crate::config::Feature::Apps,
+        ));
+
+    let sticker_stats = warp::get()
+        .and(admin.clone())
+        .and(warp::path("stickers"))
+        .and(warp::path("stats"))
+        .and(warp::path::end())
+        .and(with_api_token(state.clone(), ApiTokenScope::ReadOnly))
+        .and(with_state(state.clone()))
+        .and_then(|request, state: Arc<RwLock<AppState>>| {
+            crate::panic_guard::guard(
+                "/api/v1/admin/stickers/stats",
+                state.clone(),
+                handle_sticker_stats(request, state),
+            )
+        });
+
+    let sticker_reload = warp::post()
+        .and(admin.clone())
+        .and(warp::path("stickers"))
+        .and(warp::path("reload"))
+        .and(warp::path::end())
+        .and(with_api_token(state.clone(), ApiTokenScope::StickerAdmin))
+        .and(with_state(state.clone()))
+        .and_then(|request, state: Arc<RwLock<AppState>>| {
+            crate::panic_guard::guard(
+                "/api/v1/admin/stickers/reload",
+                state.clone(),
+                handle_sticker_reload(request, state),
+            )
+        });
+
+    let get_group_buy = warp::get()
+        .and(admin.clone())
+        .and(warp::path("group_buys"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(with_api_token(state.clone(), ApiTokenScope::ReadOnly))
+        .and(with_state(state.clone()))
+        .and_then(|id, request, state: Arc<RwLock<AppState>>| {
+            crate::panic_guard::guard(
+                "/api/v1/admin/group_buys/{id}",
+                state.clone(),
+                handle_get_group_buy(id, request, state),
+            )
+        });
+
+    let group_buy_logs = warp::get()
+        .and(admin.clone())
+        .and(warp::path("group_buys"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("logs"))
+        .and(warp::path::end())
+        .and(warp::query::<LogFilter>())
+        .and(with_api_token(state.clone(), ApiTokenScope::ReadOnly))
+        .and(with_state(state.clone()))
+        .and_then(|id, filter, request, state: Arc<RwLock<AppState>>| {
+            crate::panic_guard::guard(
+                "/api/v1/admin/group_buys/{id}/logs",
+                state.clone(),
+                handle_group_buy_logs(id, filter, request, state),
+            )
+        });
+
+    let query = warp::get().and(admin.clone()).and(warp::path("query"));
+
+    let query_group_buys = query
+        .clone()
+        .and(warp::path("group_buys"))
+        .and(warp::path::end())
+        .and(warp::query::<GroupBuyQuery>())
+        .and(with_api_token(state.clone(), ApiTokenScope::ReadOnly))
+        .and(with_state(state.clone()))
+        .and_then(|filter, request, state: Arc<RwLock<AppState>>| {
+            crate::panic_guard::guard(
+                "/api/v1/admin/query/group_buys",
+                state.clone(),
+                handle_query_group_buys(filter, request, state),
+            )
+        });
+
+    let query_orders = query
+        .clone()
+        .and(warp::path("orders"))
+        .and(warp::path::end())
+        .and(warp::query::<OrderQuery>())
+        .and(with_api_token(state.clone(), ApiTokenScope::ReadOnly))
+        .and(with_state(state.clone()))
+        .and_then(|filter, request, state: Arc<RwLock<AppState>>| {
+            crate::panic_guard::guard(
+                "/api/v1/admin/query/orders",
+                state.clone(),
+                handle_query_orders(filter, request, state),
+            )
+        });
+
+    let query_sticker_usage = query
+        .clone()
+        .and(warp::path("sticker_usage"))
+        .and(warp::path::end())
+        .and(warp::query::<StickerUsageQuery>())
+        .and(with_api_token(state.clone(), ApiTokenScope::ReadOnly))
+        .and(with_state(state.clone()))
+        .and_then(|filter, request, state: Arc<RwLock<AppState>>| {
+            crate::panic_guard::guard(
+                "/api/v1/admin/query/sticker_usage",
+                state.clone(),
+                handle_query_sticker_usage(filter, request, state),
+            )
+        });
+
+    let query_logs = query
+        .and(warp::path("logs"))
+        .and(warp::path::end())
+        .and(warp::query::<LogFilter>())
+        .and(with_api_token(state.clone(), ApiTokenScope::ReadOnly))
+        .and(with_state(state.clone()))
+        .and_then(|filter, request, state: Arc<RwLock<AppState>>| {
+            crate::panic_guard::guard(
+                "/api/v1/admin/query/logs",
+                state.clone(),
+                handle_query_logs(filter, request, state),
+            )
+        });
+
+    let close_group_buy = warp::post()
+        .and(admin)
+        .and(warp::path("group_buys"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("close"))
+        .and(warp::path::end())
+        .and(with_api_token(state.clone(), ApiTokenScope::GbAdmin))
+        .and(with_state(state))
+        .and_then(|id, request, state: Arc<RwLock<AppState>>| {
+            crate::panic_guard::guard(
+                "/api/v1/admin/group_buys/{id}/close",
+                state.clone(),
+                handle_close_group_buy(id, request, state),
+            )
+        });
+
+    sticker_stats
+        .or(sticker_reload)
+        .unify()
+        .or(get_group_buy)
+        .unify()
+        .or(group_buy_logs)
+        .unify()
+        .or(close_group_buy)
+        .unify()
+        .or(query_group_buys)
+        .unify()
+        .or(query_orders)
+        .unify()
+        .or(query_sticker_usage)
+        .unify()
+        .or(query_logs)
+        .unify()
+}
+
+/// GET /api/v1/admin/stickers/stats（read-only）
+async fn handle_sticker_stats(
+    request: ApiRequest,
+    state: Arc<RwLock<AppState>>,
+) -> Result<WithStatus<Json>, warp::Rejection> {
+    let app_state = state.read().await;
+
+    let total = app_state.sticker_database.get_total_count().await;
+    let categories = app_state.sticker_database.get_category_stats().await;
+
+    match (total, categories) {
+        (Ok(total), Ok(categories)) => {
+            respond(
+                &app_state,
+                &request,
+                StatusCode::OK,
+                serde_json::json!({ "total": total, "categories": categories }),
+            )
+            .await
+        }
+        (Err(e), _) | (_, Err(e)) => {
+            error!("取得貼圖統計失敗: {}", e);
+            respond(
+                &app_state,
+                &request,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::DatabaseError.json("取得貼圖統計失敗"),
+            )
+            .await
+        }
+    }
+}
+
+/// POST /api/v1/admin/stickers/reload（sticker-admin）
+async fn handle_sticker_reload(
+    request: ApiRequest,
+    state: Arc<RwLock<AppState>>,
+) -> Result<WithStatus<Json>, warp::Rejection> {
+    info!(
+        "API token {} ({}) 重新載入配置",
+        request.token.id, request.token.name
+    );
+    let result = crate::websocket::handle_reload_config(state.clone()).await;
+
+    let app_state = state.read().await;
+    match result {
+        Ok(message) => {
+            respond(
+                &app_state,
+                &request,
+                StatusCode::OK,
+                serde_json::json!({ "message": message }),
+            )
+            .await
+        }
+        Err(e) => {
+            error!("重新載入配置失敗: {}", e);
+            respond(
+                &app_state,
+                &request,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::of(&e).json(format!("重新載入配置失敗: {}", e)),
+            )
+            .await
+        }
+    }
+}
+
+/// GET /api/v1/admin/group_buys/{id}（read-only）
+async fn handle_get_group_buy(
+    group_buy_id: String,
+    request: ApiRequest,
+    state: Arc<RwLock<AppState>>,
+) -> Result<WithStatus<Json>, warp::Rejection> {
+    let app_state = state.read().await;
+
+    let group_buy = match app_state.database.get_group_buy(&group_buy_id).await {
+        Ok(Some(gb)) => gb,
+        Ok(None) => {
+            return respond(
+                &app_state,
+                &request,
+                StatusCode::NOT_FOUND,
+                ErrorCode::GbNotFound.json("找不到該團購"),
+            )
+            .await;
+        }
+        Err(e) => {
+            error!("取得團購資料失敗: {}", e);
+            return respond(
+                &app_state,
+                &request,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::DatabaseError.json("取得團購資料失敗"),
+            )
+            .await;
+        }
+    };
+
+    let orders = app_state
+        .database
+        .get_orders_by_group_buy(&group_buy_id)
+        .await
+        .unwrap_or_default();
+
+    respond(
+        &app_state,
+        &request,
+        StatusCode::OK,
+        serde_json::json!({ "group_buy": group_buy, "orders": orders }),
+    )
+    .await
+}
+
+/// GET /api/v1/admin/group_buys/{id}/logs?action=&user_id=&version=&since=&limit=（read-only）
+async fn handle_group_buy_logs(
+    group_buy_id: String,
+    mut filter: LogFilter,
+    request: ApiRequest,
+    state: Arc<RwLock<AppState>>,
+) -> Result<WithStatus<Json>, warp::Rejection> {
+    let app_state = state.read().await;
+
+    filter.group_buy_id = Some(group_buy_id);
+    filter.limit = Some(filter.limit.unwrap_or(100).clamp(1, 1000));
+
+    match app_state.database.query_logs(&filter).await {
+        Ok(logs) => {
+            respond(
+                &app_state,
+                &request,
+                StatusCode::OK,
+                serde_json::json!({ "logs": logs }),
+            )
+            .await
+        }
+        Err(e) => {
+            error!("查詢團購日誌失敗: {}", e);
+            respond(
+                &app_state,
+                &request,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::DatabaseError.json("查詢團購日誌失敗"),
+            )
+            .await
+        }
+    }
+}
+
+/// POST /api/v1/admin/group_buys/{id}/close（gb-admin）
+async fn handle_close_group_buy(
+    group_buy_id: String,
+    request: ApiRequest,
+    state: Arc<RwLock<AppState>>,
+) -> Result<WithStatus<Json>, warp::Rejection> {
+    let app_state = state.read().await;
+
+    let group_buy = match app_state.database.get_group_buy(&group_buy_id).await {
+        Ok(Some(gb)) => gb,
+        Ok(None) => {
+            return respond(
+                &app_state,
+                &request,
+                StatusCode::NOT_FOUND,
+                ErrorCode::GbNotFound.json("找不到該團購"),
+            )
+            .await;
+        }
+        Err(e) => {
+            error!("取得團購資料失敗: {}", e);
+            return respond(
+                &app_state,
+                &request,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::DatabaseError.json("取得團購資料失敗"),
+            )
+            .await;
+        }
+    };
+
+    if !group_buy.status.can_transition_to(&GroupBuyStatus::Closed) {
+        return respond(
+            &app_state,
+            &request,
+            StatusCode::CONFLICT,
+            ErrorCode::GbInvalidStatus.json(format!(
+                "團購目前為「{}」，無法截止",
+                group_buy.status.label()
+            )),
+        )
+        .await;
+    }
+
+    let actor = format!("api-token:{}", request.token.id);
+    if let Err(e) = app_state
+        .database
+        .update_status(
+            &group_buy_id,
+            GroupBuyStatus::Closed,
+            group_buy.version,
+            &actor,
+            &request.token.name,
+        )
+        .await
+    {
+        return respond(
+            &app_state,
+            &request,
+            StatusCode::CONFLICT,
+            ErrorCode::of(&e).json(&e),
+        )
+        .await;
+    }
+
+    // 同步更新團購貼文
+    let closed = GroupBuy {
+        status: GroupBuyStatus::Closed,
+        version: group_buy.version + 1,
+        ..group_buy
+    };
+    super::group_buy::sync_group_buy_post(&app_state, &closed).await;
+
+    info!(
+        "API token {} ({}) 截止了團購 {}",
+        request.token.id, request.token.name, group_buy_id
+    );
+
+    respond(
+        &app_state,
+        &request,
+        StatusCode::OK,
+        serde_json::json!({ "id": group_buy_id, "status": GroupBuyStatus::Closed.to_string() }),
+    )
+    .await
+}
+
+/// 查詢端點的分頁參數：`limit` 預設 100、介於 1 到 1000，`offset` 不小於 0
+fn page_bounds(limit: Option<i64>, offset: Option<i64>) -> (i64, i64) {
+    (
+        limit.unwrap_or(100).clamp(1, 1000),
+        offset.unwrap_or(0).max(0),
+    )
+}
+
+/// 回傳一頁查詢結果。結果剛好滿一頁時附上下一頁的 `next_offset`，否則為 null
+async fn respond_page<T: serde::Serialize>(
+    app_state: &AppState,
+    request: &ApiRequest,
+    result: anyhow::Result<Vec<T>>,
+    (limit, offset): (i64, i64),
+    what: &str,
+) -> Result<WithStatus<Json>, warp::Rejection> {
+    match result {
+        Ok(items) => {
+            let next_offset = (items.len() as i64 == limit).then_some(offset + limit);
+            respond(
+                app_state,
+                request,
+                StatusCode::OK,
+                serde_json::json!({
+                    "items": items,
+                    "limit": limit,
+                    "offset": offset,
+                    "next_offset": next_offset,
+                }),
+            )
+            .await
+        }
+        Err(e) => {
+            error!("查詢{}失敗: {}", what, e);
+            respond(
+                app_state,
+                request,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::DatabaseError.json(format!("查詢{}失敗", what)),
+            )
+            .await
+        }
+    }
+}
+
+/// GET /api/v1/admin/query/group_buys?status=&channel_id=&creator_id=&merchant_name=&since=&until=&limit=&offset=（read-only）
+async fn handle_query_group_buys(
+    mut filter: GroupBuyQuery,
+    request: ApiRequest,
+    state: Arc<RwLock<AppState>>,
+) -> Result<WithStatus<Json>, warp::Rejection> {
+    let app_state = state.read().await;
+
+    let page = page_bounds(filter.limit, filter.offset);
+    (filter.limit, filter.offset) = (Some(page.0), Some(page.1));
+    let result = app_state.database.query_group_buys(&filter).await;
+    respond_page(&app_state, &request, result, page, "團購").await
+}
+
+/// GET /api/v1/admin/query/orders?group_buy_id=&buyer_id=&registrar_id=&item_name=&since=&until=&limit=&offset=（read-only）
+async fn handle_query_orders(
+    mut filter: OrderQuery,
+    request: ApiRequest,
+    state: Arc<RwLock<AppState>>,
+) -> Result<WithStatus<Json>, warp::Rejection> {
+    let app_state = state.read().await;
+
+    let page = page_bounds(filter.limit, filter.offset);
+    (filter.limit, filter.offset) = (Some(page.0), Some(page.1));
+    let result = app_state.database.query_orders(&filter).await;
+    respond_page(&app_state, &request, result, page, "登記").await
+}
+
+/// GET /api/v1/admin/query/sticker_usage?user_id=&category=&image_url=&since=&until=&limit=&offset=（read-only）
+async fn handle_query_sticker_usage(
+    mut filter: StickerUsageQuery,
+    request: ApiRequest,
+    state: Arc<RwLock<AppState>>,
+) -> Result<WithStatus<Json>, warp::Rejection> {
+    let app_state = state.read().await;
+
+    let page = page_bounds(filter.limit, filter.offset);
+    (filter.limit, filter.offset) = (Some(page.0), Some(page.1));
+    let result = app_state.database.query_sticker_usage(&filter).await;
+    respond_page(&app_state, &request, result, page, "貼圖發送紀錄").await
+}
+
+/// GET /api/v1/admin/query/logs?group_buy_id=&action=&user_id=&version=&since=&until=&limit=&offset=（read-only）
+async fn handle_query_logs(
+    mut filter: LogFilter,
+    request: ApiRequest,
+    state: Arc<RwLock<AppState>>,
+) -> Result<WithStatus<Json>, warp::Rejection> {
+    let app_state = state.read().await;
+
+    let page = page_bounds(filter.limit, filter.offset);
+    (filter.limit, filter.offset) = (Some(page.0), Some(page.1));
+    let result = app_state.database.query_logs(&filter).await;
+    respond_page(&app_state, &request, result, page, "團購日誌").await
+}
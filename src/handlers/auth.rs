@@ -1,8 +1,11 @@
 //! 認證相關功能
 
+use ipnet::IpNet;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tracing::{error, info};
+use tracing::{error, info, warn};
+use warp::Filter;
 
 use crate::AppState;
 
@@ -11,6 +14,88 @@ use crate::AppState;
 pub struct UnauthorizedError;
 impl warp::reject::Reject for UnauthorizedError {}
 
+/// 自訂錯誤類型：來源 IP 不在 callback 允許清單中
+#[derive(Debug)]
+pub struct ForbiddenSource;
+impl warp::reject::Reject for ForbiddenSource {}
+
+/// 限制只有允許清單內的來源可以呼叫 callback 端點，清單為空時不限制
+pub fn callback_allowlist(
+    networks: Arc<Vec<IpNet>>,
+) -> impl warp::Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::addr::remote()
+        .and_then(move |remote: Option<SocketAddr>| {
+            let networks = networks.clone();
+            async move {
+                if is_source_allowed(&networks, remote.map(|addr| addr.ip())) {
+                    Ok(())
+                } else {
+                    warn!("拒絕來自允許清單外的 callback 請求: {:?}", remote);
+                    Err(warp::reject::custom(ForbiddenSource))
+                }
+            }
+        })
+        .untuple_one()
+}
+
+/// 未設定 `bot_callback_url` 時，從允許清單內來源的請求 header 偵測 bot 的對外位址並寫回設定。
+/// 只接受允許清單內的來源，避免偽造的 header 改變按鈕的回呼位址；不會拒絕任何請求。
+pub fn detect_callback_url(
+    state: Arc<RwLock<AppState>>,
+    networks: Arc<Vec<IpNet>>,
+) -> impl warp::Filter<Extract = (), Error = std::convert::Infallible> + Clone {
+    warp::addr::remote()
+        .and(warp::header::headers_cloned())
+        .then(
+            move |remote: Option<SocketAddr>, headers: warp::http::HeaderMap| {
+                let state = state.clone();
+                let networks = networks.clone();
+                async move {
+                    let header = {
+                        let app_state = state.read().await;
+                        let mattermost = &app_state.config.mattermost;
+                        match &mattermost.callback_host_header {
+                            Some(header) if mattermost.bot_callback_url.is_none() => header.clone(),
+                            _ => return,
+                        }
+                    };
+                    if networks.is_empty() || !is_source_allowed(&networks, remote.map(|a| a.ip()))
+                    {
+                        return;
+                    }
+                    let Some(url) = crate::startup::callback_url_from_headers(&headers, &header)
+                    else {
+                        return;
+                    };
+
+                    let mut app_state = state.write().await;
+                    // 取得寫入鎖前可能已被其他請求設定
+                    if app_state.config.mattermost.bot_callback_url.is_none() {
+                        info!("從 {} header 偵測到 callback URL: {}", header, url);
+                        app_state.config.mattermost.bot_callback_url = Some(url.clone());
+                        crate::startup::spawn_callback_verification(url);
+                    }
+                }
+            },
+        )
+        .untuple_one()
+}
+
+fn is_source_allowed(networks: &[IpNet], ip: Option<IpAddr>) -> bool {
+    if networks.is_empty() {
+        return true;
+    }
+    let Some(ip) = ip else {
+        return false;
+    };
+    // dual-stack socket 收到的 IPv4 連線會以 ::ffff:a.b.c.d 表示
+    let ip = match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        IpAddr::V4(_) => ip,
+    };
+    networks.iter().any(|network| network.contains(&ip))
+}
+
 /// 驗證 slash command token
 pub async fn verify_slash_command_token(
     form: &std::collections::HashMap<String, String>,
@@ -54,3 +139,38 @@ pub async fn verify_slash_command_token(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn networks(entries: &[&str]) -> Vec<IpNet> {
+        entries.iter().map(|e| e.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_is_source_allowed() {
+        let allowlist = networks(&["10.0.0.0/8", "fd00::/64"]);
+
+        assert!(is_source_allowed(
+            &allowlist,
+            Some("10.1.2.3".parse().unwrap())
+        ));
+        assert!(is_source_allowed(
+            &allowlist,
+            Some("::ffff:10.1.2.3".parse().unwrap())
+        ));
+        assert!(is_source_allowed(
+            &allowlist,
+            Some("fd00::1".parse().unwrap())
+        ));
+        assert!(!is_source_allowed(
+            &allowlist,
+            Some("192.168.0.1".parse().unwrap())
+        ));
+        assert!(!is_source_allowed(&allowlist, None));
+
+        // 未設定允許清單時不限制
+        assert!(is_source_allowed(&[], None));
+    }
+}
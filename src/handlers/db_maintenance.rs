@@ -0,0 +1,87 @@
+//! 定期資料庫維護
+//!
+//! 設定了 `database.maintenance_interval_hours` 時，排程工作定期執行 `PRAGMA integrity_check`，
+//! 檢查通過後再執行 VACUUM；發現問題時不 VACUUM，以 ERROR 記錄問題（會送到錯誤回報）。
+//! 與私訊的 `db check`／`db vacuum` 共用同一把維護鎖，手動執行中時這一輪略過。
+
+use crate::database::Database;
+use crate::scheduler::Job;
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+
+/// 記錄在日誌中的問題數上限
+const MAX_LOGGED_PROBLEMS: usize = 20;
+
+/// 執行一次完整性檢查與 VACUUM，回傳是否完成
+pub async fn run_maintenance(database: &Database) -> bool {
+    let started = Instant::now();
+    match database.integrity_check().await {
+        Ok(problems) if problems == ["ok"] => {}
+        Ok(problems) => {
+            error!(
+                "定期資料庫完整性檢查發現 {} 個問題，略過 VACUUM: {}",
+                problems.len(),
+                problems
+                    .iter()
+                    .take(MAX_LOGGED_PROBLEMS)
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            );
+            return false;
+        }
+        Err(e) => {
+            error!("定期資料庫完整性檢查失敗: {}", e);
+            return false;
+        }
+    }
+
+    let size_before = database.database_size().await.ok();
+    if let Err(e) = database.vacuum().await {
+        error!("定期 VACUUM 失敗: {}", e);
+        return false;
+    }
+    let size_after = database.database_size().await.ok();
+    info!(
+        "定期資料庫維護完成，耗時 {:?}，大小 {:?} → {:?} bytes",
+        started.elapsed(),
+        size_before,
+        size_after
+    );
+    true
+}
+
+/// 定期資料庫維護的排程工作。啟動後等第一個間隔過後才執行，避免每次重新啟動都 VACUUM
+pub fn db_maintenance_job() -> Job {
+    Job::new(
+        "資料庫維護",
+        |config| {
+            (config.database.maintenance_interval_hours > 0).then(|| {
+                Duration::from_secs(
+                    config
+                        .database
+                        .maintenance_interval_hours
+                        .saturating_mul(60 * 60),
+                )
+            })
+        },
+        |state| async move {
+            // 維護可能要很久，不持有 AppState 的鎖
+            let database = state.read().await.database.clone();
+            run_maintenance(&database).await;
+        },
+    )
+    .delayed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::utils::setup_db;
+
+    #[tokio::test]
+    async fn test_run_maintenance() {
+        let db = setup_db().await;
+        assert!(run_maintenance(&db).await);
+    }
+}
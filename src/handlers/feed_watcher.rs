@@ -0,0 +1,402 @@
+//! RSS／Atom 訂閱
+//!
+//! 排程工作每分鐘檢查一次配置中的 `feeds`，到了各自的 `interval_minutes` 就以條件式請求讀取，
+//! 把還沒貼過的項目依 `template`（與 webhook 轉發相同的 `{{field}}` 語法）貼到指定頻道。
+//! 已貼過的項目記錄在資料庫，重新啟動或換 leader 後不會重複；第一次成功讀取的 feed 只記錄目前的項目，
+//! 不貼出舊文。每輪每個 feed 最多貼 [`MAX_POSTS_PER_CHECK`] 則最新的項目（依發布時間，沒有時間時依
+//! feed 中的順序），較舊的項目視為已讀略過。
+//! 讀取 feed 與發文時不持有 AppState 的鎖；請求有逾時，失敗時記錄錯誤，下一輪再試。
+
+use crate::config::FeedConfig;
+use crate::database::{Database, FeedStateRecord};
+use crate::feed::{FeedItem, parse_feed};
+use crate::locale::{LocaleFormat, format_datetime};
+use crate::mattermost::MattermostClient;
+use crate::scheduler::Job;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+use std::time::Duration;
+use tracing::{debug, error, info};
+
+/// 檢查間隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 每個 feed 每輪最多貼出的項目數，避免 feed 久未讀取或大量更新時洗版
+const MAX_POSTS_PER_CHECK: usize = 5;
+
+/// 連線逾時
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 整個請求（包含讀取內容）的逾時，避免一個沒有回應的 feed 卡住其他 feed
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 距離上次讀取是否已超過 feed 的讀取間隔，從未讀取過的 feed 立即讀取
+fn feed_due(interval_minutes: u64, checked_at: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+    checked_at.is_none_or(|checked_at| {
+        now - checked_at >= chrono::Duration::minutes(interval_minutes as i64)
+    })
+}
+
+/// 代入範本的欄位
+fn item_payload(
+    feed: &FeedConfig,
+    feed_title: &str,
+    item: &FeedItem,
+    locale: &LocaleFormat,
+) -> serde_json::Value {
+    serde_json::json!({
+        "feed": feed.name,
+        "feed_title": if feed_title.is_empty() { feed.name.as_str() } else { feed_title },
+        "id": item.id,
+        "title": item.title,
+        "link": item.link,
+        "summary": item.summary,
+        "published": item
+            .published
+            .map(|t| format_datetime(t.with_timezone(&chrono::Local), locale)),
+    })
+}
+
+/// 還沒貼過的項目由新到舊排列：有發布時間的依時間，沒有的排在後面並維持 feed 中的順序
+fn newest_first<'a>(items: &'a [FeedItem], unseen: &[String]) -> Vec<&'a FeedItem> {
+    // 同一個 ID 出現多次時只取第一個
+    let mut unseen: HashSet<&str> = unseen.iter().map(String::as_str).collect();
+    let mut items: Vec<&FeedItem> = items
+        .iter()
+        .filter(|item| unseen.remove(item.id.as_str()))
+        .collect();
+    items.sort_by(|a, b| b.published.cmp(&a.published));
+    items
+}
+
+/// 讀取一個 feed 並貼出新的項目，回傳貼出的數量
+async fn check_feed(
+    database: &Database,
+    http: &reqwest::Client,
+    client: &MattermostClient,
+    feed: &FeedConfig,
+    locale: &LocaleFormat,
+    previous: Option<FeedStateRecord>,
+) -> Result<usize> {
+    let now = Utc::now();
+    let mut record = previous.unwrap_or_else(|| FeedStateRecord {
+        name: feed.name.clone(),
+        etag: None,
+        last_modified: None,
+        error: None,
+        checked_at: now,
+        succeeded_at: None,
+    });
+    let seeded = record.succeeded_at.is_some();
+    record.checked_at = now;
+
+    let result = async {
+        let mut request = http.get(&feed.url);
+        for (key, value) in &feed.headers {
+            request = request.header(key, value);
+        }
+        // 還沒成功讀取過時不發出條件式請求，以取得目前的項目
+        if seeded {
+            if let Some(etag) = &record.etag {
+                request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &record.last_modified {
+                request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("無法讀取 feed: {}", feed.url))?;
+        if seeded && response.status() == reqwest::StatusCode::NOT_MODIFIED {
+            return anyhow::Ok(0);
+        }
+        let response = response
+            .error_for_status()
+            .with_context(|| format!("無法讀取 feed: {}", feed.url))?;
+        let header = |name: reqwest::header::HeaderName| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        let etag = header(reqwest::header::ETAG);
+        let last_modified = header(reqwest::header::LAST_MODIFIED);
+        let content = response
+            .text()
+            .await
+            .with_context(|| format!("無法讀取 HTTP 回應內容: {}", feed.url))?;
+        let parsed = parse_feed(&content)?;
+
+        let ids: Vec<String> = parsed.items.iter().map(|item| item.id.clone()).collect();
+        let unseen = database.unseen_feed_items(&feed.name, &ids).await?;
+        if !seeded {
+            database.mark_feed_items_seen(&feed.name, &unseen).await?;
+            info!(
+                "第一次讀取 feed {}，略過目前的 {} 則項目",
+                feed.name,
+                unseen.len()
+            );
+            record.etag = etag;
+            record.last_modified = last_modified;
+            return Ok(0);
+        }
+
+        // 貼出最新的幾則，依舊到新的順序發文
+        let items = newest_first(&parsed.items, &unseen);
+        let (to_post, skipped) = items.split_at(items.len().min(MAX_POSTS_PER_CHECK));
+        if !skipped.is_empty() {
+            info!("feed {} 有 {} 則較舊的項目略過", feed.name, skipped.len());
+            let skipped: Vec<String> = skipped.iter().map(|item| item.id.clone()).collect();
+            database.mark_feed_items_seen(&feed.name, &skipped).await?;
+        }
+        let mut posted = 0;
+        for item in to_post.iter().rev() {
+            let message = super::webhook::render_template(
+                &feed.template,
+                &item_payload(feed, &parsed.title, item, locale),
+            );
+            if !message.trim().is_empty() {
+                client
+                    .create_post_simple(&feed.channel_id, &message, None)
+                    .await
+                    .with_context(|| format!("feed {} 發文失敗", feed.name))?;
+                posted += 1;
+            }
+            database
+                .mark_feed_items_seen(&feed.name, std::slice::from_ref(&item.id))
+                .await?;
+        }
+
+        // 全部貼出後才更新驗證資訊，發文失敗時下一輪重新取得完整內容
+        record.etag = etag;
+        record.last_modified = last_modified;
+        Ok(posted)
+    }
+    .await;
+
+    match &result {
+        Ok(_) => {
+            record.error = None;
+            record.succeeded_at = Some(now);
+        }
+        Err(e) => record.error = Some(format!("{:#}", e)),
+    }
+    if let Err(e) = database.save_feed_state(&record).await {
+        tracing::warn!("記錄 feed {} 的狀態失敗: {}", feed.name, e);
+    }
+    result
+}
+
+/// 讀取 feed 用的 HTTP 客戶端，所有 feed 共用
+fn http_client() -> Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .context("建立讀取 feed 用的 HTTP 客戶端失敗")
+}
+
+/// 定期讀取 feed 的排程工作。每輪重新讀取設定
+pub fn feed_watcher_job() -> Result<Job> {
+    let http = http_client()?;
+    Ok(Job::new(
+        "RSS 訂閱",
+        |config| (config.features.feeds && !config.feeds.is_empty()).then_some(CHECK_INTERVAL),
+        move |state| {
+            let http = http.clone();
+            async move {
+                let (feeds, locale, database, client) = {
+                    let state_guard = state.read().await;
+                    (
+                        state_guard.config.feeds.clone(),
+                        state_guard.config.group_buy.locale_format(),
+                        state_guard.database.clone(),
+                        state_guard.mattermost_client.clone(),
+                    )
+                };
+
+                for feed in &feeds {
+                    let previous = match database.get_feed_state(&feed.name).await {
+                        Ok(previous) => previous,
+                        Err(e) => {
+                            error!("讀取 feed {} 的狀態失敗: {}", feed.name, e);
+                            continue;
+                        }
+                    };
+                    if !feed_due(
+                        feed.interval_minutes,
+                        previous.as_ref().map(|p| p.checked_at),
+                        Utc::now(),
+                    ) {
+                        continue;
+                    }
+                    match check_feed(&database, &http, &client, feed, &locale, previous).await {
+                        Ok(0) => debug!("feed {} 沒有新項目", feed.name),
+                        Ok(count) => info!("feed {} 已貼出 {} 則新項目", feed.name, count),
+                        Err(e) => error!("讀取 feed {} 失敗: {:#}", feed.name, e),
+                    }
+                }
+            }
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::utils::setup_db;
+
+    fn rss(items: &[&str]) -> String {
+        let items: String = items
+            .iter()
+            .map(|id| {
+                format!(
+                    "<item><title>Post {id}</title><link>https://example.com/{id}</link><guid>{id}</guid></item>"
+                )
+            })
+            .collect();
+        format!("<rss version=\"2.0\"><channel><title>Blog</title>{items}</channel></rss>")
+    }
+
+    #[test]
+    fn test_feed_due() {
+        let now = Utc::now();
+        assert!(feed_due(30, None, now));
+        assert!(!feed_due(
+            30,
+            Some(now - chrono::Duration::minutes(29)),
+            now
+        ));
+        assert!(feed_due(30, Some(now - chrono::Duration::minutes(30)), now));
+    }
+
+    #[test]
+    fn test_newest_first_sorts_by_date() {
+        let item = |id: &str, published: Option<&str>| FeedItem {
+            id: id.to_string(),
+            title: String::new(),
+            link: String::new(),
+            summary: String::new(),
+            published: published.map(|t| t.parse().unwrap()),
+        };
+        // 由舊到新排列的 feed，沒有發布時間的項目排在最後
+        let items = vec![
+            item("1", Some("2026-10-01T00:00:00Z")),
+            item("2", Some("2026-10-02T00:00:00Z")),
+            item("3", Some("2026-10-03T00:00:00Z")),
+            item("x", None),
+        ];
+        let unseen = vec!["x".to_string(), "1".to_string(), "3".to_string()];
+        let ids: Vec<&str> = newest_first(&items, &unseen)
+            .iter()
+            .map(|item| item.id.as_str())
+            .collect();
+        assert_eq!(ids, ["3", "1", "x"]);
+    }
+
+    #[tokio::test]
+    async fn test_check_feed_posts_new_items_once() {
+        let db = setup_db().await;
+        let mut server = mockito::Server::new_async().await;
+        let client = MattermostClient::new(server.url(), "token".to_string()).unwrap();
+        let http = http_client().unwrap();
+        let feed = FeedConfig {
+            name: "blog".to_string(),
+            url: format!("{}/feed.xml", server.url()),
+            channel_id: "c1".to_string(),
+            interval_minutes: 30,
+            template: "{{feed_title}}: [{{title}}]({{link}})".to_string(),
+            headers: Default::default(),
+        };
+
+        // 第一次讀取只記錄目前的項目
+        let first = server
+            .mock("GET", "/feed.xml")
+            .with_body(rss(&["2", "1"]))
+            .create_async()
+            .await;
+        let posts = server
+            .mock("POST", "/api/v4/posts")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "channel_id": "c1",
+                "message": "Blog: [Post 3](https://example.com/3)"
+            })))
+            .with_status(201)
+            .with_body(r#"{"id": "p1", "channel_id": "c1"}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        assert_eq!(
+            check_feed(&db, &http, &client, &feed, &LocaleFormat::default(), None)
+                .await
+                .unwrap(),
+            0
+        );
+        first.remove_async().await;
+
+        // 之後只貼新的項目
+        let second = server
+            .mock("GET", "/feed.xml")
+            .with_body(rss(&["3", "2", "1"]))
+            .create_async()
+            .await;
+        let previous = db.get_feed_state("blog").await.unwrap();
+        assert!(previous.as_ref().unwrap().succeeded_at.is_some());
+        assert_eq!(
+            check_feed(
+                &db,
+                &http,
+                &client,
+                &feed,
+                &LocaleFormat::default(),
+                previous
+            )
+            .await
+            .unwrap(),
+            1
+        );
+        let previous = db.get_feed_state("blog").await.unwrap();
+        assert_eq!(
+            check_feed(
+                &db,
+                &http,
+                &client,
+                &feed,
+                &LocaleFormat::default(),
+                previous
+            )
+            .await
+            .unwrap(),
+            0
+        );
+        posts.assert_async().await;
+        second.remove_async().await;
+
+        // 讀取失敗時記錄錯誤
+        server
+            .mock("GET", "/feed.xml")
+            .with_status(500)
+            .create_async()
+            .await;
+        let previous = db.get_feed_state("blog").await.unwrap();
+        assert!(
+            check_feed(
+                &db,
+                &http,
+                &client,
+                &feed,
+                &LocaleFormat::default(),
+                previous
+            )
+            .await
+            .is_err()
+        );
+        let state = db.get_feed_state("blog").await.unwrap().unwrap();
+        assert!(state.error.is_some());
+        assert!(state.succeeded_at.is_some());
+    }
+}
@@ -12,20 +12,49 @@ use warp::reply::{Json, WithStatus};
 use super::auth::verify_slash_command_token;
 use crate::AppState;
 use crate::database::{GroupBuy, GroupBuyOrder, GroupBuyStatus};
+use crate::error_code::ErrorCode;
 use crate::mattermost::{DialogElement, DialogElementType, DialogOption, MattermostClient};
 
 mod messages;
 pub use messages::{
-    generate_action_buttons, generate_group_buy_message, generate_group_buy_message_with_orders,
+    GROUP_BUY_MARKER_PROP, add_full_list_button_if_needed, generate_action_buttons,
+    generate_group_buy_message, generate_group_buy_message_with_orders, group_buy_marker,
+    group_buy_post_props,
 };
 mod actions;
+mod budget;
+mod creation;
+mod crosspost;
+mod deactivated;
+mod deadline;
 mod dialogs;
+mod flash;
+mod merchant;
+mod order_fields;
+mod orphans;
+mod reactions;
+mod refresh;
+mod reminder;
+mod repair;
+mod retry;
+mod shortage;
+mod templates;
 mod utils;
 pub use actions::handle_group_buy_action;
+pub use deactivated::update_user_status;
+pub use deadline::deadline_closer_job;
 pub use dialogs::{
     handle_adjust_shortage_dialog, handle_cancel_register_dialog, handle_create_dialog,
-    handle_edit_items_dialog, handle_register_dialog,
+    handle_deactivated_buyers_dialog, handle_edit_items_dialog, handle_payments_dialog,
+    handle_register_dialog,
 };
+pub use flash::parse_duration;
+pub use orphans::orphan_detector_job;
+pub use reactions::{Reaction, handle_reaction_added};
+pub use refresh::spawn_post_refresher;
+pub use reminder::reminder_job;
+pub use repair::{format_repair_report, repair_channel};
+pub use utils::sync_group_buy_post;
 // Re-export params structs so other modules (examples) can reuse the canonical types
 // Note: dialog param types are defined in `dialogs` and are intended to be
 // referenced directly (`crate::handlers::group_buy::dialogs::CreateDialogParams`)
@@ -33,6 +62,9 @@ pub use dialogs::{
 // unused-export warnings; add explicit `pub use` lines only when a consumer
 // outside the crate requires them.
 
+/// 建立團購時列出的既有商家數量上限
+const MERCHANT_SUGGESTION_LIMIT: i64 = 50;
+
 /// Slash command 參數
 #[derive(Debug, Deserialize)]
 #[allow(dead_code)]
@@ -107,14 +139,82 @@ pub async fn handle_group_buy_command(
 ) -> Result<WithStatus<Json>, warp::Rejection> {
     // 驗證 slash command token
     verify_slash_command_token(&form, &state, "group_buy").await?;
+    crate::logging::log_request(
+        &state.read().await.config.logging.routes,
+        "/group_buy",
+        || format!("{:?}", crate::logging::redact_form(&form)),
+    );
 
     let req = parse_slash_command(&form);
 
     let state_guard = state.read().await;
 
+    // `/leko group_buy ...` 的參數從第二個字開始
+    let mut args: Vec<&str> = req.text.split_whitespace().collect();
+    if req.command.trim_start_matches('/') == "leko" && !args.is_empty() {
+        args.remove(0);
+    }
+    // 個人預算與建立團購無關，不受發文權限與頻道限制影響
+    if args.first() == Some(&"budget") {
+        return Ok(budget::handle_budget_command(&req, &args[1..], &state_guard).await);
+    }
+
+    // 團購需要以 bot 身分發文，沒有發文權限時停用
+    if !state_guard.capabilities.post {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&SlashCommandResponse {
+                response_type: "ephemeral".to_string(),
+                text: "⚠️ Bot 沒有發文權限，團購功能已停用，請聯繫管理員".to_string(),
+            }),
+            StatusCode::OK,
+        ));
+    }
+
+    // 頻道可以限制建立團購的人
+    let settings = state_guard
+        .database
+        .get_channel_settings(&req.channel_id)
+        .await
+        .unwrap_or_else(|e| {
+            error!("取得頻道設定失敗: {}", e);
+            Default::default()
+        });
+    if !settings.can_create_group_buy(&req.user_name) {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&SlashCommandResponse {
+                response_type: "ephemeral".to_string(),
+                text: "⚠️ 此頻道只有指定的成員可以建立團購，請聯繫管理員".to_string(),
+            }),
+            StatusCode::OK,
+        ));
+    }
+
+    if args.first() == Some(&"flash") {
+        return Ok(flash::handle_flash_command(&req, &args[1..], &state_guard).await);
+    }
+    let template = if args.first() == Some(&"template") {
+        match templates::handle_template_command(&req, &args[1..], &state_guard).await {
+            templates::TemplateCommand::Reply(reply) => return Ok(reply),
+            templates::TemplateCommand::Use(template) => Some(template),
+        }
+    } else {
+        None
+    };
+
     // 取得 bot_callback_url
     let bot_callback_url = utils::bot_callback_url_from_state(&state_guard);
 
+    // 既有商家名稱，讓使用者直接選擇，避免同一家店有多種寫法
+    let known_merchants = state_guard
+        .database
+        .get_merchant_names(MERCHANT_SUGGESTION_LIMIT)
+        .await
+        .map(merchant::dedupe_merchant_names)
+        .unwrap_or_else(|e| {
+            error!("取得既有商家名稱失敗: {}", e);
+            Vec::new()
+        });
+
     // 開啟建立團購的 Dialog
     let create_params = dialogs::CreateDialogParams {
         trigger_id: &req.trigger_id,
@@ -123,6 +223,8 @@ pub async fn handle_group_buy_command(
         user_id: &req.user_id,
         user_name: &req.user_name,
         bot_callback_url: &bot_callback_url,
+        known_merchants: &known_merchants,
+        template: template.as_ref(),
     };
 
     match dialogs::open_create_dialog(&state_guard.mattermost_client, &create_params).await {
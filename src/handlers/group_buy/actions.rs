@@ -1,12 +1,19 @@
 use super::*;
-use std::collections::HashMap;
+use crate::handlers::preferences::favorite_item_key;
+use crate::signing::ContextError;
+use warp::Reply;
 
 /// 處理團購按鈕 Action（dispatcher）
 pub async fn handle_group_buy_action(
     action_req: crate::mattermost::ActionRequest,
     state: Arc<RwLock<AppState>>,
-) -> Result<impl warp::Reply, warp::Rejection> {
-    info!("收到團購 Action: {:?}", action_req);
+) -> Result<warp::reply::Response, warp::Rejection> {
+    info!("收到團購 Action");
+    crate::logging::log_request(
+        &state.read().await.config.logging.routes,
+        "/api/v1/group_buy/action",
+        || format!("{:?}", action_req),
+    );
 
     // 取得 group_buy_id
     let group_buy_id = action_req
@@ -18,12 +25,62 @@ pub async fn handle_group_buy_action(
             warp::reject::reject()
         })?;
 
+    // 驗證 context 簽章，避免偽造的 callback 操作團購
+    let verified = state
+        .read()
+        .await
+        .mattermost_client
+        .signer()
+        .verify_context(&action_req.context);
+    match verified {
+        Ok(()) => {}
+        Err(ContextError::Unsigned) => {
+            // 簽章機制上線前建立的團購貼文：重新產生已簽章的按鈕
+            let state_guard = state.read().await;
+            return refresh_unsigned_buttons(&state_guard, group_buy_id)
+                .await
+                .map(Reply::into_response);
+        }
+        Err(e) => {
+            error!(
+                "拒絕團購 Action（{}）: {}",
+                e,
+                crate::logging::redact_json(&action_req.context)
+            );
+            return Ok(warp::reply::json(
+                &ErrorCode::InvalidAction.ephemeral("⚠️ 無效的操作，請重新整理後再試"),
+            )
+            .into_response());
+        }
+    }
+
+    let action = action_req
+        .context
+        .get("action")
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+        .to_string();
+
+    if action == "retry" {
+        return super::retry::handle_retry_action(action_req, state).await;
+    }
+
     // 檢查並更新 post_id（在獨立的作用域中），使用 utils::fetch_group_buy 以統一錯誤處理
-    {
+    // 只有團購貼文上的按鈕才能用來補 post_id，私訊或臨時訊息的按鈕不算
+    if crate::config::GROUP_BUY_BUTTON_ACTIONS.contains(&action.as_str()) {
         let state_guard = state.read().await;
         match super::utils::fetch_group_buy(&state_guard, group_buy_id).await {
             Ok(group_buy) => {
-                if group_buy.post_id.is_none() {
+                // 草稿的按鈕在臨時訊息上，轉發到其他頻道的貼文也不是原本的貼文
+                let is_cross_post = state_guard
+                    .database
+                    .is_cross_post(&action_req.post_id)
+                    .await
+                    .unwrap_or(false);
+                if group_buy.post_id.is_none()
+                    && group_buy.status != GroupBuyStatus::Draft
+                    && !is_cross_post
+                {
                     info!(
                         "更新團購 {} 的 post_id: {}",
                         group_buy_id, action_req.post_id
@@ -44,12 +101,37 @@ pub async fn handle_group_buy_action(
         }
     }
 
-    let action = action_req
-        .context
-        .get("action")
-        .and_then(|v| v.as_str())
-        .unwrap_or("");
+    // 按鈕的回應只更新被按的貼文，轉發到其他頻道的同一個團購在回應後另外更新
+    let sync_post = POST_UPDATING_ACTIONS
+        .contains(&action.as_str())
+        .then(|| (group_buy_id.to_string(), action_req.post_id.clone()));
+    let response = dispatch(&action, action_req, state.clone()).await;
+    if let Some((group_buy_id, clicked_post_id)) = sync_post {
+        tokio::spawn(async move {
+            let state_guard = state.read().await;
+            if let Ok(group_buy) = super::utils::fetch_group_buy(&state_guard, &group_buy_id).await
+            {
+                super::utils::sync_other_group_buy_posts(
+                    &state_guard,
+                    &group_buy,
+                    &clicked_post_id,
+                )
+                .await;
+            }
+        });
+    }
+    response.map(Reply::into_response)
+}
 
+/// 回應中以 `update` 更新團購貼文的按鈕
+const POST_UPDATING_ACTIONS: &[&str] = &["close", "reopen", "mark_ordered", "archive"];
+
+/// 依 action 交給對應的處理函式。「重試」按鈕也從這裡重新執行原本的操作
+pub(super) async fn dispatch(
+    action: &str,
+    action_req: crate::mattermost::ActionRequest,
+    state: Arc<RwLock<AppState>>,
+) -> Result<warp::reply::Json, warp::Rejection> {
     match action {
         "edit_items" => handle_edit_items_action(action_req, state).await,
         "register" => handle_register_action(action_req, state).await,
@@ -57,17 +139,103 @@ pub async fn handle_group_buy_action(
         "close" => handle_close_action(action_req, state).await,
         "reopen" => handle_reopen_action(action_req, state).await,
         "adjust_shortage" => handle_adjust_shortage_action(action_req, state).await,
+        "adjustment_history" => handle_adjustment_history_action(action_req, state).await,
+        "payments" => handle_payments_action(action_req, state).await,
+        "deactivated_buyers" => handle_deactivated_buyers_action(action_req, state).await,
         "shopping_list" => handle_shopping_list_action(action_req, state).await,
         "subtotal" => handle_subtotal_action(action_req, state).await,
+        "restore_orders" => handle_restore_orders_action(action_req, state).await,
+        "publish" => handle_publish_action(action_req, state).await,
+        "mark_ordered" => handle_mark_ordered_action(action_req, state).await,
+        "archive" => handle_archive_action(action_req, state).await,
+        "full_list" => handle_full_list_action(action_req, state).await,
+        "my_registrations" => handle_my_registrations_action(action_req, state).await,
+        "my_subtotal" => handle_my_subtotal_action(action_req, state).await,
+        "reject_order" => handle_reject_order_action(action_req, state).await,
+        "apply_shortage_split" => handle_apply_shortage_split_action(action_req, state).await,
+        "apply_adjustments" => handle_apply_adjustments_action(action_req, state).await,
         _ => {
             error!("未知的 action: {}", action);
-            Ok(warp::reply::json(&serde_json::json!({
-                "ephemeral_text": "未知的操作"
-            })))
+            Ok(warp::reply::json(
+                &ErrorCode::InvalidAction.ephemeral("未知的操作"),
+            ))
         }
     }
 }
 
+/// 舊版團購貼文的按鈕沒有簽章：以目前資料重新產生訊息與按鈕，請使用者再操作一次
+async fn refresh_unsigned_buttons(
+    state_guard: &AppState,
+    group_buy_id: &str,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    let group_buy = match super::utils::fetch_group_buy(state_guard, group_buy_id).await {
+        Ok(gb) => gb,
+        Err(msg) => {
+            return Ok(warp::reply::json(
+                &serde_json::json!({"ephemeral_text": msg}),
+            ));
+        }
+    };
+
+    let bot_callback_url = super::utils::bot_callback_url_from_state(state_guard);
+
+    let orders = state_guard
+        .database
+        .get_orders_by_group_buy(group_buy_id)
+        .await
+        .unwrap_or_default();
+
+    let deactivated = super::deactivated::load_deactivated_users(&state_guard.database).await;
+    let message = generate_group_buy_message_with_orders(
+        &group_buy.merchant_name,
+        &group_buy.description,
+        &group_buy.metadata,
+        &group_buy.status,
+        &group_buy.items,
+        &group_buy.item_icons,
+        &group_buy.item_sections,
+        &group_buy.currency,
+        &orders,
+        &deactivated,
+        &state_guard.config.group_buy,
+    );
+
+    let mut attachments = generate_action_buttons(
+        group_buy_id,
+        &group_buy.status,
+        &bot_callback_url,
+        state_guard.mattermost_client.signer(),
+        &state_guard.config.group_buy.buttons,
+    );
+    add_full_list_button_if_needed(
+        &mut attachments,
+        group_buy_id,
+        orders.len(),
+        &state_guard.config.group_buy,
+        &bot_callback_url,
+        state_guard.mattermost_client.signer(),
+    );
+    super::deactivated::add_deactivated_buyers_button_if_needed(
+        &mut attachments,
+        group_buy_id,
+        &group_buy.status,
+        &orders,
+        &deactivated,
+        &bot_callback_url,
+        state_guard.mattermost_client.signer(),
+    );
+
+    info!("團購 {} 的按鈕未簽章，已重新產生", group_buy_id);
+
+    Ok(warp::reply::json(&serde_json::json!({
+        "update": {
+            "message": message,
+            "props": group_buy_post_props(group_buy_id, &attachments)
+        },
+        "ephemeral_text": "🔄 按鈕已更新，請再操作一次"
+    })))
+}
+
 /// 處理「編輯商品」按鈕
 async fn handle_edit_items_action(
     action_req: crate::mattermost::ActionRequest,
@@ -92,21 +260,29 @@ async fn handle_edit_items_action(
     };
 
     // 檢查權限：只有建立者可以編輯
-    if group_buy.creator_id != action_req.user_id {
-        return Ok(warp::reply::json(&serde_json::json!({
-            "ephemeral_text": "⚠️ 只有團購建立者可以編輯商品"
-        })));
+    if !super::utils::can_manage_group_buy(&state_guard, &group_buy, &action_req.user_id).await {
+        return Ok(warp::reply::json(
+            &ErrorCode::GbForbidden.ephemeral("⚠️ 只有團購建立者可以編輯商品"),
+        ));
     }
 
-    // 檢查狀態：只有 Active 狀態可以編輯
-    if group_buy.status != GroupBuyStatus::Active {
-        return Ok(warp::reply::json(&serde_json::json!({
-            "ephemeral_text": "⚠️ 只有進行中的團購可以編輯商品"
-        })));
+    // 檢查狀態：只有草稿與 Active 狀態可以編輯
+    if !matches!(
+        group_buy.status,
+        GroupBuyStatus::Draft | GroupBuyStatus::Active
+    ) {
+        return Ok(warp::reply::json(
+            &ErrorCode::GbClosed.ephemeral("⚠️ 只有進行中的團購可以編輯商品"),
+        ));
     }
 
     // 將當前商品轉換為 YAML 格式（helper in dialogs submodule）
-    let items_yaml = super::dialogs::items_to_yaml(&group_buy.items);
+    let items_yaml = super::dialogs::items_to_yaml(
+        &group_buy.items,
+        &group_buy.item_icons,
+        &group_buy.item_sections,
+        &group_buy.item_translations,
+    );
 
     // 打開編輯商品的 Dialog
     let trigger_id = action_req.trigger_id.as_ref().ok_or_else(|| {
@@ -116,6 +292,16 @@ async fn handle_edit_items_action(
 
     let bot_callback_url = super::utils::bot_callback_url_from_state(&state_guard);
 
+    // 同商家之前的團購，提供「載入之前的菜單」
+    let previous = state_guard
+        .database
+        .get_recent_group_buys_by_merchant(&group_buy.merchant_name, &group_buy.id, 5)
+        .await
+        .unwrap_or_else(|e| {
+            error!("取得之前的菜單失敗: {}", e);
+            Vec::new()
+        });
+
     let edit_params = super::dialogs::EditItemsDialogParams {
         trigger_id: trigger_id.as_str(),
         group_buy_id,
@@ -123,15 +309,23 @@ async fn handle_edit_items_action(
         version: group_buy.version,
         post_id: group_buy.post_id.as_deref(), // 傳遞 post_id
         bot_callback_url: bot_callback_url.as_str(),
+        previous_menus: super::dialogs::previous_menu_options(&previous),
+        textarea_length: state_guard.config.group_buy.items_textarea_length,
     };
 
     if let Err(e) =
         super::dialogs::open_edit_items_dialog(&state_guard.mattermost_client, &edit_params).await
     {
         error!("打開編輯商品 Dialog 失敗: {}", e);
-        return Ok(warp::reply::json(&serde_json::json!({
-            "ephemeral_text": "打開編輯視窗失敗"
-        })));
+        return Ok(warp::reply::json(
+            &super::retry::action_error(
+                &state_guard,
+                &action_req,
+                ErrorCode::MattermostError,
+                format!("打開編輯視窗失敗：{}", e),
+            )
+            .await,
+        ));
     }
 
     Ok(warp::reply::json(&serde_json::json!({})))
@@ -153,41 +347,122 @@ async fn handle_register_action(
     let group_buy = match state_guard.database.get_group_buy(group_buy_id).await {
         Ok(Some(gb)) => gb,
         Ok(None) => {
-            return Ok(warp::reply::json(&serde_json::json!({
-                "ephemeral_text": "找不到該團購"
-            })));
+            return Ok(warp::reply::json(
+                &ErrorCode::GbNotFound.ephemeral("找不到該團購"),
+            ));
         }
         Err(e) => {
             error!("取得團購資料失敗: {}", e);
-            return Ok(warp::reply::json(&serde_json::json!({
-                "ephemeral_text": "取得團購資料失敗"
-            })));
+            return Ok(warp::reply::json(
+                &ErrorCode::DatabaseError.ephemeral("取得團購資料失敗"),
+            ));
         }
     };
 
     // 檢查狀態
-    if group_buy.status != GroupBuyStatus::Active {
-        return Ok(warp::reply::json(&serde_json::json!({
-            "ephemeral_text": "⚠️ 此團購已截止，無法登記"
-        })));
+    if !group_buy.status.accepts_registrations() {
+        return Ok(warp::reply::json(&ErrorCode::GbClosed.ephemeral(format!(
+            "⚠️ 此團購{}，無法登記",
+            group_buy.status.label()
+        ))));
     }
 
     // 檢查是否有商品
     if group_buy.items.is_empty()
         || (group_buy.items.len() == 1 && group_buy.items.contains_key("範例商品"))
     {
-        return Ok(warp::reply::json(&serde_json::json!({
-            "ephemeral_text": "⚠️ 請先編輯商品列表"
-        })));
+        return Ok(warp::reply::json(
+            &ErrorCode::GbNoItems.ephemeral("⚠️ 請先編輯商品列表"),
+        ));
+    }
+
+    let bot_callback_url = super::utils::bot_callback_url_from_state(&state_guard);
+
+    // 商品分成多個分類時先選分類，再開啟只列出該分類商品的 Dialog
+    let sections: Vec<Option<&str>> = super::messages::group_by_section(
+        group_buy.items.keys().map(String::as_str),
+        &group_buy.item_sections,
+    )
+    .into_keys()
+    .collect();
+    let selected_section = action_req.context.get("section").and_then(|v| v.as_str());
+
+    if sections.len() > 1 && selected_section.is_none() {
+        let props = serde_json::json!({
+            "attachments": super::messages::generate_section_picker(
+                group_buy_id,
+                &sections,
+                &bot_callback_url,
+                state_guard.mattermost_client.signer(),
+            )
+        });
+        if let Err(e) = state_guard
+            .mattermost_client
+            .send_ephemeral_post_with_props(
+                &action_req.channel_id,
+                &action_req.user_id,
+                "請先選擇分類：",
+                props,
+            )
+            .await
+        {
+            error!("發送分類選單失敗: {}", e);
+            return Ok(warp::reply::json(
+                &super::retry::action_error(
+                    &state_guard,
+                    &action_req,
+                    ErrorCode::MattermostError,
+                    "打開登記視窗失敗",
+                )
+                .await,
+            ));
+        }
+        return Ok(warp::reply::json(&serde_json::json!({})));
     }
 
+    // 只列出選定分類的商品（空字串代表未分類）
+    let items: HashMap<String, Decimal> = match selected_section {
+        Some(section) => group_buy
+            .items
+            .iter()
+            .filter(|(name, _)| {
+                group_buy
+                    .item_sections
+                    .get(*name)
+                    .map(String::as_str)
+                    .unwrap_or("")
+                    == section
+            })
+            .map(|(name, price)| (name.clone(), *price))
+            .collect(),
+        None => group_buy.items.clone(),
+    };
+
     // 打開登記 Dialog
     let trigger_id = action_req.trigger_id.as_ref().ok_or_else(|| {
         error!("Action 缺少 trigger_id");
         warp::reject::reject()
     })?;
 
-    let bot_callback_url = super::utils::bot_callback_url_from_state(&state_guard);
+    // 商品有翻譯時，依開啟對話框的使用者語系顯示商品名稱
+    let locale_index = if group_buy.item_translations.is_empty() {
+        None
+    } else {
+        match state_guard
+            .mattermost_client
+            .get_user(&action_req.user_id)
+            .await
+        {
+            Ok(user) => state_guard
+                .config
+                .group_buy
+                .item_locale_index(user.locale.as_deref()),
+            Err(e) => {
+                error!("取得用戶語系失敗，使用原本的商品名稱: {}", e);
+                None
+            }
+        }
+    };
 
     // 建立 introduction_text：顯示該使用者目前已登記的商品（表格）
     let intro_text = match state_guard
@@ -198,8 +473,8 @@ async fn handle_register_action(
         Ok(orders) if !orders.is_empty() => {
             let mut s = String::new();
             s.push_str("已購買項目：\n\n| 商品 | 數量 | 小計 |\n|------|----:|-----:|\n");
-            use std::collections::HashMap;
-            let mut by_item: HashMap<String, (i32, rust_decimal::Decimal)> = HashMap::new();
+            let mut by_item: std::collections::BTreeMap<String, (i32, rust_decimal::Decimal)> =
+                std::collections::BTreeMap::new();
             for o in orders {
                 let entry = by_item
                     .entry(o.item_name.clone())
@@ -207,18 +482,43 @@ async fn handle_register_action(
                 entry.0 += o.quantity;
             }
             for (name, (qty, price)) in by_item {
-                let subtotal = price * rust_decimal::Decimal::from(qty);
-                s.push_str(&format!("| {} | {} | ${} |\n", name, qty, subtotal));
+                let subtotal = crate::money::line_total(price, qty);
+                s.push_str(&format!(
+                    "| {} | {} | {}{} |\n",
+                    super::messages::item_label(&group_buy.item_translations, &name, locale_index),
+                    qty,
+                    group_buy.currency,
+                    subtotal
+                ));
             }
             Some(s)
         }
         _ => None,
     };
 
+    // 預先選好使用者在這個商家常點的商品
+    let favorite_item = state_guard
+        .database
+        .get_user_preference(
+            &action_req.user_id,
+            &favorite_item_key(&super::merchant::merchant_key(&group_buy.merchant_name)),
+        )
+        .await
+        .unwrap_or_else(|e| {
+            error!("取得常點商品失敗: {}", e);
+            None
+        });
+
     let register_params = super::dialogs::RegisterDialogParams {
         trigger_id: trigger_id.as_str(),
         group_buy_id,
-        items: &group_buy.items,
+        items: &items,
+        item_icons: &group_buy.item_icons,
+        item_translations: &group_buy.item_translations,
+        locale_index,
+        order_fields: &group_buy.order_fields,
+        currency: &group_buy.currency,
+        default_item: favorite_item.as_deref(),
         version: group_buy.version,
         post_id: group_buy.post_id.as_deref(), // 傳遞 post_id
         introduction_text: intro_text.as_deref(),
@@ -229,9 +529,15 @@ async fn handle_register_action(
         super::dialogs::open_register_dialog(&state_guard.mattermost_client, &register_params).await
     {
         error!("打開登記 Dialog 失敗: {}", e);
-        return Ok(warp::reply::json(&serde_json::json!({
-            "ephemeral_text": "打開登記視窗失敗"
-        })));
+        return Ok(warp::reply::json(
+            &super::retry::action_error(
+                &state_guard,
+                &action_req,
+                ErrorCode::MattermostError,
+                "打開登記視窗失敗",
+            )
+            .await,
+        ));
     }
 
     Ok(warp::reply::json(&serde_json::json!({})))
@@ -273,6 +579,7 @@ async fn handle_cancel_register_action(
     }
 
     use std::collections::HashMap;
+    let deactivated = super::deactivated::load_deactivated_users(&state_guard.database).await;
     let mut buyers: HashMap<String, String> = HashMap::new(); // buyer_id -> buyer_username
     for o in &orders {
         buyers.insert(o.buyer_id.clone(), o.buyer_username.clone());
@@ -280,18 +587,34 @@ async fn handle_cancel_register_action(
 
     let mut buyer_options: Vec<DialogOption> = Vec::new();
     for (id, username) in &buyers {
+        let text = if deactivated.contains(id) {
+            format!("@{}（已停用）", username)
+        } else {
+            format!("@{}", username)
+        };
         buyer_options.push(DialogOption {
-            text: format!("@{}", username),
+            text,
             value: id.clone(),
         });
     }
+    buyer_options.sort_by(|a, b| a.text.cmp(&b.text));
+
+    let mut sorted_orders: Vec<_> = orders.iter().collect();
+    sorted_orders.sort_by(|a, b| {
+        a.buyer_username
+            .cmp(&b.buyer_username)
+            .then_with(|| a.item_name.cmp(&b.item_name))
+    });
 
     let mut intro = String::new();
     intro.push_str("目前登記：\n\n| 被登記人 | 商品 | 數量 | 登記人 |\n|---|---|---:|---|\n");
-    for o in &orders {
+    for o in sorted_orders {
         intro.push_str(&format!(
-            "| @{} | {} | {} | @{} |\n",
-            o.buyer_username, o.item_name, o.quantity, o.registrar_username
+            "| {} | {} | {} | @{} |\n",
+            super::deactivated::buyer_mention(&o.buyer_id, &o.buyer_username, &deactivated),
+            o.item_name,
+            o.quantity,
+            o.registrar_username
         ));
     }
 
@@ -317,9 +640,15 @@ async fn handle_cancel_register_action(
             .await
     {
         error!("打開取消登記 Dialog 失敗: {}", e);
-        return Ok(warp::reply::json(&serde_json::json!({
-            "ephemeral_text": "打開取消登記視窗失敗"
-        })));
+        return Ok(warp::reply::json(
+            &super::retry::action_error(
+                &state_guard,
+                &action_req,
+                ErrorCode::MattermostError,
+                "打開取消登記視窗失敗",
+            )
+            .await,
+        ));
     }
 
     Ok(warp::reply::json(&serde_json::json!({})))
@@ -348,17 +677,17 @@ async fn handle_close_action(
     };
 
     // 檢查權限：只有建立者可以截止
-    if group_buy.creator_id != action_req.user_id {
-        return Ok(warp::reply::json(&serde_json::json!({
-            "ephemeral_text": "⚠️ 只有團購建立者可以截止"
-        })));
+    if !super::utils::can_manage_group_buy(&state_guard, &group_buy, &action_req.user_id).await {
+        return Ok(warp::reply::json(
+            &ErrorCode::GbForbidden.ephemeral("⚠️ 只有團購建立者可以截止"),
+        ));
     }
 
     // 檢查狀態
     if group_buy.status != GroupBuyStatus::Active {
-        return Ok(warp::reply::json(&serde_json::json!({
-            "ephemeral_text": "⚠️ 此團購已截止"
-        })));
+        return Ok(warp::reply::json(
+            &ErrorCode::GbClosed.ephemeral("⚠️ 此團購已截止"),
+        ));
     }
 
     // 取得用戶資訊
@@ -370,9 +699,15 @@ async fn handle_close_action(
         Ok(u) => u,
         Err(e) => {
             error!("取得用戶資訊失敗: {}", e);
-            return Ok(warp::reply::json(&serde_json::json!({
-                "ephemeral_text": "無法取得用戶資訊"
-            })));
+            return Ok(warp::reply::json(
+                &super::retry::action_error(
+                    &state_guard,
+                    &action_req,
+                    ErrorCode::MattermostError,
+                    "無法取得用戶資訊",
+                )
+                .await,
+            ));
         }
     };
 
@@ -389,18 +724,24 @@ async fn handle_close_action(
         .await
     {
         error!("更新狀態失敗: {}", e);
-        return Ok(warp::reply::json(&serde_json::json!({
-            "ephemeral_text": format!("截止失敗: {}", e)
-        })));
+        return Ok(warp::reply::json(
+            &super::retry::action_error(
+                &state_guard,
+                &action_req,
+                ErrorCode::of(&e),
+                format!("截止失敗: {}", e),
+            )
+            .await,
+        ));
     }
 
     // 重新取得團購資料
     let group_buy = match state_guard.database.get_group_buy(group_buy_id).await {
         Ok(Some(gb)) => gb,
         _ => {
-            return Ok(warp::reply::json(&serde_json::json!({
-                "ephemeral_text": "取得團購資料失敗"
-            })));
+            return Ok(warp::reply::json(
+                &ErrorCode::DatabaseError.ephemeral("取得團購資料失敗"),
+            ));
         }
     };
 
@@ -413,25 +754,52 @@ async fn handle_close_action(
         .await
         .unwrap_or_default();
 
+    let deactivated = super::deactivated::load_deactivated_users(&state_guard.database).await;
     let message = generate_group_buy_message_with_orders(
         &group_buy.merchant_name,
         &group_buy.description,
         &group_buy.metadata,
         &group_buy.status,
         &group_buy.items,
+        &group_buy.item_icons,
+        &group_buy.item_sections,
+        &group_buy.currency,
         &orders,
+        &deactivated,
+        &state_guard.config.group_buy,
     );
 
-    let attachments = generate_action_buttons(group_buy_id, &group_buy.status, &bot_callback_url);
+    let mut attachments = generate_action_buttons(
+        group_buy_id,
+        &group_buy.status,
+        &bot_callback_url,
+        state_guard.mattermost_client.signer(),
+        &state_guard.config.group_buy.buttons,
+    );
+    add_full_list_button_if_needed(
+        &mut attachments,
+        group_buy_id,
+        orders.len(),
+        &state_guard.config.group_buy,
+        &bot_callback_url,
+        state_guard.mattermost_client.signer(),
+    );
+    super::deactivated::add_deactivated_buyers_button_if_needed(
+        &mut attachments,
+        group_buy_id,
+        &group_buy.status,
+        &orders,
+        &deactivated,
+        &bot_callback_url,
+        state_guard.mattermost_client.signer(),
+    );
 
     info!("{} 截止了團購 {}", user.username, group_buy_id);
 
     Ok(warp::reply::json(&serde_json::json!({
         "update": {
             "message": message,
-            "props": {
-                "attachments": attachments
-            }
+            "props": group_buy_post_props(group_buy_id, &attachments)
         }
     })))
 }
@@ -459,17 +827,17 @@ async fn handle_reopen_action(
     };
 
     // 檢查權限：只有建立者可以重新開放
-    if group_buy.creator_id != action_req.user_id {
-        return Ok(warp::reply::json(&serde_json::json!({
-            "ephemeral_text": "⚠️ 只有團購建立者可以重新開放"
-        })));
+    if !super::utils::can_manage_group_buy(&state_guard, &group_buy, &action_req.user_id).await {
+        return Ok(warp::reply::json(
+            &ErrorCode::GbForbidden.ephemeral("⚠️ 只有團購建立者可以重新開放"),
+        ));
     }
 
     // 檢查狀態
     if group_buy.status != GroupBuyStatus::Closed {
-        return Ok(warp::reply::json(&serde_json::json!({
-            "ephemeral_text": "⚠️ 此團購尚未截止"
-        })));
+        return Ok(warp::reply::json(
+            &ErrorCode::GbInvalidStatus.ephemeral("⚠️ 此團購尚未截止"),
+        ));
     }
 
     // 取得用戶資訊
@@ -481,9 +849,15 @@ async fn handle_reopen_action(
         Ok(u) => u,
         Err(e) => {
             error!("取得用戶資訊失敗: {}", e);
-            return Ok(warp::reply::json(&serde_json::json!({
-                "ephemeral_text": "無法取得用戶資訊"
-            })));
+            return Ok(warp::reply::json(
+                &super::retry::action_error(
+                    &state_guard,
+                    &action_req,
+                    ErrorCode::MattermostError,
+                    "無法取得用戶資訊",
+                )
+                .await,
+            ));
         }
     };
 
@@ -500,18 +874,24 @@ async fn handle_reopen_action(
         .await
     {
         error!("更新狀態失敗: {}", e);
-        return Ok(warp::reply::json(&serde_json::json!({
-            "ephemeral_text": format!("重新開放失敗: {}", e)
-        })));
+        return Ok(warp::reply::json(
+            &super::retry::action_error(
+                &state_guard,
+                &action_req,
+                ErrorCode::of(&e),
+                format!("重新開放失敗: {}", e),
+            )
+            .await,
+        ));
     }
 
     // 重新取得團購資料
     let group_buy = match state_guard.database.get_group_buy(group_buy_id).await {
         Ok(Some(gb)) => gb,
         _ => {
-            return Ok(warp::reply::json(&serde_json::json!({
-                "ephemeral_text": "取得團購資料失敗"
-            })));
+            return Ok(warp::reply::json(
+                &ErrorCode::DatabaseError.ephemeral("取得團購資料失敗"),
+            ));
         }
     };
 
@@ -524,30 +904,58 @@ async fn handle_reopen_action(
         .await
         .unwrap_or_default();
 
+    let deactivated = super::deactivated::load_deactivated_users(&state_guard.database).await;
     let message = generate_group_buy_message_with_orders(
         &group_buy.merchant_name,
         &group_buy.description,
         &group_buy.metadata,
         &group_buy.status,
         &group_buy.items,
+        &group_buy.item_icons,
+        &group_buy.item_sections,
+        &group_buy.currency,
         &orders,
+        &deactivated,
+        &state_guard.config.group_buy,
     );
 
-    let attachments = generate_action_buttons(group_buy_id, &group_buy.status, &bot_callback_url);
+    let mut attachments = generate_action_buttons(
+        group_buy_id,
+        &group_buy.status,
+        &bot_callback_url,
+        state_guard.mattermost_client.signer(),
+        &state_guard.config.group_buy.buttons,
+    );
+    add_full_list_button_if_needed(
+        &mut attachments,
+        group_buy_id,
+        orders.len(),
+        &state_guard.config.group_buy,
+        &bot_callback_url,
+        state_guard.mattermost_client.signer(),
+    );
+    super::deactivated::add_deactivated_buyers_button_if_needed(
+        &mut attachments,
+        group_buy_id,
+        &group_buy.status,
+        &orders,
+        &deactivated,
+        &bot_callback_url,
+        state_guard.mattermost_client.signer(),
+    );
 
     info!("{} 重新開放了團購 {}", user.username, group_buy_id);
 
     Ok(warp::reply::json(&serde_json::json!({
         "update": {
             "message": message,
-            "props": {
-                "attachments": attachments
-            }
+            "props": group_buy_post_props(group_buy_id, &attachments)
         }
     })))
 }
 
-async fn handle_adjust_shortage_action(
+/// 復原：在期限內還原被取消的登記（只有團購建立者可以操作）
+async fn handle_restore_orders_action(
     action_req: crate::mattermost::ActionRequest,
     state: Arc<RwLock<AppState>>,
 ) -> Result<warp::reply::Json, warp::Rejection> {
@@ -556,10 +964,14 @@ async fn handle_adjust_shortage_action(
         .get("group_buy_id")
         .and_then(|v| v.as_str())
         .unwrap_or("");
+    let batch_id = action_req
+        .context
+        .get("batch_id")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
 
     let state_guard = state.read().await;
 
-    // 取得團購資料（使用 utils helper）
     let group_buy = match super::utils::fetch_group_buy(&state_guard, group_buy_id).await {
         Ok(gb) => gb,
         Err(msg) => {
@@ -569,99 +981,1170 @@ async fn handle_adjust_shortage_action(
         }
     };
 
-    // 檢查權限：只有建立者可以調整
-    if group_buy.creator_id != action_req.user_id {
-        return Ok(warp::reply::json(&serde_json::json!({
-            "ephemeral_text": "⚠️ 只有團購建立者可以調整缺貨"
-        })));
-    }
-
-    // 檢查狀態：只有 Closed 可以調整
-    if group_buy.status != GroupBuyStatus::Closed {
-        return Ok(warp::reply::json(&serde_json::json!({
-            "ephemeral_text": "⚠️ 只有已截止的團購可以調整缺貨"
-        })));
+    if !super::utils::can_manage_group_buy(&state_guard, &group_buy, &action_req.user_id).await {
+        return Ok(warp::reply::json(
+            &ErrorCode::GbForbidden.ephemeral("⚠️ 只有團購建立者可以復原登記"),
+        ));
     }
 
-    // 取得訂單
-    let orders = match state_guard
-        .database
-        .get_orders_by_group_buy(group_buy_id)
+    let user = match state_guard
+        .mattermost_client
+        .get_user(&action_req.user_id)
         .await
     {
-        Ok(o) => o,
+        Ok(u) => u,
         Err(e) => {
-            error!("取得訂單失敗: {}", e);
-            return Ok(warp::reply::json(&serde_json::json!({
-                "ephemeral_text": "取得訂單失敗"
-            })));
+            error!("取得用戶資訊失敗: {}", e);
+            return Ok(warp::reply::json(
+                &super::retry::action_error(
+                    &state_guard,
+                    &action_req,
+                    ErrorCode::MattermostError,
+                    "無法取得用戶資訊",
+                )
+                .await,
+            ));
         }
     };
 
-    if orders.is_empty() {
-        return Ok(warp::reply::json(&serde_json::json!({
-            "ephemeral_text": "尚無登記資料"
-        })));
-    }
-
-    // 打開調整缺貨 Dialog
-    let trigger_id = action_req.trigger_id.as_ref().ok_or_else(|| {
-        error!("Action 缺少 trigger_id");
-        warp::reject::reject()
-    })?;
-
-    let bot_callback_url = super::utils::bot_callback_url_from_state(&state_guard);
-
-    let adjust_params = super::dialogs::AdjustShortageDialogParams {
-        trigger_id: trigger_id.as_str(),
-        group_buy_id,
-        orders: &orders,
-        version: group_buy.version,
-        bot_callback_url: bot_callback_url.as_str(),
-    };
-
-    if let Err(e) =
-        super::dialogs::open_adjust_shortage_dialog(&state_guard.mattermost_client, &adjust_params)
-            .await
+    match state_guard
+        .database
+        .restore_deleted_orders(
+            group_buy_id,
+            batch_id,
+            chrono::Duration::minutes(crate::database::ORDER_RESTORE_GRACE_MINUTES),
+            &action_req.user_id,
+            &user.username,
+        )
+        .await
     {
-        error!("打開調整缺貨 Dialog 失敗: {}", e);
-        return Ok(warp::reply::json(&serde_json::json!({
-            "ephemeral_text": "打開調整視窗失敗"
-        })));
+        Ok(count) => {
+            info!(
+                "{} 復原了團購 {} 的 {} 筆登記",
+                user.username, group_buy_id, count
+            );
+            super::refresh::request_post_refresh(&state_guard, group_buy_id);
+            Ok(warp::reply::json(&serde_json::json!({
+                "update": {
+                    "message": format!("↩️ 已復原 {} 筆登記", count),
+                    "props": {}
+                }
+            })))
+        }
+        Err(e) => Ok(warp::reply::json(
+            &super::retry::action_error(
+                &state_guard,
+                &action_req,
+                ErrorCode::of(&e),
+                format!("⚠️ 復原失敗: {}", e),
+            )
+            .await,
+        )),
     }
-
-    Ok(warp::reply::json(&serde_json::json!({})))
 }
 
-async fn handle_shopping_list_action(
+/// 購買人從代登記通知取消該筆訂單
+async fn handle_reject_order_action(
     action_req: crate::mattermost::ActionRequest,
     state: Arc<RwLock<AppState>>,
 ) -> Result<warp::reply::Json, warp::Rejection> {
-    let group_buy_id = action_req
-        .context
-        .get("group_buy_id")
-        .and_then(|v| v.as_str())
-        .unwrap_or("");
+    let context_str = |key: &str| {
+        action_req
+            .context
+            .get(key)
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string()
+    };
+    let group_buy_id = context_str("group_buy_id");
+    let order_id = context_str("order_id");
+    let buyer_id = context_str("buyer_id");
 
-    let state_guard = state.read().await;
+    if buyer_id != action_req.user_id {
+        return Ok(warp::reply::json(
+            &ErrorCode::GbForbidden.ephemeral("⚠️ 只有購買人可以取消這筆登記"),
+        ));
+    }
+
+    let state_guard = state.read().await;
+
+    let group_buy = match super::utils::fetch_group_buy(&state_guard, &group_buy_id).await {
+        Ok(gb) => gb,
+        Err(msg) => {
+            return Ok(warp::reply::json(
+                &serde_json::json!({"ephemeral_text": msg}),
+            ));
+        }
+    };
+
+    if !group_buy.status.accepts_registrations() {
+        return Ok(warp::reply::json(&ErrorCode::GbClosed.ephemeral(format!(
+            "⚠️ 此團購{}，無法取消，請聯絡團購建立者",
+            group_buy.status.label()
+        ))));
+    }
+
+    let user = match state_guard
+        .mattermost_client
+        .get_user(&action_req.user_id)
+        .await
+    {
+        Ok(u) => u,
+        Err(e) => {
+            error!("取得用戶資訊失敗: {}", e);
+            return Ok(warp::reply::json(
+                &super::retry::action_error(
+                    &state_guard,
+                    &action_req,
+                    ErrorCode::MattermostError,
+                    "無法取得用戶資訊",
+                )
+                .await,
+            ));
+        }
+    };
+
+    let deleted = match state_guard
+        .database
+        .delete_order(&group_buy_id, &order_id, &buyer_id, &user.username)
+        .await
+    {
+        Ok(deleted) => deleted,
+        Err(e) => {
+            error!("取消代登記訂單失敗: {}", e);
+            return Ok(warp::reply::json(
+                &super::retry::action_error(
+                    &state_guard,
+                    &action_req,
+                    ErrorCode::of(&e),
+                    format!("⚠️ 取消失敗: {}", e),
+                )
+                .await,
+            ));
+        }
+    };
+
+    if deleted.count == 0 {
+        return Ok(warp::reply::json(&serde_json::json!({
+            "update": {
+                "message": "此登記已取消",
+                "props": {}
+            }
+        })));
+    }
+
+    info!(
+        "{} 拒絕了團購 {} 的代登記訂單 {}",
+        user.username, group_buy_id, order_id
+    );
+    super::utils::offer_restore(
+        &state_guard,
+        &group_buy_id,
+        &deleted,
+        &format!("@{} 取消了別人幫他登記的訂單", user.username),
+    )
+    .await;
+    super::utils::sync_group_buy_post(&state_guard, &group_buy).await;
+
+    Ok(warp::reply::json(&serde_json::json!({
+        "update": {
+            "message": format!("✅ 已取消「{}」的這筆登記", group_buy.merchant_name),
+            "props": {}
+        }
+    })))
+}
+
+/// 套用預覽過的依商品缺貨分配
+async fn handle_apply_shortage_split_action(
+    action_req: crate::mattermost::ActionRequest,
+    state: Arc<RwLock<AppState>>,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    let group_buy_id = action_req
+        .context
+        .get("group_buy_id")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+    let item_name = action_req
+        .context
+        .get("item_name")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+    let results: Vec<super::shortage::SplitResult> = action_req
+        .context
+        .get("results")
+        .cloned()
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default();
+
+    let state_guard = state.read().await;
+
+    let group_buy = match super::utils::fetch_group_buy(&state_guard, group_buy_id).await {
+        Ok(gb) => gb,
+        Err(msg) => {
+            return Ok(warp::reply::json(
+                &serde_json::json!({"ephemeral_text": msg}),
+            ));
+        }
+    };
+
+    if !super::utils::can_manage_group_buy(&state_guard, &group_buy, &action_req.user_id).await {
+        return Ok(warp::reply::json(
+            &ErrorCode::GbForbidden.ephemeral("⚠️ 只有團購建立者可以調整缺貨"),
+        ));
+    }
+    if !group_buy.status.accepts_adjustments() {
+        return Ok(warp::reply::json(
+            &ErrorCode::GbInvalidStatus.ephemeral("⚠️ 只有已截止的團購可以調整缺貨"),
+        ));
+    }
+
+    // 預覽後訂單有變動時不套用，避免蓋掉別人的調整
+    let orders = state_guard
+        .database
+        .get_orders_by_group_buy(group_buy_id)
+        .await
+        .unwrap_or_default();
+    let unchanged = results.iter().all(|r| {
+        orders
+            .iter()
+            .filter(|o| o.item_name == item_name && o.buyer_username == r.buyer_username)
+            .map(|o| o.quantity)
+            .sum::<i32>()
+            == r.old_quantity
+    });
+    if results.is_empty() || !unchanged {
+        return Ok(warp::reply::json(&serde_json::json!({
+            "update": {
+                "message": "⚠️ 預覽後訂單已有變動，請重新調整缺貨",
+                "props": {}
+            }
+        })));
+    }
+
+    let user = match state_guard
+        .mattermost_client
+        .get_user(&action_req.user_id)
+        .await
+    {
+        Ok(u) => u,
+        Err(e) => {
+            error!("取得用戶資訊失敗: {}", e);
+            return Ok(warp::reply::json(
+                &super::retry::action_error(
+                    &state_guard,
+                    &action_req,
+                    ErrorCode::MattermostError,
+                    "無法取得用戶資訊",
+                )
+                .await,
+            ));
+        }
+    };
+
+    let adjustments: HashMap<String, i32> = results
+        .iter()
+        .filter(|r| r.old_quantity != r.new_quantity)
+        .map(|r| (r.buyer_username.clone(), r.new_quantity))
+        .collect();
+    match state_guard
+        .database
+        .adjust_order_quantity(
+            group_buy_id,
+            item_name,
+            &adjustments,
+            &action_req.user_id,
+            &user.username,
+        )
+        .await
+    {
+        Ok(records) => {
+            info!(
+                "{} 依商品分配了團購 {} 的 {} 缺貨，調整 {} 筆訂單",
+                user.username,
+                group_buy_id,
+                item_name,
+                records.len()
+            );
+            super::utils::sync_group_buy_post(&state_guard, &group_buy).await;
+            Ok(warp::reply::json(&serde_json::json!({
+                "update": {
+                    "message": format!(
+                        "✅ 已套用「{}」的缺貨分配，調整 {} 位購買人",
+                        item_name,
+                        adjustments.len()
+                    ),
+                    "props": {}
+                }
+            })))
+        }
+        Err(e) => {
+            error!("套用缺貨分配失敗: {}", e);
+            Ok(warp::reply::json(
+                &super::retry::action_error(
+                    &state_guard,
+                    &action_req,
+                    ErrorCode::of(&e),
+                    format!("⚠️ 套用失敗: {}", e),
+                )
+                .await,
+            ))
+        }
+    }
+}
+
+/// 套用預覽過的逐筆缺貨調整
+async fn handle_apply_adjustments_action(
+    action_req: crate::mattermost::ActionRequest,
+    state: Arc<RwLock<AppState>>,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    let group_buy_id = action_req
+        .context
+        .get("group_buy_id")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+    let changes: Vec<super::shortage::OrderChange> = action_req
+        .context
+        .get("changes")
+        .cloned()
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default();
+
+    let state_guard = state.read().await;
+
+    let group_buy = match super::utils::fetch_group_buy(&state_guard, group_buy_id).await {
+        Ok(gb) => gb,
+        Err(msg) => {
+            return Ok(warp::reply::json(
+                &serde_json::json!({"ephemeral_text": msg}),
+            ));
+        }
+    };
+
+    if !super::utils::can_manage_group_buy(&state_guard, &group_buy, &action_req.user_id).await {
+        return Ok(warp::reply::json(
+            &ErrorCode::GbForbidden.ephemeral("⚠️ 只有團購建立者可以調整缺貨"),
+        ));
+    }
+    if !group_buy.status.accepts_adjustments() {
+        return Ok(warp::reply::json(
+            &ErrorCode::GbInvalidStatus.ephemeral("⚠️ 只有已截止的團購可以調整缺貨"),
+        ));
+    }
+
+    // 預覽後訂單有變動時不套用，避免蓋掉別人的調整
+    let orders = state_guard
+        .database
+        .get_orders_by_group_buy(group_buy_id)
+        .await
+        .unwrap_or_default();
+    let unchanged = changes.iter().all(|c| {
+        orders
+            .iter()
+            .any(|o| o.id == c.order_id && o.quantity == c.old_quantity)
+    });
+    if changes.is_empty() || !unchanged {
+        return Ok(warp::reply::json(&serde_json::json!({
+            "update": {
+                "message": "⚠️ 預覽後訂單已有變動，請重新調整缺貨",
+                "props": {}
+            }
+        })));
+    }
+
+    let user = match state_guard
+        .mattermost_client
+        .get_user(&action_req.user_id)
+        .await
+    {
+        Ok(u) => u,
+        Err(e) => {
+            error!("取得用戶資訊失敗: {}", e);
+            return Ok(warp::reply::json(
+                &super::retry::action_error(
+                    &state_guard,
+                    &action_req,
+                    ErrorCode::MattermostError,
+                    "無法取得用戶資訊",
+                )
+                .await,
+            ));
+        }
+    };
+
+    for change in &changes {
+        if let Err(e) = state_guard
+            .database
+            .adjust_single_order(
+                &change.order_id,
+                change.new_quantity,
+                &action_req.user_id,
+                &user.username,
+            )
+            .await
+        {
+            error!("調整訂單 {} 數量失敗: {}", change.order_id, e);
+            return Ok(warp::reply::json(
+                &super::retry::action_error(
+                    &state_guard,
+                    &action_req,
+                    ErrorCode::of(&e),
+                    format!("⚠️ 調整訂單失敗: {}", e),
+                )
+                .await,
+            ));
+        }
+    }
+
+    info!("{} 調整了團購 {} 的缺貨", user.username, group_buy_id);
+    super::utils::sync_group_buy_post(&state_guard, &group_buy).await;
+
+    Ok(warp::reply::json(&serde_json::json!({
+        "update": {
+            "message": format!("✅ 已套用 {} 筆缺貨調整", changes.len()),
+            "props": {}
+        }
+    })))
+}
+
+/// 發布草稿：在頻道建立公開的團購貼文
+async fn handle_publish_action(
+    action_req: crate::mattermost::ActionRequest,
+    state: Arc<RwLock<AppState>>,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    let group_buy_id = action_req
+        .context
+        .get("group_buy_id")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+
+    let state_guard = state.read().await;
+
+    let group_buy = match super::utils::fetch_group_buy(&state_guard, group_buy_id).await {
+        Ok(gb) => gb,
+        Err(msg) => {
+            return Ok(warp::reply::json(
+                &serde_json::json!({"ephemeral_text": msg}),
+            ));
+        }
+    };
+
+    if group_buy.creator_id != action_req.user_id {
+        return Ok(warp::reply::json(
+            &ErrorCode::GbForbidden.ephemeral("⚠️ 只有團購建立者可以發布"),
+        ));
+    }
+
+    if group_buy.status != GroupBuyStatus::Draft {
+        return Ok(warp::reply::json(
+            &ErrorCode::GbInvalidStatus.ephemeral("⚠️ 此團購已經發布過了"),
+        ));
+    }
+
+    if group_buy.items.is_empty() {
+        return Ok(warp::reply::json(
+            &ErrorCode::GbNoItems.ephemeral("⚠️ 請先編輯商品再發布"),
+        ));
+    }
+
+    if let Err(e) = state_guard
+        .database
+        .update_status(
+            group_buy_id,
+            GroupBuyStatus::Active,
+            group_buy.version,
+            &action_req.user_id,
+            &group_buy.creator_username,
+        )
+        .await
+    {
+        error!("更新狀態失敗: {}", e);
+        return Ok(warp::reply::json(
+            &super::retry::action_error(
+                &state_guard,
+                &action_req,
+                ErrorCode::of(&e),
+                format!("發布失敗: {}", e),
+            )
+            .await,
+        ));
+    }
+
+    let bot_callback_url = super::utils::bot_callback_url_from_state(&state_guard);
+    let message = generate_group_buy_message(
+        &group_buy.merchant_name,
+        &group_buy.description,
+        &group_buy.metadata,
+        &GroupBuyStatus::Active,
+        &group_buy.items,
+        &group_buy.item_icons,
+        &group_buy.item_sections,
+        &group_buy.currency,
+    );
+    let attachments = generate_action_buttons(
+        group_buy_id,
+        &GroupBuyStatus::Active,
+        &bot_callback_url,
+        state_guard.mattermost_client.signer(),
+        &state_guard.config.group_buy.buttons,
+    );
+
+    // 與直接建立的團購一樣以建立者的身份顯示
+    let post = crate::mattermost::Post {
+        id: None,
+        channel_id: group_buy.channel_id.clone(),
+        message,
+        root_id: None,
+        props: Some(serde_json::json!({
+            "attachments": attachments,
+            GROUP_BUY_MARKER_PROP: group_buy_marker(group_buy_id),
+            "override_username": group_buy.creator_username,
+            "override_icon_url": format!(
+                "{}/api/v4/users/{}/image",
+                state_guard.config.mattermost.url, group_buy.creator_id
+            ),
+        })),
+    };
+
+    match state_guard
+        .mattermost_client
+        .create_post_with_response(&post)
+        .await
+    {
+        Ok(post_id) => {
+            if let Err(e) = state_guard
+                .database
+                .update_post_id(group_buy_id, &post_id)
+                .await
+            {
+                error!("更新 post_id 失敗: {}", e);
+            }
+            let published = GroupBuy {
+                status: GroupBuyStatus::Active,
+                post_id: Some(post_id),
+                ..group_buy.clone()
+            };
+            super::crosspost::publish_cross_posts(&state_guard, &published).await;
+        }
+        Err(e) => {
+            // 狀態已變更為進行中，使用者可以在頻道找不到貼文時回報
+            error!("發布團購貼文失敗: {}", e);
+            return Ok(warp::reply::json(
+                &ErrorCode::MattermostError.ephemeral(format!("發布團購貼文失敗: {}", e)),
+            ));
+        }
+    }
+
+    info!("{} 發布了團購 {}", group_buy.creator_username, group_buy_id);
+
+    Ok(warp::reply::json(&serde_json::json!({
+        "update": {
+            "message": format!("✅ 已發布團購「{}」", group_buy.merchant_name),
+            "props": {}
+        }
+    })))
+}
+
+/// 標記為已下單：鎖定登記，保留調整缺貨
+async fn handle_mark_ordered_action(
+    action_req: crate::mattermost::ActionRequest,
+    state: Arc<RwLock<AppState>>,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    let group_buy_id = action_req
+        .context
+        .get("group_buy_id")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+
+    let state_guard = state.read().await;
+
+    let group_buy = match super::utils::fetch_group_buy(&state_guard, group_buy_id).await {
+        Ok(gb) => gb,
+        Err(msg) => {
+            return Ok(warp::reply::json(
+                &serde_json::json!({"ephemeral_text": msg}),
+            ));
+        }
+    };
+
+    if !super::utils::can_manage_group_buy(&state_guard, &group_buy, &action_req.user_id).await {
+        return Ok(warp::reply::json(
+            &ErrorCode::GbForbidden.ephemeral("⚠️ 只有團購建立者可以標記已下單"),
+        ));
+    }
+
+    if !group_buy.status.can_transition_to(&GroupBuyStatus::Ordered) {
+        return Ok(warp::reply::json(
+            &ErrorCode::GbInvalidStatus.ephemeral("⚠️ 只有已截止的團購可以標記已下單"),
+        ));
+    }
+
+    let user = match state_guard
+        .mattermost_client
+        .get_user(&action_req.user_id)
+        .await
+    {
+        Ok(u) => u,
+        Err(e) => {
+            error!("取得用戶資訊失敗: {}", e);
+            return Ok(warp::reply::json(
+                &super::retry::action_error(
+                    &state_guard,
+                    &action_req,
+                    ErrorCode::MattermostError,
+                    "無法取得用戶資訊",
+                )
+                .await,
+            ));
+        }
+    };
+
+    if let Err(e) = state_guard
+        .database
+        .update_status(
+            group_buy_id,
+            GroupBuyStatus::Ordered,
+            group_buy.version,
+            &action_req.user_id,
+            &user.username,
+        )
+        .await
+    {
+        error!("更新狀態失敗: {}", e);
+        return Ok(warp::reply::json(
+            &super::retry::action_error(
+                &state_guard,
+                &action_req,
+                ErrorCode::of(&e),
+                format!("標記失敗: {}", e),
+            )
+            .await,
+        ));
+    }
+
+    let bot_callback_url = super::utils::bot_callback_url_from_state(&state_guard);
+    let orders = state_guard
+        .database
+        .get_orders_by_group_buy(group_buy_id)
+        .await
+        .unwrap_or_default();
+
+    let deactivated = super::deactivated::load_deactivated_users(&state_guard.database).await;
+    let message = generate_group_buy_message_with_orders(
+        &group_buy.merchant_name,
+        &group_buy.description,
+        &group_buy.metadata,
+        &GroupBuyStatus::Ordered,
+        &group_buy.items,
+        &group_buy.item_icons,
+        &group_buy.item_sections,
+        &group_buy.currency,
+        &orders,
+        &deactivated,
+        &state_guard.config.group_buy,
+    );
+    let mut attachments = generate_action_buttons(
+        group_buy_id,
+        &GroupBuyStatus::Ordered,
+        &bot_callback_url,
+        state_guard.mattermost_client.signer(),
+        &state_guard.config.group_buy.buttons,
+    );
+    add_full_list_button_if_needed(
+        &mut attachments,
+        group_buy_id,
+        orders.len(),
+        &state_guard.config.group_buy,
+        &bot_callback_url,
+        state_guard.mattermost_client.signer(),
+    );
+    super::deactivated::add_deactivated_buyers_button_if_needed(
+        &mut attachments,
+        group_buy_id,
+        &GroupBuyStatus::Ordered,
+        &orders,
+        &deactivated,
+        &bot_callback_url,
+        state_guard.mattermost_client.signer(),
+    );
+
+    info!("{} 將團購 {} 標記為已下單", user.username, group_buy_id);
+
+    Ok(warp::reply::json(&serde_json::json!({
+        "update": {
+            "message": message,
+            "props": group_buy_post_props(group_buy_id, &attachments)
+        }
+    })))
+}
+
+/// 封存：在討論串貼出最終摘要，並移除貼文上的所有按鈕
+async fn handle_archive_action(
+    action_req: crate::mattermost::ActionRequest,
+    state: Arc<RwLock<AppState>>,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    let group_buy_id = action_req
+        .context
+        .get("group_buy_id")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+
+    let state_guard = state.read().await;
+
+    let group_buy = match super::utils::fetch_group_buy(&state_guard, group_buy_id).await {
+        Ok(gb) => gb,
+        Err(msg) => {
+            return Ok(warp::reply::json(
+                &serde_json::json!({"ephemeral_text": msg}),
+            ));
+        }
+    };
+
+    if !super::utils::can_manage_group_buy(&state_guard, &group_buy, &action_req.user_id).await {
+        return Ok(warp::reply::json(
+            &ErrorCode::GbForbidden.ephemeral("⚠️ 只有團購建立者可以封存"),
+        ));
+    }
+
+    if !group_buy
+        .status
+        .can_transition_to(&GroupBuyStatus::Archived)
+    {
+        return Ok(warp::reply::json(
+            &ErrorCode::GbInvalidStatus.ephemeral("⚠️ 只有已截止或已下單的團購可以封存"),
+        ));
+    }
+
+    let user = match state_guard
+        .mattermost_client
+        .get_user(&action_req.user_id)
+        .await
+    {
+        Ok(u) => u,
+        Err(e) => {
+            error!("取得用戶資訊失敗: {}", e);
+            return Ok(warp::reply::json(
+                &super::retry::action_error(
+                    &state_guard,
+                    &action_req,
+                    ErrorCode::MattermostError,
+                    "無法取得用戶資訊",
+                )
+                .await,
+            ));
+        }
+    };
+
+    // 摘要要在狀態變更前取得，確保內容與封存當下一致
+    let orders = state_guard
+        .database
+        .get_orders_by_group_buy(group_buy_id)
+        .await
+        .unwrap_or_default();
+    let adjustments = state_guard
+        .database
+        .get_shortage_adjustments(group_buy_id)
+        .await
+        .unwrap_or_default();
+    let paid_buyers = state_guard
+        .database
+        .get_paid_buyers(group_buy_id)
+        .await
+        .unwrap_or_default();
+    let deactivated = super::deactivated::load_deactivated_users(&state_guard.database).await;
+
+    if let Err(e) = state_guard
+        .database
+        .update_status(
+            group_buy_id,
+            GroupBuyStatus::Archived,
+            group_buy.version,
+            &action_req.user_id,
+            &user.username,
+        )
+        .await
+    {
+        error!("更新狀態失敗: {}", e);
+        return Ok(warp::reply::json(
+            &super::retry::action_error(
+                &state_guard,
+                &action_req,
+                ErrorCode::of(&e),
+                format!("封存失敗: {}", e),
+            )
+            .await,
+        ));
+    }
+
+    // 摘要一律貼在團購貼文的討論串，不受 group_buy.replies 影響
+    let summary = crate::mattermost::Post {
+        id: None,
+        channel_id: group_buy.channel_id.clone(),
+        message: super::messages::generate_archive_summary(
+            &group_buy,
+            &orders,
+            &adjustments,
+            &paid_buyers,
+            &deactivated,
+            &user.username,
+            &state_guard.config.group_buy.rounding,
+            &state_guard.config.group_buy.locale_format(),
+        ),
+        root_id: group_buy
+            .post_id
+            .clone()
+            .or_else(|| Some(action_req.post_id.clone())),
+        props: None,
+    };
+    if let Err(e) = state_guard.mattermost_client.create_post(&summary).await {
+        error!("發送封存摘要失敗: {}", e);
+    }
+
+    let message = generate_group_buy_message_with_orders(
+        &group_buy.merchant_name,
+        &group_buy.description,
+        &group_buy.metadata,
+        &GroupBuyStatus::Archived,
+        &group_buy.items,
+        &group_buy.item_icons,
+        &group_buy.item_sections,
+        &group_buy.currency,
+        &orders,
+        &deactivated,
+        &state_guard.config.group_buy,
+    );
+
+    info!("{} 封存了團購 {}", user.username, group_buy_id);
+
+    Ok(warp::reply::json(&serde_json::json!({
+        "update": {
+            "message": message,
+            "props": group_buy_post_props(group_buy_id, &[])
+        }
+    })))
+}
+
+/// 完整名單：以臨時訊息回覆所有登記
+async fn handle_full_list_action(
+    action_req: crate::mattermost::ActionRequest,
+    state: Arc<RwLock<AppState>>,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    let group_buy_id = action_req
+        .context
+        .get("group_buy_id")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+
+    let state_guard = state.read().await;
+
+    let group_buy = match super::utils::fetch_group_buy(&state_guard, group_buy_id).await {
+        Ok(gb) => gb,
+        Err(msg) => {
+            return Ok(warp::reply::json(
+                &serde_json::json!({"ephemeral_text": msg}),
+            ));
+        }
+    };
+
+    let orders = match state_guard
+        .database
+        .get_orders_by_group_buy(group_buy_id)
+        .await
+    {
+        Ok(o) => o,
+        Err(e) => {
+            error!("取得訂單失敗: {}", e);
+            return Ok(warp::reply::json(
+                &ErrorCode::DatabaseError.ephemeral("取得訂單失敗"),
+            ));
+        }
+    };
+
+    if orders.is_empty() {
+        return Ok(warp::reply::json(&serde_json::json!({
+            "ephemeral_text": "目前沒有任何登記"
+        })));
+    }
+
+    let deactivated = super::deactivated::load_deactivated_users(&state_guard.database).await;
+    Ok(warp::reply::json(&serde_json::json!({
+        "ephemeral_text": super::messages::generate_full_order_table(
+            &group_buy.merchant_name,
+            &orders,
+            &group_buy.item_icons,
+            &deactivated,
+        )
+    })))
+}
+
+/// 我登記的：列出按下按鈕的人代為輸入的訂單，依購買人分組
+async fn handle_my_registrations_action(
+    action_req: crate::mattermost::ActionRequest,
+    state: Arc<RwLock<AppState>>,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    let group_buy_id = action_req
+        .context
+        .get("group_buy_id")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+
+    let state_guard = state.read().await;
+
+    let group_buy = match super::utils::fetch_group_buy(&state_guard, group_buy_id).await {
+        Ok(gb) => gb,
+        Err(msg) => {
+            return Ok(warp::reply::json(
+                &serde_json::json!({"ephemeral_text": msg}),
+            ));
+        }
+    };
+
+    let orders = match state_guard
+        .database
+        .get_orders_by_group_buy(group_buy_id)
+        .await
+    {
+        Ok(o) => o,
+        Err(e) => {
+            error!("取得訂單失敗: {}", e);
+            return Ok(warp::reply::json(
+                &ErrorCode::DatabaseError.ephemeral("取得訂單失敗"),
+            ));
+        }
+    };
+
+    let mine: Vec<_> = orders
+        .into_iter()
+        .filter(|o| o.registrar_id == action_req.user_id)
+        .collect();
+
+    if mine.is_empty() {
+        return Ok(warp::reply::json(&serde_json::json!({
+            "ephemeral_text": "你在這個團購還沒有登記任何訂單"
+        })));
+    }
+
+    let deactivated = super::deactivated::load_deactivated_users(&state_guard.database).await;
+    Ok(warp::reply::json(&serde_json::json!({
+        "ephemeral_text": super::messages::generate_registrar_view(&group_buy, &mine, &deactivated)
+    })))
+}
+
+/// 私訊我的小計：只把按下按鈕的人自己的訂單與金額私訊給他
+async fn handle_my_subtotal_action(
+    action_req: crate::mattermost::ActionRequest,
+    state: Arc<RwLock<AppState>>,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    let group_buy_id = action_req
+        .context
+        .get("group_buy_id")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+
+    let state_guard = state.read().await;
+
+    let group_buy = match super::utils::fetch_group_buy(&state_guard, group_buy_id).await {
+        Ok(gb) => gb,
+        Err(msg) => {
+            return Ok(warp::reply::json(
+                &serde_json::json!({"ephemeral_text": msg}),
+            ));
+        }
+    };
+
+    // 服務費依所有人的金額分攤，所以取得整個團購的訂單
+    let orders = match state_guard
+        .database
+        .get_orders_by_group_buy(group_buy_id)
+        .await
+    {
+        Ok(o) => o,
+        Err(e) => {
+            error!("取得訂單失敗: {}", e);
+            return Ok(warp::reply::json(
+                &ErrorCode::DatabaseError.ephemeral("取得訂單失敗"),
+            ));
+        }
+    };
+
+    if !orders.iter().any(|o| o.buyer_id == action_req.user_id) {
+        return Ok(warp::reply::json(&serde_json::json!({
+            "ephemeral_text": "你在這個團購還沒有登記"
+        })));
+    }
+
+    let message = super::messages::generate_personal_subtotal(
+        &group_buy,
+        &orders,
+        &action_req.user_id,
+        &state_guard.config.group_buy.rounding,
+        &state_guard.config.group_buy.locale_format(),
+    );
+
+    let client = &state_guard.mattermost_client;
+    let channel = match client
+        .create_direct_channel(&state_guard.bot_user_id, &action_req.user_id)
+        .await
+    {
+        Ok(c) => c,
+        Err(e) => {
+            error!("建立私訊頻道失敗: {}", e);
+            return Ok(warp::reply::json(
+                &super::retry::action_error(
+                    &state_guard,
+                    &action_req,
+                    ErrorCode::MattermostError,
+                    "無法私訊你，請稍後再試",
+                )
+                .await,
+            ));
+        }
+    };
+
+    if let Err(e) = client.create_post_simple(&channel.id, &message, None).await {
+        error!("發送私訊小計失敗: {}", e);
+        return Ok(warp::reply::json(
+            &super::retry::action_error(
+                &state_guard,
+                &action_req,
+                ErrorCode::MattermostError,
+                "無法私訊你，請稍後再試",
+            )
+            .await,
+        ));
+    }
+
+    Ok(warp::reply::json(&serde_json::json!({
+        "ephemeral_text": "📬 已私訊你的小計"
+    })))
+}
+
+async fn handle_adjust_shortage_action(
+    action_req: crate::mattermost::ActionRequest,
+    state: Arc<RwLock<AppState>>,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    let group_buy_id = action_req
+        .context
+        .get("group_buy_id")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
 
-    // 取得團購資料
-    let group_buy = match state_guard.database.get_group_buy(group_buy_id).await {
-        Ok(Some(gb)) => gb,
-        Ok(None) => {
-            return Ok(warp::reply::json(&serde_json::json!({
-                "ephemeral_text": "找不到該團購"
-            })));
+    let state_guard = state.read().await;
+
+    // 取得團購資料（使用 utils helper）
+    let group_buy = match super::utils::fetch_group_buy(&state_guard, group_buy_id).await {
+        Ok(gb) => gb,
+        Err(msg) => {
+            return Ok(warp::reply::json(
+                &serde_json::json!({"ephemeral_text": msg}),
+            ));
         }
+    };
+
+    // 檢查權限：只有建立者可以調整
+    if !super::utils::can_manage_group_buy(&state_guard, &group_buy, &action_req.user_id).await {
+        return Ok(warp::reply::json(
+            &ErrorCode::GbForbidden.ephemeral("⚠️ 只有團購建立者可以調整缺貨"),
+        ));
+    }
+
+    // 檢查狀態：只有已截止或已下單可以調整
+    if !group_buy.status.accepts_adjustments() {
+        return Ok(warp::reply::json(
+            &ErrorCode::GbInvalidStatus.ephemeral("⚠️ 只有已截止的團購可以調整缺貨"),
+        ));
+    }
+
+    // 取得訂單
+    let orders = match state_guard
+        .database
+        .get_orders_by_group_buy(group_buy_id)
+        .await
+    {
+        Ok(o) => o,
         Err(e) => {
-            error!("取得團購資料失敗: {}", e);
-            return Ok(warp::reply::json(&serde_json::json!({
-                "ephemeral_text": "取得團購資料失敗"
-            })));
+            error!("取得訂單失敗: {}", e);
+            return Ok(warp::reply::json(
+                &ErrorCode::DatabaseError.ephemeral("取得訂單失敗"),
+            ));
         }
     };
 
-    // 取得訂單
+    if orders.is_empty() {
+        return Ok(warp::reply::json(&serde_json::json!({
+            "ephemeral_text": "尚無登記資料"
+        })));
+    }
+
+    // 打開調整缺貨 Dialog
+    let trigger_id = action_req.trigger_id.as_ref().ok_or_else(|| {
+        error!("Action 缺少 trigger_id");
+        warp::reject::reject()
+    })?;
+
+    let bot_callback_url = super::utils::bot_callback_url_from_state(&state_guard);
+
+    let adjust_params = super::dialogs::AdjustShortageDialogParams {
+        trigger_id: trigger_id.as_str(),
+        group_buy_id,
+        orders: &orders,
+        version: group_buy.version,
+        bot_callback_url: bot_callback_url.as_str(),
+    };
+
+    if let Err(e) =
+        super::dialogs::open_adjust_shortage_dialog(&state_guard.mattermost_client, &adjust_params)
+            .await
+    {
+        error!("打開調整缺貨 Dialog 失敗: {}", e);
+        return Ok(warp::reply::json(
+            &super::retry::action_error(
+                &state_guard,
+                &action_req,
+                ErrorCode::MattermostError,
+                "打開調整視窗失敗",
+            )
+            .await,
+        ));
+    }
+
+    Ok(warp::reply::json(&serde_json::json!({})))
+}
+
+/// 開啟付款狀態 Dialog，由建立者勾選已付款的購買人
+async fn handle_payments_action(
+    action_req: crate::mattermost::ActionRequest,
+    state: Arc<RwLock<AppState>>,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    let group_buy_id = action_req
+        .context
+        .get("group_buy_id")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+
+    let state_guard = state.read().await;
+
+    let group_buy = match super::utils::fetch_group_buy(&state_guard, group_buy_id).await {
+        Ok(gb) => gb,
+        Err(msg) => {
+            return Ok(warp::reply::json(
+                &serde_json::json!({"ephemeral_text": msg}),
+            ));
+        }
+    };
+
+    if !super::utils::can_manage_group_buy(&state_guard, &group_buy, &action_req.user_id).await {
+        return Ok(warp::reply::json(
+            &ErrorCode::GbForbidden.ephemeral("⚠️ 只有團購建立者可以記錄付款狀態"),
+        ));
+    }
+
+    // 截止後金額才確定，已截止或已下單時才記錄付款
+    if !group_buy.status.accepts_adjustments() {
+        return Ok(warp::reply::json(
+            &ErrorCode::GbInvalidStatus.ephemeral("⚠️ 只有已截止的團購可以記錄付款狀態"),
+        ));
+    }
+
     let orders = match state_guard
         .database
         .get_orders_by_group_buy(group_buy_id)
@@ -670,9 +2153,9 @@ async fn handle_shopping_list_action(
         Ok(o) => o,
         Err(e) => {
             error!("取得訂單失敗: {}", e);
-            return Ok(warp::reply::json(&serde_json::json!({
-                "ephemeral_text": "取得訂單失敗"
-            })));
+            return Ok(warp::reply::json(
+                &ErrorCode::DatabaseError.ephemeral("取得訂單失敗"),
+            ));
         }
     };
 
@@ -682,59 +2165,169 @@ async fn handle_shopping_list_action(
         })));
     }
 
-    // 統計每個商品的總數量
-    let mut shopping_list: HashMap<String, i32> = HashMap::new();
-    for order in &orders {
-        *shopping_list.entry(order.item_name.clone()).or_insert(0) += order.quantity;
+    let paid_buyers = match state_guard.database.get_paid_buyers(group_buy_id).await {
+        Ok(paid) => paid,
+        Err(e) => {
+            error!("取得付款狀態失敗: {}", e);
+            return Ok(warp::reply::json(
+                &ErrorCode::DatabaseError.ephemeral("取得付款狀態失敗"),
+            ));
+        }
+    };
+
+    let trigger_id = action_req.trigger_id.as_ref().ok_or_else(|| {
+        error!("Action 缺少 trigger_id");
+        warp::reject::reject()
+    })?;
+
+    let bot_callback_url = super::utils::bot_callback_url_from_state(&state_guard);
+    let deactivated = super::deactivated::load_deactivated_users(&state_guard.database).await;
+
+    let payments_params = super::dialogs::PaymentsDialogParams {
+        trigger_id: trigger_id.as_str(),
+        group_buy: &group_buy,
+        orders: &orders,
+        paid_buyers: &paid_buyers,
+        deactivated: &deactivated,
+        bot_callback_url: bot_callback_url.as_str(),
+    };
+
+    if let Err(e) =
+        super::dialogs::open_payments_dialog(&state_guard.mattermost_client, &payments_params).await
+    {
+        error!("打開付款狀態 Dialog 失敗: {}", e);
+        return Ok(warp::reply::json(
+            &super::retry::action_error(
+                &state_guard,
+                &action_req,
+                ErrorCode::MattermostError,
+                "打開付款狀態視窗失敗",
+            )
+            .await,
+        ));
+    }
+
+    Ok(warp::reply::json(&serde_json::json!({})))
+}
+
+/// 確認購買人的帳號狀態，開啟對話框讓建立者把帳號已停用的購買人的登記改到別人名下或刪除
+async fn handle_deactivated_buyers_action(
+    action_req: crate::mattermost::ActionRequest,
+    state: Arc<RwLock<AppState>>,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    let group_buy_id = action_req
+        .context
+        .get("group_buy_id")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+
+    let state_guard = state.read().await;
+
+    let group_buy = match super::utils::fetch_group_buy(&state_guard, group_buy_id).await {
+        Ok(gb) => gb,
+        Err(msg) => {
+            return Ok(warp::reply::json(
+                &serde_json::json!({"ephemeral_text": msg}),
+            ));
+        }
+    };
+
+    if !super::utils::can_manage_group_buy(&state_guard, &group_buy, &action_req.user_id).await {
+        return Ok(warp::reply::json(
+            &ErrorCode::GbForbidden.ephemeral("⚠️ 只有團購建立者可以處理停用帳號的登記"),
+        ));
+    }
+
+    if group_buy.status == GroupBuyStatus::Archived {
+        return Ok(warp::reply::json(
+            &ErrorCode::GbInvalidStatus.ephemeral("⚠️ 已封存的團購無法修改登記"),
+        ));
     }
 
-    // 計算統計資訊
-    let num_items = shopping_list.len();
-    let num_people: std::collections::HashSet<_> =
-        orders.iter().map(|o| o.buyer_id.clone()).collect();
+    let orders = match state_guard
+        .database
+        .get_orders_by_group_buy(group_buy_id)
+        .await
+    {
+        Ok(o) => o,
+        Err(e) => {
+            error!("取得訂單失敗: {}", e);
+            return Ok(warp::reply::json(
+                &ErrorCode::DatabaseError.ephemeral("取得訂單失敗"),
+            ));
+        }
+    };
+
+    // 帳號可能已經恢復，重新確認；狀態改變時 check_buyers 會更新貼文
+    let deactivated = super::deactivated::check_buyers(&state_guard, &orders).await;
+    if deactivated.is_empty() {
+        return Ok(warp::reply::json(&serde_json::json!({
+            "ephemeral_text": "✅ 所有購買人的帳號都在使用中"
+        })));
+    }
 
-    // 生成採購列表訊息（使用表格）
-    let mut msg = "### 🛍️ 採購列表\n\n".to_string();
-    msg.push_str(&format!(
-        "**商家：{}  •  品項：{}  •  人數：{}**\n\n",
-        group_buy.merchant_name,
-        num_items,
-        num_people.len()
-    ));
-    msg.push_str("| 商品 | 數量 | 單價 | 小計 |\n");
-    msg.push_str("|------|-----:|-----:|-----:|\n");
+    let trigger_id = action_req.trigger_id.as_ref().ok_or_else(|| {
+        error!("Action 缺少 trigger_id");
+        warp::reject::reject()
+    })?;
 
-    // 排序商品名稱
-    let mut sorted_items: Vec<_> = shopping_list.iter().collect();
-    sorted_items.sort_by_key(|(name, _)| *name);
+    let bot_callback_url = super::utils::bot_callback_url_from_state(&state_guard);
 
-    for (item_name, total_qty) in sorted_items {
-        let price = group_buy
-            .items
-            .get(item_name)
-            .copied()
-            .unwrap_or(Decimal::ZERO);
-        let subtotal = price * Decimal::from(*total_qty);
-        msg.push_str(&format!(
-            "| {} | {} | ${} | ${} |\n",
-            item_name, total_qty, price, subtotal
+    if let Err(e) = super::dialogs::open_deactivated_buyers_dialog(
+        &state_guard.mattermost_client,
+        trigger_id,
+        &group_buy,
+        &deactivated,
+        &bot_callback_url,
+    )
+    .await
+    {
+        error!("打開停用帳號 Dialog 失敗: {}", e);
+        return Ok(warp::reply::json(
+            &super::retry::action_error(
+                &state_guard,
+                &action_req,
+                ErrorCode::MattermostError,
+                "打開停用帳號視窗失敗",
+            )
+            .await,
         ));
     }
 
-    // 計算總金額（使用 Decimal 進行精確計算）
-    let total_amount: Decimal = orders
-        .iter()
-        .map(|o| o.unit_price * Decimal::from(o.quantity))
-        .sum();
+    Ok(warp::reply::json(&serde_json::json!({})))
+}
+
+/// 以臨時訊息列出缺貨調整紀錄
+async fn handle_adjustment_history_action(
+    action_req: crate::mattermost::ActionRequest,
+    state: Arc<RwLock<AppState>>,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    let group_buy_id = action_req
+        .context
+        .get("group_buy_id")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
 
-    msg.push_str(&format!("\n**💰 總金額：NT${}**", total_amount));
+    let state_guard = state.read().await;
 
-    Ok(warp::reply::json(&serde_json::json!({
-        "ephemeral_text": msg
-    })))
+    match state_guard
+        .database
+        .get_shortage_adjustments(group_buy_id)
+        .await
+    {
+        Ok(adjustments) => Ok(warp::reply::json(&serde_json::json!({
+            "ephemeral_text": super::messages::generate_adjustment_history(&adjustments, &state_guard.config.group_buy.locale_format())
+        }))),
+        Err(e) => {
+            error!("取得調整紀錄失敗: {}", e);
+            Ok(warp::reply::json(
+                &ErrorCode::DatabaseError.ephemeral("取得調整紀錄失敗"),
+            ))
+        }
+    }
 }
 
-async fn handle_subtotal_action(
+async fn handle_shopping_list_action(
     action_req: crate::mattermost::ActionRequest,
     state: Arc<RwLock<AppState>>,
 ) -> Result<warp::reply::Json, warp::Rejection> {
@@ -750,15 +2343,15 @@ async fn handle_subtotal_action(
     let group_buy = match state_guard.database.get_group_buy(group_buy_id).await {
         Ok(Some(gb)) => gb,
         Ok(None) => {
-            return Ok(warp::reply::json(&serde_json::json!({
-                "ephemeral_text": "找不到該團購"
-            })));
+            return Ok(warp::reply::json(
+                &ErrorCode::GbNotFound.ephemeral("找不到該團購"),
+            ));
         }
         Err(e) => {
             error!("取得團購資料失敗: {}", e);
-            return Ok(warp::reply::json(&serde_json::json!({
-                "ephemeral_text": "取得團購資料失敗"
-            })));
+            return Ok(warp::reply::json(
+                &ErrorCode::DatabaseError.ephemeral("取得團購資料失敗"),
+            ));
         }
     };
 
@@ -771,9 +2364,9 @@ async fn handle_subtotal_action(
         Ok(o) => o,
         Err(e) => {
             error!("取得訂單失敗: {}", e);
-            return Ok(warp::reply::json(&serde_json::json!({
-                "ephemeral_text": "取得訂單失敗"
-            })));
+            return Ok(warp::reply::json(
+                &ErrorCode::DatabaseError.ephemeral("取得訂單失敗"),
+            ));
         }
     };
 
@@ -783,42 +2376,75 @@ async fn handle_subtotal_action(
         })));
     }
 
-    // 按購買人分組統計（使用 Decimal 進行精確計算）
-    let mut subtotals: HashMap<String, Decimal> = HashMap::new();
-    for order in &orders {
-        let item_total = order.unit_price * Decimal::from(order.quantity);
-        *subtotals
-            .entry(order.buyer_username.clone())
-            .or_insert(Decimal::ZERO) += item_total;
-    }
+    Ok(warp::reply::json(&serde_json::json!({
+        "ephemeral_text": super::messages::generate_shopping_list(&group_buy, &orders, &state_guard.config.group_buy.rounding, &state_guard.config.group_buy.locale_format())
+    })))
+}
 
-    // 排序（按金額由高到低）
-    let mut sorted_subtotals: Vec<_> = subtotals.iter().collect();
-    sorted_subtotals.sort_by(|a, b| b.1.cmp(a.1));
+async fn handle_subtotal_action(
+    action_req: crate::mattermost::ActionRequest,
+    state: Arc<RwLock<AppState>>,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    let group_buy_id = action_req
+        .context
+        .get("group_buy_id")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
 
-    // 生成小計訊息（使用表格）
-    let num_people = subtotals.len();
-    let mut msg = "### 💰 個人小計\n\n".to_string();
-    msg.push_str(&format!(
-        "**商家：{}  •  人數：{}**\n\n",
-        group_buy.merchant_name, num_people
-    ));
-    msg.push_str("| 訂購人 | 金額 |\n");
-    msg.push_str("|--------|-----:|\n");
+    let state_guard = state.read().await;
 
-    for (buyer, amount) in sorted_subtotals {
-        msg.push_str(&format!("| @{} | ${} |\n", buyer, amount));
-    }
+    // 取得團購資料
+    let group_buy = match state_guard.database.get_group_buy(group_buy_id).await {
+        Ok(Some(gb)) => gb,
+        Ok(None) => {
+            return Ok(warp::reply::json(
+                &ErrorCode::GbNotFound.ephemeral("找不到該團購"),
+            ));
+        }
+        Err(e) => {
+            error!("取得團購資料失敗: {}", e);
+            return Ok(warp::reply::json(
+                &ErrorCode::DatabaseError.ephemeral("取得團購資料失敗"),
+            ));
+        }
+    };
 
-    // 總金額（使用 Decimal 進行精確計算）
-    let total_amount: Decimal = orders
-        .iter()
-        .map(|o| o.unit_price * Decimal::from(o.quantity))
-        .sum();
+    // 取得訂單
+    let orders = match state_guard
+        .database
+        .get_orders_by_group_buy(group_buy_id)
+        .await
+    {
+        Ok(o) => o,
+        Err(e) => {
+            error!("取得訂單失敗: {}", e);
+            return Ok(warp::reply::json(
+                &ErrorCode::DatabaseError.ephemeral("取得訂單失敗"),
+            ));
+        }
+    };
+
+    if orders.is_empty() {
+        return Ok(warp::reply::json(&serde_json::json!({
+            "ephemeral_text": "尚無登記資料"
+        })));
+    }
 
-    msg.push_str(&format!("\n**🧮 總計：NT${}**", total_amount));
+    // 截止後才記錄付款，之前的小計不顯示付款欄
+    let paid_buyers = if group_buy.status.accepts_adjustments() {
+        match state_guard.database.get_paid_buyers(group_buy_id).await {
+            Ok(paid) => Some(paid),
+            Err(e) => {
+                error!("取得付款狀態失敗: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
 
+    let deactivated = super::deactivated::load_deactivated_users(&state_guard.database).await;
     Ok(warp::reply::json(&serde_json::json!({
-        "ephemeral_text": msg
+        "ephemeral_text": super::messages::generate_subtotal_table(&group_buy, &orders, paid_buyers.as_ref(), &deactivated, &state_guard.config.group_buy.rounding, &state_guard.config.group_buy.locale_format())
     })))
 }
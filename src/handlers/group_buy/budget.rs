@@ -0,0 +1,181 @@
+//! 個人每月團購預算：`/group_buy budget 1500`
+//!
+//! 預算存在 `user_preferences`（`group_buy_budget`）。自己登記後，若本月（本地時間月初起）
+//! 在所有團購登記的金額超過預算，以臨時訊息提醒；只是提醒，不會擋下登記。
+//! 幫別人登記時不提醒，避免把對方的預算顯示給登記人。
+
+use super::*;
+use chrono::{DateTime, Datelike, Utc};
+
+/// 每月預算的偏好名稱
+pub const BUDGET_KEY: &str = "group_buy_budget";
+
+const BUDGET_USAGE: &str =
+    "用法：`/group_buy budget <金額>` 設定每月團購預算，`/group_buy budget off` 取消";
+
+/// 解析預算金額，`off` 表示取消（回傳 None）
+pub fn parse_budget(input: &str) -> std::result::Result<Option<Decimal>, String> {
+    let input = input.trim();
+    if input.eq_ignore_ascii_case("off") {
+        return Ok(None);
+    }
+    match Decimal::from_str(&input.replace(',', "")) {
+        Ok(budget) if budget > Decimal::ZERO => Ok(Some(budget.normalize())),
+        Ok(_) => Err("預算必須大於 0".to_string()),
+        Err(_) => Err(format!("無法解析金額「{}」。{}", input, BUDGET_USAGE)),
+    }
+}
+
+/// 本月第一天 00:00（本地時間）
+pub fn month_start() -> DateTime<Utc> {
+    let today = chrono::Local::now().date_naive();
+    today
+        .with_day(1)
+        .unwrap_or(today)
+        .and_hms_opt(0, 0, 0)
+        .and_then(|t| t.and_local_timezone(chrono::Local).earliest())
+        .map(|t| t.with_timezone(&Utc))
+        .unwrap_or_else(Utc::now)
+}
+
+/// 本月金額超過預算時的提醒，沒有超過時回傳 None
+pub fn format_budget_warning(budget: Decimal, spent: Decimal) -> Option<String> {
+    (spent > budget).then(|| {
+        format!(
+            "💸 本月團購已登記 {}，超過你設定的預算 {}（超出 {}）",
+            spent,
+            budget,
+            spent - budget
+        )
+    })
+}
+
+/// 購買人設定的每月預算
+async fn get_budget(state_guard: &AppState, user_id: &str) -> Result<Option<Decimal>> {
+    let prefs = state_guard.database.list_user_preferences(user_id).await?;
+    Ok(prefs
+        .into_iter()
+        .find(|(key, _)| key == BUDGET_KEY)
+        .and_then(|(_, value)| Decimal::from_str(&value).ok()))
+}
+
+/// 登記後檢查本月金額是否超過預算，超過時回傳提醒文字。查詢失敗只記錄錯誤
+pub async fn budget_warning(state_guard: &AppState, buyer_id: &str) -> Option<String> {
+    let budget = match get_budget(state_guard, buyer_id).await {
+        Ok(budget) => budget?,
+        Err(e) => {
+            error!("取得團購預算失敗: {}", e);
+            return None;
+        }
+    };
+    match state_guard
+        .database
+        .get_buyer_spend_since(buyer_id, month_start())
+        .await
+    {
+        Ok(spent) => format_budget_warning(budget, spent),
+        Err(e) => {
+            error!("計算本月團購金額失敗: {}", e);
+            None
+        }
+    }
+}
+
+fn ephemeral(text: String) -> WithStatus<Json> {
+    warp::reply::with_status(
+        warp::reply::json(&SlashCommandResponse {
+            response_type: "ephemeral".to_string(),
+            text,
+        }),
+        StatusCode::OK,
+    )
+}
+
+/// 處理 `/group_buy budget [金額|off]`，沒有參數時顯示目前預算與本月金額
+pub async fn handle_budget_command(
+    req: &SlashCommandRequest,
+    args: &[&str],
+    state_guard: &AppState,
+) -> WithStatus<Json> {
+    let db = &state_guard.database;
+
+    if !args.is_empty() {
+        let result = match parse_budget(&args.join(" ")) {
+            Ok(Some(budget)) => {
+                db.set_user_preference(&req.user_id, BUDGET_KEY, &budget.to_string())
+                    .await
+            }
+            Ok(None) => db
+                .delete_user_preferences(&req.user_id, BUDGET_KEY)
+                .await
+                .map(|_| ()),
+            Err(msg) => return ephemeral(format!("⚠️ {}", msg)),
+        };
+        if let Err(e) = result {
+            error!("儲存團購預算失敗: {}", e);
+            return ephemeral(format!("儲存團購預算失敗: {}", e));
+        }
+        info!("{} 更新了團購預算", req.user_name);
+    }
+
+    let budget = match get_budget(state_guard, &req.user_id).await {
+        Ok(budget) => budget,
+        Err(e) => {
+            error!("取得團購預算失敗: {}", e);
+            return ephemeral("取得團購預算失敗".to_string());
+        }
+    };
+    let spent = db
+        .get_buyer_spend_since(&req.user_id, month_start())
+        .await
+        .unwrap_or_else(|e| {
+            error!("計算本月團購金額失敗: {}", e);
+            Decimal::ZERO
+        });
+
+    let text = match budget {
+        Some(budget) => format!(
+            "### 💰 每月團購預算\n\n預算：{}  •  本月已登記：{}  •  剩餘：{}\n\n{}",
+            budget,
+            spent,
+            budget - spent,
+            BUDGET_USAGE
+        ),
+        None => format!(
+            "### 💰 每月團購預算\n\n尚未設定預算  •  本月已登記：{}\n\n{}",
+            spent, BUDGET_USAGE
+        ),
+    };
+    ephemeral(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_budget() {
+        assert_eq!(parse_budget("1500"), Ok(Some(Decimal::new(1500, 0))));
+        assert_eq!(parse_budget("1,500.50"), Ok(Some(Decimal::new(150050, 2))));
+        assert_eq!(parse_budget(" OFF "), Ok(None));
+        assert!(parse_budget("0").is_err());
+        assert!(parse_budget("-10").is_err());
+        assert!(parse_budget("很多").is_err());
+    }
+
+    #[test]
+    fn test_format_budget_warning() {
+        let budget = Decimal::new(1500, 0);
+        assert_eq!(format_budget_warning(budget, Decimal::new(1500, 0)), None);
+        let warning = format_budget_warning(budget, Decimal::new(1620, 0)).unwrap();
+        assert!(warning.contains("已登記 1620"));
+        assert!(warning.contains("超出 120"));
+    }
+
+    #[test]
+    fn test_month_start() {
+        let start = month_start().with_timezone(&chrono::Local);
+        assert_eq!(start.day(), 1);
+        assert!(start <= chrono::Local::now());
+    }
+}
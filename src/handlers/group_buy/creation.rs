@@ -0,0 +1,313 @@
+//! 建立團購的流程
+//!
+//! 依序發布團購貼文、寫入資料庫、設定自動截止時間與截止前提醒。後面的步驟失敗時撤銷前面
+//! 已完成的步驟（刪除團購資料、刪除貼文），不會留下按鈕點了找不到團購的貼文，
+//! 也不會留下頻道裡看不到的團購。
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use tracing::{error, warn};
+
+use super::messages::{GROUP_BUY_MARKER_PROP, group_buy_marker};
+use crate::database::{Database, GroupBuy, GroupBuyStatus};
+use crate::mattermost::{MattermostClient, Post};
+
+/// 團購貼文的內容與發文身分
+pub struct Announcement<'a> {
+    /// slash command 的 response_url，空字串或發送失敗時改用 API 發文
+    pub response_url: &'a str,
+    pub message: &'a str,
+    pub attachments: &'a [serde_json::Value],
+    /// 以建立者的名稱與頭像發文
+    pub username: &'a str,
+    pub icon_url: &'a str,
+}
+
+/// 發布團購並寫入資料庫，成功時 `group_buy.post_id` 為 API 建立的貼文 ID。
+/// 任何步驟失敗都會撤銷已完成的步驟，回傳的錯誤說明失敗在哪一步。
+pub async fn create_group_buy(
+    client: &MattermostClient,
+    database: &Database,
+    group_buy: &mut GroupBuy,
+    announcement: &Announcement<'_>,
+    close_at: Option<DateTime<Utc>>,
+    remind_at: Option<DateTime<Utc>>,
+) -> Result<()> {
+    let post_id = publish(client, group_buy, announcement)
+        .await
+        .context("發送團購訊息失敗")?;
+    group_buy.post_id = post_id;
+
+    if let Err(e) = database.create_group_buy(group_buy).await {
+        retract(client, group_buy).await;
+        return Err(e.context("儲存團購資料失敗"));
+    }
+
+    if let Err(e) = schedule(database, &group_buy.id, close_at, remind_at).await {
+        // 截止時間與提醒隨團購一併刪除（外鍵 ON DELETE CASCADE）
+        if let Err(delete_err) = database.delete_group_buy(&group_buy.id).await {
+            error!(
+                "撤銷團購 {} 的資料失敗，需手動刪除: {}",
+                group_buy.id, delete_err
+            );
+        }
+        retract(client, group_buy).await;
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+/// 寫入自動截止時間與截止前提醒
+async fn schedule(
+    database: &Database,
+    group_buy_id: &str,
+    close_at: Option<DateTime<Utc>>,
+    remind_at: Option<DateTime<Utc>>,
+) -> Result<()> {
+    if let Some(close_at) = close_at {
+        database
+            .set_deadline(group_buy_id, close_at)
+            .await
+            .context("設定截止時間失敗")?;
+    }
+    if let Some(remind_at) = remind_at {
+        database
+            .set_reminder(group_buy_id, remind_at)
+            .await
+            .context("設定截止前提醒失敗")?;
+    }
+    Ok(())
+}
+
+/// 發布團購貼文，草稿只以臨時訊息回覆建立者。
+/// 回傳以 API 建立的貼文 ID；經 response_url 發送或臨時訊息沒有 ID。
+async fn publish(
+    client: &MattermostClient,
+    group_buy: &GroupBuy,
+    announcement: &Announcement<'_>,
+) -> Result<Option<String>> {
+    let is_draft = group_buy.status == GroupBuyStatus::Draft;
+    let payload = if is_draft {
+        serde_json::json!({
+            "response_type": "ephemeral",
+            "text": announcement.message,
+            "attachments": announcement.attachments,
+        })
+    } else {
+        serde_json::json!({
+            "response_type": "in_channel",
+            "text": announcement.message,
+            "attachments": announcement.attachments,
+            "username": announcement.username,
+            "icon_url": announcement.icon_url,
+            "props": { GROUP_BUY_MARKER_PROP: group_buy_marker(&group_buy.id) },
+        })
+    };
+
+    // response_url 過期（30 分鐘）或網路錯誤時改用 API 發文
+    let Err(e) = client
+        .post_to_response_url(announcement.response_url, &payload)
+        .await
+    else {
+        return Ok(None);
+    };
+    warn!("發送到 response_url 失敗，改用 API 發文: {}", e);
+
+    if is_draft {
+        client
+            .send_ephemeral_post_with_props(
+                &group_buy.channel_id,
+                &group_buy.creator_id,
+                announcement.message,
+                serde_json::json!({ "attachments": announcement.attachments }),
+            )
+            .await?;
+        return Ok(None);
+    }
+
+    let post = Post {
+        id: None,
+        channel_id: group_buy.channel_id.clone(),
+        message: announcement.message.to_string(),
+        root_id: None,
+        props: Some(serde_json::json!({
+            "attachments": announcement.attachments,
+            GROUP_BUY_MARKER_PROP: group_buy_marker(&group_buy.id),
+            "override_username": announcement.username,
+            "override_icon_url": announcement.icon_url,
+        })),
+    };
+    client.create_post_with_response(&post).await.map(Some)
+}
+
+/// 撤銷已發布的貼文。經 response_url 發送的貼文沒有 ID 無法刪除，改提醒建立者忽略
+async fn retract(client: &MattermostClient, group_buy: &GroupBuy) {
+    let result = match &group_buy.post_id {
+        Some(post_id) => client.delete_post(post_id).await,
+        None => {
+            client
+                .send_ephemeral_post(
+                    &group_buy.channel_id,
+                    &group_buy.creator_id,
+                    &format!(
+                        "⚠️ 「{}」的團購沒有建立成功，請忽略剛才的團購訊息並重新建立",
+                        group_buy.merchant_name
+                    ),
+                    None,
+                )
+                .await
+        }
+    };
+    if let Err(e) = result {
+        error!("撤銷團購 {} 的貼文失敗: {}", group_buy.id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::utils::{insert_group_buy, make_group_buy, setup_db};
+
+    fn announcement(response_url: &str) -> Announcement<'_> {
+        Announcement {
+            response_url,
+            message: "### 🛒 團購",
+            attachments: &[],
+            username: "creator",
+            icon_url: "https://example.com/icon.png",
+        }
+    }
+
+    fn active_group_buy(id: &str) -> GroupBuy {
+        let mut group_buy = make_group_buy(id.to_string(), 1);
+        group_buy.status = GroupBuyStatus::Active;
+        group_buy
+    }
+
+    #[tokio::test]
+    async fn test_post_failure_saves_nothing() {
+        let mut server = mockito::Server::new_async().await;
+        let post = server
+            .mock("POST", "/api/v4/posts")
+            .with_status(500)
+            .create_async()
+            .await;
+        let client = MattermostClient::new(server.url(), "token".to_string()).unwrap();
+        let db = setup_db().await;
+
+        let mut group_buy = active_group_buy("gb-1");
+        let err = create_group_buy(&client, &db, &mut group_buy, &announcement(""), None, None)
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("發送團購訊息失敗"));
+        assert!(db.get_group_buy("gb-1").await.unwrap().is_none());
+        post.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_database_failure_deletes_post() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/api/v4/posts")
+            .with_status(201)
+            .with_body(r#"{"id":"post-1"}"#)
+            .create_async()
+            .await;
+        let delete = server
+            .mock("DELETE", "/api/v4/posts/post-1")
+            .with_status(200)
+            .with_body(r#"{"status":"OK"}"#)
+            .create_async()
+            .await;
+        let client = MattermostClient::new(server.url(), "token".to_string()).unwrap();
+        let db = setup_db().await;
+        // 相同 ID 已存在，寫入資料庫會失敗
+        let existing = insert_group_buy(&db, 1).await;
+
+        let mut group_buy = active_group_buy(&existing.id);
+        let err = create_group_buy(&client, &db, &mut group_buy, &announcement(""), None, None)
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("儲存團購資料失敗"));
+        delete.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_database_failure_after_response_url_warns_creator() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/hook")
+            .with_status(200)
+            .create_async()
+            .await;
+        let notice = server
+            .mock("POST", "/api/v4/posts/ephemeral")
+            .match_body(mockito::Matcher::Regex("請忽略剛才的團購訊息".to_string()))
+            .with_status(201)
+            .with_body("{}")
+            .create_async()
+            .await;
+        let client = MattermostClient::new(server.url(), "token".to_string()).unwrap();
+        let db = setup_db().await;
+        let existing = insert_group_buy(&db, 1).await;
+
+        let hook = format!("{}/hook", server.url());
+        let mut group_buy = active_group_buy(&existing.id);
+        assert!(
+            create_group_buy(
+                &client,
+                &db,
+                &mut group_buy,
+                &announcement(&hook),
+                None,
+                None
+            )
+            .await
+            .is_err()
+        );
+        notice.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_create_saves_post_id_deadline_and_reminder() {
+        let mut server = mockito::Server::new_async().await;
+        let post = server
+            .mock("POST", "/api/v4/posts")
+            .match_body(mockito::Matcher::Regex(
+                r#""leko_group_buy":\{"group_buy_id":"gb-1""#.to_string(),
+            ))
+            .with_status(201)
+            .with_body(r#"{"id":"post-1"}"#)
+            .create_async()
+            .await;
+        let client = MattermostClient::new(server.url(), "token".to_string()).unwrap();
+        let db = setup_db().await;
+
+        let close_at = Utc::now();
+        let mut group_buy = active_group_buy("gb-1");
+        create_group_buy(
+            &client,
+            &db,
+            &mut group_buy,
+            &announcement(""),
+            Some(close_at),
+            Some(close_at - chrono::Duration::minutes(10)),
+        )
+        .await
+        .unwrap();
+
+        let saved = db.get_group_buy("gb-1").await.unwrap().unwrap();
+        assert_eq!(saved.post_id.as_deref(), Some("post-1"));
+        assert_eq!(
+            db.get_due_deadlines(close_at).await.unwrap(),
+            vec!["gb-1".to_string()]
+        );
+        let reminders = db.get_due_reminders(close_at).await.unwrap();
+        assert_eq!(reminders.len(), 1);
+        assert_eq!(reminders[0].0, "gb-1");
+        post.assert_async().await;
+    }
+}
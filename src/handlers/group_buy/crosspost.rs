@@ -0,0 +1,153 @@
+//! 把團購貼文轉發到其他頻道
+//!
+//! 建立團購時在「同時發到」填其他頻道（`~lunch ~dinner`），bot 在這些頻道各發一則相同的
+//! 團購貼文，按鈕與原本的貼文相同。轉發的貼文記在 `group_buy_posts`，
+//! `sync_group_buy_post` 更新原本的貼文時一併更新（狀態、商品、登記）。
+//! 草稿先記下頻道，按下「發布」時才轉發。
+
+use super::*;
+
+/// 一個團購最多轉發到幾個頻道
+pub const MAX_CROSS_POST_CHANNELS: usize = 5;
+
+/// 解析「同時發到」的頻道名稱：以空白或逗號分隔，可加上 `~`，重複的只留一個
+pub fn parse_channel_names(input: &str) -> std::result::Result<Vec<String>, String> {
+    let mut names: Vec<String> = Vec::new();
+    for name in input
+        .split(|c: char| c.is_whitespace() || matches!(c, ',' | '，' | '、'))
+        .map(|name| name.trim_start_matches('~').to_lowercase())
+        .filter(|name| !name.is_empty())
+    {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    if names.len() > MAX_CROSS_POST_CHANNELS {
+        return Err(format!("最多轉發到 {} 個頻道", MAX_CROSS_POST_CHANNELS));
+    }
+    Ok(names)
+}
+
+/// 把頻道名稱轉成頻道 ID。建立者必須是頻道成員，團購所在的頻道略過。
+/// 錯誤為要顯示在對話框欄位的訊息
+pub async fn resolve_cross_post_channels(
+    state_guard: &AppState,
+    team_id: &str,
+    user_id: &str,
+    origin_channel_id: &str,
+    input: &str,
+) -> std::result::Result<Vec<String>, String> {
+    let client = &state_guard.mattermost_client;
+    let mut channel_ids = Vec::new();
+    for name in parse_channel_names(input)? {
+        let channel = match client.get_channel_by_name(team_id, &name).await {
+            Ok(Some(channel)) => channel,
+            Ok(None) => return Err(format!("找不到頻道 ~{}", name)),
+            Err(e) => {
+                error!("取得頻道 ~{} 失敗: {}", name, e);
+                return Err(format!("無法取得頻道 ~{}", name));
+            }
+        };
+        if channel.id == origin_channel_id {
+            continue;
+        }
+        match client.is_channel_member(&channel.id, user_id).await {
+            Ok(true) => channel_ids.push(channel.id),
+            Ok(false) => return Err(format!("你不在頻道 ~{}，無法轉發到該頻道", name)),
+            Err(e) => {
+                error!("檢查頻道 ~{} 成員失敗: {}", name, e);
+                return Err(format!("無法確認你是否在頻道 ~{}", name));
+            }
+        }
+    }
+    Ok(channel_ids)
+}
+
+/// 在記下的頻道發出還沒發出的轉發貼文，失敗時以臨時訊息告知建立者
+pub async fn publish_cross_posts(state_guard: &AppState, group_buy: &GroupBuy) {
+    let pending: Vec<String> = match state_guard.database.get_cross_posts(&group_buy.id).await {
+        Ok(posts) => posts
+            .into_iter()
+            .filter(|(_, post_id)| post_id.is_none())
+            .map(|(channel_id, _)| channel_id)
+            .collect(),
+        Err(e) => {
+            error!("取得轉發的團購貼文失敗: {}", e);
+            return;
+        }
+    };
+    if pending.is_empty() {
+        return;
+    }
+
+    let (message, mut props) = super::utils::group_buy_post_content(state_guard, group_buy).await;
+    // 與原本的貼文一樣以建立者的身份顯示
+    props["override_username"] = serde_json::json!(group_buy.creator_username);
+    props["override_icon_url"] = serde_json::json!(format!(
+        "{}/api/v4/users/{}/image",
+        state_guard.config.mattermost.url, group_buy.creator_id
+    ));
+
+    let mut failed = 0;
+    for channel_id in pending {
+        let post = crate::mattermost::Post {
+            id: None,
+            channel_id: channel_id.clone(),
+            message: message.clone(),
+            root_id: None,
+            props: Some(props.clone()),
+        };
+        let result = match state_guard
+            .mattermost_client
+            .create_post_with_response(&post)
+            .await
+        {
+            Ok(post_id) => {
+                state_guard
+                    .database
+                    .set_cross_post_id(&group_buy.id, &channel_id, &post_id)
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            error!(
+                "轉發團購 {} 到頻道 {} 失敗: {}",
+                group_buy.id, channel_id, e
+            );
+            failed += 1;
+        }
+    }
+
+    if failed > 0
+        && let Err(e) = state_guard
+            .mattermost_client
+            .send_ephemeral_post(
+                &group_buy.channel_id,
+                &group_buy.creator_id,
+                &format!(
+                    "⚠️ 「{}」有 {} 個頻道轉發失敗，請確認 bot 已加入這些頻道",
+                    group_buy.merchant_name, failed
+                ),
+                None,
+            )
+            .await
+    {
+        error!("發送轉發失敗通知失敗: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_channel_names() {
+        assert_eq!(
+            parse_channel_names(" ~Lunch, dinner ~lunch、town-square ").unwrap(),
+            vec!["lunch", "dinner", "town-square"]
+        );
+        assert!(parse_channel_names("").unwrap().is_empty());
+        assert!(parse_channel_names("a b c d e f").is_err());
+    }
+}
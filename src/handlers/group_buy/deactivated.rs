@@ -0,0 +1,191 @@
+//! 帳號已停用的購買人
+//!
+//! 購買人的 Mattermost 帳號停用（`delete_at` 不為 0）或被刪除後，團購貼文與各種表格仍列出
+//! 他的登記，但名稱改以刪除線加上「已停用」顯示，不再 @-mention。停用的帳號記在
+//! `deactivated_users`，每次產生訊息前由 [`load_deactivated_users`] 讀取後傳入，多實例部署時
+//! 各實例看到相同的狀態；WebSocket 的 `user_updated` 事件與按下「停用帳號」按鈕時更新。
+//! 有停用帳號的購買人時貼文多一個「停用帳號」按鈕，建立者可以把他的登記改到別人名下或刪除。
+
+use super::*;
+use crate::database::Database;
+use crate::mattermost::action_id;
+use crate::signing::StateSigner;
+use serde_json::json;
+use std::collections::{BTreeMap, HashSet};
+
+/// 從資料庫讀取已停用的帳號。讀取失敗時記錄警告並視為沒有停用的帳號，照常 @-mention
+pub async fn load_deactivated_users(database: &Database) -> HashSet<String> {
+    match database.get_deactivated_users().await {
+        Ok(users) => users.into_iter().collect(),
+        Err(e) => {
+            tracing::warn!("讀取已停用的帳號失敗: {}", e);
+            HashSet::new()
+        }
+    }
+}
+
+/// 購買人的顯示名稱：帳號使用中時 @-mention，已停用時以刪除線標示且不 mention
+pub fn buyer_mention(buyer_id: &str, username: &str, deactivated: &HashSet<String>) -> String {
+    if deactivated.contains(buyer_id) {
+        format!("~~{}~~（已停用）", username)
+    } else {
+        format!("@{}", username)
+    }
+}
+
+/// 記錄帳號狀態，有改變時寫入資料庫並更新他有登記的團購貼文
+pub async fn update_user_status(state_guard: &AppState, user_id: &str, deactivated: bool) {
+    match state_guard
+        .database
+        .set_user_deactivated(user_id, deactivated)
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(e) => {
+            error!("記錄帳號狀態失敗: {}", e);
+            return;
+        }
+    }
+    info!(
+        "使用者 {} 的帳號{}",
+        user_id,
+        if deactivated {
+            "已停用"
+        } else {
+            "已恢復"
+        }
+    );
+    match state_guard
+        .database
+        .get_open_group_buy_ids_by_buyer(user_id)
+        .await
+    {
+        Ok(group_buy_ids) => {
+            for group_buy_id in group_buy_ids {
+                super::refresh::request_post_refresh(state_guard, &group_buy_id);
+            }
+        }
+        Err(e) => error!("取得使用者登記的團購失敗: {}", e),
+    }
+}
+
+/// 向 Mattermost 確認每位購買人的帳號狀態，回傳已停用或已刪除的購買人
+/// （buyer_id, 使用者名稱），依名稱排序。查詢失敗的購買人維持原本的狀態
+pub async fn check_buyers(
+    state_guard: &AppState,
+    orders: &[GroupBuyOrder],
+) -> Vec<(String, String)> {
+    let buyers: BTreeMap<&str, &str> = orders
+        .iter()
+        .map(|o| (o.buyer_id.as_str(), o.buyer_username.as_str()))
+        .collect();
+    let known = load_deactivated_users(&state_guard.database).await;
+
+    let mut deactivated = Vec::new();
+    for (buyer_id, username) in buyers {
+        let inactive = match state_guard.mattermost_client.find_user(buyer_id).await {
+            Ok(user) => user.is_none_or(|u| u.is_deactivated()),
+            Err(e) => {
+                tracing::warn!("無法確認購買人 {} 的帳號狀態: {}", buyer_id, e);
+                known.contains(buyer_id)
+            }
+        };
+        update_user_status(state_guard, buyer_id, inactive).await;
+        if inactive {
+            deactivated.push((buyer_id.to_string(), username.to_string()));
+        }
+    }
+    deactivated.sort_by(|a, b| a.1.cmp(&b.1));
+    deactivated
+}
+
+/// 有帳號已停用的購買人時，在按鈕列加上「停用帳號」（草稿與封存後不顯示）
+pub fn add_deactivated_buyers_button_if_needed(
+    attachments: &mut [serde_json::Value],
+    group_buy_id: &str,
+    status: &GroupBuyStatus,
+    orders: &[GroupBuyOrder],
+    deactivated: &HashSet<String>,
+    bot_callback_url: &str,
+    signer: &StateSigner,
+) {
+    if matches!(status, GroupBuyStatus::Draft | GroupBuyStatus::Archived)
+        || !orders.iter().any(|o| deactivated.contains(&o.buyer_id))
+    {
+        return;
+    }
+    let Some(actions) = attachments
+        .first_mut()
+        .and_then(|a| a["actions"].as_array_mut())
+    else {
+        return;
+    };
+    actions.push(json!({
+        "id": action_id("deactivated", group_buy_id),
+        "name": "停用帳號",
+        "type": "button",
+        "integration": {
+            "url": format!("{}/api/v1/group_buy/action/deactivated_buyers", bot_callback_url.trim_end_matches('/')),
+            "context": signer.sign_context(json!({
+                "action": "deactivated_buyers",
+                "group_buy_id": group_buy_id,
+            }), None)
+        }
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::utils::{make_order_for, setup_db};
+
+    #[test]
+    fn test_buyer_mention_and_button() {
+        let signer = StateSigner::new("secret");
+        let orders = vec![make_order_for("gb".to_string(), "left", "left")];
+        let button_count = |status: &GroupBuyStatus, deactivated: &HashSet<String>| {
+            let mut attachments = vec![json!({ "actions": [] })];
+            add_deactivated_buyers_button_if_needed(
+                &mut attachments,
+                "gb",
+                status,
+                &orders,
+                deactivated,
+                "http://bot",
+                &signer,
+            );
+            attachments[0]["actions"].as_array().unwrap().len()
+        };
+
+        let active = HashSet::new();
+        assert_eq!(buyer_mention("left", "bob", &active), "@bob");
+        assert_eq!(button_count(&GroupBuyStatus::Active, &active), 0);
+
+        let deactivated: HashSet<String> = ["left".to_string()].into_iter().collect();
+        assert_eq!(
+            buyer_mention("left", "bob", &deactivated),
+            "~~bob~~（已停用）"
+        );
+        assert_eq!(button_count(&GroupBuyStatus::Active, &deactivated), 1);
+        assert_eq!(button_count(&GroupBuyStatus::Archived, &deactivated), 0);
+    }
+
+    #[tokio::test]
+    async fn test_load_deactivated_users_reads_database() {
+        let db = setup_db().await;
+        assert!(load_deactivated_users(&db).await.is_empty());
+
+        // 其他實例寫入的狀態在下一次產生訊息時就看得到
+        assert!(db.set_user_deactivated("left", true).await.unwrap());
+        let deactivated = load_deactivated_users(&db).await;
+        assert_eq!(
+            buyer_mention("left", "bob", &deactivated),
+            "~~bob~~（已停用）"
+        );
+
+        assert!(db.set_user_deactivated("left", false).await.unwrap());
+        let deactivated = load_deactivated_users(&db).await;
+        assert_eq!(buyer_mention("left", "bob", &deactivated), "@bob");
+    }
+}
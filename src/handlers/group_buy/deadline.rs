@@ -0,0 +1,110 @@
+//! 團購自動截止
+//!
+//! 設定了截止時間（`group_buy_deadlines`）的團購由背景工作定期檢查，
+//! 到期後以建立者身分截止並更新貼文。
+
+use super::*;
+use chrono::Utc;
+
+/// 檢查截止時間的間隔
+const DEADLINE_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// 截止所有已到期的團購，回傳實際截止的數量。
+/// 截止失敗（例如剛好有人在操作造成版本衝突）時保留截止時間，下次檢查再試。
+pub async fn close_due_group_buys(state_guard: &AppState) -> Result<usize> {
+    let due = state_guard.database.get_due_deadlines(Utc::now()).await?;
+    let mut closed = 0;
+
+    for group_buy_id in due {
+        match close_expired(state_guard, &group_buy_id).await {
+            Ok(true) => closed += 1,
+            Ok(false) => {}
+            Err(e) => {
+                error!("自動截止團購 {} 失敗: {}", group_buy_id, e);
+                continue;
+            }
+        }
+        state_guard.database.clear_deadline(&group_buy_id).await?;
+    }
+
+    Ok(closed)
+}
+
+/// 截止單一團購；已不是進行中（手動截止、已下單）時不做任何事並回傳 false
+async fn close_expired(state_guard: &AppState, group_buy_id: &str) -> Result<bool> {
+    let Some(group_buy) = state_guard.database.get_group_buy(group_buy_id).await? else {
+        return Ok(false);
+    };
+    if group_buy.status != GroupBuyStatus::Active {
+        return Ok(false);
+    }
+
+    state_guard
+        .database
+        .update_status(
+            group_buy_id,
+            GroupBuyStatus::Closed,
+            group_buy.version,
+            &group_buy.creator_id,
+            &group_buy.creator_username,
+        )
+        .await?;
+
+    let group_buy = GroupBuy {
+        status: GroupBuyStatus::Closed,
+        version: group_buy.version + 1,
+        ..group_buy
+    };
+    super::utils::sync_group_buy_post(state_guard, &group_buy).await;
+
+    // 頻道的靜音時段只更新貼文，不另外發通知
+    let settings = state_guard
+        .database
+        .get_channel_settings(&group_buy.channel_id)
+        .await
+        .unwrap_or_default();
+    if crate::handlers::settings::is_quiet_at(&settings, chrono::Local::now().time()) {
+        info!(
+            "團購 {} 已到截止時間，自動截止（靜音時段，不發通知）",
+            group_buy_id
+        );
+        return Ok(true);
+    }
+
+    let notice = crate::mattermost::Post {
+        id: None,
+        channel_id: group_buy.channel_id.clone(),
+        message: format!(
+            "⏰ 團購「{}」已到截止時間，自動截止",
+            group_buy.merchant_name
+        ),
+        root_id: state_guard
+            .config
+            .group_buy
+            .reply_root_id(group_buy.post_id.as_deref()),
+        props: None,
+    };
+    if let Err(e) = state_guard.mattermost_client.create_post(&notice).await {
+        error!("發送自動截止通知失敗: {}", e);
+    }
+
+    info!("團購 {} 已到截止時間，自動截止", group_buy_id);
+    Ok(true)
+}
+
+/// 定期截止到期團購的排程工作。啟動後立即執行一次，補上停機期間到期的團購；
+/// 團購功能停用時暫停，重新啟用後的下一次檢查會補上期間到期的團購
+pub fn deadline_closer_job() -> crate::scheduler::Job {
+    crate::scheduler::Job::new(
+        "自動截止",
+        |config| config.features.group_buy.then_some(DEADLINE_CHECK_INTERVAL),
+        |state| async move {
+            let state_guard = state.read().await;
+            match close_due_group_buys(&state_guard).await {
+                Ok(0) => {}
+                Ok(closed) => info!("自動截止了 {} 個團購", closed),
+                Err(e) => error!("檢查團購截止時間失敗: {}", e),
+            }
+        },
+    )
+}
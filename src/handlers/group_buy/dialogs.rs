@@ -1,6 +1,11 @@
+use super::shortage::{
+    SplitMethod, format_changes_preview, format_split_preview, plan_order_changes, split_shortage,
+};
 use super::*;
+use crate::database::ItemList;
+use crate::handlers::preferences::favorite_item_key;
 use chrono::Utc;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 /// Parameters for opening the create dialog.
 pub struct CreateDialogParams<'a> {
@@ -10,6 +15,10 @@ pub struct CreateDialogParams<'a> {
     pub user_id: &'a str,
     pub user_name: &'a str,
     pub bot_callback_url: &'a str,
+    /// 既有商家名稱，顯示為選單
+    pub known_merchants: &'a [String],
+    /// `template use` 時預先填入的範本
+    pub template: Option<&'a crate::database::GroupBuyTemplate>,
 }
 
 // Open create dialog
@@ -17,19 +26,47 @@ pub async fn open_create_dialog(
     client: &MattermostClient,
     params: &CreateDialogParams<'_>,
 ) -> Result<()> {
-    let elements = vec![
+    let template = params.template;
+    let mut elements = Vec::new();
+    if !params.known_merchants.is_empty() {
+        elements.push(DialogElement {
+            display_name: "既有商家".to_string(),
+            name: "merchant_existing".to_string(),
+            element_type: DialogElementType::Select,
+            placeholder: Some("選擇之前團購過的商家".to_string()),
+            help_text: Some("清單中沒有時，請在下方輸入商家名稱".to_string()),
+            optional: true,
+            min_length: None,
+            max_length: None,
+            data_source: None,
+            options: Some(
+                params
+                    .known_merchants
+                    .iter()
+                    .map(|name| DialogOption {
+                        text: name.clone(),
+                        value: name.clone(),
+                    })
+                    .collect(),
+            ),
+            default: None,
+            subtype: None,
+        });
+    }
+    elements.extend([
         DialogElement {
             display_name: "商家名稱".to_string(),
             name: "merchant_name".to_string(),
             element_type: DialogElementType::Text,
             placeholder: Some("例如：五十嵐".to_string()),
             help_text: None,
-            optional: false,
-            min_length: Some(1),
+            // 有既有商家選單時可以只選不填
+            optional: !params.known_merchants.is_empty(),
+            min_length: None,
             max_length: Some(100),
             data_source: None,
             options: None,
-            default: None,
+            default: template.map(|t| t.merchant_name.clone()),
             subtype: None,
         },
         DialogElement {
@@ -43,7 +80,7 @@ pub async fn open_create_dialog(
             max_length: Some(500),
             data_source: None,
             options: None,
-            default: None,
+            default: template.and_then(|t| t.description.clone()),
             subtype: None,
         },
         DialogElement {
@@ -53,16 +90,108 @@ pub async fn open_create_dialog(
             placeholder: Some(
                 "YAML 格式，例如：\n截止時間: 2026-01-25 18:00\n取貨地點: 公司大廳".to_string(),
             ),
-            help_text: Some("使用 YAML 格式填寫 key-value pairs（可選）".to_string()),
+            help_text: Some(
+                "使用 YAML 格式填寫 key-value pairs（可選）；填 `服務費: 5%` 時小計會依比例加上服務費"
+                    .to_string(),
+            ),
             optional: true,
             min_length: None,
             max_length: Some(1000),
             data_source: None,
             options: None,
+            default: template.filter(|t| !t.metadata.is_empty()).and_then(|t| {
+                serde_yaml::to_string(&t.metadata.iter().collect::<BTreeMap<_, _>>()).ok()
+            }),
+            subtype: None,
+        },
+        DialogElement {
+            display_name: "訂單欄位".to_string(),
+            name: "order_fields".to_string(),
+            element_type: DialogElementType::Textarea,
+            placeholder: Some("一行一個欄位，例如：\n內用/外帶: 內用, 外帶\n備註".to_string()),
+            help_text: Some(format!(
+                "登記時要多填的欄位，冒號後為選項，沒有選項則自由填寫（可選，最多 {} 個）",
+                super::order_fields::MAX_ORDER_FIELDS
+            )),
+            optional: true,
+            min_length: None,
+            max_length: Some(500),
+            data_source: None,
+            options: None,
             default: None,
             subtype: None,
         },
-    ];
+        DialogElement {
+            display_name: "截止前提醒".to_string(),
+            name: "reminder".to_string(),
+            element_type: DialogElementType::Text,
+            placeholder: Some("例如：15m".to_string()),
+            help_text: Some(
+                "截止前多久在團購貼文下提醒大家登記，只在有截止時間時生效（可選）".to_string(),
+            ),
+            optional: true,
+            min_length: None,
+            max_length: Some(20),
+            data_source: None,
+            options: None,
+            default: None,
+            subtype: None,
+        },
+        DialogElement {
+            display_name: "同時發到".to_string(),
+            name: "cross_post_channels".to_string(),
+            element_type: DialogElementType::Text,
+            placeholder: Some("例如：~lunch ~dinner".to_string()),
+            help_text: Some(format!(
+                "在其他頻道也發一則團購貼文，與這裡的貼文同步更新（可選，最多 {} 個頻道）",
+                super::crosspost::MAX_CROSS_POST_CHANNELS
+            )),
+            optional: true,
+            min_length: None,
+            max_length: Some(200),
+            data_source: None,
+            options: None,
+            default: None,
+            subtype: None,
+        },
+        DialogElement {
+            display_name: "先存為草稿".to_string(),
+            name: "draft".to_string(),
+            element_type: DialogElementType::Bool,
+            placeholder: Some("只有自己看得到，填好商品後再發布".to_string()),
+            help_text: None,
+            optional: true,
+            min_length: None,
+            max_length: None,
+            data_source: None,
+            options: None,
+            default: None,
+            subtype: None,
+        },
+    ]);
+
+    // 建立對話框平常沒有商品欄位（建立後再編輯商品），使用範本時直接帶入範本的商品
+    if let Some(template) = template {
+        elements.push(DialogElement {
+            display_name: "商品列表".to_string(),
+            name: "items".to_string(),
+            element_type: DialogElementType::Textarea,
+            placeholder: Some("商品名稱: 價格".to_string()),
+            help_text: Some(format!("範本「{}」的商品，可以直接修改", template.name)),
+            optional: true,
+            min_length: None,
+            max_length: Some(3000),
+            data_source: None,
+            options: None,
+            default: Some(items_to_yaml(
+                &template.items,
+                &template.item_icons,
+                &template.item_sections,
+                &template.item_translations,
+            )),
+            subtype: None,
+        });
+    }
 
     let state = serde_json::json!({
         "response_url": params.response_url,
@@ -121,8 +250,15 @@ pub async fn handle_create_dialog(
         ));
     }
 
-    let state_data = serde_json::from_str(submission.state.as_deref().unwrap_or("{}"))
-        .unwrap_or_else(|_| serde_json::json!({}));
+    let state_data =
+        match super::utils::extract_state_value(&state.read().await.mattermost_client, &submission)
+        {
+            Ok(v) => v,
+            Err(e) => {
+                error!("{}", e);
+                return Ok(super::utils::invalid_dialog_response());
+            }
+        };
 
     let response_url = state_data
         .get("response_url")
@@ -144,24 +280,31 @@ pub async fn handle_create_dialog(
         .and_then(|v| v.as_str())
         .unwrap_or("");
 
-    if response_url.is_empty() {
-        error!("response_url 為空");
+    // 選單優先，其次是輸入的名稱
+    let merchant_input = ["merchant_existing", "merchant_name"]
+        .iter()
+        .filter_map(|field| submission.submission.get(*field).and_then(|v| v.as_str()))
+        .map(str::trim)
+        .find(|name| !name.is_empty())
+        .unwrap_or("")
+        .to_string();
+    if merchant_input.is_empty() {
         return Ok(warp::reply::with_status(
             warp::reply::json(&DialogSubmissionResponse {
-                error: Some("內部錯誤：缺少 response_url".to_string()),
+                error: None,
                 text: None,
-                errors: None,
+                errors: Some(
+                    [(
+                        "merchant_name".to_string(),
+                        "請選擇既有商家或輸入商家名稱".to_string(),
+                    )]
+                    .into_iter()
+                    .collect(),
+                ),
             }),
             StatusCode::OK,
         ));
     }
-
-    let merchant_name = submission
-        .submission
-        .get("merchant_name")
-        .and_then(|v| v.as_str())
-        .unwrap_or("")
-        .to_string();
     let description = submission
         .submission
         .get("description")
@@ -173,7 +316,7 @@ pub async fn handle_create_dialog(
         .and_then(|v| v.as_str())
         .map(|s| s.to_string());
 
-    let metadata: HashMap<String, String> = if let Some(yaml_str) = metadata_yaml {
+    let mut metadata: HashMap<String, String> = if let Some(yaml_str) = metadata_yaml {
         if !yaml_str.trim().is_empty() {
             match serde_yaml::from_str(&yaml_str) {
                 Ok(data) => data,
@@ -199,8 +342,166 @@ pub async fn handle_create_dialog(
         HashMap::new()
     };
 
+    // 服務費比例會用在小計與採購列表，建立時就要能解析
+    if let Some(Err(e)) = metadata
+        .get(super::messages::SERVICE_FEE_KEY)
+        .map(|value| super::messages::parse_service_fee(value))
+    {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&DialogSubmissionResponse {
+                error: None,
+                text: None,
+                errors: Some([("metadata".to_string(), e)].into_iter().collect()),
+            }),
+            StatusCode::OK,
+        ));
+    }
+
+    let order_fields = match super::order_fields::parse_order_fields(
+        submission
+            .submission
+            .get("order_fields")
+            .and_then(|v| v.as_str())
+            .unwrap_or(""),
+    ) {
+        Ok(fields) => fields,
+        Err(e) => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&DialogSubmissionResponse {
+                    error: None,
+                    text: None,
+                    errors: Some([("order_fields".to_string(), e)].into_iter().collect()),
+                }),
+                StatusCode::OK,
+            ));
+        }
+    };
+
+    // 只有以範本開啟的對話框有商品欄位
+    let item_list = match submission
+        .submission
+        .get("items")
+        .and_then(|v| v.as_str())
+        .filter(|yaml| !yaml.trim().is_empty())
+        .map(parse_items_yaml)
+        .transpose()
+    {
+        Ok(item_list) => item_list.unwrap_or_default(),
+        Err(e) => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&DialogSubmissionResponse {
+                    error: None,
+                    text: None,
+                    errors: Some(
+                        [("items".to_string(), format!("格式錯誤: {}", e))]
+                            .into_iter()
+                            .collect(),
+                    ),
+                }),
+                StatusCode::OK,
+            ));
+        }
+    };
+
+    // Bool 欄位可能以 true 或 "true" 送出
+    let is_draft = submission
+        .submission
+        .get("draft")
+        .is_some_and(|v| v.as_bool() == Some(true) || v.as_str() == Some("true"));
+    let initial_status = if is_draft {
+        GroupBuyStatus::Draft
+    } else {
+        GroupBuyStatus::Active
+    };
+
     let state_guard = state.read().await;
 
+    // 與既有商家寫法不同但實際相同時（「五十嵐」與「50嵐」），沿用既有名稱
+    let known_merchants = state_guard
+        .database
+        .get_merchant_names(i64::MAX)
+        .await
+        .unwrap_or_else(|e| {
+            error!("取得既有商家名稱失敗: {}", e);
+            Vec::new()
+        });
+    let merchant_name = super::merchant::canonical_merchant_name(&merchant_input, &known_merchants);
+
+    let settings = state_guard
+        .database
+        .get_channel_settings(channel_id)
+        .await
+        .unwrap_or_else(|e| {
+            error!("取得頻道設定失敗: {}", e);
+            Default::default()
+        });
+    // 頻道有預設截止時間、且沒有自己填截止時間時，直接發布的團購時間到自動截止
+    let close_at = settings
+        .default_deadline_minutes
+        .filter(|_| !is_draft && !metadata.contains_key("截止時間"))
+        .and_then(chrono::Duration::try_minutes)
+        .and_then(|duration| Utc::now().checked_add_signed(duration));
+    if let Some(close_at) = close_at {
+        metadata.insert(
+            "截止時間".to_string(),
+            close_at
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string(),
+        );
+    }
+
+    let remind_at = match super::reminder::parse_reminder(
+        submission
+            .submission
+            .get("reminder")
+            .and_then(|v| v.as_str())
+            .unwrap_or(""),
+        close_at,
+    ) {
+        Ok(remind_at) => remind_at,
+        Err(e) => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&DialogSubmissionResponse {
+                    error: None,
+                    text: None,
+                    errors: Some([("reminder".to_string(), e)].into_iter().collect()),
+                }),
+                StatusCode::OK,
+            ));
+        }
+    };
+
+    let cross_post_channels = match super::crosspost::resolve_cross_post_channels(
+        &state_guard,
+        &submission.team_id,
+        &submission.user_id,
+        channel_id,
+        submission
+            .submission
+            .get("cross_post_channels")
+            .and_then(|v| v.as_str())
+            .unwrap_or(""),
+    )
+    .await
+    {
+        Ok(channel_ids) => channel_ids,
+        Err(e) => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&DialogSubmissionResponse {
+                    error: None,
+                    text: None,
+                    errors: Some(
+                        [("cross_post_channels".to_string(), e)]
+                            .into_iter()
+                            .collect(),
+                    ),
+                }),
+                StatusCode::OK,
+            ));
+        }
+    };
+
     let group_buy_id = uuid::Uuid::new_v4().to_string();
 
     let user = match state_guard
@@ -213,7 +514,7 @@ pub async fn handle_create_dialog(
             error!("取得用戶資訊失敗: {}", e);
             return Ok(warp::reply::with_status(
                 warp::reply::json(&DialogSubmissionResponse {
-                    error: Some("無法取得用戶資訊".to_string()),
+                    error: Some(ErrorCode::MattermostError.user_message("無法取得用戶資訊")),
                     text: None,
                     errors: None,
                 }),
@@ -228,90 +529,74 @@ pub async fn handle_create_dialog(
         &merchant_name,
         &description,
         &metadata,
-        &GroupBuyStatus::Active,
-        &HashMap::new(),
+        &initial_status,
+        &item_list.items,
+        &item_list.item_icons,
+        &item_list.item_sections,
+        settings.currency(),
+    );
+    let attachments = generate_action_buttons(
+        &group_buy_id,
+        &initial_status,
+        &bot_callback_url,
+        state_guard.mattermost_client.signer(),
+        &state_guard.config.group_buy.buttons,
     );
-    let attachments =
-        generate_action_buttons(&group_buy_id, &GroupBuyStatus::Active, &bot_callback_url);
 
     let mattermost_url = &state_guard.config.mattermost.url;
     let icon_url = format!("{}/api/v4/users/{}/image", mattermost_url, user_id);
 
-    let response_payload = serde_json::json!({
-        "response_type": "in_channel",
-        "text": message,
-        "attachments": attachments,
-        "username": user_name,
-        "icon_url": icon_url
-    });
-
-    let client = reqwest::Client::new();
-    let response = client
-        .post(response_url)
-        .json(&response_payload)
-        .send()
-        .await;
-
-    if let Err(e) = response {
-        error!("發送到 response_url 失敗: {}", e);
-        return Ok(warp::reply::with_status(
-            warp::reply::json(&DialogSubmissionResponse {
-                error: Some(format!("建立團購訊息失敗: {}", e)),
-                text: None,
-                errors: None,
-            }),
-            StatusCode::OK,
-        ));
-    }
-
-    let response = response.unwrap();
-    let status_code = response.status();
-    let response_text = response.text().await.unwrap_or_default();
-
-    if !status_code.is_success() {
-        error!(
-            "發送到 response_url 失敗，狀態碼: {}, 回應: {}",
-            status_code, response_text
-        );
-        return Ok(warp::reply::with_status(
-            warp::reply::json(&DialogSubmissionResponse {
-                error: Some(format!("建立團購訊息失敗: HTTP {}", status_code)),
-                text: None,
-                errors: None,
-            }),
-            StatusCode::OK,
-        ));
-    }
-
-    let post_id = None;
+    // 草稿只回覆給建立者，按下「發布」後才會在頻道建立貼文
+    let announcement = super::creation::Announcement {
+        response_url,
+        message: &message,
+        attachments: &attachments,
+        username: user_name,
+        icon_url: &icon_url,
+    };
 
     let now = Utc::now();
-    let group_buy = GroupBuy {
+    let mut group_buy = GroupBuy {
         id: group_buy_id.clone(),
         creator_id: submission.user_id.clone(),
         creator_username: user.username.clone(),
         channel_id: channel_id.to_string(),
-        post_id,
+        post_id: None,
         merchant_name: merchant_name.clone(),
         description: description.filter(|s| !s.is_empty()),
         metadata,
-        items: HashMap::new(),
-        status: GroupBuyStatus::Active,
+        items: item_list.items,
+        item_icons: item_list.item_icons,
+        item_sections: item_list.item_sections,
+        item_translations: item_list.item_translations,
+        order_fields,
+        currency: settings.currency().to_string(),
+        status: initial_status,
         version: 1,
         created_at: now,
         updated_at: now,
     };
 
-    if let Err(e) = state_guard.database.create_group_buy(&group_buy).await {
-        error!("儲存團購到資料庫失敗: {}", e);
-        return Ok(warp::reply::with_status(
-            warp::reply::json(&DialogSubmissionResponse {
-                error: Some(format!("儲存團購資料失敗: {}", e)),
-                text: None,
-                errors: None,
-            }),
-            StatusCode::OK,
-        ));
+    if let Err(e) = super::creation::create_group_buy(
+        &state_guard.mattermost_client,
+        &state_guard.database,
+        &mut group_buy,
+        &announcement,
+        close_at,
+        remind_at,
+    )
+    .await
+    {
+        error!("建立團購失敗: {:#}", e);
+        return Ok(super::retry::dialog_error(
+            &state_guard,
+            "create",
+            &submission,
+            &state_data,
+            ErrorCode::of(&e),
+            format!("{:#}", e),
+        )
+        .await);
     }
 
     info!(
@@ -319,6 +604,21 @@ pub async fn handle_create_dialog(
         user.username, merchant_name, group_buy_id
     );
 
+    // 草稿只記下頻道，發布時才轉發
+    if !cross_post_channels.is_empty() {
+        match state_guard
+            .database
+            .add_cross_post_channels(&group_buy_id, &cross_post_channels)
+            .await
+        {
+            Ok(()) if !is_draft => {
+                super::crosspost::publish_cross_posts(&state_guard, &group_buy).await
+            }
+            Ok(()) => {}
+            Err(e) => error!("記下轉發頻道失敗: {}", e),
+        }
+    }
+
     Ok(warp::reply::with_status(
         warp::reply::json(&DialogSubmissionResponse {
             error: None,
@@ -330,23 +630,85 @@ pub async fn handle_create_dialog(
 }
 
 // helpers: items_to_yaml & parse_items_yaml
-pub fn items_to_yaml(items: &HashMap<String, Decimal>) -> String {
+// 每行格式：`商品名稱: 價格`，可選擇在價格後以 `|` 加上 emoji 或縮圖網址。
+// 商品名稱可以 `|` 附上其他語言的名稱（`珍珠奶茶|Bubble tea: 50`），
+// 第一個名稱為儲存用的商品名稱，翻譯依序對應 `group_buy.item_locales`。
+// 沒有價格的 `分類:` 開始一個分類，其下縮排的商品屬於該分類：
+//
+//   飲料:
+//     珍珠奶茶: 50 | 🧋
+//   炸物:
+//     雞排: 80
+
+pub fn items_to_yaml(
+    items: &HashMap<String, Decimal>,
+    item_icons: &HashMap<String, String>,
+    item_sections: &HashMap<String, String>,
+    item_translations: &HashMap<String, Vec<String>>,
+) -> String {
     if items.len() == 1 && items.contains_key("範例商品") {
         return "# 範例商品: 10\n".to_string();
     }
 
     let mut yaml = String::new();
-    for (name, price) in items {
-        yaml.push_str(&format!("{}: {}\n", name, price));
+    let grouped =
+        super::messages::group_by_section(items.keys().map(String::as_str), item_sections);
+    for (section, names) in grouped {
+        let indent = match section {
+            Some(section) => {
+                yaml.push_str(&format!("{}:\n", section));
+                "  "
+            }
+            None => "",
+        };
+        for name in names {
+            let price = &items[name];
+            let label = match item_translations.get(name) {
+                Some(translations) if !translations.is_empty() => {
+                    format!("{}|{}", name, translations.join("|"))
+                }
+                _ => name.to_string(),
+            };
+            match item_icons.get(name) {
+                Some(icon) => {
+                    yaml.push_str(&format!("{}{}: {} | {}\n", indent, label, price, icon))
+                }
+                None => yaml.push_str(&format!("{}{}: {}\n", indent, label, price)),
+            }
+        }
     }
     yaml
 }
 
-pub fn parse_items_yaml(yaml: &str) -> Result<HashMap<String, Decimal>> {
-    let mut items = HashMap::new();
+/// 取得之前團購的商品與圖示，只允許同商家的團購
+async fn load_previous_menu(
+    state: &Arc<RwLock<AppState>>,
+    group_buy_id: &str,
+    previous_id: &str,
+) -> std::result::Result<ItemList, String> {
+    let state_guard = state.read().await;
+    let current = super::utils::fetch_group_buy(&state_guard, group_buy_id).await?;
+    let previous = super::utils::fetch_group_buy(&state_guard, previous_id).await?;
+
+    if previous.merchant_name != current.merchant_name {
+        return Err("只能載入同商家的菜單".to_string());
+    }
+    Ok(ItemList {
+        items: previous.items,
+        item_icons: previous.item_icons,
+        item_sections: previous.item_sections,
+        item_translations: previous.item_translations,
+    })
+}
+
+/// 解析商品列表（格式見 [`items_to_yaml`] 上方說明）
+pub fn parse_items_yaml(yaml: &str) -> Result<ItemList> {
+    let mut list = ItemList::default();
+    // 目前的分類與該分類下的商品數
+    let mut section: Option<(String, usize)> = None;
 
-    for line in yaml.lines() {
-        let line = line.trim();
+    for raw_line in yaml.lines() {
+        let line = raw_line.trim();
         if line.is_empty() || line.starts_with('#') {
             continue;
         }
@@ -357,12 +719,28 @@ pub fn parse_items_yaml(yaml: &str) -> Result<HashMap<String, Decimal>> {
         }
 
         let name = parts[0].trim();
-        let price_str = parts[1].trim();
+        let (price_str, icon) = match parts[1].split_once('|') {
+            Some((price, icon)) => (price.trim(), Some(icon.trim())),
+            None => (parts[1].trim(), None),
+        };
 
         if name.is_empty() {
             anyhow::bail!("商品名稱不能為空");
         }
 
+        // 沒有縮排的行結束目前的分類；沒有價格的則開始新分類
+        let indented = raw_line.starts_with(char::is_whitespace);
+        if !indented {
+            if let Some((previous, 0)) = &section {
+                anyhow::bail!("分類「{}」沒有商品，分類下的商品需要縮排", previous);
+            }
+            section = None;
+            if price_str.is_empty() && icon.is_none() {
+                section = Some((name.to_string(), 0));
+                continue;
+            }
+        }
+
         let price = Decimal::from_str(price_str)
             .map_err(|_| anyhow::anyhow!("價格格式錯誤：{}", price_str))?;
 
@@ -370,10 +748,136 @@ pub fn parse_items_yaml(yaml: &str) -> Result<HashMap<String, Decimal>> {
             anyhow::bail!("價格不能為負數");
         }
 
-        items.insert(name.to_string(), price);
+        // 第一個名稱為商品名稱，其餘為翻譯
+        let mut names = name.split('|').map(str::trim);
+        let name = names.next().unwrap_or_default();
+        if name.is_empty() {
+            anyhow::bail!("商品名稱不能為空");
+        }
+        let translations: Vec<String> = names.map(str::to_string).collect();
+        if translations.iter().any(String::is_empty) {
+            anyhow::bail!("「{}」的翻譯不能為空", name);
+        }
+        if !translations.is_empty() {
+            list.item_translations
+                .insert(name.to_string(), translations);
+        }
+
+        if let Some(icon) = icon.filter(|i| !i.is_empty()) {
+            list.item_icons.insert(name.to_string(), icon.to_string());
+        }
+        if let Some((section_name, count)) = &mut section {
+            list.item_sections
+                .insert(name.to_string(), section_name.clone());
+            *count += 1;
+        }
+        list.items.insert(name.to_string(), price);
+    }
+
+    if let Some((previous, 0)) = &section {
+        anyhow::bail!("分類「{}」沒有商品，分類下的商品需要縮排", previous);
+    }
+
+    Ok(list)
+}
+
+/// 編輯商品對話框最多的文字框數
+const MAX_ITEM_TEXTAREAS: usize = 5;
+
+/// 第 n 個商品文字框的欄位名稱，第一個沿用 `items`
+fn items_field_name(index: usize) -> String {
+    if index == 0 {
+        "items".to_string()
+    } else {
+        format!("items_{}", index + 1)
+    }
+}
+
+/// Mattermost 以 JavaScript 字串長度（UTF-16）計算欄位長度
+fn dialog_text_len(s: &str) -> usize {
+    s.encode_utf16().count()
+}
+
+/// 將商品列表依行切成不超過 `max_len` 字的區塊，同一行不會被拆開
+pub fn split_items_yaml(yaml: &str, max_len: usize) -> Result<Vec<String>> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+
+    for line in yaml.lines() {
+        let line_len = dialog_text_len(line) + 1;
+        if line_len > max_len {
+            anyhow::bail!("「{}」超過文字框的長度上限 {} 字", line, max_len);
+        }
+        if dialog_text_len(&current) + line_len > max_len {
+            chunks.push(std::mem::take(&mut current));
+        }
+        current.push_str(line);
+        current.push('\n');
+    }
+
+    if !current.is_empty() || chunks.is_empty() {
+        chunks.push(current);
+    }
+    Ok(chunks)
+}
+
+/// 商品列表的文字框。列表超過一個文字框的長度時拆成多個，
+/// 最後一個區塊超過一半長度時再多留一個空白文字框供追加商品
+pub fn item_textarea_elements(items_yaml: &str, max_len: usize) -> Result<Vec<DialogElement>> {
+    let chunks = split_items_yaml(items_yaml, max_len)?;
+    if chunks.len() > MAX_ITEM_TEXTAREAS {
+        anyhow::bail!(
+            "商品列表太長，超過 {} 個文字框（每個 {} 字）可容納的長度",
+            MAX_ITEM_TEXTAREAS,
+            max_len
+        );
     }
 
-    Ok(items)
+    let needs_spare = chunks
+        .last()
+        .is_some_and(|last| dialog_text_len(last) > max_len / 2);
+    let field_count = (chunks.len() + usize::from(needs_spare)).min(MAX_ITEM_TEXTAREAS);
+
+    Ok((0..field_count)
+        .map(|index| {
+            let first = index == 0;
+            DialogElement {
+                display_name: if first {
+                    "商品列表 (YAML 格式)".to_string()
+                } else {
+                    format!("商品列表（續 {}）", index)
+                },
+                name: items_field_name(index),
+                element_type: DialogElementType::Textarea,
+                subtype: None,
+                placeholder: first
+                    .then(|| "商品名稱: 價格\n例：\n珍珠奶茶: 50 | 🧋\n紅茶拿鐵: 45".to_string()),
+                help_text: Some(if first {
+                    "每行一個商品，格式：商品名稱: 價格，可在價格後加上 `| emoji` 或 `| 圖片網址`。\
+                     只寫 `分類:` 的行開始一個分類，其下的商品需縮排。\
+                     商品名稱可用 `|` 附上翻譯，例如 `珍珠奶茶|Bubble tea: 50`"
+                        .to_string()
+                } else {
+                    "列表較長時接續在這裡，格式同上".to_string()
+                }),
+                default: chunks.get(index).cloned(),
+                optional: !first,
+                min_length: None,
+                max_length: Some(max_len),
+                data_source: None,
+                options: None,
+            }
+        })
+        .collect())
+}
+
+/// 依序合併所有商品文字框的內容
+fn collect_items_text(submission: &HashMap<String, serde_json::Value>) -> String {
+    (0..MAX_ITEM_TEXTAREAS)
+        .filter_map(|index| submission.get(&items_field_name(index)))
+        .filter_map(|v| v.as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 // Open edit items dialog
@@ -381,20 +885,24 @@ pub async fn open_edit_items_dialog(
     client: &MattermostClient,
     params: &EditItemsDialogParams<'_>,
 ) -> Result<()> {
-    let elements = vec![DialogElement {
-        display_name: "商品列表 (YAML 格式)".to_string(),
-        name: "items".to_string(),
-        element_type: DialogElementType::Textarea,
-        subtype: None,
-        placeholder: Some("商品名稱: 價格\n例：\n珍珠奶茶: 50\n紅茶拿鐵: 45".to_string()),
-        help_text: Some("每行一個商品，格式：商品名稱: 價格".to_string()),
-        default: Some(params.items_yaml.to_string()),
-        optional: false,
-        min_length: None,
-        max_length: Some(3000),
-        data_source: None,
-        options: None,
-    }];
+    let mut elements = item_textarea_elements(params.items_yaml, params.textarea_length)?;
+
+    if !params.previous_menus.is_empty() {
+        elements.push(DialogElement {
+            display_name: "載入之前的菜單".to_string(),
+            name: "load_previous".to_string(),
+            element_type: DialogElementType::Select,
+            subtype: None,
+            placeholder: Some("選擇同商家之前的團購".to_string()),
+            help_text: Some("選擇後會以該次團購的商品取代上方的列表".to_string()),
+            default: None,
+            optional: true,
+            min_length: None,
+            max_length: None,
+            data_source: None,
+            options: Some(params.previous_menus.clone()),
+        });
+    }
 
     let state = serde_json::json!({
         "group_buy_id": params.group_buy_id,
@@ -431,6 +939,25 @@ pub struct EditItemsDialogParams<'a> {
     pub version: i32,
     pub post_id: Option<&'a str>,
     pub bot_callback_url: &'a str,
+    /// 同商家之前的團購，顯示為「載入之前的菜單」選單
+    pub previous_menus: Vec<DialogOption>,
+    /// 每個商品文字框的長度上限（`group_buy.items_textarea_length`）
+    pub textarea_length: usize,
+}
+
+/// 「載入之前的菜單」的選項：以團購 ID 為值，顯示建立日期與商品數
+pub fn previous_menu_options(group_buys: &[GroupBuy]) -> Vec<DialogOption> {
+    group_buys
+        .iter()
+        .map(|gb| DialogOption {
+            text: format!(
+                "{} 的菜單（{} 項）",
+                gb.created_at.format("%Y-%m-%d"),
+                gb.items.len()
+            ),
+            value: gb.id.clone(),
+        })
+        .collect()
 }
 
 // Handle edit items submission
@@ -448,13 +975,15 @@ pub async fn handle_edit_items_dialog(
         }
     };
 
-    let state_data = match super::utils::extract_state_value(&submission) {
-        Ok(v) => v,
-        Err(e) => {
-            error!("{}", e);
-            return Err(warp::reject::reject());
-        }
-    };
+    let state_data =
+        match super::utils::extract_state_value(&state.read().await.mattermost_client, &submission)
+        {
+            Ok(v) => v,
+            Err(e) => {
+                error!("{}", e);
+                return Ok(super::utils::invalid_dialog_response());
+            }
+        };
 
     let group_buy_id = state_data
         .get("group_buy_id")
@@ -478,14 +1007,39 @@ pub async fn handle_edit_items_dialog(
         .and_then(|v| v.as_str())
         .map(|s| s.to_string());
 
-    let items_yaml = submission
+    let items_yaml = collect_items_text(&submission.submission);
+
+    let load_previous = submission
         .submission
-        .get("items")
+        .get("load_previous")
         .and_then(|v| v.as_str())
-        .unwrap_or("");
+        .filter(|v| !v.is_empty());
 
-    let items = match parse_items_yaml(items_yaml) {
-        Ok(items) => items,
+    // 選了之前的菜單時，以該次團購的商品取代輸入的列表
+    let previous_menu = match load_previous {
+        Some(previous_id) => match load_previous_menu(&state, &group_buy_id, previous_id).await {
+            Ok(menu) => Some(menu),
+            Err(msg) => {
+                return Ok(warp::reply::with_status(
+                    warp::reply::json(&DialogSubmissionResponse {
+                        error: None,
+                        text: None,
+                        errors: Some([("load_previous".to_string(), msg)].into_iter().collect()),
+                    }),
+                    StatusCode::OK,
+                ));
+            }
+        },
+        None => None,
+    };
+
+    let parsed = match previous_menu {
+        Some(menu) => Ok(menu),
+        None => parse_items_yaml(&items_yaml),
+    };
+
+    let menu = match parsed {
+        Ok(parsed) => parsed,
         Err(e) => {
             return Ok(warp::reply::with_status(
                 warp::reply::json(&DialogSubmissionResponse {
@@ -502,7 +1056,7 @@ pub async fn handle_edit_items_dialog(
         }
     };
 
-    if items.is_empty() {
+    if menu.items.is_empty() {
         return Ok(warp::reply::with_status(
             warp::reply::json(&DialogSubmissionResponse {
                 error: None,
@@ -519,6 +1073,25 @@ pub async fn handle_edit_items_dialog(
 
     let state_guard = state.read().await;
 
+    let max_items = state_guard.config.group_buy.max_items;
+    if menu.items.len() > max_items {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&DialogSubmissionResponse {
+                error: None,
+                text: None,
+                errors: Some(
+                    [(
+                        "items".to_string(),
+                        format!("共 {} 個商品，超過上限 {} 個", menu.items.len(), max_items),
+                    )]
+                    .into_iter()
+                    .collect(),
+                ),
+            }),
+            StatusCode::OK,
+        ));
+    }
+
     let user = match state_guard
         .mattermost_client
         .get_user(&submission.user_id)
@@ -529,7 +1102,7 @@ pub async fn handle_edit_items_dialog(
             error!("取得用戶資訊失敗: {}", e);
             return Ok(warp::reply::with_status(
                 warp::reply::json(&DialogSubmissionResponse {
-                    error: Some("無法取得用戶資訊".to_string()),
+                    error: Some(ErrorCode::MattermostError.user_message("無法取得用戶資訊")),
                     text: None,
                     errors: None,
                 }),
@@ -542,7 +1115,7 @@ pub async fn handle_edit_items_dialog(
         .database
         .update_items(
             &group_buy_id,
-            &items,
+            &menu,
             version,
             &submission.user_id,
             &user.username,
@@ -550,14 +1123,15 @@ pub async fn handle_edit_items_dialog(
         .await
     {
         error!("更新商品列表失敗: {}", e);
-        return Ok(warp::reply::with_status(
-            warp::reply::json(&DialogSubmissionResponse {
-                error: Some(format!("更新失敗: {}", e)),
-                text: None,
-                errors: None,
-            }),
-            StatusCode::OK,
-        ));
+        return Ok(super::retry::dialog_error(
+            &state_guard,
+            "edit_items",
+            &submission,
+            &state_data,
+            ErrorCode::of(&e),
+            format!("更新失敗: {}", e),
+        )
+        .await);
     }
 
     let group_buy = match state_guard.database.get_group_buy(&group_buy_id).await {
@@ -566,7 +1140,7 @@ pub async fn handle_edit_items_dialog(
             error!("更新後找不到團購資料");
             return Ok(warp::reply::with_status(
                 warp::reply::json(&DialogSubmissionResponse {
-                    error: Some("內部錯誤：找不到團購資料".to_string()),
+                    error: Some(ErrorCode::GbNotFound.user_message("內部錯誤：找不到團購資料")),
                     text: None,
                     errors: None,
                 }),
@@ -577,7 +1151,7 @@ pub async fn handle_edit_items_dialog(
             error!("取得團購資料失敗: {}", e);
             return Ok(warp::reply::with_status(
                 warp::reply::json(&DialogSubmissionResponse {
-                    error: Some("內部錯誤".to_string()),
+                    error: Some(ErrorCode::DatabaseError.user_message("內部錯誤")),
                     text: None,
                     errors: None,
                 }),
@@ -588,27 +1162,68 @@ pub async fn handle_edit_items_dialog(
 
     info!("成功更新團購 {} 的商品列表", group_buy_id);
 
-    let mut items_list = String::new();
-    items_list.push_str("### ✅ 商品列表更新成功\n\n");
-    items_list.push_str("| 商品 | 價格 |\n");
-    items_list.push_str("|------|-----:|\n");
+    // 草稿尚未公開：以臨時訊息回覆更新後的預覽，不在頻道發公開回覆
+    if group_buy.status == GroupBuyStatus::Draft {
+        let bot_callback_url = super::utils::bot_callback_url_from_state(&state_guard);
+        let message = generate_group_buy_message(
+            &group_buy.merchant_name,
+            &group_buy.description,
+            &group_buy.metadata,
+            &group_buy.status,
+            &group_buy.items,
+            &group_buy.item_icons,
+            &group_buy.item_sections,
+            &group_buy.currency,
+        );
+        let attachments = generate_action_buttons(
+            &group_buy_id,
+            &group_buy.status,
+            &bot_callback_url,
+            state_guard.mattermost_client.signer(),
+            &state_guard.config.group_buy.buttons,
+        );
+        if let Err(e) = state_guard
+            .mattermost_client
+            .send_ephemeral_post_with_props(
+                &submission.channel_id,
+                &submission.user_id,
+                &message,
+                serde_json::json!({ "attachments": attachments }),
+            )
+            .await
+        {
+            error!("發送草稿預覽失敗: {}", e);
+        }
 
-    let mut sorted_items: Vec<_> = group_buy.items.iter().collect();
-    sorted_items.sort_by_key(|(name, _)| *name);
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&DialogSubmissionResponse {
+                error: None,
+                text: None,
+                errors: None,
+            }),
+            StatusCode::OK,
+        ));
+    }
 
-    for (name, price) in sorted_items {
-        items_list.push_str(&format!("| {} | ${} |\n", name, price));
+    // 以更新後的商品重新產生團購貼文，讓頻道上的菜單與資料庫一致
+    let mut group_buy = group_buy;
+    if group_buy.post_id.is_none() {
+        group_buy.post_id = post_id.clone();
     }
+    super::utils::sync_group_buy_post(&state_guard, &group_buy).await;
 
     let channel_id = submission.channel_id.clone();
     let user_username = user.username.clone();
-    let post_id_clone = post_id.clone();
+    let root_id = state_guard
+        .config
+        .group_buy
+        .reply_root_id(group_buy.post_id.as_deref());
     let client = state_guard.mattermost_client.clone();
 
     info!("準備發送公開回覆（tag user）:");
     info!("  channel_id: {}", channel_id);
     info!("  user: {}", user_username);
-    info!("  post_id (root_id): {:?}", post_id_clone);
+    info!("  root_id: {:?}", root_id);
 
     tokio::spawn(async move {
         tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
@@ -618,7 +1233,7 @@ pub async fn handle_edit_items_dialog(
             id: None,
             channel_id: channel_id.clone(),
             message,
-            root_id: post_id_clone.as_deref().map(|s: &str| s.to_string()),
+            root_id,
             props: None,
         };
 
@@ -645,20 +1260,25 @@ pub async fn open_cancel_register_dialog(
     client: &MattermostClient,
     params: &CancelRegisterDialogParams,
 ) -> Result<()> {
-    let elements = vec![DialogElement {
-        display_name: "被登記人 (要取消的人)".to_string(),
-        name: "target_buyer".to_string(),
-        element_type: DialogElementType::Select,
-        placeholder: Some("選擇被登記人".to_string()),
-        help_text: Some("將會清除該用戶的所有登記".to_string()),
-        optional: false,
-        min_length: None,
-        max_length: None,
-        data_source: None,
-        options: Some(params.buyer_options.clone()),
-        default: None,
-        subtype: None,
-    }];
+    // 每位被登記人一個勾選框，可以一次清除多位沒來的人
+    let elements: Vec<DialogElement> = params
+        .buyer_options
+        .iter()
+        .map(|option| DialogElement {
+            display_name: option.text.clone(),
+            name: format!("{}{}", CANCEL_BUYER_PREFIX, option.value),
+            element_type: DialogElementType::Bool,
+            placeholder: Some("清除此人的所有登記".to_string()),
+            help_text: None,
+            optional: true,
+            min_length: None,
+            max_length: None,
+            data_source: None,
+            options: None,
+            default: None,
+            subtype: None,
+        })
+        .collect();
 
     let state = serde_json::json!({
         "group_buy_id": params.group_buy_id,
@@ -687,6 +1307,22 @@ pub async fn open_cancel_register_dialog(
     Ok(())
 }
 
+/// 取消登記對話框中被登記人勾選框的名稱前綴，後面接 buyer_id
+const CANCEL_BUYER_PREFIX: &str = "cancel_buyer_";
+
+/// 取出取消登記對話框中被勾選的被登記人，依 buyer_id 排序
+fn selected_cancel_buyers(submission: &HashMap<String, serde_json::Value>) -> Vec<String> {
+    let mut buyers: Vec<String> = submission
+        .iter()
+        .filter(|(_, v)| v.as_bool() == Some(true) || v.as_str() == Some("true"))
+        .filter_map(|(name, _)| name.strip_prefix(CANCEL_BUYER_PREFIX))
+        .filter(|buyer_id| !buyer_id.is_empty())
+        .map(str::to_string)
+        .collect();
+    buyers.sort();
+    buyers
+}
+
 /// Parameters for opening the cancel-register dialog.
 pub struct CancelRegisterDialogParams {
     pub trigger_id: String,
@@ -712,13 +1348,15 @@ pub async fn handle_cancel_register_dialog(
         }
     };
 
-    let state_data = match super::utils::extract_state_value(&submission) {
-        Ok(v) => v,
-        Err(e) => {
-            error!("{}", e);
-            return Err(warp::reject::reject());
-        }
-    };
+    let state_data =
+        match super::utils::extract_state_value(&state.read().await.mattermost_client, &submission)
+        {
+            Ok(v) => v,
+            Err(e) => {
+                error!("{}", e);
+                return Ok(super::utils::invalid_dialog_response());
+            }
+        };
 
     let group_buy_id = state_data
         .get("group_buy_id")
@@ -726,16 +1364,12 @@ pub async fn handle_cancel_register_dialog(
         .ok_or_else(warp::reject::reject)?
         .to_string();
 
-    let target_buyer = submission
-        .submission
-        .get("target_buyer")
-        .and_then(|v| v.as_str())
-        .unwrap_or("");
+    let target_buyers = selected_cancel_buyers(&submission.submission);
 
-    if target_buyer.is_empty() {
+    if target_buyers.is_empty() {
         return Ok(warp::reply::with_status(
             warp::reply::json(&DialogSubmissionResponse {
-                error: Some("請選擇要取消的被登記人".to_string()),
+                error: Some("請勾選要取消的被登記人".to_string()),
                 text: None,
                 errors: None,
             }),
@@ -755,7 +1389,9 @@ pub async fn handle_cancel_register_dialog(
             error!("取得操作使用者資訊失敗: {}", e);
             return Ok(warp::reply::with_status(
                 warp::reply::json(&DialogSubmissionResponse {
-                    error: Some("內部錯誤：無法取得使用者資訊".to_string()),
+                    error: Some(
+                        ErrorCode::MattermostError.user_message("內部錯誤：無法取得使用者資訊"),
+                    ),
                     text: None,
                     errors: None,
                 }),
@@ -766,28 +1402,585 @@ pub async fn handle_cancel_register_dialog(
 
     match state_guard
         .database
-        .delete_orders_for_buyer(
+        .delete_orders_for_buyers(
             &group_buy_id,
-            target_buyer,
+            &target_buyers,
             &submission.user_id,
             &actor.username,
         )
         .await
     {
-        Ok(rows) => {
-            info!("已刪除 {} 筆訂單，buyer: {}", rows, target_buyer);
+        Ok(deleted) => {
+            info!(
+                "已刪除 {} 筆訂單，buyers: {:?}",
+                deleted.count, target_buyers
+            );
+            let mut buyer_names = Vec::with_capacity(target_buyers.len());
+            for buyer_id in &target_buyers {
+                buyer_names.push(
+                    state_guard
+                        .mattermost_client
+                        .get_user(buyer_id)
+                        .await
+                        .map(|u| format!("@{}", u.username))
+                        .unwrap_or_else(|_| buyer_id.clone()),
+                );
+            }
+            super::utils::offer_restore(
+                &state_guard,
+                &group_buy_id,
+                &deleted,
+                &format!(
+                    "@{} 取消了 {} 的所有登記",
+                    actor.username,
+                    buyer_names.join("、")
+                ),
+            )
+            .await;
+            super::refresh::request_post_refresh(&state_guard, &group_buy_id);
         }
         Err(e) => {
             error!("刪除訂單失敗: {}", e);
-            return Ok(warp::reply::with_status(
-                warp::reply::json(&DialogSubmissionResponse {
-                    error: Some(format!("刪除失敗: {}", e)),
-                    text: None,
-                    errors: None,
-                }),
-                StatusCode::OK,
+            return Ok(super::retry::dialog_error(
+                &state_guard,
+                "cancel_register",
+                &submission,
+                &state_data,
+                ErrorCode::of(&e),
+                format!("刪除失敗: {}", e),
+            )
+            .await);
+        }
+    }
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&DialogSubmissionResponse {
+            error: None,
+            text: None,
+            errors: None,
+        }),
+        StatusCode::OK,
+    ))
+}
+
+/// Parameters for opening the payments dialog.
+pub struct PaymentsDialogParams<'a> {
+    pub trigger_id: &'a str,
+    pub group_buy: &'a GroupBuy,
+    pub orders: &'a [GroupBuyOrder],
+    pub paid_buyers: &'a HashSet<String>,
+    pub deactivated: &'a HashSet<String>,
+    pub bot_callback_url: &'a str,
+}
+
+/// 付款狀態對話框中購買人勾選框的名稱前綴，後面接 buyer_id
+const PAID_BUYER_PREFIX: &str = "paid_buyer_";
+
+/// 付款狀態：每位購買人一個勾選框，顯示應付金額，預設勾選已付款的人
+pub async fn open_payments_dialog(
+    client: &MattermostClient,
+    params: &PaymentsDialogParams<'_>,
+) -> Result<()> {
+    let mut buyers: BTreeMap<(&str, &str), Decimal> = BTreeMap::new();
+    for order in params.orders {
+        *buyers
+            .entry((order.buyer_username.as_str(), order.buyer_id.as_str()))
+            .or_insert(Decimal::ZERO) += crate::money::line_total(order.unit_price, order.quantity);
+    }
+
+    let elements: Vec<DialogElement> = buyers
+        .into_iter()
+        .map(|((username, buyer_id), amount)| DialogElement {
+            display_name: if params.deactivated.contains(buyer_id) {
+                format!("@{}（已停用）", username)
+            } else {
+                format!("@{}", username)
+            },
+            name: format!("{}{}", PAID_BUYER_PREFIX, buyer_id),
+            element_type: DialogElementType::Bool,
+            placeholder: Some(format!(
+                "已付款（應付 {}{}）",
+                params.group_buy.currency, amount
+            )),
+            help_text: None,
+            optional: true,
+            min_length: None,
+            max_length: None,
+            data_source: None,
+            options: None,
+            default: params
+                .paid_buyers
+                .contains(buyer_id)
+                .then(|| "true".to_string()),
+            subtype: None,
+        })
+        .collect();
+
+    let state = serde_json::json!({
+        "group_buy_id": params.group_buy.id,
+    })
+    .to_string();
+
+    let dialog_url = format!(
+        "{}/api/v1/group_buy/dialog/payments",
+        params.bot_callback_url.trim_end_matches('/')
+    );
+
+    client
+        .open_dialog(
+            params.trigger_id,
+            &dialog_url,
+            "付款狀態",
+            &elements,
+            Some("儲存"),
+            Some("勾選已付款的購買人，取消勾選即改回未付款"),
+            Some(&state),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// 取出付款狀態對話框中被勾選的購買人，依 buyer_id 排序
+fn selected_paid_buyers(submission: &HashMap<String, serde_json::Value>) -> Vec<String> {
+    let mut buyers: Vec<String> = submission
+        .iter()
+        .filter(|(_, v)| v.as_bool() == Some(true) || v.as_str() == Some("true"))
+        .filter_map(|(name, _)| name.strip_prefix(PAID_BUYER_PREFIX))
+        .filter(|buyer_id| !buyer_id.is_empty())
+        .map(str::to_string)
+        .collect();
+    buyers.sort();
+    buyers
+}
+
+pub async fn handle_payments_dialog(
+    form: HashMap<String, String>,
+    state: Arc<RwLock<AppState>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    info!("收到付款狀態 Dialog 提交");
+
+    let submission = match super::utils::parse_dialog_submission_form(&form) {
+        Ok(s) => s,
+        Err(e) => {
+            error!("{}", e);
+            return Err(warp::reject::reject());
+        }
+    };
+
+    let state_data =
+        match super::utils::extract_state_value(&state.read().await.mattermost_client, &submission)
+        {
+            Ok(v) => v,
+            Err(e) => {
+                error!("{}", e);
+                return Ok(super::utils::invalid_dialog_response());
+            }
+        };
+
+    let group_buy_id = state_data
+        .get("group_buy_id")
+        .and_then(|v| v.as_str())
+        .ok_or_else(warp::reject::reject)?
+        .to_string();
+
+    let state_guard = state.read().await;
+    let dialog_error = |message: String| {
+        warp::reply::with_status(
+            warp::reply::json(&DialogSubmissionResponse {
+                error: Some(message),
+                text: None,
+                errors: None,
+            }),
+            StatusCode::OK,
+        )
+    };
+
+    let group_buy = match super::utils::fetch_group_buy(&state_guard, &group_buy_id).await {
+        Ok(gb) => gb,
+        Err(msg) => return Ok(dialog_error(msg)),
+    };
+    // Dialog 開啟後狀態可能已改變，送出時再檢查一次
+    if !super::utils::can_manage_group_buy(&state_guard, &group_buy, &submission.user_id).await {
+        return Ok(dialog_error(
+            ErrorCode::GbForbidden.user_message("只有團購建立者可以記錄付款狀態"),
+        ));
+    }
+    if !group_buy.status.accepts_adjustments() {
+        return Ok(dialog_error(
+            ErrorCode::GbInvalidStatus.user_message("只有已截止的團購可以記錄付款狀態"),
+        ));
+    }
+
+    let orders = match state_guard
+        .database
+        .get_orders_by_group_buy(&group_buy_id)
+        .await
+    {
+        Ok(orders) => orders,
+        Err(e) => {
+            error!("取得訂單失敗: {}", e);
+            return Ok(dialog_error(
+                ErrorCode::DatabaseError.user_message("取得訂單失敗"),
             ));
         }
+    };
+
+    // 只記錄仍有訂單的購買人
+    let paid_buyers: Vec<String> = selected_paid_buyers(&submission.submission)
+        .into_iter()
+        .filter(|buyer_id| orders.iter().any(|o| &o.buyer_id == buyer_id))
+        .collect();
+
+    if let Err(e) = state_guard
+        .database
+        .set_paid_buyers(&group_buy_id, &paid_buyers, &submission.user_id)
+        .await
+    {
+        error!("更新付款狀態失敗: {}", e);
+        return Ok(super::retry::dialog_error(
+            &state_guard,
+            "payments",
+            &submission,
+            &state_data,
+            ErrorCode::of(&e),
+            format!("更新付款狀態失敗: {}", e),
+        )
+        .await);
+    }
+
+    info!(
+        "團購 {} 的付款狀態已更新：{} 人已付款",
+        group_buy_id,
+        paid_buyers.len()
+    );
+
+    // 以臨時訊息回覆更新後的小計
+    let paid_set: HashSet<String> = paid_buyers.into_iter().collect();
+    let deactivated = super::deactivated::load_deactivated_users(&state_guard.database).await;
+    let table = super::messages::generate_subtotal_table(
+        &group_buy,
+        &orders,
+        Some(&paid_set),
+        &deactivated,
+        &state_guard.config.group_buy.rounding,
+        &state_guard.config.group_buy.locale_format(),
+    );
+    if let Err(e) = state_guard
+        .mattermost_client
+        .send_ephemeral_post(&submission.channel_id, &submission.user_id, &table, None)
+        .await
+    {
+        error!("發送付款狀態失敗: {}", e);
+    }
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&DialogSubmissionResponse {
+            error: None,
+            text: None,
+            errors: None,
+        }),
+        StatusCode::OK,
+    ))
+}
+
+/// 停用帳號對話框：選擇帳號已停用的購買人，把他的登記改到別人名下或刪除
+pub async fn open_deactivated_buyers_dialog(
+    client: &MattermostClient,
+    trigger_id: &str,
+    group_buy: &GroupBuy,
+    deactivated: &[(String, String)],
+    bot_callback_url: &str,
+) -> Result<()> {
+    let buyer_options: Vec<DialogOption> = deactivated
+        .iter()
+        .map(|(buyer_id, username)| DialogOption {
+            text: format!("@{}", username),
+            value: buyer_id.clone(),
+        })
+        .collect();
+
+    let elements = vec![
+        DialogElement {
+            display_name: "購買人".to_string(),
+            name: "buyer".to_string(),
+            element_type: DialogElementType::Select,
+            placeholder: Some("選擇帳號已停用的購買人".to_string()),
+            help_text: None,
+            optional: false,
+            min_length: None,
+            max_length: None,
+            data_source: None,
+            options: Some(buyer_options),
+            default: deactivated.first().map(|(buyer_id, _)| buyer_id.clone()),
+            subtype: None,
+        },
+        DialogElement {
+            display_name: "處理方式".to_string(),
+            name: "handling".to_string(),
+            element_type: DialogElementType::Radio,
+            placeholder: None,
+            help_text: None,
+            optional: false,
+            min_length: None,
+            max_length: None,
+            data_source: None,
+            options: Some(vec![
+                DialogOption {
+                    text: "改到其他人名下".to_string(),
+                    value: "reassign".to_string(),
+                },
+                DialogOption {
+                    text: "刪除登記".to_string(),
+                    value: "remove".to_string(),
+                },
+            ]),
+            default: Some("reassign".to_string()),
+            subtype: None,
+        },
+        DialogElement {
+            display_name: "新的購買人".to_string(),
+            name: "new_buyer".to_string(),
+            element_type: DialogElementType::Select,
+            placeholder: Some("選擇使用者".to_string()),
+            help_text: Some("改到其他人名下時必填，付款狀態一併移過去".to_string()),
+            optional: true,
+            min_length: None,
+            max_length: None,
+            data_source: Some("users".to_string()),
+            options: None,
+            default: None,
+            subtype: None,
+        },
+    ];
+
+    let state = serde_json::json!({
+        "group_buy_id": group_buy.id,
+    })
+    .to_string();
+
+    let dialog_url = format!(
+        "{}/api/v1/group_buy/dialog/deactivated_buyers",
+        bot_callback_url.trim_end_matches('/')
+    );
+
+    client
+        .open_dialog(
+            trigger_id,
+            &dialog_url,
+            "停用帳號的登記",
+            &elements,
+            Some("送出"),
+            Some("帳號已停用的購買人不會再被 @-mention，請把他的登記交給其他人或刪除"),
+            Some(&state),
+        )
+        .await?;
+
+    Ok(())
+}
+
+pub async fn handle_deactivated_buyers_dialog(
+    form: HashMap<String, String>,
+    state: Arc<RwLock<AppState>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    info!("收到停用帳號 Dialog 提交");
+
+    let submission = match super::utils::parse_dialog_submission_form(&form) {
+        Ok(s) => s,
+        Err(e) => {
+            error!("{}", e);
+            return Err(warp::reject::reject());
+        }
+    };
+
+    let state_data =
+        match super::utils::extract_state_value(&state.read().await.mattermost_client, &submission)
+        {
+            Ok(v) => v,
+            Err(e) => {
+                error!("{}", e);
+                return Ok(super::utils::invalid_dialog_response());
+            }
+        };
+
+    let group_buy_id = state_data
+        .get("group_buy_id")
+        .and_then(|v| v.as_str())
+        .ok_or_else(warp::reject::reject)?
+        .to_string();
+
+    let field = |name: &str| {
+        submission
+            .submission
+            .get(name)
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string()
+    };
+    let buyer_id = field("buyer");
+    let handling = field("handling");
+    let new_buyer_id = field("new_buyer");
+
+    let state_guard = state.read().await;
+    let dialog_error = |message: String| {
+        warp::reply::with_status(
+            warp::reply::json(&DialogSubmissionResponse {
+                error: Some(message),
+                text: None,
+                errors: None,
+            }),
+            StatusCode::OK,
+        )
+    };
+    let field_error = |field: &str, message: &str| {
+        warp::reply::with_status(
+            warp::reply::json(&super::utils::make_field_error_response(field, message)),
+            StatusCode::OK,
+        )
+    };
+
+    let group_buy = match super::utils::fetch_group_buy(&state_guard, &group_buy_id).await {
+        Ok(gb) => gb,
+        Err(msg) => return Ok(dialog_error(msg)),
+    };
+    // Dialog 開啟後狀態可能已改變，送出時再檢查一次
+    if !super::utils::can_manage_group_buy(&state_guard, &group_buy, &submission.user_id).await {
+        return Ok(dialog_error(
+            ErrorCode::GbForbidden.user_message("只有團購建立者可以處理停用帳號的登記"),
+        ));
+    }
+    if group_buy.status == GroupBuyStatus::Archived {
+        return Ok(dialog_error(
+            ErrorCode::GbInvalidStatus.user_message("已封存的團購無法修改登記"),
+        ));
+    }
+
+    let orders = match state_guard
+        .database
+        .get_orders_by_group_buy(&group_buy_id)
+        .await
+    {
+        Ok(orders) => orders,
+        Err(e) => {
+            error!("取得訂單失敗: {}", e);
+            return Ok(dialog_error(
+                ErrorCode::DatabaseError.user_message("取得訂單失敗"),
+            ));
+        }
+    };
+    let Some(buyer_username) = orders
+        .iter()
+        .find(|o| o.buyer_id == buyer_id)
+        .map(|o| o.buyer_username.clone())
+    else {
+        return Ok(field_error("buyer", "這位購買人已經沒有登記"));
+    };
+
+    let actor = match state_guard
+        .mattermost_client
+        .get_user(&submission.user_id)
+        .await
+    {
+        Ok(u) => u,
+        Err(e) => {
+            error!("取得操作使用者資訊失敗: {}", e);
+            return Ok(dialog_error(
+                ErrorCode::MattermostError.user_message("內部錯誤：無法取得使用者資訊"),
+            ));
+        }
+    };
+
+    let summary = match handling.as_str() {
+        "remove" => {
+            match state_guard
+                .database
+                .delete_orders_for_buyers(
+                    &group_buy_id,
+                    std::slice::from_ref(&buyer_id),
+                    &submission.user_id,
+                    &actor.username,
+                )
+                .await
+            {
+                Ok(deleted) => {
+                    super::utils::offer_restore(
+                        &state_guard,
+                        &group_buy_id,
+                        &deleted,
+                        &format!(
+                            "@{} 刪除了 {}（帳號已停用）的所有登記",
+                            actor.username, buyer_username
+                        ),
+                    )
+                    .await;
+                    format!("已刪除 {} 的 {} 筆登記", buyer_username, deleted.count)
+                }
+                Err(e) => {
+                    error!("刪除訂單失敗: {}", e);
+                    return Ok(dialog_error(format!("刪除失敗: {}", e)));
+                }
+            }
+        }
+        "reassign" => {
+            if new_buyer_id.is_empty() {
+                return Ok(field_error("new_buyer", "請選擇新的購買人"));
+            }
+            if new_buyer_id == buyer_id {
+                return Ok(field_error("new_buyer", "請選擇其他人"));
+            }
+            let new_buyer = match state_guard.mattermost_client.get_user(&new_buyer_id).await {
+                Ok(u) if u.is_deactivated() => {
+                    return Ok(field_error("new_buyer", "這個帳號也已停用"));
+                }
+                Ok(u) => u,
+                Err(e) => {
+                    error!("取得購買人資訊失敗: {}", e);
+                    return Ok(dialog_error(
+                        ErrorCode::MattermostError.user_message("無法取得購買人資訊"),
+                    ));
+                }
+            };
+            match state_guard
+                .database
+                .reassign_buyer_orders(
+                    &group_buy_id,
+                    &buyer_id,
+                    &new_buyer.id,
+                    &new_buyer.username,
+                    &submission.user_id,
+                    &actor.username,
+                )
+                .await
+            {
+                Ok(count) => format!(
+                    "已把 {} 的 {} 筆登記改到 @{} 名下",
+                    buyer_username, count, new_buyer.username
+                ),
+                Err(e) => {
+                    error!("移動訂單失敗: {}", e);
+                    return Ok(dialog_error(format!("移動登記失敗: {}", e)));
+                }
+            }
+        }
+        _ => return Ok(field_error("handling", "請選擇處理方式")),
+    };
+
+    info!(
+        "團購 {}：{}（由 {} 處理）",
+        group_buy_id, summary, actor.username
+    );
+    super::refresh::request_post_refresh(&state_guard, &group_buy_id);
+    if let Err(e) = state_guard
+        .mattermost_client
+        .send_ephemeral_post(
+            &submission.channel_id,
+            &submission.user_id,
+            &format!("✅ {}", summary),
+            None,
+        )
+        .await
+    {
+        error!("發送停用帳號處理結果失敗: {}", e);
     }
 
     Ok(warp::reply::with_status(
@@ -806,16 +1999,23 @@ pub async fn open_register_dialog(
     client: &MattermostClient,
     params: &RegisterDialogParams<'_>,
 ) -> Result<()> {
-    let item_options: Vec<DialogOption> = params
-        .items
-        .iter()
+    let mut sorted_items: Vec<_> = params.items.iter().collect();
+    sorted_items.sort_by_key(|(name, _)| *name);
+    let item_options: Vec<DialogOption> = sorted_items
+        .into_iter()
         .map(|(name, price)| DialogOption {
-            text: format!("{} (NT${})", name, price),
+            text: format!(
+                "{}{} ({}{})",
+                super::messages::item_icon_plain_prefix(params.item_icons, name),
+                super::messages::item_label(params.item_translations, name, params.locale_index),
+                params.currency,
+                price
+            ),
             value: name.clone(),
         })
         .collect();
 
-    let elements = vec![
+    let mut elements = vec![
         DialogElement {
             display_name: "購買人".to_string(),
             name: "buyer".to_string(),
@@ -841,7 +2041,10 @@ pub async fn open_register_dialog(
             max_length: None,
             data_source: None,
             options: Some(item_options),
-            default: None,
+            default: params
+                .default_item
+                .filter(|item| params.items.contains_key(*item))
+                .map(str::to_string),
             subtype: None,
         },
         DialogElement {
@@ -858,7 +2061,33 @@ pub async fn open_register_dialog(
             default: Some("1".to_string()),
             subtype: Some("number".to_string()),
         },
+        DialogElement {
+            display_name: "已登記同商品時".to_string(),
+            name: "on_duplicate".to_string(),
+            element_type: DialogElementType::Select,
+            placeholder: Some("合併或另外新增".to_string()),
+            help_text: Some("購買人已登記過這個商品時必須選擇".to_string()),
+            optional: true,
+            min_length: None,
+            max_length: None,
+            data_source: None,
+            options: Some(vec![
+                DialogOption {
+                    text: "合併數量".to_string(),
+                    value: "merge".to_string(),
+                },
+                DialogOption {
+                    text: "另外新增一筆".to_string(),
+                    value: "add".to_string(),
+                },
+            ]),
+            default: None,
+            subtype: None,
+        },
     ];
+    elements.extend(super::order_fields::order_field_elements(
+        params.order_fields,
+    ));
 
     let state = serde_json::json!({
         "group_buy_id": params.group_buy_id,
@@ -892,6 +2121,15 @@ pub struct RegisterDialogParams<'a> {
     pub trigger_id: &'a str,
     pub group_buy_id: &'a str,
     pub items: &'a HashMap<String, Decimal>,
+    pub item_icons: &'a HashMap<String, String>,
+    pub item_translations: &'a HashMap<String, Vec<String>>,
+    /// 開啟對話框的使用者語系對應的翻譯位置（見 `GroupBuyConfig::item_locale_index`），選項的值仍為商品名稱
+    pub locale_index: Option<usize>,
+    /// 建立者定義的訂單欄位，接在數量後面
+    pub order_fields: &'a [crate::database::OrderField],
+    pub currency: &'a str,
+    /// 使用者在這個商家常點的商品，存在於商品列表時預先選好
+    pub default_item: Option<&'a str>,
     pub version: i32,
     pub post_id: Option<&'a str>,
     pub introduction_text: Option<&'a str>,
@@ -913,13 +2151,15 @@ pub async fn handle_register_dialog(
         }
     };
 
-    let state_data = match super::utils::extract_state_value(&submission) {
-        Ok(v) => v,
-        Err(e) => {
-            error!("{}", e);
-            return Err(warp::reject::reject());
-        }
-    };
+    let state_data =
+        match super::utils::extract_state_value(&state.read().await.mattermost_client, &submission)
+        {
+            Ok(v) => v,
+            Err(e) => {
+                error!("{}", e);
+                return Ok(super::utils::invalid_dialog_response());
+            }
+        };
 
     let group_buy_id = state_data
         .get("group_buy_id")
@@ -980,7 +2220,7 @@ pub async fn handle_register_dialog(
             error!("取得購買人資訊失敗: {}", e);
             return Ok(warp::reply::with_status(
                 warp::reply::json(&DialogSubmissionResponse {
-                    error: Some("無法取得購買人資訊".to_string()),
+                    error: Some(ErrorCode::MattermostError.user_message("無法取得購買人資訊")),
                     text: None,
                     errors: None,
                 }),
@@ -999,7 +2239,7 @@ pub async fn handle_register_dialog(
             error!("取得登記人資訊失敗: {}", e);
             return Ok(warp::reply::with_status(
                 warp::reply::json(&DialogSubmissionResponse {
-                    error: Some("無法取得登記人資訊".to_string()),
+                    error: Some(ErrorCode::MattermostError.user_message("無法取得登記人資訊")),
                     text: None,
                     errors: None,
                 }),
@@ -1013,7 +2253,7 @@ pub async fn handle_register_dialog(
         _ => {
             return Ok(warp::reply::with_status(
                 warp::reply::json(&DialogSubmissionResponse {
-                    error: Some("找不到該團購".to_string()),
+                    error: Some(ErrorCode::GbNotFound.user_message("找不到該團購")),
                     text: None,
                     errors: None,
                 }),
@@ -1048,22 +2288,34 @@ pub async fn handle_register_dialog(
             )
             .await
         {
-            Ok(rows) => {
+            Ok(deleted) => {
                 info!(
                     "刪除了 {} 筆 {} 的登記 (buyer: {})",
-                    rows, item_name, buyer_id
+                    deleted.count, item_name, buyer_id
                 );
+                super::utils::offer_restore(
+                    &state_guard,
+                    &group_buy_id,
+                    &deleted,
+                    &format!(
+                        "@{} 刪除了 @{} 的 {} 登記",
+                        registrar.username, buyer.username, item_name
+                    ),
+                )
+                .await;
+                super::refresh::request_post_refresh(&state_guard, &group_buy_id);
             }
             Err(e) => {
                 error!("刪除登記失敗: {}", e);
-                return Ok(warp::reply::with_status(
-                    warp::reply::json(&DialogSubmissionResponse {
-                        error: Some(format!("刪除失敗: {}", e)),
-                        text: None,
-                        errors: None,
-                    }),
-                    StatusCode::OK,
-                ));
+                return Ok(super::retry::dialog_error(
+                    &state_guard,
+                    "register",
+                    &submission,
+                    &state_data,
+                    ErrorCode::of(&e),
+                    format!("刪除失敗: {}", e),
+                )
+                .await);
             }
         }
 
@@ -1077,36 +2329,170 @@ pub async fn handle_register_dialog(
         ));
     }
 
-    let order = GroupBuyOrder {
-        id: uuid::Uuid::new_v4().to_string(),
-        group_buy_id: group_buy_id.clone(),
-        registrar_id: submission.user_id.clone(),
-        registrar_username: registrar.username.clone(),
-        buyer_id: buyer_id.to_string(),
-        buyer_username: buyer.username.clone(),
-        item_name: item_name.to_string(),
-        quantity,
-        original_quantity: None,
-        unit_price,
-        created_at: Utc::now(),
+    let custom_fields = match super::order_fields::collect_custom_fields(
+        &group_buy.order_fields,
+        &submission.submission,
+    ) {
+        Ok(values) => values,
+        Err((field, message)) => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&DialogSubmissionResponse {
+                    error: None,
+                    text: None,
+                    errors: Some([(field, message)].into_iter().collect()),
+                }),
+                StatusCode::OK,
+            ));
+        }
     };
 
-    if let Err(e) = state_guard.database.create_order(&order).await {
-        error!("建立訂單失敗: {}", e);
+    // 購買人已登記同一商品（且自訂欄位相同）時，要求明確選擇合併或另外新增，避免不小心重複登記
+    let existing_orders: Vec<GroupBuyOrder> = state_guard
+        .database
+        .get_buyer_orders(&group_buy_id, buyer_id)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|o| o.item_name == item_name && o.custom_fields == custom_fields)
+        .collect();
+    let on_duplicate = submission
+        .submission
+        .get("on_duplicate")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+    if !existing_orders.is_empty() && !matches!(on_duplicate, "merge" | "add") {
         return Ok(warp::reply::with_status(
             warp::reply::json(&DialogSubmissionResponse {
-                error: Some(format!("登記失敗: {}", e)),
+                error: None,
                 text: None,
-                errors: None,
+                errors: Some(
+                    [(
+                        "on_duplicate".to_string(),
+                        duplicate_order_warning(&buyer.username, item_name, &existing_orders),
+                    )]
+                    .into_iter()
+                    .collect(),
+                ),
             }),
             StatusCode::OK,
         ));
     }
 
+    let merge_target = if on_duplicate == "merge" {
+        existing_orders.first()
+    } else {
+        None
+    };
+    let order = if let Some(existing) = merge_target {
+        if let Err(e) = state_guard
+            .database
+            .merge_order_quantity(
+                &group_buy_id,
+                &existing.id,
+                quantity,
+                &submission.user_id,
+                &registrar.username,
+            )
+            .await
+        {
+            error!("合併訂單失敗: {}", e);
+            return Ok(super::retry::dialog_error(
+                &state_guard,
+                "register",
+                &submission,
+                &state_data,
+                ErrorCode::of(&e),
+                format!("登記失敗: {}", e),
+            )
+            .await);
+        }
+        GroupBuyOrder {
+            quantity: existing.quantity + quantity,
+            registrar_id: submission.user_id.clone(),
+            registrar_username: registrar.username.clone(),
+            ..existing.clone()
+        }
+    } else {
+        let order = GroupBuyOrder {
+            id: uuid::Uuid::new_v4().to_string(),
+            group_buy_id: group_buy_id.clone(),
+            registrar_id: submission.user_id.clone(),
+            registrar_username: registrar.username.clone(),
+            buyer_id: buyer_id.to_string(),
+            buyer_username: buyer.username.clone(),
+            item_name: item_name.to_string(),
+            quantity,
+            original_quantity: None,
+            unit_price,
+            custom_fields,
+            created_at: Utc::now(),
+        };
+
+        if let Err(e) = state_guard.database.create_order(&order).await {
+            error!("建立訂單失敗: {}", e);
+            return Ok(super::retry::dialog_error(
+                &state_guard,
+                "register",
+                &submission,
+                &state_data,
+                ErrorCode::of(&e),
+                format!("登記失敗: {}", e),
+            )
+            .await);
+        }
+        order
+    };
+
     info!(
         "{} 為 {} 登記：{} x{}",
         registrar.username, buyer.username, item_name, quantity
     );
+    super::refresh::request_post_refresh(&state_guard, &group_buy_id);
+
+    // 以臨時訊息回覆登記收據，包含購買人目前的小計
+    let buyer_orders = state_guard
+        .database
+        .get_buyer_orders(&group_buy_id, buyer_id)
+        .await
+        .unwrap_or_else(|e| {
+            error!("取得購買人訂單失敗: {}", e);
+            vec![order.clone()]
+        });
+    let mut receipt = super::messages::generate_registration_receipt(
+        &group_buy,
+        &order,
+        &buyer_orders,
+        &state_guard.config.group_buy.rounding,
+        &state_guard.config.group_buy.locale_format(),
+    );
+    // 自己登記時檢查每月預算
+    if buyer_id == submission.user_id
+        && let Some(warning) = super::budget::budget_warning(&state_guard, buyer_id).await
+    {
+        receipt.push_str(&format!("\n\n{}", warning));
+    }
+    if let Err(e) = state_guard
+        .mattermost_client
+        .send_ephemeral_post(&submission.channel_id, &submission.user_id, &receipt, None)
+        .await
+    {
+        error!("發送登記收據失敗: {}", e);
+    }
+
+    // 代人登記時私訊購買人，附上一鍵取消按鈕；自己登記時記住常點的商品
+    if buyer_id != submission.user_id {
+        notify_proxy_registration(&state_guard, &group_buy, &order).await;
+    } else if let Err(e) = state_guard
+        .database
+        .set_user_preference(
+            buyer_id,
+            &favorite_item_key(&super::merchant::merchant_key(&group_buy.merchant_name)),
+            item_name,
+        )
+        .await
+    {
+        error!("儲存常點商品失敗: {}", e);
+    }
 
     Ok(warp::reply::with_status(
         warp::reply::json(&DialogSubmissionResponse {
@@ -1118,6 +2504,61 @@ pub async fn handle_register_dialog(
     ))
 }
 
+/// 購買人已登記同一商品時的提示，列出目前的數量
+fn duplicate_order_warning(
+    buyer_username: &str,
+    item_name: &str,
+    existing_orders: &[GroupBuyOrder],
+) -> String {
+    let existing_quantity: i32 = existing_orders.iter().map(|o| o.quantity).sum();
+    format!(
+        "@{} 已登記 {} x{}，請選擇「合併數量」或「另外新增一筆」",
+        buyer_username, item_name, existing_quantity
+    )
+}
+
+/// 私訊購買人有人幫他登記，讓他能一鍵取消不是自己要的訂單
+async fn notify_proxy_registration(
+    state_guard: &AppState,
+    group_buy: &GroupBuy,
+    order: &GroupBuyOrder,
+) {
+    let client = &state_guard.mattermost_client;
+    let channel = match client
+        .create_direct_channel(&state_guard.bot_user_id, &order.buyer_id)
+        .await
+    {
+        Ok(c) => c,
+        Err(e) => {
+            error!("建立私訊頻道失敗: {}", e);
+            return;
+        }
+    };
+
+    let bot_callback_url = super::utils::bot_callback_url_from_state(state_guard);
+    let post = crate::mattermost::Post {
+        id: None,
+        channel_id: channel.id,
+        message: super::messages::generate_proxy_registration_notice(
+            group_buy,
+            order,
+            &state_guard.config.group_buy.locale_format(),
+        ),
+        root_id: None,
+        props: Some(serde_json::json!({
+            "attachments": super::messages::generate_reject_order_button(
+                &group_buy.id,
+                order,
+                &bot_callback_url,
+                client.signer(),
+            )
+        })),
+    };
+    if let Err(e) = client.create_post(&post).await {
+        error!("私訊代登記通知失敗: {}", e);
+    }
+}
+
 // Open adjust shortage dialog
 pub async fn open_adjust_shortage_dialog(
     client: &MattermostClient,
@@ -1134,20 +2575,86 @@ pub async fn open_adjust_shortage_dialog(
         ));
     }
 
-    let elements = vec![DialogElement {
-        display_name: "調整數量 (YAML 格式)".to_string(),
-        name: "adjustments".to_string(),
-        element_type: DialogElementType::Textarea,
-        placeholder: Some("order_id: 新數量".to_string()),
-        help_text: Some("只需填寫要調整的訂單，格式：order_id: 新數量".to_string()),
-        optional: false,
-        min_length: None,
-        max_length: Some(3000),
-        data_source: None,
-        options: None,
-        default: Some(yaml),
-        subtype: None,
-    }];
+    let mut item_names: Vec<&str> = params.orders.iter().map(|o| o.item_name.as_str()).collect();
+    item_names.sort();
+    item_names.dedup();
+
+    let elements = vec![
+        DialogElement {
+            display_name: "調整數量 (YAML 格式)".to_string(),
+            name: "adjustments".to_string(),
+            element_type: DialogElementType::Textarea,
+            placeholder: Some("order_id: 新數量".to_string()),
+            help_text: Some(
+                "只需填寫要調整的訂單，格式：order_id: 新數量；下方選了商品時忽略此欄".to_string(),
+            ),
+            optional: true,
+            min_length: None,
+            max_length: Some(3000),
+            data_source: None,
+            options: None,
+            default: Some(yaml),
+            subtype: None,
+        },
+        DialogElement {
+            display_name: "依商品分配：商品".to_string(),
+            name: "split_item".to_string(),
+            element_type: DialogElementType::Select,
+            placeholder: Some("選擇缺貨的商品".to_string()),
+            help_text: Some("只填實際到貨數量，由 bot 分配給購買人，套用前會先預覽".to_string()),
+            optional: true,
+            min_length: None,
+            max_length: None,
+            data_source: None,
+            options: Some(
+                item_names
+                    .iter()
+                    .map(|name| DialogOption {
+                        text: name.to_string(),
+                        value: name.to_string(),
+                    })
+                    .collect(),
+            ),
+            default: None,
+            subtype: None,
+        },
+        DialogElement {
+            display_name: "依商品分配：實際到貨份數".to_string(),
+            name: "split_arrived".to_string(),
+            element_type: DialogElementType::Text,
+            placeholder: Some("例如 5".to_string()),
+            help_text: None,
+            optional: true,
+            min_length: None,
+            max_length: Some(5),
+            data_source: None,
+            options: None,
+            default: None,
+            subtype: Some("number".to_string()),
+        },
+        DialogElement {
+            display_name: "依商品分配：分配方式".to_string(),
+            name: "split_method".to_string(),
+            element_type: DialogElementType::Select,
+            placeholder: None,
+            help_text: None,
+            optional: true,
+            min_length: None,
+            max_length: None,
+            data_source: None,
+            options: Some(
+                [SplitMethod::Proportional, SplitMethod::Fifo]
+                    .into_iter()
+                    .map(|method| DialogOption {
+                        text: method.label().to_string(),
+                        value: method.as_str().to_string(),
+                    })
+                    .collect(),
+            ),
+            default: Some("proportional".to_string()),
+            subtype: None,
+        },
+    ];
 
     let state = serde_json::json!({
         "group_buy_id": params.group_buy_id,
@@ -1241,14 +2748,15 @@ pub async fn handle_adjust_shortage_dialog(
         warp::reject::reject()
     })?;
 
-    let state_data: serde_json::Value = if let Some(state_str) = &submission.state {
-        serde_json::from_str(state_str).map_err(|e| {
-            error!("解析 state 失敗: {}", e);
-            warp::reject::reject()
-        })?
-    } else {
-        return Err(warp::reject::reject());
-    };
+    let state_data =
+        match super::utils::extract_state_value(&state.read().await.mattermost_client, &submission)
+        {
+            Ok(v) => v,
+            Err(e) => {
+                error!("{}", e);
+                return Ok(super::utils::invalid_dialog_response());
+            }
+        };
 
     let group_buy_id = state_data
         .get("group_buy_id")
@@ -1256,6 +2764,15 @@ pub async fn handle_adjust_shortage_dialog(
         .ok_or_else(warp::reject::reject)?
         .to_string();
 
+    let split_item = submission
+        .submission
+        .get("split_item")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+    if !split_item.is_empty() {
+        return preview_shortage_split(&state, &submission, &group_buy_id, split_item).await;
+    }
+
     let adjustments_yaml = submission
         .submission
         .get("adjustments")
@@ -1291,69 +2808,373 @@ pub async fn handle_adjust_shortage_dialog(
         ));
     }
 
+    // 先預覽原數量 → 調整後與受影響的小計，按「套用」才寫入
     let state_guard = state.read().await;
-
-    let user = match state_guard
-        .mattermost_client
-        .get_user(&submission.user_id)
+    let group_buy = match super::utils::fetch_group_buy(&state_guard, &group_buy_id).await {
+        Ok(gb) => gb,
+        Err(msg) => return Ok(adjust_field_error("adjustments", msg)),
+    };
+    let orders = match state_guard
+        .database
+        .get_orders_by_group_buy(&group_buy_id)
         .await
     {
-        Ok(u) => u,
+        Ok(orders) => orders,
         Err(e) => {
-            error!("取得用戶資訊失敗: {}", e);
-            return Ok(warp::reply::with_status(
-                warp::reply::json(&DialogSubmissionResponse {
-                    error: Some("無法取得用戶資訊".to_string()),
-                    text: None,
-                    errors: None,
-                }),
-                StatusCode::OK,
+            error!("取得訂單失敗: {}", e);
+            return Ok(adjust_field_error(
+                "adjustments",
+                "取得訂單失敗".to_string(),
             ));
         }
     };
+    let changes = match plan_order_changes(&orders, &adjustments) {
+        Ok(changes) => changes,
+        Err(msg) => return Ok(adjust_field_error("adjustments", msg)),
+    };
+
+    let preview = format_changes_preview(&orders, &changes, &group_buy.currency);
+    let apply_context = (!changes.is_empty()).then(|| {
+        serde_json::json!({
+            "action": "apply_adjustments",
+            "group_buy_id": group_buy_id,
+            "changes": changes,
+        })
+    });
+    Ok(send_adjustment_preview(
+        &state_guard,
+        &submission,
+        "adjustments",
+        &preview,
+        apply_context,
+    )
+    .await)
+}
 
-    let state_guard = state_guard; // keep borrow
+fn adjust_field_error(field: &str, message: String) -> WithStatus<Json> {
+    warp::reply::with_status(
+        warp::reply::json(&DialogSubmissionResponse {
+            error: None,
+            text: None,
+            errors: Some([(field.to_string(), message)].into_iter().collect()),
+        }),
+        StatusCode::OK,
+    )
+}
 
-    for (order_id, new_quantity) in adjustments {
-        if let Err(e) = state_guard
-            .database
-            .adjust_single_order(&order_id, new_quantity, &submission.user_id, &user.username)
-            .await
-        {
-            error!("調整訂單 {} 數量失敗: {}", order_id, e);
-            return Ok(warp::reply::with_status(
-                warp::reply::json(&DialogSubmissionResponse {
-                    error: Some(format!("調整訂單失敗: {}", e)),
-                    text: None,
-                    errors: None,
-                }),
-                StatusCode::OK,
+/// 依商品分配缺貨：計算分配結果，以臨時訊息預覽並附「套用」按鈕，尚不寫入
+async fn preview_shortage_split(
+    state: &Arc<RwLock<AppState>>,
+    submission: &DialogSubmission,
+    group_buy_id: &str,
+    item_name: &str,
+) -> Result<WithStatus<Json>, warp::Rejection> {
+    let arrived: i32 = match submission
+        .submission
+        .get("split_arrived")
+        .and_then(|v| {
+            v.as_i64()
+                .or_else(|| v.as_str().and_then(|s| s.trim().parse().ok()))
+        })
+        .and_then(|n| i32::try_from(n).ok())
+    {
+        Some(n) if n >= 0 => n,
+        _ => {
+            return Ok(adjust_field_error(
+                "split_arrived",
+                "請填寫實際到貨份數（0 以上的整數）".to_string(),
             ));
         }
+    };
+    let method = submission
+        .submission
+        .get("split_method")
+        .and_then(|v| v.as_str())
+        .and_then(SplitMethod::from_string)
+        .unwrap_or(SplitMethod::Proportional);
+
+    let state_guard = state.read().await;
+    let orders = match state_guard
+        .database
+        .get_orders_by_group_buy(group_buy_id)
+        .await
+    {
+        Ok(orders) => orders,
+        Err(e) => {
+            error!("取得訂單失敗: {}", e);
+            return Ok(adjust_field_error("split_item", "取得訂單失敗".to_string()));
+        }
+    };
+    let results = split_shortage(&orders, item_name, arrived, method);
+    if results.is_empty() {
+        return Ok(adjust_field_error(
+            "split_item",
+            format!("沒有人登記「{}」", item_name),
+        ));
     }
 
-    let _group_buy = match state_guard.database.get_group_buy(&group_buy_id).await {
-        Ok(Some(gb)) => gb,
-        _ => {
-            return Ok(warp::reply::with_status(
-                warp::reply::json(&DialogSubmissionResponse {
-                    error: Some("取得團購資料失敗".to_string()),
-                    text: None,
-                    errors: None,
-                }),
-                StatusCode::OK,
-            ));
+    let preview = format_split_preview(item_name, arrived, method, &results);
+    let apply_context = results
+        .iter()
+        .any(|r| r.old_quantity != r.new_quantity)
+        .then(|| {
+            serde_json::json!({
+                "action": "apply_shortage_split",
+                "group_buy_id": group_buy_id,
+                "item_name": item_name,
+                "results": results,
+            })
+        });
+    Ok(send_adjustment_preview(
+        &state_guard,
+        submission,
+        "split_item",
+        &preview,
+        apply_context,
+    )
+    .await)
+}
+
+/// 以臨時訊息送出缺貨調整預覽；`apply_context` 有值時附「套用」按鈕，
+/// 按鈕的 action 取自 context 中的 `action`
+async fn send_adjustment_preview(
+    state_guard: &AppState,
+    submission: &DialogSubmission,
+    error_field: &str,
+    preview: &str,
+    apply_context: Option<serde_json::Value>,
+) -> WithStatus<Json> {
+    let props = match apply_context {
+        Some(context) => {
+            let action = context["action"].as_str().unwrap_or_default().to_string();
+            let group_buy_id = context["group_buy_id"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string();
+            let bot_callback_url = super::utils::bot_callback_url_from_state(state_guard);
+            serde_json::json!({
+                "attachments": [{
+                    "actions": [{
+                        "id": crate::mattermost::action_id(&action, &group_buy_id),
+                        "name": "套用",
+                        "type": "button",
+                        "style": "primary",
+                        "integration": {
+                            "url": format!("{}/api/v1/group_buy/action/{}", bot_callback_url, action),
+                            "context": state_guard.mattermost_client.signer().sign_context(context, None),
+                        }
+                    }]
+                }]
+            })
         }
+        None => serde_json::json!({}),
     };
 
-    info!("{} 調整了團購 {} 的缺貨", user.username, group_buy_id);
+    if let Err(e) = state_guard
+        .mattermost_client
+        .send_ephemeral_post_with_props(&submission.channel_id, &submission.user_id, preview, props)
+        .await
+    {
+        error!("發送缺貨調整預覽失敗: {}", e);
+        return adjust_field_error(error_field, "發送預覽失敗，請稍後再試".to_string());
+    }
 
-    Ok(warp::reply::with_status(
+    warp::reply::with_status(
         warp::reply::json(&DialogSubmissionResponse {
             error: None,
             text: None,
             errors: None,
         }),
         StatusCode::OK,
-    ))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selected_cancel_buyers() {
+        let submission: HashMap<String, serde_json::Value> = [
+            ("cancel_buyer_u2", serde_json::json!(true)),
+            ("cancel_buyer_u1", serde_json::json!("true")),
+            ("cancel_buyer_u3", serde_json::json!(false)),
+            ("cancel_buyer_", serde_json::json!(true)),
+            ("draft", serde_json::json!(true)),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+
+        assert_eq!(selected_cancel_buyers(&submission), vec!["u1", "u2"]);
+    }
+
+    #[test]
+    fn test_selected_paid_buyers() {
+        let submission: HashMap<String, serde_json::Value> = [
+            ("paid_buyer_u2", serde_json::json!(true)),
+            ("paid_buyer_u1", serde_json::json!("true")),
+            ("paid_buyer_u3", serde_json::json!(false)),
+            ("cancel_buyer_u4", serde_json::json!(true)),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+
+        assert_eq!(selected_paid_buyers(&submission), vec!["u1", "u2"]);
+    }
+
+    #[test]
+    fn test_duplicate_order_warning_sums_existing_quantity() {
+        let orders: Vec<GroupBuyOrder> = [2, 1]
+            .into_iter()
+            .map(|qty| {
+                let mut order =
+                    crate::test_utils::utils::make_order_for("gb-1".to_string(), "bob", "bob");
+                order.item_name = "紅茶".to_string();
+                order.quantity = qty;
+                order
+            })
+            .collect();
+
+        let warning = duplicate_order_warning("bob", "紅茶", &orders);
+        assert!(warning.starts_with("@bob 已登記 紅茶 x3"));
+        assert!(warning.contains("合併數量"));
+    }
+
+    #[test]
+    fn test_parse_items_yaml_with_icons() {
+        let yaml =
+            "# 菜單\n珍珠奶茶: 50 | 🧋\n紅茶拿鐵: 45\n鬆餅: 80 | https://example.com/waffle.png\n";
+        let ItemList {
+            items,
+            item_icons: icons,
+            ..
+        } = parse_items_yaml(yaml).expect("parse should succeed");
+
+        assert_eq!(items.len(), 3);
+        assert_eq!(items.get("珍珠奶茶"), Some(&Decimal::new(50, 0)));
+        assert_eq!(icons.get("珍珠奶茶").map(String::as_str), Some("🧋"));
+        assert_eq!(
+            icons.get("鬆餅").map(String::as_str),
+            Some("https://example.com/waffle.png")
+        );
+        assert!(!icons.contains_key("紅茶拿鐵"));
+
+        // 加上圖示時價格仍然必填
+        assert!(parse_items_yaml("綠茶:  | 🍵").is_err());
+    }
+
+    #[test]
+    fn test_items_yaml_roundtrip_keeps_icons() {
+        let list = parse_items_yaml("珍珠奶茶: 50 | 🧋\n紅茶拿鐵: 45\n").unwrap();
+        let yaml = items_to_yaml(
+            &list.items,
+            &list.item_icons,
+            &list.item_sections,
+            &list.item_translations,
+        );
+        assert_eq!(parse_items_yaml(&yaml).unwrap(), list);
+    }
+
+    #[test]
+    fn test_items_yaml_translations() {
+        let list = parse_items_yaml(
+            "珍珠奶茶|Bubble tea: 50 | 🧋\n紅茶 | Black tea | こうちゃ: 30\n綠茶: 30\n",
+        )
+        .unwrap();
+
+        // 以第一個名稱為商品名稱儲存
+        assert_eq!(list.items.get("珍珠奶茶"), Some(&Decimal::new(50, 0)));
+        assert_eq!(
+            list.item_translations.get("珍珠奶茶"),
+            Some(&vec!["Bubble tea".to_string()])
+        );
+        assert_eq!(
+            list.item_translations.get("紅茶"),
+            Some(&vec!["Black tea".to_string(), "こうちゃ".to_string()])
+        );
+        assert!(!list.item_translations.contains_key("綠茶"));
+        assert_eq!(
+            list.item_icons.get("珍珠奶茶").map(String::as_str),
+            Some("🧋")
+        );
+
+        let yaml = items_to_yaml(
+            &list.items,
+            &list.item_icons,
+            &list.item_sections,
+            &list.item_translations,
+        );
+        assert!(yaml.contains("珍珠奶茶|Bubble tea: 50 | 🧋\n"));
+        assert_eq!(parse_items_yaml(&yaml).unwrap(), list);
+
+        assert!(parse_items_yaml("|Bubble tea: 50\n").is_err());
+        assert!(parse_items_yaml("珍珠奶茶||: 50\n").is_err());
+    }
+
+    #[test]
+    fn test_items_yaml_sections() {
+        let yaml = "紅茶: 30\n飲料:\n  珍珠奶茶: 50 | 🧋\n  綠茶: 30\n炸物:\n  雞排: 80\n";
+        let list = parse_items_yaml(yaml).unwrap();
+
+        assert_eq!(list.items.len(), 4);
+        assert_eq!(
+            list.item_sections.get("綠茶").map(String::as_str),
+            Some("飲料")
+        );
+        assert_eq!(
+            list.item_sections.get("雞排").map(String::as_str),
+            Some("炸物")
+        );
+        assert!(!list.item_sections.contains_key("紅茶"));
+        assert_eq!(
+            list.item_icons.get("珍珠奶茶").map(String::as_str),
+            Some("🧋")
+        );
+
+        let yaml2 = items_to_yaml(
+            &list.items,
+            &list.item_icons,
+            &list.item_sections,
+            &list.item_translations,
+        );
+        assert_eq!(
+            yaml2,
+            "紅茶: 30\n炸物:\n  雞排: 80\n飲料:\n  珍珠奶茶: 50 | 🧋\n  綠茶: 30\n"
+        );
+        assert_eq!(parse_items_yaml(&yaml2).unwrap(), list);
+
+        // 分類下沒有縮排的商品（常見於漏打價格）要回報錯誤
+        assert!(parse_items_yaml("綠茶:\n紅茶: 30\n").is_err());
+        assert!(parse_items_yaml("紅茶: 30\n綠茶:\n").is_err());
+    }
+
+    #[test]
+    fn test_long_item_list_is_split_across_textareas() {
+        let yaml: String = (0..30).map(|i| format!("商品{:02}: {}\n", i, i)).collect();
+        let elements = item_textarea_elements(&yaml, 100).unwrap();
+
+        // 30 行共 260 字，拆成 3 個區塊；最後一個區塊超過一半，再多一個追加用的空白文字框
+        assert_eq!(elements.len(), 4);
+        assert_eq!(elements[0].name, "items");
+        assert_eq!(elements[3].name, "items_4");
+        assert!(elements[3].default.is_none());
+        assert!(!elements[0].optional && elements[1].optional);
+        assert!(elements.iter().all(|e| e.max_length == Some(100)));
+
+        let submission: HashMap<String, serde_json::Value> = elements
+            .iter()
+            .map(|e| {
+                let value = e.default.clone().unwrap_or_default();
+                (e.name.clone(), serde_json::Value::String(value))
+            })
+            .collect();
+        let list = parse_items_yaml(&collect_items_text(&submission)).unwrap();
+        assert_eq!(list.items.len(), 30);
+
+        // 超過文字框數量或單行過長時回報錯誤而不是截斷
+        assert!(item_textarea_elements(&yaml, 20).is_err());
+        assert!(item_textarea_elements("很長的商品名稱: 100\n", 5).is_err());
+    }
 }
@@ -0,0 +1,285 @@
+//! 限時團購：`/group_buy flash 30m 五十嵐` 一個指令建立團購
+//!
+//! 沿用同商家最近一次團購的菜單，不開啟建立對話框，直接發布並設定自動截止時間。
+
+use super::*;
+use chrono::Utc;
+
+/// 限時團購的時限上限（小時）
+const MAX_FLASH_HOURS: i64 = 24;
+
+const FLASH_USAGE: &str = "用法：`/group_buy flash <時限> <商家名稱>`，例如 `/group_buy flash 30m 五十嵐`。時限可以是 `30m`、`1h`、`1h30m`";
+
+/// 解析時限，例如 `30m`、`2h`、`1h30m`（只接受小時與分鐘）。數值超出範圍時視為格式錯誤
+pub fn parse_duration(input: &str) -> Option<chrono::Duration> {
+    let mut total = chrono::Duration::zero();
+    let mut number = String::new();
+
+    for c in input.trim().to_lowercase().chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'h' | 'm' => {
+                let value: i64 = number.parse().ok()?;
+                number.clear();
+                let part = if c == 'h' {
+                    chrono::Duration::try_hours(value)?
+                } else {
+                    chrono::Duration::try_minutes(value)?
+                };
+                total = total.checked_add(&part)?;
+            }
+            _ => return None,
+        }
+    }
+
+    // 結尾沒有單位（例如 `30`）視為格式錯誤
+    (number.is_empty() && total > chrono::Duration::zero()).then_some(total)
+}
+
+/// 解析 `flash` 之後的參數，回傳 (時限, 商家名稱)。
+/// 頻道設定了預設截止時間（`default`）時可以省略時限。
+pub fn parse_flash_args(
+    args: &[&str],
+    default: Option<chrono::Duration>,
+) -> std::result::Result<(chrono::Duration, String), String> {
+    let (duration, merchant) = match (args.first().and_then(|d| parse_duration(d)), default) {
+        (Some(duration), _) => (duration, args[1..].join(" ")),
+        (None, Some(default)) => (default, args.join(" ")),
+        (None, None) => match args {
+            [duration, _, ..] => {
+                return Err(format!("無法解析時限「{}」。{}", duration, FLASH_USAGE));
+            }
+            _ => return Err(FLASH_USAGE.to_string()),
+        },
+    };
+    if merchant.is_empty() {
+        return Err(FLASH_USAGE.to_string());
+    }
+
+    if duration > chrono::Duration::hours(MAX_FLASH_HOURS) {
+        return Err(format!("限時團購最長 {} 小時", MAX_FLASH_HOURS));
+    }
+
+    Ok((duration, merchant))
+}
+
+fn ephemeral(text: String) -> WithStatus<Json> {
+    warp::reply::with_status(
+        warp::reply::json(&SlashCommandResponse {
+            response_type: "ephemeral".to_string(),
+            text,
+        }),
+        StatusCode::OK,
+    )
+}
+
+/// 建立並發布限時團購
+pub async fn handle_flash_command(
+    req: &SlashCommandRequest,
+    args: &[&str],
+    state_guard: &AppState,
+) -> WithStatus<Json> {
+    let settings = state_guard
+        .database
+        .get_channel_settings(&req.channel_id)
+        .await
+        .unwrap_or_else(|e| {
+            error!("取得頻道設定失敗: {}", e);
+            Default::default()
+        });
+    let default_duration = settings
+        .default_deadline_minutes
+        .and_then(chrono::Duration::try_minutes);
+
+    let (duration, merchant_input) = match parse_flash_args(args, default_duration) {
+        Ok(parsed) => parsed,
+        Err(msg) => return ephemeral(format!("⚠️ {}", msg)),
+    };
+
+    let known_merchants = state_guard
+        .database
+        .get_merchant_names(i64::MAX)
+        .await
+        .unwrap_or_else(|e| {
+            error!("取得既有商家名稱失敗: {}", e);
+            Vec::new()
+        });
+    let merchant_name = super::merchant::canonical_merchant_name(&merchant_input, &known_merchants);
+
+    // 沿用同商家最近一次的菜單
+    let previous = match state_guard
+        .database
+        .get_recent_group_buys_by_merchant(&merchant_name, "", 1)
+        .await
+    {
+        Ok(previous) => previous.into_iter().next(),
+        Err(e) => {
+            error!("取得之前的菜單失敗: {}", e);
+            return ephemeral("取得之前的菜單失敗".to_string());
+        }
+    };
+    let Some(previous) = previous else {
+        return ephemeral(format!(
+            "⚠️ 找不到「{}」之前的菜單，請先用 `/group_buy` 建立一次團購",
+            merchant_name
+        ));
+    };
+
+    let now = Utc::now();
+    let close_at = now + duration;
+    let close_at_label = close_at
+        .with_timezone(&chrono::Local)
+        .format("%Y-%m-%d %H:%M")
+        .to_string();
+    let metadata: HashMap<String, String> = [("截止時間".to_string(), close_at_label.clone())]
+        .into_iter()
+        .collect();
+
+    let group_buy = GroupBuy {
+        id: uuid::Uuid::new_v4().to_string(),
+        creator_id: req.user_id.clone(),
+        creator_username: req.user_name.clone(),
+        channel_id: req.channel_id.clone(),
+        post_id: None,
+        merchant_name: merchant_name.clone(),
+        description: None,
+        metadata,
+        items: previous.items,
+        item_icons: previous.item_icons,
+        item_sections: previous.item_sections,
+        item_translations: previous.item_translations,
+        order_fields: previous.order_fields,
+        currency: settings.currency().to_string(),
+        status: GroupBuyStatus::Active,
+        version: 1,
+        created_at: now,
+        updated_at: now,
+    };
+
+    if let Err(e) = state_guard.database.create_group_buy(&group_buy).await {
+        error!("儲存團購到資料庫失敗: {}", e);
+        return ephemeral(format!("儲存團購資料失敗: {}", e));
+    }
+    if let Err(e) = state_guard
+        .database
+        .set_deadline(&group_buy.id, close_at)
+        .await
+    {
+        error!("設定截止時間失敗: {}", e);
+        return ephemeral(format!("設定截止時間失敗: {}", e));
+    }
+
+    let bot_callback_url = super::utils::bot_callback_url_from_state(state_guard);
+    let message = generate_group_buy_message(
+        &group_buy.merchant_name,
+        &group_buy.description,
+        &group_buy.metadata,
+        &group_buy.status,
+        &group_buy.items,
+        &group_buy.item_icons,
+        &group_buy.item_sections,
+        &group_buy.currency,
+    );
+    let attachments = generate_action_buttons(
+        &group_buy.id,
+        &group_buy.status,
+        &bot_callback_url,
+        state_guard.mattermost_client.signer(),
+        &state_guard.config.group_buy.buttons,
+    );
+
+    // 與直接建立的團購一樣以建立者的身份顯示
+    let post = crate::mattermost::Post {
+        id: None,
+        channel_id: group_buy.channel_id.clone(),
+        message,
+        root_id: None,
+        props: Some(serde_json::json!({
+            "attachments": attachments,
+            GROUP_BUY_MARKER_PROP: group_buy_marker(&group_buy.id),
+            "override_username": group_buy.creator_username,
+            "override_icon_url": format!(
+                "{}/api/v4/users/{}/image",
+                state_guard.config.mattermost.url, group_buy.creator_id
+            ),
+        })),
+    };
+
+    match state_guard
+        .mattermost_client
+        .create_post_with_response(&post)
+        .await
+    {
+        Ok(post_id) => {
+            if let Err(e) = state_guard
+                .database
+                .update_post_id(&group_buy.id, &post_id)
+                .await
+            {
+                error!("更新 post_id 失敗: {}", e);
+            }
+        }
+        Err(e) => {
+            error!("發布限時團購貼文失敗: {}", e);
+            return ephemeral(format!("發布團購貼文失敗: {}", e));
+        }
+    }
+
+    info!(
+        "用戶 {} 建立限時團購: {} (ID: {})，{} 分鐘後截止",
+        req.user_name,
+        merchant_name,
+        group_buy.id,
+        duration.num_minutes()
+    );
+
+    ephemeral(format!(
+        "✅ 已建立限時團購「{}」，將於 {} 自動截止",
+        merchant_name, close_at_label
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30m"), Some(chrono::Duration::minutes(30)));
+        assert_eq!(parse_duration("2H"), Some(chrono::Duration::hours(2)));
+        assert_eq!(parse_duration("1h30m"), Some(chrono::Duration::minutes(90)));
+        assert_eq!(parse_duration("30"), None);
+        assert_eq!(parse_duration("0m"), None);
+        assert_eq!(parse_duration("m"), None);
+        assert_eq!(parse_duration("半小時"), None);
+        // 超出範圍的數值不會 panic
+        assert_eq!(parse_duration("9999999999999999h"), None);
+        assert_eq!(parse_duration("99999999999999999999m"), None);
+        assert_eq!(parse_duration("2000000000000h2000000000000h"), None);
+    }
+
+    #[test]
+    fn test_parse_flash_args() {
+        let (duration, merchant) = parse_flash_args(&["30m", "五十嵐", "918"], None).unwrap();
+        assert_eq!(duration, chrono::Duration::minutes(30));
+        assert_eq!(merchant, "五十嵐 918");
+
+        assert!(parse_flash_args(&["30m"], None).is_err());
+        assert!(parse_flash_args(&["soon", "五十嵐"], None).is_err());
+        assert!(parse_flash_args(&["25h", "五十嵐"], None).is_err());
+        assert!(parse_flash_args(&["9999999999999999h", "五十嵐"], None).is_err());
+    }
+
+    #[test]
+    fn test_parse_flash_args_uses_channel_default() {
+        let default = Some(chrono::Duration::hours(2));
+        let (duration, merchant) = parse_flash_args(&["五十嵐"], default).unwrap();
+        assert_eq!(duration, chrono::Duration::hours(2));
+        assert_eq!(merchant, "五十嵐");
+
+        // 有寫時限時以指定的為準
+        let (duration, _) = parse_flash_args(&["30m", "五十嵐"], default).unwrap();
+        assert_eq!(duration, chrono::Duration::minutes(30));
+        assert!(parse_flash_args(&["30m"], default).is_err());
+    }
+}
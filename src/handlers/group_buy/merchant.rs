@@ -0,0 +1,155 @@
+//! 商家名稱正規化
+//!
+//! 同一家店常被輸入成「50嵐」「五十嵐」「50 嵐」「５０嵐」，統計與歷史菜單會因此分散。
+//! 建立團購時先整理輸入，若與既有商家的比對鍵相同就沿用既有名稱。
+
+/// 整理顯示用的名稱：全形轉半形、去除前後空白、連續空白合併為一個
+pub fn normalize_merchant_name(name: &str) -> String {
+    let half_width: String = name.chars().map(to_half_width).collect();
+    half_width.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// 比對用的鍵：在 [`normalize_merchant_name`] 之外再轉小寫、移除空白，並把中文數字換成阿拉伯數字
+pub fn merchant_key(name: &str) -> String {
+    let normalized: String = normalize_merchant_name(name)
+        .to_lowercase()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    replace_chinese_numerals(&normalized)
+}
+
+/// 輸入的名稱若與既有商家相同（比對鍵一致），回傳既有名稱；否則回傳整理後的輸入
+pub fn canonical_merchant_name(input: &str, existing: &[String]) -> String {
+    let key = merchant_key(input);
+    existing
+        .iter()
+        .find(|name| merchant_key(name) == key)
+        .cloned()
+        .unwrap_or_else(|| normalize_merchant_name(input))
+}
+
+/// 移除比對鍵重複的名稱，保留先出現的寫法（呼叫端依最近使用排序）
+pub fn dedupe_merchant_names(names: Vec<String>) -> Vec<String> {
+    let mut seen = std::collections::HashSet::new();
+    names
+        .into_iter()
+        .filter(|name| seen.insert(merchant_key(name)))
+        .collect()
+}
+
+fn to_half_width(c: char) -> char {
+    match c {
+        '\u{3000}' => ' ',
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        _ => c,
+    }
+}
+
+fn chinese_digit(c: char) -> Option<u32> {
+    match c {
+        '〇' | '零' => Some(0),
+        '一' => Some(1),
+        '二' | '兩' => Some(2),
+        '三' => Some(3),
+        '四' => Some(4),
+        '五' => Some(5),
+        '六' => Some(6),
+        '七' => Some(7),
+        '八' => Some(8),
+        '九' => Some(9),
+        _ => None,
+    }
+}
+
+fn is_chinese_numeral(c: char) -> bool {
+    chinese_digit(c).is_some() || matches!(c, '十' | '百')
+}
+
+/// 將連續的中文數字換成阿拉伯數字，例如「五十嵐」→「50嵐」、「二〇二」→「202」
+fn replace_chinese_numerals(s: &str) -> String {
+    let mut out = String::new();
+    let mut run = String::new();
+    for c in s.chars() {
+        if is_chinese_numeral(c) {
+            run.push(c);
+            continue;
+        }
+        if !run.is_empty() {
+            out.push_str(&chinese_number(&run));
+            run.clear();
+        }
+        out.push(c);
+    }
+    if !run.is_empty() {
+        out.push_str(&chinese_number(&run));
+    }
+    out
+}
+
+fn chinese_number(run: &str) -> String {
+    // 沒有位數字時逐字轉換（「二〇二」）
+    if !run.contains(['十', '百']) {
+        return run
+            .chars()
+            .filter_map(chinese_digit)
+            .map(|d| char::from_digit(d, 10).unwrap_or('0'))
+            .collect();
+    }
+
+    let mut total = 0;
+    let mut current: Option<u32> = None;
+    for c in run.chars() {
+        match c {
+            '十' => {
+                total += current.unwrap_or(1) * 10;
+                current = None;
+            }
+            '百' => {
+                total += current.unwrap_or(1) * 100;
+                current = None;
+            }
+            _ => current = chinese_digit(c),
+        }
+    }
+    (total + current.unwrap_or(0)).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merchant_key_merges_variants() {
+        let key = merchant_key("50嵐");
+        assert_eq!(key, "50嵐");
+        assert_eq!(merchant_key("五十嵐"), key);
+        assert_eq!(merchant_key(" 50 嵐 "), key);
+        assert_eq!(merchant_key("５０嵐"), key);
+
+        assert_eq!(
+            merchant_key("ＳｔａｒＢｕｃｋｓ"),
+            merchant_key("starbucks ")
+        );
+        assert_eq!(merchant_key("十五"), "15");
+        assert_eq!(merchant_key("一百二十三"), "123");
+        assert_ne!(merchant_key("五十嵐"), merchant_key("清心福全"));
+    }
+
+    #[test]
+    fn test_canonical_merchant_name_prefers_existing() {
+        let existing = vec!["50嵐".to_string(), "清心福全".to_string()];
+        assert_eq!(canonical_merchant_name("五十嵐", &existing), "50嵐");
+        assert_eq!(
+            canonical_merchant_name("　可不可　 熟成 ", &existing),
+            "可不可 熟成"
+        );
+
+        let names = vec![
+            "五十嵐".to_string(),
+            "50嵐".to_string(),
+            "清心福全".to_string(),
+        ];
+        assert_eq!(dedupe_merchant_names(names), vec!["五十嵐", "清心福全"]);
+    }
+}
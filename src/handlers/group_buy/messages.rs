@@ -1,21 +1,106 @@
-use crate::database::{GroupBuyOrder, GroupBuyStatus};
+use super::deactivated::buyer_mention;
+use crate::config::{GroupBuyButtonsConfig, GroupBuyConfig, MessageLayout, RoundingConfig};
+use crate::database::{GroupBuy, GroupBuyOrder, GroupBuyStatus, ShortageAdjustment};
+use crate::locale::{LocaleFormat, format_datetime, format_number, format_short_datetime};
+use crate::mattermost::action_id;
+use crate::signing::StateSigner;
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+
+/// 依商品名稱、購買人、登記時間排序，讓每次產生的名單順序一致
+fn sorted_orders(orders: &[GroupBuyOrder]) -> Vec<&GroupBuyOrder> {
+    let mut sorted: Vec<&GroupBuyOrder> = orders.iter().collect();
+    sorted.sort_by(|a, b| {
+        a.item_name
+            .cmp(&b.item_name)
+            .then_with(|| a.buyer_username.cmp(&b.buyer_username))
+            .then_with(|| a.created_at.cmp(&b.created_at))
+    });
+    sorted
+}
+
+/// 依分類分組商品名稱：未分類的商品排在最前面，分類與商品都依名稱排序
+pub fn group_by_section<'a>(
+    names: impl IntoIterator<Item = &'a str>,
+    item_sections: &'a HashMap<String, String>,
+) -> BTreeMap<Option<&'a str>, Vec<&'a str>> {
+    let mut grouped: BTreeMap<Option<&str>, Vec<&str>> = BTreeMap::new();
+    for name in names {
+        grouped
+            .entry(item_sections.get(name).map(String::as_str))
+            .or_default()
+            .push(name);
+    }
+    for names in grouped.values_mut() {
+        names.sort_unstable();
+    }
+    grouped
+}
+
+/// 分類的顯示名稱，未分類的商品歸在「其他」
+pub fn section_label(section: Option<&str>) -> &str {
+    section.unwrap_or("其他")
+}
+
+fn is_icon_url(icon: &str) -> bool {
+    icon.starts_with("http://") || icon.starts_with("https://")
+}
+
+/// 商品圖示前綴（Markdown 用）：emoji 原樣顯示，網址則顯示為小縮圖
+pub fn item_icon_prefix(item_icons: &HashMap<String, String>, item_name: &str) -> String {
+    match item_icons.get(item_name) {
+        Some(icon) if is_icon_url(icon) => format!("![{}]({} =20x20) ", item_name, icon),
+        Some(icon) => format!("{} ", icon),
+        None => String::new(),
+    }
+}
+
+/// 依使用者語系選擇商品名稱的翻譯，沒有對應的翻譯時使用商品名稱本身
+pub fn item_label<'a>(
+    item_translations: &'a HashMap<String, Vec<String>>,
+    item_name: &'a str,
+    locale_index: Option<usize>,
+) -> &'a str {
+    locale_index
+        .and_then(|index| item_translations.get(item_name)?.get(index))
+        .map(String::as_str)
+        .unwrap_or(item_name)
+}
+
+/// 商品圖示前綴（純文字用，例如 Dialog 選項）：網址與 `:shortcode:` 無法顯示，直接略過
+pub fn item_icon_plain_prefix(item_icons: &HashMap<String, String>, item_name: &str) -> String {
+    match item_icons.get(item_name) {
+        Some(icon) if !is_icon_url(icon) && !(icon.starts_with(':') && icon.ends_with(':')) => {
+            format!("{} ", icon)
+        }
+        _ => String::new(),
+    }
+}
 
 /// 生成團購訊息內容
+#[allow(clippy::too_many_arguments)]
 pub fn generate_group_buy_message(
     merchant_name: &str,
     description: &Option<String>,
     metadata: &HashMap<String, String>,
     status: &GroupBuyStatus,
     items: &HashMap<String, Decimal>,
+    item_icons: &HashMap<String, String>,
+    item_sections: &HashMap<String, String>,
+    currency: &str,
 ) -> String {
     let mut msg = String::new();
 
     // 狀態標記
-    if *status == GroupBuyStatus::Closed {
-        msg.push_str("🔒 **【已截止】** ");
+    match status {
+        GroupBuyStatus::Draft => msg.push_str("📝 **【草稿】** "),
+        GroupBuyStatus::Active => {}
+        GroupBuyStatus::Closed => msg.push_str("🔒 **【已截止】** "),
+        GroupBuyStatus::Ordered => msg.push_str("📦 **【已下單】** "),
+        GroupBuyStatus::Archived => msg.push_str("🗄️ **【已封存】** "),
     }
 
     msg.push_str(&format!("🛒 **【團購】{}**\n\n", merchant_name));
@@ -30,7 +115,8 @@ pub fn generate_group_buy_message(
     // 其他資訊
     if !metadata.is_empty() {
         msg.push_str("ℹ️ **其他資訊:**\n");
-        for (key, value) in metadata {
+        let sorted_metadata: BTreeMap<_, _> = metadata.iter().collect();
+        for (key, value) in sorted_metadata {
             msg.push_str(&format!("• {}: {}\n", key, value));
         }
         msg.push('\n');
@@ -39,9 +125,19 @@ pub fn generate_group_buy_message(
     // 商品列表（如果有且不只是範例）
     if !(items.is_empty() || (items.len() == 1 && items.contains_key("範例商品"))) {
         msg.push_str("🍱 **商品列表:**\n");
-        for (item, price) in items {
-            // 格式化價格，移除不必要的尾部零
-            msg.push_str(&format!("• {} - NT${}\n", item, price));
+        for (section, names) in group_by_section(items.keys().map(String::as_str), item_sections) {
+            if let Some(section) = section {
+                msg.push_str(&format!("**{}**\n", section));
+            }
+            for item in names {
+                msg.push_str(&format!(
+                    "• {}{} - {}{}\n",
+                    item_icon_prefix(item_icons, item),
+                    item,
+                    currency,
+                    items[item]
+                ));
+            }
         }
         msg.push('\n');
     }
@@ -51,101 +147,311 @@ pub fn generate_group_buy_message(
     msg
 }
 
-/// 生成操作按鈕
+/// 依設定篩選並排序按鈕；該狀態沒有設定時原樣回傳
+fn apply_button_config(
+    status: &GroupBuyStatus,
+    actions: Vec<serde_json::Value>,
+    buttons: &GroupBuyButtonsConfig,
+) -> Vec<serde_json::Value> {
+    let Some(wanted) = buttons.for_status(&status.to_string()) else {
+        return actions;
+    };
+    let action_name = |a: &serde_json::Value| {
+        a["integration"]["context"]["action"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string()
+    };
+    wanted
+        .iter()
+        .filter_map(|name| actions.iter().find(|a| action_name(a) == *name).cloned())
+        .collect()
+}
+
+/// 團購貼文 props 中標記所屬團購的欄位。即使資料庫的 post_id 遺失，
+/// 也能掃描頻道歷史找回 bot 發出的團購貼文
+pub const GROUP_BUY_MARKER_PROP: &str = "leko_group_buy";
+
+/// 團購標記的格式版本，欄位改變時遞增
+pub const GROUP_BUY_MARKER_SCHEMA_VERSION: u32 = 1;
+
+/// 團購貼文上的機器可讀標記
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupBuyMarker {
+    pub group_buy_id: String,
+    pub schema_version: u32,
+}
+
+/// 團購標記，放在貼文 props 的 `GROUP_BUY_MARKER_PROP` 欄位
+pub fn group_buy_marker(group_buy_id: &str) -> serde_json::Value {
+    json!(GroupBuyMarker {
+        group_buy_id: group_buy_id.to_string(),
+        schema_version: GROUP_BUY_MARKER_SCHEMA_VERSION,
+    })
+}
+
+/// 團購貼文的 props：按鈕與團購標記。
+/// 更新貼文時 props 會被整個取代，每次更新都要帶上標記
+pub fn group_buy_post_props(
+    group_buy_id: &str,
+    attachments: &[serde_json::Value],
+) -> serde_json::Value {
+    json!({
+        "attachments": attachments,
+        GROUP_BUY_MARKER_PROP: group_buy_marker(group_buy_id),
+    })
+}
+
+/// 從貼文 props 讀取團購標記，沒有標記或格式不符時回傳 None
+pub fn parse_group_buy_marker(props: &serde_json::Value) -> Option<GroupBuyMarker> {
+    props
+        .get(GROUP_BUY_MARKER_PROP)
+        .and_then(|marker| serde_json::from_value(marker.clone()).ok())
+}
+
+/// 生成操作按鈕（context 皆經過簽章，團購貼文長期存在故不設定到期時間）。
+/// `buttons` 可依狀態隱藏或重新排列按鈕，見 `group_buy.buttons` 設定。
 pub fn generate_action_buttons(
     group_buy_id: &str,
     status: &GroupBuyStatus,
     bot_callback_url: &str,
+    signer: &StateSigner,
+    buttons: &GroupBuyButtonsConfig,
 ) -> Vec<serde_json::Value> {
     let mut actions = Vec::new();
 
-    // 移除 group_buy_id 中的 hyphen，使其成為有效的 action id
-    let clean_id = group_buy_id.replace("-", "");
-
     match status {
+        // 封存後貼文凍結，不再提供任何按鈕
+        GroupBuyStatus::Archived => return Vec::new(),
+        GroupBuyStatus::Draft => {
+            // 草稿只有建立者看得到，只提供編輯與發布
+            actions.push(json!({
+                "id": action_id("edititems", group_buy_id),
+                "name": "編輯商品",
+                "type": "button",
+                "integration": {
+                    "url": format!("{}/api/v1/group_buy/action/edit_items", bot_callback_url.trim_end_matches('/')),
+                    "context": signer.sign_context(json!({
+                        "action": "edit_items",
+                        "group_buy_id": group_buy_id,
+                    }), None)
+                }
+            }));
+
+            actions.push(json!({
+                "id": action_id("publish", group_buy_id),
+                "name": "發布",
+                "type": "button",
+                "style": "primary",
+                "integration": {
+                    "url": format!("{}/api/v1/group_buy/action/publish", bot_callback_url.trim_end_matches('/')),
+                    "context": signer.sign_context(json!({
+                        "action": "publish",
+                        "group_buy_id": group_buy_id,
+                    }), None)
+                }
+            }));
+
+            return vec![json!({
+                "actions": apply_button_config(status, actions, buttons)
+            })];
+        }
         GroupBuyStatus::Active => {
             // 編輯商品
             actions.push(json!({
-                "id": format!("edititems{}", clean_id),
+                "id": action_id("edititems", group_buy_id),
                 "name": "編輯商品",
                 "type": "button",
                 "integration": {
                     "url": format!("{}/api/v1/group_buy/action/edit_items", bot_callback_url.trim_end_matches('/')),
-                    "context": {
+                    "context": signer.sign_context(json!({
                         "action": "edit_items",
                         "group_buy_id": group_buy_id,
-                    }
+                    }), None)
                 }
             }));
 
             // 登記
             actions.push(json!({
-                "id": format!("register{}", clean_id),
+                "id": action_id("register", group_buy_id),
                 "name": "登記",
                 "type": "button",
                 "integration": {
                     "url": format!("{}/api/v1/group_buy/action/register", bot_callback_url.trim_end_matches('/')),
-                    "context": {
+                    "context": signer.sign_context(json!({
                         "action": "register",
                         "group_buy_id": group_buy_id,
-                    }
+                    }), None)
                 }
             }));
 
             // 取消登記（清除某一被登記人的所有登記）
             actions.push(json!({
-                "id": format!("cancelregister{}", clean_id),
+                "id": action_id("cancelregister", group_buy_id),
                 "name": "取消登記",
                 "type": "button",
                 "integration": {
                     "url": format!("{}/api/v1/group_buy/action/cancel_register", bot_callback_url.trim_end_matches('/')),
-                    "context": {
+                    "context": signer.sign_context(json!({
                         "action": "cancel_register",
                         "group_buy_id": group_buy_id,
-                    }
+                    }), None)
                 }
             }));
 
             // 截止
             actions.push(json!({
-                "id": format!("close{}", clean_id),
+                "id": action_id("close", group_buy_id),
                 "name": "截止",
                 "type": "button",
                 "integration": {
                     "url": format!("{}/api/v1/group_buy/action/close", bot_callback_url.trim_end_matches('/')),
-                    "context": {
+                    "context": signer.sign_context(json!({
                         "action": "close",
                         "group_buy_id": group_buy_id,
-                    }
+                    }), None)
                 }
             }));
         }
         GroupBuyStatus::Closed => {
             // 重新開放
             actions.push(json!({
-                "id": format!("reopen{}", clean_id),
+                "id": action_id("reopen", group_buy_id),
                 "name": "重新開放",
                 "type": "button",
                 "integration": {
                     "url": format!("{}/api/v1/group_buy/action/reopen", bot_callback_url.trim_end_matches('/')),
-                    "context": {
+                    "context": signer.sign_context(json!({
                         "action": "reopen",
                         "group_buy_id": group_buy_id,
-                    }
+                    }), None)
+                }
+            }));
+
+            // 已下單
+            actions.push(json!({
+                "id": action_id("markordered", group_buy_id),
+                "name": "已下單",
+                "type": "button",
+                "integration": {
+                    "url": format!("{}/api/v1/group_buy/action/mark_ordered", bot_callback_url.trim_end_matches('/')),
+                    "context": signer.sign_context(json!({
+                        "action": "mark_ordered",
+                        "group_buy_id": group_buy_id,
+                    }), None)
                 }
             }));
 
             // 調整缺貨
             actions.push(json!({
-                "id": format!("adjustshortage{}", clean_id),
+                "id": action_id("adjustshortage", group_buy_id),
+                "name": "調整缺貨",
+                "type": "button",
+                "integration": {
+                    "url": format!("{}/api/v1/group_buy/action/adjust_shortage", bot_callback_url.trim_end_matches('/')),
+                    "context": signer.sign_context(json!({
+                        "action": "adjust_shortage",
+                        "group_buy_id": group_buy_id,
+                    }), None)
+                }
+            }));
+
+            // 調整紀錄
+            actions.push(json!({
+                "id": action_id("adjustmenthistory", group_buy_id),
+                "name": "調整紀錄",
+                "type": "button",
+                "integration": {
+                    "url": format!("{}/api/v1/group_buy/action/adjustment_history", bot_callback_url.trim_end_matches('/')),
+                    "context": signer.sign_context(json!({
+                        "action": "adjustment_history",
+                        "group_buy_id": group_buy_id,
+                    }), None)
+                }
+            }));
+
+            // 付款狀態
+            actions.push(json!({
+                "id": action_id("payments", group_buy_id),
+                "name": "付款狀態",
+                "type": "button",
+                "integration": {
+                    "url": format!("{}/api/v1/group_buy/action/payments", bot_callback_url.trim_end_matches('/')),
+                    "context": signer.sign_context(json!({
+                        "action": "payments",
+                        "group_buy_id": group_buy_id,
+                    }), None)
+                }
+            }));
+
+            // 封存
+            actions.push(json!({
+                "id": action_id("archive", group_buy_id),
+                "name": "封存",
+                "type": "button",
+                "integration": {
+                    "url": format!("{}/api/v1/group_buy/action/archive", bot_callback_url.trim_end_matches('/')),
+                    "context": signer.sign_context(json!({
+                        "action": "archive",
+                        "group_buy_id": group_buy_id,
+                    }), None)
+                }
+            }));
+        }
+        GroupBuyStatus::Ordered => {
+            // 已下單後登記鎖定，仍可調整缺貨
+            actions.push(json!({
+                "id": action_id("adjustshortage", group_buy_id),
                 "name": "調整缺貨",
                 "type": "button",
                 "integration": {
                     "url": format!("{}/api/v1/group_buy/action/adjust_shortage", bot_callback_url.trim_end_matches('/')),
-                    "context": {
+                    "context": signer.sign_context(json!({
                         "action": "adjust_shortage",
                         "group_buy_id": group_buy_id,
-                    }
+                    }), None)
+                }
+            }));
+
+            // 調整紀錄
+            actions.push(json!({
+                "id": action_id("adjustmenthistory", group_buy_id),
+                "name": "調整紀錄",
+                "type": "button",
+                "integration": {
+                    "url": format!("{}/api/v1/group_buy/action/adjustment_history", bot_callback_url.trim_end_matches('/')),
+                    "context": signer.sign_context(json!({
+                        "action": "adjustment_history",
+                        "group_buy_id": group_buy_id,
+                    }), None)
+                }
+            }));
+
+            // 付款狀態
+            actions.push(json!({
+                "id": action_id("payments", group_buy_id),
+                "name": "付款狀態",
+                "type": "button",
+                "integration": {
+                    "url": format!("{}/api/v1/group_buy/action/payments", bot_callback_url.trim_end_matches('/')),
+                    "context": signer.sign_context(json!({
+                        "action": "payments",
+                        "group_buy_id": group_buy_id,
+                    }), None)
+                }
+            }));
+
+            // 封存
+            actions.push(json!({
+                "id": action_id("archive", group_buy_id),
+                "name": "封存",
+                "type": "button",
+                "integration": {
+                    "url": format!("{}/api/v1/group_buy/action/archive", bot_callback_url.trim_end_matches('/')),
+                    "context": signer.sign_context(json!({
+                        "action": "archive",
+                        "group_buy_id": group_buy_id,
+                    }), None)
                 }
             }));
         }
@@ -153,77 +459,1569 @@ pub fn generate_action_buttons(
 
     // 這些按鈕在任何狀態都顯示
     actions.push(json!({
-        "id": format!("shoppinglist{}", clean_id),
+        "id": action_id("shoppinglist", group_buy_id),
         "name": "採購列表",
         "type": "button",
         "integration": {
             "url": format!("{}/api/v1/group_buy/action/shopping_list", bot_callback_url.trim_end_matches('/')),
-            "context": {
+            "context": signer.sign_context(json!({
                 "action": "shopping_list",
                 "group_buy_id": group_buy_id,
-            }
+            }), None)
         }
     }));
 
     actions.push(json!({
-        "id": format!("subtotal{}", clean_id),
+        "id": action_id("subtotal", group_buy_id),
         "name": "小計",
         "type": "button",
         "integration": {
             "url": format!("{}/api/v1/group_buy/action/subtotal", bot_callback_url.trim_end_matches('/')),
-            "context": {
+            "context": signer.sign_context(json!({
                 "action": "subtotal",
                 "group_buy_id": group_buy_id,
-            }
+            }), None)
+        }
+    }));
+
+    actions.push(json!({
+        "id": action_id("myregistrations", group_buy_id),
+        "name": "我登記的",
+        "type": "button",
+        "integration": {
+            "url": format!("{}/api/v1/group_buy/action/my_registrations", bot_callback_url.trim_end_matches('/')),
+            "context": signer.sign_context(json!({
+                "action": "my_registrations",
+                "group_buy_id": group_buy_id,
+            }), None)
+        }
+    }));
+
+    actions.push(json!({
+        "id": action_id("mysubtotal", group_buy_id),
+        "name": "私訊我的小計",
+        "type": "button",
+        "integration": {
+            "url": format!("{}/api/v1/group_buy/action/my_subtotal", bot_callback_url.trim_end_matches('/')),
+            "context": signer.sign_context(json!({
+                "action": "my_subtotal",
+                "group_buy_id": group_buy_id,
+            }), None)
         }
     }));
 
     vec![json!({
-        "actions": actions
+        "actions": apply_button_config(status, actions, buttons)
     })]
 }
 
+/// 登記前選擇分類的按鈕，按下後開啟只列出該分類商品的登記 Dialog
+pub fn generate_section_picker(
+    group_buy_id: &str,
+    sections: &[Option<&str>],
+    bot_callback_url: &str,
+    signer: &StateSigner,
+) -> Vec<serde_json::Value> {
+    let actions: Vec<serde_json::Value> = sections
+        .iter()
+        .map(|section| {
+            let value = section.unwrap_or("");
+            json!({
+                "id": action_id("registersection", &format!("{}:{}", group_buy_id, value)),
+                "name": section_label(*section),
+                "type": "button",
+                "integration": {
+                    "url": format!("{}/api/v1/group_buy/action/register", bot_callback_url.trim_end_matches('/')),
+                    "context": signer.sign_context(json!({
+                        "action": "register",
+                        "group_buy_id": group_buy_id,
+                        "section": value,
+                    }), None)
+                }
+            })
+        })
+        .collect();
+
+    vec![json!({ "actions": actions })]
+}
+
 /// 生成包含訂單的團購訊息
+#[allow(clippy::too_many_arguments)]
 pub fn generate_group_buy_message_with_orders(
     merchant_name: &str,
     description: &Option<String>,
     metadata: &HashMap<String, String>,
     status: &GroupBuyStatus,
     items: &HashMap<String, Decimal>,
+    item_icons: &HashMap<String, String>,
+    item_sections: &HashMap<String, String>,
+    currency: &str,
     orders: &[GroupBuyOrder],
+    deactivated: &HashSet<String>,
+    config: &GroupBuyConfig,
 ) -> String {
-    let mut msg = generate_group_buy_message(merchant_name, description, metadata, status, items);
+    let mut msg = generate_group_buy_message(
+        merchant_name,
+        description,
+        metadata,
+        status,
+        items,
+        item_icons,
+        item_sections,
+        currency,
+    );
+
+    if !orders.is_empty() && config.layout == MessageLayout::Compact {
+        msg.push_str(&compact_registration_summary(orders, item_icons));
+        return msg;
+    }
 
     if !orders.is_empty() {
         msg.push_str("\n📋 **登記名單:**\n");
 
-        // 按商品分組
-        let mut orders_by_item: HashMap<String, Vec<&GroupBuyOrder>> = HashMap::new();
-        for order in orders {
+        // 按商品分組（商品與購買人皆排序）
+        let mut orders_by_item: BTreeMap<String, Vec<&GroupBuyOrder>> = BTreeMap::new();
+        for order in sorted_orders(orders) {
             orders_by_item
                 .entry(order.item_name.clone())
                 .or_default()
                 .push(order);
         }
 
+        let mut listed = 0;
         for (item_name, item_orders) in orders_by_item {
+            if listed >= config.max_listed_orders {
+                break;
+            }
             let total_qty: i32 = item_orders.iter().map(|o| o.quantity).sum();
-            msg.push_str(&format!("\n**{}** (共 {} 份):\n", item_name, total_qty));
+            msg.push_str(&format!(
+                "\n{}**{}** (共 {} 份):\n",
+                item_icon_prefix(item_icons, &item_name),
+                item_name,
+                total_qty
+            ));
 
             for order in item_orders {
+                if listed >= config.max_listed_orders {
+                    break;
+                }
+                listed += 1;
                 let registrar_note = if order.registrar_id != order.buyer_id {
                     format!(" (由 @{} 登記)", order.registrar_username)
                 } else {
                     String::new()
                 };
                 msg.push_str(&format!(
-                    "• @{} x{}{}\n",
-                    order.buyer_username, order.quantity, registrar_note
+                    "• {} x{}{}\n",
+                    buyer_mention(&order.buyer_id, &order.buyer_username, deactivated),
+                    order.quantity,
+                    registrar_note
                 ));
             }
         }
+        if orders.len() > listed {
+            msg.push_str(&format!(
+                "\n…另有 {} 筆登記未顯示，請按「完整名單」查看\n",
+                orders.len() - listed
+            ));
+        }
+        msg.push('\n');
+    }
+
+    msg
+}
+
+/// 貼文是否沒有列出所有登記（精簡格式或超過 `max_listed_orders`）
+pub fn hides_orders(order_count: usize, config: &GroupBuyConfig) -> bool {
+    order_count > 0
+        && (config.layout == MessageLayout::Compact || order_count > config.max_listed_orders)
+}
+
+/// 貼文沒有列出所有登記時，在按鈕列加上「完整名單」
+pub fn add_full_list_button_if_needed(
+    attachments: &mut [serde_json::Value],
+    group_buy_id: &str,
+    order_count: usize,
+    config: &GroupBuyConfig,
+    bot_callback_url: &str,
+    signer: &StateSigner,
+) {
+    if !hides_orders(order_count, config) {
+        return;
+    }
+    let Some(actions) = attachments
+        .first_mut()
+        .and_then(|a| a["actions"].as_array_mut())
+    else {
+        return;
+    };
+    actions.push(json!({
+        "id": action_id("fulllist", group_buy_id),
+        "name": "完整名單",
+        "type": "button",
+        "integration": {
+            "url": format!("{}/api/v1/group_buy/action/full_list", bot_callback_url.trim_end_matches('/')),
+            "context": signer.sign_context(json!({
+                "action": "full_list",
+                "group_buy_id": group_buy_id,
+            }), None)
+        }
+    }));
+}
+
+/// 完整登記名單表格（以臨時訊息回覆）
+pub fn generate_full_order_table(
+    merchant_name: &str,
+    orders: &[GroupBuyOrder],
+    item_icons: &HashMap<String, String>,
+    deactivated: &HashSet<String>,
+) -> String {
+    let mut table = format!(
+        "### 📋 {} 完整登記名單（{} 筆）\n\n| 被登記人 | 商品 | 數量 | 登記人 |\n|---|---|---:|---|\n",
+        merchant_name,
+        orders.len()
+    );
+    for order in sorted_orders(orders) {
+        table.push_str(&format!(
+            "| {} | {}{} | {} | @{} |\n",
+            buyer_mention(&order.buyer_id, &order.buyer_username, deactivated),
+            item_icon_plain_prefix(item_icons, &order.item_name),
+            order.item_name,
+            order.quantity,
+            order.registrar_username
+        ));
+    }
+    table
+}
+
+/// 精簡版登記名單：每個商品一行，只列總數與人數
+fn compact_registration_summary(
+    orders: &[GroupBuyOrder],
+    item_icons: &HashMap<String, String>,
+) -> String {
+    // 商品名稱 -> (總數, 購買人)
+    let mut per_item: BTreeMap<&str, (i32, HashSet<&str>)> = BTreeMap::new();
+    let mut buyers = HashSet::new();
+    for order in orders {
+        let entry = per_item.entry(&order.item_name).or_default();
+        entry.0 += order.quantity;
+        entry.1.insert(&order.buyer_id);
+        buyers.insert(order.buyer_id.as_str());
+    }
+
+    let mut out = format!("\n📋 **登記統計:** 共 {} 人\n", buyers.len());
+    for (item_name, (total_qty, item_buyers)) in per_item {
+        out.push_str(&format!(
+            "• {}{} x{}（{} 人）\n",
+            item_icon_prefix(item_icons, item_name),
+            item_name,
+            total_qty,
+            item_buyers.len()
+        ));
+    }
+    out.push('\n');
+    out
+}
+
+/// 採購列表：各商品總數、單價與小計（依分類與商品名稱排序）
+pub fn generate_shopping_list(
+    group_buy: &GroupBuy,
+    orders: &[GroupBuyOrder],
+    rounding: &RoundingConfig,
+    locale: &LocaleFormat,
+) -> String {
+    // 統計每個商品的總數量
+    let mut shopping_list: BTreeMap<&str, i32> = BTreeMap::new();
+    for order in orders {
+        *shopping_list.entry(&order.item_name).or_insert(0) += order.quantity;
+    }
+
+    // 計算統計資訊
+    let num_items = shopping_list.len();
+    let num_people: HashSet<_> = orders.iter().map(|o| o.buyer_id.as_str()).collect();
+
+    // 生成採購列表訊息（使用表格）
+    let mut msg = "### 🛍️ 採購列表\n\n".to_string();
+    msg.push_str(&format!(
+        "**商家：{}  •  品項：{}  •  人數：{}**\n\n",
+        group_buy.merchant_name,
+        num_items,
+        num_people.len()
+    ));
+    msg.push_str("| 商品 | 數量 | 單價 | 小計 |\n");
+    msg.push_str("|------|-----:|-----:|-----:|\n");
+
+    let grouped = group_by_section(shopping_list.keys().copied(), &group_buy.item_sections);
+    for (section, names) in grouped {
+        if let Some(section) = section {
+            msg.push_str(&format!("| **{}** | | | |\n", section));
+        }
+        for item_name in names {
+            let total_qty = shopping_list[item_name];
+            let price = group_buy
+                .items
+                .get(item_name)
+                .copied()
+                .unwrap_or(Decimal::ZERO);
+            let subtotal = crate::money::line_total(price, total_qty);
+            msg.push_str(&format!(
+                "| {}{} | {} | {cur}{} | {cur}{} |\n",
+                item_icon_prefix(&group_buy.item_icons, item_name),
+                item_name,
+                total_qty,
+                format_number(price, locale),
+                format_number(subtotal, locale),
+                cur = group_buy.currency
+            ));
+
+            // 有訂單自訂欄位時，列出各選項的數量（例如內用 x1、外帶 x2）
+            let mut by_fields: BTreeMap<String, i32> = BTreeMap::new();
+            for order in orders.iter().filter(|o| o.item_name == item_name) {
+                if !order.custom_fields.is_empty() {
+                    *by_fields
+                        .entry(super::order_fields::format_custom_fields(
+                            &order.custom_fields,
+                        ))
+                        .or_insert(0) += order.quantity;
+                }
+            }
+            for (fields, qty) in by_fields {
+                msg.push_str(&format!("| ↳ {} | {} | | |\n", fields, qty));
+            }
+        }
+    }
+
+    // 計算總金額（使用 Decimal 進行精確計算）
+    let total_amount: Decimal = orders
+        .iter()
+        .map(|o| crate::money::line_total(o.unit_price, o.quantity))
+        .sum();
+
+    match service_fee_percent(group_buy) {
+        Some(percent) => {
+            let fee_total = crate::money::percent_of(total_amount, percent, rounding);
+            msg.push_str(&format!(
+                "\n商品合計：{cur}{}  •  服務費 {}%：{cur}{}\n**💰 總金額：{cur}{}**",
+                format_number(total_amount, locale),
+                format_number(percent, locale),
+                format_number(fee_total, locale),
+                format_number(total_amount + fee_total, locale),
+                cur = group_buy.currency
+            ));
+        }
+        None => msg.push_str(&format!(
+            "\n**💰 總金額：{}{}**",
+            group_buy.currency,
+            format_number(total_amount, locale)
+        )),
+    }
+    msg
+}
+
+/// 缺貨調整紀錄：依時間先後列出誰在何時把誰的哪個商品從幾份改成幾份
+pub fn generate_adjustment_history(
+    adjustments: &[ShortageAdjustment],
+    locale: &LocaleFormat,
+) -> String {
+    if adjustments.is_empty() {
+        return "尚無缺貨調整紀錄".to_string();
+    }
+
+    let mut msg = String::from(
+        "### 🗒️ 調整紀錄\n\n| 時間 | 調整人 | 購買人 | 商品 | 數量 |\n|------|--------|--------|------|------|\n",
+    );
+    for adjustment in adjustments {
+        msg.push_str(&format!(
+            "| {} | @{} | @{} | {} | {} → {} |\n",
+            format_short_datetime(adjustment.created_at.with_timezone(&chrono::Local), locale),
+            adjustment.adjuster_username,
+            adjustment.buyer_username,
+            adjustment.item_name,
+            adjustment.old_quantity,
+            adjustment.new_quantity
+        ));
+    }
+    msg
+}
+
+/// 封存時貼到討論串的最終摘要：採購列表、個人小計（含付款狀態）與缺貨調整紀錄
+pub fn generate_archive_summary(
+    group_buy: &GroupBuy,
+    orders: &[GroupBuyOrder],
+    adjustments: &[ShortageAdjustment],
+    paid_buyers: &HashSet<String>,
+    deactivated: &HashSet<String>,
+    archiver_username: &str,
+    rounding: &RoundingConfig,
+    locale: &LocaleFormat,
+) -> String {
+    let mut msg = format!(
+        "## 🗄️ 團購已封存：{}\n\n由 @{} 於 {} 封存，以下為最終紀錄，之後不再變更。\n\n",
+        group_buy.merchant_name,
+        archiver_username,
+        format_datetime(chrono::Local::now(), locale)
+    );
+    if orders.is_empty() {
+        msg.push_str("沒有任何登記。\n\n");
+    } else {
+        msg.push_str(&generate_shopping_list(group_buy, orders, rounding, locale));
+        msg.push_str("\n\n");
+        msg.push_str(&generate_subtotal_table(
+            group_buy,
+            orders,
+            Some(paid_buyers),
+            deactivated,
+            rounding,
+            locale,
+        ));
+        msg.push_str("\n\n");
+    }
+    msg.push_str(&generate_adjustment_history(adjustments, locale));
+    msg
+}
+
+/// 其他資訊中代表服務費比例的欄位，例如 `服務費: 5%`
+pub const SERVICE_FEE_KEY: &str = "服務費";
+
+/// 解析服務費比例（百分比），`5%`、`5`、`2.5 %` 皆可，必須介於 0 到 100
+pub fn parse_service_fee(value: &str) -> Result<Decimal, String> {
+    let number = value.trim().trim_end_matches(['%', '％']).trim();
+    match Decimal::from_str(number) {
+        Ok(percent) if percent >= Decimal::ZERO && percent <= Decimal::ONE_HUNDRED => {
+            Ok(percent.normalize())
+        }
+        Ok(_) => Err("服務費必須介於 0% 到 100%".to_string()),
+        Err(_) => Err(format!(
+            "無法解析服務費「{}」，請填寫百分比，例如 `5%`",
+            value
+        )),
+    }
+}
+
+/// 團購設定的服務費比例，沒有設定、無法解析或為 0 時回傳 None
+pub fn service_fee_percent(group_buy: &GroupBuy) -> Option<Decimal> {
+    group_buy
+        .metadata
+        .get(SERVICE_FEE_KEY)
+        .and_then(|value| parse_service_fee(value).ok())
+        .filter(|percent| !percent.is_zero())
+}
+
+/// 每位購買人（使用者名稱, buyer_id）的商品金額
+fn buyer_subtotals(orders: &[GroupBuyOrder]) -> HashMap<(&str, &str), Decimal> {
+    let mut subtotals: HashMap<(&str, &str), Decimal> = HashMap::new();
+    for order in orders {
+        *subtotals
+            .entry((order.buyer_username.as_str(), order.buyer_id.as_str()))
+            .or_insert(Decimal::ZERO) += crate::money::line_total(order.unit_price, order.quantity);
+    }
+    subtotals
+}
+
+/// 每位購買人（使用者名稱, buyer_id）分攤的服務費，與 [`buyer_subtotals`] 的分組相同：
+/// 依全部商品合計計算服務費，再依各自的商品金額分攤，各人的服務費加總等於採購列表的服務費
+pub fn buyer_service_fees<'a>(
+    orders: &'a [GroupBuyOrder],
+    percent: Decimal,
+    rounding: &RoundingConfig,
+) -> HashMap<(&'a str, &'a str), Decimal> {
+    let mut subtotals: Vec<_> = buyer_subtotals(orders).into_iter().collect();
+    // 分攤的餘數依 buyer_id（相同時依使用者名稱）順序分配，結果不受訂單順序影響
+    subtotals.sort_by(|a, b| a.0.1.cmp(b.0.1).then_with(|| a.0.0.cmp(b.0.0)));
+    let amounts: Vec<Decimal> = subtotals.iter().map(|(_, amount)| *amount).collect();
+    let fee_total = crate::money::percent_of(amounts.iter().sum(), percent, rounding);
+    subtotals
+        .iter()
+        .map(|(key, _)| *key)
+        .zip(crate::money::split(fee_total, &amounts, rounding))
+        .collect()
+}
+
+/// 個人小計：依金額由高到低排序，金額相同時依購買人名稱排序。
+/// 設定了服務費時多兩欄服務費與應付金額；有 `paid_buyers`（已截止後）時多一欄付款狀態，
+/// 已付款 ✅、未付款 ❌
+pub fn generate_subtotal_table(
+    group_buy: &GroupBuy,
+    orders: &[GroupBuyOrder],
+    paid_buyers: Option<&HashSet<String>>,
+    deactivated: &HashSet<String>,
+    rounding: &RoundingConfig,
+    locale: &LocaleFormat,
+) -> String {
+    // 按購買人分組統計（使用 Decimal 進行精確計算）
+    let mut sorted_subtotals: Vec<_> = buyer_subtotals(orders).into_iter().collect();
+    sorted_subtotals.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let fee_percent = service_fee_percent(group_buy);
+    let fees = fee_percent.map(|percent| buyer_service_fees(orders, percent, rounding));
+
+    // 生成小計訊息（使用表格）
+    let mut msg = "### 💰 個人小計\n\n".to_string();
+    msg.push_str(&format!(
+        "**商家：{}  •  人數：{}**\n\n",
+        group_buy.merchant_name,
+        sorted_subtotals.len()
+    ));
+    let mut header = "| 訂購人 | 金額 |".to_string();
+    let mut separator = "|--------|-----:|".to_string();
+    if let Some(percent) = fee_percent {
+        header.push_str(&format!(
+            " 服務費 {}% | 應付 |",
+            format_number(percent, locale)
+        ));
+        separator.push_str("-----:|-----:|");
+    }
+    if paid_buyers.is_some() {
+        header.push_str(" 付款 |");
+        separator.push_str(":----:|");
+    }
+    msg.push_str(&format!("{}\n{}\n", header, separator));
+
+    // 每人的金額依 group_buy.rounding 進位，總計為每人應付的合計
+    let mut fee_total = Decimal::ZERO;
+    let mut due_total = Decimal::ZERO;
+    for (key, amount) in &sorted_subtotals {
+        let (buyer, buyer_id) = *key;
+        let rounded = crate::money::round(*amount, rounding);
+        msg.push_str(&format!(
+            "| {} | {}{} |",
+            buyer_mention(buyer_id, buyer, deactivated),
+            group_buy.currency,
+            format_number(rounded, locale)
+        ));
+        let fee = fees.as_ref().map(|fees| fees[key]);
+        if let Some(fee) = fee {
+            fee_total += fee;
+            msg.push_str(&format!(
+                " {cur}{} | {cur}{} |",
+                format_number(fee, locale),
+                format_number(rounded + fee, locale),
+                cur = group_buy.currency
+            ));
+        }
+        due_total += rounded + fee.unwrap_or(Decimal::ZERO);
+        if let Some(paid_buyers) = paid_buyers {
+            let mark = if paid_buyers.contains(buyer_id) {
+                "✅"
+            } else {
+                "❌"
+            };
+            msg.push_str(&format!(" {} |", mark));
+        }
         msg.push('\n');
     }
+    let fee_total = fee_total.normalize();
+
+    // 總金額（使用 Decimal 進行精確計算）
+    let total_amount: Decimal = orders
+        .iter()
+        .map(|o| crate::money::line_total(o.unit_price, o.quantity))
+        .sum();
+
+    if let Some(percent) = fee_percent {
+        msg.push_str(&format!(
+            "\n商品合計：{cur}{}  •  服務費 {}%：{cur}{}",
+            format_number(total_amount, locale),
+            format_number(percent, locale),
+            format_number(fee_total, locale),
+            cur = group_buy.currency
+        ));
+    }
+    msg.push_str(&format!(
+        "\n**🧮 總計：{}{}**",
+        group_buy.currency,
+        format_number(due_total.normalize(), locale)
+    ));
+    // 進位後與實際金額不同時註明，方便對帳
+    let exact_total = total_amount + fee_total;
+    if due_total != exact_total {
+        msg.push_str(&format!(
+            "（進位前 {}{}）",
+            group_buy.currency,
+            format_number(exact_total, locale)
+        ));
+    }
+    if let Some(paid_buyers) = paid_buyers {
+        let paid_count = sorted_subtotals
+            .iter()
+            .filter(|((_, buyer_id), _)| paid_buyers.contains(*buyer_id))
+            .count();
+        msg.push_str(&format!(
+            "\n**💵 已付款：{}/{}**",
+            paid_count,
+            sorted_subtotals.len()
+        ));
+    }
+    msg
+}
+
+/// 個人小計（私訊用）：只列出該購買人的訂單。`orders` 為團購的所有訂單，用來計算分攤的服務費
+pub fn generate_personal_subtotal(
+    group_buy: &GroupBuy,
+    orders: &[GroupBuyOrder],
+    buyer_id: &str,
+    rounding: &RoundingConfig,
+    locale: &LocaleFormat,
+) -> String {
+    let mut msg = format!("### 💰 我的小計：{}\n\n", group_buy.merchant_name);
+    msg.push_str("| 商品 | 數量 | 單價 | 小計 |\n");
+    msg.push_str("|------|-----:|-----:|-----:|\n");
+
+    let mine: Vec<GroupBuyOrder> = orders
+        .iter()
+        .filter(|o| o.buyer_id == buyer_id)
+        .cloned()
+        .collect();
+    let mut total = Decimal::ZERO;
+    for order in sorted_orders(&mine) {
+        let subtotal = crate::money::line_total(order.unit_price, order.quantity);
+        total += subtotal;
+        let registrar_note = if order.registrar_id != order.buyer_id {
+            format!(" (由 @{} 登記)", order.registrar_username)
+        } else {
+            String::new()
+        };
+        msg.push_str(&format!(
+            "| {}{}{} | {} | {cur}{} | {cur}{} |\n",
+            item_icon_prefix(&group_buy.item_icons, &order.item_name),
+            order.item_name,
+            registrar_note,
+            order.quantity,
+            format_number(order.unit_price, locale),
+            format_number(subtotal, locale),
+            cur = group_buy.currency
+        ));
+    }
+
+    let mut due = crate::money::round(total, rounding);
+    if let Some(percent) = service_fee_percent(group_buy) {
+        let fee: Decimal = buyer_service_fees(orders, percent, rounding)
+            .into_iter()
+            .filter(|((_, id), _)| *id == buyer_id)
+            .map(|(_, fee)| fee)
+            .sum();
+        msg.push_str(&format!(
+            "\n商品合計：{cur}{}  •  服務費 {}%：{cur}{}",
+            format_number(due, locale),
+            format_number(percent, locale),
+            format_number(fee, locale),
+            cur = group_buy.currency
+        ));
+        due += fee;
+    }
+    msg.push_str(&format!(
+        "\n**🧮 應付：{}{}**",
+        group_buy.currency,
+        format_number(due, locale)
+    ));
+    msg
+}
+
+/// 登記人檢視：列出某人代為輸入的訂單，依購買人分組，方便核對
+pub fn generate_registrar_view(
+    group_buy: &GroupBuy,
+    orders: &[GroupBuyOrder],
+    deactivated: &HashSet<String>,
+) -> String {
+    let mut by_buyer: BTreeMap<(&str, &str), Vec<&GroupBuyOrder>> = BTreeMap::new();
+    for order in sorted_orders(orders) {
+        by_buyer
+            .entry((order.buyer_username.as_str(), order.buyer_id.as_str()))
+            .or_default()
+            .push(order);
+    }
+
+    let mut msg = "### 📝 我登記的\n\n".to_string();
+    msg.push_str(&format!(
+        "**商家：{}  •  人數：{}  •  筆數：{}**\n\n",
+        group_buy.merchant_name,
+        by_buyer.len(),
+        orders.len()
+    ));
+
+    for ((buyer, buyer_id), buyer_orders) in by_buyer {
+        let items: Vec<String> = buyer_orders
+            .iter()
+            .map(|o| {
+                format!(
+                    "{}{} x{}",
+                    item_icon_prefix(&group_buy.item_icons, &o.item_name),
+                    o.item_name,
+                    o.quantity
+                )
+            })
+            .collect();
+        msg.push_str(&format!(
+            "• {}: {}\n",
+            buyer_mention(buyer_id, buyer, deactivated),
+            items.join(", ")
+        ));
+    }
+
+    msg
+}
+
+/// 登記收據（臨時訊息給登記人）：這次登記的內容與購買人在此團購目前的小計
+pub fn generate_registration_receipt(
+    group_buy: &GroupBuy,
+    order: &GroupBuyOrder,
+    buyer_orders: &[GroupBuyOrder],
+    rounding: &RoundingConfig,
+    locale: &LocaleFormat,
+) -> String {
+    let subtotal = crate::money::line_total(order.unit_price, order.quantity);
+    let running_total: Decimal = buyer_orders
+        .iter()
+        .map(|o| crate::money::line_total(o.unit_price, o.quantity))
+        .sum();
 
+    let mut msg = format!("🧾 **已登記：{}**\n\n", group_buy.merchant_name);
+    msg.push_str("| 購買人 | 商品 | 數量 | 單價 | 小計 |\n");
+    msg.push_str("|--------|------|-----:|-----:|-----:|\n");
+    msg.push_str(&format!(
+        "| @{} | {}{} | {} | {cur}{} | {cur}{} |\n",
+        order.buyer_username,
+        item_icon_prefix(&group_buy.item_icons, &order.item_name),
+        order.item_name,
+        order.quantity,
+        format_number(order.unit_price, locale),
+        format_number(subtotal, locale),
+        cur = group_buy.currency
+    ));
+    msg.push_str(&format!(
+        "\n@{} 在此團購目前共 {}{}",
+        order.buyer_username,
+        group_buy.currency,
+        format_number(crate::money::round(running_total, rounding), locale)
+    ));
     msg
 }
+
+/// 代人登記時私訊給購買人的通知
+pub fn generate_proxy_registration_notice(
+    group_buy: &GroupBuy,
+    order: &GroupBuyOrder,
+    locale: &LocaleFormat,
+) -> String {
+    format!(
+        "📦 @{} 幫你登記了「{}」：{}{} x{}（{}{}）",
+        order.registrar_username,
+        group_buy.merchant_name,
+        item_icon_prefix(&group_buy.item_icons, &order.item_name),
+        order.item_name,
+        order.quantity,
+        group_buy.currency,
+        format_number(
+            crate::money::line_total(order.unit_price, order.quantity),
+            locale
+        )
+    )
+}
+
+/// 代人登記通知附帶的「這不是我要的，取消」按鈕
+pub fn generate_reject_order_button(
+    group_buy_id: &str,
+    order: &GroupBuyOrder,
+    bot_callback_url: &str,
+    signer: &StateSigner,
+) -> Vec<serde_json::Value> {
+    vec![json!({
+        "actions": [{
+            "id": action_id("rejectorder", &order.id),
+            "name": "這不是我要的，取消",
+            "type": "button",
+            "style": "danger",
+            "integration": {
+                "url": format!("{}/api/v1/group_buy/action/reject_order", bot_callback_url.trim_end_matches('/')),
+                "context": signer.sign_context(json!({
+                    "action": "reject_order",
+                    "group_buy_id": group_buy_id,
+                    "order_id": order.id,
+                    "buyer_id": order.buyer_id,
+                }), None)
+            }
+        }]
+    })]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::DEFAULT_CURRENCY;
+
+    #[test]
+    fn test_group_buy_post_props_carry_marker() {
+        let props = group_buy_post_props("gb-1", &[json!({"text": "buttons"})]);
+        assert_eq!(props["attachments"][0]["text"], "buttons");
+
+        assert_eq!(
+            parse_group_buy_marker(&props).unwrap(),
+            GroupBuyMarker {
+                group_buy_id: "gb-1".to_string(),
+                schema_version: GROUP_BUY_MARKER_SCHEMA_VERSION,
+            }
+        );
+        assert!(parse_group_buy_marker(&json!({"attachments": []})).is_none());
+    }
+
+    #[test]
+    fn test_item_icons_rendered_in_message() {
+        let items: HashMap<String, Decimal> = [
+            ("珍珠奶茶".to_string(), Decimal::new(50, 0)),
+            ("鬆餅".to_string(), Decimal::new(80, 0)),
+        ]
+        .into_iter()
+        .collect();
+        let icons: HashMap<String, String> = [
+            ("珍珠奶茶".to_string(), "🧋".to_string()),
+            (
+                "鬆餅".to_string(),
+                "https://example.com/waffle.png".to_string(),
+            ),
+        ]
+        .into_iter()
+        .collect();
+
+        let msg = generate_group_buy_message(
+            "飲料店",
+            &None,
+            &HashMap::new(),
+            &GroupBuyStatus::Active,
+            &items,
+            &icons,
+            &HashMap::new(),
+            DEFAULT_CURRENCY,
+        );
+        assert!(msg.contains("• 🧋 珍珠奶茶 - NT$50"));
+        assert!(msg.contains("• ![鬆餅](https://example.com/waffle.png =20x20) 鬆餅 - NT$80"));
+
+        // Dialog 選項只保留 emoji
+        assert_eq!(item_icon_plain_prefix(&icons, "珍珠奶茶"), "🧋 ");
+        assert_eq!(item_icon_plain_prefix(&icons, "鬆餅"), "");
+
+        let translations: HashMap<String, Vec<String>> =
+            [("珍珠奶茶".to_string(), vec!["Bubble tea".to_string()])]
+                .into_iter()
+                .collect();
+        assert_eq!(item_label(&translations, "珍珠奶茶", Some(0)), "Bubble tea");
+        assert_eq!(item_label(&translations, "珍珠奶茶", Some(1)), "珍珠奶茶");
+        assert_eq!(item_label(&translations, "珍珠奶茶", None), "珍珠奶茶");
+        assert_eq!(item_label(&translations, "鬆餅", Some(0)), "鬆餅");
+    }
+
+    #[test]
+    fn test_compact_layout_shows_counts_only() {
+        let items: HashMap<String, Decimal> = [("紅茶".to_string(), Decimal::new(30, 0))]
+            .into_iter()
+            .collect();
+        let mut orders = Vec::new();
+        for buyer in ["alice", "bob", "alice"] {
+            let mut order =
+                crate::test_utils::utils::make_order_for("gb-1".to_string(), buyer, buyer);
+            order.item_name = "紅茶".to_string();
+            orders.push(order);
+        }
+
+        let compact = generate_group_buy_message_with_orders(
+            "飲料店",
+            &None,
+            &HashMap::new(),
+            &GroupBuyStatus::Active,
+            &items,
+            &HashMap::new(),
+            &HashMap::new(),
+            DEFAULT_CURRENCY,
+            &orders,
+            &HashSet::new(),
+            &GroupBuyConfig {
+                layout: MessageLayout::Compact,
+                ..Default::default()
+            },
+        );
+        assert!(compact.contains("共 2 人"));
+        assert!(compact.contains(&format!("• 紅茶 x{}（2 人）", orders[0].quantity * 3)));
+        assert!(!compact.contains("@alice"));
+
+        let full = generate_group_buy_message_with_orders(
+            "飲料店",
+            &None,
+            &HashMap::new(),
+            &GroupBuyStatus::Active,
+            &items,
+            &HashMap::new(),
+            &HashMap::new(),
+            DEFAULT_CURRENCY,
+            &orders,
+            &HashSet::new(),
+            &GroupBuyConfig::default(),
+        );
+        assert!(full.contains("@alice"));
+    }
+
+    #[test]
+    fn test_long_registration_list_is_truncated() {
+        let items: HashMap<String, Decimal> = [("apple".to_string(), Decimal::new(10, 0))]
+            .into_iter()
+            .collect();
+        let orders: Vec<GroupBuyOrder> = (0..5)
+            .map(|i| {
+                crate::test_utils::utils::make_order_for(
+                    "gb-1".to_string(),
+                    &format!("buyer{}", i),
+                    "reg",
+                )
+            })
+            .collect();
+        let config = GroupBuyConfig {
+            max_listed_orders: 3,
+            ..Default::default()
+        };
+
+        let msg = generate_group_buy_message_with_orders(
+            "shop",
+            &None,
+            &HashMap::new(),
+            &GroupBuyStatus::Active,
+            &items,
+            &HashMap::new(),
+            &HashMap::new(),
+            DEFAULT_CURRENCY,
+            &orders,
+            &HashSet::new(),
+            &config,
+        );
+        assert_eq!(msg.matches("• @buyer").count(), 3);
+        assert!(msg.contains("另有 2 筆登記未顯示"));
+
+        let signer = StateSigner::new("secret");
+        let mut attachments = generate_action_buttons(
+            "gb-1",
+            &GroupBuyStatus::Active,
+            "http://bot",
+            &signer,
+            &GroupBuyButtonsConfig::default(),
+        );
+        add_full_list_button_if_needed(
+            &mut attachments,
+            "gb-1",
+            orders.len(),
+            &config,
+            "http://bot",
+            &signer,
+        );
+        let last = attachments[0]["actions"]
+            .as_array()
+            .unwrap()
+            .last()
+            .unwrap();
+        assert_eq!(last["name"], "完整名單");
+        assert_eq!(
+            signer.verify_context(&last["integration"]["context"]),
+            Ok(())
+        );
+
+        let table = generate_full_order_table("shop", &orders, &HashMap::new(), &HashSet::new());
+        assert_eq!(table.matches("| @buyer").count(), 5);
+
+        // 名單未被截斷時不加按鈕
+        assert!(!hides_orders(3, &config));
+    }
+
+    /// 產生多個商品與購買人的訂單，並以不同順序排列
+    fn unordered_orders() -> (GroupBuy, Vec<GroupBuyOrder>) {
+        let mut group_buy = crate::test_utils::utils::make_group_buy("gb-1".to_string(), 1);
+        group_buy.items = ["紅茶", "綠茶", "奶茶", "咖啡"]
+            .iter()
+            .map(|name| (name.to_string(), Decimal::new(30, 0)))
+            .collect();
+        group_buy.metadata = [("取貨", "大廳"), ("截止", "18:00"), ("付款", "現金")]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+
+        let mut orders = Vec::new();
+        for (buyer, item, qty) in [
+            ("carol", "綠茶", 1),
+            ("alice", "紅茶", 2),
+            ("bob", "綠茶", 1),
+            ("alice", "咖啡", 1),
+            ("dave", "奶茶", 3),
+        ] {
+            let mut order =
+                crate::test_utils::utils::make_order_for(group_buy.id.clone(), buyer, buyer);
+            order.item_name = item.to_string();
+            order.quantity = qty;
+            order.unit_price = Decimal::new(30, 0);
+            orders.push(order);
+        }
+        (group_buy, orders)
+    }
+
+    #[test]
+    fn test_generated_tables_have_stable_order() {
+        let (group_buy, orders) = unordered_orders();
+        let mut reversed = orders.clone();
+        reversed.reverse();
+
+        let render = |orders: &[GroupBuyOrder]| {
+            (
+                generate_group_buy_message_with_orders(
+                    &group_buy.merchant_name,
+                    &group_buy.description,
+                    &group_buy.metadata,
+                    &group_buy.status,
+                    &group_buy.items,
+                    &group_buy.item_icons,
+                    &group_buy.item_sections,
+                    &group_buy.currency,
+                    orders,
+                    &HashSet::new(),
+                    &GroupBuyConfig::default(),
+                ),
+                generate_shopping_list(
+                    &group_buy,
+                    orders,
+                    &RoundingConfig::default(),
+                    &LocaleFormat::default(),
+                ),
+                generate_subtotal_table(
+                    &group_buy,
+                    orders,
+                    None,
+                    &HashSet::new(),
+                    &RoundingConfig::default(),
+                    &LocaleFormat::default(),
+                ),
+                generate_full_order_table(
+                    &group_buy.merchant_name,
+                    orders,
+                    &HashMap::new(),
+                    &HashSet::new(),
+                ),
+            )
+        };
+
+        // 不論訂單順序，輸出都相同
+        assert_eq!(render(&orders), render(&reversed));
+
+        let (message, shopping_list, subtotal, full_table) = render(&orders);
+
+        let position = |haystack: &str, needle: &str| haystack.find(needle).unwrap();
+        assert!(position(&message, "• 取貨") < position(&message, "• 截止"));
+        assert!(position(&message, "**咖啡**") < position(&message, "**綠茶**"));
+        assert!(position(&message, "• @bob") < position(&message, "• @carol"));
+        assert!(position(&shopping_list, "| 咖啡") < position(&shopping_list, "| 綠茶"));
+        assert!(position(&full_table, "| @bob") < position(&full_table, "| @carol"));
+
+        // 小計依金額排序，同金額依名稱
+        assert!(position(&subtotal, "@alice") < position(&subtotal, "@bob"));
+        assert!(position(&subtotal, "@bob") < position(&subtotal, "@carol"));
+        assert!(position(&subtotal, "@dave") < position(&subtotal, "@bob"));
+    }
+
+    #[test]
+    fn test_personal_subtotal_only_lists_buyer_orders() {
+        let (group_buy, orders) = unordered_orders();
+
+        let msg = generate_personal_subtotal(
+            &group_buy,
+            &orders,
+            "alice",
+            &RoundingConfig::default(),
+            &LocaleFormat::default(),
+        );
+        assert!(msg.contains("| 咖啡 | 1 | NT$30 | NT$30 |"));
+        assert!(msg.contains("| 紅茶 | 2 | NT$30 | NT$60 |"));
+        assert!(!msg.contains("綠茶"));
+        assert!(msg.contains("NT$90"));
+    }
+
+    #[test]
+    fn test_subtotal_table_payment_marks() {
+        let (group_buy, orders) = unordered_orders();
+
+        let without = generate_subtotal_table(
+            &group_buy,
+            &orders,
+            None,
+            &HashSet::new(),
+            &RoundingConfig::default(),
+            &LocaleFormat::default(),
+        );
+        assert!(without.contains("| @alice | NT$90 |\n"));
+        assert!(!without.contains("付款"));
+
+        let paid = HashSet::from(["alice".to_string(), "bob".to_string()]);
+        let with = generate_subtotal_table(
+            &group_buy,
+            &orders,
+            Some(&paid),
+            &HashSet::new(),
+            &RoundingConfig::default(),
+            &LocaleFormat::default(),
+        );
+        assert!(with.contains("| @alice | NT$90 | ✅ |"));
+        assert!(with.contains("| @bob | NT$30 | ✅ |"));
+        assert!(with.contains("| @dave | NT$90 | ❌ |"));
+        assert!(with.contains("已付款：2/4"));
+    }
+
+    #[test]
+    fn test_parse_service_fee() {
+        assert_eq!(parse_service_fee("5%"), Ok(Decimal::new(5, 0)));
+        assert_eq!(parse_service_fee(" 2.5 ％"), Ok(Decimal::new(25, 1)));
+        assert_eq!(parse_service_fee("10"), Ok(Decimal::new(10, 0)));
+        assert!(parse_service_fee("-1%").is_err());
+        assert!(parse_service_fee("150%").is_err());
+        assert!(parse_service_fee("外送費").is_err());
+    }
+
+    #[test]
+    fn test_service_fee_line_items() {
+        let (mut group_buy, orders) = unordered_orders();
+        group_buy
+            .metadata
+            .insert(SERVICE_FEE_KEY.to_string(), "5%".to_string());
+
+        // 服務費 240 x 5% = 12 依金額分攤：alice $90 → 4.5、bob $30 → 1.5、carol $30 → 1.5、dave $90 → 4.5
+        let subtotal = generate_subtotal_table(
+            &group_buy,
+            &orders,
+            None,
+            &HashSet::new(),
+            &RoundingConfig::default(),
+            &LocaleFormat::default(),
+        );
+        assert!(subtotal.contains("| 訂購人 | 金額 | 服務費 5% | 應付 |"));
+        assert!(subtotal.contains("| @alice | NT$90 | NT$4.5 | NT$94.5 |"));
+        assert!(subtotal.contains("| @bob | NT$30 | NT$1.5 | NT$31.5 |"));
+        assert!(subtotal.contains("服務費 5%：NT$12"));
+        assert!(subtotal.contains("總計：NT$252**"));
+
+        let shopping_list = generate_shopping_list(
+            &group_buy,
+            &orders,
+            &RoundingConfig::default(),
+            &LocaleFormat::default(),
+        );
+        assert!(shopping_list.contains("商品合計：NT$240  •  服務費 5%：NT$12"));
+        assert!(shopping_list.contains("總金額：NT$252**"));
+
+        let personal = generate_personal_subtotal(
+            &group_buy,
+            &orders,
+            "alice",
+            &RoundingConfig::default(),
+            &LocaleFormat::default(),
+        );
+        assert!(personal.contains("服務費 5%：NT$4.5"));
+        assert!(personal.contains("應付：NT$94.5**"));
+
+        // 付款欄位接在服務費之後
+        let paid = HashSet::from(["alice".to_string()]);
+        let with_paid = generate_subtotal_table(
+            &group_buy,
+            &orders,
+            Some(&paid),
+            &HashSet::new(),
+            &RoundingConfig::default(),
+            &LocaleFormat::default(),
+        );
+        assert!(with_paid.contains("| @alice | NT$90 | NT$4.5 | NT$94.5 | ✅ |"));
+
+        // 進位方式由呼叫端傳入：整數、無條件進位時服務費 12 依 3:1:1:3 分成 5、2、1、4
+        let ceil = RoundingConfig {
+            mode: crate::config::RoundingMode::Ceil,
+            decimal_places: 0,
+        };
+        let personal = generate_personal_subtotal(
+            &group_buy,
+            &orders,
+            "alice",
+            &ceil,
+            &LocaleFormat::default(),
+        );
+        assert!(personal.contains("服務費 5%：NT$5"));
+        assert!(personal.contains("應付：NT$95**"));
+
+        // 語系格式也由呼叫端傳入
+        let de = LocaleFormat::for_locale("de").unwrap();
+        let personal = generate_personal_subtotal(
+            &group_buy,
+            &orders,
+            "alice",
+            &RoundingConfig::default(),
+            &de,
+        );
+        assert!(personal.contains("服務費 5%：NT$4,5"));
+        assert!(personal.contains("應付：NT$94,5**"));
+    }
+
+    #[test]
+    fn test_service_fee_split_sums_to_total() {
+        let (mut group_buy, orders) = unordered_orders();
+        group_buy
+            .metadata
+            .insert(SERVICE_FEE_KEY.to_string(), "3.35%".to_string());
+
+        // 240 x 3.35% = 8.04 依 3:1:1:3 分攤，捨去後多出的 0.02 依 buyer_id 順序給 alice、bob
+        let fees = buyer_service_fees(&orders, Decimal::new(335, 2), &RoundingConfig::default());
+        assert_eq!(fees.values().sum::<Decimal>(), Decimal::new(804, 2));
+        assert_eq!(fees[&("alice", "alice")], Decimal::new(302, 2));
+        assert_eq!(fees[&("bob", "bob")], Decimal::new(101, 2));
+        assert_eq!(fees[&("carol", "carol")], Decimal::new(100, 2));
+        assert_eq!(fees[&("dave", "dave")], Decimal::new(301, 2));
+
+        let subtotal = generate_subtotal_table(
+            &group_buy,
+            &orders,
+            None,
+            &HashSet::new(),
+            &RoundingConfig::default(),
+            &LocaleFormat::default(),
+        );
+        assert!(subtotal.contains("| @alice | NT$90 | NT$3.02 | NT$93.02 |"));
+        assert!(subtotal.contains("服務費 3.35%：NT$8.04"));
+        assert!(subtotal.contains("總計：NT$248.04**"));
+        let shopping_list = generate_shopping_list(
+            &group_buy,
+            &orders,
+            &RoundingConfig::default(),
+            &LocaleFormat::default(),
+        );
+        assert!(shopping_list.contains("總金額：NT$248.04**"));
+    }
+
+    #[test]
+    fn test_service_fee_same_buyer_under_two_usernames() {
+        let (mut group_buy, mut orders) = unordered_orders();
+        group_buy
+            .metadata
+            .insert(SERVICE_FEE_KEY.to_string(), "5%".to_string());
+        // alice 改名後又登記：同一個 buyer_id 以兩個使用者名稱出現
+        let coffee = orders.iter_mut().find(|o| o.item_name == "咖啡").unwrap();
+        coffee.buyer_username = "alice2".to_string();
+
+        // 服務費 12 依 60:30:30:30:90 分攤
+        let fees = buyer_service_fees(&orders, Decimal::new(5, 0), &RoundingConfig::default());
+        assert_eq!(fees.len(), 5);
+        assert_eq!(fees[&("alice", "alice")], Decimal::new(3, 0));
+        assert_eq!(fees[&("alice2", "alice")], Decimal::new(15, 1));
+
+        let subtotal = generate_subtotal_table(
+            &group_buy,
+            &orders,
+            None,
+            &HashSet::new(),
+            &RoundingConfig::default(),
+            &LocaleFormat::default(),
+        );
+        assert!(subtotal.contains("| @alice | NT$60 | NT$3 | NT$63 |"));
+        assert!(subtotal.contains("| @alice2 | NT$30 | NT$1.5 | NT$31.5 |"));
+        assert!(subtotal.contains("服務費 5%：NT$12"));
+        assert!(subtotal.contains("總計：NT$252**"));
+
+        // 私訊的個人小計依 buyer_id 合計兩個名稱的服務費
+        let personal = generate_personal_subtotal(
+            &group_buy,
+            &orders,
+            "alice",
+            &RoundingConfig::default(),
+            &LocaleFormat::default(),
+        );
+        assert!(personal.contains("服務費 5%：NT$4.5"));
+        assert!(personal.contains("應付：NT$94.5**"));
+    }
+
+    #[test]
+    fn test_archive_summary() {
+        let (group_buy, orders) = unordered_orders();
+        let adjustments = [ShortageAdjustment {
+            adjuster_username: "leko".to_string(),
+            item_name: "紅茶".to_string(),
+            buyer_username: "alice".to_string(),
+            old_quantity: 3,
+            new_quantity: 2,
+            created_at: chrono::Utc::now(),
+        }];
+
+        let summary = generate_archive_summary(
+            &group_buy,
+            &orders,
+            &adjustments,
+            &HashSet::new(),
+            &HashSet::new(),
+            "leko",
+            &RoundingConfig::default(),
+            &LocaleFormat::default(),
+        );
+        assert!(summary.contains("由 @leko 於"));
+        assert!(summary.contains("### 🛍️ 採購列表"));
+        assert!(summary.contains("### 💰 個人小計"));
+        assert!(summary.contains("| @leko | @alice | 紅茶 | 3 → 2 |"));
+
+        let empty = generate_archive_summary(
+            &group_buy,
+            &[],
+            &[],
+            &HashSet::new(),
+            &HashSet::new(),
+            "leko",
+            &RoundingConfig::default(),
+            &LocaleFormat::default(),
+        );
+        assert!(empty.contains("沒有任何登記"));
+        assert!(empty.contains("尚無缺貨調整紀錄"));
+    }
+
+    #[test]
+    fn test_registration_receipt_shows_running_total() {
+        let (group_buy, orders) = unordered_orders();
+        let alice: Vec<GroupBuyOrder> = orders
+            .iter()
+            .filter(|o| o.buyer_id == "alice")
+            .cloned()
+            .collect();
+        let latest = alice.iter().find(|o| o.item_name == "紅茶").unwrap();
+
+        let msg = generate_registration_receipt(
+            &group_buy,
+            latest,
+            &alice,
+            &RoundingConfig::default(),
+            &LocaleFormat::default(),
+        );
+        assert!(msg.contains("| @alice | 紅茶 | 2 | NT$30 | NT$60 |"));
+        assert!(msg.contains("@alice 在此團購目前共 NT$90"));
+    }
+
+    #[test]
+    fn test_proxy_registration_notice_and_reject_button() {
+        let (group_buy, mut orders) = unordered_orders();
+        let order = orders.iter_mut().find(|o| o.item_name == "紅茶").unwrap();
+        order.registrar_username = "helper".to_string();
+
+        let notice =
+            generate_proxy_registration_notice(&group_buy, order, &LocaleFormat::default());
+        assert!(notice.contains("@helper 幫你登記了"));
+        assert!(notice.contains("紅茶 x2（NT$60）"));
+
+        let signer = StateSigner::new("secret");
+        let attachments =
+            generate_reject_order_button(&group_buy.id, order, "https://bot.example.com/", &signer);
+        let button = &attachments[0]["actions"][0];
+        assert_eq!(button["name"], "這不是我要的，取消");
+        assert_eq!(
+            button["integration"]["url"],
+            "https://bot.example.com/api/v1/group_buy/action/reject_order"
+        );
+        let context = &button["integration"]["context"];
+        assert_eq!(context["order_id"], order.id.as_str());
+        assert_eq!(context["buyer_id"], order.buyer_id.as_str());
+        assert!(signer.verify_context(context).is_ok());
+    }
+
+    #[test]
+    fn test_shopping_list_shows_custom_fields() {
+        let (group_buy, mut orders) = unordered_orders();
+        for order in orders.iter_mut().filter(|o| o.item_name == "綠茶") {
+            let choice = if order.buyer_id == "bob" {
+                "內用"
+            } else {
+                "外帶"
+            };
+            order
+                .custom_fields
+                .insert("內用/外帶".to_string(), choice.to_string());
+        }
+
+        let msg = generate_shopping_list(
+            &group_buy,
+            &orders,
+            &RoundingConfig::default(),
+            &LocaleFormat::default(),
+        );
+        assert!(msg.contains("| 綠茶 | 2 | NT$30 | NT$60 |\n| ↳ 內用/外帶：內用 | 1 | | |\n| ↳ 內用/外帶：外帶 | 1 | | |\n"));
+        // 沒有自訂欄位的商品不加細項
+        assert!(!msg.contains("| 紅茶 | 2 | NT$30 | NT$60 |\n| ↳"));
+    }
+
+    #[test]
+    fn test_registrar_view_groups_by_buyer() {
+        let (group_buy, mut orders) = unordered_orders();
+        for order in orders.iter_mut() {
+            order.registrar_id = "helper".to_string();
+            order.registrar_username = "helper".to_string();
+        }
+
+        let msg = generate_registrar_view(&group_buy, &orders, &HashSet::new());
+        assert!(msg.contains("人數：4  •  筆數：5"));
+        assert!(msg.contains("• @alice: 咖啡 x1, 紅茶 x2\n"));
+        let position = |needle: &str| msg.find(needle).unwrap();
+        assert!(position("• @alice") < position("• @bob"));
+        assert!(position("• @carol") < position("• @dave"));
+    }
+
+    #[test]
+    fn test_items_grouped_by_section() {
+        let (mut group_buy, orders) = unordered_orders();
+        group_buy.item_sections = [("咖啡", "熱飲"), ("奶茶", "熱飲"), ("綠茶", "冷飲")]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+
+        let message = generate_group_buy_message(
+            &group_buy.merchant_name,
+            &None,
+            &HashMap::new(),
+            &group_buy.status,
+            &group_buy.items,
+            &group_buy.item_icons,
+            &group_buy.item_sections,
+            &group_buy.currency,
+        );
+        let position = |haystack: &str, needle: &str| haystack.find(needle).unwrap();
+        // 未分類的紅茶在前，接著依分類名稱排序
+        assert!(position(&message, "• 紅茶") < position(&message, "**冷飲**"));
+        assert!(position(&message, "**冷飲**") < position(&message, "• 綠茶"));
+        assert!(position(&message, "• 綠茶") < position(&message, "**熱飲**"));
+        assert!(position(&message, "• 咖啡") < position(&message, "• 奶茶"));
+
+        let shopping_list = generate_shopping_list(
+            &group_buy,
+            &orders,
+            &RoundingConfig::default(),
+            &LocaleFormat::default(),
+        );
+        assert!(shopping_list.contains("| **熱飲** | | | |"));
+        assert!(position(&shopping_list, "| 紅茶") < position(&shopping_list, "| 綠茶"));
+
+        let signer = StateSigner::new("secret");
+        let picker = generate_section_picker(
+            &group_buy.id,
+            &[None, Some("冷飲"), Some("熱飲")],
+            "https://bot",
+            &signer,
+        );
+        let actions = picker[0]["actions"].as_array().unwrap();
+        assert_eq!(actions.len(), 3);
+        assert_eq!(actions[0]["name"], "其他");
+        assert_eq!(actions[0]["integration"]["context"]["section"], "");
+        assert_eq!(actions[2]["integration"]["context"]["section"], "熱飲");
+    }
+
+    #[test]
+    fn test_action_buttons_have_signed_context() {
+        let signer = StateSigner::new("secret");
+        let attachments = generate_action_buttons(
+            "gb-1",
+            &GroupBuyStatus::Active,
+            "http://bot",
+            &signer,
+            &GroupBuyButtonsConfig::default(),
+        );
+
+        let actions = attachments[0]["actions"].as_array().unwrap();
+        assert!(!actions.is_empty());
+        for action in actions {
+            let context = &action["integration"]["context"];
+            assert_eq!(context["group_buy_id"], "gb-1");
+            assert_eq!(signer.verify_context(context), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_action_buttons_per_status() {
+        let signer = StateSigner::new("secret");
+        let names = |status: GroupBuyStatus| -> Vec<String> {
+            generate_action_buttons(
+                "gb-1",
+                &status,
+                "http://bot",
+                &signer,
+                &GroupBuyButtonsConfig::default(),
+            )[0]["actions"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|a| a["name"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        assert_eq!(names(GroupBuyStatus::Draft), vec!["編輯商品", "發布"]);
+        assert!(names(GroupBuyStatus::Closed).contains(&"已下單".to_string()));
+        let ordered = names(GroupBuyStatus::Ordered);
+        assert!(ordered.contains(&"調整缺貨".to_string()));
+        assert!(ordered.contains(&"調整紀錄".to_string()));
+        assert!(!ordered.contains(&"重新開放".to_string()));
+        assert!(!ordered.contains(&"登記".to_string()));
+        assert!(ordered.contains(&"封存".to_string()));
+        assert!(names(GroupBuyStatus::Closed).contains(&"封存".to_string()));
+
+        // 封存後沒有任何按鈕
+        assert!(
+            generate_action_buttons(
+                "gb-1",
+                &GroupBuyStatus::Archived,
+                "http://bot",
+                &signer,
+                &GroupBuyButtonsConfig::default(),
+            )
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_adjustment_history() {
+        assert_eq!(
+            generate_adjustment_history(&[], &LocaleFormat::default()),
+            "尚無缺貨調整紀錄"
+        );
+
+        let history = generate_adjustment_history(
+            &[ShortageAdjustment {
+                adjuster_username: "leko".to_string(),
+                item_name: "雞排".to_string(),
+                buyer_username: "alice".to_string(),
+                old_quantity: 3,
+                new_quantity: 1,
+                created_at: chrono::Utc::now(),
+            }],
+            &LocaleFormat::default(),
+        );
+        assert!(history.contains("| @leko | @alice | 雞排 | 3 → 1 |"));
+    }
+
+    #[test]
+    fn test_action_buttons_follow_config() {
+        let signer = StateSigner::new("secret");
+        let buttons = GroupBuyButtonsConfig {
+            closed: Some(vec!["subtotal".to_string(), "adjust_shortage".to_string()]),
+            ..Default::default()
+        };
+
+        let closed = generate_action_buttons(
+            "gb-1",
+            &GroupBuyStatus::Closed,
+            "http://bot",
+            &signer,
+            &buttons,
+        );
+        let names: Vec<&str> = closed[0]["actions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|a| a["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["小計", "調整缺貨"]);
+
+        // 未設定的狀態維持預設
+        let active = generate_action_buttons(
+            "gb-1",
+            &GroupBuyStatus::Active,
+            "http://bot",
+            &signer,
+            &buttons,
+        );
+        assert_eq!(active[0]["actions"].as_array().unwrap().len(), 8);
+    }
+}
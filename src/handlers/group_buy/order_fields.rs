@@ -0,0 +1,170 @@
+//! 訂單自訂欄位
+//!
+//! 建立者在建立團購時定義每筆訂單要多填的欄位（例如內用/外帶、甜度），
+//! 登記對話框依定義產生對應的欄位，填寫的值以 JSON 存在訂單上。
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::database::OrderField;
+use crate::mattermost::{DialogElement, DialogElementType, DialogOption};
+
+/// 每個團購最多的自訂欄位數量，避免登記對話框過長
+pub const MAX_ORDER_FIELDS: usize = 3;
+
+/// 解析建立對話框填寫的欄位定義，一行一個欄位：
+/// `內用/外帶: 內用, 外帶` 為選單，只寫名稱（`備註`）為自由填寫
+pub fn parse_order_fields(text: &str) -> Result<Vec<OrderField>, String> {
+    let mut fields: Vec<OrderField> = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (name, options) = line.split_once([':', '：']).unwrap_or((line, ""));
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(format!("第 {} 行缺少欄位名稱", idx + 1));
+        }
+        if fields.iter().any(|f| f.name == name) {
+            return Err(format!("欄位「{}」重複", name));
+        }
+
+        let mut values: Vec<String> = Vec::new();
+        for option in options.split([',', '，', '、']).map(str::trim) {
+            if !option.is_empty() && !values.iter().any(|v| v == option) {
+                values.push(option.to_string());
+            }
+        }
+        fields.push(OrderField {
+            name: name.to_string(),
+            options: values,
+        });
+    }
+
+    if fields.len() > MAX_ORDER_FIELDS {
+        return Err(format!("最多只能定義 {} 個欄位", MAX_ORDER_FIELDS));
+    }
+    Ok(fields)
+}
+
+/// 登記對話框中第 `index` 個自訂欄位的名稱
+fn field_element_name(index: usize) -> String {
+    format!("field_{}", index)
+}
+
+/// 登記對話框的自訂欄位：有選項的是必選的選單，其餘為選填的文字欄位
+pub fn order_field_elements(fields: &[OrderField]) -> Vec<DialogElement> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(idx, field)| {
+            let is_select = !field.options.is_empty();
+            DialogElement {
+                display_name: field.name.clone(),
+                name: field_element_name(idx),
+                element_type: if is_select {
+                    DialogElementType::Select
+                } else {
+                    DialogElementType::Text
+                },
+                placeholder: None,
+                help_text: None,
+                optional: !is_select,
+                min_length: None,
+                max_length: if is_select { None } else { Some(100) },
+                data_source: None,
+                options: is_select.then(|| {
+                    field
+                        .options
+                        .iter()
+                        .map(|option| DialogOption {
+                            text: option.clone(),
+                            value: option.clone(),
+                        })
+                        .collect()
+                }),
+                default: None,
+                subtype: None,
+            }
+        })
+        .collect()
+}
+
+/// 從登記對話框的送出內容取出自訂欄位的值。
+/// 選單沒有選或選了不存在的值時，回傳 (對話框欄位名稱, 錯誤訊息)。
+pub fn collect_custom_fields(
+    fields: &[OrderField],
+    submission: &HashMap<String, serde_json::Value>,
+) -> Result<BTreeMap<String, String>, (String, String)> {
+    let mut values = BTreeMap::new();
+    for (idx, field) in fields.iter().enumerate() {
+        let element_name = field_element_name(idx);
+        let value = submission
+            .get(&element_name)
+            .and_then(|v| v.as_str())
+            .map(str::trim)
+            .unwrap_or("");
+
+        if !field.options.is_empty() && !field.options.iter().any(|o| o == value) {
+            return Err((element_name, format!("請選擇{}", field.name)));
+        }
+        if !value.is_empty() {
+            values.insert(field.name.clone(), value.to_string());
+        }
+    }
+    Ok(values)
+}
+
+/// 顯示用：`內用/外帶：外帶，甜度：半糖`
+pub fn format_custom_fields(values: &BTreeMap<String, String>) -> String {
+    values
+        .iter()
+        .map(|(name, value)| format!("{}：{}", name, value))
+        .collect::<Vec<_>>()
+        .join("，")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_order_fields() {
+        let fields =
+            parse_order_fields("內用/外帶: 內用, 外帶\n\n甜度：全糖，半糖、半糖\n備註").unwrap();
+        assert_eq!(fields.len(), 3);
+        assert_eq!(fields[0].name, "內用/外帶");
+        assert_eq!(fields[0].options, vec!["內用", "外帶"]);
+        assert_eq!(fields[1].options, vec!["全糖", "半糖"]);
+        assert!(fields[2].options.is_empty());
+
+        assert!(parse_order_fields(": 內用").is_err());
+        assert!(parse_order_fields("甜度\n甜度: 全糖").is_err());
+        assert!(parse_order_fields("a\nb\nc\nd").is_err());
+        assert!(parse_order_fields("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_collect_custom_fields() {
+        let fields = parse_order_fields("內用/外帶: 內用, 外帶\n備註").unwrap();
+        let elements = order_field_elements(&fields);
+        assert!(matches!(
+            elements[0].element_type,
+            DialogElementType::Select
+        ));
+        assert!(!elements[0].optional);
+        assert!(elements[1].optional);
+
+        let mut submission: HashMap<String, serde_json::Value> = HashMap::new();
+        let err = collect_custom_fields(&fields, &submission).unwrap_err();
+        assert_eq!(err.0, "field_0");
+
+        submission.insert("field_0".to_string(), serde_json::json!("外帶"));
+        let values = collect_custom_fields(&fields, &submission).unwrap();
+        assert_eq!(format_custom_fields(&values), "內用/外帶：外帶");
+
+        submission.insert("field_1".to_string(), serde_json::json!(" 少冰 "));
+        let values = collect_custom_fields(&fields, &submission).unwrap();
+        assert_eq!(values.get("備註").map(String::as_str), Some("少冰"));
+    }
+}
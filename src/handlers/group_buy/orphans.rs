@@ -0,0 +1,254 @@
+//! 孤兒團購偵測
+//!
+//! 背景工作定期檢查進行中的團購：貼文已在 Mattermost 被刪除，或進行超過
+//! `group_buy.orphans.idle_days` 天仍沒有任何登記。依設定私訊提醒建立者
+//! （每個團購只提醒一次），或直接截止團購。
+
+use super::*;
+use crate::config::OrphanAction;
+use crate::database::OrphanCandidate;
+use chrono::{DateTime, Utc};
+
+/// 團購被視為孤兒的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrphanReason {
+    /// 團購貼文已被刪除
+    PostDeleted,
+    /// 進行超過指定天數仍沒有任何登記
+    Idle { days: i64 },
+}
+
+impl OrphanReason {
+    fn describe(self) -> String {
+        match self {
+            OrphanReason::PostDeleted => "團購貼文已被刪除".to_string(),
+            OrphanReason::Idle { days } => format!("已進行 {} 天仍沒有任何登記", days),
+        }
+    }
+}
+
+/// 依建立時間與登記數判斷是否閒置，`idle_days` 為 0 時不檢查
+pub fn idle_reason(
+    candidate: &OrphanCandidate,
+    now: DateTime<Utc>,
+    idle_days: i64,
+) -> Option<OrphanReason> {
+    if idle_days <= 0 || candidate.order_count > 0 {
+        return None;
+    }
+    let days = (now - candidate.created_at).num_days();
+    (days >= idle_days).then_some(OrphanReason::Idle { days })
+}
+
+/// 判斷團購是否為孤兒。查詢貼文失敗（例如暫時連不上）時不當作已刪除，只檢查是否閒置
+async fn orphan_reason(
+    state_guard: &AppState,
+    candidate: &OrphanCandidate,
+    now: DateTime<Utc>,
+) -> Option<OrphanReason> {
+    if let Some(post_id) = &candidate.post_id {
+        match state_guard.mattermost_client.post_exists(post_id).await {
+            Ok(false) => return Some(OrphanReason::PostDeleted),
+            Ok(true) => {}
+            Err(e) => tracing::warn!("檢查團購 {} 的貼文失敗: {}", candidate.group_buy_id, e),
+        }
+    }
+    idle_reason(
+        candidate,
+        now,
+        state_guard.config.group_buy.orphans.idle_days,
+    )
+}
+
+/// 檢查所有進行中的團購並處理孤兒，回傳實際提醒或截止的數量
+pub async fn handle_orphaned_group_buys(state_guard: &AppState) -> Result<usize> {
+    let now = Utc::now();
+    let action = state_guard.config.group_buy.orphans.action;
+    let mut handled = 0;
+
+    for candidate in state_guard.database.get_orphan_candidates().await? {
+        if action == OrphanAction::Notify && candidate.notified {
+            continue;
+        }
+        let Some(reason) = orphan_reason(state_guard, &candidate, now).await else {
+            continue;
+        };
+        let result = match action {
+            OrphanAction::Notify => notify_creator(state_guard, &candidate, reason).await,
+            OrphanAction::Archive => archive(state_guard, &candidate, reason).await,
+        };
+        match result {
+            Ok(()) => handled += 1,
+            Err(e) => error!("處理孤兒團購 {} 失敗: {}", candidate.group_buy_id, e),
+        }
+    }
+
+    Ok(handled)
+}
+
+/// 私訊建立者並記錄 `orphan_notice`，之後不再提醒
+async fn notify_creator(
+    state_guard: &AppState,
+    candidate: &OrphanCandidate,
+    reason: OrphanReason,
+) -> Result<()> {
+    let Some(group_buy) = state_guard
+        .database
+        .get_group_buy(&candidate.group_buy_id)
+        .await?
+    else {
+        return Ok(());
+    };
+
+    send_direct_message(
+        state_guard,
+        &group_buy.creator_id,
+        &format!(
+            "👻 你建立的團購「{}」{}，如果不再需要，請記得截止團購",
+            group_buy.merchant_name,
+            reason.describe()
+        ),
+    )
+    .await?;
+
+    let details = serde_json::json!({
+        "action": "orphan_notice",
+        "reason": reason.describe(),
+        "version": group_buy.version,
+    });
+    state_guard
+        .database
+        .log_action(
+            &group_buy.id,
+            &state_guard.bot_user_id,
+            "bot",
+            "orphan_notice",
+            Some(&details.to_string()),
+        )
+        .await?;
+
+    info!(
+        "已提醒團購 {} 的建立者：{}",
+        group_buy.id,
+        reason.describe()
+    );
+    Ok(())
+}
+
+/// 以 bot 身分截止團購，貼文還在時一併更新，並私訊告知建立者
+async fn archive(
+    state_guard: &AppState,
+    candidate: &OrphanCandidate,
+    reason: OrphanReason,
+) -> Result<()> {
+    let Some(group_buy) = state_guard
+        .database
+        .get_group_buy(&candidate.group_buy_id)
+        .await?
+    else {
+        return Ok(());
+    };
+    if group_buy.status != GroupBuyStatus::Active {
+        return Ok(());
+    }
+
+    state_guard
+        .database
+        .update_status(
+            &group_buy.id,
+            GroupBuyStatus::Closed,
+            group_buy.version,
+            &state_guard.bot_user_id,
+            "bot",
+        )
+        .await?;
+
+    let group_buy = GroupBuy {
+        status: GroupBuyStatus::Closed,
+        version: group_buy.version + 1,
+        ..group_buy
+    };
+    if reason != OrphanReason::PostDeleted {
+        super::utils::sync_group_buy_post(state_guard, &group_buy).await;
+    }
+
+    if let Err(e) = send_direct_message(
+        state_guard,
+        &group_buy.creator_id,
+        &format!(
+            "👻 你建立的團購「{}」{}，已自動截止",
+            group_buy.merchant_name,
+            reason.describe()
+        ),
+    )
+    .await
+    {
+        error!("通知建立者團購已自動截止失敗: {}", e);
+    }
+
+    info!("團購 {} {}，已自動截止", group_buy.id, reason.describe());
+    Ok(())
+}
+
+async fn send_direct_message(state_guard: &AppState, user_id: &str, message: &str) -> Result<()> {
+    let channel = state_guard
+        .mattermost_client
+        .create_direct_channel(&state_guard.bot_user_id, user_id)
+        .await?;
+    state_guard
+        .mattermost_client
+        .create_post_simple(&channel.id, message, None)
+        .await?;
+    Ok(())
+}
+
+/// 定期檢查孤兒團購的排程工作。啟動後等第一個間隔過後才執行；
+/// `check_interval_secs` 為 0 或團購功能停用時暫停
+pub fn orphan_detector_job() -> crate::scheduler::Job {
+    crate::scheduler::Job::new(
+        "孤兒團購檢查",
+        |config| {
+            let interval_secs = config.group_buy.orphans.check_interval_secs;
+            (config.features.group_buy && interval_secs > 0)
+                .then(|| std::time::Duration::from_secs(interval_secs))
+        },
+        |state| async move {
+            let state_guard = state.read().await;
+            match handle_orphaned_group_buys(&state_guard).await {
+                Ok(0) => {}
+                Ok(handled) => info!("處理了 {} 個孤兒團購", handled),
+                Err(e) => error!("檢查孤兒團購失敗: {}", e),
+            }
+        },
+    )
+    .delayed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(order_count: i64, created_at: DateTime<Utc>) -> OrphanCandidate {
+        OrphanCandidate {
+            group_buy_id: "gb-1".to_string(),
+            post_id: Some("post-1".to_string()),
+            order_count,
+            notified: false,
+            created_at,
+        }
+    }
+
+    #[test]
+    fn test_idle_reason() {
+        let now = Utc::now();
+        let days_ago = |days| now - chrono::Duration::days(days);
+        assert_eq!(
+            idle_reason(&candidate(0, days_ago(8)), now, 7),
+            Some(OrphanReason::Idle { days: 8 })
+        );
+        assert_eq!(idle_reason(&candidate(0, days_ago(3)), now, 7), None);
+        assert_eq!(idle_reason(&candidate(2, days_ago(30)), now, 7), None);
+        // 0 代表不檢查閒置
+        assert_eq!(idle_reason(&candidate(0, days_ago(30)), now, 0), None);
+    }
+}
@@ -0,0 +1,204 @@
+//! 以表情回應快速登記
+//!
+//! 商品圖示是 `:shortcode:`（例如 `珍珠奶茶: 50 | :bubble_tea:`）時，在進行中的團購貼文
+//! 按下同一個表情回應，就以回應的人為購買人登記該商品一份，並私訊登記收據。
+//! 事件來自 WebSocket 的 `reaction_added`，與私訊指令相同，多實例部署時只應有一個實例連線 WebSocket。
+
+use super::*;
+use chrono::Utc;
+
+/// `reaction_added` 事件中的表情回應
+#[derive(Debug, Deserialize)]
+pub struct Reaction {
+    pub user_id: String,
+    pub post_id: String,
+    pub emoji_name: String,
+}
+
+/// 表情回應對應的商品：圖示為 `:emoji_name:` 的商品，沒有或對應到多個商品時回傳 None
+pub fn reaction_item<'a>(
+    item_icons: &'a HashMap<String, String>,
+    emoji_name: &str,
+) -> Option<&'a str> {
+    let shortcode = format!(":{}:", emoji_name);
+    let mut matches = item_icons
+        .iter()
+        .filter(|(_, icon)| icon.trim() == shortcode)
+        .map(|(item, _)| item.as_str());
+    let item = matches.next()?;
+    matches.next().is_none().then_some(item)
+}
+
+/// 處理團購貼文上的表情回應，不是團購貼文或沒有對應的商品時不做任何事
+pub async fn handle_reaction_added(state_guard: &AppState, reaction: &Reaction) {
+    if !state_guard.config.features.group_buy || reaction.user_id == state_guard.bot_user_id {
+        return;
+    }
+
+    let group_buy = match state_guard
+        .database
+        .get_group_buy_by_post_id(&reaction.post_id)
+        .await
+    {
+        Ok(Some(group_buy)) => group_buy,
+        Ok(None) => return,
+        Err(e) => {
+            error!("以貼文查詢團購失敗: {}", e);
+            return;
+        }
+    };
+    if group_buy.status != GroupBuyStatus::Active {
+        return;
+    }
+    let Some(item_name) = reaction_item(&group_buy.item_icons, &reaction.emoji_name) else {
+        return;
+    };
+    let Some(&unit_price) = group_buy.items.get(item_name) else {
+        return;
+    };
+
+    // 有選單欄位時必須在對話框中選擇，表情回應無法登記
+    if group_buy.order_fields.iter().any(|f| !f.options.is_empty()) {
+        send_dm(
+            state_guard,
+            &reaction.user_id,
+            &format!(
+                "⚠️ 「{}」的團購登記時需要選擇{}，請使用團購貼文的「登記」按鈕",
+                group_buy.merchant_name,
+                group_buy
+                    .order_fields
+                    .iter()
+                    .filter(|f| !f.options.is_empty())
+                    .map(|f| f.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join("、")
+            ),
+        )
+        .await;
+        return;
+    }
+
+    let user = match state_guard
+        .mattermost_client
+        .get_user(&reaction.user_id)
+        .await
+    {
+        Ok(user) => user,
+        Err(e) => {
+            error!("取得表情回應使用者資訊失敗: {}", e);
+            return;
+        }
+    };
+
+    let order = GroupBuyOrder {
+        id: uuid::Uuid::new_v4().to_string(),
+        group_buy_id: group_buy.id.clone(),
+        registrar_id: user.id.clone(),
+        registrar_username: user.username.clone(),
+        buyer_id: user.id.clone(),
+        buyer_username: user.username.clone(),
+        item_name: item_name.to_string(),
+        quantity: 1,
+        original_quantity: None,
+        unit_price,
+        custom_fields: Default::default(),
+        created_at: Utc::now(),
+    };
+    if let Err(e) = state_guard.database.create_order(&order).await {
+        error!("以表情回應登記失敗: {}", e);
+        send_dm(
+            state_guard,
+            &user.id,
+            &format!(
+                "⚠️ 以表情回應登記「{}」的 {} 失敗，請使用「登記」按鈕",
+                group_buy.merchant_name, item_name
+            ),
+        )
+        .await;
+        return;
+    }
+
+    info!(
+        "{} 以表情回應 :{}: 登記：{} x1",
+        user.username, reaction.emoji_name, item_name
+    );
+
+    super::refresh::request_post_refresh(state_guard, &group_buy.id);
+
+    let buyer_orders = state_guard
+        .database
+        .get_buyer_orders(&group_buy.id, &user.id)
+        .await
+        .unwrap_or_else(|e| {
+            error!("取得購買人訂單失敗: {}", e);
+            vec![order.clone()]
+        });
+    let mut receipt = super::messages::generate_registration_receipt(
+        &group_buy,
+        &order,
+        &buyer_orders,
+        &state_guard.config.group_buy.rounding,
+        &state_guard.config.group_buy.locale_format(),
+    );
+    if let Some(warning) = super::budget::budget_warning(state_guard, &user.id).await {
+        receipt.push_str(&format!("\n\n{}", warning));
+    }
+    send_dm(
+        state_guard,
+        &user.id,
+        &format!(
+            "{}\n\n（以表情回應 :{}: 登記，移除回應不會取消，要取消請使用團購貼文的「取消登記」）",
+            receipt, reaction.emoji_name
+        ),
+    )
+    .await;
+}
+
+/// 私訊使用者，失敗只記錄錯誤
+async fn send_dm(state_guard: &AppState, user_id: &str, message: &str) {
+    let client = &state_guard.mattermost_client;
+    let channel = match client
+        .create_direct_channel(&state_guard.bot_user_id, user_id)
+        .await
+    {
+        Ok(channel) => channel,
+        Err(e) => {
+            error!("建立私訊頻道失敗: {}", e);
+            return;
+        }
+    };
+    let post = crate::mattermost::Post {
+        id: None,
+        channel_id: channel.id,
+        message: message.to_string(),
+        root_id: None,
+        props: None,
+    };
+    if let Err(e) = client.create_post(&post).await {
+        error!("私訊表情回應登記結果失敗: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reaction_item() {
+        let icons: HashMap<String, String> = [
+            ("珍珠奶茶".to_string(), ":bubble_tea:".to_string()),
+            ("紅茶".to_string(), "🍵".to_string()),
+            ("雞排".to_string(), ":poultry_leg:".to_string()),
+            ("雞腿".to_string(), ":poultry_leg:".to_string()),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(reaction_item(&icons, "bubble_tea"), Some("珍珠奶茶"));
+        // 直接填 emoji 的商品無法對應到回應名稱
+        assert_eq!(reaction_item(&icons, "tea"), None);
+        // 同一個表情對應到多個商品時不登記
+        assert_eq!(reaction_item(&icons, "poultry_leg"), None);
+        assert_eq!(reaction_item(&icons, "+1"), None);
+    }
+}
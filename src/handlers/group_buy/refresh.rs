@@ -0,0 +1,99 @@
+//! 團購貼文的即時更新
+//!
+//! 登記、取消登記、復原與表情回應登記不是由團購貼文上的按鈕觸發，回應無法更新貼文。
+//! 這些變更以 `request_post_refresh` 排入背景工作，第一個要求後等待 `REFRESH_DEBOUNCE`，
+//! 把期間內同一個團購的多次變更合併成一次，再重新產生貼文（轉發到其他頻道的貼文一併更新）。
+//! 要求送到 `AppState.post_refresh`，背景工作沒有啟動時（例如測試）直接略過。
+//! 內容在持有 AppState 的鎖時以 `PostSync::prepare` 產生，更新貼文的 API 呼叫前放開鎖。
+
+use super::*;
+use std::collections::BTreeSet;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// 第一個更新要求後等待合併的時間
+const REFRESH_DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// 要求在背景更新團購貼文
+pub fn request_post_refresh(state_guard: &AppState, group_buy_id: &str) {
+    let _ = state_guard.post_refresh.send(group_buy_id.to_string());
+}
+
+/// 收下第一個要求後 `debounce` 內收到的要求，合併成一批要更新的團購
+async fn collect_batch(
+    receiver: &mut mpsc::UnboundedReceiver<String>,
+    debounce: Duration,
+) -> Option<BTreeSet<String>> {
+    let first = receiver.recv().await?;
+    let mut batch = BTreeSet::from([first]);
+    let deadline = tokio::time::Instant::now() + debounce;
+    while let Ok(Some(group_buy_id)) = tokio::time::timeout_at(deadline, receiver.recv()).await {
+        batch.insert(group_buy_id);
+    }
+    Some(batch)
+}
+
+/// 啟動更新團購貼文的背景工作，`receiver` 對應 `AppState.post_refresh`
+pub fn spawn_post_refresher(
+    state: Arc<RwLock<AppState>>,
+    mut receiver: mpsc::UnboundedReceiver<String>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        while let Some(batch) = collect_batch(&mut receiver, REFRESH_DEBOUNCE).await {
+            for group_buy_id in batch {
+                // 只在讀取資料庫、產生內容時持有鎖，呼叫 Mattermost API 前放開
+                let sync = {
+                    let state_guard = state.read().await;
+                    if !state_guard.config.features.group_buy {
+                        break;
+                    }
+                    match state_guard.database.get_group_buy(&group_buy_id).await {
+                        Ok(Some(group_buy)) => {
+                            super::utils::PostSync::prepare(&state_guard, &group_buy, None).await
+                        }
+                        Ok(None) => None,
+                        Err(e) => {
+                            error!("取得團購 {} 失敗，略過更新貼文: {}", group_buy_id, e);
+                            None
+                        }
+                    }
+                };
+                if let Some(sync) = sync {
+                    sync.run().await;
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_collect_batch_merges_requests_within_debounce() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let debounce = Duration::from_millis(100);
+
+        sender.send("gb-1".to_string()).unwrap();
+        sender.send("gb-2".to_string()).unwrap();
+        sender.send("gb-1".to_string()).unwrap();
+        let batch = collect_batch(&mut receiver, debounce).await.unwrap();
+        assert_eq!(
+            batch,
+            BTreeSet::from(["gb-1".to_string(), "gb-2".to_string()])
+        );
+
+        // 等待結束後的要求歸到下一批
+        let late = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            sender.send("gb-3".to_string()).unwrap();
+        });
+        let batch = collect_batch(&mut receiver, debounce).await.unwrap();
+        assert_eq!(batch, BTreeSet::from(["gb-3".to_string()]));
+        late.await.unwrap();
+
+        // 所有 sender 都結束後停止
+        assert!(collect_batch(&mut receiver, debounce).await.is_none());
+    }
+}
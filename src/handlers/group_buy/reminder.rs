@@ -0,0 +1,145 @@
+//! 團購截止前提醒
+//!
+//! 建立團購時可以設定截止前多久提醒（`group_buy_reminders`），由排程工作定期檢查，
+//! 時間到時在團購貼文下提醒還沒登記的人。提醒存在資料庫，重新啟動後仍會發送。
+
+use super::*;
+use chrono::{DateTime, Utc};
+
+/// 檢查提醒的間隔
+const REMINDER_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// 解析建立對話框的「截止前提醒」欄位，回傳提醒時間；空白表示不提醒。
+/// 提醒需要截止時間，且必須早於截止時間
+pub fn parse_reminder(
+    input: &str,
+    close_at: Option<DateTime<Utc>>,
+) -> std::result::Result<Option<DateTime<Utc>>, String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Ok(None);
+    }
+
+    let Some(before) = super::flash::parse_duration(input) else {
+        return Err(format!(
+            "無法解析「{}」，請填寫 `15m`、`1h`、`1h30m` 等時間",
+            input
+        ));
+    };
+    let Some(close_at) = close_at else {
+        return Err("這個團購沒有截止時間，無法設定提醒".to_string());
+    };
+
+    let remind_at = close_at - before;
+    if remind_at <= Utc::now() {
+        return Err("提醒時間已經過了，請填寫較短的時間".to_string());
+    }
+    Ok(Some(remind_at))
+}
+
+/// 發送所有已到時間的提醒，回傳實際發送的數量。
+/// 發送失敗時保留提醒，下次檢查再試；團購已截止時直接移除。
+pub async fn send_due_reminders(state_guard: &AppState) -> Result<usize> {
+    let now = Utc::now();
+    let due = state_guard.database.get_due_reminders(now).await?;
+    let mut sent = 0;
+
+    for (group_buy_id, close_at) in due {
+        // 截止時間已移除（已自動截止）或已過時不再提醒
+        if let Some(close_at) = close_at.filter(|close_at| *close_at > now) {
+            match remind(state_guard, &group_buy_id, close_at).await {
+                Ok(true) => sent += 1,
+                Ok(false) => {}
+                Err(e) => {
+                    error!("發送團購 {} 的截止前提醒失敗: {}", group_buy_id, e);
+                    continue;
+                }
+            }
+        }
+        state_guard.database.clear_reminder(&group_buy_id).await?;
+    }
+
+    Ok(sent)
+}
+
+/// 在團購貼文下發送提醒；已不是進行中或在頻道靜音時段時不發送並回傳 false
+async fn remind(
+    state_guard: &AppState,
+    group_buy_id: &str,
+    close_at: DateTime<Utc>,
+) -> Result<bool> {
+    let Some(group_buy) = state_guard.database.get_group_buy(group_buy_id).await? else {
+        return Ok(false);
+    };
+    if group_buy.status != GroupBuyStatus::Active {
+        return Ok(false);
+    }
+
+    let settings = state_guard
+        .database
+        .get_channel_settings(&group_buy.channel_id)
+        .await
+        .unwrap_or_default();
+    if crate::handlers::settings::is_quiet_at(&settings, chrono::Local::now().time()) {
+        info!("團購 {} 的截止前提醒落在靜音時段，不發送", group_buy_id);
+        return Ok(false);
+    }
+
+    let minutes_left = (close_at - Utc::now()).num_minutes().max(1);
+    let notice = crate::mattermost::Post {
+        id: None,
+        channel_id: group_buy.channel_id.clone(),
+        message: format!(
+            "⏰ 團購「{}」將在 {} 分鐘後（{}）截止，還沒登記的請把握時間",
+            group_buy.merchant_name,
+            minutes_left,
+            close_at.with_timezone(&chrono::Local).format("%H:%M")
+        ),
+        root_id: state_guard
+            .config
+            .group_buy
+            .reply_root_id(group_buy.post_id.as_deref()),
+        props: None,
+    };
+    state_guard.mattermost_client.create_post(&notice).await?;
+
+    info!("已發送團購 {} 的截止前提醒", group_buy_id);
+    Ok(true)
+}
+
+/// 定期發送到期的截止前提醒的排程工作。啟動後立即執行一次，補上停機期間到期的提醒；
+/// 與自動截止相同，團購功能停用時暫停
+pub fn reminder_job() -> crate::scheduler::Job {
+    crate::scheduler::Job::new(
+        "截止前提醒",
+        |config| config.features.group_buy.then_some(REMINDER_CHECK_INTERVAL),
+        |state| async move {
+            let state_guard = state.read().await;
+            match send_due_reminders(&state_guard).await {
+                Ok(0) => {}
+                Ok(sent) => info!("發送了 {} 則截止前提醒", sent),
+                Err(e) => error!("檢查團購截止前提醒失敗: {}", e),
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reminder() {
+        let close_at = Utc::now() + chrono::Duration::hours(2);
+
+        assert_eq!(parse_reminder("", Some(close_at)), Ok(None));
+        assert_eq!(parse_reminder("  ", None), Ok(None));
+        assert_eq!(
+            parse_reminder("15m", Some(close_at)),
+            Ok(Some(close_at - chrono::Duration::minutes(15)))
+        );
+        assert!(parse_reminder("soon", Some(close_at)).is_err());
+        assert!(parse_reminder("15m", None).is_err());
+        assert!(parse_reminder("3h", Some(close_at)).is_err());
+    }
+}
@@ -0,0 +1,264 @@
+//! `gb repair <channel>`：從頻道歷史修復團購貼文
+//!
+//! 掃描頻道最近的訊息，依 props 的團購標記（`leko_group_buy`）找回 bot 發出的團購貼文：
+//! 資料庫沒有記錄 post_id 或記錄的貼文已被刪除時改指向找到的貼文，
+//! 貼文的按鈕被移除（例如被其他整合覆蓋 props）時重新產生。
+
+use super::messages::parse_group_buy_marker;
+use super::*;
+use crate::mattermost::PostDetail;
+use std::collections::HashSet;
+
+/// 每頁讀取的訊息數（Mattermost 上限 200）
+const POSTS_PER_PAGE: u32 = 200;
+
+/// 最多掃描的頁數
+const MAX_PAGES: u32 = 5;
+
+/// 單一團購貼文需要的修復
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Repair {
+    /// 資料庫的 post_id 改指向這則貼文
+    pub relink: bool,
+    /// 重新產生貼文的訊息與按鈕
+    pub reattach: bool,
+}
+
+/// 修復結果
+#[derive(Debug, Default)]
+pub struct RepairReport {
+    /// 掃描的訊息數
+    pub scanned: usize,
+    /// 找到的團購貼文數
+    pub found: usize,
+    /// 已修復的團購：(商家名稱, 修復內容)
+    pub repaired: Vec<(String, Repair)>,
+    /// 無法修復的原因
+    pub errors: Vec<String>,
+}
+
+/// 貼文上是否還有任何按鈕
+fn has_buttons(post: &PostDetail) -> bool {
+    post.props
+        .get("attachments")
+        .and_then(|v| v.as_array())
+        .is_some_and(|attachments| {
+            attachments.iter().any(|a| {
+                a.get("actions")
+                    .and_then(|v| v.as_array())
+                    .is_some_and(|actions| !actions.is_empty())
+            })
+        })
+}
+
+/// bot 以 API 發出的貼文，或經 slash command 的 response_url 發出的貼文
+/// （作者是執行指令的使用者，props 帶有 `from_webhook`）
+fn is_bot_post(post: &PostDetail, bot_user_id: &str) -> bool {
+    post.user_id == bot_user_id
+        || post.props.get("from_webhook").and_then(|v| v.as_str()) == Some("true")
+}
+
+/// 判斷找到的團購貼文需要哪些修復。
+/// `recorded_post_alive` 為資料庫記錄的貼文是否還在；記錄的貼文還在且不是這則時
+/// 以資料庫為準，不做任何修改。
+pub fn plan_repair(
+    post: &PostDetail,
+    group_buy: &GroupBuy,
+    recorded_post_alive: bool,
+) -> Option<Repair> {
+    let is_recorded = group_buy.post_id.as_deref() == Some(post.id.as_str());
+    if !is_recorded && recorded_post_alive {
+        return None;
+    }
+    let repair = Repair {
+        relink: !is_recorded,
+        reattach: !has_buttons(post),
+    };
+    (repair != Repair::default()).then_some(repair)
+}
+
+/// 掃描頻道最近的訊息並修復團購貼文
+pub async fn repair_channel(state_guard: &AppState, channel_id: &str) -> Result<RepairReport> {
+    let client = &state_guard.mattermost_client;
+    let mut report = RepairReport::default();
+    // 同一個團購可能有多則貼文（例如重新發布），只處理最新的一則
+    let mut seen: HashSet<String> = HashSet::new();
+
+    for page in 0..MAX_PAGES {
+        let posts = client
+            .get_channel_posts(channel_id, page, POSTS_PER_PAGE)
+            .await?;
+        report.scanned += posts.len();
+
+        for post in &posts {
+            if !is_bot_post(post, &state_guard.bot_user_id) {
+                continue;
+            }
+            let Some(marker) = parse_group_buy_marker(&post.props) else {
+                continue;
+            };
+            if !seen.insert(marker.group_buy_id.clone()) {
+                continue;
+            }
+            report.found += 1;
+
+            match repair_post(state_guard, post, &marker.group_buy_id).await {
+                Ok(Some((merchant_name, repair))) => report.repaired.push((merchant_name, repair)),
+                Ok(None) => {}
+                Err(e) => report
+                    .errors
+                    .push(format!("`{}`：{}", marker.group_buy_id, e)),
+            }
+        }
+
+        if posts.len() < POSTS_PER_PAGE as usize {
+            break;
+        }
+    }
+
+    Ok(report)
+}
+
+/// 修復單一團購貼文，不需要修復時回傳 None
+async fn repair_post(
+    state_guard: &AppState,
+    post: &PostDetail,
+    group_buy_id: &str,
+) -> Result<Option<(String, Repair)>> {
+    let Some(mut group_buy) = state_guard.database.get_group_buy(group_buy_id).await? else {
+        anyhow::bail!("資料庫中找不到此團購");
+    };
+    if group_buy.channel_id != post.channel_id {
+        anyhow::bail!("團購不屬於此頻道");
+    }
+
+    let recorded_post_alive = match group_buy.post_id.as_deref() {
+        Some(recorded) if recorded != post.id => state_guard
+            .mattermost_client
+            .post_exists(recorded)
+            .await
+            .unwrap_or(true),
+        _ => false,
+    };
+    let Some(repair) = plan_repair(post, &group_buy, recorded_post_alive) else {
+        return Ok(None);
+    };
+
+    if repair.relink {
+        state_guard
+            .database
+            .update_post_id(&group_buy.id, &post.id)
+            .await?;
+        group_buy.post_id = Some(post.id.clone());
+    }
+    if repair.reattach {
+        super::utils::sync_group_buy_post(state_guard, &group_buy).await;
+    }
+
+    info!("修復團購 {} 的貼文 {}：{:?}", group_buy.id, post.id, repair);
+    Ok(Some((group_buy.merchant_name, repair)))
+}
+
+/// 產生修復結果訊息
+pub fn format_repair_report(channel_id: &str, report: &RepairReport) -> String {
+    let mut message = format!(
+        "### 🔧 團購貼文修復\n\n頻道：`{}`\n\n- **掃描訊息**: {} 則\n- **團購貼文**: {} 則\n- **已修復**: {} 個\n",
+        channel_id,
+        report.scanned,
+        report.found,
+        report.repaired.len()
+    );
+    for (merchant_name, repair) in &report.repaired {
+        let mut fixes = Vec::new();
+        if repair.relink {
+            fixes.push("補上 post_id");
+        }
+        if repair.reattach {
+            fixes.push("重新加上按鈕");
+        }
+        message.push_str(&format!("  - {}：{}\n", merchant_name, fixes.join("、")));
+    }
+    if !report.errors.is_empty() {
+        message.push_str(&format!("- **無法修復**: {} 個\n", report.errors.len()));
+        for error in &report.errors {
+            message.push_str(&format!("  - {}\n", error));
+        }
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::utils::make_group_buy;
+
+    fn post(id: &str, with_buttons: bool) -> PostDetail {
+        let actions = if with_buttons {
+            serde_json::json!([{ "id": "register", "name": "登記" }])
+        } else {
+            serde_json::json!([])
+        };
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "channel_id": "chan",
+            "user_id": "bot",
+            "props": group_buy_post_props("gb-1", &[serde_json::json!({ "actions": actions })]),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_plan_repair() {
+        let mut group_buy = make_group_buy("gb-1".to_string(), 1);
+
+        // 資料庫沒有 post_id
+        assert_eq!(
+            plan_repair(&post("p1", true), &group_buy, false),
+            Some(Repair {
+                relink: true,
+                reattach: false
+            })
+        );
+
+        // 已記錄的貼文按鈕被移除
+        group_buy.post_id = Some("p1".to_string());
+        assert_eq!(
+            plan_repair(&post("p1", false), &group_buy, false),
+            Some(Repair {
+                relink: false,
+                reattach: true
+            })
+        );
+        assert_eq!(plan_repair(&post("p1", true), &group_buy, false), None);
+
+        // 記錄的是另一則貼文：還在時以資料庫為準，已刪除時改指向找到的貼文
+        assert_eq!(plan_repair(&post("p2", true), &group_buy, true), None);
+        assert_eq!(
+            plan_repair(&post("p2", true), &group_buy, false),
+            Some(Repair {
+                relink: true,
+                reattach: false
+            })
+        );
+    }
+
+    #[test]
+    fn test_format_repair_report() {
+        let report = RepairReport {
+            scanned: 120,
+            found: 2,
+            repaired: vec![(
+                "五十嵐".to_string(),
+                Repair {
+                    relink: true,
+                    reattach: true,
+                },
+            )],
+            errors: vec!["`gb-2`：資料庫中找不到此團購".to_string()],
+        };
+        let message = format_repair_report("chan", &report);
+        assert!(message.contains("- **掃描訊息**: 120 則"));
+        assert!(message.contains("五十嵐：補上 post_id、重新加上按鈕"));
+        assert!(message.contains("- **無法修復**: 1 個"));
+    }
+}
@@ -0,0 +1,343 @@
+//! 「重試」按鈕
+//!
+//! 版本衝突（`GB_CONFLICT`）或 Mattermost 暫時失敗（`MATTERMOST_ERROR`）時，錯誤改以臨時訊息
+//! 發送並附上「重試」按鈕。按鈕保存原本的操作：團購按鈕保存原本的 context，dialog 保存填寫的
+//! 內容與 state，重試時不需要重新填寫。重試 dialog 時 state 中的版本號會換成團購目前的版本。
+
+use super::*;
+use crate::mattermost::{ActionRequest, action_id};
+use crate::signing::StateSigner;
+use warp::Reply;
+
+/// 重試按鈕的有效時間
+const RETRY_TTL_MINUTES: i64 = 30;
+
+/// 重試時重新送出的 dialog 以此 callback_id 標記：dialog 已經關閉，錯誤一律改以臨時訊息回覆
+pub const RETRY_CALLBACK_ID: &str = "retry";
+
+/// 重試按鈕保存的操作
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum RetryTarget {
+    /// 團購按鈕。`context` 為簽章前的原始 context，`post_id` 為原本按鈕所在的貼文
+    Action {
+        context: serde_json::Value,
+        post_id: String,
+    },
+    /// dialog 送出。`state` 為驗證過的 state 內容
+    Dialog {
+        dialog: String,
+        team_id: String,
+        submission: HashMap<String, serde_json::Value>,
+        state: serde_json::Value,
+    },
+}
+
+/// 重試有機會成功的錯誤
+pub fn is_retryable(code: ErrorCode) -> bool {
+    matches!(code, ErrorCode::GbConflict | ErrorCode::MattermostError)
+}
+
+/// 錯誤訊息與「重試」按鈕
+fn retry_attachment(
+    text: &str,
+    group_buy_id: &str,
+    target: &RetryTarget,
+    bot_callback_url: &str,
+    signer: &StateSigner,
+) -> serde_json::Value {
+    serde_json::json!({
+        "text": text,
+        "actions": [{
+            "id": action_id("retry", group_buy_id),
+            "name": "重試",
+            "type": "button",
+            "integration": {
+                "url": format!("{}/api/v1/group_buy/action/retry", bot_callback_url.trim_end_matches('/')),
+                "context": signer.sign_context(serde_json::json!({
+                    "action": "retry",
+                    "group_buy_id": group_buy_id,
+                    "retry": target,
+                }), Some(chrono::Duration::minutes(RETRY_TTL_MINUTES)))
+            }
+        }]
+    })
+}
+
+/// 以臨時訊息發送錯誤，`target` 有值時附上「重試」按鈕
+async fn send_error_prompt(
+    state_guard: &AppState,
+    channel_id: &str,
+    user_id: &str,
+    group_buy_id: &str,
+    text: &str,
+    target: Option<&RetryTarget>,
+) -> Result<()> {
+    let client = &state_guard.mattermost_client;
+    let Some(target) = target else {
+        return client
+            .send_ephemeral_post(channel_id, user_id, text, None)
+            .await;
+    };
+    let bot_callback_url = super::utils::bot_callback_url_from_state(state_guard);
+    let attachment = retry_attachment(
+        text,
+        group_buy_id,
+        target,
+        &bot_callback_url,
+        client.signer(),
+    );
+    client
+        .send_ephemeral_post_with_props(
+            channel_id,
+            user_id,
+            "",
+            serde_json::json!({ "attachments": [attachment] }),
+        )
+        .await
+}
+
+/// 團購按鈕失敗時的回應。可重試的錯誤另外發送附有「重試」按鈕的臨時訊息，
+/// 發送失敗時退回一般的錯誤回應
+pub async fn action_error(
+    state_guard: &AppState,
+    action_req: &ActionRequest,
+    code: ErrorCode,
+    message: impl std::fmt::Display,
+) -> serde_json::Value {
+    if !is_retryable(code) {
+        return code.ephemeral(message);
+    }
+    let text = code.user_message(message);
+    let target = RetryTarget::Action {
+        context: StateSigner::unsigned_context(&action_req.context),
+        post_id: action_req.post_id.clone(),
+    };
+    let group_buy_id = action_req.context["group_buy_id"]
+        .as_str()
+        .unwrap_or_default();
+    match send_error_prompt(
+        state_guard,
+        &action_req.channel_id,
+        &action_req.user_id,
+        group_buy_id,
+        &text,
+        Some(&target),
+    )
+    .await
+    {
+        Ok(()) => serde_json::json!({ "error_code": code }),
+        Err(e) => {
+            error!("發送重試按鈕失敗: {}", e);
+            serde_json::json!({ "ephemeral_text": text, "error_code": code })
+        }
+    }
+}
+
+/// dialog 送出失敗時的回應。可重試的錯誤關閉 dialog 並發送附有「重試」按鈕的臨時訊息；
+/// 重試送出的 dialog 已經關閉，其他錯誤也改以臨時訊息回覆
+pub async fn dialog_error(
+    state_guard: &AppState,
+    dialog: &str,
+    submission: &DialogSubmission,
+    state_data: &serde_json::Value,
+    code: ErrorCode,
+    message: impl std::fmt::Display,
+) -> WithStatus<Json> {
+    let text = code.user_message(message);
+    let is_retry = submission.callback_id == RETRY_CALLBACK_ID;
+
+    if is_retryable(code) || is_retry {
+        let target = is_retryable(code).then(|| RetryTarget::Dialog {
+            dialog: dialog.to_string(),
+            team_id: submission.team_id.clone(),
+            submission: submission.submission.clone(),
+            state: state_data.clone(),
+        });
+        let group_buy_id = state_data["group_buy_id"].as_str().unwrap_or_default();
+        match send_error_prompt(
+            state_guard,
+            &submission.channel_id,
+            &submission.user_id,
+            group_buy_id,
+            &text,
+            target.as_ref(),
+        )
+        .await
+        {
+            Ok(()) => {
+                return warp::reply::with_status(
+                    warp::reply::json(&DialogSubmissionResponse {
+                        error: None,
+                        text: None,
+                        errors: None,
+                    }),
+                    StatusCode::OK,
+                );
+            }
+            Err(e) => error!("發送重試按鈕失敗: {}", e),
+        }
+    }
+
+    warp::reply::with_status(
+        warp::reply::json(&DialogSubmissionResponse {
+            error: Some(text),
+            text: None,
+            errors: None,
+        }),
+        StatusCode::OK,
+    )
+}
+
+/// 處理「重試」按鈕：每個按鈕只能使用一次，依保存的操作重新執行
+pub async fn handle_retry_action(
+    action_req: ActionRequest,
+    state: Arc<RwLock<AppState>>,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let (signer, nonce_store) = {
+        let app_state = state.read().await;
+        (
+            app_state.mattermost_client.signer().clone(),
+            app_state.nonce_store.clone(),
+        )
+    };
+    let target = match signer
+        .consume_context(&action_req.context, &nonce_store)
+        .await
+    {
+        Ok(()) => serde_json::from_value::<RetryTarget>(action_req.context["retry"].clone()).ok(),
+        Err(e) => {
+            info!("拒絕重試（{}）", e);
+            None
+        }
+    };
+    let Some(target) = target else {
+        return Ok(warp::reply::json(
+            &ErrorCode::InvalidAction.ephemeral("⚠️ 重試按鈕已失效，請重新操作"),
+        )
+        .into_response());
+    };
+
+    match target {
+        RetryTarget::Action { context, post_id } => {
+            let action = context["action"].as_str().unwrap_or_default().to_string();
+            info!("{} 重試團購操作 {}", action_req.user_id, action);
+            let request = ActionRequest {
+                user_id: action_req.user_id,
+                user_name: action_req.user_name,
+                channel_id: action_req.channel_id,
+                post_id,
+                trigger_id: action_req.trigger_id,
+                context,
+            };
+            super::actions::dispatch(&action, request, state)
+                .await
+                .map(Reply::into_response)
+        }
+        RetryTarget::Dialog {
+            dialog,
+            team_id,
+            submission,
+            state: mut state_data,
+        } => {
+            info!("{} 重試送出 {} dialog", action_req.user_id, dialog);
+            refresh_version(&state.read().await.database, &mut state_data).await;
+
+            let payload = serde_json::json!({
+                "type": "dialog_submission",
+                "callback_id": RETRY_CALLBACK_ID,
+                "state": signer.sign(&state_data.to_string()),
+                "user_id": action_req.user_id,
+                "channel_id": action_req.channel_id,
+                "team_id": team_id,
+                "submission": submission,
+                "cancelled": false,
+            });
+            let form = HashMap::from([("payload".to_string(), payload.to_string())]);
+
+            // dialog 的回應對按鈕沒有意義，失敗時 dialog_error 會另外發送臨時訊息
+            match dialog.as_str() {
+                "create" => handle_create_dialog(form, state).await?.into_response(),
+                "edit_items" => handle_edit_items_dialog(form, state).await?.into_response(),
+                "register" => handle_register_dialog(form, state).await?.into_response(),
+                "cancel_register" => handle_cancel_register_dialog(form, state)
+                    .await?
+                    .into_response(),
+                "payments" => handle_payments_dialog(form, state).await?.into_response(),
+                _ => {
+                    error!("未知的重試 dialog: {}", dialog);
+                    return Ok(warp::reply::json(
+                        &ErrorCode::InvalidAction.ephemeral("⚠️ 重試按鈕已失效，請重新操作"),
+                    )
+                    .into_response());
+                }
+            };
+
+            Ok(warp::reply::json(&serde_json::json!({
+                "update": { "message": "🔄 已重新送出", "props": {} }
+            }))
+            .into_response())
+        }
+    }
+}
+
+/// state 中有團購版本時換成目前的版本，讓版本衝突後的重試以最新資料為準
+async fn refresh_version(database: &crate::database::Database, state_data: &mut serde_json::Value) {
+    if state_data.get("version").is_none() {
+        return;
+    }
+    let Some(group_buy_id) = state_data["group_buy_id"].as_str() else {
+        return;
+    };
+    match database.get_group_buy(group_buy_id).await {
+        Ok(Some(group_buy)) => state_data["version"] = serde_json::json!(group_buy.version),
+        Ok(None) => {}
+        Err(e) => error!("重試時取得團購版本失敗: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::utils::{insert_group_buy, setup_db};
+
+    #[test]
+    fn test_retry_target_roundtrip() {
+        let signer = StateSigner::new("secret");
+        let target = RetryTarget::Dialog {
+            dialog: "edit_items".to_string(),
+            team_id: "team".to_string(),
+            submission: HashMap::from([("items".to_string(), serde_json::json!("紅茶: 30"))]),
+            state: serde_json::json!({ "group_buy_id": "gb-1", "version": 1 }),
+        };
+        let attachment = retry_attachment("⚠️ 失敗", "gb-1", &target, "http://bot/", &signer);
+
+        let button = &attachment["actions"][0];
+        assert_eq!(button["name"], "重試");
+        assert_eq!(
+            button["integration"]["url"],
+            "http://bot/api/v1/group_buy/action/retry"
+        );
+        let context = &button["integration"]["context"];
+        assert_eq!(signer.verify_context(context), Ok(()));
+        assert_eq!(
+            serde_json::from_value::<RetryTarget>(context["retry"].clone()).unwrap(),
+            target
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresh_version() {
+        let db = setup_db().await;
+        let group_buy = insert_group_buy(&db, 3).await;
+
+        let mut state_data = serde_json::json!({ "group_buy_id": group_buy.id, "version": 1 });
+        refresh_version(&db, &mut state_data).await;
+        assert_eq!(state_data["version"], 3);
+
+        // 沒有版本的 state 不變
+        let mut state_data = serde_json::json!({ "response_url": "" });
+        refresh_version(&db, &mut state_data).await;
+        assert_eq!(state_data, serde_json::json!({ "response_url": "" }));
+    }
+}
@@ -0,0 +1,351 @@
+//! 缺貨調整的預覽
+//!
+//! 建立者只填「某商品實際到貨 N 份」時，bot 依比例或登記先後把缺少的數量分配給
+//! 各購買人，確認後才透過 `adjust_order_quantity` 寫入；逐筆填寫的 YAML 也先列出
+//! 原數量 → 調整後與受影響的小計，確認後才套用。
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::database::GroupBuyOrder;
+
+/// 缺貨的分配方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitMethod {
+    /// 依登記數量比例分配，餘數給小數部分較大（同分時較早登記）的人
+    Proportional,
+    /// 依登記先後，先登記的人先拿滿
+    Fifo,
+}
+
+impl SplitMethod {
+    pub fn from_string(s: &str) -> Option<Self> {
+        match s {
+            "proportional" => Some(SplitMethod::Proportional),
+            "fifo" => Some(SplitMethod::Fifo),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SplitMethod::Proportional => "proportional",
+            SplitMethod::Fifo => "fifo",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SplitMethod::Proportional => "依比例",
+            SplitMethod::Fifo => "依登記先後",
+        }
+    }
+}
+
+/// 一位購買人分配後的數量
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SplitResult {
+    pub buyer_username: String,
+    pub old_quantity: i32,
+    pub new_quantity: i32,
+}
+
+/// 依購買人彙總同一商品的訂單：(購買人, 數量合計, 最早登記時間)，依最早登記時間排序
+fn buyer_demands(orders: &[GroupBuyOrder], item_name: &str) -> Vec<(String, i32, DateTime<Utc>)> {
+    let mut demands: BTreeMap<&str, (i32, DateTime<Utc>)> = BTreeMap::new();
+    for order in orders.iter().filter(|o| o.item_name == item_name) {
+        let entry = demands
+            .entry(order.buyer_username.as_str())
+            .or_insert((0, order.created_at));
+        entry.0 += order.quantity;
+        entry.1 = entry.1.min(order.created_at);
+    }
+    let mut demands: Vec<_> = demands
+        .into_iter()
+        .map(|(buyer, (quantity, first))| (buyer.to_string(), quantity, first))
+        .collect();
+    demands.sort_by(|a, b| a.2.cmp(&b.2).then_with(|| a.0.cmp(&b.0)));
+    demands
+}
+
+/// 把 `arrived` 份分配給登記該商品的購買人，結果依登記先後排序。
+/// 到貨數量不少於登記總數時每個人維持原數量。
+pub fn split_shortage(
+    orders: &[GroupBuyOrder],
+    item_name: &str,
+    arrived: i32,
+    method: SplitMethod,
+) -> Vec<SplitResult> {
+    let demands = buyer_demands(orders, item_name);
+    let total: i64 = demands.iter().map(|d| d.1 as i64).sum();
+    let arrived = arrived.max(0) as i64;
+
+    let allocated: Vec<i64> = if arrived >= total {
+        demands.iter().map(|d| d.1 as i64).collect()
+    } else {
+        match method {
+            SplitMethod::Fifo => {
+                let mut remaining = arrived;
+                demands
+                    .iter()
+                    .map(|d| {
+                        let take = remaining.min(d.1 as i64);
+                        remaining -= take;
+                        take
+                    })
+                    .collect()
+            }
+            SplitMethod::Proportional => {
+                // 最大餘數法：先取整數部分，剩下的份數依餘數由大到小分配
+                let mut allocated: Vec<i64> = demands
+                    .iter()
+                    .map(|d| d.1 as i64 * arrived / total)
+                    .collect();
+                let mut by_remainder: Vec<usize> = (0..demands.len()).collect();
+                by_remainder
+                    .sort_by_key(|&i| std::cmp::Reverse(demands[i].1 as i64 * arrived % total));
+                let leftover = arrived - allocated.iter().sum::<i64>();
+                for &i in by_remainder.iter().take(leftover as usize) {
+                    allocated[i] += 1;
+                }
+                allocated
+            }
+        }
+    };
+
+    demands
+        .into_iter()
+        .zip(allocated)
+        .map(
+            |((buyer_username, old_quantity, _), new_quantity)| SplitResult {
+                buyer_username,
+                old_quantity,
+                new_quantity: new_quantity as i32,
+            },
+        )
+        .collect()
+}
+
+/// 預覽分配結果的表格，只列出數量有變動的人
+pub fn format_split_preview(
+    item_name: &str,
+    arrived: i32,
+    method: SplitMethod,
+    results: &[SplitResult],
+) -> String {
+    let total: i32 = results.iter().map(|r| r.old_quantity).sum();
+    let mut text = format!(
+        "### 📦 {} 實際到貨 {} 份（登記 {} 份，{}分配）\n\n",
+        item_name,
+        arrived,
+        total,
+        method.label()
+    );
+    let changed: Vec<&SplitResult> = results
+        .iter()
+        .filter(|r| r.old_quantity != r.new_quantity)
+        .collect();
+    if changed.is_empty() {
+        text.push_str("到貨數量足夠，不需要調整。");
+        return text;
+    }
+    text.push_str("| 購買人 | 原數量 | 調整後 |\n|--------|------:|------:|\n");
+    for r in changed {
+        text.push_str(&format!(
+            "| @{} | {} | {} |\n",
+            r.buyer_username, r.old_quantity, r.new_quantity
+        ));
+    }
+    text.push_str("\n確認無誤後按「套用」。");
+    text
+}
+
+/// 一筆訂單的數量調整
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrderChange {
+    pub order_id: String,
+    pub old_quantity: i32,
+    pub new_quantity: i32,
+}
+
+/// 把逐筆填寫的調整（order_id → 新數量）對應到現有訂單，只保留數量有變動的訂單。
+/// 有不存在的 order_id 時回傳錯誤訊息。
+pub fn plan_order_changes(
+    orders: &[GroupBuyOrder],
+    adjustments: &HashMap<String, i32>,
+) -> Result<Vec<OrderChange>, String> {
+    let mut unknown: Vec<&str> = adjustments
+        .keys()
+        .filter(|id| !orders.iter().any(|o| &o.id == *id))
+        .map(String::as_str)
+        .collect();
+    if !unknown.is_empty() {
+        unknown.sort();
+        return Err(format!("找不到訂單：{}", unknown.join("、")));
+    }
+
+    Ok(orders
+        .iter()
+        .filter_map(|order| {
+            let new_quantity = *adjustments.get(&order.id)?;
+            (new_quantity != order.quantity).then(|| OrderChange {
+                order_id: order.id.clone(),
+                old_quantity: order.quantity,
+                new_quantity,
+            })
+        })
+        .collect())
+}
+
+/// 預覽逐筆調整：每筆訂單的數量與小計變化，以及受影響購買人的應付金額
+pub fn format_changes_preview(
+    orders: &[GroupBuyOrder],
+    changes: &[OrderChange],
+    currency: &str,
+) -> String {
+    if changes.is_empty() {
+        return "### 📦 調整預覽\n\n填寫的數量都和目前相同，不需要調整。".to_string();
+    }
+
+    let new_quantity_of = |order: &GroupBuyOrder| {
+        changes
+            .iter()
+            .find(|c| c.order_id == order.id)
+            .map(|c| c.new_quantity)
+            .unwrap_or(order.quantity)
+    };
+
+    let mut text = String::from(
+        "### 📦 調整預覽\n\n| 購買人 | 商品 | 數量 | 小計 |\n|--------|------|------|------|\n",
+    );
+    let mut affected: BTreeMap<&str, (Decimal, Decimal)> = BTreeMap::new();
+    for order in orders {
+        if changes.iter().any(|c| c.order_id == order.id) {
+            let old_subtotal = crate::money::line_total(order.unit_price, order.quantity);
+            let new_subtotal = crate::money::line_total(order.unit_price, new_quantity_of(order));
+            text.push_str(&format!(
+                "| @{} | {} | {} → {} | {}{} → {}{} |\n",
+                order.buyer_username,
+                order.item_name,
+                order.quantity,
+                new_quantity_of(order),
+                currency,
+                old_subtotal,
+                currency,
+                new_subtotal
+            ));
+            affected.insert(
+                order.buyer_username.as_str(),
+                (Decimal::ZERO, Decimal::ZERO),
+            );
+        }
+    }
+
+    for order in orders {
+        if let Some(totals) = affected.get_mut(order.buyer_username.as_str()) {
+            totals.0 += crate::money::line_total(order.unit_price, order.quantity);
+            totals.1 += crate::money::line_total(order.unit_price, new_quantity_of(order));
+        }
+    }
+    text.push_str("\n| 購買人 | 應付 |\n|--------|------|\n");
+    for (buyer, (old_total, new_total)) in affected {
+        text.push_str(&format!(
+            "| @{} | {}{} → {}{} |\n",
+            buyer, currency, old_total, currency, new_total
+        ));
+    }
+    text.push_str("\n確認無誤後按「套用」。");
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::utils::make_order_for;
+
+    fn orders(spec: &[(&str, i32)]) -> Vec<GroupBuyOrder> {
+        let start = Utc::now();
+        spec.iter()
+            .enumerate()
+            .map(|(i, (buyer, qty))| {
+                let mut order = make_order_for("gb-1".to_string(), buyer, buyer);
+                order.item_name = "雞排".to_string();
+                order.quantity = *qty;
+                order.created_at = start + chrono::Duration::seconds(i as i64);
+                order
+            })
+            .collect()
+    }
+
+    fn new_quantities(results: &[SplitResult]) -> Vec<(&str, i32)> {
+        results
+            .iter()
+            .map(|r| (r.buyer_username.as_str(), r.new_quantity))
+            .collect()
+    }
+
+    #[test]
+    fn test_split_proportional_uses_largest_remainder() {
+        let orders = orders(&[("alice", 3), ("bob", 2), ("carol", 1), ("alice", 1)]);
+        // 登記 7 份、到貨 4 份：4*4/7=2.28、2*4/7=1.14、1*4/7=0.57
+        let results = split_shortage(&orders, "雞排", 4, SplitMethod::Proportional);
+        assert_eq!(
+            new_quantities(&results),
+            vec![("alice", 2), ("bob", 1), ("carol", 1)]
+        );
+        assert_eq!(results[0].old_quantity, 4);
+        assert_eq!(results.iter().map(|r| r.new_quantity).sum::<i32>(), 4);
+    }
+
+    #[test]
+    fn test_split_fifo_and_enough_stock() {
+        let orders = orders(&[("alice", 2), ("bob", 2), ("carol", 2)]);
+        let results = split_shortage(&orders, "雞排", 3, SplitMethod::Fifo);
+        assert_eq!(
+            new_quantities(&results),
+            vec![("alice", 2), ("bob", 1), ("carol", 0)]
+        );
+
+        let results = split_shortage(&orders, "雞排", 10, SplitMethod::Fifo);
+        assert!(results.iter().all(|r| r.old_quantity == r.new_quantity));
+        assert!(
+            format_split_preview("雞排", 10, SplitMethod::Fifo, &results).contains("不需要調整")
+        );
+        assert!(split_shortage(&orders, "排骨", 1, SplitMethod::Fifo).is_empty());
+    }
+
+    #[test]
+    fn test_plan_and_preview_order_changes() {
+        let mut orders = orders(&[("alice", 2), ("alice", 1), ("bob", 3)]);
+        for order in &mut orders {
+            order.unit_price = Decimal::from(50);
+        }
+        orders[1].item_name = "地瓜球".to_string();
+
+        let adjustments = HashMap::from([(orders[0].id.clone(), 1), (orders[2].id.clone(), 3)]);
+        let changes = plan_order_changes(&orders, &adjustments).unwrap();
+        assert_eq!(
+            changes,
+            vec![OrderChange {
+                order_id: orders[0].id.clone(),
+                old_quantity: 2,
+                new_quantity: 1,
+            }]
+        );
+
+        let preview = format_changes_preview(&orders, &changes, "NT$");
+        assert!(preview.contains("| @alice | 雞排 | 2 → 1 | NT$100 → NT$50 |"));
+        // 應付金額包含同一購買人沒有調整的訂單
+        assert!(preview.contains("| @alice | NT$150 → NT$100 |"));
+        assert!(!preview.contains("@bob"));
+
+        let unknown = HashMap::from([("nope".to_string(), 0)]);
+        assert_eq!(
+            plan_order_changes(&orders, &unknown).unwrap_err(),
+            "找不到訂單：nope"
+        );
+    }
+}
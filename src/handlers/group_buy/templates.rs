@@ -0,0 +1,197 @@
+//! 團購範本：`/group_buy template save|use|list|delete <名稱>`
+//!
+//! 每週固定跟同一家店訂時，把自己在頻道中最近一次的團購（商家、描述、其他資訊、商品）
+//! 存成範本，之後 `template use` 開啟預先填好的建立對話框。範本存在
+//! `group_buy_templates`，同頻道的成員共用，名稱在頻道內不重複。
+
+use super::*;
+use crate::database::GroupBuyTemplate;
+
+/// 範本名稱的長度上限（字元）
+const MAX_TEMPLATE_NAME_CHARS: usize = 50;
+
+/// 存成範本時不保留的其他資訊：每次團購都不同
+const SKIPPED_METADATA_KEYS: &[&str] = &["截止時間"];
+
+const TEMPLATE_USAGE: &str = "用法：\n\
+- `/group_buy template save <名稱>` - 把你在此頻道最近一次的團購存成範本\n\
+- `/group_buy template use <名稱>` - 以範本開啟建立團購對話框\n\
+- `/group_buy template list` - 列出此頻道的範本\n\
+- `/group_buy template delete <名稱>` - 刪除範本";
+
+/// `template` 子指令的結果
+pub enum TemplateCommand {
+    /// 直接回覆使用者
+    Reply(WithStatus<Json>),
+    /// 以範本開啟建立對話框
+    Use(GroupBuyTemplate),
+}
+
+fn reply(text: String) -> TemplateCommand {
+    TemplateCommand::Reply(warp::reply::with_status(
+        warp::reply::json(&SlashCommandResponse {
+            response_type: "ephemeral".to_string(),
+            text,
+        }),
+        StatusCode::OK,
+    ))
+}
+
+/// 檢查範本名稱
+pub fn validate_template_name(name: &str) -> std::result::Result<&str, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(format!("請填寫範本名稱\n\n{}", TEMPLATE_USAGE));
+    }
+    if name.chars().count() > MAX_TEMPLATE_NAME_CHARS {
+        return Err(format!("範本名稱最多 {} 個字", MAX_TEMPLATE_NAME_CHARS));
+    }
+    Ok(name)
+}
+
+/// 由團購建立範本，略過每次都不同的其他資訊
+pub fn template_from_group_buy(name: &str, group_buy: &GroupBuy) -> GroupBuyTemplate {
+    GroupBuyTemplate {
+        channel_id: group_buy.channel_id.clone(),
+        name: name.to_string(),
+        merchant_name: group_buy.merchant_name.clone(),
+        description: group_buy.description.clone(),
+        metadata: group_buy
+            .metadata
+            .iter()
+            .filter(|(key, _)| !SKIPPED_METADATA_KEYS.contains(&key.as_str()))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect(),
+        items: group_buy.items.clone(),
+        item_icons: group_buy.item_icons.clone(),
+        item_sections: group_buy.item_sections.clone(),
+        item_translations: group_buy.item_translations.clone(),
+    }
+}
+
+/// 處理 `/group_buy template ...`
+pub async fn handle_template_command(
+    req: &SlashCommandRequest,
+    args: &[&str],
+    state_guard: &AppState,
+) -> TemplateCommand {
+    let db = &state_guard.database;
+    let (subcommand, rest) = args.split_first().unwrap_or((&"", &[]));
+    let name = rest.join(" ");
+
+    match *subcommand {
+        "save" => {
+            let name = match validate_template_name(&name) {
+                Ok(name) => name,
+                Err(msg) => return reply(format!("⚠️ {}", msg)),
+            };
+            let group_buy = match db
+                .get_latest_group_buy_by_creator(&req.channel_id, &req.user_id)
+                .await
+            {
+                Ok(Some(group_buy)) => group_buy,
+                Ok(None) => {
+                    return reply("⚠️ 你在此頻道還沒有填好商品的團購，無法存成範本".to_string());
+                }
+                Err(e) => {
+                    error!("取得最近的團購失敗: {}", e);
+                    return reply("取得最近的團購失敗".to_string());
+                }
+            };
+            let template = template_from_group_buy(name, &group_buy);
+            if let Err(e) = db.save_group_buy_template(&template, &req.user_id).await {
+                error!("儲存團購範本失敗: {}", e);
+                return reply(format!("儲存團購範本失敗: {}", e));
+            }
+            info!("{} 儲存了團購範本「{}」", req.user_name, name);
+            reply(format!(
+                "✅ 已將「{}」（{} 項商品）存為範本「{}」，使用 `/group_buy template use {}` 建立團購",
+                template.merchant_name,
+                template.items.len(),
+                name,
+                name
+            ))
+        }
+        "use" => {
+            let name = match validate_template_name(&name) {
+                Ok(name) => name,
+                Err(msg) => return reply(format!("⚠️ {}", msg)),
+            };
+            match db.get_group_buy_template(&req.channel_id, name).await {
+                Ok(Some(template)) => TemplateCommand::Use(template),
+                Ok(None) => reply(format!(
+                    "⚠️ 此頻道沒有名為「{}」的範本，使用 `/group_buy template list` 查看",
+                    name
+                )),
+                Err(e) => {
+                    error!("取得團購範本失敗: {}", e);
+                    reply("取得團購範本失敗".to_string())
+                }
+            }
+        }
+        "list" => match db.list_group_buy_templates(&req.channel_id).await {
+            Ok(templates) if templates.is_empty() => {
+                reply(format!("此頻道還沒有團購範本\n\n{}", TEMPLATE_USAGE))
+            }
+            Ok(templates) => {
+                let mut text = "### 📋 團購範本\n\n| 名稱 | 商家 |\n|------|------|\n".to_string();
+                for (name, merchant_name) in templates {
+                    text.push_str(&format!("| {} | {} |\n", name, merchant_name));
+                }
+                reply(text)
+            }
+            Err(e) => {
+                error!("取得團購範本失敗: {}", e);
+                reply("取得團購範本失敗".to_string())
+            }
+        },
+        "delete" => {
+            let name = match validate_template_name(&name) {
+                Ok(name) => name,
+                Err(msg) => return reply(format!("⚠️ {}", msg)),
+            };
+            match db.delete_group_buy_template(&req.channel_id, name).await {
+                Ok(true) => {
+                    info!("{} 刪除了團購範本「{}」", req.user_name, name);
+                    reply(format!("🗑️ 已刪除範本「{}」", name))
+                }
+                Ok(false) => reply(format!("⚠️ 此頻道沒有名為「{}」的範本", name)),
+                Err(e) => {
+                    error!("刪除團購範本失敗: {}", e);
+                    reply(format!("刪除團購範本失敗: {}", e))
+                }
+            }
+        }
+        _ => reply(TEMPLATE_USAGE.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_template_name() {
+        assert_eq!(validate_template_name("  週五飲料 "), Ok("週五飲料"));
+        assert!(validate_template_name(" ").is_err());
+        assert!(validate_template_name(&"長".repeat(MAX_TEMPLATE_NAME_CHARS + 1)).is_err());
+    }
+
+    #[test]
+    fn test_template_from_group_buy_skips_deadline() {
+        let mut group_buy = crate::test_utils::utils::make_group_buy("gb-1".to_string(), 1);
+        group_buy.metadata = [("取貨", "大廳"), ("截止時間", "2026-01-25 18:00")]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+
+        let template = template_from_group_buy("週五", &group_buy);
+        assert_eq!(template.name, "週五");
+        assert_eq!(template.merchant_name, group_buy.merchant_name);
+        assert_eq!(template.items, group_buy.items);
+        assert_eq!(
+            template.metadata,
+            [("取貨".to_string(), "大廳".to_string())].into()
+        );
+    }
+}
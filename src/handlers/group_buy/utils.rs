@@ -1,4 +1,5 @@
 use super::*;
+use crate::database::{DeletedOrders, ORDER_RESTORE_GRACE_MINUTES};
 use anyhow::Result;
 use std::collections::HashMap;
 
@@ -19,9 +20,16 @@ pub fn parse_dialog_submission_form(form: &HashMap<String, String>) -> Result<Di
     Ok(submission)
 }
 
-/// 從 `DialogSubmission` 的 `state` 欄位解析成 serde_json::Value
-pub fn extract_state_value(submission: &DialogSubmission) -> Result<serde_json::Value> {
+/// 驗證 `DialogSubmission` 的 `state` 簽章後解析成 serde_json::Value。
+/// 簽章不符（遭竄改或非本 bot 開啟的 dialog）時回傳錯誤。
+pub fn extract_state_value(
+    client: &MattermostClient,
+    submission: &DialogSubmission,
+) -> Result<serde_json::Value> {
     if let Some(state_str) = &submission.state {
+        let state_str = client
+            .verify_dialog_state(state_str)
+            .map_err(|e| anyhow::anyhow!("state 驗證失敗: {}", e))?;
         let v = serde_json::from_str(state_str)
             .map_err(|e| anyhow::anyhow!("解析 state 失敗: {}", e))?;
         Ok(v)
@@ -60,14 +68,284 @@ pub async fn fetch_group_buy(
 ) -> Result<GroupBuy, String> {
     match state_guard.database.get_group_buy(group_buy_id).await {
         Ok(Some(gb)) => Ok(gb),
-        Ok(None) => Err("找不到該團購".to_string()),
+        Ok(None) => Err(ErrorCode::GbNotFound.user_message("找不到該團購")),
         Err(e) => {
             tracing::error!("取得團購資料失敗: {}", e);
-            Err("取得團購資料失敗".to_string())
+            Err(ErrorCode::DatabaseError.user_message("取得團購資料失敗"))
         }
     }
 }
 
+/// 使用者是否可以管理團購：建立者，或開啟 `group_buy.channel_admins_can_manage` 時
+/// 團購所在頻道的頻道管理員。查詢頻道角色失敗時視為不能管理
+pub async fn can_manage_group_buy(
+    state_guard: &AppState,
+    group_buy: &GroupBuy,
+    user_id: &str,
+) -> bool {
+    if group_buy.creator_id == user_id {
+        return true;
+    }
+    if !state_guard.config.group_buy.channel_admins_can_manage {
+        return false;
+    }
+    match state_guard
+        .mattermost_client
+        .is_channel_admin(&group_buy.channel_id, user_id)
+        .await
+    {
+        Ok(is_admin) => {
+            if is_admin {
+                tracing::info!("{} 以頻道管理員身分管理團購 {}", user_id, group_buy.id);
+            }
+            is_admin
+        }
+        Err(e) => {
+            tracing::warn!("查詢頻道管理員失敗: {}", e);
+            false
+        }
+    }
+}
+
+/// dialog state 驗證失敗（dialog 已失效或遭竄改）時回覆的錯誤，請使用者重新開啟
+pub fn invalid_dialog_response() -> WithStatus<Json> {
+    warp::reply::with_status(
+        warp::reply::json(&DialogSubmissionResponse {
+            error: Some(ErrorCode::InvalidDialog.user_message("對話框已失效，請關閉後重新開啟")),
+            text: None,
+            errors: None,
+        }),
+        StatusCode::OK,
+    )
+}
+
+/// 以目前資料產生團購貼文的訊息與 props（按鈕、「完整名單」與標記）
+pub async fn group_buy_post_content(
+    state_guard: &AppState,
+    group_buy: &GroupBuy,
+) -> (String, serde_json::Value) {
+    let orders = state_guard
+        .database
+        .get_orders_by_group_buy(&group_buy.id)
+        .await
+        .unwrap_or_default();
+    let deactivated = super::deactivated::load_deactivated_users(&state_guard.database).await;
+    let message = generate_group_buy_message_with_orders(
+        &group_buy.merchant_name,
+        &group_buy.description,
+        &group_buy.metadata,
+        &group_buy.status,
+        &group_buy.items,
+        &group_buy.item_icons,
+        &group_buy.item_sections,
+        &group_buy.currency,
+        &orders,
+        &deactivated,
+        &state_guard.config.group_buy,
+    );
+
+    let bot_callback_url = bot_callback_url_from_state(state_guard);
+    let mut attachments = generate_action_buttons(
+        &group_buy.id,
+        &group_buy.status,
+        &bot_callback_url,
+        state_guard.mattermost_client.signer(),
+        &state_guard.config.group_buy.buttons,
+    );
+    add_full_list_button_if_needed(
+        &mut attachments,
+        &group_buy.id,
+        orders.len(),
+        &state_guard.config.group_buy,
+        &bot_callback_url,
+        state_guard.mattermost_client.signer(),
+    );
+    super::deactivated::add_deactivated_buyers_button_if_needed(
+        &mut attachments,
+        &group_buy.id,
+        &group_buy.status,
+        &orders,
+        &deactivated,
+        &bot_callback_url,
+        state_guard.mattermost_client.signer(),
+    );
+
+    (message, group_buy_post_props(&group_buy.id, &attachments))
+}
+
+/// 以目前資料重新產生團購貼文（訊息、按鈕與「完整名單」），轉發到其他頻道的貼文一併更新。
+/// 沒有 post_id 或貼文已被刪除時略過。
+/// 用於不是由貼文按鈕觸發的變更（管理 API、自動截止），失敗只記錄錯誤。
+pub async fn sync_group_buy_post(state_guard: &AppState, group_buy: &GroupBuy) {
+    sync_posts(state_guard, group_buy, None).await;
+}
+
+/// 按鈕的回應只會更新被按的那則貼文，其他頻道的同一個團購貼文由這裡更新
+pub async fn sync_other_group_buy_posts(
+    state_guard: &AppState,
+    group_buy: &GroupBuy,
+    clicked_post_id: &str,
+) {
+    sync_posts(state_guard, group_buy, Some(clicked_post_id)).await;
+}
+
+async fn sync_posts(state_guard: &AppState, group_buy: &GroupBuy, skip: Option<&str>) {
+    if let Some(sync) = PostSync::prepare(state_guard, group_buy, skip).await {
+        sync.run().await;
+    }
+}
+
+/// 一次更新團購貼文的內容。在持有 AppState 的鎖時以 `prepare` 讀取資料庫並產生內容，
+/// `run` 只呼叫 Mattermost API，背景工作可以先放開鎖再送出
+pub(super) struct PostSync {
+    client: MattermostClient,
+    database: crate::database::Database,
+    group_buy_id: String,
+    /// 團購本身的貼文，其他為轉發到其他頻道的貼文
+    main_post_id: Option<String>,
+    post_ids: Vec<String>,
+    message: String,
+    updates: serde_json::Value,
+}
+
+impl PostSync {
+    /// 找出要更新的貼文（略過 `skip`）並產生訊息與 props，沒有要更新的貼文時回傳 None
+    pub(super) async fn prepare(
+        state_guard: &AppState,
+        group_buy: &GroupBuy,
+        skip: Option<&str>,
+    ) -> Option<Self> {
+        let cross_posts = state_guard
+            .database
+            .get_cross_posts(&group_buy.id)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::error!("取得轉發的團購貼文失敗: {}", e);
+                Vec::new()
+            });
+        let post_ids: Vec<String> = group_buy
+            .post_id
+            .iter()
+            .chain(
+                cross_posts
+                    .iter()
+                    .filter_map(|(_, post_id)| post_id.as_ref()),
+            )
+            .filter(|post_id| Some(post_id.as_str()) != skip)
+            .cloned()
+            .collect();
+        if post_ids.is_empty() {
+            return None;
+        }
+
+        let (message, updates) = group_buy_post_content(state_guard, group_buy).await;
+        Some(Self {
+            client: state_guard.mattermost_client.clone(),
+            database: state_guard.database.clone(),
+            group_buy_id: group_buy.id.clone(),
+            main_post_id: group_buy.post_id.clone(),
+            post_ids,
+            message,
+            updates,
+        })
+    }
+
+    /// 更新各則貼文，失敗只記錄錯誤
+    pub(super) async fn run(self) {
+        for post_id in &self.post_ids {
+            // 先讀取貼文：已被刪除時不更新，還在時保留其他 props（例如代建立者發文的名稱與頭像）
+            let props = match self.client.get_post(post_id).await {
+                Ok(Some(post)) => post.merged_props(self.updates.clone()),
+                Ok(None) if self.main_post_id.as_ref() == Some(post_id) => {
+                    tracing::warn!("團購 {} 的貼文已被刪除，略過更新", self.group_buy_id);
+                    continue;
+                }
+                Ok(None) => {
+                    tracing::warn!(
+                        "團購 {} 轉發的貼文 {} 已被刪除，之後不再更新",
+                        self.group_buy_id,
+                        post_id
+                    );
+                    if let Err(e) = self.database.delete_cross_post(post_id).await {
+                        tracing::error!("刪除轉發貼文紀錄失敗: {}", e);
+                    }
+                    continue;
+                }
+                Err(e) => {
+                    tracing::warn!("讀取團購貼文失敗，直接更新: {}", e);
+                    self.updates.clone()
+                }
+            };
+
+            if let Err(e) = self
+                .client
+                .update_post(post_id, &self.message, Some(props))
+                .await
+            {
+                tracing::error!("更新團購貼文失敗: {}", e);
+            }
+        }
+    }
+}
+
+/// 取消登記後，以臨時訊息提供團購建立者「復原」按鈕。
+/// 按鈕簽章的有效時間與復原期限相同，發送失敗只記錄錯誤。
+pub async fn offer_restore(
+    state_guard: &AppState,
+    group_buy_id: &str,
+    deleted: &DeletedOrders,
+    summary: &str,
+) {
+    if deleted.count == 0 {
+        return;
+    }
+    let group_buy = match fetch_group_buy(state_guard, group_buy_id).await {
+        Ok(gb) => gb,
+        Err(msg) => {
+            tracing::error!("無法提供復原按鈕: {}", msg);
+            return;
+        }
+    };
+
+    let grace = chrono::Duration::minutes(ORDER_RESTORE_GRACE_MINUTES);
+    let bot_callback_url = bot_callback_url_from_state(state_guard);
+    let context = state_guard.mattermost_client.signer().sign_context(
+        serde_json::json!({
+            "action": "restore_orders",
+            "group_buy_id": group_buy_id,
+            "batch_id": deleted.batch_id,
+        }),
+        Some(grace),
+    );
+    let props = serde_json::json!({
+        "attachments": [{
+            "text": format!("{} 分鐘內可以復原", ORDER_RESTORE_GRACE_MINUTES),
+            "actions": [{
+                "id": crate::mattermost::action_id("restore", &deleted.batch_id),
+                "name": "復原",
+                "type": "button",
+                "integration": {
+                    "url": format!("{}/api/v1/group_buy/action/restore_orders", bot_callback_url),
+                    "context": context,
+                }
+            }]
+        }]
+    });
+
+    if let Err(e) = state_guard
+        .mattermost_client
+        .send_ephemeral_post_with_props(
+            &group_buy.channel_id,
+            &group_buy.creator_id,
+            &format!("🗑️ {}（{} 筆）", summary, deleted.count),
+            props,
+        )
+        .await
+    {
+        tracing::error!("發送復原按鈕失敗: {}", e);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -96,20 +374,41 @@ mod tests {
         assert!(submission.state.is_some());
     }
 
-    #[test]
-    fn test_extract_state_value() {
-        let submission = DialogSubmission {
+    fn submission_with_state(state: &str) -> DialogSubmission {
+        DialogSubmission {
             r#type: "dialog_submission".to_string(),
             callback_id: "cb".to_string(),
-            state: Some("{\"hello\": \"world\"}".to_string()),
+            state: Some(state.to_string()),
             user_id: "u".to_string(),
             channel_id: "c".to_string(),
             team_id: "t".to_string(),
             submission: HashMap::new(),
             cancelled: None,
-        };
+        }
+    }
+
+    #[test]
+    fn test_extract_state_value() {
+        let client =
+            MattermostClient::new("https://example.com".to_string(), "token".to_string()).unwrap();
+        let signer = crate::signing::StateSigner::new("token");
+        let submission = submission_with_state(&signer.sign("{\"hello\": \"world\"}"));
 
-        let v = extract_state_value(&submission).expect("extract should succeed");
+        let v = extract_state_value(&client, &submission).expect("extract should succeed");
         assert_eq!(v.get("hello").and_then(|x| x.as_str()), Some("world"));
     }
+
+    #[test]
+    fn test_extract_state_value_rejects_tampered_state() {
+        let client =
+            MattermostClient::new("https://example.com".to_string(), "token".to_string()).unwrap();
+        let signer = crate::signing::StateSigner::new("token");
+        let signed = signer.sign("{\"group_buy_id\": \"a\", \"version\": 1}");
+
+        let tampered = submission_with_state(&signed.replace("\"a\"", "\"b\""));
+        assert!(extract_state_value(&client, &tampered).is_err());
+
+        let unsigned = submission_with_state("{\"group_buy_id\": \"a\", \"version\": 1}");
+        assert!(extract_state_value(&client, &unsigned).is_err());
+    }
 }
@@ -6,9 +6,13 @@ use tracing::info;
 use warp::http::StatusCode;
 
 use super::auth::verify_slash_command_token;
+use super::feature_disabled_reply;
 use super::group_buy::handle_group_buy_command;
+use super::preferences::handle_prefs_command;
+use super::settings::handle_settings_command;
 use super::sticker::handle_sticker_command_impl;
 use crate::AppState;
+use crate::config::Feature;
 
 /// 處理 /leko slash command
 pub async fn handle_leko_command(
@@ -16,8 +20,9 @@ pub async fn handle_leko_command(
     state: Arc<RwLock<AppState>>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     info!("收到 /leko 指令");
-    info!("請求參數: {:?}", form.keys().collect::<Vec<_>>());
-    info!("完整表單內容: {:?}", form);
+    crate::logging::log_request(&state.read().await.config.logging.routes, "/leko", || {
+        format!("{:?}", crate::logging::redact_form(&form))
+    });
 
     // 驗證 slash command token
     verify_slash_command_token(&form, &state, "leko").await?;
@@ -29,6 +34,21 @@ pub async fn handle_leko_command(
     let parts: Vec<&str> = text_trimmed.split_whitespace().collect();
     let subcommand = parts.first().copied().unwrap_or("");
 
+    // 停用的功能對應的子指令只回覆提示
+    let feature = match subcommand {
+        "group_buy" | "settings" => Some(Feature::GroupBuy),
+        "sticker" => Some(Feature::Stickers),
+        _ => None,
+    };
+    if let Some(feature) = feature
+        && !state.read().await.config.features.is_enabled(feature)
+    {
+        return Ok(warp::reply::with_status(
+            feature_disabled_reply(feature),
+            StatusCode::OK,
+        ));
+    }
+
     match subcommand {
         "" => {
             // 無參數，顯示 help
@@ -42,6 +62,16 @@ pub async fn handle_leko_command(
             // 團購功能
             handle_group_buy_command(form, state).await
         }
+        "settings" => {
+            // 頻道團購設定
+            let response = handle_settings_command(&form, &parts[1..], state).await;
+            Ok(warp::reply::with_status(response, StatusCode::OK))
+        }
+        "prefs" => {
+            // 個人偏好設定
+            let response = handle_prefs_command(&form, &parts[1..], state).await;
+            Ok(warp::reply::with_status(response, StatusCode::OK))
+        }
         "sticker" => {
             // 取得 sticker 後面的關鍵字
             let keyword = parts.get(1..).map(|s| s.join(" ")).unwrap_or_default();
@@ -63,6 +93,6 @@ fn handle_leko_help() -> warp::reply::Json {
     info!("顯示 /leko 使用說明");
     warp::reply::json(&serde_json::json!({
         "response_type": "ephemeral",
-        "text": "### 📚 `/leko` 指令使用說明\n\n**可用子指令：**\n\n- `/leko help` - 顯示此說明訊息\n- `/leko group_buy` - 開啟建立團購對話框\n- `/leko sticker [關鍵字]` - 搜尋並發送貼圖\n\n**範例：**\n```\n/leko group_buy\n/leko sticker 快樂\n/leko sticker\n```\n\n💡 提示：你也可以直接使用 `/group_buy` 或 `/sticker` 指令。"
+        "text": "### 📚 `/leko` 指令使用說明\n\n**可用子指令：**\n\n- `/leko help` - 顯示此說明訊息\n- `/leko group_buy` - 開啟建立團購對話框\n- `/leko group_buy flash 30m 商家` - 沿用該商家上次的菜單建立限時團購，時間到自動截止\n- `/leko group_buy budget 1500` - 設定每月團購預算，登記超過時提醒\n- `/leko group_buy template save|use|list|delete 名稱` - 儲存或使用此頻道的團購範本\n- `/leko settings` - 查看或修改此頻道的團購設定（幣別、靜音時段、預設截止時間、可建立團購的成員）\n- `/leko prefs` - 查看或修改個人偏好（偏好的貼圖分類、各商家常點商品）\n- `/leko sticker [關鍵字]` - 搜尋並發送貼圖\n- `/leko sticker fav` - 列出你的最愛貼圖\n- `/leko sticker recent` - 列出你最近發送過的貼圖\n\n**範例：**\n```\n/leko group_buy\n/leko sticker 快樂\n/leko sticker fav\n/leko sticker recent\n/leko sticker\n```\n\n💡 提示：你也可以直接使用 `/group_buy` 或 `/sticker` 指令。"
     }))
 }
@@ -1,50 +1,131 @@
 //! HTTP 請求處理器模組
 
 mod actions;
+mod admin_api;
 mod auth;
+mod db_maintenance;
+mod feed_watcher;
 mod group_buy;
 mod leko;
+mod picker_cleanup;
+mod preferences;
+mod settings;
 mod sticker;
+mod sticker_panel;
+mod sticker_refresh;
+mod webhook;
 
 // 重新導出公開的處理器函數
 pub use actions::handle_action;
-pub use auth::UnauthorizedError;
+pub use admin_api::admin_api_routes;
+pub use auth::{UnauthorizedError, callback_allowlist, detect_callback_url};
+pub use db_maintenance::db_maintenance_job;
+pub use feed_watcher::feed_watcher_job;
 pub use group_buy::{
-    handle_adjust_shortage_dialog, handle_cancel_register_dialog, handle_create_dialog,
+    Reaction, deadline_closer_job, format_repair_report, handle_adjust_shortage_dialog,
+    handle_cancel_register_dialog, handle_create_dialog, handle_deactivated_buyers_dialog,
     handle_edit_items_dialog, handle_group_buy_action, handle_group_buy_command,
-    handle_register_dialog,
+    handle_payments_dialog, handle_reaction_added, handle_register_dialog, orphan_detector_job,
+    reminder_job, repair_channel, spawn_post_refresher, update_user_status,
 };
 pub use leko::handle_leko_command;
+pub use picker_cleanup::picker_cleanup_job;
 pub use sticker::handle_sticker_command;
+pub use sticker_refresh::sticker_refresh_job;
+pub use webhook::webhook_routes;
 
+use crate::AppState;
+use crate::config::Feature;
+use crate::error_code::ErrorCode;
+use std::sync::Arc;
+use tokio::sync::RwLock;
 use tracing::error;
+use warp::Filter;
 use warp::http::StatusCode;
 
+/// 功能已在 `features` 設定中停用
+#[derive(Debug)]
+pub struct FeatureDisabled(pub Feature);
+
+impl warp::reject::Reject for FeatureDisabled {}
+
+/// 路由的功能開關。每次請求都讀取目前的配置，重新載入配置後立即生效；
+/// 要放在路徑比對之後，否則會蓋過其他路由的 404
+pub fn require_feature(
+    state: Arc<RwLock<AppState>>,
+    feature: Feature,
+) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::any()
+        .and_then(move || {
+            let state = state.clone();
+            async move {
+                if state.read().await.config.features.is_enabled(feature) {
+                    Ok(())
+                } else {
+                    Err(warp::reject::custom(FeatureDisabled(feature)))
+                }
+            }
+        })
+        .untuple_one()
+}
+
+/// slash command 子指令的功能停用時回覆的臨時訊息
+pub fn feature_disabled_reply(feature: Feature) -> warp::reply::Json {
+    warp::reply::json(&serde_json::json!({
+        "response_type": "ephemeral",
+        "text": ErrorCode::FeatureDisabled.user_message(format!("⚠️ {}功能目前已停用", feature.label())),
+    }))
+}
+
 /// 錯誤處理器
 pub async fn handle_rejection(
     err: warp::Rejection,
 ) -> Result<impl warp::Reply, std::convert::Infallible> {
     if err.is_not_found() {
         Ok(warp::reply::with_status(
-            warp::reply::json(&serde_json::json!({
-                "error": "Not Found"
-            })),
+            warp::reply::json(&ErrorCode::NotFound.json("Not Found")),
             StatusCode::NOT_FOUND,
         ))
     } else if err.find::<UnauthorizedError>().is_some() {
         error!("未授權的請求");
         Ok(warp::reply::with_status(
-            warp::reply::json(&serde_json::json!({
-                "error": "Unauthorized: Invalid slash command token"
-            })),
+            warp::reply::json(
+                &ErrorCode::InvalidToken.json("Unauthorized: Invalid slash command token"),
+            ),
             StatusCode::UNAUTHORIZED,
         ))
+    } else if err.find::<auth::ForbiddenSource>().is_some() {
+        Ok(warp::reply::with_status(
+            warp::reply::json(
+                &ErrorCode::ForbiddenSource.json("Forbidden: Source address not allowed"),
+            ),
+            StatusCode::FORBIDDEN,
+        ))
+    } else if let Some(FeatureDisabled(feature)) = err.find::<FeatureDisabled>() {
+        Ok(warp::reply::with_status(
+            warp::reply::json(&ErrorCode::FeatureDisabled.json(format!(
+                "Service Unavailable: feature '{}' is disabled",
+                feature.name()
+            ))),
+            StatusCode::SERVICE_UNAVAILABLE,
+        ))
+    } else if err.find::<admin_api::InvalidApiToken>().is_some() {
+        Ok(warp::reply::with_status(
+            warp::reply::json(&ErrorCode::InvalidApiToken.json("Unauthorized: Invalid API token")),
+            StatusCode::UNAUTHORIZED,
+        ))
+    } else if err.find::<admin_api::InsufficientScope>().is_some() {
+        Ok(warp::reply::with_status(
+            warp::reply::json(
+                &ErrorCode::InsufficientScope
+                    .json("Forbidden: API token scope does not allow this endpoint"),
+            ),
+            StatusCode::FORBIDDEN,
+        ))
     } else {
         error!("未處理的錯誤: {:?}", err);
         Ok(warp::reply::with_status(
-            warp::reply::json(&serde_json::json!({
-                "error": "Internal Server Error"
-            })),
+            warp::reply::json(&ErrorCode::Internal.json("Internal Server Error")),
             StatusCode::INTERNAL_SERVER_ERROR,
         ))
     }
@@ -0,0 +1,137 @@
+//! 自動清除沒人理會的貼圖選擇器
+//!
+//! `/sticker` 發出的選擇器記在 `sticker_pickers`，發送或取消後移除。背景工作定期找出
+//! 超過 `stickers.picker_cleanup_minutes` 分鐘沒有操作的選擇器並刪除貼文，沒有權限刪除時
+//! 改為收起按鈕。從未操作過的選擇器不知道貼文 ID，從頻道最近的訊息中依標記找出。
+
+use crate::AppState;
+use crate::database::StickerPickerRecord;
+use crate::mattermost::PostDetail;
+use anyhow::Result;
+use tracing::{error, info, warn};
+
+/// 選擇器貼文 props 中的標記，值為選擇器 ID
+const PICKER_MARKER_KEY: &str = "leko_sticker_picker";
+
+/// 檢查間隔
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// 找不到貼文 ID 時掃描的頻道訊息數
+const POSTS_TO_SCAN: u32 = 100;
+
+/// 無法刪除時收起按鈕後顯示的訊息
+const COLLAPSED_MESSAGE: &str = "（貼圖選擇器已逾時關閉）";
+
+/// 選擇器貼文的 props
+pub(super) fn picker_props(picker_id: &str) -> serde_json::Value {
+    serde_json::json!({ PICKER_MARKER_KEY: picker_id })
+}
+
+/// 從頻道訊息中找出帶有指定選擇器標記的貼文
+fn find_picker_post<'a>(posts: &'a [PostDetail], picker_id: &str) -> Option<&'a PostDetail> {
+    posts
+        .iter()
+        .find(|post| post.props.get(PICKER_MARKER_KEY).and_then(|v| v.as_str()) == Some(picker_id))
+}
+
+/// 刪除或收起一個選擇器，找不到貼文（例如已被刪除）時回傳 false
+async fn clean_up(state_guard: &AppState, picker: &StickerPickerRecord) -> Result<bool> {
+    let client = &state_guard.mattermost_client;
+    let post_id = match &picker.post_id {
+        Some(post_id) => post_id.clone(),
+        None => {
+            let posts = client
+                .get_channel_posts(&picker.channel_id, 0, POSTS_TO_SCAN)
+                .await?;
+            match find_picker_post(&posts, &picker.id) {
+                Some(post) => post.id.clone(),
+                None => return Ok(false),
+            }
+        }
+    };
+
+    let Some(post) = client.get_post(&post_id).await? else {
+        return Ok(false);
+    };
+    if let Err(e) = client.delete_post(&post.id).await {
+        warn!("刪除貼圖選擇器 {} 失敗，改為收起按鈕: {}", post.id, e);
+        client
+            .update_post(
+                &post.id,
+                COLLAPSED_MESSAGE,
+                Some(post.merged_props(serde_json::json!({ "attachments": [] }))),
+            )
+            .await?;
+    }
+    Ok(true)
+}
+
+/// 清除超過設定時間沒有操作的選擇器，回傳實際清除的數量。
+/// 處理過的選擇器不論成功與否都不再追蹤，避免沒有權限時每輪重試
+pub async fn cleanup_stale_pickers(state_guard: &AppState) -> Result<usize> {
+    let minutes = state_guard.config.stickers.picker_cleanup_minutes;
+    if minutes == 0 {
+        return Ok(0);
+    }
+    let before = chrono::Utc::now() - chrono::Duration::minutes(minutes as i64);
+    let mut cleaned = 0;
+
+    for picker in state_guard
+        .database
+        .get_stale_sticker_pickers(before)
+        .await?
+    {
+        match clean_up(state_guard, &picker).await {
+            Ok(true) => cleaned += 1,
+            Ok(false) => {}
+            Err(e) => error!(
+                "清除使用者 {} 的貼圖選擇器 {} 失敗: {}",
+                picker.user_id, picker.id, e
+            ),
+        }
+        state_guard
+            .database
+            .delete_sticker_picker(&picker.id)
+            .await?;
+    }
+
+    Ok(cleaned)
+}
+
+/// 定期清除沒人理會的選擇器的排程工作，貼圖功能停用時暫停
+pub fn picker_cleanup_job() -> crate::scheduler::Job {
+    crate::scheduler::Job::new(
+        "清除貼圖選擇器",
+        |config| config.features.stickers.then_some(CHECK_INTERVAL),
+        |state| async move {
+            let state_guard = state.read().await;
+            match cleanup_stale_pickers(&state_guard).await {
+                Ok(0) => {}
+                Ok(cleaned) => info!("清除了 {} 個沒人理會的貼圖選擇器", cleaned),
+                Err(e) => error!("清除貼圖選擇器失敗: {}", e),
+            }
+        },
+    )
+    .delayed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_picker_post_by_marker() {
+        let posts: Vec<PostDetail> = serde_json::from_value(serde_json::json!([
+            {"id": "post-1", "channel_id": "c1", "props": {}},
+            {"id": "post-2", "channel_id": "c1", "props": picker_props("picker-2")},
+            {"id": "post-3", "channel_id": "c1", "props": picker_props("picker-3")},
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            find_picker_post(&posts, "picker-3").map(|p| p.id.as_str()),
+            Some("post-3")
+        );
+        assert!(find_picker_post(&posts, "picker-9").is_none());
+    }
+}
@@ -0,0 +1,221 @@
+//! `/leko prefs`：個人偏好設定
+//!
+//! 偏好存在 `user_preferences`，用來預填對話框與調整搜尋結果：
+//! 偏好的貼圖分類會排在 `/sticker` 結果前面，自己登記過的商品會在
+//! 同一商家下次團購時預先選好。開啟純文字貼圖時，送出的貼圖改為名稱與連結，
+//! 方便使用螢幕閱讀器或網路較慢的使用者。
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{error, info};
+
+use crate::AppState;
+use crate::sticker::Sticker;
+
+/// 偏好的貼圖分類，以逗號分隔
+pub const STICKER_CATEGORIES_KEY: &str = "sticker_categories";
+
+/// 設為 `on` 時貼圖以名稱與連結的純文字送出，而不是內嵌圖片
+pub const STICKER_TEXT_ONLY_KEY: &str = "sticker_text_only";
+
+/// 各商家最常點的商品，名稱為 `favorite_item:<商家 key>`
+const FAVORITE_ITEM_PREFIX: &str = "favorite_item:";
+
+const PREFS_USAGE: &str = "用法：`/leko prefs <項目> <值>`，值為 `off` 時清除\n\
+- `sticker_categories 貓, 狗` - 搜尋貼圖時優先列出這些分類\n\
+- `sticker_text_only on` - 貼圖改以名稱與連結的純文字送出（螢幕閱讀器、低頻寬）\n\
+- `favorites off` - 清除記住的各商家常點商品（自己登記時會自動記住）";
+
+/// 商家常點商品的偏好名稱，`merchant_key` 為正規化後的商家名稱
+pub fn favorite_item_key(merchant_key: &str) -> String {
+    format!("{}{}", FAVORITE_ITEM_PREFIX, merchant_key)
+}
+
+/// 解析偏好的貼圖分類
+pub fn parse_categories(value: &str) -> Vec<String> {
+    let mut categories: Vec<String> = Vec::new();
+    for category in value.split([',', '，', '、']).map(str::trim) {
+        if !category.is_empty() && !categories.iter().any(|c| c == category) {
+            categories.push(category.to_string());
+        }
+    }
+    categories
+}
+
+/// 純文字貼圖的訊息：名稱與連結，不內嵌圖片
+pub fn text_only_sticker_message(name: &str, image_url: &str) -> String {
+    format!("[貼圖] {}：<{}>", name, image_url)
+}
+
+/// 把偏好分類的貼圖依偏好順序排到前面，其餘維持原本順序
+pub fn prioritize_categories(stickers: &mut [Sticker], preferred: &[String]) {
+    if preferred.is_empty() {
+        return;
+    }
+    stickers.sort_by_key(|sticker| {
+        preferred
+            .iter()
+            .position(|c| *c == sticker.category)
+            .unwrap_or(preferred.len())
+    });
+}
+
+/// 顯示目前的偏好設定
+fn format_preferences(prefs: &[(String, String)]) -> String {
+    let categories = prefs
+        .iter()
+        .find(|(key, _)| key == STICKER_CATEGORIES_KEY)
+        .map(|(_, value)| value.as_str())
+        .unwrap_or("無");
+    let text_only = if prefs
+        .iter()
+        .any(|(key, value)| key == STICKER_TEXT_ONLY_KEY && value == "on")
+    {
+        "開啟"
+    } else {
+        "關閉"
+    };
+    let favorites: Vec<String> = prefs
+        .iter()
+        .filter_map(|(key, value)| {
+            key.strip_prefix(FAVORITE_ITEM_PREFIX)
+                .map(|merchant| format!("{}：{}", merchant, value))
+        })
+        .collect();
+    let favorites = if favorites.is_empty() {
+        "無".to_string()
+    } else {
+        favorites.join("、")
+    };
+
+    format!(
+        "### 🙋 個人偏好設定\n\n\
+| 項目 | 值 |\n|------|----|\n\
+| 偏好貼圖分類 (`sticker_categories`) | {} |\n\
+| 純文字貼圖 (`sticker_text_only`) | {} |\n\
+| 常點商品 (`favorites`) | {} |\n\n{}",
+        categories, text_only, favorites, PREFS_USAGE
+    )
+}
+
+fn ephemeral(text: String) -> warp::reply::Json {
+    warp::reply::json(&serde_json::json!({
+        "response_type": "ephemeral",
+        "text": text,
+    }))
+}
+
+/// 處理 `/leko prefs [項目 值]`
+pub async fn handle_prefs_command(
+    form: &HashMap<String, String>,
+    args: &[&str],
+    state: Arc<RwLock<AppState>>,
+) -> warp::reply::Json {
+    let user_id = form.get("user_id").map(String::as_str).unwrap_or("");
+    let user_name = form.get("user_name").map(String::as_str).unwrap_or("");
+
+    let state_guard = state.read().await;
+    let db = &state_guard.database;
+
+    if let Some((key, value)) = args.split_first() {
+        let value = value.join(" ");
+        let reset = value.trim().eq_ignore_ascii_case("off");
+        let result = match *key {
+            STICKER_CATEGORIES_KEY if reset => db
+                .delete_user_preferences(user_id, STICKER_CATEGORIES_KEY)
+                .await
+                .map(|_| ()),
+            STICKER_CATEGORIES_KEY => {
+                let categories = parse_categories(&value);
+                if categories.is_empty() {
+                    return ephemeral(format!("⚠️ 請填寫至少一個分類\n\n{}", PREFS_USAGE));
+                }
+                db.set_user_preference(user_id, STICKER_CATEGORIES_KEY, &categories.join(","))
+                    .await
+            }
+            STICKER_TEXT_ONLY_KEY if reset => db
+                .delete_user_preferences(user_id, STICKER_TEXT_ONLY_KEY)
+                .await
+                .map(|_| ()),
+            STICKER_TEXT_ONLY_KEY if value.trim().eq_ignore_ascii_case("on") => {
+                db.set_user_preference(user_id, STICKER_TEXT_ONLY_KEY, "on")
+                    .await
+            }
+            "favorites" if reset => db
+                .delete_user_preferences(user_id, FAVORITE_ITEM_PREFIX)
+                .await
+                .map(|_| ()),
+            _ => {
+                return ephemeral(format!(
+                    "⚠️ 無法設定「{} {}」\n\n{}",
+                    key, value, PREFS_USAGE
+                ));
+            }
+        };
+        if let Err(e) = result {
+            error!("儲存偏好設定失敗: {}", e);
+            return ephemeral(format!("儲存偏好設定失敗: {}", e));
+        }
+        info!("{} 更新了偏好設定 {}", user_name, key);
+    }
+
+    match db.list_user_preferences(user_id).await {
+        Ok(prefs) => ephemeral(format_preferences(&prefs)),
+        Err(e) => {
+            error!("取得偏好設定失敗: {}", e);
+            ephemeral("取得偏好設定失敗".to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sticker(name: &str, category: &str) -> Sticker {
+        Sticker {
+            name: name.to_string(),
+            image_url: format!("https://example.com/{}.png", name),
+            category: category.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_prioritize_categories() {
+        let mut stickers = vec![
+            sticker("a", "狗"),
+            sticker("b", "貓"),
+            sticker("c", "鳥"),
+            sticker("d", "貓"),
+        ];
+        let preferred = parse_categories("貓，鳥、貓");
+        assert_eq!(preferred, vec!["貓", "鳥"]);
+
+        prioritize_categories(&mut stickers, &preferred);
+        let names: Vec<&str> = stickers.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["b", "d", "c", "a"]);
+    }
+
+    #[test]
+    fn test_format_preferences() {
+        let text = format_preferences(&[
+            (favorite_item_key("50嵐"), "紅茶".to_string()),
+            (STICKER_CATEGORIES_KEY.to_string(), "貓,狗".to_string()),
+        ]);
+        assert!(text.contains("| 偏好貼圖分類 (`sticker_categories`) | 貓,狗 |"));
+        assert!(text.contains("| 常點商品 (`favorites`) | 50嵐：紅茶 |"));
+        assert!(text.contains("| 純文字貼圖 (`sticker_text_only`) | 關閉 |"));
+
+        let text = format_preferences(&[(STICKER_TEXT_ONLY_KEY.to_string(), "on".to_string())]);
+        assert!(text.contains("| 純文字貼圖 (`sticker_text_only`) | 開啟 |"));
+    }
+
+    #[test]
+    fn test_text_only_sticker_message() {
+        assert_eq!(
+            text_only_sticker_message("開心貓", "https://example.com/cat.png"),
+            "[貼圖] 開心貓：<https://example.com/cat.png>"
+        );
+    }
+}
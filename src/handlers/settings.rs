@@ -0,0 +1,228 @@
+//! `/leko settings`：頻道層級的團購設定
+//!
+//! 設定存在 `channel_settings`，建立團購、自動截止等流程會依頻道設定調整行為。
+//! 所有人都可以查看，只有管理員可以修改。
+
+use chrono::NaiveTime;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{error, info};
+
+use super::group_buy::parse_duration;
+use crate::AppState;
+use crate::database::ChannelSettings;
+
+const SETTINGS_USAGE: &str = "用法：`/leko settings <項目> <值>`，值為 `off` 時恢復預設\n\
+- `currency NT$` - 幣別符號\n\
+- `quiet_hours 22:00-08:00` - 靜音時段，期間不在頻道發自動截止等通知\n\
+- `default_deadline 2h` - 建立團購時預設的截止時間\n\
+- `allowed_creators @alice @bob` - 只有這些人可以建立團購";
+
+/// 預設截止時間的上限（天）
+const MAX_DEFAULT_DEADLINE_DAYS: i64 = 365;
+
+/// 解析靜音時段 `22:00-08:00`，開始與結束相同時視為格式錯誤
+pub fn parse_quiet_hours(input: &str) -> Option<(NaiveTime, NaiveTime)> {
+    let (start, end) = input.trim().split_once('-')?;
+    let start = NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?;
+    let end = NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?;
+    (start != end).then_some((start, end))
+}
+
+/// 指定時間是否在頻道的靜音時段內；時段可以跨過午夜
+pub fn is_quiet_at(settings: &ChannelSettings, time: NaiveTime) -> bool {
+    let Some((start, end)) = settings.quiet_hours.as_deref().and_then(parse_quiet_hours) else {
+        return false;
+    };
+    if start < end {
+        start <= time && time < end
+    } else {
+        time >= start || time < end
+    }
+}
+
+/// 套用一個設定項目，`off` 清除該項目
+fn apply_setting(settings: &mut ChannelSettings, key: &str, value: &str) -> Result<(), String> {
+    let reset = value.eq_ignore_ascii_case("off");
+    match key {
+        "currency" => {
+            if value.is_empty() || value.chars().count() > 8 {
+                return Err("幣別符號需為 1 到 8 個字".to_string());
+            }
+            settings.currency = (!reset).then(|| value.to_string());
+        }
+        "quiet_hours" => {
+            if !reset && parse_quiet_hours(value).is_none() {
+                return Err(format!(
+                    "無法解析靜音時段「{}」，格式為 `22:00-08:00`",
+                    value
+                ));
+            }
+            settings.quiet_hours = (!reset).then(|| value.trim().to_string());
+        }
+        "default_deadline" => {
+            settings.default_deadline_minutes = if reset {
+                None
+            } else {
+                let duration = parse_duration(value).ok_or_else(|| {
+                    format!("無法解析時間「{}」，可以是 `30m`、`2h`、`1h30m`", value)
+                })?;
+                if duration > chrono::Duration::days(MAX_DEFAULT_DEADLINE_DAYS) {
+                    return Err(format!("預設截止時間最長 {} 天", MAX_DEFAULT_DEADLINE_DAYS));
+                }
+                Some(duration.num_minutes())
+            };
+        }
+        "allowed_creators" => {
+            settings.allowed_creators = if reset {
+                Vec::new()
+            } else {
+                value
+                    .split([' ', ','])
+                    .map(|name| name.trim().trim_start_matches('@'))
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            };
+        }
+        _ => return Err(format!("未知的設定項目「{}」\n\n{}", key, SETTINGS_USAGE)),
+    }
+    Ok(())
+}
+
+/// 顯示目前的頻道設定
+pub fn format_settings(settings: &ChannelSettings) -> String {
+    let creators = if settings.allowed_creators.is_empty() {
+        "所有人".to_string()
+    } else {
+        settings
+            .allowed_creators
+            .iter()
+            .map(|name| format!("@{}", name))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let deadline = settings
+        .default_deadline_minutes
+        .map(|minutes| format!("{} 分鐘", minutes))
+        .unwrap_or_else(|| "不設定".to_string());
+
+    format!(
+        "### ⚙️ 頻道團購設定\n\n\
+| 項目 | 值 |\n|------|----|\n\
+| 幣別 (`currency`) | {} |\n\
+| 靜音時段 (`quiet_hours`) | {} |\n\
+| 預設截止時間 (`default_deadline`) | {} |\n\
+| 可建立團購 (`allowed_creators`) | {} |\n\n{}",
+        settings.currency(),
+        settings.quiet_hours.as_deref().unwrap_or("無"),
+        deadline,
+        creators,
+        SETTINGS_USAGE
+    )
+}
+
+fn ephemeral(text: String) -> warp::reply::Json {
+    warp::reply::json(&serde_json::json!({
+        "response_type": "ephemeral",
+        "text": text,
+    }))
+}
+
+/// 處理 `/leko settings [項目 值]`
+pub async fn handle_settings_command(
+    form: &HashMap<String, String>,
+    args: &[&str],
+    state: Arc<RwLock<AppState>>,
+) -> warp::reply::Json {
+    let channel_id = form.get("channel_id").map(String::as_str).unwrap_or("");
+    let user_id = form.get("user_id").map(String::as_str).unwrap_or("");
+    let user_name = form.get("user_name").map(String::as_str).unwrap_or("");
+
+    let state_guard = state.read().await;
+    let mut settings = match state_guard.database.get_channel_settings(channel_id).await {
+        Ok(settings) => settings,
+        Err(e) => {
+            error!("取得頻道設定失敗: {}", e);
+            return ephemeral("取得頻道設定失敗".to_string());
+        }
+    };
+
+    let Some((key, value)) = args.split_first() else {
+        return ephemeral(format_settings(&settings));
+    };
+
+    if !state_guard.config.is_admin(user_id, user_name) {
+        return ephemeral("⚠️ 只有管理員可以修改頻道設定".to_string());
+    }
+
+    if let Err(msg) = apply_setting(&mut settings, key, &value.join(" ")) {
+        return ephemeral(format!("⚠️ {}", msg));
+    }
+    if let Err(e) = state_guard
+        .database
+        .save_channel_settings(&settings, user_name)
+        .await
+    {
+        error!("儲存頻道設定失敗: {}", e);
+        return ephemeral(format!("儲存頻道設定失敗: {}", e));
+    }
+
+    info!("{} 更新了頻道 {} 的設定 {}", user_name, channel_id, key);
+    ephemeral(format!("✅ 已更新\n\n{}", format_settings(&settings)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(s: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(s, "%H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_quiet_hours_wrap_midnight() {
+        let mut settings = ChannelSettings::default();
+        assert!(!is_quiet_at(&settings, time("23:00")));
+
+        apply_setting(&mut settings, "quiet_hours", "22:00-08:00").unwrap();
+        assert!(is_quiet_at(&settings, time("23:30")));
+        assert!(is_quiet_at(&settings, time("07:59")));
+        assert!(!is_quiet_at(&settings, time("08:00")));
+        assert!(!is_quiet_at(&settings, time("12:00")));
+
+        apply_setting(&mut settings, "quiet_hours", "12:00-13:00").unwrap();
+        assert!(is_quiet_at(&settings, time("12:30")));
+        assert!(!is_quiet_at(&settings, time("13:00")));
+
+        assert!(apply_setting(&mut settings, "quiet_hours", "12:00").is_err());
+        assert!(apply_setting(&mut settings, "quiet_hours", "12:00-12:00").is_err());
+    }
+
+    #[test]
+    fn test_apply_setting() {
+        let mut settings = ChannelSettings::default();
+        apply_setting(&mut settings, "currency", "US$").unwrap();
+        apply_setting(&mut settings, "default_deadline", "1h30m").unwrap();
+        apply_setting(&mut settings, "allowed_creators", "@alice, bob").unwrap();
+        assert_eq!(settings.currency(), "US$");
+        assert_eq!(settings.default_deadline_minutes, Some(90));
+        assert_eq!(settings.allowed_creators, vec!["alice", "bob"]);
+        assert!(
+            format_settings(&settings)
+                .contains("| 可建立團購 (`allowed_creators`) | @alice @bob |")
+        );
+
+        apply_setting(&mut settings, "currency", "off").unwrap();
+        apply_setting(&mut settings, "allowed_creators", "OFF").unwrap();
+        assert_eq!(settings.currency(), crate::database::DEFAULT_CURRENCY);
+        assert!(settings.allowed_creators.is_empty());
+
+        assert!(apply_setting(&mut settings, "default_deadline", "soon").is_err());
+        assert!(apply_setting(&mut settings, "default_deadline", "8760h").is_ok());
+        assert!(apply_setting(&mut settings, "default_deadline", "8761h").is_err());
+        assert_eq!(settings.default_deadline_minutes, Some(8760 * 60));
+        assert!(apply_setting(&mut settings, "color", "red").is_err());
+    }
+}
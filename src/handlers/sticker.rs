@@ -5,8 +5,67 @@ use tokio::sync::RwLock;
 use tracing::{error, info};
 
 use super::auth::verify_slash_command_token;
+use super::picker_cleanup::picker_props;
+use super::preferences::{STICKER_CATEGORIES_KEY, parse_categories, prioritize_categories};
+use super::sticker_panel::{PanelSource, StickerPanel};
 use crate::AppState;
-use crate::mattermost::{Action, ActionOption, Attachment, Integration};
+use crate::database::Database;
+use crate::error_code::ErrorCode;
+use crate::sticker::{Sticker, StickerDatabase};
+
+/// 「本週熱門」顯示的貼圖數量
+const TRENDING_LIMIT: i64 = 5;
+
+/// `/sticker recent` 列出的貼圖數量
+const RECENT_LIMIT: i64 = 10;
+
+/// 搜尋貼圖（不限分類），使用者偏好的分類排在前面。
+/// 面板的選擇與換頁會重新搜尋，必須與第一次搜尋的順序一致，下拉選單的索引才會對應到同一張貼圖。
+pub(super) async fn search_for_user(
+    sticker_db: &StickerDatabase,
+    database: &Database,
+    user_id: &str,
+    keyword: &str,
+) -> anyhow::Result<Vec<Sticker>> {
+    // 偏好的貼圖分類只影響排序，查詢失敗時照原本順序
+    let preferred_categories = database
+        .get_user_preference(user_id, STICKER_CATEGORIES_KEY)
+        .await
+        .unwrap_or_else(|e| {
+            error!("取得偏好設定失敗: {}", e);
+            None
+        })
+        .map(|value| parse_categories(&value))
+        .unwrap_or_default();
+
+    let mut stickers = sticker_db.search_async(keyword, None).await?;
+    prioritize_categories(&mut stickers, &preferred_categories);
+    Ok(stickers)
+}
+
+/// 依面板來源取得貼圖清單，按鈕回呼時用來重建與第一次相同的清單
+pub(super) async fn load_panel_stickers(
+    sticker_db: &StickerDatabase,
+    database: &Database,
+    user_id: &str,
+    keyword: &str,
+    source: PanelSource,
+) -> anyhow::Result<Vec<Sticker>> {
+    match source {
+        PanelSource::Search => search_for_user(sticker_db, database, user_id, keyword).await,
+        PanelSource::Favorites => sticker_db.favorites(user_id).await,
+        PanelSource::Recent => sticker_db.recent(user_id, RECENT_LIMIT).await,
+    }
+}
+
+/// 指令文字對應的面板來源：`fav` 列出最愛、`recent` 列出最近發送過的，其他文字當作關鍵字
+fn panel_source(text: &str) -> PanelSource {
+    match text.trim() {
+        "fav" | "最愛" => PanelSource::Favorites,
+        "recent" | "最近" => PanelSource::Recent,
+        _ => PanelSource::Search,
+    }
+}
 
 /// 處理 /sticker slash command
 pub async fn handle_sticker_command(
@@ -14,8 +73,11 @@ pub async fn handle_sticker_command(
     state: Arc<RwLock<AppState>>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     info!("收到 /sticker 指令");
-    info!("請求參數: {:?}", form.keys().collect::<Vec<_>>());
-    info!("完整表單內容: {:?}", form);
+    crate::logging::log_request(
+        &state.read().await.config.logging.routes,
+        "/sticker",
+        || format!("{:?}", crate::logging::redact_form(&form)),
+    );
 
     // 驗證 slash command token
     verify_slash_command_token(&form, &state, "stickers").await?;
@@ -32,6 +94,7 @@ pub async fn handle_sticker_command_impl(
     let user_name = form.get("user_name").cloned().unwrap_or_default();
     let user_id = form.get("user_id").cloned().unwrap_or_default();
     let response_url = form.get("response_url").cloned().unwrap_or_default();
+    let channel_id = form.get("channel_id").cloned().unwrap_or_default();
 
     info!("搜尋關鍵字: '{}', 使用者: {}", text, user_name);
 
@@ -46,26 +109,41 @@ pub async fn handle_sticker_command_impl(
         .as_ref()
         .map(|url| format!("{}/action", url.trim_end_matches('/')))
         .unwrap_or_else(|| "http://localhost/action".to_string());
+    let signer = app_state.mattermost_client.signer().clone();
+    let mattermost_client = app_state.mattermost_client.clone();
+    let database = app_state.database.clone();
+    let ephemeral = app_state.config.stickers.ephemeral_picker;
     drop(app_state);
 
-    // 搜尋貼圖（不限分類）
-    let stickers = match sticker_db.search_async(&text, None).await {
-        Ok(v) => v.into_iter().take(25).collect::<Vec<_>>(),
-        Err(e) => {
-            error!("搜尋貼圖失敗: {}", e);
-            return Ok(warp::reply::json(&serde_json::json!({
-                "response_type": "ephemeral",
-                "text": "搜尋貼圖失敗，請稍後再試"
-            })));
-        }
+    // 子指令以外的文字當作關鍵字搜尋（不限分類），結果超過一頁時由面板分頁
+    let source = panel_source(&text);
+    let keyword = match source {
+        PanelSource::Search => text.as_str(),
+        PanelSource::Favorites | PanelSource::Recent => "",
     };
+    let stickers =
+        match load_panel_stickers(&sticker_db, &database, &user_id, keyword, source).await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("搜尋貼圖失敗: {}", e);
+                return Ok(warp::reply::json(&serde_json::json!({
+                    "response_type": "ephemeral",
+                    "text": "搜尋貼圖失敗，請稍後再試"
+                })));
+            }
+        };
 
     if stickers.is_empty() {
         // 沒有找到貼圖
-        let message = if text.is_empty() {
-            "沒有可用的貼圖".to_string()
-        } else {
-            format!("找不到符合「{}」的貼圖", text)
+        let message = match source {
+            PanelSource::Favorites => {
+                "你還沒有最愛的貼圖，在貼圖預覽中按「⭐ 加入最愛」即可加入".to_string()
+            }
+            PanelSource::Recent => "你最近還沒有發送過貼圖".to_string(),
+            PanelSource::Search if text.is_empty() => "沒有可用的貼圖".to_string(),
+            PanelSource::Search => {
+                ErrorCode::StickerNotFound.user_message(format!("找不到符合「{}」的貼圖", text))
+            }
         };
         return Ok(warp::reply::json(&serde_json::json!({
             "response_type": "ephemeral",
@@ -73,73 +151,51 @@ pub async fn handle_sticker_command_impl(
         })));
     }
 
-    // 建立貼圖選項
-    let sticker_options: Vec<ActionOption> = stickers
-        .iter()
-        .enumerate()
-        .map(|(idx, s)| ActionOption {
-            text: s.get_display_name(),
-            value: idx.to_string(),
-        })
-        .collect();
-
-    let stickers_count = sticker_options.len();
-
-    // 建立 Interactive Message
-    let attachment = Attachment {
-        fallback: Some("選擇貼圖".to_string()),
-        color: Some("#3AA3E3".to_string()),
-        pretext: None,
-        text: Some(if text.is_empty() {
-            format!("共 {} 張貼圖，請從下拉選單選擇：", stickers_count)
-        } else {
-            format!("搜尋「{}」找到 {} 張貼圖，請選擇：", text, stickers_count)
-        }),
-        author_name: None,
-        author_icon: None,
-        title: Some("🎨 貼圖選擇器".to_string()),
-        image_url: None,
-        thumb_url: None,
-        actions: Some(vec![
-            Action {
-                id: "stickerselect".to_string(),
-                name: "選擇貼圖".to_string(),
-                action_type: "select".to_string(),
-                style: None,
-                integration: Some(Integration {
-                    url: callback_url.clone(),
-                    context: Some(serde_json::json!({
-                        "action": "select_sticker",
-                        "user_id": user_id,
-                        "user_name": user_name,
-                        "keyword": text,
-                    })),
-                }),
-                options: Some(sticker_options),
-            },
-            Action {
-                id: "cancel".to_string(),
-                name: "❌ 取消".to_string(),
-                action_type: "button".to_string(),
-                style: Some("danger".to_string()),
-                integration: Some(Integration {
-                    url: callback_url.clone(),
-                    context: Some(serde_json::json!({
-                        "action": "cancel",
-                        "user_id": user_id,
-                    })),
-                }),
-                options: None,
-            },
-        ]),
+    let stickers_count = stickers.len();
+
+    // 沒有關鍵字時附上本週熱門，查詢失敗只略過
+    let trending = if source == PanelSource::Search && text.is_empty() {
+        sticker_db
+            .trending_this_week(TRENDING_LIMIT)
+            .await
+            .unwrap_or_else(|e| {
+                error!("取得熱門貼圖失敗: {}", e);
+                Vec::new()
+            })
+    } else {
+        Vec::new()
     };
 
+    // 建立 Interactive Message，選擇器 ID 用來追蹤沒人理會的選擇器。
+    // 臨時訊息無法由 API 刪除，也不會出現在頻道訊息中，不需要追蹤
+    let picker_id = if ephemeral {
+        String::new()
+    } else {
+        uuid::Uuid::new_v4().to_string()
+    };
+    let panel = StickerPanel {
+        callback_url: &callback_url,
+        signer: &signer,
+        user_id: &user_id,
+        user_name: &user_name,
+        keyword,
+        source,
+        picker_id: &picker_id,
+        ephemeral,
+        page: 0,
+    };
+    let mut attachments = vec![panel.picker(&stickers)];
+    if !trending.is_empty() {
+        attachments.push(panel.trending(&trending));
+    }
+
     // 透過 response_url 發送 Interactive Message
     let response_payload = serde_json::json!({
-        "response_type": "in_channel",
+        "response_type": if ephemeral { "ephemeral" } else { "in_channel" },
         "username": user_name,
         "icon_url": format!("{}/api/v4/users/{}/image", mattermost_url, user_id),
-        "attachments": [attachment]
+        "attachments": attachments,
+        "props": picker_props(&picker_id)
     });
 
     if !response_url.is_empty() {
@@ -147,10 +203,8 @@ pub async fn handle_sticker_command_impl(
             "透過 response_url 發送 Interactive Message: {}",
             response_url
         );
-        if let Err(e) = reqwest::Client::new()
-            .post(&response_url)
-            .json(&response_payload)
-            .send()
+        if let Err(e) = mattermost_client
+            .post_to_response_url(&response_url, &response_payload)
             .await
         {
             error!("透過 response_url 發送失敗: {}", e);
@@ -163,6 +217,13 @@ pub async fn handle_sticker_command_impl(
             "已建立 Interactive Message，共 {} 個貼圖選項",
             stickers_count
         );
+        if !picker_id.is_empty()
+            && let Err(e) = database
+                .create_sticker_picker(&picker_id, &channel_id, &user_id)
+                .await
+        {
+            error!("記錄貼圖選擇器失敗: {}", e);
+        }
         // 回傳空回應
         Ok(warp::reply::json(&serde_json::json!({})))
     } else {
@@ -0,0 +1,533 @@
+//! 貼圖選擇面板的 Attachment 建構
+//!
+//! `/sticker` 的搜尋結果與選擇後的預覽使用同一組按鈕，集中在這裡建構，
+//! 新增或修改按鈕只需要改一處。搜尋結果超過一頁時以「上一頁／下一頁」按鈕切換，
+//! 頁碼放在簽章過的 context 中，下拉選單的值是整個搜尋結果中的索引。
+//! 面板可以列出搜尋結果、使用者的最愛或最近發送過的貼圖，來源同樣記在 context 中，
+//! 按鈕回呼時依來源重新取得同一份清單。每個按鈕的 context 也帶有選擇器 ID（`picker`），
+//! 用來追蹤沒人理會的選擇器並自動清除。以臨時訊息顯示的選擇器在 context 標記 `ephemeral`，
+//! 發送時改為另外公開貼出貼圖。
+
+use crate::mattermost::{Action, ActionOption, Attachment, Integration, action_id};
+use crate::signing::StateSigner;
+use crate::sticker::Sticker;
+
+/// 每頁顯示的貼圖數量
+pub(super) const PAGE_SIZE: usize = 25;
+
+/// 貼圖選擇面板的按鈕有效時間
+pub(super) fn sticker_context_ttl() -> chrono::Duration {
+    chrono::Duration::hours(1)
+}
+
+/// 面板列出的貼圖來源
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum PanelSource {
+    /// 關鍵字搜尋結果
+    Search,
+    /// 使用者的最愛
+    Favorites,
+    /// 使用者最近發送過的貼圖
+    Recent,
+}
+
+impl PanelSource {
+    fn as_str(self) -> &'static str {
+        match self {
+            PanelSource::Search => "search",
+            PanelSource::Favorites => "favorites",
+            PanelSource::Recent => "recent",
+        }
+    }
+
+    /// 從按鈕 context 讀取來源，舊面板沒有這個欄位時視為搜尋
+    pub fn from_context(context: &serde_json::Value) -> Self {
+        match context.get("source").and_then(|v| v.as_str()) {
+            Some("favorites") => PanelSource::Favorites,
+            Some("recent") => PanelSource::Recent,
+            _ => PanelSource::Search,
+        }
+    }
+}
+
+/// 建構貼圖面板所需的共用參數
+pub(super) struct StickerPanel<'a> {
+    /// 按鈕 callback 的完整網址（`.../action`）
+    pub callback_url: &'a str,
+    pub signer: &'a StateSigner,
+    /// 發起搜尋的使用者，只有他能操作面板
+    pub user_id: &'a str,
+    pub user_name: &'a str,
+    pub keyword: &'a str,
+    pub source: PanelSource,
+    /// `sticker_pickers` 中的選擇器 ID，空字串代表不追蹤
+    pub picker_id: &'a str,
+    /// 選擇器是否為臨時訊息
+    pub ephemeral: bool,
+    /// 目前的頁碼（從 0 開始），超過最後一頁時顯示最後一頁
+    pub page: usize,
+}
+
+/// 搜尋結果的總頁數，沒有結果時為 1
+pub(super) fn page_count(total: usize) -> usize {
+    total.div_ceil(PAGE_SIZE).max(1)
+}
+
+impl StickerPanel<'_> {
+    /// 搜尋結果：下拉選單、換頁與取消按鈕。`stickers` 為完整的搜尋結果
+    pub fn picker(&self, stickers: &[Sticker]) -> Attachment {
+        let mut text = match self.source {
+            PanelSource::Favorites => format!("你的最愛共 {} 張貼圖，請選擇：", stickers.len()),
+            PanelSource::Recent => format!("你最近發送過的 {} 張貼圖，請選擇：", stickers.len()),
+            PanelSource::Search if self.keyword.is_empty() => {
+                format!("共 {} 張貼圖，請從下拉選單選擇：", stickers.len())
+            }
+            PanelSource::Search => format!(
+                "搜尋「{}」找到 {} 張貼圖，請選擇：",
+                self.keyword,
+                stickers.len()
+            ),
+        };
+        let pages = page_count(stickers.len());
+        if pages > 1 {
+            text.push_str(&format!(
+                "（第 {}/{} 頁）",
+                self.current_page(stickers.len()) + 1,
+                pages
+            ));
+        }
+
+        let mut actions = vec![self.select_action(stickers)];
+        actions.extend(self.page_actions(stickers.len()));
+        actions.push(self.cancel_action());
+
+        Attachment {
+            fallback: Some("選擇貼圖".to_string()),
+            color: Some("#3AA3E3".to_string()),
+            pretext: None,
+            text: Some(text),
+            author_name: None,
+            author_icon: None,
+            title: Some(match self.source {
+                PanelSource::Search => "🎨 貼圖選擇器".to_string(),
+                PanelSource::Favorites => "⭐ 我的最愛".to_string(),
+                PanelSource::Recent => "🕘 最近使用".to_string(),
+            }),
+            image_url: None,
+            thumb_url: None,
+            actions: Some(actions),
+        }
+    }
+
+    /// 預覽：顯示選中的貼圖，附上下拉選單、發送、最愛、換頁與取消按鈕。
+    /// `favorite` 為選中的貼圖目前是否在使用者的最愛中
+    pub fn preview(
+        &self,
+        stickers: &[Sticker],
+        selected: &Sticker,
+        author_icon: String,
+        favorite: bool,
+    ) -> Attachment {
+        let mut actions = vec![
+            self.select_action(stickers),
+            self.send_action(selected),
+            self.favorite_action(stickers.len(), selected, favorite),
+        ];
+        actions.extend(self.page_actions(stickers.len()));
+        actions.push(self.cancel_action());
+
+        Attachment {
+            fallback: Some(format!("已選擇: {}", selected.name)),
+            color: Some("#36a64f".to_string()),
+            pretext: None,
+            text: Some(format!("已選擇: **{}**", selected.get_display_name())),
+            author_name: Some(self.user_name.to_string()),
+            author_icon: Some(author_icon),
+            title: Some("🎨 貼圖預覽".to_string()),
+            image_url: Some(selected.image_url.clone()),
+            thumb_url: None,
+            actions: Some(actions),
+        }
+    }
+
+    /// 本週熱門：每張貼圖一個按鈕，按下直接發送
+    pub fn trending(&self, stickers: &[Sticker]) -> Attachment {
+        Attachment {
+            fallback: Some("本週熱門貼圖".to_string()),
+            color: Some("#E8A33D".to_string()),
+            pretext: None,
+            text: None,
+            author_name: None,
+            author_icon: None,
+            title: Some("🔥 本週熱門".to_string()),
+            image_url: None,
+            thumb_url: None,
+            actions: Some(
+                stickers
+                    .iter()
+                    .map(|s| Action {
+                        name: s.get_display_name(),
+                        style: None,
+                        id: action_id("trending", &format!("{}:{}", self.id_scope(), s.image_url)),
+                        ..self.send_action(s)
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// 同一使用者、來源與關鍵字的面板共用 scope，讓 action ID 不與其他面板衝突
+    fn id_scope(&self) -> String {
+        format!("{}:{}:{}", self.user_id, self.source.as_str(), self.keyword)
+    }
+
+    fn integration(&self, mut context: serde_json::Value) -> Option<Integration> {
+        if !self.picker_id.is_empty() {
+            context["picker"] = serde_json::json!(self.picker_id);
+        }
+        if self.ephemeral {
+            context["ephemeral"] = serde_json::json!(true);
+        }
+        Some(Integration {
+            url: self.callback_url.to_string(),
+            context: Some(
+                self.signer
+                    .sign_context(context, Some(sticker_context_ttl())),
+            ),
+        })
+    }
+
+    fn current_page(&self, total: usize) -> usize {
+        self.page.min(page_count(total) - 1)
+    }
+
+    /// 目前這頁的下拉選單，選項的值是在完整搜尋結果中的索引
+    fn select_action(&self, stickers: &[Sticker]) -> Action {
+        let start = self.current_page(stickers.len()) * PAGE_SIZE;
+        let options = stickers
+            .iter()
+            .enumerate()
+            .skip(start)
+            .take(PAGE_SIZE)
+            .map(|(idx, s)| ActionOption {
+                text: s.get_display_name(),
+                value: idx.to_string(),
+            })
+            .collect();
+
+        Action {
+            id: action_id("stickerselect", &self.id_scope()),
+            name: "選擇貼圖".to_string(),
+            action_type: "select".to_string(),
+            style: None,
+            integration: self.integration(serde_json::json!({
+                "action": "select_sticker",
+                "user_id": self.user_id,
+                "user_name": self.user_name,
+                "keyword": self.keyword,
+                "source": self.source.as_str(),
+                "page": self.current_page(stickers.len()),
+            })),
+            options: Some(options),
+        }
+    }
+
+    /// 上一頁／下一頁按鈕，只有一頁時沒有按鈕
+    fn page_actions(&self, total: usize) -> Vec<Action> {
+        let page = self.current_page(total);
+        let mut actions = Vec::new();
+        if page > 0 {
+            actions.push(self.page_action("stickerprev", "⬅️ 上一頁", page - 1));
+        }
+        if page + 1 < page_count(total) {
+            actions.push(self.page_action("stickernext", "下一頁 ➡️", page + 1));
+        }
+        actions
+    }
+
+    fn page_action(&self, prefix: &str, name: &str, page: usize) -> Action {
+        Action {
+            id: action_id(prefix, &self.id_scope()),
+            name: name.to_string(),
+            action_type: "button".to_string(),
+            style: None,
+            integration: self.integration(serde_json::json!({
+                "action": "sticker_page",
+                "user_id": self.user_id,
+                "user_name": self.user_name,
+                "keyword": self.keyword,
+                "source": self.source.as_str(),
+                "page": page,
+            })),
+            options: None,
+        }
+    }
+
+    fn send_action(&self, sticker: &Sticker) -> Action {
+        Action {
+            id: action_id("send", &format!("{}:{}", self.id_scope(), sticker.name)),
+            name: "✅ 發送".to_string(),
+            action_type: "button".to_string(),
+            style: Some("primary".to_string()),
+            integration: self.integration(serde_json::json!({
+                "action": "send_sticker",
+                "sticker_name": sticker.name,
+                "sticker_image_url": sticker.image_url,
+                "user_id": self.user_id,
+                "user_name": self.user_name,
+            })),
+            options: None,
+        }
+    }
+
+    /// 加入或移除最愛，按下後停留在同一張貼圖的預覽
+    fn favorite_action(&self, total: usize, sticker: &Sticker, favorite: bool) -> Action {
+        Action {
+            id: action_id("favorite", &format!("{}:{}", self.id_scope(), sticker.name)),
+            name: if favorite {
+                "💔 移除最愛".to_string()
+            } else {
+                "⭐ 加入最愛".to_string()
+            },
+            action_type: "button".to_string(),
+            style: None,
+            integration: self.integration(serde_json::json!({
+                "action": "toggle_favorite",
+                "favorite": !favorite,
+                "sticker_name": sticker.name,
+                "sticker_image_url": sticker.image_url,
+                "user_id": self.user_id,
+                "user_name": self.user_name,
+                "keyword": self.keyword,
+                "source": self.source.as_str(),
+                "page": self.current_page(total),
+            })),
+            options: None,
+        }
+    }
+
+    fn cancel_action(&self) -> Action {
+        Action {
+            id: action_id("cancel", &self.id_scope()),
+            name: "❌ 取消".to_string(),
+            action_type: "button".to_string(),
+            style: Some("danger".to_string()),
+            integration: self.integration(serde_json::json!({
+                "action": "cancel",
+                "user_id": self.user_id,
+            })),
+            options: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stickers() -> Vec<Sticker> {
+        ["cat", "dog"]
+            .iter()
+            .map(|name| Sticker {
+                name: name.to_string(),
+                image_url: format!("https://example.com/{}.png", name),
+                category: "animals".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_picker_and_preview_share_buttons() {
+        let signer = StateSigner::new("secret");
+        let panel = StickerPanel {
+            callback_url: "https://bot.example.com/action",
+            signer: &signer,
+            user_id: "u1",
+            user_name: "alice",
+            keyword: "貓",
+            source: PanelSource::Search,
+            picker_id: "",
+            ephemeral: false,
+            page: 0,
+        };
+        let stickers = stickers();
+
+        let picker = panel.picker(&stickers);
+        let preview = panel.preview(&stickers, &stickers[1], "icon".to_string(), false);
+        let picker_actions = picker.actions.unwrap();
+        let preview_actions = preview.actions.unwrap();
+
+        assert_eq!(picker_actions.len(), 2);
+        assert_eq!(preview_actions.len(), 4);
+        assert_eq!(picker_actions[0].id, preview_actions[0].id);
+        assert_eq!(picker_actions[1].id, preview_actions[3].id);
+        assert_eq!(preview_actions[0].options.as_ref().unwrap().len(), 2);
+
+        let send = preview_actions[1].integration.as_ref().unwrap();
+        let context = send.context.as_ref().unwrap();
+        assert_eq!(context["action"], "send_sticker");
+        assert_eq!(context["sticker_image_url"], "https://example.com/dog.png");
+        assert!(signer.verify_context(context).is_ok());
+    }
+
+    #[test]
+    fn test_favorite_button_toggles() {
+        let signer = StateSigner::new("secret");
+        let panel = StickerPanel {
+            callback_url: "https://bot.example.com/action",
+            signer: &signer,
+            user_id: "u1",
+            user_name: "alice",
+            keyword: "",
+            source: PanelSource::Favorites,
+            picker_id: "picker-1",
+            ephemeral: false,
+            page: 0,
+        };
+        let stickers = stickers();
+
+        let picker = panel.picker(&stickers);
+        assert_eq!(picker.title.as_deref(), Some("⭐ 我的最愛"));
+        assert_eq!(
+            picker.text.as_deref(),
+            Some("你的最愛共 2 張貼圖，請選擇：")
+        );
+
+        let button = |favorite| {
+            panel
+                .preview(&stickers, &stickers[0], "icon".to_string(), favorite)
+                .actions
+                .unwrap()
+                .remove(2)
+        };
+        let add = button(false);
+        assert_eq!(add.name, "⭐ 加入最愛");
+        let context = add.integration.unwrap().context.unwrap();
+        assert_eq!(context["action"], "toggle_favorite");
+        assert_eq!(context["favorite"], true);
+        assert_eq!(context["picker"], "picker-1");
+        assert_eq!(PanelSource::from_context(&context), PanelSource::Favorites);
+
+        let remove = button(true);
+        assert_eq!(remove.name, "💔 移除最愛");
+        assert_eq!(
+            remove.integration.unwrap().context.unwrap()["favorite"],
+            false
+        );
+    }
+
+    #[test]
+    fn test_recent_picker_keeps_source() {
+        let signer = StateSigner::new("secret");
+        let panel = StickerPanel {
+            callback_url: "https://bot.example.com/action",
+            signer: &signer,
+            user_id: "u1",
+            user_name: "alice",
+            keyword: "",
+            source: PanelSource::Recent,
+            picker_id: "",
+            ephemeral: true,
+            page: 0,
+        };
+
+        let picker = panel.picker(&stickers());
+        assert_eq!(picker.title.as_deref(), Some("🕘 最近使用"));
+        let context = picker.actions.unwrap()[0]
+            .integration
+            .as_ref()
+            .unwrap()
+            .context
+            .clone()
+            .unwrap();
+        assert_eq!(PanelSource::from_context(&context), PanelSource::Recent);
+        assert_eq!(context["ephemeral"], true);
+        assert_eq!(
+            PanelSource::from_context(&serde_json::json!({})),
+            PanelSource::Search
+        );
+    }
+
+    #[test]
+    fn test_trending_buttons_send_directly() {
+        let signer = StateSigner::new("secret");
+        let panel = StickerPanel {
+            callback_url: "https://bot.example.com/action",
+            signer: &signer,
+            user_id: "u1",
+            user_name: "alice",
+            keyword: "",
+            source: PanelSource::Search,
+            picker_id: "",
+            ephemeral: false,
+            page: 0,
+        };
+
+        let actions = panel.trending(&stickers()).actions.unwrap();
+        assert_eq!(actions.len(), 2);
+        assert_ne!(actions[0].id, actions[1].id);
+        let context = actions[1]
+            .integration
+            .as_ref()
+            .unwrap()
+            .context
+            .as_ref()
+            .unwrap();
+        assert_eq!(context["action"], "send_sticker");
+        assert_eq!(context["sticker_name"], "dog");
+    }
+
+    #[test]
+    fn test_picker_pages_large_results() {
+        let signer = StateSigner::new("secret");
+        let stickers: Vec<Sticker> = (0..60)
+            .map(|i| Sticker {
+                name: format!("s{}", i),
+                image_url: format!("https://example.com/{}.png", i),
+                category: "animals".to_string(),
+            })
+            .collect();
+        let panel = |page| StickerPanel {
+            callback_url: "https://bot.example.com/action",
+            signer: &signer,
+            user_id: "u1",
+            user_name: "alice",
+            keyword: "",
+            source: PanelSource::Search,
+            picker_id: "",
+            ephemeral: false,
+            page,
+        };
+
+        // 第一頁：選單、下一頁、取消
+        let first = panel(0).picker(&stickers);
+        assert!(first.text.unwrap().ends_with("（第 1/3 頁）"));
+        let actions = first.actions.unwrap();
+        assert_eq!(actions.len(), 3);
+        assert_eq!(actions[0].options.as_ref().unwrap().len(), PAGE_SIZE);
+        let next = actions[1]
+            .integration
+            .as_ref()
+            .unwrap()
+            .context
+            .as_ref()
+            .unwrap();
+        assert_eq!(next["action"], "sticker_page");
+        assert_eq!(next["page"], 1);
+
+        // 中間頁：上一頁與下一頁，選項的值是完整結果中的索引
+        let actions = panel(1).picker(&stickers).actions.unwrap();
+        assert_eq!(actions.len(), 4);
+        assert_eq!(actions[0].options.as_ref().unwrap()[0].value, "25");
+        assert_eq!(actions[1].name, "⬅️ 上一頁");
+        assert_eq!(actions[2].name, "下一頁 ➡️");
+
+        // 超過最後一頁時顯示最後一頁
+        let last = panel(9).picker(&stickers);
+        assert!(last.text.unwrap().ends_with("（第 3/3 頁）"));
+        let actions = last.actions.unwrap();
+        assert_eq!(actions.len(), 3);
+        assert_eq!(actions[0].options.as_ref().unwrap().len(), 10);
+        assert_eq!(actions[1].name, "⬅️ 上一頁");
+    }
+}
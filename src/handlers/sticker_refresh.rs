@@ -0,0 +1,113 @@
+//! 定期重新讀取貼圖來源
+//!
+//! 設定 `stickers.refresh_interval_minutes` 後，排程工作每隔這麼多分鐘重新讀取配置中的貼圖來源
+//! （有 HTTP 來源時才執行），以 `replace_stickers` 在一個交易中替換資料庫中的貼圖，
+//! 來源新增的貼圖不必私訊 `reload` 就會出現。HTTP 來源以條件式請求讀取，沒有變更時不重新下載，
+//! 貼圖列表與上次相同時也不重新寫入。讀取來源時不持有 AppState 的鎖；
+//! 期間配置被重新載入時放棄這次的結果。讀取失敗時保留原本的貼圖，下一輪再試。
+
+use crate::AppState;
+use crate::config::{Config, StickersConfig};
+use crate::scheduler::Job;
+use anyhow::Result;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{debug, error, info};
+
+/// 重新讀取的間隔：貼圖功能啟用、有 HTTP 來源且 `refresh_interval_minutes` 大於 0 時才執行
+fn refresh_interval(config: &Config) -> Option<Duration> {
+    let stickers = &config.stickers;
+    (config.features.stickers
+        && stickers.has_http_sources()
+        && stickers.refresh_interval_minutes > 0)
+        .then(|| Duration::from_secs(stickers.refresh_interval_minutes.saturating_mul(60)))
+}
+
+/// 一輪重新讀取的結果
+enum RefreshOutcome {
+    /// 貼圖有變更，已替換，附上替換後的貼圖數量
+    Replaced(i64),
+    /// 來源的內容沒有變更
+    Unchanged,
+    /// 讀取期間配置已重新載入，放棄這次的結果
+    ConfigChanged,
+}
+
+/// 重新讀取貼圖來源，有變更時替換資料庫中的貼圖
+async fn refresh_stickers(
+    state: &Arc<RwLock<AppState>>,
+    config: &StickersConfig,
+) -> Result<RefreshOutcome> {
+    let sticker_database = state.read().await.sticker_database.clone();
+    let stickers = sticker_database.fetch_sources(config).await?;
+
+    let state_guard = state.read().await;
+    if state_guard.config.stickers != *config {
+        return Ok(RefreshOutcome::ConfigChanged);
+    }
+    if !state_guard.sticker_database.replace_all(&stickers).await? {
+        return Ok(RefreshOutcome::Unchanged);
+    }
+    Ok(RefreshOutcome::Replaced(
+        state_guard.sticker_database.count().await?,
+    ))
+}
+
+/// 定期重新讀取貼圖來源的排程工作。啟動時已經讀取過一次，等第一個間隔過後才執行
+pub fn sticker_refresh_job() -> Job {
+    Job::new("重新讀取貼圖來源", refresh_interval, |state| async move {
+        let config = state.read().await.config.stickers.clone();
+        match refresh_stickers(&state, &config).await {
+            Ok(RefreshOutcome::Replaced(count)) => {
+                info!("已重新讀取貼圖來源，共 {} 張貼圖", count)
+            }
+            Ok(RefreshOutcome::Unchanged) => debug!("貼圖來源沒有變更"),
+            Ok(RefreshOutcome::ConfigChanged) => {
+                info!("讀取貼圖來源期間配置已重新載入，略過這次的結果")
+            }
+            Err(e) => error!("重新讀取貼圖來源失敗，保留原本的貼圖: {:#}", e),
+        }
+    })
+    .delayed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refresh_interval() {
+        let mut config: Config = serde_yaml::from_str(
+            r#"
+mattermost:
+  url: http://127.0.0.1:1
+  bot_token: test_token
+stickers:
+  refresh_interval_minutes: 30
+  categories:
+    - name: 測試
+      sources:
+        - type: http_get
+          format: csv
+          url: https://example.com/stickers.csv
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            refresh_interval(&config),
+            Some(Duration::from_secs(30 * 60))
+        );
+
+        config.features.stickers = false;
+        assert_eq!(refresh_interval(&config), None);
+        config.features.stickers = true;
+
+        config.stickers.refresh_interval_minutes = 0;
+        assert_eq!(refresh_interval(&config), None);
+        config.stickers.refresh_interval_minutes = 30;
+
+        config.stickers.categories[0].sources.clear();
+        assert_eq!(refresh_interval(&config), None);
+    }
+}
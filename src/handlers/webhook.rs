@@ -0,0 +1,222 @@
+//! Webhook 轉發
+//!
+//! `POST /webhook/<name>` 接收外部系統（CI、監控）送來的任意 JSON，依配置中同名 webhook 的
+//! `template` 代入欄位後以 bot 身分發文到指定頻道，bot 因此可以當作簡單的通知閘道。
+//! 呼叫端以 `Authorization: Bearer <token>` header 或 `?token=` 帶上 token；很多系統的
+//! webhook 設定只能填網址，所以也接受 query string。端點不使用 cookie，不受 CSRF 影響。
+//! Webhook 設定在重新載入配置後生效。
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{error, info, warn};
+use warp::Filter;
+use warp::http::StatusCode;
+use warp::reply::{Json, WithStatus};
+
+use crate::AppState;
+use crate::config::Feature;
+use crate::error_code::ErrorCode;
+
+/// 請求內容的大小上限
+const MAX_PAYLOAD_BYTES: u64 = 256 * 1024;
+
+fn with_state(
+    state: Arc<RwLock<AppState>>,
+) -> impl Filter<Extract = (Arc<RwLock<AppState>>,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || state.clone())
+}
+
+/// Webhook 路由（POST /webhook/<name>）
+pub fn webhook_routes(
+    state: Arc<RwLock<AppState>>,
+) -> impl Filter<Extract = (warp::reply::Response,), Error = warp::Rejection> + Clone {
+    warp::post()
+        .and(warp::path("webhook"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(super::require_feature(state.clone(), Feature::Webhooks))
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::body::content_length_limit(MAX_PAYLOAD_BYTES))
+        .and(warp::body::bytes())
+        .and(with_state(state))
+        .and_then(
+            |name: String,
+             authorization: Option<String>,
+             query: HashMap<String, String>,
+             body: warp::hyper::body::Bytes,
+             state: Arc<RwLock<AppState>>| {
+                let token = authorization
+                    .as_deref()
+                    .and_then(|v| v.strip_prefix("Bearer "))
+                    .or(query.get("token").map(String::as_str))
+                    .map(|t| t.trim().to_string());
+                crate::panic_guard::guard(
+                    "/webhook",
+                    state.clone(),
+                    handle_webhook(name, token, body, state),
+                )
+            },
+        )
+}
+
+/// 以 SHA-256 比對 token，比較時間與 token 的內容無關
+fn token_matches(expected: &str, received: &str) -> bool {
+    use sha2::{Digest, Sha256};
+
+    Sha256::digest(expected.as_bytes()) == Sha256::digest(received.as_bytes())
+}
+
+/// 依路徑取得 JSON 中的值。路徑以 `.` 分隔，陣列以數字索引；`.` 代表整個 JSON
+fn lookup<'a>(payload: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    if path == "." {
+        return Some(payload);
+    }
+    path.split('.').try_fold(payload, |value, key| match value {
+        serde_json::Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+        _ => value.get(key),
+    })
+}
+
+/// 以 JSON 代入範本中的 `{{path}}`：字串直接代入，找不到的欄位與 null 代入空字串，
+/// 其他值（數字、布林、物件、陣列）以 JSON 表示。沒有對應 `}}` 的 `{{` 保持原樣
+pub fn render_template(template: &str, payload: &serde_json::Value) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        let path = rest[start + 2..start + 2 + end].trim();
+        match lookup(payload, path) {
+            None | Some(serde_json::Value::Null) => {}
+            Some(serde_json::Value::String(s)) => out.push_str(s),
+            Some(value) => out.push_str(&value.to_string()),
+        }
+        rest = &rest[start + 2 + end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// POST /webhook/<name>
+async fn handle_webhook(
+    name: String,
+    token: Option<String>,
+    body: warp::hyper::body::Bytes,
+    state: Arc<RwLock<AppState>>,
+) -> Result<WithStatus<Json>, warp::Rejection> {
+    let reply = |status: StatusCode,
+                 body: serde_json::Value|
+     -> Result<WithStatus<Json>, warp::Rejection> {
+        Ok(warp::reply::with_status(warp::reply::json(&body), status))
+    };
+
+    let app_state = state.read().await;
+    let Some(webhook) = app_state.config.webhook(&name) else {
+        return reply(
+            StatusCode::NOT_FOUND,
+            ErrorCode::NotFound.json(format!("找不到 webhook: {}", name)),
+        );
+    };
+
+    if !token.is_some_and(|token| token_matches(&webhook.token, &token)) {
+        warn!("webhook {} 的 token 無效", name);
+        return reply(
+            StatusCode::UNAUTHORIZED,
+            ErrorCode::InvalidWebhookToken.json("Unauthorized: Invalid webhook token"),
+        );
+    }
+
+    let payload: serde_json::Value = match serde_json::from_slice(&body) {
+        Ok(payload) => payload,
+        Err(e) => {
+            return reply(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidPayload.json(format!("請求內容不是有效的 JSON: {}", e)),
+            );
+        }
+    };
+
+    let message = render_template(&webhook.template, &payload);
+    if message.trim().is_empty() {
+        info!("webhook {} 的範本結果為空，不發文", name);
+        return reply(StatusCode::OK, serde_json::json!({ "posted": false }));
+    }
+
+    let mut props = serde_json::Map::new();
+    if let Some(username) = &webhook.username {
+        props.insert("override_username".to_string(), serde_json::json!(username));
+    }
+    if let Some(icon_url) = &webhook.icon_url {
+        props.insert("override_icon_url".to_string(), serde_json::json!(icon_url));
+    }
+    let props = (!props.is_empty()).then_some(serde_json::Value::Object(props));
+
+    match app_state
+        .mattermost_client
+        .create_post_simple(&webhook.channel_id, &message, props)
+        .await
+    {
+        Ok(post) => {
+            info!("webhook {} 已發文到頻道 {}", name, webhook.channel_id);
+            reply(
+                StatusCode::OK,
+                serde_json::json!({ "posted": true, "post_id": post.id }),
+            )
+        }
+        Err(e) => {
+            error!("webhook {} 發文失敗: {}", name, e);
+            reply(
+                StatusCode::BAD_GATEWAY,
+                ErrorCode::MattermostError.json(format!("發文失敗: {}", e)),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_render_template() {
+        let payload = json!({
+            "repository": { "name": "bot" },
+            "status": "成功",
+            "build": { "number": 42, "passed": true, "url": null },
+            "commits": [{ "id": "abc" }, { "id": "def" }]
+        });
+
+        assert_eq!(
+            render_template(
+                "**{{repository.name}}** #{{ build.number }} {{status}}（{{build.passed}}）",
+                &payload
+            ),
+            "**bot** #42 成功（true）"
+        );
+        assert_eq!(render_template("{{commits.1.id}}", &payload), "def");
+        // 找不到的欄位與 null 代入空字串
+        assert_eq!(
+            render_template("[{{build.url}}{{missing.x}}]", &payload),
+            "[]"
+        );
+        assert_eq!(
+            render_template("{{repository}}", &payload),
+            r#"{"name":"bot"}"#
+        );
+        assert_eq!(render_template("{{.}}", &json!("raw")), "raw");
+        // 沒有結尾的 {{ 保持原樣
+        assert_eq!(render_template("a {{status", &payload), "a {{status");
+    }
+
+    #[test]
+    fn test_token_matches() {
+        assert!(token_matches("secret", "secret"));
+        assert!(!token_matches("secret", "Secret"));
+        assert!(!token_matches("secret", ""));
+    }
+}
@@ -0,0 +1,121 @@
+//! 背景工作的 leader 選舉
+//!
+//! 多個 bot 實例共用資料庫時，自動截止、孤兒團購檢查等背景工作只應由一個實例執行，
+//! 否則同一則通知會發送多次。各實例定期嘗試取得資料庫中的 lease（`leader_leases`），
+//! 取得的實例成為 leader；leader 停止續約（當機、停機）超過 `LEASE_TTL` 後由其他實例接手。
+//! lease 的到期時間以各實例的系統時間計算，實例之間的時鐘需要同步。
+//!
+//! 本實例的識別碼與 leader 狀態存在 `AppState.leadership`，排程工作執行前由 `scheduler` 檢查。
+
+use crate::database::Database;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tracing::{error, info};
+
+/// 背景工作共用的 lease 名稱
+const LEASE_NAME: &str = "background_jobs";
+
+/// 續約間隔
+const RENEW_INTERVAL: Duration = Duration::from_secs(10);
+
+/// lease 的有效時間，需明顯大於續約間隔，避免短暫的資料庫延遲造成 leader 交替
+const LEASE_TTL: chrono::Duration = chrono::Duration::seconds(30);
+
+/// 本實例的識別碼與目前是否為 leader，複製後共用同一個狀態
+#[derive(Debug, Clone)]
+pub struct Leadership {
+    instance_id: Arc<str>,
+    leader: Arc<AtomicBool>,
+}
+
+impl Default for Leadership {
+    /// 產生新的識別碼（每次啟動一個），尚未取得 lease
+    fn default() -> Self {
+        Self {
+            instance_id: uuid::Uuid::new_v4().simple().to_string().into(),
+            leader: Default::default(),
+        }
+    }
+}
+
+impl Leadership {
+    /// 本實例的識別碼，`/health` 也會回傳，用來確認 callback URL 連到的是哪個實例
+    pub fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    /// 本實例目前是否為 leader，排程在每次執行工作前檢查
+    pub fn is_leader(&self) -> bool {
+        self.leader.load(Ordering::Relaxed)
+    }
+}
+
+/// 嘗試取得或續約 lease 並更新 leader 狀態。資料庫錯誤時視為失去 lease，寧可暫停也不重複執行。
+async fn renew(database: &Database, leadership: &Leadership) -> bool {
+    let holder = leadership.instance_id();
+    let leader = match database
+        .try_acquire_lease(LEASE_NAME, holder, LEASE_TTL)
+        .await
+    {
+        Ok(acquired) => acquired,
+        Err(e) => {
+            error!("續約 leader lease 失敗: {}", e);
+            false
+        }
+    };
+
+    let was_leader = leadership.leader.swap(leader, Ordering::Relaxed);
+    if leader && !was_leader {
+        info!("成為 leader，由本實例 ({}) 執行背景工作", holder);
+    } else if !leader && was_leader {
+        info!("失去 leader lease，暫停本實例的背景工作");
+    }
+    leader
+}
+
+/// 取得第一次的 lease 結果後，在背景定期續約。
+/// 第一次嘗試會等待完成，單一實例啟動時背景工作可以立即執行。
+pub async fn spawn_leader_election(
+    database: Database,
+    leadership: Leadership,
+) -> tokio::task::JoinHandle<()> {
+    renew(&database, &leadership).await;
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(RENEW_INTERVAL);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        // 第一次 tick 會立即完成，跳過
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            renew(&database, &leadership).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::utils::setup_db;
+
+    #[tokio::test]
+    async fn test_renew_elects_single_leader() {
+        let db = setup_db().await;
+        let a = Leadership::default();
+        let b = Leadership::default();
+        assert_ne!(a.instance_id(), b.instance_id());
+
+        assert!(renew(&db, &a).await);
+        assert!(a.is_leader());
+
+        // 其他實例在 lease 有效期間無法成為 leader
+        assert!(!renew(&db, &b).await);
+        assert!(!b.is_leader());
+        assert!(a.is_leader());
+
+        // 續約不影響其他實例的狀態
+        assert!(renew(&db, &a).await);
+        assert!(!b.is_leader());
+    }
+}
@@ -0,0 +1,204 @@
+//! 依部署語系格式化日期與數字
+//!
+//! 採購列表、個人小計、封存摘要與調整紀錄中的日期與金額一律經過這裡，依 `group_buy.locale`
+//! 決定日期的順序（年/月/日、日/月/年、月/日/年）與千分位、小數點符號，
+//! 其他模組不直接以 `format("%Y/%m/%d")` 或 Decimal 原本的字串輸出。
+//! 沒有設定語系時維持原本的格式：年/月/日、不加千分位。格式由呼叫端從
+//! `config.group_buy.locale_format()` 傳入。
+
+use chrono::{DateTime, Local};
+use rust_decimal::Decimal;
+
+/// 日期中年、月、日的順序
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateOrder {
+    Ymd,
+    Dmy,
+    Mdy,
+}
+
+/// 一個語系的日期與數字格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocaleFormat {
+    pub date_order: DateOrder,
+    pub date_separator: char,
+    /// 千分位符號，None 代表不分隔
+    pub thousands_separator: Option<char>,
+    pub decimal_separator: char,
+}
+
+impl Default for LocaleFormat {
+    fn default() -> Self {
+        Self {
+            date_order: DateOrder::Ymd,
+            date_separator: '/',
+            thousands_separator: None,
+            decimal_separator: '.',
+        }
+    }
+}
+
+/// `group_buy.locale` 支援的語系（`en` 也符合 `en-GB` 等地區）
+pub const SUPPORTED_LOCALES: &[&str] = &[
+    "zh-TW", "zh-CN", "ja", "en-US", "en", "de", "fr", "es", "it", "pt", "nl",
+];
+
+impl LocaleFormat {
+    /// 語系對應的格式，`zh_TW` 與 `zh-tw` 視為相同；不支援的語系回傳 None
+    pub fn for_locale(locale: &str) -> Option<Self> {
+        let locale = locale.trim().to_ascii_lowercase().replace('_', "-");
+        let language = locale.split('-').next().unwrap_or_default();
+        let (date_order, date_separator, thousands_separator, decimal_separator) =
+            match (language, locale.as_str()) {
+                (_, "en" | "en-us") => (DateOrder::Mdy, '/', ',', '.'),
+                ("en", _) => (DateOrder::Dmy, '/', ',', '.'),
+                ("zh" | "ja", _) => (DateOrder::Ymd, '/', ',', '.'),
+                ("de", _) => (DateOrder::Dmy, '.', '.', ','),
+                ("fr", _) => (DateOrder::Dmy, '/', ' ', ','),
+                ("es" | "it" | "pt", _) => (DateOrder::Dmy, '/', '.', ','),
+                ("nl", _) => (DateOrder::Dmy, '-', '.', ','),
+                _ => return None,
+            };
+        Some(Self {
+            date_order,
+            date_separator,
+            thousands_separator: Some(thousands_separator),
+            decimal_separator,
+        })
+    }
+}
+
+/// 依語系格式輸出數字（金額、百分比），小數位數與 Decimal 本身相同
+pub fn format_number(value: Decimal, format: &LocaleFormat) -> String {
+    let text = value.to_string();
+    let (sign, digits) = match text.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", text.as_str()),
+    };
+    let (integer, fraction) = match digits.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (digits, None),
+    };
+
+    let mut out = String::from(sign);
+    for (i, c) in integer.chars().enumerate() {
+        if let Some(separator) = format.thousands_separator
+            && i > 0
+            && (integer.len() - i) % 3 == 0
+        {
+            out.push(separator);
+        }
+        out.push(c);
+    }
+    if let Some(fraction) = fraction {
+        out.push(format.decimal_separator);
+        out.push_str(fraction);
+    }
+    out
+}
+
+/// 日期的 strftime 格式，`with_year` 為 false 時只有月與日
+fn date_pattern(format: &LocaleFormat, with_year: bool) -> String {
+    let fields: &[&str] = match (format.date_order, with_year) {
+        (DateOrder::Ymd, true) => &["%Y", "%m", "%d"],
+        (DateOrder::Dmy, true) => &["%d", "%m", "%Y"],
+        (DateOrder::Mdy, true) => &["%m", "%d", "%Y"],
+        (DateOrder::Ymd | DateOrder::Mdy, false) => &["%m", "%d"],
+        (DateOrder::Dmy, false) => &["%d", "%m"],
+    };
+    fields.join(&format.date_separator.to_string())
+}
+
+/// 依指定的格式輸出日期與時間（時:分）
+pub fn format_datetime_with(
+    time: DateTime<Local>,
+    format: &LocaleFormat,
+    with_year: bool,
+) -> String {
+    time.format(&format!("{} %H:%M", date_pattern(format, with_year)))
+        .to_string()
+}
+
+/// 依語系格式輸出完整的日期與時間
+pub fn format_datetime(time: DateTime<Local>, format: &LocaleFormat) -> String {
+    format_datetime_with(time, format, true)
+}
+
+/// 依語系格式輸出不含年份的日期與時間，用於同一個團購期間內的紀錄
+pub fn format_short_datetime(time: DateTime<Local>, format: &LocaleFormat) -> String {
+    format_datetime_with(time, format, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_for_locale() {
+        for locale in SUPPORTED_LOCALES {
+            assert!(LocaleFormat::for_locale(locale).is_some(), "{}", locale);
+        }
+        assert_eq!(
+            LocaleFormat::for_locale("en_GB").unwrap().date_order,
+            DateOrder::Dmy
+        );
+        assert_eq!(
+            LocaleFormat::for_locale("EN-us").unwrap().date_order,
+            DateOrder::Mdy
+        );
+        assert!(LocaleFormat::for_locale("xx").is_none());
+    }
+
+    #[test]
+    fn test_format_number() {
+        let plain = LocaleFormat::default();
+        let de = LocaleFormat::for_locale("de").unwrap();
+        let fr = LocaleFormat::for_locale("fr").unwrap();
+        let tw = LocaleFormat::for_locale("zh-TW").unwrap();
+
+        // 未設定語系時與 Decimal 原本的字串相同
+        assert_eq!(
+            format_number(Decimal::new(123456789, 2), &plain),
+            "1234567.89"
+        );
+        assert_eq!(
+            format_number(Decimal::new(123456789, 2), &tw),
+            "1,234,567.89"
+        );
+        assert_eq!(
+            format_number(Decimal::new(123456789, 2), &de),
+            "1.234.567,89"
+        );
+        assert_eq!(format_number(Decimal::new(-15000, 0), &fr), "-15 000");
+        assert_eq!(format_number(Decimal::new(999, 0), &tw), "999");
+        assert_eq!(format_number(Decimal::new(25, 1), &de), "2,5");
+    }
+
+    #[test]
+    fn test_format_datetime_with() {
+        let time = Local.with_ymd_and_hms(2026, 3, 7, 9, 5, 0).unwrap();
+        let format = |locale: &str| LocaleFormat::for_locale(locale).unwrap();
+
+        assert_eq!(
+            format_datetime_with(time, &LocaleFormat::default(), true),
+            "2026/03/07 09:05"
+        );
+        assert_eq!(
+            format_datetime_with(time, &LocaleFormat::default(), false),
+            "03/07 09:05"
+        );
+        assert_eq!(
+            format_datetime_with(time, &format("en-US"), true),
+            "03/07/2026 09:05"
+        );
+        assert_eq!(
+            format_datetime_with(time, &format("de"), true),
+            "07.03.2026 09:05"
+        );
+        assert_eq!(
+            format_datetime_with(time, &format("en-GB"), false),
+            "07/03 09:05"
+        );
+    }
+}
@@ -0,0 +1,269 @@
+//! 日誌初始化與記錄政策
+//!
+//! 標準輸出之外可以另外寫入輪替的日誌檔（`logging.file`），兩者的 filter 分開設定，
+//! 適合沒有集中收集日誌的部署。錯誤回報的 layer 也在這裡掛上。
+//!
+//! 請求內容只在路由設為 `full` 時記錄（`logging.routes`），且會遮蔽 token、
+//! response_url、trigger_id 等欄位；設為 `off` 的路由連 access log 都不記錄。
+
+use crate::config::{LogFileConfig, LogRotation, LoggingConfig, RouteLogLevel};
+use crate::error_reporting::{ErrorReportLayer, ErrorReporter};
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Arc;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::prelude::*;
+
+/// 記錄時遮蔽的欄位：slash command token、可直接回覆的 response_url、可開啟對話框的 trigger_id，
+/// 以及一次性 context 的簽章
+const REDACTED_KEYS: &[&str] = &["token", "response_url", "trigger_id", "_sig"];
+
+pub const REDACTED: &str = "[redacted]";
+
+/// 初始化全域的日誌。`log_dir` 為命令列的 `--log-dir`，會覆蓋配置中的目錄；
+/// ERROR 日誌交給 `reporter` 計數與回報。
+///
+/// 有寫入日誌檔時回傳 guard，必須保留到程式結束，否則最後的日誌可能沒有寫入。
+pub fn init(
+    config: &LoggingConfig,
+    log_dir: Option<&Path>,
+    reporter: Arc<ErrorReporter>,
+) -> Result<Option<WorkerGuard>> {
+    let stdout_filter = match std::env::var("RUST_LOG") {
+        Ok(directives) => EnvFilter::try_new(&directives)
+            .with_context(|| format!("RUST_LOG 格式錯誤: {}", directives))?,
+        Err(_) => EnvFilter::try_new(&config.level)
+            .with_context(|| format!("logging.level 格式錯誤: {}", config.level))?,
+    };
+
+    let file_config = file_config(config, log_dir);
+    let (file_layer, guard) = match &file_config {
+        Some(file) => {
+            let (writer, guard) = tracing_appender::non_blocking(file_appender(file)?);
+            let filter = EnvFilter::try_new(&file.level)
+                .with_context(|| format!("logging.file.level 格式錯誤: {}", file.level))?;
+            let layer = tracing_subscriber::fmt::layer()
+                .with_writer(writer)
+                .with_ansi(false)
+                .with_filter(filter);
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_filter(stdout_filter))
+        .with(file_layer)
+        // 錯誤回報的 layer 先掛上，`ErrorReporter::configure` 後才會開始送出
+        .with(ErrorReportLayer::new(reporter))
+        .try_init()
+        .context("初始化日誌失敗")?;
+
+    if let Some(file) = &file_config {
+        tracing::info!(
+            "日誌同時寫入 {}（{:?} 輪替，保留 {} 個檔案）",
+            file.directory.join(&file.prefix).display(),
+            file.rotation,
+            file.max_files
+        );
+    }
+
+    Ok(guard)
+}
+
+/// 合併配置與 `--log-dir`：只給 `--log-dir` 時以預設值寫入該目錄
+fn file_config(config: &LoggingConfig, log_dir: Option<&Path>) -> Option<LogFileConfig> {
+    match (config.file.clone(), log_dir) {
+        (Some(file), Some(dir)) => Some(LogFileConfig {
+            directory: dir.to_path_buf(),
+            ..file
+        }),
+        (Some(file), None) => Some(file),
+        (None, Some(dir)) => Some(LogFileConfig {
+            directory: dir.to_path_buf(),
+            ..LogFileConfig::default()
+        }),
+        (None, None) => None,
+    }
+}
+
+fn file_appender(file: &LogFileConfig) -> Result<RollingFileAppender> {
+    let rotation = match file.rotation {
+        LogRotation::Minutely => Rotation::MINUTELY,
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Daily => Rotation::DAILY,
+        LogRotation::Never => Rotation::NEVER,
+    };
+    let mut builder = RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix(&file.prefix);
+    if file.max_files > 0 {
+        builder = builder.max_log_files(file.max_files);
+    }
+    builder
+        .build(&file.directory)
+        .with_context(|| format!("無法建立日誌檔: {}", file.directory.display()))
+}
+
+/// 路由的日誌詳細程度（`logging.routes`）：以路徑前綴比對，最長者優先
+pub fn route_level(routes: &BTreeMap<String, RouteLogLevel>, path: &str) -> RouteLogLevel {
+    routes
+        .iter()
+        .filter(|(prefix, _)| {
+            let prefix = prefix.trim_end_matches('/');
+            path == prefix
+                || prefix.is_empty()
+                || path
+                    .strip_prefix(prefix)
+                    .is_some_and(|rest| rest.starts_with('/'))
+        })
+        .max_by_key(|(prefix, _)| prefix.trim_end_matches('/').len())
+        .map(|(_, level)| *level)
+        .unwrap_or_default()
+}
+
+/// 路由設為 `full` 時記錄請求內容。`describe` 只在需要記錄時才呼叫，內容應先經過遮蔽
+pub fn log_request(
+    routes: &BTreeMap<String, RouteLogLevel>,
+    route: &str,
+    describe: impl FnOnce() -> String,
+) {
+    if route_level(routes, route) == RouteLogLevel::Full {
+        tracing::info!("{} 請求內容: {}", route, describe());
+    }
+}
+
+fn is_sensitive(key: &str) -> bool {
+    REDACTED_KEYS.contains(&key)
+}
+
+/// 遮蔽表單中的敏感欄位。值為 JSON（例如 dialog 的 `payload`）時遮蔽其中的欄位
+pub fn redact_form(form: &HashMap<String, String>) -> BTreeMap<String, String> {
+    form.iter()
+        .map(|(key, value)| {
+            let value = if is_sensitive(key) {
+                REDACTED.to_string()
+            } else {
+                match serde_json::from_str::<serde_json::Value>(value) {
+                    Ok(json @ serde_json::Value::Object(_)) => redact_json(&json).to_string(),
+                    _ => value.clone(),
+                }
+            };
+            (key.clone(), value)
+        })
+        .collect()
+}
+
+/// 遞迴遮蔽 JSON 中的敏感欄位
+pub fn redact_json(value: &serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => serde_json::Value::Object(
+            map.iter()
+                .map(|(key, value)| {
+                    let value = if is_sensitive(key) {
+                        serde_json::json!(REDACTED)
+                    } else {
+                        redact_json(value)
+                    };
+                    (key.clone(), value)
+                })
+                .collect(),
+        ),
+        serde_json::Value::Array(items) => {
+            serde_json::Value::Array(items.iter().map(redact_json).collect())
+        }
+        other => other.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_file_config_merges_log_dir() {
+        let dir = Path::new("/var/log/bot");
+
+        assert!(file_config(&LoggingConfig::default(), None).is_none());
+
+        let file = file_config(&LoggingConfig::default(), Some(dir)).unwrap();
+        assert_eq!(file.directory, dir);
+        assert_eq!(file.rotation, LogRotation::Daily);
+
+        let config: LoggingConfig =
+            serde_yaml::from_str("file:\n  directory: logs\n  rotation: hourly\n  level: debug\n")
+                .unwrap();
+        let file = file_config(&config, Some(dir)).unwrap();
+        assert_eq!(file.directory, dir);
+        assert_eq!(file.rotation, LogRotation::Hourly);
+        assert_eq!(file.level, "debug");
+    }
+
+    #[test]
+    fn test_route_level_longest_prefix() {
+        let routes = BTreeMap::from([
+            ("/api/v1/group_buy".to_string(), RouteLogLevel::Full),
+            ("/api/v1/group_buy/dialog/".to_string(), RouteLogLevel::Off),
+            ("/health".to_string(), RouteLogLevel::Off),
+        ]);
+        assert_eq!(
+            route_level(&routes, "/api/v1/group_buy/action/close"),
+            RouteLogLevel::Full
+        );
+        assert_eq!(
+            route_level(&routes, "/api/v1/group_buy/dialog/create"),
+            RouteLogLevel::Off
+        );
+        assert_eq!(route_level(&routes, "/health"), RouteLogLevel::Off);
+        // 只比對完整的路徑段
+        assert_eq!(route_level(&routes, "/healthz"), RouteLogLevel::Summary);
+        assert_eq!(route_level(&routes, "/sticker"), RouteLogLevel::Summary);
+    }
+
+    #[test]
+    fn test_redact_form() {
+        let form = HashMap::from([
+            ("token".to_string(), "secret".to_string()),
+            ("text".to_string(), "貓".to_string()),
+            (
+                "payload".to_string(),
+                r#"{"callback_id":"cb","response_url":"http://mm/hooks/1","context":{"_sig":"abc","action":"close"}}"#
+                    .to_string(),
+            ),
+        ]);
+        let redacted = redact_form(&form);
+        assert_eq!(redacted["token"], REDACTED);
+        assert_eq!(redacted["text"], "貓");
+
+        let payload: serde_json::Value = serde_json::from_str(&redacted["payload"]).unwrap();
+        assert_eq!(payload["response_url"], REDACTED);
+        assert_eq!(payload["context"]["_sig"], REDACTED);
+        assert_eq!(payload["context"]["action"], "close");
+        assert_eq!(payload["callback_id"], "cb");
+    }
+
+    #[test]
+    fn test_file_appender_writes_to_directory() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = LogFileConfig {
+            directory: temp_dir.path().to_path_buf(),
+            ..LogFileConfig::default()
+        };
+
+        let mut appender = file_appender(&file).unwrap();
+        appender.write_all(b"hello\n").unwrap();
+        appender.flush().unwrap();
+
+        let names: Vec<String> = std::fs::read_dir(temp_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names.len(), 1);
+        assert!(names[0].starts_with("bot.log."), "{:?}", names);
+    }
+}
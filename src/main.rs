@@ -1,8 +1,24 @@
+mod capabilities;
 mod config;
 mod database;
+mod error_code;
+mod error_reporting;
+mod feed;
 mod handlers;
+mod leader;
+mod locale;
+mod logging;
 mod mattermost;
+mod metrics;
+mod money;
+mod panic_guard;
+mod scheduler;
+mod shared_store;
+mod signing;
+mod slash_deadline;
+mod startup;
 mod sticker;
+mod sticker_cache;
 #[cfg(test)]
 mod test_utils;
 mod websocket;
@@ -13,17 +29,21 @@ use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tracing::{error, info};
+use tracing::{Instrument, error, info, warn};
 use url::form_urlencoded;
 use warp::Filter;
 
-use config::Config;
+use capabilities::Capabilities;
+use config::{Config, Feature};
 use database::Database;
 use handlers::{
-    handle_action, handle_adjust_shortage_dialog, handle_cancel_register_dialog,
-    handle_create_dialog, handle_edit_items_dialog, handle_group_buy_action,
-    handle_group_buy_command, handle_leko_command, handle_register_dialog, handle_rejection,
-    handle_sticker_command,
+    admin_api_routes, callback_allowlist, db_maintenance_job, deadline_closer_job,
+    detect_callback_url, feed_watcher_job, handle_action, handle_adjust_shortage_dialog,
+    handle_cancel_register_dialog, handle_create_dialog, handle_deactivated_buyers_dialog,
+    handle_edit_items_dialog, handle_group_buy_action, handle_group_buy_command,
+    handle_leko_command, handle_payments_dialog, handle_register_dialog, handle_rejection,
+    handle_sticker_command, orphan_detector_job, picker_cleanup_job, reminder_job, require_feature,
+    spawn_post_refresher, sticker_refresh_job, webhook_routes,
 };
 use mattermost::MattermostClient;
 use sticker::StickerDatabase;
@@ -44,6 +64,15 @@ struct Args {
     /// HTTP 伺服器監聯埠號
     #[arg(short, long, default_value = "3000")]
     port: u16,
+
+    /// 演練模式：Mattermost 的變更操作（發文、編輯、刪除、對話框）只記錄不執行，
+    /// 資料庫寫入暫存的副本，可用正式設定安全地演練設定變更與新功能
+    #[arg(long)]
+    dry_run: bool,
+
+    /// 日誌檔目錄，覆蓋配置中的 logging.file.directory（未設定 logging.file 時以預設值啟用）
+    #[arg(long, value_name = "DIR")]
+    log_dir: Option<PathBuf>,
 }
 
 pub struct AppState {
@@ -51,20 +80,24 @@ pub struct AppState {
     pub mattermost_client: MattermostClient,
     pub sticker_database: StickerDatabase,
     pub database: Database,
+    /// 一次性按鈕已使用的 nonce（啟動時依 `shared_store` 建立）
+    pub nonce_store: shared_store::NonceStore,
     pub bot_user_id: String,
     pub config_path: PathBuf,
+    /// 啟動時偵測到的 bot token 權限
+    pub capabilities: Capabilities,
+    /// 延遲與錯誤統計，`database`、`mattermost_client` 與日誌持有同一份
+    pub metrics: Arc<metrics::Metrics>,
+    /// 錯誤回報，`reload` 時依新的 `error_reporting` 重新設定
+    pub error_reporter: Arc<error_reporting::ErrorReporter>,
+    /// 本實例的識別碼與是否為 leader，排程工作只在 leader 執行
+    pub leadership: leader::Leadership,
+    /// 要在背景更新貼文的團購 ID，由 `spawn_post_refresher` 接收
+    pub post_refresh: tokio::sync::mpsc::UnboundedSender<String>,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // 初始化日誌
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
-        )
-        .init();
-
     // We standardize on the aws-lc-rs crypto provider at compile time via cargo
     // features (sqlx uses `tls-rustls-aws-lc-rs` and reqwest/hyper-rustls also
     // select aws-lc). That avoids runtime ambiguity in rustls, so no manual
@@ -73,26 +106,64 @@ async fn main() -> Result<()> {
     // 解析命令列參數
     let args = Args::parse();
 
-    info!("正在啟動 Leko's Mattermost Bot...");
-
     // 確定配置文件路徑
     let config_path = args
         .config
         .or_else(|| std::env::var("CONFIG_YAML").ok().map(PathBuf::from))
         .unwrap_or_else(|| PathBuf::from("config.yaml"));
 
-    // 載入配置
+    // 載入配置（日誌的輸出方式由配置決定，此時尚未初始化日誌）
     let config = Config::from_path(&config_path).context("載入配置失敗")?;
 
+    // 延遲、panic 與 ERROR 日誌的統計，整個程式共用同一份
+    let metrics = Arc::new(metrics::Metrics::default());
+    let error_reporter = Arc::new(error_reporting::ErrorReporter::new(metrics.clone()));
+
+    // 初始化日誌；guard 在 main 結束前都要保留，檔案日誌才會寫完
+    let _log_guard = logging::init(
+        &config.logging,
+        args.log_dir.as_deref(),
+        error_reporter.clone(),
+    )?;
+
+    // handler panic 時記下 backtrace，回報給管理員
+    panic_guard::install_hook();
+
+    info!("正在啟動 Leko's Mattermost Bot...");
     info!("配置載入成功");
-    info!("Mattermost URL: {}", config.mattermost.url);
-    info!("Bot Token 長度: {} 字元", config.mattermost.bot_token.len());
+
+    error_reporter
+        .configure(&config.error_reporting)
+        .context("啟動錯誤回報失敗")?;
+    if let Some(dsn) = &config.error_reporting.sentry_dsn {
+        info!(
+            "ERROR 日誌會回報到 Sentry: {}",
+            error_reporting::SentryDsn::parse(dsn)?.envelope_url
+        );
+    }
+    if config.error_reporting.webhook_url.is_some() {
+        info!("ERROR 日誌會回報到 error webhook");
+    }
+    startup::log_banner(&config);
+    startup::check_callback_url(&config.mattermost)?;
 
     // 初始化 Mattermost 客戶端
-    let mattermost_client = MattermostClient::new(
+    let mut mattermost_client = MattermostClient::new(
         config.mattermost.url.clone(),
         config.mattermost.bot_token.clone(),
-    )?;
+    )?
+    .with_metrics(metrics.clone());
+    if let Some(secret) = &config.mattermost.signing_secret {
+        mattermost_client = mattermost_client.with_state_secret(secret);
+    }
+    if config.mattermost.trace_api {
+        info!("已啟用 Mattermost API 請求追蹤");
+        mattermost_client = mattermost_client.with_api_tracing(true);
+    }
+    if args.dry_run {
+        warn!("演練模式：Mattermost 的變更操作只記錄不執行，資料庫寫入暫存副本");
+        mattermost_client = mattermost_client.with_dry_run(true);
+    }
 
     info!("Mattermost 客戶端初始化成功");
 
@@ -111,12 +182,49 @@ async fn main() -> Result<()> {
 
     info!("Bot 使用者: {} ({})", bot_user.username, bot_user_id);
 
+    // 偵測 bot token 的權限，缺少權限的功能會被停用
+    let capabilities = Capabilities::probe(&mattermost_client, &bot_user.roles).await;
+    info!("Bot 權限: {:?}", capabilities);
+
     // 初始化 SQLite 資料庫
-    let database = Database::new(&config.database_url)
+    let database_url = if args.dry_run {
+        let url = database::dry_run_database_url(&config.database_url)
+            .context("建立演練用的資料庫副本失敗")?;
+        info!("演練用資料庫: {}", url);
+        url
+    } else {
+        config.database_url.clone()
+    };
+    // 演練時讀寫都使用副本，不讀取正式的唯讀副本
+    let mut database_config = config.database.clone();
+    if args.dry_run {
+        database_config.read_replica_url = None;
+    }
+    let database = Database::connect(&database_url, &database_config)
         .await
-        .context("初始化資料庫失敗")?;
+        .context("初始化資料庫失敗")?
+        .with_metrics(metrics.clone());
+
+    // WAL 模式下定期 checkpoint，限制 WAL 檔大小
+    if config.database.journal_mode.eq_ignore_ascii_case("wal")
+        && config.database.checkpoint_interval_secs > 0
+    {
+        database.spawn_wal_checkpoint(std::time::Duration::from_secs(
+            config.database.checkpoint_interval_secs,
+        ));
+        info!(
+            "已啟用定期 WAL checkpoint，間隔 {} 秒",
+            config.database.checkpoint_interval_secs
+        );
+    }
+    if config.database.maintenance_interval_hours > 0 {
+        info!(
+            "已啟用定期資料庫維護（完整性檢查與 VACUUM），間隔 {} 小時",
+            config.database.maintenance_interval_hours
+        );
+    }
 
-    info!("SQLite 資料庫初始化成功: {}", config.database_url);
+    info!("SQLite 資料庫初始化成功: {}", database_url);
 
     // 載入貼圖資料庫並寫入 SQLite（避免把所有貼圖緩存在記憶體）
     let sticker_database = StickerDatabase::load_from_config(&database, &config.stickers)
@@ -129,6 +237,17 @@ async fn main() -> Result<()> {
     };
     info!("貼圖資料庫載入成功，共 {} 張貼圖", sticker_count);
 
+    // 依使用統計預先下載常用貼圖，以檔案發送時不必等待圖床
+    if let Some(cache_dir) = &config.stickers.image_cache_dir
+        && config.stickers.prewarm_count > 0
+    {
+        sticker_cache::spawn_prewarm(
+            sticker_cache::ImageCache::new(cache_dir),
+            database.clone(),
+            config.stickers.prewarm_count,
+        );
+    }
+
     // 顯示管理員配置
     if !config.admin.is_empty() {
         info!("管理員列表: {:?}", config.admin);
@@ -136,23 +255,67 @@ async fn main() -> Result<()> {
         info!("未設定管理員");
     }
 
+    // 多個實例共用資料庫時，只有取得 lease 的實例執行背景工作
+    let leadership = leader::Leadership::default();
+    leader::spawn_leader_election(database.clone(), leadership.clone()).await;
+
+    let nonce_store = shared_store::NonceStore::from_config(&config.shared_store, &database);
+
     // 建立應用狀態
+    let (post_refresh, post_refresh_receiver) = tokio::sync::mpsc::unbounded_channel();
     let state = Arc::new(RwLock::new(AppState {
         config,
         mattermost_client,
         sticker_database,
         database,
+        nonce_store,
         bot_user_id,
         config_path,
+        capabilities,
+        metrics,
+        error_reporter,
+        leadership: leadership.clone(),
+        post_refresh,
     }));
 
     // 啟動 WebSocket 客戶端（在背景執行）
+    // WebSocket 的錯誤回報時以 websocket span 標示來源
     let ws_state = state.clone();
-    tokio::spawn(async move {
-        if let Err(e) = start_websocket(ws_state).await {
-            error!("WebSocket 客戶端錯誤: {}", e);
+    tokio::spawn(
+        async move {
+            if let Err(e) = start_websocket(ws_state).await {
+                error!("WebSocket 客戶端錯誤: {}", e);
+            }
         }
-    });
+        .instrument(tracing::info_span!("websocket")),
+    );
+
+    // 排程工作：自動截止限時團購、截止前提醒、孤兒團購檢查、清除沒人理會的貼圖選擇器、
+    // 重新讀取 HTTP 貼圖來源、定期資料庫維護、讀取 RSS／Atom feed
+    scheduler::spawn_scheduler(
+        state.clone(),
+        leadership.clone(),
+        vec![
+            deadline_closer_job(),
+            reminder_job(),
+            orphan_detector_job(),
+            picker_cleanup_job(),
+            sticker_refresh_job(),
+            db_maintenance_job(),
+            feed_watcher_job()?,
+        ],
+    );
+
+    // 登記、取消登記後在背景更新團購貼文
+    spawn_post_refresher(state.clone(), post_refresh_receiver);
+
+    // 確認 callback URL 連得到本實例（HTTP 伺服器啟動後執行）
+    if let Some(callback_url) = &state.read().await.config.mattermost.bot_callback_url {
+        startup::spawn_callback_verification(
+            callback_url.trim_end_matches('/').to_string(),
+            leadership.instance_id().to_string(),
+        );
+    }
 
     // 啟動 HTTP 伺服器
     let addr = format!("{}:{}", args.host, args.port);
@@ -164,13 +327,81 @@ async fn main() -> Result<()> {
 }
 
 async fn start_server(state: Arc<RwLock<AppState>>, addr: &str) -> Result<()> {
+    // Callback 端點的來源 IP 允許清單（載入配置時已驗證格式）
+    let callback_networks = state.read().await.config.mattermost.callback_networks()?;
+    if callback_networks.is_empty() {
+        info!("未設定 callback_allowlist，不限制 callback 來源");
+    } else {
+        info!("Callback 來源允許清單: {:?}", callback_networks);
+    }
+
+    // 加上請求日誌中間件。access log 的 callback 是同步的，重新載入配置正持有寫鎖時
+    // 取不到 logging.routes，就照預設記錄這一筆
+    let log_state = state.clone();
+    let log_metrics = state.read().await.metrics.clone();
+    let log = warp::log::custom(move |info| {
+        let level = log_state
+            .try_read()
+            .map(|state| logging::route_level(&state.config.logging.routes, info.path()))
+            .unwrap_or_default();
+        if level != config::RouteLogLevel::Off {
+            info!(
+                "{} {} {} - {}",
+                info.method(),
+                info.path(),
+                info.status(),
+                info.elapsed().as_millis()
+            );
+        }
+        // 不存在的路徑不記錄，避免掃描流量產生大量序列
+        if info.status() != warp::http::StatusCode::NOT_FOUND {
+            log_metrics.record(
+                "handler",
+                &metrics::normalize_path(info.path()),
+                info.elapsed(),
+            );
+        }
+    });
+
+    let routes = routes(state, callback_networks)
+        .with(log)
+        // 每個請求一個 span，處理過程中的日誌（包含 Mattermost API 追蹤）都會帶上 request_id
+        .with(warp::trace(|info| {
+            let request_id = uuid::Uuid::new_v4().simple().to_string();
+            tracing::info_span!(
+                "request",
+                request_id = %&request_id[..8],
+                method = %info.method(),
+                path = %info.path(),
+            )
+        }));
+
+    warp::serve(routes)
+        .run(addr.parse::<std::net::SocketAddr>()?)
+        .await;
+
+    Ok(())
+}
+
+/// 所有 HTTP 路由，每個端點都只對應到 `handlers` 模組中的一個實作
+fn routes(
+    state: Arc<RwLock<AppState>>,
+    callback_networks: Vec<ipnet::IpNet>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = std::convert::Infallible> + Clone {
+    let networks = Arc::new(callback_networks);
+    let allowlist = callback_allowlist(networks.clone());
+    let detect_callback = detect_callback_url(state.clone(), networks);
+
     // Slash command 路由
     let sticker_command = warp::post()
         .and(warp::path("sticker"))
         .and(warp::path::end())
+        .and(require_feature(state.clone(), Feature::Stickers))
         .and(warp::body::form())
         .and(with_state(state.clone()))
-        .and_then(handle_sticker_command);
+        .and_then(|form, state| {
+            slash_deadline::guard("/sticker", state, form, handle_sticker_command)
+        });
 
     // /leko slash command 路由
     let leko_command = warp::post()
@@ -178,179 +409,569 @@ async fn start_server(state: Arc<RwLock<AppState>>, addr: &str) -> Result<()> {
         .and(warp::path::end())
         .and(warp::body::form())
         .and(with_state(state.clone()))
-        .and_then(handle_leko_command);
+        .and_then(|form, state| slash_deadline::guard("/leko", state, form, handle_leko_command));
 
     // /group_buy slash command 路由
     let group_buy_command = warp::post()
         .and(warp::path("group_buy"))
         .and(warp::path::end())
+        .and(require_feature(state.clone(), Feature::GroupBuy))
         .and(warp::body::form())
         .and(with_state(state.clone()))
-        .and_then(handle_group_buy_command);
+        .and_then(|form, state| {
+            slash_deadline::guard("/group_buy", state, form, handle_group_buy_command)
+        });
 
     // 團購 Dialog 處理路由
-    let group_buy_dialog_create = warp::post()
+    let group_buy_dialogs = dialog_route(
+        "/api/v1/group_buy/dialog/create",
+        allowlist.clone(),
+        state.clone(),
+        handle_create_dialog,
+    )
+    .or(dialog_route(
+        "/api/v1/group_buy/dialog/edit_items",
+        allowlist.clone(),
+        state.clone(),
+        handle_edit_items_dialog,
+    ))
+    .or(dialog_route(
+        "/api/v1/group_buy/dialog/register",
+        allowlist.clone(),
+        state.clone(),
+        handle_register_dialog,
+    ))
+    .or(dialog_route(
+        "/api/v1/group_buy/dialog/cancel_register",
+        allowlist.clone(),
+        state.clone(),
+        handle_cancel_register_dialog,
+    ))
+    .or(dialog_route(
+        "/api/v1/group_buy/dialog/adjust_shortage",
+        allowlist.clone(),
+        state.clone(),
+        handle_adjust_shortage_dialog,
+    ))
+    .or(dialog_route(
+        "/api/v1/group_buy/dialog/payments",
+        allowlist.clone(),
+        state.clone(),
+        handle_payments_dialog,
+    ))
+    .or(dialog_route(
+        "/api/v1/group_buy/dialog/deactivated_buyers",
+        allowlist.clone(),
+        state.clone(),
+        handle_deactivated_buyers_dialog,
+    ));
+
+    // 團購按鈕 Action 處理路由
+    let group_buy_action = warp::post()
         .and(warp::path("api"))
         .and(warp::path("v1"))
         .and(warp::path("group_buy"))
-        .and(warp::path("dialog"))
-        .and(warp::path("create"))
+        .and(warp::path("action"))
+        .and(warp::path::param::<String>()) // 捕獲 action 名稱（如 edit_items, register 等）
         .and(warp::path::end())
-        .and(warp::header::optional::<String>("content-type"))
-        .and(warp::body::content_length_limit(1024 * 32))
-        .and(warp::body::bytes())
+        .and(allowlist.clone())
+        .and(require_feature(state.clone(), Feature::GroupBuy))
+        .and(warp::body::json())
         .and(with_state(state.clone()))
         .and_then(
-            |content_type: Option<String>, body: warp::hyper::body::Bytes, state| async move {
-                info!("收到 dialog 請求，Content-Type: {:?}", content_type);
-                // 將 bytes 解析為 form data
-                let body_str = String::from_utf8_lossy(&body);
-                info!("Body: {}", &body_str[..body_str.len().min(200)]);
-
-                let form: HashMap<String, String> = form_urlencoded::parse(body_str.as_bytes())
-                    .into_owned()
-                    .collect();
-
-                handle_create_dialog(form, state).await
+            |_action_name: String, action_req, state: Arc<RwLock<AppState>>| {
+                // action_name 不使用，因為 handle_group_buy_action 會從 action_req.context.action 中取得
+                panic_guard::guard(
+                    "/api/v1/group_buy/action",
+                    state.clone(),
+                    handle_group_buy_action(action_req, state),
+                )
             },
         );
 
-    let group_buy_dialog_edit_items = warp::post()
-        .and(warp::path("api"))
-        .and(warp::path("v1"))
-        .and(warp::path("group_buy"))
-        .and(warp::path("dialog"))
-        .and(warp::path("edit_items"))
+    // Interactive Message Action 處理器（貼圖面板）
+    let action_handler = warp::post()
+        .and(warp::path("action"))
         .and(warp::path::end())
-        .and(warp::body::content_length_limit(1024 * 32))
-        .and(warp::body::bytes())
+        .and(allowlist.clone())
+        .and(require_feature(state.clone(), Feature::Stickers))
+        .and(warp::body::json())
         .and(with_state(state.clone()))
-        .and_then(|body: warp::hyper::body::Bytes, state| async move {
-            let body_str = String::from_utf8_lossy(&body);
-            let form: HashMap<String, String> = form_urlencoded::parse(body_str.as_bytes())
-                .into_owned()
-                .collect();
-            handle_edit_items_dialog(form, state).await
+        .and_then(|action_req, state: Arc<RwLock<AppState>>| {
+            panic_guard::guard("/action", state.clone(), handle_action(action_req, state))
         });
 
-    let group_buy_dialog_register = warp::post()
-        .and(warp::path("api"))
-        .and(warp::path("v1"))
-        .and(warp::path("group_buy"))
-        .and(warp::path("dialog"))
-        .and(warp::path("register"))
+    // 健康檢查端點
+    let health = warp::get()
+        .and(warp::path("health"))
         .and(warp::path::end())
-        .and(warp::body::content_length_limit(1024 * 32))
-        .and(warp::body::bytes())
         .and(with_state(state.clone()))
-        .and_then(|body: warp::hyper::body::Bytes, state| async move {
-            let body_str = String::from_utf8_lossy(&body);
-            let form: HashMap<String, String> = form_urlencoded::parse(body_str.as_bytes())
-                .into_owned()
-                .collect();
-            handle_register_dialog(form, state).await
+        .then(|state: Arc<RwLock<AppState>>| async move {
+            let state_guard = state.read().await;
+            warp::reply::json(&serde_json::json!({
+                "status": "ok",
+                "instance_id": state_guard.leadership.instance_id(),
+                "capabilities": state_guard.capabilities,
+            }))
         });
 
-    let group_buy_dialog_cancel_register = warp::post()
-        .and(warp::path("api"))
-        .and(warp::path("v1"))
-        .and(warp::path("group_buy"))
-        .and(warp::path("dialog"))
-        .and(warp::path("cancel_register"))
+    // Prometheus 延遲統計
+    let metrics_endpoint = warp::get()
+        .and(warp::path("metrics"))
         .and(warp::path::end())
-        .and(warp::body::content_length_limit(1024 * 32))
-        .and(warp::body::bytes())
         .and(with_state(state.clone()))
-        .and_then(|body: warp::hyper::body::Bytes, state| async move {
-            let body_str = String::from_utf8_lossy(&body);
-            let form: HashMap<String, String> = form_urlencoded::parse(body_str.as_bytes())
-                .into_owned()
-                .collect();
-            handle_cancel_register_dialog(form, state).await
+        .then(|state: Arc<RwLock<AppState>>| async move {
+            let metrics = state.read().await.metrics.clone();
+            warp::reply::with_header(
+                metrics.render_prometheus(),
+                "content-type",
+                "text/plain; version=0.0.4",
+            )
         });
 
-    let group_buy_dialog_adjust_shortage = warp::post()
+    detect_callback
+        .and(
+            health
+                .or(metrics_endpoint)
+                .or(group_buy_dialogs)
+                .or(group_buy_action)
+                .or(action_handler)
+                .or(group_buy_command)
+                .or(leko_command)
+                .or(sticker_command)
+                .or(webhook_routes(state.clone()))
+                .or(admin_api_routes(state)),
+        )
+        .recover(handle_rejection)
+}
+
+/// 團購 Dialog 的 submission 路由，`route` 為完整路徑 `/api/v1/group_buy/dialog/<name>`
+///
+/// Mattermost 送來的 body 以 form 解析後交給對應的 handler。handler panic 時回覆 500。
+fn dialog_route<H, Fut, R>(
+    route: &'static str,
+    allowlist: impl Filter<Extract = (), Error = warp::Rejection> + Clone,
+    state: Arc<RwLock<AppState>>,
+    handler: H,
+) -> impl Filter<Extract = (warp::reply::Response,), Error = warp::Rejection> + Clone
+where
+    H: Fn(HashMap<String, String>, Arc<RwLock<AppState>>) -> Fut + Clone + Send + Sync + 'static,
+    Fut: std::future::Future<Output = Result<R, warp::Rejection>> + Send,
+    R: warp::Reply + Send,
+{
+    let name = route
+        .strip_prefix("/api/v1/group_buy/dialog/")
+        .expect("dialog 路由必須在 /api/v1/group_buy/dialog/ 之下");
+    warp::post()
         .and(warp::path("api"))
         .and(warp::path("v1"))
         .and(warp::path("group_buy"))
         .and(warp::path("dialog"))
-        .and(warp::path("adjust_shortage"))
+        .and(warp::path(name))
         .and(warp::path::end())
+        .and(allowlist)
+        .and(require_feature(state.clone(), Feature::GroupBuy))
         .and(warp::body::content_length_limit(1024 * 32))
         .and(warp::body::bytes())
-        .and(with_state(state.clone()))
-        .and_then(|body: warp::hyper::body::Bytes, state| async move {
-            let body_str = String::from_utf8_lossy(&body);
-            let form: HashMap<String, String> = form_urlencoded::parse(body_str.as_bytes())
-                .into_owned()
-                .collect();
-            handle_adjust_shortage_dialog(form, state).await
-        });
+        .and(with_state(state))
+        .and_then(move |body: warp::hyper::body::Bytes, state| {
+            let handler = handler.clone();
+            async move {
+                info!("收到 {} dialog 請求", name);
+                let form: HashMap<String, String> =
+                    form_urlencoded::parse(&body).into_owned().collect();
+                logging::log_request(&state.read().await.config.logging.routes, route, || {
+                    format!("{:?}", logging::redact_form(&form))
+                });
+                panic_guard::guard(route, state.clone(), handler(form, state)).await
+            }
+        })
+}
 
-    // 團購按鈕 Action 處理路由
-    let group_buy_action = warp::post()
-        .and(warp::path("api"))
-        .and(warp::path("v1"))
-        .and(warp::path("group_buy"))
-        .and(warp::path("action"))
-        .and(warp::path::param::<String>()) // 捕獲 action 名稱（如 edit_items, register 等）
-        .and(warp::path::end())
-        .and(warp::body::json())
-        .and(with_state(state.clone()))
-        .and_then(|_action_name: String, action_req, state| {
-            // action_name 不使用，因為 handle_group_buy_action 會從 action_req.context.action 中取得
-            handle_group_buy_action(action_req, state)
-        });
+fn with_state(
+    state: Arc<RwLock<AppState>>,
+) -> impl warp::Filter<Extract = (Arc<RwLock<AppState>>,), Error = std::convert::Infallible> + Clone
+{
+    warp::any().map(move || state.clone())
+}
 
-    // Interactive Message Action 處理器
-    let action_handler = warp::post()
-        .and(warp::path("action"))
-        .and(warp::path::end())
-        .and(warp::body::json())
-        .and(with_state(state.clone()))
-        .and_then(handle_action);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::utils::setup_db;
+    use reqwest::StatusCode;
 
-    // 健康檢查端點
-    let health = warp::get()
-        .and(warp::path("health"))
-        .and(warp::path::end())
-        .map(|| warp::reply::json(&serde_json::json!({"status": "ok"})));
+    async fn test_state() -> Arc<RwLock<AppState>> {
+        test_state_with_database(setup_db().await)
+    }
 
-    // 加上請求日誌中間件
-    let log = warp::log::custom(|info| {
-        info!(
-            "{} {} {} - {}",
-            info.method(),
-            info.path(),
-            info.status(),
-            info.elapsed().as_millis()
+    fn test_state_with_database(database: Database) -> Arc<RwLock<AppState>> {
+        let config: Config = serde_yaml::from_str(
+            r#"
+mattermost:
+  url: http://127.0.0.1:1
+  bot_token: test_token
+stickers:
+  categories: []
+"#,
+        )
+        .unwrap();
+
+        Arc::new(RwLock::new(AppState {
+            mattermost_client: MattermostClient::new(
+                config.mattermost.url.clone(),
+                config.mattermost.bot_token.clone(),
+            )
+            .unwrap(),
+            sticker_database: StickerDatabase::new(database.clone()),
+            nonce_store: shared_store::NonceStore::Database(database.clone()),
+            metrics: database.metrics().clone(),
+            error_reporter: Arc::new(error_reporting::ErrorReporter::new(
+                database.metrics().clone(),
+            )),
+            database,
+            bot_user_id: "bot".to_string(),
+            config_path: PathBuf::from("config.yaml"),
+            capabilities: Capabilities::default(),
+            leadership: leader::Leadership::default(),
+            // 測試不啟動更新貼文的背景工作，要求直接丟棄
+            post_refresh: tokio::sync::mpsc::unbounded_channel().0,
+            config,
+        }))
+    }
+
+    /// 在隨機埠號啟動完整的路由，回傳 base URL
+    async fn spawn_routes(
+        state: Arc<RwLock<AppState>>,
+        callback_networks: Vec<ipnet::IpNet>,
+    ) -> String {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        tokio::spawn(warp::serve(routes(state, callback_networks)).run(addr));
+
+        let base = format!("http://{}", addr);
+        for _ in 0..50 {
+            if reqwest::get(format!("{}/health", base)).await.is_ok() {
+                return base;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        panic!("測試伺服器未啟動");
+    }
+
+    async fn post_json(url: String, body: serde_json::Value) -> (StatusCode, serde_json::Value) {
+        let resp = reqwest::Client::new()
+            .post(url)
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        (resp.status(), resp.json().await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_sticker_route_searches_database() {
+        let base = spawn_routes(test_state().await, Vec::new()).await;
+
+        let resp = reqwest::Client::new()
+            .post(format!("{}/sticker", base))
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body("text=%E8%B2%93&user_id=u1&user_name=alice")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(body["text"], "找不到符合「貓」的貼圖（STICKER_NOT_FOUND）");
+    }
+
+    #[tokio::test]
+    async fn test_action_route_verifies_signed_context() {
+        let base = spawn_routes(test_state().await, Vec::new()).await;
+
+        let (status, body) = post_json(
+            format!("{}/action", base),
+            serde_json::json!({
+                "user_id": "u1",
+                "channel_id": "c1",
+                "post_id": "p1",
+                "context": {"action": "send_sticker", "user_id": "u1"},
+            }),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body["ephemeral_text"],
+            "此選擇器已過期，請重新輸入 /sticker"
         );
-    });
+        // 無法刪除面板訊息時清掉按鈕
+        assert_eq!(body["update"]["props"], serde_json::json!({}));
+    }
 
-    let routes = health
-        .or(group_buy_dialog_create)
-        .or(group_buy_dialog_edit_items)
-        .or(group_buy_dialog_register)
-        .or(group_buy_dialog_cancel_register)
-        .or(group_buy_dialog_adjust_shortage)
-        .or(group_buy_action)
-        .or(action_handler)
-        .or(group_buy_command)
-        .or(leko_command)
-        .or(sticker_command)
-        .recover(handle_rejection)
-        .with(log);
+    #[tokio::test]
+    async fn test_group_buy_action_route_dispatches_by_context() {
+        let state = test_state().await;
+        let context = state.read().await.mattermost_client.signer().sign_context(
+            serde_json::json!({"action": "shopping_list", "group_buy_id": "missing"}),
+            None,
+        );
+        let base = spawn_routes(state, Vec::new()).await;
+
+        let (status, body) = post_json(
+            format!("{}/api/v1/group_buy/action/shopping_list", base),
+            serde_json::json!({
+                "user_id": "u1",
+                "channel_id": "c1",
+                "post_id": "p1",
+                "context": context,
+            }),
+        )
+        .await;
 
-    warp::serve(routes)
-        .run(addr.parse::<std::net::SocketAddr>()?)
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["ephemeral_text"], "找不到該團購（GB_NOT_FOUND）");
+    }
+
+    #[tokio::test]
+    async fn test_callback_routes_enforce_allowlist() {
+        // 測試請求來自 127.0.0.1，不在允許清單內
+        let networks = vec!["10.0.0.0/8".parse().unwrap()];
+        let base = spawn_routes(test_state().await, networks).await;
+
+        for path in [
+            "/action",
+            "/api/v1/group_buy/action/register",
+            "/api/v1/group_buy/dialog/create",
+            "/api/v1/group_buy/dialog/adjust_shortage",
+        ] {
+            let (status, _) = post_json(format!("{}{}", base, path), serde_json::json!({})).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_admin_and_unknown_routes() {
+        let base = spawn_routes(test_state().await, Vec::new()).await;
+
+        let resp = reqwest::get(format!("{}/api/v1/admin/stickers/stats", base))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let (status, _) = post_json(format!("{}/stickers", base), serde_json::json!({})).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let health: serde_json::Value = reqwest::get(format!("{}/health", base))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(health["status"], "ok");
+        assert_eq!(health["capabilities"]["post"], true);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_route() {
+        let state = test_state().await;
+        state.write().await.config.webhooks = vec![config::WebhookConfig {
+            name: "ci".to_string(),
+            channel_id: "c1".to_string(),
+            token: "secret".to_string(),
+            template: "{{status}}".to_string(),
+            username: None,
+            icon_url: None,
+        }];
+        let base = spawn_routes(state.clone(), Vec::new()).await;
+
+        let (status, body) = post_json(
+            format!("{}/webhook/other?token=secret", base),
+            serde_json::json!({}),
+        )
         .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["code"], "NOT_FOUND");
 
-    Ok(())
-}
+        let (status, body) = post_json(
+            format!("{}/webhook/ci?token=wrong", base),
+            serde_json::json!({}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["code"], "INVALID_WEBHOOK_TOKEN");
+
+        let resp = reqwest::Client::new()
+            .post(format!("{}/webhook/ci", base))
+            .bearer_auth("secret")
+            .body("not json")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        // 範本結果為空時不發文
+        let (status, body) = post_json(
+            format!("{}/webhook/ci?token=secret", base),
+            serde_json::json!({}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["posted"], false);
 
-fn with_state(
-    state: Arc<RwLock<AppState>>,
-) -> impl warp::Filter<Extract = (Arc<RwLock<AppState>>,), Error = std::convert::Infallible> + Clone
-{
-    warp::any().map(move || state.clone())
+        // 測試用的 Mattermost 連不到
+        let (status, body) = post_json(
+            format!("{}/webhook/ci?token=secret", base),
+            serde_json::json!({"status": "ok"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(body["code"], "MATTERMOST_ERROR");
+
+        state.write().await.config.features.webhooks = false;
+        let (status, _) = post_json(
+            format!("{}/webhook/ci?token=secret", base),
+            serde_json::json!({}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_two_instances_serve_one_conversation() {
+        use crate::test_utils::utils::{create_and_insert_order, insert_group_buy};
+
+        // 兩個實例共用同一個資料庫檔案與 bot token，各自有獨立的連接池
+        let dir = tempfile::TempDir::new().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("bot.db").display());
+        let db_config = config::DatabaseConfig::default();
+        let connect = || Database::connect(&url, &db_config);
+        let state_a = test_state_with_database(connect().await.unwrap());
+        let state_b = test_state_with_database(connect().await.unwrap());
+
+        // 兩個實例各自參與 leader 選舉，先啟動的 A 取得 lease，排程工作只在 A 執行
+        for state in [&state_a, &state_b] {
+            let app_state = state.read().await;
+            leader::spawn_leader_election(app_state.database.clone(), app_state.leadership.clone())
+                .await;
+        }
+        {
+            let (a, b) = (state_a.read().await, state_b.read().await);
+            assert_ne!(a.leadership.instance_id(), b.leadership.instance_id());
+            assert!(a.leadership.is_leader());
+            assert!(!b.leadership.is_leader());
+        }
+
+        let base_a = spawn_routes(state_a.clone(), Vec::new()).await;
+        let base_b = spawn_routes(state_b.clone(), Vec::new()).await;
+
+        // 實例 A 建立團購並產生按鈕，按鈕送到實例 B
+        let (group_buy, signer) = {
+            let app_state = state_a.read().await;
+            let group_buy = insert_group_buy(&app_state.database, 1).await;
+            create_and_insert_order(&app_state.database, &group_buy.id, "alice", "alice", 2).await;
+            (group_buy, app_state.mattermost_client.signer().clone())
+        };
+        let context = signer.sign_context(
+            serde_json::json!({"action": "shopping_list", "group_buy_id": group_buy.id}),
+            None,
+        );
+        let (status, body) = post_json(
+            format!("{}/api/v1/group_buy/action/shopping_list", base_b),
+            serde_json::json!({"user_id": "u1", "channel_id": "c1", "post_id": "p1", "context": context}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(
+            body["ephemeral_text"]
+                .as_str()
+                .unwrap()
+                .starts_with("### 🛍️ 採購列表")
+        );
+
+        // 實例 A 的貼圖面板由實例 B 發送，重放到實例 A 會被拒絕
+        let context = signer.sign_context(
+            serde_json::json!({
+                "action": "send_sticker",
+                "user_id": "u1",
+                "sticker_name": "cat",
+                "sticker_image_url": "https://example.com/cat.png",
+            }),
+            Some(chrono::Duration::minutes(5)),
+        );
+        let request = serde_json::json!({"user_id": "u1", "channel_id": "c1", "post_id": "p1", "context": context});
+        let (_, body) = post_json(format!("{}/action", base_b), request.clone()).await;
+        assert_eq!(
+            body["update"]["message"],
+            "![cat](https://example.com/cat.png)"
+        );
+        let (_, body) = post_json(format!("{}/action", base_a), request).await;
+        assert_eq!(body["ephemeral_text"], "⚠️ 此貼圖已經發送過了");
+    }
+
+    #[tokio::test]
+    async fn test_callback_url_detected_from_allowed_source() {
+        let state = test_state().await;
+        state.write().await.config.mattermost.callback_host_header = Some("Host".to_string());
+
+        // 允許清單外的來源不會改變 callback URL
+        let base = spawn_routes(state.clone(), vec!["10.0.0.0/8".parse().unwrap()]).await;
+        reqwest::get(format!("{}/health", base)).await.unwrap();
+        assert_eq!(state.read().await.config.mattermost.bot_callback_url, None);
+
+        let base = spawn_routes(state.clone(), vec!["127.0.0.0/8".parse().unwrap()]).await;
+        let health: serde_json::Value = reqwest::get(format!("{}/health", base))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(
+            health["instance_id"],
+            state.read().await.leadership.instance_id()
+        );
+        assert_eq!(
+            state.read().await.config.mattermost.bot_callback_url,
+            Some(base)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_disabled_features_reject_routes() {
+        let state = test_state().await;
+        let base = spawn_routes(state.clone(), Vec::new()).await;
+
+        {
+            let mut state_guard = state.write().await;
+            state_guard.config.features.group_buy = false;
+            state_guard.config.features.apps = false;
+        }
+
+        let (status, body) = post_json(
+            format!("{}/api/v1/group_buy/action/close", base),
+            serde_json::json!({"user_id": "u1", "channel_id": "c1", "post_id": "p1", "context": {}}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["code"], "FEATURE_DISABLED");
+
+        let resp = reqwest::get(format!("{}/api/v1/admin/stickers/stats", base))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        // 其他路由不受影響，不存在的路徑仍是 404
+        let (status, _) = post_json(format!("{}/stickers", base), serde_json::json!({})).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // 重新啟用後立即生效（不需重新註冊路由）
+        state.write().await.config.features.apps = true;
+        let resp = reqwest::get(format!("{}/api/v1/admin/stickers/stats", base))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
 }
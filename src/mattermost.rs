@@ -2,12 +2,36 @@ use anyhow::{Context, Result};
 use reqwest::{Client, header};
 use serde::{Deserialize, Serialize};
 
+use crate::signing::StateSigner;
+
+/// 追蹤紀錄中 body 的最大長度（位元組）
+const TRACE_BODY_LIMIT: usize = 4096;
+
+/// 追蹤紀錄中需要遮蔽的 header
+const REDACTED_HEADERS: &[&str] = &["authorization", "cookie", "set-cookie"];
+
+/// 不是 GET 但不會改變任何內容的 API，演練模式下照常送出
+const DRY_RUN_PASSTHROUGH_PATHS: &[&str] = &[
+    "/api/v4/roles/names",
+    // 建立 DM 頻道已存在時直接回傳，使用者看不到任何變化
+    "/api/v4/channels/direct",
+];
+
+/// JSON body 中名稱包含這些字的欄位會被遮蔽
+const REDACTED_KEYWORDS: &[&str] = &["token", "secret", "password"];
+
 #[derive(Debug, Clone)]
 pub struct MattermostClient {
     base_url: String,
-    #[allow(dead_code)]
     bot_token: String,
     client: Client,
+    state_signer: StateSigner,
+    /// 是否記錄完整的 API 請求與回應
+    trace_api: bool,
+    /// 演練模式：變更操作只記錄、不送出
+    dry_run: bool,
+    /// API 呼叫耗時記錄到的統計
+    metrics: std::sync::Arc<crate::metrics::Metrics>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -78,14 +102,12 @@ pub struct ActionOption {
 }
 
 /// Interactive Message Action Callback Request
-#[derive(Debug, Deserialize)]
+#[derive(Deserialize)]
 pub struct ActionRequest {
     pub user_id: String,
     #[serde(default)]
     pub user_name: Option<String>,
-    #[allow(dead_code)]
     pub channel_id: String,
-    #[allow(dead_code)]
     pub post_id: String,
     #[serde(default)]
     #[allow(dead_code)]
@@ -93,6 +115,23 @@ pub struct ActionRequest {
     pub context: serde_json::Value,
 }
 
+/// 記錄時遮蔽 trigger_id 與 context 中的簽章
+impl std::fmt::Debug for ActionRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ActionRequest")
+            .field("user_id", &self.user_id)
+            .field("user_name", &self.user_name)
+            .field("channel_id", &self.channel_id)
+            .field("post_id", &self.post_id)
+            .field(
+                "trigger_id",
+                &self.trigger_id.as_ref().map(|_| crate::logging::REDACTED),
+            )
+            .field("context", &crate::logging::redact_json(&self.context))
+            .finish()
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 pub struct SlashCommand {
@@ -140,6 +179,64 @@ pub struct User {
     pub first_name: Option<String>,
     #[serde(default)]
     pub last_name: Option<String>,
+    /// 以空白分隔的 system 角色名稱
+    #[serde(default)]
+    pub roles: String,
+    /// 使用者介面的語系（`en`、`zh-TW`…）
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// 帳號停用的時間（毫秒），0 代表使用中
+    #[serde(default)]
+    pub delete_at: i64,
+}
+
+impl User {
+    /// 帳號是否已停用
+    pub fn is_deactivated(&self) -> bool {
+        self.delete_at != 0
+    }
+}
+
+/// 角色與其權限
+#[derive(Debug, Deserialize)]
+pub struct Role {
+    #[allow(dead_code)]
+    pub name: String,
+    #[serde(default)]
+    pub permissions: Vec<String>,
+}
+
+/// 頻道成員資料
+#[derive(Debug, Deserialize)]
+pub struct ChannelMember {
+    /// 以空白分隔的角色，例如 `channel_user channel_admin`
+    #[serde(default)]
+    pub roles: String,
+    /// 頻道 scheme 的管理員（使用權限 scheme 時不一定出現在 `roles`）
+    #[serde(default)]
+    pub scheme_admin: bool,
+}
+
+impl ChannelMember {
+    pub fn is_admin(&self) -> bool {
+        self.scheme_admin
+            || self
+                .roles
+                .split_whitespace()
+                .any(|role| role == "channel_admin")
+    }
+}
+
+/// 上傳到 Mattermost 的檔案資訊
+#[derive(Debug, Deserialize)]
+pub struct FileInfo {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub extension: String,
+    /// 檔案大小（bytes）
+    #[serde(default)]
+    pub size: u64,
 }
 
 /// Channel 資訊
@@ -165,22 +262,170 @@ impl MattermostClient {
 
         let client = Client::builder().default_headers(headers).build()?;
 
+        // 預設以 bot token 作為 dialog state 的簽章金鑰，可由 with_state_secret 覆寫
+        let state_signer = StateSigner::new(&bot_token);
+
         Ok(Self {
             base_url,
             bot_token,
             client,
+            state_signer,
+            trace_api: false,
+            dry_run: false,
+            metrics: Default::default(),
         })
     }
 
+    /// 啟用 API 請求追蹤：記錄完整的請求與回應（token 會被遮蔽）。
+    /// 追蹤紀錄會帶上目前的 tracing span，可對應到觸發它的 HTTP 請求。
+    pub fn with_api_tracing(mut self, enabled: bool) -> Self {
+        self.trace_api = enabled;
+        self
+    }
+
+    /// 啟用演練模式：發文、編輯、刪除、開啟對話框等變更操作只記錄、不送出，
+    /// 回傳以請求內容加上假 ID 組成的成功回應
+    pub fn with_dry_run(mut self, enabled: bool) -> Self {
+        self.dry_run = enabled;
+        self
+    }
+
+    /// API 呼叫耗時記錄到指定的統計
+    pub fn with_metrics(mut self, metrics: std::sync::Arc<crate::metrics::Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// 演練模式下代替實際送出的回應
+    fn dry_run_response(&self, request: &reqwest::Request) -> Result<reqwest::Response> {
+        let body = request
+            .body()
+            .and_then(|b| b.as_bytes())
+            .unwrap_or_default();
+        tracing::info!(
+            "[dry-run] 略過 {} {} body={}",
+            request.method(),
+            request.url(),
+            self.redact_body(body)
+        );
+
+        let mut response = match serde_json::from_slice::<serde_json::Value>(body) {
+            Ok(serde_json::Value::Object(object)) => object,
+            _ => serde_json::Map::new(),
+        };
+        response
+            .entry("id")
+            .or_insert_with(|| format!("dryrun{}", uuid::Uuid::new_v4().simple()).into());
+        let response = http::Response::builder()
+            .status(http::StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(serde_json::Value::Object(response).to_string())?;
+        Ok(reqwest::Response::from(response))
+    }
+
+    /// 發送請求，啟用追蹤時記錄請求與回應內容
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let (client, request) = request.build_split();
+        let request = request?;
+        if self.dry_run && is_mutation(&request) {
+            return self.dry_run_response(&request);
+        }
+        let _timer = self.metrics.timer(
+            "mattermost",
+            format!(
+                "{} {}",
+                request.method(),
+                crate::metrics::normalize_path(request.url().path())
+            ),
+        );
+
+        if !self.trace_api {
+            return Ok(client.execute(request).await?);
+        }
+
+        tracing::info!(
+            "Mattermost API → {} {} headers={:?} body={}",
+            request.method(),
+            request.url(),
+            redact_headers(request.headers()),
+            request
+                .body()
+                .and_then(|b| b.as_bytes())
+                .map(|b| self.redact_body(b))
+                .unwrap_or_default()
+        );
+
+        let started = std::time::Instant::now();
+        let response = client.execute(request).await?;
+        let status = response.status();
+        let version = response.version();
+        let headers = response.headers().clone();
+        let body = response.bytes().await?;
+        tracing::info!(
+            "Mattermost API ← {} ({} ms) headers={:?} body={}",
+            status,
+            started.elapsed().as_millis(),
+            redact_headers(&headers),
+            self.redact_body(&body)
+        );
+
+        // body 已經讀出，重新組成 Response 交給呼叫端
+        let mut rebuilt = http::Response::builder()
+            .status(status)
+            .version(version)
+            .body(body)?;
+        *rebuilt.headers_mut() = headers;
+        Ok(reqwest::Response::from(rebuilt))
+    }
+
+    /// 遮蔽 body 中的 token 後轉為可記錄的字串
+    fn redact_body(&self, body: &[u8]) -> String {
+        let text = match serde_json::from_slice::<serde_json::Value>(body) {
+            Ok(mut json) => {
+                redact_json(&mut json);
+                json.to_string()
+            }
+            Err(_) => String::from_utf8_lossy(body).into_owned(),
+        };
+        let mut text = if self.bot_token.is_empty() {
+            text
+        } else {
+            text.replace(&self.bot_token, "***")
+        };
+
+        if text.len() > TRACE_BODY_LIMIT {
+            let mut end = TRACE_BODY_LIMIT;
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            text.truncate(end);
+            text.push_str("…(truncated)");
+        }
+        text
+    }
+
+    /// 使用獨立的金鑰簽署 dialog state
+    pub fn with_state_secret(mut self, secret: &str) -> Self {
+        self.state_signer = StateSigner::new(secret);
+        self
+    }
+
+    /// 簽署 dialog state 與按鈕 context 用的 signer
+    pub fn signer(&self) -> &StateSigner {
+        &self.state_signer
+    }
+
+    /// 驗證 dialog 提交時帶回的 state，回傳簽章前的原始內容
+    pub fn verify_dialog_state<'a>(&self, state: &'a str) -> Result<&'a str> {
+        self.state_signer.verify(state)
+    }
+
     /// 發送訊息到頻道
     pub async fn create_post(&self, post: &Post) -> Result<()> {
         let url = format!("{}/api/v4/posts", self.base_url);
 
         let response = self
-            .client
-            .post(&url)
-            .json(post)
-            .send()
+            .send(self.client.post(&url).json(post))
             .await
             .context("發送訊息失敗")?;
 
@@ -199,10 +444,7 @@ impl MattermostClient {
         let url = format!("{}/api/v4/posts", self.base_url);
 
         let response = self
-            .client
-            .post(&url)
-            .json(post)
-            .send()
+            .send(self.client.post(&url).json(post))
             .await
             .context("發送訊息失敗")?;
 
@@ -222,6 +464,109 @@ impl MattermostClient {
         Ok(post_id)
     }
 
+    /// 上傳檔案到頻道並回傳 file ID，之後以 `create_post_with_files` 附加到貼文
+    pub async fn upload_file(
+        &self,
+        channel_id: &str,
+        filename: &str,
+        data: Vec<u8>,
+    ) -> Result<String> {
+        let url = format!(
+            "{}/api/v4/files?channel_id={}&filename={}",
+            self.base_url,
+            urlencoding::encode(channel_id),
+            urlencoding::encode(filename)
+        );
+
+        let response = self
+            .send(self.client.post(&url).body(data))
+            .await
+            .context("上傳檔案失敗")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            anyhow::bail!("上傳檔案失敗: {} - {}", status, text);
+        }
+
+        let body: serde_json::Value = response.json().await.context("解析上傳回應失敗")?;
+        // 演練模式的假回應沒有 file_infos，只有頂層的 id
+        body.pointer("/file_infos/0/id")
+            .or_else(|| body.get("id"))
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+            .ok_or_else(|| anyhow::anyhow!("回應中缺少 file id"))
+    }
+
+    /// 取得檔案資訊（檔名、大小），例如私訊附加的檔案
+    pub async fn get_file_info(&self, file_id: &str) -> Result<FileInfo> {
+        let url = format!("{}/api/v4/files/{}/info", self.base_url, file_id);
+
+        let response = self
+            .send(self.client.get(&url))
+            .await
+            .context("獲取檔案資訊失敗")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            anyhow::bail!("獲取檔案資訊失敗: {} - {}", status, text);
+        }
+
+        let info: FileInfo = response.json().await.context("解析檔案資訊失敗")?;
+        Ok(info)
+    }
+
+    /// 下載檔案內容
+    pub async fn download_file(&self, file_id: &str) -> Result<Vec<u8>> {
+        let url = format!("{}/api/v4/files/{}", self.base_url, file_id);
+
+        let response = self
+            .send(self.client.get(&url))
+            .await
+            .context("下載檔案失敗")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            anyhow::bail!("下載檔案失敗: {} - {}", status, text);
+        }
+
+        let data = response.bytes().await.context("讀取檔案內容失敗")?;
+        Ok(data.to_vec())
+    }
+
+    /// 發送附帶已上傳檔案的訊息
+    pub async fn create_post_with_files(
+        &self,
+        channel_id: &str,
+        message: &str,
+        file_ids: &[String],
+        props: serde_json::Value,
+    ) -> Result<()> {
+        let url = format!("{}/api/v4/posts", self.base_url);
+
+        let post = serde_json::json!({
+            "channel_id": channel_id,
+            "message": message,
+            "file_ids": file_ids,
+            "props": props,
+        });
+
+        let response = self
+            .send(self.client.post(&url).json(&post))
+            .await
+            .context("發送訊息失敗")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            anyhow::bail!("發送訊息失敗: {} - {}", status, text);
+        }
+
+        Ok(())
+    }
+
     /// 更新訊息
     #[allow(dead_code)]
     pub async fn update_post(
@@ -242,10 +587,7 @@ impl MattermostClient {
         }
 
         let response = self
-            .client
-            .put(&url)
-            .json(&payload)
-            .send()
+            .send(self.client.put(&url).json(&payload))
             .await
             .context("更新訊息失敗")?;
 
@@ -264,9 +606,7 @@ impl MattermostClient {
         let url = format!("{}/api/v4/posts/{}", self.base_url, post_id);
 
         let response = self
-            .client
-            .delete(&url)
-            .send()
+            .send(self.client.delete(&url))
             .await
             .context("刪除訊息失敗")?;
 
@@ -279,6 +619,86 @@ impl MattermostClient {
         Ok(())
     }
 
+    /// 透過 slash command 或互動訊息的 response_url 回覆，失敗時回傳原因。
+    /// response_url 不需要 bot token，使用不帶授權 header 的 client
+    pub async fn post_to_response_url(
+        &self,
+        response_url: &str,
+        payload: &serde_json::Value,
+    ) -> Result<()> {
+        if response_url.is_empty() {
+            anyhow::bail!("缺少 response_url");
+        }
+
+        let response = self
+            .send(Client::new().post(response_url).json(payload))
+            .await?;
+        let status_code = response.status();
+        if !status_code.is_success() {
+            let response_text = response.text().await.unwrap_or_default();
+            anyhow::bail!("狀態碼: {}, 回應: {}", status_code, response_text);
+        }
+        Ok(())
+    }
+
+    /// 讀取訊息，已被刪除時回傳 None（一般讀取回傳 404，管理員讀取時則帶有 `delete_at`）
+    pub async fn get_post(&self, post_id: &str) -> Result<Option<PostDetail>> {
+        let url = format!("{}/api/v4/posts/{}", self.base_url, post_id);
+
+        let response = self
+            .send(self.client.get(&url))
+            .await
+            .context("讀取訊息失敗")?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            anyhow::bail!("讀取訊息失敗: {} - {}", status, text);
+        }
+
+        let post: PostDetail = response.json().await.context("解析訊息失敗")?;
+        Ok((post.delete_at == 0).then_some(post))
+    }
+
+    /// 檢查訊息是否還在
+    pub async fn post_exists(&self, post_id: &str) -> Result<bool> {
+        Ok(self.get_post(post_id).await?.is_some())
+    }
+
+    /// 讀取頻道的訊息（由新到舊），`page` 從 0 開始
+    pub async fn get_channel_posts(
+        &self,
+        channel_id: &str,
+        page: u32,
+        per_page: u32,
+    ) -> Result<Vec<PostDetail>> {
+        let url = format!(
+            "{}/api/v4/channels/{}/posts?page={}&per_page={}",
+            self.base_url, channel_id, page, per_page
+        );
+
+        let response = self
+            .send(self.client.get(&url))
+            .await
+            .context("讀取頻道訊息失敗")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            anyhow::bail!("讀取頻道訊息失敗: {} - {}", status, text);
+        }
+
+        let mut list: PostList = response.json().await.context("解析頻道訊息失敗")?;
+        Ok(list
+            .order
+            .iter()
+            .filter_map(|id| list.posts.remove(id))
+            .collect())
+    }
+
     /// 發送臨時訊息（只有使用者看得到）
     #[allow(dead_code)]
     pub async fn send_ephemeral_post(
@@ -315,10 +735,7 @@ impl MattermostClient {
         info!("  message 長度: {} 字元", message.len());
 
         let response = self
-            .client
-            .post(&url)
-            .json(&payload)
-            .send()
+            .send(self.client.post(&url).json(&payload))
             .await
             .context("發送臨時訊息失敗")?;
 
@@ -335,14 +752,86 @@ impl MattermostClient {
         Ok(())
     }
 
+    /// 發送附帶 props（例如 attachments 按鈕）的臨時訊息
+    pub async fn send_ephemeral_post_with_props(
+        &self,
+        channel_id: &str,
+        user_id: &str,
+        message: &str,
+        props: serde_json::Value,
+    ) -> Result<()> {
+        let url = format!("{}/api/v4/posts/ephemeral", self.base_url);
+
+        let payload = serde_json::json!({
+            "user_id": user_id,
+            "post": {
+                "channel_id": channel_id,
+                "message": message,
+                "props": props,
+            }
+        });
+
+        let response = self
+            .send(self.client.post(&url).json(&payload))
+            .await
+            .context("發送臨時訊息失敗")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            anyhow::bail!("發送臨時訊息失敗: {} - {}", status, text);
+        }
+
+        Ok(())
+    }
+
     /// 獲取使用者資訊
     pub async fn get_user(&self, user_id: &str) -> Result<User> {
         let url = format!("{}/api/v4/users/{}", self.base_url, user_id);
 
         let response = self
-            .client
-            .get(&url)
-            .send()
+            .send(self.client.get(&url))
+            .await
+            .context("獲取使用者資訊失敗")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            anyhow::bail!("獲取使用者資訊失敗: {} - {}", status, text);
+        }
+
+        let user: User = response.json().await.context("解析使用者資訊失敗")?;
+        Ok(user)
+    }
+
+    /// 獲取使用者資訊，帳號已被刪除（404）時回傳 None
+    pub async fn find_user(&self, user_id: &str) -> Result<Option<User>> {
+        let url = format!("{}/api/v4/users/{}", self.base_url, user_id);
+
+        let response = self
+            .send(self.client.get(&url))
+            .await
+            .context("獲取使用者資訊失敗")?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            anyhow::bail!("獲取使用者資訊失敗: {} - {}", status, text);
+        }
+
+        let user: User = response.json().await.context("解析使用者資訊失敗")?;
+        Ok(Some(user))
+    }
+
+    /// 以使用者名稱獲取使用者資訊
+    pub async fn get_user_by_username(&self, username: &str) -> Result<User> {
+        let url = format!("{}/api/v4/users/username/{}", self.base_url, username);
+
+        let response = self
+            .send(self.client.get(&url))
             .await
             .context("獲取使用者資訊失敗")?;
 
@@ -361,9 +850,7 @@ impl MattermostClient {
         let url = format!("{}/api/v4/users/me", self.base_url);
 
         let response = self
-            .client
-            .get(&url)
-            .send()
+            .send(self.client.get(&url))
             .await
             .context("獲取當前使用者資訊失敗")?;
 
@@ -377,14 +864,31 @@ impl MattermostClient {
         Ok(user)
     }
 
+    /// 依名稱查詢角色的權限
+    pub async fn get_roles_by_names(&self, names: &[&str]) -> Result<Vec<Role>> {
+        let url = format!("{}/api/v4/roles/names", self.base_url);
+
+        let response = self
+            .send(self.client.post(&url).json(names))
+            .await
+            .context("查詢角色權限失敗")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            anyhow::bail!("查詢角色權限失敗: {} - {}", status, text);
+        }
+
+        let roles: Vec<Role> = response.json().await.context("解析角色權限失敗")?;
+        Ok(roles)
+    }
+
     /// 獲取頻道資訊
     pub async fn get_channel(&self, channel_id: &str) -> Result<Channel> {
         let url = format!("{}/api/v4/channels/{}", self.base_url, channel_id);
 
         let response = self
-            .client
-            .get(&url)
-            .send()
+            .send(self.client.get(&url))
             .await
             .context("獲取頻道資訊失敗")?;
 
@@ -398,6 +902,76 @@ impl MattermostClient {
         Ok(channel)
     }
 
+    /// 以頻道名稱（`~town-square` 的 `town-square`）取得團隊中的頻道，找不到時回傳 None
+    pub async fn get_channel_by_name(&self, team_id: &str, name: &str) -> Result<Option<Channel>> {
+        let url = format!(
+            "{}/api/v4/teams/{}/channels/name/{}",
+            self.base_url, team_id, name
+        );
+
+        let response = self
+            .send(self.client.get(&url))
+            .await
+            .context("獲取頻道資訊失敗")?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            anyhow::bail!("獲取頻道資訊失敗: {} - {}", status, text);
+        }
+
+        let channel: Channel = response.json().await.context("解析頻道資訊失敗")?;
+        Ok(Some(channel))
+    }
+
+    /// 取得使用者在頻道中的成員資料，不是成員時回傳 None
+    pub async fn get_channel_member(
+        &self,
+        channel_id: &str,
+        user_id: &str,
+    ) -> Result<Option<ChannelMember>> {
+        let url = format!(
+            "{}/api/v4/channels/{}/members/{}",
+            self.base_url, channel_id, user_id
+        );
+
+        let response = self
+            .send(self.client.get(&url))
+            .await
+            .context("獲取頻道成員失敗")?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            anyhow::bail!("獲取頻道成員失敗: {} - {}", status, text);
+        }
+
+        let member: ChannelMember = response.json().await.context("解析頻道成員失敗")?;
+        Ok(Some(member))
+    }
+
+    /// 使用者是否為頻道成員
+    pub async fn is_channel_member(&self, channel_id: &str, user_id: &str) -> Result<bool> {
+        Ok(self
+            .get_channel_member(channel_id, user_id)
+            .await?
+            .is_some())
+    }
+
+    /// 使用者是否為頻道管理員
+    pub async fn is_channel_admin(&self, channel_id: &str, user_id: &str) -> Result<bool> {
+        Ok(self
+            .get_channel_member(channel_id, user_id)
+            .await?
+            .is_some_and(|member| member.is_admin()))
+    }
+
     /// 創建 DM 頻道（如果不存在）
     pub async fn create_direct_channel(&self, user_id_1: &str, user_id_2: &str) -> Result<Channel> {
         let url = format!("{}/api/v4/channels/direct", self.base_url);
@@ -405,10 +979,7 @@ impl MattermostClient {
         let payload = vec![user_id_1, user_id_2];
 
         let response = self
-            .client
-            .post(&url)
-            .json(&payload)
-            .send()
+            .send(self.client.post(&url).json(&payload))
             .await
             .context("創建 DM 頻道失敗")?;
 
@@ -441,10 +1012,7 @@ impl MattermostClient {
         }
 
         let response = self
-            .client
-            .post(&url)
-            .json(&post)
-            .send()
+            .send(self.client.post(&url).json(&post))
             .await
             .context("建立貼文失敗")?;
 
@@ -482,15 +1050,12 @@ impl MattermostClient {
                 submit_label: submit_label.unwrap_or("送出").to_string(),
                 notify_on_cancel: false,
                 introduction_text: introduction_text.map(|s| s.to_string()),
-                state: state.map(|s| s.to_string()),
+                state: state.map(|s| self.state_signer.sign(s)),
             },
         };
 
         let response = self
-            .client
-            .post(&api_url)
-            .json(&dialog)
-            .send()
+            .send(self.client.post(&api_url).json(&dialog))
             .await
             .context("開啟對話框失敗")?;
 
@@ -504,6 +1069,109 @@ impl MattermostClient {
     }
 }
 
+/// 產生 Interactive Message 的 action ID
+///
+/// Mattermost 以 action ID 找出被按下的按鈕，同一則貼文內重複的 ID 會觸發錯誤的動作，
+/// 且 ID 只能包含英數字。以前綴加上 scope（例如團購 ID）的雜湊組成，確保不會互相衝突。
+pub fn action_id(prefix: &str, scope: &str) -> String {
+    use sha2::{Digest, Sha256};
+
+    let prefix: String = prefix
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect();
+    let digest = Sha256::digest(format!("{}\0{}", prefix, scope).as_bytes());
+    format!("{}{}", prefix, &hex::encode(digest)[..16])
+}
+
+/// 會改變 Mattermost 內容的請求（演練模式下不送出）
+fn is_mutation(request: &reqwest::Request) -> bool {
+    request.method() != reqwest::Method::GET
+        && !DRY_RUN_PASSTHROUGH_PATHS
+            .iter()
+            .any(|path| request.url().path().ends_with(path))
+}
+
+/// 遮蔽敏感 header 後轉為可記錄的列表
+fn redact_headers(headers: &header::HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if REDACTED_HEADERS.contains(&name.as_str()) {
+                "***".to_string()
+            } else {
+                value.to_str().unwrap_or("<binary>").to_string()
+            };
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+/// 遞迴遮蔽 JSON 中名稱看起來像憑證的欄位
+fn redact_json(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, v) in map.iter_mut() {
+                let key = key.to_lowercase();
+                if REDACTED_KEYWORDS.iter().any(|k| key.contains(k)) && !v.is_null() {
+                    *v = serde_json::json!("***");
+                } else {
+                    redact_json(v);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact_json),
+        _ => {}
+    }
+}
+
+/// 從 Mattermost 讀取的貼文
+#[derive(Debug, Clone, Deserialize)]
+pub struct PostDetail {
+    pub id: String,
+    pub channel_id: String,
+    #[serde(default)]
+    pub user_id: String,
+    /// 不在討論串中時為空字串
+    #[serde(default)]
+    pub root_id: String,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default)]
+    pub props: serde_json::Value,
+    /// 表情回應、檔案、連結預覽等
+    #[serde(default)]
+    pub metadata: serde_json::Value,
+    #[serde(default)]
+    pub update_at: i64,
+    #[serde(default)]
+    pub delete_at: i64,
+}
+
+impl PostDetail {
+    /// 以 `updates` 的欄位覆蓋現有 props，其餘欄位（例如 `override_username`）保留。
+    /// 更新貼文時 Mattermost 會以送出的 props 整個取代原本的 props。
+    pub fn merged_props(&self, updates: serde_json::Value) -> serde_json::Value {
+        let mut props = match &self.props {
+            serde_json::Value::Object(existing) => existing.clone(),
+            _ => serde_json::Map::new(),
+        };
+        if let serde_json::Value::Object(updates) = updates {
+            props.extend(updates);
+        }
+        serde_json::Value::Object(props)
+    }
+}
+
+/// `GET /channels/{id}/posts` 的回應，`order` 為由新到舊的訊息 ID
+#[derive(Debug, Deserialize)]
+struct PostList {
+    #[serde(default)]
+    order: Vec<String>,
+    #[serde(default)]
+    posts: std::collections::HashMap<String, PostDetail>,
+}
+
 /// 貼文回應
 #[derive(Debug, Deserialize)]
 pub struct PostResponse {
@@ -578,6 +1246,22 @@ pub struct DialogOption {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_action_id_is_alphanumeric_and_scoped() {
+        let id = action_id("adjust_shortage", "0b6f7a52-1c1e-4a8e-9f57-2f0c9d1e3a4b");
+        assert!(id.starts_with("adjustshortage"));
+        assert!(id.chars().all(|c| c.is_ascii_alphanumeric()));
+
+        // 相同輸入產生相同 ID，不同前綴或 scope 不會衝突
+        assert_eq!(
+            id,
+            action_id("adjust_shortage", "0b6f7a52-1c1e-4a8e-9f57-2f0c9d1e3a4b")
+        );
+        assert_ne!(id, action_id("adjust_shortage", "another"));
+        assert_ne!(action_id("cancel", "u1"), action_id("cancel", "u2"));
+        assert_ne!(action_id("a", "bc"), action_id("ab", "c"));
+    }
+
     #[test]
     fn test_create_client() {
         let client =
@@ -585,6 +1269,318 @@ mod tests {
         assert!(client.is_ok());
     }
 
+    #[test]
+    fn test_verify_dialog_state_uses_configured_secret() {
+        let client =
+            MattermostClient::new("https://example.com".to_string(), "test_token".to_string())
+                .unwrap()
+                .with_state_secret("state_secret");
+
+        let signed = StateSigner::new("state_secret").sign(r#"{"group_buy_id":"abc"}"#);
+        assert_eq!(
+            client.verify_dialog_state(&signed).unwrap(),
+            r#"{"group_buy_id":"abc"}"#
+        );
+
+        // 以 bot token 簽署的 state 不再有效
+        let signed = StateSigner::new("test_token").sign(r#"{"group_buy_id":"abc"}"#);
+        assert!(client.verify_dialog_state(&signed).is_err());
+    }
+
+    #[test]
+    fn test_trace_redaction() {
+        let client =
+            MattermostClient::new("https://example.com".to_string(), "test_token".to_string())
+                .unwrap();
+
+        let body = serde_json::json!({
+            "message": "hello test_token",
+            "props": { "access_token": "abc", "attachments": [{ "secret": "x" }] },
+        });
+        let redacted = client.redact_body(body.to_string().as_bytes());
+        assert!(!redacted.contains("test_token"));
+        assert!(!redacted.contains("abc"));
+        assert!(redacted.contains(r#""secret":"***""#));
+        assert!(redacted.contains("hello ***"));
+
+        let long = "a".repeat(TRACE_BODY_LIMIT + 10);
+        assert!(
+            client
+                .redact_body(long.as_bytes())
+                .ends_with("…(truncated)")
+        );
+
+        let mut headers = header::HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            header::HeaderValue::from_static("Bearer test_token"),
+        );
+        headers.insert(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/json"),
+        );
+        let headers = redact_headers(&headers);
+        assert!(headers.contains(&("authorization".to_string(), "***".to_string())));
+        assert!(headers.contains(&("content-type".to_string(), "application/json".to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_traced_request_keeps_response_body() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/api/v4/users/me")
+            .match_header("authorization", "Bearer test_token")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"id":"bot","username":"leko-bot"}"#)
+            .create_async()
+            .await;
+
+        let client = MattermostClient::new(server.url(), "test_token".to_string())
+            .unwrap()
+            .with_api_tracing(true);
+        let user = client.get_me().await.unwrap();
+
+        assert_eq!(user.username, "leko-bot");
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_get_post() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/api/v4/posts/post-1")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"id":"post-1","channel_id":"chan","user_id":"bot","message":"hi",
+                    "props":{"override_username":"alice","attachments":[]},"delete_at":0}"#,
+            )
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/api/v4/posts/deleted")
+            .with_status(200)
+            .with_body(r#"{"id":"deleted","channel_id":"chan","delete_at":1700000000000}"#)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/api/v4/posts/missing")
+            .with_status(404)
+            .create_async()
+            .await;
+        let client = MattermostClient::new(server.url(), "test_token".to_string()).unwrap();
+
+        let post = client.get_post("post-1").await.unwrap().unwrap();
+        assert_eq!(post.message, "hi");
+        let props = post.merged_props(serde_json::json!({ "attachments": [{"text": "new"}] }));
+        assert_eq!(props["override_username"], "alice");
+        assert_eq!(props["attachments"][0]["text"], "new");
+
+        assert!(client.get_post("deleted").await.unwrap().is_none());
+        assert!(!client.post_exists("missing").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_channel_member_roles() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/api/v4/channels/chan/members/admin")
+            .with_status(200)
+            .with_body(
+                r#"{"channel_id":"chan","user_id":"admin","roles":"channel_user channel_admin"}"#,
+            )
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/api/v4/channels/chan/members/scheme")
+            .with_status(200)
+            .with_body(r#"{"channel_id":"chan","user_id":"scheme","roles":"","scheme_admin":true}"#)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/api/v4/channels/chan/members/user")
+            .with_status(200)
+            .with_body(r#"{"channel_id":"chan","user_id":"user","roles":"channel_user"}"#)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/api/v4/channels/chan/members/stranger")
+            .with_status(404)
+            .create_async()
+            .await;
+        let client = MattermostClient::new(server.url(), "test_token".to_string()).unwrap();
+
+        assert!(client.is_channel_admin("chan", "admin").await.unwrap());
+        assert!(client.is_channel_admin("chan", "scheme").await.unwrap());
+        assert!(!client.is_channel_admin("chan", "user").await.unwrap());
+        assert!(client.is_channel_member("chan", "user").await.unwrap());
+        assert!(!client.is_channel_admin("chan", "stranger").await.unwrap());
+        assert!(!client.is_channel_member("chan", "stranger").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_find_user_deactivated() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/api/v4/users/active")
+            .with_status(200)
+            .with_body(r#"{"id":"active","username":"alice","delete_at":0}"#)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/api/v4/users/left")
+            .with_status(200)
+            .with_body(r#"{"id":"left","username":"bob","delete_at":1700000000000}"#)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/api/v4/users/gone")
+            .with_status(404)
+            .create_async()
+            .await;
+        let client = MattermostClient::new(server.url(), "test_token".to_string()).unwrap();
+
+        assert!(
+            !client
+                .find_user("active")
+                .await
+                .unwrap()
+                .unwrap()
+                .is_deactivated()
+        );
+        assert!(
+            client
+                .find_user("left")
+                .await
+                .unwrap()
+                .unwrap()
+                .is_deactivated()
+        );
+        assert!(client.find_user("gone").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_skips_mutations() {
+        let mut server = mockito::Server::new_async().await;
+        let create = server
+            .mock("POST", "/api/v4/posts")
+            .expect(0)
+            .create_async()
+            .await;
+        let read = server
+            .mock("GET", "/api/v4/users/me")
+            .with_status(200)
+            .with_body(r#"{"id":"bot","username":"leko-bot"}"#)
+            .create_async()
+            .await;
+
+        let client = MattermostClient::new(server.url(), "test_token".to_string())
+            .unwrap()
+            .with_dry_run(true);
+        let post = Post {
+            id: None,
+            channel_id: "chan".to_string(),
+            message: "hi".to_string(),
+            root_id: None,
+            props: None,
+        };
+        let post_id = client.create_post_with_response(&post).await.unwrap();
+        assert!(post_id.starts_with("dryrun"));
+        assert_eq!(client.get_me().await.unwrap().username, "leko-bot");
+
+        create.assert_async().await;
+        read.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_upload_file_and_post() {
+        let mut server = mockito::Server::new_async().await;
+        let upload = server
+            .mock("POST", "/api/v4/files?channel_id=chan&filename=cat.png")
+            .match_body(mockito::Matcher::Exact("PNGDATA".to_string()))
+            .with_status(201)
+            .with_body(r#"{"file_infos":[{"id":"file-1","name":"cat.png"}]}"#)
+            .create_async()
+            .await;
+        let post = server
+            .mock("POST", "/api/v4/posts")
+            .match_body(mockito::Matcher::PartialJson(
+                serde_json::json!({ "channel_id": "chan", "file_ids": ["file-1"] }),
+            ))
+            .with_status(201)
+            .with_body(r#"{"id":"post-1"}"#)
+            .create_async()
+            .await;
+        let client = MattermostClient::new(server.url(), "test_token".to_string()).unwrap();
+
+        let file_id = client
+            .upload_file("chan", "cat.png", b"PNGDATA".to_vec())
+            .await
+            .unwrap();
+        assert_eq!(file_id, "file-1");
+        client
+            .create_post_with_files("chan", "", &[file_id], serde_json::json!({}))
+            .await
+            .unwrap();
+
+        upload.assert_async().await;
+        post.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_get_file_info_and_download() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/api/v4/files/file-1/info")
+            .with_status(200)
+            .with_body(r#"{"id":"file-1","name":"stickers.csv","extension":"csv","size":12}"#)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/api/v4/files/file-1")
+            .with_status(200)
+            .with_body("名稱,圖片\n")
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/api/v4/files/missing")
+            .with_status(404)
+            .create_async()
+            .await;
+        let client = MattermostClient::new(server.url(), "test_token".to_string()).unwrap();
+
+        let info = client.get_file_info("file-1").await.unwrap();
+        assert_eq!(info.name, "stickers.csv");
+        assert_eq!(info.extension, "csv");
+        assert_eq!(info.size, 12);
+        assert_eq!(
+            client.download_file("file-1").await.unwrap(),
+            "名稱,圖片\n".as_bytes()
+        );
+        assert!(client.download_file("missing").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_get_channel_posts_keeps_order() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/api/v4/channels/chan/posts?page=0&per_page=2")
+            .with_status(200)
+            .with_body(
+                r#"{"order":["new","old"],"posts":{
+                    "old":{"id":"old","channel_id":"chan","message":"first"},
+                    "new":{"id":"new","channel_id":"chan","message":"second"}}}"#,
+            )
+            .create_async()
+            .await;
+        let client = MattermostClient::new(server.url(), "test_token".to_string()).unwrap();
+
+        let posts = client.get_channel_posts("chan", 0, 2).await.unwrap();
+        let ids: Vec<&str> = posts.iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(ids, vec!["new", "old"]);
+    }
+
     #[test]
     fn test_attachment_serialization() {
         let attachment = Attachment {
@@ -598,7 +1594,7 @@ mod tests {
             image_url: None,
             thumb_url: None,
             actions: Some(vec![Action {
-                id: "sticker_select".to_string(),
+                id: action_id("stickerselect", "u1"),
                 name: "選擇貼圖".to_string(),
                 action_type: "select".to_string(),
                 style: None,
@@ -616,7 +1612,7 @@ mod tests {
         };
 
         let json = serde_json::to_string(&attachment).unwrap();
-        assert!(json.contains("sticker_select"));
+        assert!(json.contains("stickerselect"));
         assert!(json.contains("選擇貼圖"));
     }
 }
@@ -0,0 +1,372 @@
+//! 延遲統計
+//!
+//! 記錄 HTTP handler、貼圖搜尋、資料庫查詢與 Mattermost API 呼叫的耗時，
+//! 提供 Prometheus 格式的 `/metrics` 與 DM `perf` 指令使用的最近一小時百分位數。
+//! 另外計算 handler panic 與 ERROR 日誌的次數，並記錄 WebSocket 連線與貼圖來源重新整理的時間，
+//! 供 DM `status` 指令顯示。
+//!
+//! 統計存在 `AppState.metrics`，`Database` 與 `MattermostClient` 各自持有同一份的 `Arc`。
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Histogram 的 bucket 上限（秒）
+const BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// 百分位數統計的時間範圍
+const WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// 每個序列保留的樣本上限，避免高流量時佔用過多記憶體
+const MAX_SAMPLES: usize = 10_000;
+
+pub struct Timer {
+    metrics: Arc<Metrics>,
+    kind: &'static str,
+    name: String,
+    started: Instant,
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.metrics
+            .record(self.kind, &self.name, self.started.elapsed());
+    }
+}
+
+#[derive(Default)]
+struct Series {
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+    recent: VecDeque<(Instant, f64)>,
+}
+
+/// 單一序列最近一小時的統計（毫秒）
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencySummary {
+    pub kind: &'static str,
+    pub name: String,
+    pub count: usize,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    series: Mutex<BTreeMap<(&'static str, String), Series>>,
+    panics: Mutex<BTreeMap<String, u64>>,
+    errors: AtomicU64,
+    websocket_connected_at: Mutex<Option<Instant>>,
+    last_source_refresh: Mutex<Option<chrono::DateTime<chrono::Utc>>>,
+}
+
+impl std::fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Metrics").finish_non_exhaustive()
+    }
+}
+
+impl Metrics {
+    /// 開始計時，`Timer` 被 drop 時記錄到這份統計
+    pub fn timer(self: &Arc<Self>, kind: &'static str, name: impl Into<String>) -> Timer {
+        Timer {
+            metrics: self.clone(),
+            kind,
+            name: name.into(),
+            started: Instant::now(),
+        }
+    }
+
+    /// 記錄一次耗時
+    pub fn record(&self, kind: &'static str, name: &str, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let now = Instant::now();
+
+        let mut series = self.series.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = series.entry((kind, name.to_string())).or_default();
+
+        for (bucket, le) in entry.buckets.iter_mut().zip(BUCKETS) {
+            if seconds <= *le {
+                *bucket += 1;
+            }
+        }
+        entry.count += 1;
+        entry.sum += seconds;
+
+        entry.recent.push_back((now, seconds));
+        while entry.recent.len() > MAX_SAMPLES {
+            entry.recent.pop_front();
+        }
+        while entry
+            .recent
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) > WINDOW)
+        {
+            entry.recent.pop_front();
+        }
+    }
+
+    /// 記錄一次 handler panic
+    pub fn record_panic(&self, route: &str) {
+        let mut panics = self.panics.lock().unwrap_or_else(|e| e.into_inner());
+        *panics.entry(route.to_string()).or_default() += 1;
+    }
+
+    /// 各路由 panic 的次數
+    pub fn panic_count(&self, route: &str) -> u64 {
+        let panics = self.panics.lock().unwrap_or_else(|e| e.into_inner());
+        panics.get(route).copied().unwrap_or(0)
+    }
+
+    /// 記錄一筆 ERROR 日誌
+    pub fn record_error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 啟動後的 ERROR 日誌次數
+    pub fn error_count(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    /// 記錄 WebSocket 連線（`true`）或斷線（`false`）
+    pub fn set_websocket_connected(&self, connected: bool) {
+        let mut at = self
+            .websocket_connected_at
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        *at = connected.then(Instant::now);
+    }
+
+    /// 目前 WebSocket 連線已持續的時間，未連線時為 `None`
+    pub fn websocket_uptime(&self) -> Option<Duration> {
+        let at = self
+            .websocket_connected_at
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        at.map(|at| at.elapsed())
+    }
+
+    /// 記錄貼圖來源重新整理完成
+    pub fn record_source_refresh(&self) {
+        let mut last = self
+            .last_source_refresh
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        *last = Some(chrono::Utc::now());
+    }
+
+    /// 最後一次貼圖來源重新整理的時間
+    pub fn last_source_refresh(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        *self
+            .last_source_refresh
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 輸出 Prometheus text format
+    pub fn render_prometheus(&self) -> String {
+        let series = self.series.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut out = String::new();
+        out.push_str("# HELP leko_latency_seconds Latency of handlers, sticker search, DB queries and Mattermost API calls\n");
+        out.push_str("# TYPE leko_latency_seconds histogram\n");
+
+        for ((kind, name), s) in series.iter() {
+            let labels = format!("kind=\"{}\",name=\"{}\"", kind, escape_label(name));
+            for (bucket, le) in s.buckets.iter().zip(BUCKETS) {
+                out.push_str(&format!(
+                    "leko_latency_seconds_bucket{{{},le=\"{}\"}} {}\n",
+                    labels, le, bucket
+                ));
+            }
+            out.push_str(&format!(
+                "leko_latency_seconds_bucket{{{},le=\"+Inf\"}} {}\n",
+                labels, s.count
+            ));
+            out.push_str(&format!(
+                "leko_latency_seconds_sum{{{}}} {}\n",
+                labels, s.sum
+            ));
+            out.push_str(&format!(
+                "leko_latency_seconds_count{{{}}} {}\n",
+                labels, s.count
+            ));
+        }
+
+        let panics = self.panics.lock().unwrap_or_else(|e| e.into_inner());
+        out.push_str("# HELP leko_handler_panics_total Panics caught in HTTP handlers\n");
+        out.push_str("# TYPE leko_handler_panics_total counter\n");
+        for (route, count) in panics.iter() {
+            out.push_str(&format!(
+                "leko_handler_panics_total{{route=\"{}\"}} {}\n",
+                escape_label(route),
+                count
+            ));
+        }
+
+        out.push_str("# HELP leko_errors_total Error logs since start\n");
+        out.push_str("# TYPE leko_errors_total counter\n");
+        out.push_str(&format!("leko_errors_total {}\n", self.error_count()));
+
+        out
+    }
+
+    /// 最近一小時各序列的 p50 / p95
+    pub fn summary(&self) -> Vec<LatencySummary> {
+        let series = self.series.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+
+        series
+            .iter()
+            .filter_map(|((kind, name), s)| {
+                let mut samples: Vec<f64> = s
+                    .recent
+                    .iter()
+                    .filter(|(at, _)| now.duration_since(*at) <= WINDOW)
+                    .map(|(_, v)| *v)
+                    .collect();
+                if samples.is_empty() {
+                    return None;
+                }
+                samples.sort_by(|a, b| a.total_cmp(b));
+
+                Some(LatencySummary {
+                    kind,
+                    name: name.clone(),
+                    count: samples.len(),
+                    p50_ms: percentile(&samples, 0.50) * 1000.0,
+                    p95_ms: percentile(&samples, 0.95) * 1000.0,
+                })
+            })
+            .collect()
+    }
+}
+
+/// 已排序樣本的百分位數（nearest-rank）
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// 將路徑中的 ID 替換成 `{id}`，避免每個團購或貼文各自產生一個序列。
+/// 動作名稱（如 `adjust_shortage`）以底線分隔，不會被視為 ID。
+pub fn normalize_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            let looks_like_id = !segment.contains('_')
+                && (segment.len() >= 16
+                    || (segment.len() >= 8 && segment.chars().any(|c| c.is_ascii_digit())));
+            if looks_like_id { "{id}" } else { segment }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_percentiles() {
+        let metrics = Metrics::default();
+        for ms in 1..=100 {
+            metrics.record("db", "get_group_buy", Duration::from_millis(ms));
+        }
+
+        let summary = metrics.summary();
+        assert_eq!(summary.len(), 1);
+        assert_eq!(summary[0].count, 100);
+        assert!((summary[0].p50_ms - 50.0).abs() < 0.5);
+        assert!((summary[0].p95_ms - 95.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_render_prometheus_histogram() {
+        let metrics = Metrics::default();
+        metrics.record("handler", "/action", Duration::from_millis(3));
+        metrics.record("handler", "/action", Duration::from_millis(300));
+
+        let text = metrics.render_prometheus();
+        assert!(text.contains(
+            r#"leko_latency_seconds_bucket{kind="handler",name="/action",le="0.005"} 1"#
+        ));
+        assert!(
+            text.contains(
+                r#"leko_latency_seconds_bucket{kind="handler",name="/action",le="0.5"} 2"#
+            )
+        );
+        assert!(text.contains(r#"leko_latency_seconds_count{kind="handler",name="/action"} 2"#));
+
+        metrics.record_panic("/action");
+        assert_eq!(metrics.panic_count("/action"), 1);
+        assert!(
+            metrics
+                .render_prometheus()
+                .contains(r#"leko_handler_panics_total{route="/action"} 1"#)
+        );
+    }
+
+    #[test]
+    fn test_timer_records_on_drop() {
+        let metrics = Arc::new(Metrics::default());
+        drop(metrics.timer("db", "get_group_buy"));
+
+        let summary = metrics.summary();
+        assert_eq!(summary.len(), 1);
+        assert_eq!(summary[0].kind, "db");
+        assert_eq!(summary[0].name, "get_group_buy");
+        assert!(Metrics::default().summary().is_empty());
+    }
+
+    #[test]
+    fn test_status_counters() {
+        let metrics = Metrics::default();
+        assert_eq!(metrics.error_count(), 0);
+        assert!(metrics.websocket_uptime().is_none());
+        assert!(metrics.last_source_refresh().is_none());
+
+        metrics.record_error();
+        metrics.record_error();
+        assert_eq!(metrics.error_count(), 2);
+        assert!(
+            metrics
+                .render_prometheus()
+                .contains("leko_errors_total 2\n")
+        );
+
+        metrics.set_websocket_connected(true);
+        assert!(metrics.websocket_uptime().is_some());
+        metrics.set_websocket_connected(false);
+        assert!(metrics.websocket_uptime().is_none());
+
+        metrics.record_source_refresh();
+        assert!(metrics.last_source_refresh().is_some());
+    }
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(
+            normalize_path("/api/v1/admin/group_buys/0b6f7a52-1c1e-4a8e-9f57-2f0c9d1e3a4b/close"),
+            "/api/v1/admin/group_buys/{id}/close"
+        );
+        assert_eq!(
+            normalize_path("/api/v4/users/8xk3n5p9qtrbdfe7ymuw1hzc4a"),
+            "/api/v4/users/{id}"
+        );
+        assert_eq!(
+            normalize_path("/api/v1/group_buy/action/edit_items"),
+            "/api/v1/group_buy/action/edit_items"
+        );
+    }
+}
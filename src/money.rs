@@ -0,0 +1,217 @@
+//! 金額計算
+//!
+//! 金額一律以 Decimal 計算，單價乘數量、依比例計算的費用、進位與分攤都集中在這裡，
+//! 其他模組不直接做 Decimal 運算。
+//!
+//! 結算到個人的金額（個人小計、服務費、應付）依部署設定的 `group_buy.rounding` 進位，
+//! 讓 bot 算出的金額與實際收錢的方式一致；付給商家的商品金額不進位。進位設定由呼叫端從
+//! `config.group_buy.rounding` 傳入。分攤（`split`）保證各份加總等於原本的金額。
+
+use crate::config::{RoundingConfig, RoundingMode};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::{Decimal, RoundingStrategy};
+
+/// 依部署設定的進位方式進位個人應付的金額
+pub fn round(amount: Decimal, config: &RoundingConfig) -> Decimal {
+    let strategy = match config.mode {
+        RoundingMode::Bankers => RoundingStrategy::MidpointNearestEven,
+        RoundingMode::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+        RoundingMode::Ceil => RoundingStrategy::ToPositiveInfinity,
+        RoundingMode::Floor => RoundingStrategy::ToNegativeInfinity,
+    };
+    amount
+        .round_dp_with_strategy(config.decimal_places, strategy)
+        .normalize()
+}
+
+/// 單價乘以數量，不進位
+pub fn line_total(unit_price: Decimal, quantity: impl Into<Decimal>) -> Decimal {
+    unit_price * quantity.into()
+}
+
+/// 金額的百分比（例如服務費），依部署設定進位
+pub fn percent_of(amount: Decimal, percent: Decimal, rounding: &RoundingConfig) -> Decimal {
+    round(amount * percent / Decimal::ONE_HUNDRED, rounding)
+}
+
+/// 依權重分攤金額，精確到部署設定的小數位數
+pub fn split(total: Decimal, weights: &[Decimal], rounding: &RoundingConfig) -> Vec<Decimal> {
+    split_with(total, weights, rounding.decimal_places)
+}
+
+/// 依權重（不為負）分攤金額，每份是 `decimal_places` 位小數的倍數（金額本身的小數位數
+/// 更多時以金額為準），各份加總一定等於 `total`。
+/// 先依比例無條件捨去，剩下的最小單位依序給被捨去較多（同分時排在前面）的份；
+/// 權重全為 0 時平分
+pub fn split_with(total: Decimal, weights: &[Decimal], decimal_places: u32) -> Vec<Decimal> {
+    if weights.is_empty() {
+        return Vec::new();
+    }
+    let weights: Vec<Decimal> = if weights.iter().all(Decimal::is_zero) {
+        vec![Decimal::ONE; weights.len()]
+    } else {
+        weights.to_vec()
+    };
+    let weight_sum: Decimal = weights.iter().sum();
+    let scale = total.scale().max(decimal_places);
+    let unit = Decimal::new(1, scale);
+
+    let exact: Vec<Decimal> = weights.iter().map(|w| total * w / weight_sum).collect();
+    let mut shares: Vec<Decimal> = exact
+        .iter()
+        .map(|share| share.round_dp_with_strategy(scale, RoundingStrategy::ToZero))
+        .collect();
+
+    // 捨去後不足（金額為負時多出）的最小單位數，最多與份數相同
+    let leftover = total - shares.iter().sum::<Decimal>();
+    let units = (leftover / unit).round().to_i64().unwrap_or(0);
+    let step = if units < 0 { -unit } else { unit };
+    let mut order: Vec<usize> = (0..shares.len()).collect();
+    order.sort_by(|&a, &b| {
+        let remainder = |i: usize| (exact[i] - shares[i]).abs();
+        remainder(b).cmp(&remainder(a)).then(a.cmp(&b))
+    });
+    for i in 0..units.unsigned_abs() as usize {
+        shares[order[i % order.len()]] += step;
+    }
+
+    shares.into_iter().map(|share| share.normalize()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_round_modes() {
+        let config = |mode, decimal_places| RoundingConfig {
+            mode,
+            decimal_places,
+        };
+        let amount = Decimal::new(2125, 2); // 21.25
+
+        assert_eq!(
+            round(amount, &config(RoundingMode::Bankers, 1)),
+            Decimal::new(212, 1)
+        );
+        assert_eq!(
+            round(amount, &config(RoundingMode::HalfUp, 1)),
+            Decimal::new(213, 1)
+        );
+        assert_eq!(
+            round(amount, &config(RoundingMode::Ceil, 0)),
+            Decimal::new(22, 0)
+        );
+        assert_eq!(
+            round(amount, &config(RoundingMode::Floor, 0)),
+            Decimal::new(21, 0)
+        );
+        // 已經是整數時不變，也不補上小數位
+        assert_eq!(
+            round(Decimal::new(90, 0), &config(RoundingMode::Bankers, 2)).to_string(),
+            "90"
+        );
+    }
+
+    #[test]
+    fn test_split_with() {
+        let weights = |values: &[i64]| values.iter().map(|&v| Decimal::from(v)).collect::<Vec<_>>();
+
+        // 10 元分三份：各 3.33，多的 0.01 給第一份
+        assert_eq!(
+            split_with(Decimal::from(10), &weights(&[1, 1, 1]), 2),
+            vec![
+                Decimal::new(334, 2),
+                Decimal::new(333, 2),
+                Decimal::new(333, 2)
+            ]
+        );
+        // 依比例，不足的單位給被捨去較多的份
+        assert_eq!(
+            split_with(Decimal::from(100), &weights(&[1, 2]), 0),
+            vec![Decimal::from(33), Decimal::from(67)]
+        );
+        // 權重全為 0 時平分
+        assert_eq!(
+            split_with(Decimal::from(4), &weights(&[0, 0]), 2),
+            vec![Decimal::from(2), Decimal::from(2)]
+        );
+        assert!(split_with(Decimal::from(4), &[], 2).is_empty());
+    }
+
+    fn amount() -> impl Strategy<Value = Decimal> {
+        (-10_000_000i64..10_000_000, 0u32..=4).prop_map(|(n, scale)| Decimal::new(n, scale))
+    }
+
+    fn weight() -> impl Strategy<Value = Decimal> {
+        (0i64..100_000, 0u32..=2).prop_map(|(n, scale)| Decimal::new(n, scale))
+    }
+
+    proptest! {
+        #[test]
+        fn prop_split_sums_to_total(
+            total in amount(),
+            weights in prop::collection::vec(weight(), 1..20),
+            decimal_places in 0u32..=4,
+        ) {
+            let shares = split_with(total, &weights, decimal_places);
+            prop_assert_eq!(shares.len(), weights.len());
+            prop_assert_eq!(shares.iter().sum::<Decimal>(), total);
+        }
+
+        #[test]
+        fn prop_split_is_close_to_proportional(
+            total in amount(),
+            weights in prop::collection::vec(weight(), 1..20),
+            decimal_places in 0u32..=4,
+        ) {
+            let shares = split_with(total, &weights, decimal_places);
+            let unit = Decimal::new(1, total.scale().max(decimal_places));
+            let weight_sum: Decimal = weights.iter().sum();
+            for (share, weight) in shares.iter().zip(&weights) {
+                let exact = if weight_sum.is_zero() {
+                    total / Decimal::from(weights.len())
+                } else {
+                    total * weight / weight_sum
+                };
+                prop_assert!((*share - exact).abs() < unit);
+                // 每份都是最小單位的倍數
+                prop_assert!((*share % unit).is_zero());
+            }
+        }
+
+        #[test]
+        fn prop_round_with_stays_within_one_unit(
+            value in amount(),
+            mode in prop_oneof![
+                Just(RoundingMode::Bankers),
+                Just(RoundingMode::HalfUp),
+                Just(RoundingMode::Ceil),
+                Just(RoundingMode::Floor),
+            ],
+            decimal_places in 0u32..=4,
+        ) {
+            let config = RoundingConfig { mode, decimal_places };
+            let rounded = round(value, &config);
+            prop_assert!((rounded - value).abs() < Decimal::new(1, decimal_places));
+            prop_assert!(rounded.scale() <= decimal_places);
+            // 進位後再進位不變
+            prop_assert_eq!(round(rounded, &config), rounded);
+            match mode {
+                RoundingMode::Ceil => prop_assert!(rounded >= value),
+                RoundingMode::Floor => prop_assert!(rounded <= value),
+                _ => {}
+            }
+        }
+
+        #[test]
+        fn prop_line_total_matches_repeated_addition(
+            unit_price in amount(),
+            quantity in 0i32..50,
+        ) {
+            let repeated: Decimal = (0..quantity).map(|_| unit_price).sum();
+            prop_assert_eq!(line_total(unit_price, quantity), repeated);
+        }
+    }
+}
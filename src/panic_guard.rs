@@ -0,0 +1,245 @@
+//! Handler panic 防護
+//!
+//! HTTP handler panic 時 warp 只會直接斷線，Mattermost 端看到的是沒有內容的錯誤。
+//! 以 `guard` 包住 handler 的 future：panic 轉成 500 回應、計入 `/metrics`，
+//! 設定 `error_reporting.notify_admins_on_panic` 時另外私訊管理員 backtrace 片段。
+
+use crate::AppState;
+use crate::error_code::ErrorCode;
+use crate::metrics::Metrics;
+use futures_util::FutureExt;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tracing::{error, warn};
+use warp::Reply;
+
+/// 私訊中 backtrace 片段的行數上限
+const BACKTRACE_LINES: usize = 20;
+
+/// 同一路由重複 panic 時，私訊管理員的最短間隔
+const NOTIFY_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+thread_local! {
+    /// panic hook 在 panic 的執行緒記下 backtrace，`catch` 在同一個執行緒取出
+    static LAST_BACKTRACE: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+static LAST_NOTIFIED: LazyLock<Mutex<HashMap<&'static str, Instant>>> =
+    LazyLock::new(Default::default);
+
+/// 安裝 panic hook：保留原本的輸出，另外記下 backtrace 供回報使用
+pub fn install_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let backtrace = std::backtrace::Backtrace::force_capture().to_string();
+        LAST_BACKTRACE.with(|last| *last.borrow_mut() = Some(backtrace));
+        previous(info);
+    }));
+}
+
+/// 捕捉到的 panic
+#[derive(Debug, Clone)]
+pub struct PanicReport {
+    pub route: &'static str,
+    pub message: String,
+    pub backtrace: Option<String>,
+    /// 此路由啟動後累計的 panic 次數（包含這一次）
+    pub count: u64,
+}
+
+impl PanicReport {
+    /// 私訊管理員的內容
+    pub fn admin_message(&self) -> String {
+        let mut message = format!(
+            "### 🚨 Handler panic\n\n- **路由**: `{}`\n- **訊息**: {}\n- **累計次數**: {}",
+            self.route, self.message, self.count
+        );
+        if let Some(backtrace) = &self.backtrace {
+            message.push_str(&format!(
+                "\n\n```\n{}\n```",
+                backtrace_snippet(backtrace, BACKTRACE_LINES)
+            ));
+        }
+        message
+    }
+}
+
+/// 執行 handler 的 future，panic 時回傳 `PanicReport` 並計入統計
+pub async fn catch<F, T>(route: &'static str, metrics: &Metrics, fut: F) -> Result<T, PanicReport>
+where
+    F: Future<Output = T>,
+{
+    match AssertUnwindSafe(fut).catch_unwind().await {
+        Ok(output) => Ok(output),
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "（無法取得 panic 訊息）".to_string());
+            let backtrace = LAST_BACKTRACE.with(|last| last.borrow_mut().take());
+
+            metrics.record_panic(route);
+            // backtrace 片段一併記錄，錯誤回報（Sentry、webhook）也會帶上
+            let snippet = backtrace
+                .as_deref()
+                .map(|b| backtrace_snippet(b, BACKTRACE_LINES))
+                .unwrap_or_default();
+            error!(
+                error_code = ErrorCode::Internal.as_str(),
+                backtrace = %snippet,
+                "處理 {} 時發生 panic: {}", route, message
+            );
+
+            Err(PanicReport {
+                route,
+                message,
+                backtrace,
+                count: metrics.panic_count(route),
+            })
+        }
+    }
+}
+
+/// 包住 handler：panic 時回覆 500，並依設定私訊管理員
+pub async fn guard<F, R>(
+    route: &'static str,
+    state: Arc<RwLock<AppState>>,
+    fut: F,
+) -> Result<warp::reply::Response, warp::Rejection>
+where
+    F: Future<Output = Result<R, warp::Rejection>>,
+    R: Reply,
+{
+    let metrics = state.read().await.metrics.clone();
+    match catch(route, &metrics, fut).await {
+        Ok(result) => result.map(Reply::into_response),
+        Err(report) => {
+            if state
+                .read()
+                .await
+                .config
+                .error_reporting
+                .notify_admins_on_panic
+                && should_notify(route)
+            {
+                tokio::spawn(notify_admins(state, report));
+            }
+            Ok(internal_error_response())
+        }
+    }
+}
+
+pub fn internal_error_response() -> warp::reply::Response {
+    warp::reply::with_status(
+        warp::reply::json(&ErrorCode::Internal.json("伺服器內部錯誤")),
+        warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+    )
+    .into_response()
+}
+
+/// 同一路由在 `NOTIFY_INTERVAL` 內只私訊一次，避免持續 panic 時洗版
+fn should_notify(route: &'static str) -> bool {
+    let mut last_notified = LAST_NOTIFIED.lock().unwrap_or_else(|e| e.into_inner());
+    let now = Instant::now();
+    if last_notified
+        .get(route)
+        .is_some_and(|at| now.duration_since(*at) < NOTIFY_INTERVAL)
+    {
+        return false;
+    }
+    last_notified.insert(route, now);
+    true
+}
+
+/// 私訊所有管理員。`admin` 中以 `@` 開頭的項目以使用者名稱查詢 ID
+async fn notify_admins(state: Arc<RwLock<AppState>>, report: PanicReport) {
+    let state_guard = state.read().await;
+    let client = &state_guard.mattermost_client;
+    let message = report.admin_message();
+
+    for admin in &state_guard.config.admin {
+        let user_id = match admin.strip_prefix('@') {
+            Some(username) => match client.get_user_by_username(username).await {
+                Ok(user) => user.id,
+                Err(e) => {
+                    warn!("查詢管理員 {} 失敗: {}", admin, e);
+                    continue;
+                }
+            },
+            None => admin.clone(),
+        };
+        let result = async {
+            let channel = client
+                .create_direct_channel(&state_guard.bot_user_id, &user_id)
+                .await?;
+            client.create_post_simple(&channel.id, &message, None).await
+        }
+        .await;
+        if let Err(e) = result {
+            warn!("私訊管理員 {} panic 回報失敗: {}", admin, e);
+        }
+    }
+}
+
+/// 從 backtrace 中取出本專案的 frame 開始的片段，略過 panic 機制本身的 frame
+pub fn backtrace_snippet(backtrace: &str, max_lines: usize) -> String {
+    let lines: Vec<&str> = backtrace.lines().collect();
+    let start = lines
+        .iter()
+        .position(|line| line.contains("leko_mattermost_bot::"))
+        .unwrap_or(0);
+    lines[start..]
+        .iter()
+        .take(max_lines)
+        .copied()
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_catch_panic() {
+        let metrics = Metrics::default();
+        let result = catch("/test/ok", &metrics, async { 42 }).await;
+        assert_eq!(result.unwrap(), 42);
+
+        async fn explode() {
+            panic!("炸了");
+        }
+        let report = catch("/test/panic", &metrics, explode()).await.unwrap_err();
+        assert_eq!(report.route, "/test/panic");
+        assert_eq!(report.message, "炸了");
+        assert_eq!(metrics.panic_count("/test/panic"), 1);
+        assert_eq!(report.count, 1);
+        assert!(report.admin_message().contains("`/test/panic`"));
+    }
+
+    #[test]
+    fn test_backtrace_snippet() {
+        let backtrace = "   0: std::backtrace::Backtrace::force_capture\n   \
+                         1: std::panicking::rust_panic_with_hook\n   \
+                         2: leko_mattermost_bot::handlers::sticker::search\n             at ./src/handlers/sticker.rs:10:5\n   \
+                         3: tokio::runtime::task::harness::poll";
+        let snippet = backtrace_snippet(backtrace, 2);
+        assert_eq!(
+            snippet,
+            "   2: leko_mattermost_bot::handlers::sticker::search\n             at ./src/handlers/sticker.rs:10:5"
+        );
+    }
+
+    #[test]
+    fn test_should_notify_throttles_per_route() {
+        assert!(should_notify("/test/throttle"));
+        assert!(!should_notify("/test/throttle"));
+        assert!(should_notify("/test/throttle_other"));
+    }
+}
@@ -0,0 +1,153 @@
+//! 定期背景工作的排程
+//!
+//! 每個工作有名稱、依目前設定決定的執行間隔與執行內容。排程每 [`TICK`] 檢查一次，
+//! 到了間隔的工作各自在背景執行，上一次還沒結束時不會重疊執行。
+//! 多實例部署時只由 leader 執行；間隔每次檢查時從設定讀取，重新載入設定（例如關閉功能）後立即生效。
+
+use crate::AppState;
+use crate::config::Config;
+use crate::leader::Leadership;
+use futures_util::FutureExt;
+use futures_util::future::BoxFuture;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tracing::debug;
+
+/// 檢查是否有工作到期的間隔
+const TICK: Duration = Duration::from_secs(5);
+
+type JobFn = Box<dyn Fn(Arc<RwLock<AppState>>) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// 一個定期工作
+pub struct Job {
+    /// 記錄日誌用的名稱
+    name: &'static str,
+    /// 依目前的設定回傳執行間隔，None 表示停用
+    interval: fn(&Config) -> Option<Duration>,
+    /// 啟動後是否立即執行一次；否則等第一個間隔過後才執行
+    immediate: bool,
+    run: JobFn,
+}
+
+impl Job {
+    /// 建立啟動後立即執行一次的工作
+    pub fn new<F, Fut>(
+        name: &'static str,
+        interval: fn(&Config) -> Option<Duration>,
+        run: F,
+    ) -> Self
+    where
+        F: Fn(Arc<RwLock<AppState>>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        Self {
+            name,
+            interval,
+            immediate: true,
+            run: Box::new(move |state| run(state).boxed()),
+        }
+    }
+
+    /// 啟動後等第一個間隔過後才執行
+    pub fn delayed(mut self) -> Self {
+        self.immediate = false;
+        self
+    }
+
+    /// 依目前的設定回傳執行間隔，None 表示停用
+    pub fn interval(&self, config: &Config) -> Option<Duration> {
+        (self.interval)(config)
+    }
+}
+
+/// 上次執行後是否已超過間隔，從未執行過的工作立即執行
+fn job_due(last_run: Option<Instant>, interval: Duration, now: Instant) -> bool {
+    last_run.is_none_or(|last_run| now.duration_since(last_run) >= interval)
+}
+
+/// 工作結束（包含 panic）時清除執行中的標記
+struct RunningGuard(Arc<AtomicBool>);
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+/// 在背景依各自的間隔執行工作，`leadership` 不是 leader 時暫停
+pub fn spawn_scheduler(
+    state: Arc<RwLock<AppState>>,
+    leadership: Leadership,
+    jobs: Vec<Job>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let started = Instant::now();
+        let mut last_runs: Vec<Option<Instant>> = jobs
+            .iter()
+            .map(|job| (!job.immediate).then_some(started))
+            .collect();
+        let running: Vec<Arc<AtomicBool>> = jobs.iter().map(|_| Default::default()).collect();
+
+        let mut ticker = tokio::time::interval(TICK);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            ticker.tick().await;
+            if !leadership.is_leader() {
+                continue;
+            }
+
+            let intervals: Vec<Option<Duration>> = {
+                let state_guard = state.read().await;
+                jobs.iter()
+                    .map(|job| job.interval(&state_guard.config))
+                    .collect()
+            };
+
+            let now = Instant::now();
+            for (index, job) in jobs.iter().enumerate() {
+                let Some(interval) = intervals[index] else {
+                    continue;
+                };
+                if !job_due(last_runs[index], interval, now) {
+                    continue;
+                }
+                if running[index].swap(true, Ordering::AcqRel) {
+                    debug!("背景工作「{}」上一次還沒結束，略過", job.name);
+                    continue;
+                }
+                last_runs[index] = Some(now);
+                let guard = RunningGuard(running[index].clone());
+                let task = (job.run)(state.clone());
+                tokio::spawn(async move {
+                    let _guard = guard;
+                    task.await;
+                });
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_due() {
+        let now = Instant::now();
+        let interval = Duration::from_secs(60);
+        assert!(job_due(None, interval, now));
+        assert!(!job_due(Some(now), interval, now));
+        assert!(!job_due(Some(now), interval, now + Duration::from_secs(59)));
+        assert!(job_due(Some(now), interval, now + interval));
+    }
+
+    #[test]
+    fn test_running_guard_clears_on_drop() {
+        let running = Arc::new(AtomicBool::new(true));
+        drop(RunningGuard(running.clone()));
+        assert!(!running.load(Ordering::Acquire));
+    }
+}
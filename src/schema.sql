@@ -8,7 +8,12 @@ CREATE TABLE IF NOT EXISTS group_buys (
     description TEXT,
     metadata TEXT,
     items TEXT NOT NULL,
-    status TEXT NOT NULL CHECK(status IN ('active', 'closed')),
+    item_icons TEXT,
+    item_sections TEXT,
+    item_translations TEXT,
+    order_fields TEXT,
+    currency TEXT,
+    status TEXT NOT NULL CHECK(status IN ('draft', 'active', 'closed', 'ordered', 'archived')),
     version INTEGER NOT NULL DEFAULT 1,
     created_at TEXT NOT NULL,
     updated_at TEXT NOT NULL
@@ -25,7 +30,10 @@ CREATE TABLE IF NOT EXISTS group_buy_orders (
     quantity INTEGER NOT NULL,
     original_quantity INTEGER,
     unit_price TEXT NOT NULL,
+    custom_fields TEXT,
     created_at TEXT NOT NULL,
+    deleted_at TEXT,
+    deleted_batch TEXT,
     FOREIGN KEY (group_buy_id) REFERENCES group_buys(id) ON DELETE CASCADE
 );
 
@@ -37,6 +45,8 @@ CREATE TABLE IF NOT EXISTS group_buy_logs (
     action TEXT NOT NULL,
     details TEXT,
     created_at TEXT NOT NULL,
+    -- details 為 JSON 物件，常用欄位以 generated column 建立索引（索引在資料遷移 v3 建立）
+    details_version INTEGER GENERATED ALWAYS AS (json_extract(details, '$.version')) VIRTUAL,
     FOREIGN KEY (group_buy_id) REFERENCES group_buys(id) ON DELETE CASCADE
 );
 
@@ -59,6 +69,99 @@ CREATE TABLE IF NOT EXISTS shortage_adjustments (
 CREATE INDEX IF NOT EXISTS idx_orders_group_buy_id ON group_buy_orders(group_buy_id);
 CREATE INDEX IF NOT EXISTS idx_orders_buyer_id ON group_buy_orders(buyer_id);
 CREATE INDEX IF NOT EXISTS idx_logs_group_buy_id ON group_buy_logs(group_buy_id);
+CREATE INDEX IF NOT EXISTS idx_group_buys_post_id ON group_buys(post_id);
+
+-- Automatic close time of a group buy (flash group buys). A background task closes
+-- group buys whose close_at has passed and then removes the row.
+CREATE TABLE IF NOT EXISTS group_buy_deadlines (
+    group_buy_id TEXT PRIMARY KEY,
+    close_at TEXT NOT NULL,
+    FOREIGN KEY (group_buy_id) REFERENCES group_buys(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_group_buy_deadlines_close_at ON group_buy_deadlines(close_at);
+
+-- Pending "closing soon" reminder of a group buy with a deadline. The row is removed
+-- once the reminder has been posted, so reminders survive a restart but are sent once.
+CREATE TABLE IF NOT EXISTS group_buy_reminders (
+    group_buy_id TEXT PRIMARY KEY,
+    remind_at TEXT NOT NULL,
+    FOREIGN KEY (group_buy_id) REFERENCES group_buys(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_group_buy_reminders_remind_at ON group_buy_reminders(remind_at);
+
+-- Buyers the creator has marked as paid after a group buy closed. Buyers without a
+-- row are unpaid, so unmarking a buyer deletes the row.
+CREATE TABLE IF NOT EXISTS payments (
+    group_buy_id TEXT NOT NULL,
+    buyer_id TEXT NOT NULL,
+    marked_by TEXT NOT NULL,
+    paid_at TEXT NOT NULL,
+    PRIMARY KEY (group_buy_id, buyer_id),
+    FOREIGN KEY (group_buy_id) REFERENCES group_buys(id) ON DELETE CASCADE
+);
+
+-- Mattermost accounts found deactivated or deleted. Their names are rendered without an
+-- @-mention until the account is reactivated.
+CREATE TABLE IF NOT EXISTS deactivated_users (
+    user_id TEXT PRIMARY KEY,
+    detected_at TEXT NOT NULL
+);
+
+-- Copies of a group buy post in other channels, refreshed together with the original
+-- post (group_buys.post_id). post_id is NULL while the group buy is still a draft: the
+-- copy is posted when the draft is published
+CREATE TABLE IF NOT EXISTS group_buy_posts (
+    group_buy_id TEXT NOT NULL,
+    channel_id TEXT NOT NULL,
+    post_id TEXT,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (group_buy_id, channel_id),
+    FOREIGN KEY (group_buy_id) REFERENCES group_buys(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_group_buy_posts_post_id ON group_buy_posts(post_id);
+
+-- Reusable group buy setups saved with `/leko group_buy template save <name>`. Names are
+-- unique per channel; metadata, items, item_icons, item_sections and item_translations
+-- are JSON like the matching group_buys columns
+CREATE TABLE IF NOT EXISTS group_buy_templates (
+    channel_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    merchant_name TEXT NOT NULL,
+    description TEXT,
+    metadata TEXT NOT NULL,
+    items TEXT NOT NULL,
+    item_icons TEXT NOT NULL,
+    item_sections TEXT NOT NULL,
+    item_translations TEXT NOT NULL,
+    saved_by TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (channel_id, name)
+);
+
+-- Per-channel group buy defaults edited with /leko settings. Empty columns fall back
+-- to the deployment defaults and allowed_creators is a JSON array of usernames
+CREATE TABLE IF NOT EXISTS channel_settings (
+    channel_id TEXT PRIMARY KEY,
+    currency TEXT,
+    quiet_hours TEXT,
+    default_deadline_minutes INTEGER,
+    allowed_creators TEXT,
+    updated_by TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+-- Per-user defaults (preferred sticker categories, favorite item per merchant)
+-- used to pre-fill dialogs and order sticker search results
+CREATE TABLE IF NOT EXISTS user_preferences (
+    user_id TEXT NOT NULL,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (user_id, key)
+);
 
 -- Stickers table: store sticker metadata to avoid loading all stickers into memory
 CREATE TABLE IF NOT EXISTS stickers (
@@ -73,6 +176,141 @@ CREATE TABLE IF NOT EXISTS stickers (
 CREATE INDEX IF NOT EXISTS idx_stickers_category ON stickers(category);
 CREATE INDEX IF NOT EXISTS idx_stickers_url_hash ON stickers(url_hash);
 
+-- Old sticker hashes (e.g. from the previous DefaultHasher-based algorithm) mapped to the
+-- current url_hash, so references saved with an old hash keep resolving.
+CREATE TABLE IF NOT EXISTS sticker_hash_aliases (
+    old_hash TEXT PRIMARY KEY,
+    url_hash TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+-- Stickers added by admins through DM commands. They are not in any config source, so
+-- replacing the stickers table from config copies them back in.
+CREATE TABLE IF NOT EXISTS custom_stickers (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    image_url TEXT NOT NULL UNIQUE,
+    category TEXT NOT NULL,
+    url_hash TEXT NOT NULL,
+    added_by TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+-- HTTP sticker sources: validators from the last successful fetch, the body (so a 304 can be
+-- parsed again) and the outcome of the last attempt, shown by the admin `sticker` DM command.
+CREATE TABLE IF NOT EXISTS sticker_sources (
+    category TEXT NOT NULL,
+    url TEXT NOT NULL,
+    etag TEXT,
+    last_modified TEXT,
+    content TEXT,
+    status TEXT NOT NULL,
+    sticker_count INTEGER NOT NULL DEFAULT 0,
+    error TEXT,
+    checked_at TEXT NOT NULL,
+    changed_at TEXT,
+    PRIMARY KEY (category, url)
+);
+
+-- Fingerprint of the sticker list last written from config sources. A reload that yields the
+-- same list leaves the stickers table untouched; editing stickers from DM clears it.
+CREATE TABLE IF NOT EXISTS sticker_snapshot (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    fingerprint TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+-- One row per sticker sent, used for the weekly trending list
+CREATE TABLE IF NOT EXISTS sticker_usage (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    image_url TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    used_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_sticker_usage_used_at ON sticker_usage(used_at);
+CREATE INDEX IF NOT EXISTS idx_sticker_usage_user ON sticker_usage(user_id, used_at);
+
+-- Sticker picker messages that have not been sent or cancelled yet.
+-- post_id is filled on the first interaction, or looked up from the channel by the picker marker.
+CREATE TABLE IF NOT EXISTS sticker_pickers (
+    id TEXT PRIMARY KEY,
+    channel_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    post_id TEXT,
+    created_at TEXT NOT NULL,
+    last_active_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_sticker_pickers_last_active ON sticker_pickers(last_active_at);
+
+-- Stickers a user marked as favorite from the preview panel
+CREATE TABLE IF NOT EXISTS user_favorites (
+    user_id TEXT NOT NULL,
+    image_url TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (user_id, image_url)
+);
+
+-- Scoped tokens for the admin REST API. Only the SHA-256 hash of a token is stored.
+CREATE TABLE IF NOT EXISTS api_tokens (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    scope TEXT NOT NULL CHECK(scope IN ('read-only', 'sticker-admin', 'gb-admin')),
+    created_by TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    last_used_at TEXT,
+    revoked_at TEXT
+);
+
+-- Per-token request log for the admin REST API
+CREATE TABLE IF NOT EXISTS api_token_logs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    token_id TEXT NOT NULL,
+    method TEXT NOT NULL,
+    path TEXT NOT NULL,
+    status INTEGER NOT NULL,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (token_id) REFERENCES api_tokens(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_api_token_logs_token_id ON api_token_logs(token_id);
+
+-- Nonces of one-time Interactive Message contexts (e.g. sticker send buttons).
+-- Kept in the database so every bot instance rejects a replay.
+CREATE TABLE IF NOT EXISTS used_nonces (
+    nonce TEXT PRIMARY KEY,
+    expires_at INTEGER NOT NULL
+);
+
+-- Leases that elect one bot instance to run background jobs (auto-close, orphan checks)
+CREATE TABLE IF NOT EXISTS leader_leases (
+    name TEXT PRIMARY KEY,
+    holder TEXT NOT NULL,
+    expires_at INTEGER NOT NULL
+);
+
+-- RSS/Atom feeds read by the feed watcher: validators from the last successful fetch and the
+-- outcome of the last attempt. A feed that never succeeded marks its current items as seen
+-- without posting them.
+CREATE TABLE IF NOT EXISTS feed_state (
+    name TEXT PRIMARY KEY,
+    etag TEXT,
+    last_modified TEXT,
+    error TEXT,
+    checked_at TEXT NOT NULL,
+    succeeded_at TEXT
+);
+
+-- Feed items already posted (or skipped), so each item is posted at most once
+CREATE TABLE IF NOT EXISTS feed_items (
+    feed TEXT NOT NULL,
+    item_id TEXT NOT NULL,
+    seen_at TEXT NOT NULL,
+    PRIMARY KEY (feed, item_id)
+);
+
 -- FTS5 virtual table for sticker name search. We populate this manually when inserting/replacing
 -- stickers. We store ngram-like tokens in `name_ngrams` to improve Chinese search support.
 -- (FTS removed) If full-text features are needed later, consider adding an FTS table
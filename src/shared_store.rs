@@ -0,0 +1,69 @@
+//! 一次性 context nonce 的存放位置
+//!
+//! 「發送」「重試」這類只能成功一次的按鈕，以 context 的 nonce 當作冪等鍵：第一次寫入成功才執行。
+//! 依 `shared_store.backend` 記在資料庫的 `used_nonces`（預設，共用同一個資料庫檔案的實例彼此可見），
+//! 或只記在本機記憶體（單一實例）。多實例部署本來就必須共用資料庫（leader lease 與團購資料都在裡面），
+//! 不另外提供其他的共用存放位置。
+
+use crate::config::{SharedStoreBackend, SharedStoreConfig};
+use crate::database::Database;
+use anyhow::Result;
+use chrono::Utc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// 已使用的 nonce 存放的位置
+#[derive(Debug, Clone)]
+pub enum NonceStore {
+    Database(Database),
+    /// nonce → 到期時間（Unix 秒）
+    Memory(Arc<Mutex<HashMap<String, i64>>>),
+}
+
+impl NonceStore {
+    /// 依配置建立
+    pub fn from_config(config: &SharedStoreConfig, database: &Database) -> Self {
+        match config.backend {
+            SharedStoreBackend::Database => NonceStore::Database(database.clone()),
+            SharedStoreBackend::Memory => NonceStore::memory(),
+        }
+    }
+
+    pub fn memory() -> Self {
+        NonceStore::Memory(Arc::new(Mutex::new(HashMap::new())))
+    }
+
+    /// 將 nonce 標記為已使用，第一次使用時回傳 `true`。`expires_at` 之後可以清除
+    pub async fn consume(&self, nonce: &str, expires_at: i64) -> Result<bool> {
+        match self {
+            NonceStore::Database(database) => database.consume_nonce(nonce, expires_at).await,
+            NonceStore::Memory(used) => {
+                let now = Utc::now().timestamp();
+                let mut used = used.lock().unwrap_or_else(|e| e.into_inner());
+                used.retain(|_, exp| *exp >= now);
+                if used.contains_key(nonce) {
+                    return Ok(false);
+                }
+                used.insert(nonce.to_string(), expires_at);
+                Ok(true)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_consume_once_until_expired() {
+        let store = NonceStore::memory();
+        let now = Utc::now().timestamp();
+
+        assert!(store.consume("n1", now + 60).await.unwrap());
+        assert!(!store.consume("n1", now + 60).await.unwrap());
+        // 過期的 nonce 會被清除
+        assert!(store.consume("n2", now - 1).await.unwrap());
+        assert!(store.consume("n2", now + 60).await.unwrap());
+    }
+}
@@ -0,0 +1,301 @@
+use crate::shared_store::NonceStore;
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const SIG_KEY: &str = "_sig";
+const NONCE_KEY: &str = "_nonce";
+const EXP_KEY: &str = "_exp";
+
+/// 不列入簽章的 context 欄位：簽章本身，以及 Mattermost 在下拉選單回呼時寫入的選擇值
+const UNSIGNED_KEYS: &[&str] = &[SIG_KEY, "selected_option"];
+
+/// Interactive Message context 驗證失敗的原因
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum ContextError {
+    #[error("context 未簽章")]
+    Unsigned,
+    #[error("context 簽章驗證失敗")]
+    Invalid,
+    #[error("context 已過期")]
+    Expired,
+    #[error("context 已使用過")]
+    Replayed,
+    #[error("無法確認 context 是否使用過")]
+    Unavailable,
+}
+
+/// 以 HMAC-SHA256 簽署經由客戶端來回傳遞的資料（dialog state 與按鈕 context）。
+/// dialog state 簽章後格式為 `<hex 簽章>.<原始內容>`，驗證失敗代表內容遭到竄改。
+/// 本身不保存狀態：使用相同金鑰的 bot 實例都能驗證彼此簽出的內容。
+#[derive(Clone)]
+pub struct StateSigner {
+    key: Vec<u8>,
+}
+
+impl std::fmt::Debug for StateSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StateSigner").finish_non_exhaustive()
+    }
+}
+
+impl StateSigner {
+    pub fn new(secret: &str) -> Self {
+        Self {
+            key: secret.as_bytes().to_vec(),
+        }
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        HmacSha256::new_from_slice(&self.key).expect("HMAC 可接受任意長度的 key")
+    }
+
+    /// 簽署內容，回傳 `<簽章>.<內容>`
+    pub fn sign(&self, payload: &str) -> String {
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        let sig = hex::encode(mac.finalize().into_bytes());
+        format!("{}.{}", sig, payload)
+    }
+
+    /// 驗證簽章並回傳原始內容
+    pub fn verify<'a>(&self, signed: &'a str) -> Result<&'a str> {
+        let (sig, payload) = signed
+            .split_once('.')
+            .ok_or_else(|| anyhow::anyhow!("缺少簽章"))?;
+        let sig = hex::decode(sig).map_err(|_| anyhow::anyhow!("簽章格式錯誤"))?;
+
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        mac.verify_slice(&sig)
+            .map_err(|_| anyhow::anyhow!("簽章驗證失敗"))?;
+
+        Ok(payload)
+    }
+
+    /// 參與簽章的 context 內容（serde_json 的 Map 依 key 排序，序列化結果固定）
+    fn context_mac(&self, context: &serde_json::Map<String, serde_json::Value>) -> HmacSha256 {
+        let signed: serde_json::Map<String, serde_json::Value> = context
+            .iter()
+            .filter(|(k, _)| !UNSIGNED_KEYS.contains(&k.as_str()))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+
+        let mut mac = self.mac();
+        mac.update(serde_json::Value::Object(signed).to_string().as_bytes());
+        mac
+    }
+
+    /// 簽署 Interactive Message 的 context，加入 nonce 與（可選的）到期時間。
+    /// 長期存在的按鈕（例如團購貼文）可不設定到期時間。
+    pub fn sign_context(
+        &self,
+        mut context: serde_json::Value,
+        ttl: Option<Duration>,
+    ) -> serde_json::Value {
+        if let Some(map) = context.as_object_mut() {
+            map.insert(
+                NONCE_KEY.to_string(),
+                serde_json::json!(uuid::Uuid::new_v4().simple().to_string()),
+            );
+            if let Some(ttl) = ttl {
+                map.insert(
+                    EXP_KEY.to_string(),
+                    serde_json::json!((Utc::now() + ttl).timestamp()),
+                );
+            }
+            let sig = hex::encode(self.context_mac(map).finalize().into_bytes());
+            map.insert(SIG_KEY.to_string(), serde_json::json!(sig));
+        }
+        context
+    }
+
+    /// 驗證 context 的簽章與到期時間
+    pub fn verify_context(&self, context: &serde_json::Value) -> Result<(), ContextError> {
+        let map = context.as_object().ok_or(ContextError::Unsigned)?;
+        let sig = map
+            .get(SIG_KEY)
+            .and_then(|v| v.as_str())
+            .ok_or(ContextError::Unsigned)?;
+        let sig = hex::decode(sig).map_err(|_| ContextError::Invalid)?;
+
+        self.context_mac(map)
+            .verify_slice(&sig)
+            .map_err(|_| ContextError::Invalid)?;
+
+        if let Some(exp) = map.get(EXP_KEY).and_then(|v| v.as_i64())
+            && exp < Utc::now().timestamp()
+        {
+            return Err(ContextError::Expired);
+        }
+
+        Ok(())
+    }
+
+    /// 移除簽章相關欄位（簽章、nonce、到期時間），取回簽署前的 context
+    pub fn unsigned_context(context: &serde_json::Value) -> serde_json::Value {
+        let mut context = context.clone();
+        if let Some(map) = context.as_object_mut() {
+            for key in [SIG_KEY, NONCE_KEY, EXP_KEY] {
+                map.remove(key);
+            }
+        }
+        context
+    }
+
+    /// 驗證 context 並將其 nonce 標記為已使用，同一個 context 只能成功一次。
+    /// 用於發送貼圖這類不應重複執行的操作。nonce 記錄在 `shared_store` 設定的位置，
+    /// 使用資料庫或 Redis 時重放到其他實例也會被拒絕。
+    pub async fn consume_context(
+        &self,
+        context: &serde_json::Value,
+        nonce_store: &NonceStore,
+    ) -> Result<(), ContextError> {
+        self.verify_context(context)?;
+
+        let nonce = context
+            .get(NONCE_KEY)
+            .and_then(|v| v.as_str())
+            .ok_or(ContextError::Invalid)?;
+        // 沒有到期時間的 context 不應作為一次性使用，保守地保留一天
+        let exp = context
+            .get(EXP_KEY)
+            .and_then(|v| v.as_i64())
+            .unwrap_or_else(|| (Utc::now() + Duration::days(1)).timestamp());
+
+        match nonce_store.consume(nonce, exp).await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(ContextError::Replayed),
+            Err(e) => {
+                tracing::error!("記錄 context nonce 失敗: {}", e);
+                Err(ContextError::Unavailable)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::utils::setup_db;
+
+    #[test]
+    fn test_sign_and_verify_roundtrip() {
+        let signer = StateSigner::new("secret");
+        let payload = r#"{"group_buy_id":"abc","version":1}"#;
+
+        let signed = signer.sign(payload);
+        assert_ne!(signed, payload);
+        assert_eq!(signer.verify(&signed).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_verify_rejects_tampering() {
+        let signer = StateSigner::new("secret");
+        let signed = signer.sign(r#"{"group_buy_id":"abc","version":1}"#);
+
+        // 修改內容
+        let tampered = signed.replace("\"version\":1", "\"version\":2");
+        assert!(signer.verify(&tampered).is_err());
+
+        // 未簽章的舊格式
+        assert!(
+            signer
+                .verify(r#"{"group_buy_id":"abc","version":1}"#)
+                .is_err()
+        );
+
+        // 不同 key 簽出的內容
+        let other = StateSigner::new("other").sign(r#"{"group_buy_id":"abc"}"#);
+        assert!(signer.verify(&other).is_err());
+    }
+
+    #[test]
+    fn test_context_signing_ignores_selected_option() {
+        let signer = StateSigner::new("secret");
+        let mut context = signer.sign_context(
+            serde_json::json!({"action": "select_sticker", "user_id": "u1"}),
+            Some(Duration::minutes(5)),
+        );
+        assert_eq!(signer.verify_context(&context), Ok(()));
+
+        // Mattermost 會把下拉選單的選擇寫回 context
+        context["selected_option"] = serde_json::json!("3");
+        assert_eq!(signer.verify_context(&context), Ok(()));
+
+        // 冒充其他使用者
+        context["user_id"] = serde_json::json!("u2");
+        assert_eq!(signer.verify_context(&context), Err(ContextError::Invalid));
+    }
+
+    #[test]
+    fn test_context_expiry_and_unsigned() {
+        let signer = StateSigner::new("secret");
+
+        let expired = signer.sign_context(
+            serde_json::json!({"action": "send_sticker"}),
+            Some(Duration::seconds(-1)),
+        );
+        assert_eq!(signer.verify_context(&expired), Err(ContextError::Expired));
+
+        let unsigned = serde_json::json!({"action": "send_sticker"});
+        assert_eq!(
+            signer.verify_context(&unsigned),
+            Err(ContextError::Unsigned)
+        );
+
+        // 沒有到期時間的 context 永久有效
+        let permanent = signer.sign_context(serde_json::json!({"group_buy_id": "gb"}), None);
+        assert_eq!(signer.verify_context(&permanent), Ok(()));
+    }
+
+    #[test]
+    fn test_unsigned_context_roundtrip() {
+        let signer = StateSigner::new("secret");
+        let original = serde_json::json!({"action": "close", "group_buy_id": "gb"});
+        let signed = signer.sign_context(original.clone(), Some(Duration::minutes(5)));
+
+        let unsigned = StateSigner::unsigned_context(&signed);
+        assert_eq!(unsigned, original);
+        // 重新簽署後可以再次通過驗證
+        assert_eq!(
+            signer.verify_context(&signer.sign_context(unsigned, None)),
+            Ok(())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_consume_context_rejects_replay() {
+        let db = NonceStore::Database(setup_db().await);
+        let signer = StateSigner::new("secret");
+        let context = signer.sign_context(
+            serde_json::json!({"action": "send_sticker", "sticker_image_url": "https://example.com/a.png"}),
+            Some(Duration::minutes(5)),
+        );
+
+        assert_eq!(signer.consume_context(&context, &db).await, Ok(()));
+        assert_eq!(
+            signer.consume_context(&context, &db).await,
+            Err(ContextError::Replayed)
+        );
+
+        // 其他實例（相同金鑰、共用資料庫）也會拒絕重放
+        assert_eq!(
+            StateSigner::new("secret")
+                .consume_context(&context, &db)
+                .await,
+            Err(ContextError::Replayed)
+        );
+
+        // 記在本機記憶體時同一個實例也只接受一次
+        let memory = NonceStore::memory();
+        assert_eq!(signer.consume_context(&context, &memory).await, Ok(()));
+        assert_eq!(
+            signer.consume_context(&context, &memory).await,
+            Err(ContextError::Replayed)
+        );
+    }
+}
@@ -0,0 +1,208 @@
+//! Slash command 回應期限
+//!
+//! Mattermost 只等 slash command 約 3 秒，超過就顯示 "command timed out"。
+//! `guard` 把 handler 放到背景執行：期限內完成就直接回傳結果；超過期限先回覆
+//! 「處理中…」臨時訊息，handler 完成後再把它的回應送到 `response_url`。
+
+use crate::AppState;
+use crate::mattermost::MattermostClient;
+use http_body_util::BodyExt;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{Instrument, error, warn};
+use warp::Reply;
+
+/// 回覆 Mattermost 的期限，保留一點網路傳輸的餘裕
+const SLASH_RESPONSE_DEADLINE: Duration = Duration::from_millis(2500);
+
+/// 超過期限時先回覆的訊息
+const PROCESSING_TEXT: &str = "處理中…";
+
+/// 背景處理失敗時送到 `response_url` 的訊息
+const FAILED_TEXT: &str = "⚠️ 處理指令時發生錯誤，請稍後再試";
+
+/// 以 panic 防護與回應期限包住 slash command handler
+pub async fn guard<H, Fut, R>(
+    route: &'static str,
+    state: Arc<RwLock<AppState>>,
+    form: HashMap<String, String>,
+    handler: H,
+) -> Result<warp::reply::Response, warp::Rejection>
+where
+    H: FnOnce(HashMap<String, String>, Arc<RwLock<AppState>>) -> Fut,
+    Fut: Future<Output = Result<R, warp::Rejection>> + Send + 'static,
+    R: Reply + 'static,
+{
+    let response_url = form.get("response_url").cloned().unwrap_or_default();
+    let client = state.read().await.mattermost_client.clone();
+    let fut = crate::panic_guard::guard(route, state.clone(), handler(form, state));
+    within(route, SLASH_RESPONSE_DEADLINE, fut, response_url, client).await
+}
+
+/// 在背景執行 `fut`，超過 `deadline` 時先回覆「處理中…」，完成後改由 `response_url` 回覆。
+/// 沒有 `response_url` 時無法事後回覆，只能繼續等 handler 完成
+async fn within<F>(
+    route: &'static str,
+    deadline: Duration,
+    fut: F,
+    response_url: String,
+    client: MattermostClient,
+) -> Result<warp::reply::Response, warp::Rejection>
+where
+    F: Future<Output = Result<warp::reply::Response, warp::Rejection>> + Send + 'static,
+{
+    // 背景工作沿用請求的 span，錯誤回報才帶得到 request_id
+    let mut task = tokio::spawn(fut.in_current_span());
+    match tokio::time::timeout(deadline, &mut task).await {
+        Ok(joined) => joined.unwrap_or_else(|e| {
+            error!("{} 的背景工作中止: {}", route, e);
+            Ok(crate::panic_guard::internal_error_response())
+        }),
+        Err(_) if response_url.is_empty() => {
+            warn!("{} 超過回應期限且沒有 response_url，繼續等待", route);
+            task.await.unwrap_or_else(|e| {
+                error!("{} 的背景工作中止: {}", route, e);
+                Ok(crate::panic_guard::internal_error_response())
+            })
+        }
+        Err(_) => {
+            warn!(
+                "{} 超過 {} 毫秒仍未完成，先回覆處理中",
+                route,
+                deadline.as_millis()
+            );
+            tokio::spawn(
+                async move {
+                    let payload = match task.await {
+                        Ok(Ok(response)) => late_payload(response).await,
+                        Ok(Err(rejection)) => {
+                            warn!("{} 在背景處理時被拒絕: {:?}", route, rejection);
+                            Some(ephemeral(FAILED_TEXT))
+                        }
+                        Err(e) => {
+                            error!("{} 的背景工作中止: {}", route, e);
+                            Some(ephemeral(FAILED_TEXT))
+                        }
+                    };
+                    if let Some(payload) = payload
+                        && let Err(e) = client.post_to_response_url(&response_url, &payload).await
+                    {
+                        error!("{} 的延遲回應發送失敗: {}", route, e);
+                    }
+                }
+                .in_current_span(),
+            );
+            Ok(warp::reply::json(&ephemeral(PROCESSING_TEXT)).into_response())
+        }
+    }
+}
+
+fn ephemeral(text: &str) -> serde_json::Value {
+    serde_json::json!({
+        "response_type": "ephemeral",
+        "text": text
+    })
+}
+
+/// handler 的回應轉成 `response_url` 的 payload。
+/// 空物件表示 handler 已自行透過 `response_url` 或 API 回覆，不需要再送
+async fn late_payload(response: warp::reply::Response) -> Option<serde_json::Value> {
+    if !response.status().is_success() {
+        return Some(ephemeral(FAILED_TEXT));
+    }
+    let body = match response.into_body().collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(e) => {
+            error!("讀取延遲回應失敗: {}", e);
+            return Some(ephemeral(FAILED_TEXT));
+        }
+    };
+    let payload: serde_json::Value = serde_json::from_slice(&body).ok()?;
+    if payload.as_object().is_some_and(|o| o.is_empty()) {
+        None
+    } else {
+        Some(payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(url: &str) -> MattermostClient {
+        MattermostClient::new(url.to_string(), "test_token".to_string()).unwrap()
+    }
+
+    async fn reply(
+        text: &'static str,
+        delay: Duration,
+    ) -> Result<warp::reply::Response, warp::Rejection> {
+        tokio::time::sleep(delay).await;
+        Ok(warp::reply::json(&ephemeral(text)).into_response())
+    }
+
+    #[tokio::test]
+    async fn test_fast_handler_replies_directly() {
+        let response = within(
+            "/test/fast",
+            Duration::from_secs(1),
+            reply("完成", Duration::ZERO),
+            "http://127.0.0.1:1/hook".to_string(),
+            client("http://127.0.0.1:1"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(late_payload(response).await.unwrap()["text"], "完成");
+    }
+
+    #[tokio::test]
+    async fn test_slow_handler_continues_via_response_url() {
+        let mut server = mockito::Server::new_async().await;
+        let hook = server
+            .mock("POST", "/hook")
+            .match_body(mockito::Matcher::PartialJson(
+                serde_json::json!({"text": "完成"}),
+            ))
+            .with_status(200)
+            .create_async()
+            .await;
+
+        let response = within(
+            "/test/slow",
+            Duration::from_millis(10),
+            reply("完成", Duration::from_millis(200)),
+            format!("{}/hook", server.url()),
+            client(&server.url()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            late_payload(response).await.unwrap()["text"],
+            PROCESSING_TEXT
+        );
+
+        for _ in 0..50 {
+            if hook.matched_async().await {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        hook.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_empty_reply_is_not_forwarded() {
+        let response = warp::reply::json(&serde_json::json!({})).into_response();
+        assert!(late_payload(response).await.is_none());
+
+        let failed = warp::reply::with_status(
+            warp::reply::json(&serde_json::json!({})),
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        )
+        .into_response();
+        assert_eq!(late_payload(failed).await.unwrap()["text"], FAILED_TEXT);
+    }
+}
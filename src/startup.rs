@@ -0,0 +1,491 @@
+//! 啟動摘要
+//!
+//! 啟動時列出主要設定（啟用的功能、callback URL、貼圖來源、資料庫、token），
+//! 並對常見的設定錯誤提出警告。這些錯誤原本要等到有人按下按鈕或開啟對話框才會發現。
+//! callback URL 另外在啟動後呼叫 `<callback>/health` 確認真的連得到本實例。
+
+use crate::config::{Config, Feature, MattermostConfig, SharedStoreBackend};
+use anyhow::Result;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// 自我檢查 callback URL 時等待 HTTP 伺服器啟動的重試次數與間隔
+const VERIFY_ATTEMPTS: u32 = 5;
+const VERIFY_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 設定摘要，每行一個項目
+pub fn summary(config: &Config) -> Vec<String> {
+    let (enabled, disabled): (Vec<Feature>, Vec<Feature>) = Feature::ALL
+        .into_iter()
+        .partition(|f| config.features.is_enabled(*f));
+    let names = |features: &[Feature]| {
+        if features.is_empty() {
+            "（無）".to_string()
+        } else {
+            features
+                .iter()
+                .map(|f| f.name())
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+    };
+    let source_count: usize = config
+        .stickers
+        .categories
+        .iter()
+        .map(|c| c.sources.len())
+        .sum();
+    let tokens = &config.mattermost.slash_command_tokens;
+    let presence = |token: &Option<String>| if token.is_some() { "✓" } else { "✗" };
+
+    vec![
+        format!("啟用的功能: {}", names(&enabled)),
+        format!("停用的功能: {}", names(&disabled)),
+        format!("Mattermost URL: {}", config.mattermost.url),
+        format!(
+            "Callback URL: {}",
+            config
+                .mattermost
+                .bot_callback_url
+                .as_deref()
+                .unwrap_or("（未設定）")
+        ),
+        format!(
+            "貼圖來源: {} 個分類、{} 個來源",
+            config.stickers.categories.len(),
+            source_count
+        ),
+        format!("資料庫: {}", config.database_url),
+        format!("Webhook: {} 個", config.webhooks.len()),
+        format!("RSS 訂閱: {} 個", config.feeds.len()),
+        format!(
+            "一次性按鈕的 nonce: {}",
+            match config.shared_store.backend {
+                SharedStoreBackend::Database => "資料庫",
+                SharedStoreBackend::Memory => "本機記憶體",
+            }
+        ),
+        format!(
+            "Bot token: {}",
+            if config.mattermost.bot_token.is_empty() {
+                "（未設定）".to_string()
+            } else {
+                format!("已設定（{} 字元）", config.mattermost.bot_token.len())
+            }
+        ),
+        format!(
+            "簽章金鑰: {}",
+            if config.mattermost.signing_secret.is_some() {
+                "已設定"
+            } else {
+                "使用 bot token"
+            }
+        ),
+        format!(
+            "Slash command token: group_buy {}、leko {}、stickers {}",
+            presence(&tokens.group_buy),
+            presence(&tokens.leko),
+            presence(&tokens.stickers)
+        ),
+    ]
+}
+
+/// 常見設定錯誤的警告
+pub fn warnings(config: &Config) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let mattermost = &config.mattermost;
+
+    match mattermost.bot_callback_url.as_deref().map(url::Url::parse) {
+        // 由 check_callback_url 確認已設定 callback_host_header
+        None if mattermost.callback_host_header.is_some() => {}
+        None => warnings.push(
+            "未設定 mattermost.bot_callback_url：按鈕與對話框會回呼 http://localhost:3000，Mattermost 通常無法連到 bot"
+                .to_string(),
+        ),
+        Some(Err(e)) => warnings.push(format!(
+            "mattermost.bot_callback_url 不是有效的 URL: {}",
+            e
+        )),
+        Some(Ok(callback)) => {
+            let mattermost_host = url::Url::parse(&mattermost.url)
+                .ok()
+                .and_then(|u| u.host_str().map(str::to_string));
+            if let Some(callback_host) = callback.host_str()
+                && is_local_host(callback_host)
+                && let Some(mattermost_host) = mattermost_host
+                && !is_local_host(&mattermost_host)
+            {
+                warnings.push(format!(
+                    "mattermost.bot_callback_url 指向本機（{}），但 Mattermost 位於 {}：Mattermost 伺服器無法回呼按鈕與對話框",
+                    callback_host, mattermost_host
+                ));
+            }
+        }
+    }
+
+    let tokens = &mattermost.slash_command_tokens;
+    for (name, token, enabled) in [
+        ("group_buy", &tokens.group_buy, config.features.group_buy),
+        ("leko", &tokens.leko, true),
+        ("stickers", &tokens.stickers, config.features.stickers),
+    ] {
+        if enabled && token.is_none() {
+            warnings.push(format!(
+                "未設定 mattermost.slash_command_tokens.{}，不驗證該 slash command 的請求來源",
+                name
+            ));
+        }
+    }
+
+    if config.features.dm_admin && config.admin.is_empty() {
+        warnings.push("未設定 admin，無法使用私訊管理指令".to_string());
+    }
+
+    if config.database_url.contains(":memory:") {
+        warnings.push("database_url 使用記憶體資料庫，重新啟動後團購資料會遺失".to_string());
+    }
+
+    if config.shared_store.backend == SharedStoreBackend::Memory {
+        warnings.push(
+            "shared_store.backend 為 memory：一次性按鈕的 nonce 只記在本機，多實例部署時無法阻擋重放到其他實例"
+                .to_string(),
+        );
+    }
+
+    if config.features.stickers
+        && config
+            .stickers
+            .categories
+            .iter()
+            .all(|c| c.sources.is_empty())
+    {
+        warnings.push("已啟用貼圖功能，但沒有設定任何貼圖來源".to_string());
+    }
+
+    let stickers = &config.stickers;
+    if stickers.prewarm_count > 0 && stickers.image_cache_dir.is_none() {
+        warnings.push(
+            "已設定 stickers.prewarm_count，但沒有設定 stickers.image_cache_dir，不會預先下載貼圖"
+                .to_string(),
+        );
+    }
+    if stickers.image_cache_dir.is_some() && !stickers.send_as_file {
+        warnings.push(
+            "已設定 stickers.image_cache_dir，但貼圖快取只在 stickers.send_as_file 啟用時使用"
+                .to_string(),
+        );
+    }
+
+    warnings
+}
+
+/// 記錄啟動摘要與警告
+pub fn log_banner(config: &Config) {
+    info!("===== 啟動摘要 =====");
+    for line in summary(config) {
+        info!("{}", line);
+    }
+    for warning in warnings(config) {
+        warn!("設定檢查: {}", warning);
+    }
+}
+
+/// 未設定 `bot_callback_url` 時必須能從請求偵測，否則按鈕與對話框都無法回呼，直接拒絕啟動
+pub fn check_callback_url(mattermost: &MattermostConfig) -> Result<()> {
+    if mattermost.bot_callback_url.is_some() {
+        return Ok(());
+    }
+    let Some(header) = &mattermost.callback_host_header else {
+        anyhow::bail!(
+            "未設定 mattermost.bot_callback_url。\n\
+             按鈕與對話框的回呼需要 bot 的對外位址，請擇一設定：\n\
+             1. mattermost.bot_callback_url：Mattermost 伺服器連到 bot 的 URL，例如 http://bot:3000\n\
+             2. mattermost.callback_host_header（搭配 callback_allowlist）：從 Mattermost 請求的 header 偵測，例如 Host 或 X-Forwarded-Host"
+        );
+    };
+    if mattermost.callback_allowlist.is_empty() {
+        anyhow::bail!(
+            "mattermost.callback_host_header 需要搭配 mattermost.callback_allowlist，\
+             否則任何人都能以偽造的 {} header 改變按鈕的回呼位址",
+            header
+        );
+    }
+    info!(
+        "未設定 bot_callback_url，將從 Mattermost 請求的 {} header 偵測",
+        header
+    );
+    Ok(())
+}
+
+/// 從請求 header 取得 bot 的對外位址。協定取自 `X-Forwarded-Proto`，沒有時為 http。
+pub fn callback_url_from_headers(headers: &warp::http::HeaderMap, header: &str) -> Option<String> {
+    let first = |name: &str| {
+        headers
+            .get(name)?
+            .to_str()
+            .ok()?
+            .split(',')
+            .next()
+            .map(|v| v.trim().to_string())
+    };
+    let host = first(header).filter(|h| !h.is_empty())?;
+    let scheme = match first("x-forwarded-proto").as_deref() {
+        Some("https") => "https",
+        _ => "http",
+    };
+
+    // 只接受單純的 host[:port]，不接受路徑或帳密
+    let url = url::Url::parse(&format!("{}://{}", scheme, host)).ok()?;
+    if url.path() != "/" || !url.username().is_empty() || url.query().is_some() {
+        return None;
+    }
+    Some(url.as_str().trim_end_matches('/').to_string())
+}
+
+/// 呼叫 callback URL 的結果
+#[derive(Debug, PartialEq)]
+pub enum CallbackCheck {
+    /// 連到本實例
+    Reached,
+    /// 連到其他程式（例如負載平衡後的其他實例），附上對方的 instance_id
+    OtherInstance(Option<String>),
+    Failed(String),
+}
+
+/// 呼叫 `<callback>/health`，以 instance_id 確認回應來自本實例
+pub async fn verify_callback_url(callback_url: &str, instance_id: &str) -> CallbackCheck {
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => return CallbackCheck::Failed(e.to_string()),
+    };
+    let url = format!("{}/health", callback_url.trim_end_matches('/'));
+    let response = match client.get(&url).send().await {
+        Ok(response) => response,
+        Err(e) => return CallbackCheck::Failed(e.to_string()),
+    };
+    if !response.status().is_success() {
+        return CallbackCheck::Failed(format!("HTTP {}", response.status()));
+    }
+    let body: serde_json::Value = match response.json().await {
+        Ok(body) => body,
+        Err(e) => return CallbackCheck::Failed(format!("回應不是 JSON: {}", e)),
+    };
+
+    match body["instance_id"].as_str() {
+        Some(id) if id == instance_id => CallbackCheck::Reached,
+        other => CallbackCheck::OtherInstance(other.map(str::to_string)),
+    }
+}
+
+/// 在背景確認 callback URL，HTTP 伺服器可能尚未啟動，失敗時重試幾次
+pub fn spawn_callback_verification(callback_url: String, instance_id: String) {
+    tokio::spawn(async move {
+        let mut result = CallbackCheck::Failed("未執行".to_string());
+        for _ in 0..VERIFY_ATTEMPTS {
+            tokio::time::sleep(VERIFY_RETRY_INTERVAL).await;
+            result = verify_callback_url(&callback_url, &instance_id).await;
+            if !matches!(result, CallbackCheck::Failed(_)) {
+                break;
+            }
+        }
+
+        match result {
+            CallbackCheck::Reached => info!("Callback URL 檢查通過: {}", callback_url),
+            CallbackCheck::OtherInstance(id) => warn!(
+                "Callback URL {} 回應的不是本實例（instance_id: {:?}）。多實例部署時可能由其他實例回應；\
+                 否則請確認 bot_callback_url 指向這個 bot",
+                callback_url, id
+            ),
+            CallbackCheck::Failed(e) => error!(
+                "無法連到 callback URL {}/health: {}。按鈕與對話框的回呼可能會失敗，\
+                 請確認 bot_callback_url 是 Mattermost 伺服器連得到的位址\
+                 （bot 本身連不到自己的對外位址時，可忽略此訊息並從 Mattermost 端確認）",
+                callback_url, e
+            ),
+        }
+    });
+}
+
+fn is_local_host(host: &str) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    host.eq_ignore_ascii_case("localhost")
+        || host
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback() || ip.is_unspecified())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(yaml: &str) -> Config {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_warnings_for_common_misconfigurations() {
+        let config = config(
+            r#"
+mattermost:
+  url: https://chat.example.com
+  bot_token: token
+  bot_callback_url: http://127.0.0.1:3000
+stickers:
+  categories: []
+  prewarm_count: 20
+shared_store:
+  backend: memory
+"#,
+        );
+
+        let warnings = warnings(&config);
+        assert!(warnings.iter().any(|w| w.contains("指向本機（127.0.0.1）")));
+        assert!(
+            warnings
+                .iter()
+                .any(|w| w.contains("slash_command_tokens.group_buy"))
+        );
+        assert!(warnings.iter().any(|w| w.contains("未設定 admin")));
+        assert!(warnings.iter().any(|w| w.contains("記憶體資料庫")));
+        assert!(warnings.iter().any(|w| w.contains("沒有設定任何貼圖來源")));
+        assert!(warnings.iter().any(|w| w.contains("不會預先下載貼圖")));
+        assert!(
+            warnings
+                .iter()
+                .any(|w| w.contains("shared_store.backend 為 memory"))
+        );
+    }
+
+    #[test]
+    fn test_no_warnings_for_complete_config() {
+        let config = config(
+            r#"
+mattermost:
+  url: http://localhost:8065
+  bot_token: token
+  bot_callback_url: http://localhost:3000
+  slash_command_tokens:
+    group_buy: a
+    leko: b
+    stickers: c
+admin: ["@admin"]
+database_url: sqlite://data/bot.db
+stickers:
+  categories:
+    - name: cats
+      sources:
+        - type: file
+          format: csv
+          path: data/cats.csv
+"#,
+        );
+
+        assert_eq!(warnings(&config), Vec::<String>::new());
+
+        let summary = summary(&config);
+        assert!(summary.contains(&"停用的功能: （無）".to_string()));
+        assert!(summary.contains(&"貼圖來源: 1 個分類、1 個來源".to_string()));
+        assert!(
+            summary.contains(&"Slash command token: group_buy ✓、leko ✓、stickers ✓".to_string())
+        );
+    }
+
+    #[test]
+    fn test_check_callback_url_requires_detection_with_allowlist() {
+        let mut config = config(
+            r#"
+mattermost:
+  url: https://chat.example.com
+  bot_token: token
+stickers:
+  categories: []
+"#,
+        );
+        assert!(check_callback_url(&config.mattermost).is_err());
+
+        config.mattermost.callback_host_header = Some("X-Forwarded-Host".to_string());
+        assert!(check_callback_url(&config.mattermost).is_err());
+
+        config.mattermost.callback_allowlist = vec!["10.0.0.0/8".to_string()];
+        assert!(check_callback_url(&config.mattermost).is_ok());
+        assert!(
+            !warnings(&config)
+                .iter()
+                .any(|w| w.contains("bot_callback_url"))
+        );
+    }
+
+    #[test]
+    fn test_callback_url_from_headers() {
+        let mut headers = warp::http::HeaderMap::new();
+        assert_eq!(callback_url_from_headers(&headers, "host"), None);
+
+        headers.insert("host", "bot:3000".parse().unwrap());
+        assert_eq!(
+            callback_url_from_headers(&headers, "host").as_deref(),
+            Some("http://bot:3000")
+        );
+
+        headers.insert(
+            "x-forwarded-host",
+            "bot.example.com, proxy".parse().unwrap(),
+        );
+        headers.insert("x-forwarded-proto", "https".parse().unwrap());
+        assert_eq!(
+            callback_url_from_headers(&headers, "X-Forwarded-Host").as_deref(),
+            Some("https://bot.example.com")
+        );
+
+        headers.insert("x-forwarded-host", "evil.example.com/path".parse().unwrap());
+        assert_eq!(
+            callback_url_from_headers(&headers, "x-forwarded-host"),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_callback_url() {
+        let mut server = mockito::Server::new_async().await;
+        let _health = server
+            .mock("GET", "/health")
+            .with_body(r#"{"status":"ok","instance_id":"abc"}"#)
+            .create_async()
+            .await;
+
+        assert_eq!(
+            verify_callback_url(&server.url(), "abc").await,
+            CallbackCheck::Reached
+        );
+        assert_eq!(
+            verify_callback_url(&server.url(), "other").await,
+            CallbackCheck::OtherInstance(Some("abc".to_string()))
+        );
+        assert!(matches!(
+            verify_callback_url("http://127.0.0.1:1", "abc").await,
+            CallbackCheck::Failed(_)
+        ));
+    }
+
+    #[test]
+    fn test_missing_callback_url_warns() {
+        let config = config(
+            r#"
+mattermost:
+  url: https://chat.example.com
+  bot_token: token
+stickers:
+  categories: []
+"#,
+        );
+
+        assert!(
+            warnings(&config)
+                .iter()
+                .any(|w| w.starts_with("未設定 mattermost.bot_callback_url"))
+        );
+        assert!(summary(&config).contains(&"Callback URL: （未設定）".to_string()));
+    }
+}
@@ -1,4 +1,6 @@
-use crate::database::Database;
+pub mod line;
+
+use crate::database::{Database, StickerSourceRecord, StickerSourceStatus};
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -11,16 +13,21 @@ pub struct Sticker {
     pub category: String,
 }
 
+/// 計算圖片 URL 的穩定 hash（SHA-1 前八碼）
+///
+/// 不使用 `DefaultHasher`：它的輸出不保證跨 Rust 版本一致，升級編譯器後
+/// 已儲存的 hash 會對不上。
+pub fn url_hash(image_url: &str) -> String {
+    use sha1::{Digest, Sha1};
+
+    let digest = Sha1::digest(image_url.as_bytes());
+    digest[..4].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
 impl Sticker {
     /// 取得圖片 URL 的 hash 前八碼
     pub fn get_url_hash(&self) -> String {
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::{Hash, Hasher};
-
-        let mut hasher = DefaultHasher::new();
-        self.image_url.hash(&mut hasher);
-        let hash = hasher.finish();
-        format!("{:08x}", hash as u32)
+        url_hash(&self.image_url)
     }
 
     /// 取得顯示名稱（[分類] 名字 + hash 前八碼）
@@ -36,15 +43,177 @@ impl Sticker {
     // FTS-based tokenization removed: we use simple LIKE-based substring search instead.
 }
 
+/// 貼圖網址是否為 http(s) 網址
+pub fn is_valid_image_url(image_url: &str) -> bool {
+    url::Url::parse(image_url).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+/// 以檔案發送時貼圖圖片的大小上限
+const MAX_STICKER_FILE_BYTES: usize = 10 * 1024 * 1024;
+
+/// 下載的貼圖圖片，用來上傳成 Mattermost 檔案
+#[derive(Debug)]
+pub struct StickerImage {
+    pub filename: String,
+    pub data: Vec<u8>,
+}
+
+/// 下載貼圖圖片。回應不是圖片（例如圖床的錯誤頁面）或超過大小上限時回傳錯誤
+pub async fn download_sticker_image(image_url: &str) -> Result<StickerImage> {
+    let response = reqwest::get(image_url)
+        .await
+        .with_context(|| format!("無法下載貼圖圖片: {}", image_url))?
+        .error_for_status()
+        .with_context(|| format!("無法下載貼圖圖片: {}", image_url))?;
+
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+    if !content_type.is_empty() && !content_type.starts_with("image/") {
+        anyhow::bail!("貼圖網址回傳的不是圖片（{}）: {}", content_type, image_url);
+    }
+    if response
+        .content_length()
+        .is_some_and(|len| len > MAX_STICKER_FILE_BYTES as u64)
+    {
+        anyhow::bail!(
+            "貼圖圖片超過 {} MB: {}",
+            MAX_STICKER_FILE_BYTES >> 20,
+            image_url
+        );
+    }
+
+    let data = response
+        .bytes()
+        .await
+        .with_context(|| format!("無法讀取貼圖圖片: {}", image_url))?;
+    if data.len() > MAX_STICKER_FILE_BYTES {
+        anyhow::bail!(
+            "貼圖圖片超過 {} MB: {}",
+            MAX_STICKER_FILE_BYTES >> 20,
+            image_url
+        );
+    }
+
+    Ok(StickerImage {
+        filename: sticker_filename(image_url, &content_type),
+        data: data.to_vec(),
+    })
+}
+
+/// 以網址最後一段作為檔名，沒有副檔名時依 Content-Type 補上
+fn sticker_filename(image_url: &str, content_type: &str) -> String {
+    let name = url::Url::parse(image_url)
+        .ok()
+        .and_then(|url| {
+            url.path_segments()
+                .and_then(|mut segments| segments.next_back().map(str::to_string))
+        })
+        .map(|segment| {
+            urlencoding::decode(&segment)
+                .map(|s| s.into_owned())
+                .unwrap_or(segment)
+        })
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "sticker".to_string());
+    if name.contains('.') {
+        return name;
+    }
+
+    let extension = match content_type.split(';').next().unwrap_or("").trim() {
+        "image/gif" => "gif",
+        "image/jpeg" => "jpg",
+        "image/webp" => "webp",
+        _ => "png",
+    };
+    format!("{}.{}", name, extension)
+}
+
+/// 分類名稱索引：處理別名與上下層分類
+#[derive(Debug, Clone, Default)]
+pub struct CategoryIndex {
+    /// 小寫名稱或別名 -> 正式分類名稱
+    names: HashMap<String, String>,
+    /// 正式分類名稱 -> 直屬子分類
+    children: HashMap<String, Vec<String>>,
+}
+
+impl CategoryIndex {
+    /// 從配置建立分類索引
+    pub fn from_config(config: &crate::config::StickersConfig) -> Self {
+        let mut index = Self::default();
+
+        for category in &config.categories {
+            index
+                .names
+                .insert(category.name.to_lowercase(), category.name.clone());
+        }
+
+        for category in &config.categories {
+            for alias in &category.aliases {
+                index
+                    .names
+                    .entry(alias.to_lowercase())
+                    .or_insert_with(|| category.name.clone());
+            }
+
+            if let Some(parent) = &category.parent {
+                // 上層分類可以只是分組用途，不一定要有自己的 sources
+                let parent = index
+                    .names
+                    .entry(parent.to_lowercase())
+                    .or_insert_with(|| parent.clone())
+                    .clone();
+                index
+                    .children
+                    .entry(parent)
+                    .or_default()
+                    .push(category.name.clone());
+            }
+        }
+
+        index
+    }
+
+    /// 將分類名稱（或別名）展開為實際分類列表（包含所有子分類）
+    /// 未知的分類回傳 None，呼叫端可退回直接比對分類名稱
+    pub fn resolve(&self, name: &str) -> Option<Vec<String>> {
+        let canonical = self.names.get(&name.trim().to_lowercase())?;
+
+        let mut resolved: Vec<String> = Vec::new();
+        let mut pending = vec![canonical.clone()];
+        while let Some(current) = pending.pop() {
+            // 避免設定錯誤造成循環
+            if resolved.contains(&current) {
+                continue;
+            }
+            if let Some(children) = self.children.get(&current) {
+                pending.extend(children.iter().cloned());
+            }
+            resolved.push(current);
+        }
+
+        resolved.sort();
+        Some(resolved)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct StickerDatabase {
     db: Database,
+    categories: CategoryIndex,
 }
 
 impl StickerDatabase {
     /// 建立新的貼圖資料庫（DB-backed）
     pub fn new(db: Database) -> Self {
-        Self { db }
+        Self {
+            db,
+            categories: CategoryIndex::default(),
+        }
     }
 
     /// 從 CSV 內容載入貼圖資料
@@ -144,6 +313,24 @@ impl StickerDatabase {
         self.load_json_content_to_vec(&content, category, path)
     }
 
+    /// 依格式解析來源的內容
+    fn parse_content(
+        &self,
+        content: &str,
+        format: &crate::config::FileFormat,
+        category: &str,
+        source_name: &str,
+    ) -> Result<Vec<Sticker>> {
+        match format {
+            crate::config::FileFormat::Csv => {
+                self.load_csv_content_to_vec(content, category, source_name)
+            }
+            crate::config::FileFormat::Json => {
+                self.load_json_content_to_vec(content, category, source_name)
+            }
+        }
+    }
+
     /// 從 HTTP GET 獲取資料並載入
     pub async fn load_from_http(
         &self,
@@ -152,39 +339,186 @@ impl StickerDatabase {
         format: &crate::config::FileFormat,
         category: &str,
     ) -> Result<Vec<Sticker>> {
-        let client = reqwest::Client::new();
-        let mut request = client.get(url);
-
-        // 添加自定義 headers
-        for (key, value) in headers {
-            request = request.header(key, value);
-        }
+        self.fetch_cached(url, headers, category, |content| {
+            self.parse_content(content, format, category, url)
+        })
+        .await
+    }
 
-        let response = request
-            .send()
+    /// 從 LINE 的 CDN 載入貼圖包，`pack` 為 LINE STORE 網址或貼圖包 ID
+    pub async fn load_from_line(
+        &self,
+        pack: &str,
+        name: Option<&str>,
+        category: &str,
+    ) -> Result<Vec<Sticker>> {
+        self.load_from_line_cdn(line::CDN_BASE, pack, name, category)
             .await
-            .with_context(|| format!("無法從 URL 獲取資料: {}", url))?;
+    }
 
-        let content = response
-            .text()
-            .await
-            .with_context(|| format!("無法讀取 HTTP 回應內容: {}", url))?;
+    async fn load_from_line_cdn(
+        &self,
+        base: &str,
+        pack: &str,
+        name: Option<&str>,
+        category: &str,
+    ) -> Result<Vec<Sticker>> {
+        let pack_id = line::parse_pack_id(pack)
+            .with_context(|| format!("無法從「{}」取得 LINE 貼圖包 ID", pack))?;
+        let url = line::product_info_url(base, pack_id);
+        self.fetch_cached(&url, &HashMap::new(), category, |content| {
+            line::parse_product_info(content, base, name, category)
+        })
+        .await
+    }
 
-        match format {
-            crate::config::FileFormat::Csv => self.load_csv_content_to_vec(&content, category, url),
-            crate::config::FileFormat::Json => {
-                self.load_json_content_to_vec(&content, category, url)
+    /// 以 HTTP GET 取得來源並以 `parse` 解析。
+    /// 上次取得的內容記在 `sticker_sources`，帶著它的 ETag／Last-Modified 發出條件式請求，
+    /// 來源回應 304 時解析上次的內容，不必重新下載。每次的結果（包含失敗）都寫回 `sticker_sources`
+    async fn fetch_cached(
+        &self,
+        url: &str,
+        headers: &HashMap<String, String>,
+        category: &str,
+        parse: impl Fn(&str) -> Result<Vec<Sticker>>,
+    ) -> Result<Vec<Sticker>> {
+        let previous = self.db.get_sticker_source(category, url).await?;
+        let now = chrono::Utc::now();
+        let mut record = previous.clone().unwrap_or_else(|| StickerSourceRecord {
+            category: category.to_string(),
+            url: url.to_string(),
+            etag: None,
+            last_modified: None,
+            content: None,
+            status: StickerSourceStatus::Failed,
+            sticker_count: 0,
+            error: None,
+            checked_at: now,
+            changed_at: None,
+        });
+        record.checked_at = now;
+
+        let cached = previous.as_ref().filter(|p| p.content.is_some());
+        let result = async {
+            let client = reqwest::Client::new();
+            let mut request = client.get(url);
+
+            // 添加自定義 headers
+            for (key, value) in headers {
+                request = request.header(key, value);
+            }
+            // 只有保存了上次的內容時才發出條件式請求，否則 304 時沒有內容可以解析
+            if let Some(cached) = cached {
+                if let Some(etag) = &cached.etag {
+                    request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+                }
+                if let Some(last_modified) = &cached.last_modified {
+                    request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+                }
+            }
+
+            let response = request
+                .send()
+                .await
+                .with_context(|| format!("無法從 URL 獲取資料: {}", url))?;
+            if response.status() == reqwest::StatusCode::NOT_MODIFIED
+                && let Some(content) = cached.and_then(|c| c.content.as_deref())
+            {
+                let stickers = parse(content)?;
+                return anyhow::Ok((stickers, None));
+            }
+
+            // 錯誤頁面不當成貼圖資料，避免定期重新整理時清空貼圖
+            let response = response
+                .error_for_status()
+                .with_context(|| format!("無法從 URL 獲取資料: {}", url))?;
+            let header = |name: reqwest::header::HeaderName| {
+                response
+                    .headers()
+                    .get(name)
+                    .and_then(|v| v.to_str().ok())
+                    .map(str::to_string)
+            };
+            let etag = header(reqwest::header::ETAG);
+            let last_modified = header(reqwest::header::LAST_MODIFIED);
+            let content = response
+                .text()
+                .await
+                .with_context(|| format!("無法讀取 HTTP 回應內容: {}", url))?;
+            let stickers = parse(&content)?;
+            Ok((stickers, Some((content, etag, last_modified))))
+        }
+        .await;
+
+        match &result {
+            Ok((stickers, fresh)) => {
+                record.sticker_count = stickers.len() as i64;
+                record.error = None;
+                match fresh {
+                    Some((content, etag, last_modified)) => {
+                        record.status = StickerSourceStatus::Updated;
+                        record.content = Some(content.clone());
+                        record.etag = etag.clone();
+                        record.last_modified = last_modified.clone();
+                        record.changed_at = Some(now);
+                    }
+                    None => record.status = StickerSourceStatus::NotModified,
+                }
             }
+            Err(e) => {
+                // 保留上次的內容與驗證資訊，來源恢復後仍可回應 304
+                record.status = StickerSourceStatus::Failed;
+                record.error = Some(format!("{:#}", e));
+            }
+        }
+        if let Err(e) = self.db.save_sticker_source(&record).await {
+            tracing::warn!("記錄貼圖來源 {} 的狀態失敗: {}", url, e);
+        }
+
+        result.map(|(stickers, _)| stickers)
+    }
+
+    /// 解析管理員上傳的貼圖檔案，依副檔名判斷是 CSV 或 JSON（格式與配置的檔案來源相同）
+    pub fn load_upload(
+        &self,
+        filename: &str,
+        content: &str,
+        category: &str,
+    ) -> Result<Vec<Sticker>> {
+        let extension = filename
+            .rsplit_once('.')
+            .map(|(_, ext)| ext.to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "csv" => self.load_csv_content_to_vec(content, category, filename),
+            "json" => self.load_json_content_to_vec(content, category, filename),
+            _ => anyhow::bail!("不支援的檔案格式: {}（只接受 .csv 或 .json）", filename),
         }
     }
 
+    /// 新增管理員上傳的貼圖，不需修改配置檔，重新載入配置後仍會保留
+    pub async fn add_custom(&self, stickers: &[Sticker], added_by: &str) -> Result<usize> {
+        self.db.add_custom_stickers(stickers, added_by).await
+    }
+
     /// 從配置載入所有貼圖資料
     /// Load stickers from config and insert them into the provided Database.
     pub async fn load_from_config(
         db: &Database,
         config: &crate::config::StickersConfig,
     ) -> Result<Self> {
-        let loader = Self::new(db.clone());
+        let mut loader = Self::new(db.clone());
+        loader.categories = CategoryIndex::from_config(config);
+        let all = loader.fetch_sources(config).await?;
+        loader.replace_all(&all).await?;
+        Ok(loader)
+    }
+
+    /// 讀取配置中所有來源的貼圖（檔案與 HTTP），不寫入資料庫
+    pub async fn fetch_sources(
+        &self,
+        config: &crate::config::StickersConfig,
+    ) -> Result<Vec<Sticker>> {
         let mut all: Vec<Sticker> = Vec::new();
 
         for category_config in &config.categories {
@@ -192,7 +526,7 @@ impl StickerDatabase {
                 match source {
                     crate::config::SourceConfig::File { format, path } => match format {
                         crate::config::FileFormat::Csv => {
-                            let mut v = loader
+                            let mut v = self
                                 .load_csv_content_to_vec(
                                     &fs::read_to_string(path)?,
                                     &category_config.name,
@@ -202,7 +536,7 @@ impl StickerDatabase {
                             all.append(&mut v);
                         }
                         crate::config::FileFormat::Json => {
-                            let mut v = loader
+                            let mut v = self
                                 .load_json(path, &category_config.name)
                                 .with_context(|| format!("載入 JSON 檔案失敗: {}", path))?;
                             all.append(&mut v);
@@ -213,22 +547,79 @@ impl StickerDatabase {
                         url,
                         headers,
                     } => {
-                        let mut v = loader
+                        let mut v = self
                             .load_from_http(url, headers, format, &category_config.name)
                             .await
                             .with_context(|| format!("從 HTTP 載入資料失敗: {}", url))?;
                         all.append(&mut v);
                     }
+                    crate::config::SourceConfig::Line { pack, name } => {
+                        let mut v = self
+                            .load_from_line(pack, name.as_deref(), &category_config.name)
+                            .await
+                            .with_context(|| format!("載入 LINE 貼圖包失敗: {}", pack))?;
+                        all.append(&mut v);
+                    }
                 }
             }
         }
 
+        // 已從配置移除的 HTTP 來源（包含 LINE 貼圖包）不再顯示狀態
+        let http_sources: Vec<(String, String)> = config
+            .categories
+            .iter()
+            .flat_map(|c| {
+                c.sources.iter().filter_map(|source| match source {
+                    crate::config::SourceConfig::HttpGet { url, .. } => {
+                        Some((c.name.clone(), url.clone()))
+                    }
+                    crate::config::SourceConfig::Line { pack, .. } => line::parse_pack_id(pack)
+                        .map(|id| (c.name.clone(), line::product_info_url(line::CDN_BASE, id))),
+                    crate::config::SourceConfig::File { .. } => None,
+                })
+            })
+            .collect();
+        self.db.prune_sticker_sources(&http_sources).await?;
+
+        Ok(all)
+    }
+
+    /// 以一個交易替換資料庫中的貼圖，管理員新增的貼圖保留。
+    /// 與上次寫入的貼圖列表相同時不寫入，回傳是否有替換
+    pub async fn replace_all(&self, stickers: &[Sticker]) -> Result<bool> {
         // Replace stickers in DB so the stored state matches the config exactly.
-        db.replace_stickers(&all)
+        let replaced = self
+            .db
+            .sync_stickers(stickers)
             .await
             .with_context(|| "寫入貼圖到資料庫失敗")?;
+        self.db.metrics().record_source_refresh();
+        Ok(replaced.is_some())
+    }
 
-        Ok(loader)
+    /// HTTP 貼圖來源最後一次讀取的狀態
+    pub async fn source_statuses(&self) -> Result<Vec<StickerSourceRecord>> {
+        self.db.get_sticker_sources().await
+    }
+
+    /// 以 hash 前八碼取得貼圖
+    pub async fn find_by_hash(&self, hash: &str) -> Result<Option<Sticker>> {
+        self.db.find_sticker_by_hash(hash).await
+    }
+
+    /// 貼圖是否為管理員新增；配置來源中的貼圖刪除或改名後，重新載入配置時會還原
+    pub async fn is_custom(&self, image_url: &str) -> Result<bool> {
+        self.db.is_custom_sticker(image_url).await
+    }
+
+    /// 刪除貼圖
+    pub async fn remove(&self, image_url: &str) -> Result<bool> {
+        self.db.delete_sticker(image_url).await
+    }
+
+    /// 修改貼圖名稱
+    pub async fn rename(&self, image_url: &str, name: &str) -> Result<bool> {
+        self.db.update_sticker(image_url, name).await
     }
 
     /// 取得所有分類
@@ -244,6 +635,42 @@ impl StickerDatabase {
         self.db.get_sticker_category_stats().await
     }
 
+    /// 記錄一次貼圖發送，供熱門統計使用
+    pub async fn record_usage(&self, image_url: &str, user_id: &str) -> Result<()> {
+        self.db.record_sticker_usage(image_url, user_id).await
+    }
+
+    /// 最近七天最常發送的貼圖
+    pub async fn trending_this_week(&self, limit: i64) -> Result<Vec<Sticker>> {
+        let since = chrono::Utc::now() - chrono::Duration::days(7);
+        let trending = self.db.get_trending_stickers(since, limit).await?;
+        Ok(trending.into_iter().map(|(sticker, _)| sticker).collect())
+    }
+
+    /// 使用者最近發送過的貼圖
+    pub async fn recent(&self, user_id: &str, limit: i64) -> Result<Vec<Sticker>> {
+        self.db.get_recent_stickers(user_id, limit).await
+    }
+
+    /// 使用者的最愛貼圖
+    pub async fn favorites(&self, user_id: &str) -> Result<Vec<Sticker>> {
+        self.db.get_favorite_stickers(user_id).await
+    }
+
+    /// 貼圖是否在使用者的最愛中
+    pub async fn is_favorite(&self, user_id: &str, image_url: &str) -> Result<bool> {
+        self.db.is_favorite_sticker(user_id, image_url).await
+    }
+
+    /// 將貼圖加入或移出使用者的最愛
+    pub async fn set_favorite(&self, user_id: &str, image_url: &str, favorite: bool) -> Result<()> {
+        if favorite {
+            self.db.add_favorite_sticker(user_id, image_url).await
+        } else {
+            self.db.remove_favorite_sticker(user_id, image_url).await
+        }
+    }
+
     /// 取得貼圖總數
     pub async fn get_total_count(&self) -> Result<i64> {
         self.db.count_stickers().await
@@ -266,11 +693,15 @@ impl StickerDatabase {
     /// - "海綿寶寶: a" -> 在海綿寶寶分類中搜尋 a
     /// - "-123" -> 不包含 123
     /// - "海綿寶寶: a b -c" -> 在海綿寶寶分類中搜尋包含 a 和 b 但不包含 c
-    fn parse_query(query: &str) -> (Option<String>, Vec<String>, Vec<String>) {
+    /// - "\"神奇 海螺\"" -> 包含完整片語「神奇 海螺」（含空白）
+    /// - "a|b c" -> 包含 a 或 b，且包含 c
+    ///
+    /// 回傳的 include 為 OR 群組列表：群組之間為 AND，群組內為 OR。
+    fn parse_query(query: &str) -> (Option<String>, Vec<Vec<String>>, Vec<String>) {
         let query = query.trim();
 
-        // 檢查是否有分類指定（格式：分類: 關鍵字）
-        let (category, keyword_part) = if let Some(colon_pos) = query.find(':') {
+        // 檢查是否有分類指定（格式：分類: 關鍵字），引號內的冒號不算
+        let (category, keyword_part) = if let Some(colon_pos) = find_unquoted(query, ':') {
             let cat = query[..colon_pos].trim().to_string();
             let kw = query[colon_pos + 1..].trim();
             (Some(cat), kw)
@@ -279,20 +710,22 @@ impl StickerDatabase {
         };
 
         // 解析關鍵字和排除詞
-        let mut include_keywords: Vec<String> = Vec::new();
+        let mut include_groups: Vec<Vec<String>> = Vec::new();
         let mut exclude_keywords: Vec<String> = Vec::new();
 
-        for token in keyword_part.split_whitespace() {
+        for token in tokenize_query(keyword_part) {
             if let Some(excluded) = token.strip_prefix('-') {
-                if !excluded.is_empty() {
-                    exclude_keywords.push(excluded.to_lowercase());
+                // -a|b 代表 a 與 b 都排除
+                exclude_keywords.extend(split_alternatives(excluded));
+            } else {
+                let group = split_alternatives(&token);
+                if !group.is_empty() {
+                    include_groups.push(group);
                 }
-            } else if !token.is_empty() {
-                include_keywords.push(token.to_lowercase());
             }
         }
 
-        (category, include_keywords, exclude_keywords)
+        (category, include_groups, exclude_keywords)
     }
 
     /// 根據分類和關鍵字搜尋貼圖
@@ -300,6 +733,7 @@ impl StickerDatabase {
     /// - 空格分隔多個關鍵字（AND 條件）
     /// - `分類: 關鍵字` 指定分類搜尋
     /// - `-關鍵字` 排除包含該關鍵字的結果
+    /// - `"片語"` 以完整片語比對，`a|b` 代表 a 或 b
     /// For backward compatibility this returns an empty Vec; use `search_async` instead.
     pub fn search(&self, _keyword: &str, _categories: Option<&[String]>) -> Vec<&Sticker> {
         vec![]
@@ -311,17 +745,48 @@ impl StickerDatabase {
         keyword: &str,
         categories: Option<&[String]>,
     ) -> Result<Vec<Sticker>> {
-        let (query_category, include_keywords, exclude_keywords) = Self::parse_query(keyword);
-        let res = self
-            .db
-            .search_stickers(
-                query_category.as_deref(),
-                &include_keywords,
-                &exclude_keywords,
-                categories,
-                100,
-            )
-            .await?;
+        let _timer = self.db.metrics().timer("sticker", "search");
+        let (query_category, include_groups, exclude_keywords) = Self::parse_query(keyword);
+
+        // 分類可能是別名或上層分類，展開成實際分類後再搜尋；呼叫端另有分類限制時取交集
+        let resolved = query_category
+            .as_deref()
+            .and_then(|c| self.categories.resolve(c))
+            .map(|mut resolved| {
+                if let Some(allowed) = categories {
+                    resolved.retain(|c| allowed.contains(c));
+                }
+                resolved
+            });
+        // 交集為空時沒有符合的貼圖（空的分類列表在資料庫查詢中代表不限制）
+        if resolved.as_ref().is_some_and(Vec::is_empty) {
+            return Ok(Vec::new());
+        }
+
+        let res = match resolved {
+            Some(resolved_categories) => {
+                self.db
+                    .search_stickers_grouped(
+                        None,
+                        &include_groups,
+                        &exclude_keywords,
+                        Some(&resolved_categories),
+                        100,
+                    )
+                    .await?
+            }
+            None => {
+                self.db
+                    .search_stickers_grouped(
+                        query_category.as_deref(),
+                        &include_groups,
+                        &exclude_keywords,
+                        categories,
+                        100,
+                    )
+                    .await?
+            }
+        };
         Ok(res)
     }
 
@@ -337,6 +802,72 @@ impl StickerDatabase {
     }
 }
 
+fn is_quote(c: char) -> bool {
+    matches!(c, '"' | '“' | '”')
+}
+
+/// 找出不在引號內的字元位置（byte offset）
+fn find_unquoted(s: &str, target: char) -> Option<usize> {
+    let mut in_quotes = false;
+    for (idx, c) in s.char_indices() {
+        if is_quote(c) {
+            in_quotes = !in_quotes;
+        } else if c == target && !in_quotes {
+            return Some(idx);
+        }
+    }
+    None
+}
+
+/// 以空白切分查詢字串，但保留引號內的空白（引號本身保留給後續處理）
+fn tokenize_query(s: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+
+    for c in s.chars() {
+        if is_quote(c) {
+            in_quotes = !in_quotes;
+            current.push(c);
+        } else if c.is_whitespace() && !in_quotes {
+            if !current.is_empty() {
+                tokens.push(std::mem::take(&mut current));
+            }
+        } else {
+            current.push(c);
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+
+    tokens
+}
+
+/// 將 `a|"b c"` 切成 OR 候選詞（去除引號並轉小寫）
+fn split_alternatives(token: &str) -> Vec<String> {
+    let mut alternatives = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+
+    for c in token.chars() {
+        if is_quote(c) {
+            in_quotes = !in_quotes;
+        } else if c == '|' && !in_quotes {
+            alternatives.push(std::mem::take(&mut current));
+        } else {
+            current.push(c);
+        }
+    }
+    alternatives.push(current);
+
+    alternatives
+        .into_iter()
+        .filter(|a| !a.is_empty())
+        .map(|a| a.to_lowercase())
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -354,12 +885,58 @@ mod tests {
 
         let hash = sticker.get_url_hash();
         assert_eq!(hash.len(), 8);
+        // hash 必須跨版本穩定
+        assert_eq!(hash, "f1bec75f");
 
         let display_name = sticker.get_display_name();
         assert!(display_name.starts_with("[測試分類] 測試 ("));
         assert!(display_name.ends_with(")"));
     }
 
+    #[test]
+    fn test_sticker_filename() {
+        assert_eq!(
+            sticker_filename("https://i.imgur.com/XB4MwpR.jpg", "image/jpeg"),
+            "XB4MwpR.jpg"
+        );
+        assert_eq!(
+            sticker_filename("https://example.com/s/%E8%B2%BC%E5%9C%96", "image/gif"),
+            "貼圖.gif"
+        );
+        assert_eq!(sticker_filename("https://example.com/", ""), "sticker.png");
+    }
+
+    #[tokio::test]
+    async fn test_download_sticker_image() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/cat")
+            .with_status(200)
+            .with_header("content-type", "image/png")
+            .with_body("PNGDATA")
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/gone")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body("<html>removed</html>")
+            .create_async()
+            .await;
+
+        let image = download_sticker_image(&format!("{}/cat", server.url()))
+            .await
+            .unwrap();
+        assert_eq!(image.filename, "cat.png");
+        assert_eq!(image.data, b"PNGDATA");
+
+        assert!(
+            download_sticker_image(&format!("{}/gone", server.url()))
+                .await
+                .is_err()
+        );
+    }
+
     #[tokio::test]
     async fn test_load_csv() {
         let temp_dir = TempDir::new().unwrap();
@@ -405,6 +982,34 @@ mod tests {
         assert_eq!(v[0].category, "其他");
     }
 
+    #[tokio::test]
+    async fn test_load_upload_by_extension() {
+        let loader = StickerDatabase::new(setup_db().await);
+
+        let v = loader
+            .load_upload(
+                "new.CSV",
+                "名稱,圖片\n貓,https://example.com/cat.png\n",
+                "動物",
+            )
+            .unwrap();
+        assert_eq!(v.len(), 1);
+        assert_eq!(v[0].category, "動物");
+        let v = loader
+            .load_upload(
+                "new.json",
+                r#"{"狗": "https://example.com/dog.png"}"#,
+                "動物",
+            )
+            .unwrap();
+        assert_eq!(v[0].name, "狗");
+        assert!(loader.load_upload("new.txt", "", "動物").is_err());
+
+        assert!(is_valid_image_url("https://example.com/cat.png"));
+        assert!(!is_valid_image_url("ftp://example.com/cat.png"));
+        assert!(!is_valid_image_url("cat.png"));
+    }
+
     #[tokio::test]
     async fn test_load_json() {
         let temp_dir = TempDir::new().unwrap();
@@ -557,6 +1162,78 @@ mod tests {
         assert_eq!(results[0].name, "開心派大星");
     }
 
+    #[test]
+    fn test_parse_query_phrases_and_or_groups() {
+        let (category, include, exclude) =
+            StickerDatabase::parse_query(r#"海綿寶寶: "神奇 海螺" 派大星|章魚哥 -"難過 的""#);
+        assert_eq!(category.as_deref(), Some("海綿寶寶"));
+        assert_eq!(
+            include,
+            vec![
+                vec!["神奇 海螺".to_string()],
+                vec!["派大星".to_string(), "章魚哥".to_string()],
+            ]
+        );
+        assert_eq!(exclude, vec!["難過 的".to_string()]);
+
+        // 引號內的冒號不視為分類分隔
+        let (category, include, _) = StickerDatabase::parse_query(r#""a:b""#);
+        assert!(category.is_none());
+        assert_eq!(include, vec![vec!["a:b".to_string()]]);
+    }
+
+    #[tokio::test]
+    async fn test_search_phrases_and_or_groups() {
+        let database = setup_db().await;
+        let stickers = vec![
+            Sticker {
+                name: "神奇 海螺說不".to_string(),
+                image_url: "https://example.com/1.jpg".to_string(),
+                category: "海綿寶寶".to_string(),
+            },
+            Sticker {
+                name: "神奇的海螺".to_string(),
+                image_url: "https://example.com/2.jpg".to_string(),
+                category: "海綿寶寶".to_string(),
+            },
+            Sticker {
+                name: "開心派大星".to_string(),
+                image_url: "https://example.com/3.jpg".to_string(),
+                category: "海綿寶寶".to_string(),
+            },
+            Sticker {
+                name: "開心章魚哥".to_string(),
+                image_url: "https://example.com/4.jpg".to_string(),
+                category: "海綿寶寶".to_string(),
+            },
+        ];
+        database.bulk_insert_stickers(&stickers).await.unwrap();
+        let sticker_db = StickerDatabase::new(database.clone());
+
+        // 片語需完整比對（含空白）
+        let results = sticker_db
+            .search_async(r#""神奇 海螺""#, None)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].name, "神奇 海螺說不");
+
+        // OR 群組
+        let results = sticker_db
+            .search_async("派大星|章魚哥", None)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 2);
+
+        // OR 群組 + AND + 排除
+        let results = sticker_db
+            .search_async("開心 派大星|章魚哥 -章魚", None)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].name, "開心派大星");
+    }
+
     #[tokio::test]
     async fn test_get_categories() {
         let database = setup_db().await;
@@ -587,6 +1264,93 @@ mod tests {
         assert!(categories.contains(&"分類B".to_string()));
     }
 
+    #[tokio::test]
+    async fn test_category_alias_and_parent_search() {
+        use crate::config::{CategoryConfig, FileFormat, SourceConfig, StickersConfig};
+
+        let database = setup_db().await;
+        let temp_dir = TempDir::new().unwrap();
+
+        let sb_file = temp_dir.path().join("sb.json");
+        fs::write(&sb_file, r#"{"開心派大星": "https://example.com/sb.png"}"#).unwrap();
+        let shin_file = temp_dir.path().join("shin.json");
+        fs::write(
+            &shin_file,
+            r#"{"開心小新": "https://example.com/shin.png"}"#,
+        )
+        .unwrap();
+        let other_file = temp_dir.path().join("other.json");
+        fs::write(
+            &other_file,
+            r#"{"開心貓咪": "https://example.com/cat.png"}"#,
+        )
+        .unwrap();
+
+        let json_source = |path: &std::path::Path| SourceConfig::File {
+            format: FileFormat::Json,
+            path: path.to_string_lossy().to_string(),
+        };
+
+        let cfg = StickersConfig {
+            categories: vec![
+                CategoryConfig {
+                    name: "海綿寶寶".to_string(),
+                    sources: vec![json_source(&sb_file)],
+                    aliases: vec!["SpongeBob".to_string()],
+                    parent: Some("卡通".to_string()),
+                },
+                CategoryConfig {
+                    name: "蠟筆小新".to_string(),
+                    sources: vec![json_source(&shin_file)],
+                    aliases: vec![],
+                    parent: Some("卡通".to_string()),
+                },
+                CategoryConfig {
+                    name: "其他".to_string(),
+                    sources: vec![json_source(&other_file)],
+                    aliases: vec![],
+                    parent: None,
+                },
+            ],
+            ..Default::default()
+        };
+
+        let sticker_db = StickerDatabase::load_from_config(&database, &cfg)
+            .await
+            .expect("load");
+
+        // 上層分類包含所有子分類
+        let results = sticker_db.search_async("卡通: 開心", None).await.unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|s| s.category != "其他"));
+
+        // 別名（不分大小寫）對應到正式分類
+        let results = sticker_db
+            .search_async("spongebob: 開心", None)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].category, "海綿寶寶");
+
+        // 一般分類仍可直接搜尋
+        let results = sticker_db.search_async("其他: 開心", None).await.unwrap();
+        assert_eq!(results.len(), 1);
+
+        // 呼叫端的分類限制與展開後的分類取交集
+        let allowed = ["蠟筆小新".to_string(), "其他".to_string()];
+        let results = sticker_db
+            .search_async("卡通: 開心", Some(&allowed))
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].category, "蠟筆小新");
+        let results = sticker_db
+            .search_async("spongebob: 開心", Some(&allowed))
+            .await
+            .unwrap();
+        assert!(results.is_empty());
+    }
+
     #[tokio::test]
     async fn test_load_from_config_replaces_existing() {
         use crate::config::{CategoryConfig, FileFormat, SourceConfig, StickersConfig};
@@ -605,10 +1369,13 @@ mod tests {
                 format: FileFormat::Json,
                 path: file1.to_string_lossy().to_string(),
             }],
+            aliases: vec![],
+            parent: None,
         };
 
         let cfg1 = StickersConfig {
             categories: vec![cat1],
+            ..Default::default()
         };
 
         // Load first config
@@ -630,10 +1397,13 @@ mod tests {
                 format: FileFormat::Json,
                 path: file2.to_string_lossy().to_string(),
             }],
+            aliases: vec![],
+            parent: None,
         };
 
         let cfg2 = StickersConfig {
             categories: vec![cat2],
+            ..Default::default()
         };
 
         // Load second config (should replace existing stickers)
@@ -656,4 +1426,178 @@ mod tests {
             .unwrap();
         assert_eq!(res_c.len(), 1);
     }
+
+    #[tokio::test]
+    async fn test_fetch_sources_from_http() {
+        use crate::config::{CategoryConfig, FileFormat, SourceConfig, StickersConfig};
+
+        let database = setup_db().await;
+        let mut server = mockito::Server::new_async().await;
+        let first = server
+            .mock("GET", "/stickers.json")
+            .with_status(200)
+            .with_body(r#"{"a": "https://example.com/a.png"}"#)
+            .create_async()
+            .await;
+
+        let cfg = StickersConfig {
+            categories: vec![CategoryConfig {
+                name: "遠端".to_string(),
+                sources: vec![SourceConfig::HttpGet {
+                    format: FileFormat::Json,
+                    url: format!("{}/stickers.json", server.url()),
+                    headers: HashMap::new(),
+                }],
+                aliases: vec![],
+                parent: None,
+            }],
+            ..Default::default()
+        };
+        assert!(cfg.has_http_sources());
+        assert!(!StickersConfig::default().has_http_sources());
+
+        let sticker_db = StickerDatabase::load_from_config(&database, &cfg)
+            .await
+            .expect("load");
+        assert_eq!(database.count_stickers().await.unwrap(), 1);
+
+        // 來源新增貼圖後，重新讀取即可看到
+        first.remove_async().await;
+        let second = server
+            .mock("GET", "/stickers.json")
+            .with_status(200)
+            .with_body(r#"{"a": "https://example.com/a.png", "b": "https://example.com/b.png"}"#)
+            .create_async()
+            .await;
+        let stickers = sticker_db.fetch_sources(&cfg).await.unwrap();
+        assert_eq!(stickers.len(), 2);
+        sticker_db.replace_all(&stickers).await.unwrap();
+        assert_eq!(database.count_stickers().await.unwrap(), 2);
+
+        // 來源回應錯誤時不當成空的貼圖列表
+        second.remove_async().await;
+        server
+            .mock("GET", "/stickers.json")
+            .with_status(503)
+            .with_body("{}")
+            .create_async()
+            .await;
+        assert!(sticker_db.fetch_sources(&cfg).await.is_err());
+        assert_eq!(database.count_stickers().await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_conditional_http_fetch() {
+        use crate::config::{CategoryConfig, FileFormat, SourceConfig, StickersConfig};
+
+        let database = setup_db().await;
+        let mut server = mockito::Server::new_async().await;
+        let full = server
+            .mock("GET", "/stickers.json")
+            .with_status(200)
+            .with_header("etag", "\"v1\"")
+            .with_body(r#"{"a": "https://example.com/a.png"}"#)
+            .create_async()
+            .await;
+
+        let cfg = StickersConfig {
+            categories: vec![CategoryConfig {
+                name: "遠端".to_string(),
+                sources: vec![SourceConfig::HttpGet {
+                    format: FileFormat::Json,
+                    url: format!("{}/stickers.json", server.url()),
+                    headers: HashMap::new(),
+                }],
+                aliases: vec![],
+                parent: None,
+            }],
+            ..Default::default()
+        };
+        let sticker_db = StickerDatabase::load_from_config(&database, &cfg)
+            .await
+            .expect("load");
+        let statuses = sticker_db.source_statuses().await.unwrap();
+        assert_eq!(statuses[0].status, StickerSourceStatus::Updated);
+        assert_eq!(statuses[0].etag.as_deref(), Some("\"v1\""));
+
+        // 帶著 ETag 重新讀取，304 時沿用上次的內容，也不重新寫入貼圖表
+        full.remove_async().await;
+        let not_modified = server
+            .mock("GET", "/stickers.json")
+            .match_header("if-none-match", "\"v1\"")
+            .with_status(304)
+            .create_async()
+            .await;
+        let stickers = sticker_db.fetch_sources(&cfg).await.unwrap();
+        assert_eq!(stickers.len(), 1);
+        assert!(!sticker_db.replace_all(&stickers).await.unwrap());
+        let statuses = sticker_db.source_statuses().await.unwrap();
+        assert_eq!(statuses[0].status, StickerSourceStatus::NotModified);
+        assert_eq!(statuses[0].sticker_count, 1);
+
+        // 失敗時記下錯誤，保留驗證資訊
+        not_modified.remove_async().await;
+        server
+            .mock("GET", "/stickers.json")
+            .with_status(500)
+            .create_async()
+            .await;
+        assert!(sticker_db.fetch_sources(&cfg).await.is_err());
+        let statuses = sticker_db.source_statuses().await.unwrap();
+        assert_eq!(statuses[0].status, StickerSourceStatus::Failed);
+        assert!(statuses[0].error.is_some());
+        assert_eq!(statuses[0].etag.as_deref(), Some("\"v1\""));
+
+        // 從配置移除後不再列出
+        sticker_db
+            .fetch_sources(&StickersConfig::default())
+            .await
+            .unwrap();
+        assert!(sticker_db.source_statuses().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_load_from_line_cdn() {
+        let database = setup_db().await;
+        let sticker_db = StickerDatabase::new(database);
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/product/1234/android/productInfo.meta")
+            .with_status(200)
+            .with_header("etag", "\"p1\"")
+            .with_body(
+                r#"{"packageId": 1234, "title": {"zh-Hant": "熊大"}, "stickers": [{"id": 11}, {"id": 12}]}"#,
+            )
+            .create_async()
+            .await;
+
+        let stickers = sticker_db
+            .load_from_line_cdn(
+                &server.url(),
+                "https://store.line.me/stickershop/product/1234/zh-Hant",
+                None,
+                "LINE",
+            )
+            .await
+            .unwrap();
+        let names: Vec<&str> = stickers.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["熊大 1", "熊大 2"]);
+        assert_eq!(
+            stickers[1].image_url,
+            format!("{}/sticker/12/iPhone/sticker@2x.png", server.url())
+        );
+
+        // 與 HTTP 來源一樣記下讀取狀態
+        let statuses = sticker_db.source_statuses().await.unwrap();
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].status, StickerSourceStatus::Updated);
+        assert_eq!(statuses[0].sticker_count, 2);
+
+        assert!(
+            sticker_db
+                .load_from_line_cdn(&server.url(), "not-a-pack", None, "LINE")
+                .await
+                .is_err()
+        );
+    }
 }
@@ -0,0 +1,164 @@
+//! LINE 貼圖包來源
+//!
+//! `type: line` 的來源以 LINE STORE 的網址（例如 `https://store.line.me/stickershop/product/1234/zh-Hant`）
+//! 或貼圖包 ID 指定一組貼圖。貼圖包的資訊（`productInfo.meta`）與圖片都放在 LINE 的 CDN，
+//! 不需要登入。LINE 貼圖沒有個別的名稱，每張以「貼圖包名稱 序號」命名，圖片網址直接指向 CDN；
+//! 有動畫的貼圖包使用動畫 PNG。
+
+use super::Sticker;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// LINE 貼圖的 CDN
+pub const CDN_BASE: &str = "https://stickershop.line-scdn.net/stickershop/v1";
+
+/// 取貼圖包名稱時優先使用的語言
+const TITLE_LANGUAGES: &[&str] = &["zh-Hant", "zh_TW", "ja", "en"];
+
+#[derive(Debug, Deserialize)]
+struct ProductInfo {
+    #[serde(rename = "packageId")]
+    package_id: u64,
+    #[serde(default)]
+    title: HashMap<String, String>,
+    #[serde(rename = "hasAnimation", default)]
+    has_animation: bool,
+    #[serde(default)]
+    stickers: Vec<ProductSticker>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProductSticker {
+    id: u64,
+}
+
+/// 從 LINE STORE 網址（`/stickershop/product/1234/...`、`line.me/S/sticker/1234`）
+/// 或純數字取得貼圖包 ID
+pub fn parse_pack_id(pack: &str) -> Option<u64> {
+    let pack = pack.trim();
+    if let Ok(id) = pack.parse() {
+        return Some(id);
+    }
+    let url = url::Url::parse(pack).ok()?;
+    let segments: Vec<&str> = url.path_segments()?.collect();
+    segments
+        .windows(2)
+        .find(|pair| matches!(pair[0], "product" | "sticker" | "detail"))
+        .and_then(|pair| pair[1].parse().ok())
+}
+
+/// 貼圖包資訊的網址
+pub fn product_info_url(base: &str, pack_id: u64) -> String {
+    format!(
+        "{}/product/{}/android/productInfo.meta",
+        base.trim_end_matches('/'),
+        pack_id
+    )
+}
+
+/// 貼圖圖片的網址
+fn sticker_image_url(base: &str, sticker_id: u64, animated: bool) -> String {
+    let file = if animated {
+        "sticker_animation@2x.png"
+    } else {
+        "sticker@2x.png"
+    };
+    format!(
+        "{}/sticker/{}/iPhone/{}",
+        base.trim_end_matches('/'),
+        sticker_id,
+        file
+    )
+}
+
+/// 解析貼圖包資訊。`name` 為 None 時以貼圖包的標題（依 [`TITLE_LANGUAGES`] 的順序）命名
+pub fn parse_product_info(
+    content: &str,
+    base: &str,
+    name: Option<&str>,
+    category: &str,
+) -> Result<Vec<Sticker>> {
+    let info: ProductInfo =
+        serde_json::from_str(content).context("解析 LINE 貼圖包資訊時發生錯誤")?;
+
+    let title = name
+        .map(str::to_string)
+        .or_else(|| {
+            TITLE_LANGUAGES
+                .iter()
+                .find_map(|lang| info.title.get(*lang))
+                .or_else(|| info.title.values().next())
+                .cloned()
+        })
+        .filter(|title| !title.trim().is_empty())
+        .unwrap_or_else(|| format!("LINE {}", info.package_id));
+
+    Ok(info
+        .stickers
+        .iter()
+        .enumerate()
+        .map(|(i, sticker)| Sticker {
+            name: format!("{} {}", title.trim(), i + 1),
+            image_url: sticker_image_url(base, sticker.id, info.has_animation),
+            category: category.to_string(),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pack_id() {
+        assert_eq!(parse_pack_id("1234"), Some(1234));
+        assert_eq!(
+            parse_pack_id("https://store.line.me/stickershop/product/1234/zh-Hant"),
+            Some(1234)
+        );
+        assert_eq!(parse_pack_id("https://line.me/S/sticker/5678"), Some(5678));
+        assert_eq!(parse_pack_id("line://shop/detail/42"), Some(42));
+        assert_eq!(parse_pack_id("https://store.line.me/home"), None);
+        assert_eq!(parse_pack_id("熊大"), None);
+    }
+
+    #[test]
+    fn test_parse_product_info() {
+        let content = r#"{
+            "packageId": 1234,
+            "title": {"en": "Brown", "zh-Hant": "熊大"},
+            "hasAnimation": true,
+            "stickers": [{"id": 11, "width": 320}, {"id": 12, "width": 320}]
+        }"#;
+
+        let stickers = parse_product_info(content, CDN_BASE, None, "LINE").unwrap();
+        assert_eq!(stickers.len(), 2);
+        assert_eq!(stickers[0].name, "熊大 1");
+        assert_eq!(stickers[1].name, "熊大 2");
+        assert_eq!(
+            stickers[0].image_url,
+            "https://stickershop.line-scdn.net/stickershop/v1/sticker/11/iPhone/sticker_animation@2x.png"
+        );
+        assert_eq!(stickers[0].category, "LINE");
+
+        // 指定名稱；沒有標題時以 ID 命名
+        let named = parse_product_info(content, CDN_BASE, Some("熊"), "LINE").unwrap();
+        assert_eq!(named[1].name, "熊 2");
+        let untitled = parse_product_info(
+            r#"{"packageId": 7, "stickers": [{"id": 1}]}"#,
+            CDN_BASE,
+            None,
+            "x",
+        )
+        .unwrap();
+        assert_eq!(untitled[0].name, "LINE 7 1");
+        assert!(
+            untitled[0]
+                .image_url
+                .ends_with("/sticker/1/iPhone/sticker@2x.png")
+        );
+
+        assert!(parse_product_info("<html>", CDN_BASE, None, "x").is_err());
+    }
+}
@@ -0,0 +1,151 @@
+//! 貼圖圖片的本機快取
+//!
+//! 以檔案發送貼圖時需要先下載圖片，圖床較慢時會拖慢發送。設定
+//! `stickers.image_cache_dir` 後下載過的圖片存在本機，之後直接讀取；
+//! `stickers.prewarm_count` 另外在啟動時依使用統計預先下載最常用的貼圖。
+//! 每個網址一個子目錄（網址的 SHA-256），裡面放原本檔名的圖片檔。
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use tracing::{info, warn};
+
+use crate::database::Database;
+use crate::sticker::{StickerImage, download_sticker_image};
+
+/// 預先下載時統計使用次數的期間（天）
+const PREWARM_USAGE_DAYS: i64 = 30;
+
+#[derive(Debug, Clone)]
+pub struct ImageCache {
+    dir: PathBuf,
+}
+
+impl ImageCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// 網址對應的快取目錄
+    fn entry_dir(&self, image_url: &str) -> PathBuf {
+        self.dir
+            .join(hex::encode(Sha256::digest(image_url.as_bytes())))
+    }
+
+    /// 讀取快取的圖片，沒有快取或讀取失敗時回傳 None
+    pub async fn get(&self, image_url: &str) -> Option<StickerImage> {
+        let mut entries = tokio::fs::read_dir(self.entry_dir(image_url)).await.ok()?;
+        let entry = entries.next_entry().await.ok()??;
+        let data = tokio::fs::read(entry.path()).await.ok()?;
+        Some(StickerImage {
+            filename: entry.file_name().to_string_lossy().into_owned(),
+            data,
+        })
+    }
+
+    /// 寫入快取，已有快取時覆蓋
+    pub async fn put(&self, image_url: &str, image: &StickerImage) -> Result<()> {
+        let dir = self.entry_dir(image_url);
+        // 清掉舊檔，避免同一個網址留下兩個不同檔名的檔案
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .with_context(|| format!("無法建立貼圖快取目錄: {}", dir.display()))?;
+        // 檔名來自網址，只取最後一段避免寫到目錄外
+        let filename = std::path::Path::new(&image.filename)
+            .file_name()
+            .map(|name| name.to_os_string())
+            .unwrap_or_else(|| "sticker".into());
+        let path = dir.join(filename);
+        tokio::fs::write(&path, &image.data)
+            .await
+            .with_context(|| format!("無法寫入貼圖快取: {}", path.display()))
+    }
+
+    /// 取得貼圖圖片：有快取時直接使用，否則下載並寫入快取（寫入失敗不影響回傳）
+    pub async fn fetch(&self, image_url: &str) -> Result<StickerImage> {
+        if let Some(image) = self.get(image_url).await {
+            return Ok(image);
+        }
+        let image = download_sticker_image(image_url).await?;
+        if let Err(e) = self.put(image_url, &image).await {
+            warn!("寫入貼圖快取失敗: {:#}", e);
+        }
+        Ok(image)
+    }
+
+    /// 預先下載最近最常使用的 `count` 張貼圖，回傳新下載的數量。
+    /// 已有快取的略過，個別下載失敗只記錄警告
+    pub async fn prewarm(&self, database: &Database, count: usize) -> Result<usize> {
+        let since = Utc::now() - chrono::Duration::days(PREWARM_USAGE_DAYS);
+        let trending = database
+            .get_trending_stickers(since, count as i64)
+            .await
+            .context("取得貼圖使用統計失敗")?;
+
+        let mut fetched = 0;
+        for (sticker, _) in trending {
+            if self.get(&sticker.image_url).await.is_some() {
+                continue;
+            }
+            match self.fetch(&sticker.image_url).await {
+                Ok(_) => fetched += 1,
+                Err(e) => warn!("預先下載貼圖「{}」失敗: {:#}", sticker.name, e),
+            }
+        }
+        Ok(fetched)
+    }
+}
+
+/// 在背景預先下載常用貼圖，不延後啟動
+pub fn spawn_prewarm(cache: ImageCache, database: Database, count: usize) {
+    tokio::spawn(async move {
+        match cache.prewarm(&database, count).await {
+            Ok(fetched) => info!("已預先下載 {} 張常用貼圖到快取", fetched),
+            Err(e) => warn!("預先下載常用貼圖失敗: {:#}", e),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::utils::setup_db;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_prewarm_caches_trending_stickers() {
+        let mut server = mockito::Server::new_async().await;
+        let download = server
+            .mock("GET", "/cat.png")
+            .with_status(200)
+            .with_header("content-type", "image/png")
+            .with_body("PNGDATA")
+            .expect(1)
+            .create_async()
+            .await;
+
+        let db = setup_db().await;
+        let image_url = format!("{}/cat.png", server.url());
+        db.bulk_insert_stickers(&[crate::sticker::Sticker {
+            name: "cat".to_string(),
+            image_url: image_url.clone(),
+            category: "animals".to_string(),
+        }])
+        .await
+        .unwrap();
+        db.record_sticker_usage(&image_url, "u1").await.unwrap();
+
+        let temp_dir = TempDir::new().unwrap();
+        let cache = ImageCache::new(temp_dir.path());
+        assert_eq!(cache.prewarm(&db, 10).await.unwrap(), 1);
+        // 已有快取時不再下載
+        assert_eq!(cache.prewarm(&db, 10).await.unwrap(), 0);
+
+        let image = cache.fetch(&image_url).await.unwrap();
+        assert_eq!(image.filename, "cat.png");
+        assert_eq!(image.data, b"PNGDATA");
+        download.assert_async().await;
+    }
+}
@@ -21,6 +21,11 @@ pub mod utils {
             items: [("apple".to_string(), Decimal::new(1000, 2))]
                 .into_iter()
                 .collect(),
+            item_icons: std::collections::HashMap::new(),
+            item_sections: std::collections::HashMap::new(),
+            item_translations: std::collections::HashMap::new(),
+            order_fields: Vec::new(),
+            currency: crate::database::DEFAULT_CURRENCY.to_string(),
             status: GroupBuyStatus::Active,
             version,
             created_at: Utc::now(),
@@ -40,6 +45,7 @@ pub mod utils {
             quantity: 2,
             original_quantity: None,
             unit_price: Decimal::new(1000, 2),
+            custom_fields: Default::default(),
             created_at: Utc::now(),
         }
     }
@@ -9,7 +9,9 @@ use tokio_tungstenite::{connect_async, tungstenite::Message};
 use tracing::{debug, error, info, warn};
 
 use crate::AppState;
-use crate::mattermost::Post;
+use crate::database::{ApiTokenScope, Database, StickerSourceRecord, StickerSourceStatus};
+use crate::mattermost::{MattermostClient, Post};
+use crate::sticker::{Sticker, StickerDatabase};
 
 /// WebSocket 事件類型
 #[derive(Debug, Deserialize)]
@@ -76,8 +78,14 @@ struct PostData {
     user_id: Option<String>,
     #[serde(default)]
     message: Option<String>,
+    /// 附加的檔案
+    #[serde(default)]
+    file_ids: Vec<String>,
 }
 
+/// `features.websocket` 的檢查間隔：停用時多久確認一次是否重新啟用，連線中多久確認一次是否被停用
+const FEATURE_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
 /// 啟動 WebSocket 客戶端
 pub async fn start_websocket(state: Arc<RwLock<AppState>>) -> Result<()> {
     let app_state = state.read().await;
@@ -93,7 +101,18 @@ pub async fn start_websocket(state: Arc<RwLock<AppState>>) -> Result<()> {
 
     info!("正在連接到 Mattermost WebSocket: {}", ws_url);
 
+    let mut disabled_logged = false;
     loop {
+        if !state.read().await.config.features.websocket {
+            if !disabled_logged {
+                info!("WebSocket 已在 features 設定中停用，暫不連線");
+                disabled_logged = true;
+            }
+            tokio::time::sleep(FEATURE_CHECK_INTERVAL).await;
+            continue;
+        }
+        disabled_logged = false;
+
         match connect_and_handle(&ws_url, &bot_token, state.clone()).await {
             Ok(_) => {
                 info!("WebSocket 連接正常關閉");
@@ -136,9 +155,25 @@ async fn connect_and_handle(
         .context("發送認證訊息失敗")?;
 
     info!("已發送 WebSocket 認證請求");
+    let metrics = state.read().await.metrics.clone();
+    metrics.set_websocket_connected(true);
 
-    // 處理接收到的訊息
-    while let Some(msg) = read.next().await {
+    // 處理接收到的訊息；定期確認功能是否被停用
+    let mut feature_check = tokio::time::interval(FEATURE_CHECK_INTERVAL);
+    loop {
+        let msg = tokio::select! {
+            msg = read.next() => msg,
+            _ = feature_check.tick() => {
+                if !state.read().await.config.features.websocket {
+                    info!("WebSocket 已在 features 設定中停用，中斷連線");
+                    break;
+                }
+                continue;
+            }
+        };
+        let Some(msg) = msg else {
+            break;
+        };
         match msg {
             Ok(Message::Text(text)) => {
                 debug!("收到 WebSocket 訊息: {}", text);
@@ -166,6 +201,7 @@ async fn connect_and_handle(
             }
         }
     }
+    metrics.set_websocket_connected(false);
 
     Ok(())
 }
@@ -200,7 +236,13 @@ async fn handle_websocket_message(text: &str, state: Arc<RwLock<AppState>>) -> R
         "posted" => {
             handle_posted_event(&event.data, state).await?;
         }
-        "status_change" | "typing" | "user_updated" => {
+        "reaction_added" => {
+            handle_reaction_added_event(&event.data, state).await?;
+        }
+        "user_updated" => {
+            handle_user_updated_event(&event.data, state).await?;
+        }
+        "status_change" | "typing" => {
             // 忽略這些常見事件
         }
         _ => {
@@ -212,6 +254,39 @@ async fn handle_websocket_message(text: &str, state: Arc<RwLock<AppState>>) -> R
     Ok(())
 }
 
+/// 貼文上的表情回應，交給團購的快速登記處理
+async fn handle_reaction_added_event(
+    data: &serde_json::Value,
+    state: Arc<RwLock<AppState>>,
+) -> Result<()> {
+    let reaction_json = data
+        .get("reaction")
+        .and_then(|v| v.as_str())
+        .unwrap_or("{}");
+    let reaction: crate::handlers::Reaction =
+        serde_json::from_str(reaction_json).context("解析 reaction 資料失敗")?;
+    crate::handlers::handle_reaction_added(&*state.read().await, &reaction).await;
+    Ok(())
+}
+
+/// 使用者資料更新（包含停用與恢復帳號），更新團購貼文上購買人的顯示
+async fn handle_user_updated_event(
+    data: &serde_json::Value,
+    state: Arc<RwLock<AppState>>,
+) -> Result<()> {
+    let Some(user_json) = data.get("user") else {
+        return Ok(());
+    };
+    let user: crate::mattermost::User =
+        serde_json::from_value(user_json.clone()).context("解析 user 資料失敗")?;
+    let state_guard = state.read().await;
+    if !state_guard.config.features.group_buy {
+        return Ok(());
+    }
+    crate::handlers::update_user_status(&state_guard, &user.id, user.is_deactivated()).await;
+    Ok(())
+}
+
 async fn handle_posted_event(data: &serde_json::Value, state: Arc<RwLock<AppState>>) -> Result<()> {
     // 解析事件資料
     let event_data: PostedEventData =
@@ -223,6 +298,11 @@ async fn handle_posted_event(data: &serde_json::Value, state: Arc<RwLock<AppStat
         return Ok(());
     }
 
+    if !state.read().await.config.features.dm_admin {
+        debug!("管理員私訊指令已停用，忽略私訊");
+        return Ok(());
+    }
+
     // 解析 post 資料
     let post_json = event_data.post.as_deref().unwrap_or("{}");
     let post: PostData = serde_json::from_str(post_json).context("解析 post 資料失敗")?;
@@ -299,20 +379,10 @@ async fn handle_posted_event(data: &serde_json::Value, state: Arc<RwLock<AppStat
         "status" | "狀態" => {
             // 顯示狀態
             let sticker_db = app_state.sticker_database.clone();
+            let database = app_state.database.clone();
             let admin_count = app_state.config.admin.len();
             drop(app_state);
-            let sticker_count = match sticker_db.count().await {
-                Ok(c) => c,
-                Err(e) => {
-                    warn!("無法取得貼圖數量: {}", e);
-                    0
-                }
-            };
-
-            format!(
-                "### ℹ️ Bot 狀態\n\n- **貼圖數量**: {} 張\n- **管理員數量**: {} 人\n- **狀態**: 🟢 運行中",
-                sticker_count, admin_count
-            )
+            format_status(&collect_status(&sticker_db, &database, admin_count).await)
         }
         "reload" => {
             // 重新載入配置
@@ -330,6 +400,71 @@ async fn handle_posted_event(data: &serde_json::Value, state: Arc<RwLock<AppStat
             drop(app_state);
             handle_sticker_stats(state.clone()).await
         }
+        "add" | "新增" => {
+            // 新增貼圖
+            let sticker_db = app_state.sticker_database.clone();
+            let client = app_state.mattermost_client.clone();
+            drop(app_state);
+            handle_add_command(&sticker_db, &client, &parts[1..], &post.file_ids, &username).await
+        }
+        "remove" | "刪除" => {
+            // 刪除貼圖，需要再次確認
+            let sticker_db = app_state.sticker_database.clone();
+            drop(app_state);
+            handle_remove_command(&sticker_db, &parts[1..], &username).await
+        }
+        "rename" | "改名" => {
+            // 修改貼圖名稱
+            let sticker_db = app_state.sticker_database.clone();
+            drop(app_state);
+            handle_rename_command(&sticker_db, &parts[1..], &username).await
+        }
+        "stats" | "統計" => {
+            // 使用統計
+            let database = app_state.database.clone();
+            let client = app_state.mattermost_client.clone();
+            drop(app_state);
+            handle_stats_command(&database, &client, &parts[1..]).await
+        }
+        "perf" | "效能" => {
+            // 最近一小時的延遲統計
+            let summary = app_state.metrics.summary();
+            drop(app_state);
+            format_perf_summary(&summary)
+        }
+        "db" => {
+            // 資料庫維護，在背景執行並於完成時通知
+            let database = app_state.database.clone();
+            let client = app_state.mattermost_client.clone();
+            drop(app_state);
+            handle_db_command(database, client, channel_id.to_string(), &parts[1..]).await
+        }
+        "selftest" | "自我測試" => {
+            // 在沙盒頻道實際呼叫 API，檢查 token 擁有的權限
+            let client = app_state.mattermost_client.clone();
+            let sandbox = app_state.config.mattermost.sandbox_channel_id.clone();
+            drop(app_state);
+            match sandbox {
+                Some(sandbox) => {
+                    let results = run_selftest(&client, &sandbox, user_id).await;
+                    format_selftest(&sandbox, &results)
+                }
+                None => {
+                    "⚠️ 尚未設定 `mattermost.sandbox_channel_id`，無法執行自我測試。".to_string()
+                }
+            }
+        }
+        "gb" => {
+            // 團購維護
+            drop(app_state);
+            handle_gb_command(state.clone(), &parts[1..]).await
+        }
+        "token" => {
+            // 管理 API token
+            let database = app_state.database.clone();
+            drop(app_state);
+            handle_token_command(&database, &parts[1..], &username).await
+        }
         _ => {
             // 未知指令
             drop(app_state);
@@ -337,6 +472,11 @@ async fn handle_posted_event(data: &serde_json::Value, state: Arc<RwLock<AppStat
         }
     };
 
+    // 已由指令自行回覆
+    if response_message.is_empty() {
+        return Ok(());
+    }
+
     // 重新獲取 app_state 來發送回應
     let app_state = state.read().await;
 
@@ -372,12 +512,28 @@ fn get_help_message() -> String {
 - **`ping`** - 測試 bot 連線狀態
 - **`status`** / **`狀態`** - 顯示 bot 運行狀態
 - **`sticker`** / **`stickers`** / **`貼圖`** - 顯示貼圖庫統計資訊
+- **`add sticker <分類> <名稱> <圖片網址>`** - 新增一張貼圖（不需修改配置檔）
+- **`add stickers <分類>`** - 附上 CSV／JSON 檔案，批次新增貼圖到分類
+- **`remove sticker <hash>`** - 刪除貼圖（hash 為貼圖名稱後的八碼，會先要求確認）
+- **`rename sticker <hash> <新名稱>`** - 修改貼圖名稱
+- **`stats stickers [天數] [數量]`** - 顯示最近幾天（預設 7 天）最常發送的貼圖、各分類與各使用者的發送次數
 - **`reload`** - 重新載入配置（貼圖、管理員等）
+- **`perf`** / **`效能`** - 顯示最近一小時各項操作的延遲（p50/p95）
+- **`db check`** - 檢查資料庫完整性（integrity_check）
+- **`db vacuum`** - 重整資料庫檔案（VACUUM）
+- **`selftest`** / **`自我測試`** - 在沙盒頻道測試發文、編輯、刪除等 API，回報 token 的實際權限
+- **`gb repair <頻道 ID>`** - 掃描頻道最近的訊息，找回團購貼文並補上遺失的 post_id 與按鈕
+- **`token create <名稱> <read-only|sticker-admin|gb-admin>`** - 建立管理 API token
+- **`token list`** - 列出所有 API token
+- **`token revoke <id>`** - 撤銷 API token
+- **`token logs <id>`** - 查看 API token 最近的請求紀錄
 
 #### 提示：
 
 - 這些指令只能由管理員在 Direct Message 中使用
 - `reload` 指令會重新讀取配置檔案，但不會影響 Mattermost 連線
+- API token 只會在建立時顯示一次，請妥善保存
+- 以 `add` 新增的貼圖存在資料庫，`reload` 後仍會保留；批次檔案的格式與配置的檔案來源相同
 - 更多功能正在開發中...
 
 ---
@@ -386,8 +542,114 @@ fn get_help_message() -> String {
     .to_string()
 }
 
+/// selftest 單一項目的結果
+#[derive(Debug, PartialEq)]
+enum CheckOutcome {
+    Passed,
+    Failed(String),
+    Skipped(&'static str),
+}
+
+impl CheckOutcome {
+    fn from_result<T>(result: &Result<T>) -> Self {
+        match result {
+            Ok(_) => CheckOutcome::Passed,
+            Err(e) => CheckOutcome::Failed(format!("{:#}", e)),
+        }
+    }
+}
+
+/// 依序在沙盒頻道執行核心 API 呼叫，後面的項目依賴前面建立的貼文
+async fn run_selftest(
+    client: &MattermostClient,
+    sandbox_channel_id: &str,
+    user_id: &str,
+) -> Vec<(&'static str, CheckOutcome)> {
+    let mut results = Vec::new();
+
+    results.push((
+        "讀取 bot 帳號",
+        CheckOutcome::from_result(&client.get_me().await),
+    ));
+    results.push((
+        "讀取使用者",
+        CheckOutcome::from_result(&client.get_user(user_id).await),
+    ));
+    results.push((
+        "讀取沙盒頻道",
+        CheckOutcome::from_result(&client.get_channel(sandbox_channel_id).await),
+    ));
+
+    let post = Post {
+        id: None,
+        channel_id: sandbox_channel_id.to_string(),
+        message: "🧪 selftest：發文".to_string(),
+        root_id: None,
+        props: None,
+    };
+    let created = client.create_post_with_response(&post).await;
+    results.push(("發文", CheckOutcome::from_result(&created)));
+
+    match &created {
+        Ok(post_id) => {
+            let updated = client.update_post(post_id, "🧪 selftest：編輯", None).await;
+            results.push(("編輯貼文", CheckOutcome::from_result(&updated)));
+            let deleted = client.delete_post(post_id).await;
+            results.push(("刪除貼文", CheckOutcome::from_result(&deleted)));
+        }
+        Err(_) => {
+            results.push(("編輯貼文", CheckOutcome::Skipped("發文失敗")));
+            results.push(("刪除貼文", CheckOutcome::Skipped("發文失敗")));
+        }
+    }
+
+    let ephemeral = client
+        .send_ephemeral_post(sandbox_channel_id, user_id, "🧪 selftest：臨時訊息", None)
+        .await;
+    results.push(("臨時訊息", CheckOutcome::from_result(&ephemeral)));
+
+    // 開啟對話框需要使用者操作產生的 trigger_id，無法由 bot 自行觸發
+    results.push((
+        "開啟對話框",
+        CheckOutcome::Skipped("需要使用者觸發的 trigger_id"),
+    ));
+
+    results
+}
+
+fn format_selftest(sandbox_channel_id: &str, results: &[(&'static str, CheckOutcome)]) -> String {
+    let mut message = format!(
+        "### 🧪 自我測試結果\n\n沙盒頻道：`{}`\n\n| 項目 | 結果 |\n|------|------|\n",
+        sandbox_channel_id
+    );
+    for (name, outcome) in results {
+        let outcome = match outcome {
+            CheckOutcome::Passed => "✅ 通過".to_string(),
+            CheckOutcome::Failed(e) => {
+                format!("❌ 失敗：{}", e.replace('|', "\\|").replace('\n', " "))
+            }
+            CheckOutcome::Skipped(reason) => format!("⏭️ 略過（{}）", reason),
+        };
+        message.push_str(&format!("| {} | {} |\n", name, outcome));
+    }
+
+    let failed = results
+        .iter()
+        .filter(|(_, o)| matches!(o, CheckOutcome::Failed(_)))
+        .count();
+    if failed == 0 {
+        message.push_str("\n🟢 token 擁有所有已測試的權限");
+    } else {
+        message.push_str(&format!(
+            "\n🔴 {} 個項目失敗，請檢查 bot 帳號權限與頻道成員身分",
+            failed
+        ));
+    }
+    message
+}
+
 /// 處理重新載入配置
-async fn handle_reload_config(state: Arc<RwLock<AppState>>) -> Result<String> {
+pub(crate) async fn handle_reload_config(state: Arc<RwLock<AppState>>) -> Result<String> {
     info!("開始重新載入配置...");
 
     let mut app_state = state.write().await;
@@ -425,9 +687,26 @@ async fn handle_reload_config(state: Arc<RwLock<AppState>>) -> Result<String> {
         info!("未設定管理員");
     }
 
+    // 錯誤回報的目的地有變更時重新建立送出的 task
+    if app_state.config.error_reporting != new_config.error_reporting {
+        app_state
+            .error_reporter
+            .configure(&new_config.error_reporting)
+            .context("重新設定錯誤回報失敗")?;
+        info!("錯誤回報設定已更新");
+    }
+
     // 更新狀態（保留 mattermost_client 和 bot_user_id）
     app_state.config.stickers = new_config.stickers;
     app_state.config.admin = new_config.admin;
+    app_state.config.error_reporting = new_config.error_reporting;
+    app_state.config.logging.routes = new_config.logging.routes;
+    if app_state.config.features != new_config.features {
+        info!("功能開關已更新: {:?}", new_config.features);
+    }
+    app_state.config.features = new_config.features;
+    app_state.config.webhooks = new_config.webhooks;
+    app_state.config.feeds = new_config.feeds;
     app_state.sticker_database = new_sticker_database;
 
     info!("配置重新載入完成");
@@ -440,6 +719,715 @@ async fn handle_reload_config(state: Arc<RwLock<AppState>>) -> Result<String> {
     ))
 }
 
+/// 處理資料庫維護指令。
+/// 維護作業可能需要數分鐘，先回覆開始訊息，再於背景執行並在完成時通知；回傳空字串代表已自行回覆。
+async fn handle_db_command(
+    database: Database,
+    client: MattermostClient,
+    channel_id: String,
+    args: &[&str],
+) -> String {
+    let operation = match args {
+        ["vacuum"] => "VACUUM",
+        ["check"] => "integrity_check",
+        _ => return "用法：`db check`、`db vacuum`".to_string(),
+    };
+
+    let reply = move |message: String| {
+        let client = client.clone();
+        let channel_id = channel_id.clone();
+        async move {
+            let post = Post {
+                id: None,
+                channel_id,
+                message,
+                root_id: None,
+                props: None,
+            };
+            if let Err(e) = client.create_post(&post).await {
+                error!("發送資料庫維護訊息失敗: {}", e);
+            }
+        }
+    };
+
+    reply(format!("⏳ 開始執行 `{}`，完成後會通知…", operation)).await;
+
+    tokio::spawn(async move {
+        let started = std::time::Instant::now();
+        let size_before = database.database_size().await.ok();
+
+        let message = if operation == "VACUUM" {
+            match database.vacuum().await {
+                Ok(()) => {
+                    let size_after = database.database_size().await.ok();
+                    format!(
+                        "### ✅ VACUUM 完成\n\n- **耗時**: {:.1} 秒\n- **大小**: {} → {}",
+                        started.elapsed().as_secs_f64(),
+                        format_size(size_before),
+                        format_size(size_after)
+                    )
+                }
+                Err(e) => {
+                    error!("VACUUM 失敗: {}", e);
+                    format!("❌ VACUUM 失敗: {}", e)
+                }
+            }
+        } else {
+            match database.integrity_check().await {
+                Ok(problems) if problems == ["ok"] => format!(
+                    "### ✅ 資料庫完整性檢查通過\n\n- **耗時**: {:.1} 秒\n- **大小**: {}",
+                    started.elapsed().as_secs_f64(),
+                    format_size(size_before)
+                ),
+                Ok(problems) => {
+                    warn!("資料庫完整性檢查發現 {} 個問題", problems.len());
+                    let mut message =
+                        format!("### ⚠️ 資料庫完整性檢查發現 {} 個問題\n\n", problems.len());
+                    for problem in problems.iter().take(20) {
+                        message.push_str(&format!("- `{}`\n", problem));
+                    }
+                    if problems.len() > 20 {
+                        message.push_str(&format!("- …還有 {} 個\n", problems.len() - 20));
+                    }
+                    message
+                }
+                Err(e) => {
+                    error!("資料庫完整性檢查失敗: {}", e);
+                    format!("❌ 資料庫完整性檢查失敗: {}", e)
+                }
+            }
+        };
+
+        info!(
+            "資料庫維護 {} 結束，耗時 {:?}",
+            operation,
+            started.elapsed()
+        );
+        reply(message).await;
+    });
+
+    String::new()
+}
+
+fn format_size(bytes: Option<i64>) -> String {
+    match bytes {
+        Some(b) if b >= 1024 * 1024 => format!("{:.1} MB", b as f64 / 1024.0 / 1024.0),
+        Some(b) => format!("{:.1} KB", b as f64 / 1024.0),
+        None => "未知".to_string(),
+    }
+}
+
+/// DM `status` 指令顯示的內容
+struct BotStatus {
+    sticker_count: i64,
+    admin_count: usize,
+    db_size: Option<i64>,
+    pool: crate::database::PoolStatus,
+    websocket_uptime: Option<std::time::Duration>,
+    last_source_refresh: Option<chrono::DateTime<chrono::Utc>>,
+    group_buys: Vec<(crate::database::GroupBuyStatus, i64)>,
+    error_count: u64,
+}
+
+/// 從資料庫與延遲統計收集狀態；個別項目失敗時記錄警告並以預設值顯示
+async fn collect_status(
+    sticker_db: &crate::sticker::StickerDatabase,
+    database: &Database,
+    admin_count: usize,
+) -> BotStatus {
+    let sticker_count = sticker_db.count().await.unwrap_or_else(|e| {
+        warn!("無法取得貼圖數量: {}", e);
+        0
+    });
+    let db_size = database
+        .database_size()
+        .await
+        .inspect_err(|e| warn!("無法取得資料庫大小: {}", e))
+        .ok();
+    let group_buys = database
+        .count_group_buys_by_status()
+        .await
+        .unwrap_or_else(|e| {
+            warn!("無法取得團購數量: {}", e);
+            Vec::new()
+        });
+    let metrics = database.metrics();
+
+    BotStatus {
+        sticker_count,
+        admin_count,
+        db_size,
+        pool: database.pool_status(),
+        websocket_uptime: metrics.websocket_uptime(),
+        last_source_refresh: metrics.last_source_refresh(),
+        group_buys,
+        error_count: metrics.error_count(),
+    }
+}
+
+/// 產生狀態訊息
+fn format_status(status: &BotStatus) -> String {
+    let websocket = match status.websocket_uptime {
+        Some(uptime) => format!("🟢 已連線 {}", format_duration(uptime)),
+        None => "🔴 未連線".to_string(),
+    };
+    let last_refresh = status
+        .last_source_refresh
+        .map(|at| {
+            at.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_else(|| "尚未載入".to_string());
+    let group_buys = if status.group_buys.is_empty() {
+        "0 個".to_string()
+    } else {
+        status
+            .group_buys
+            .iter()
+            .map(|(s, count)| format!("{} {} 個", s.label(), count))
+            .collect::<Vec<_>>()
+            .join("、")
+    };
+
+    format!(
+        "### ℹ️ Bot 狀態\n\n\
+         - **貼圖數量**: {} 張\n\
+         - **管理員數量**: {} 人\n\
+         - **資料庫大小**: {}\n\
+         - **連接池**: {}/{} 使用中（閒置 {}）\n\
+         - **WebSocket**: {}\n\
+         - **貼圖來源更新**: {}\n\
+         - **團購**: {}\n\
+         - **啟動後錯誤**: {} 次\n\
+         - **狀態**: 🟢 運行中",
+        status.sticker_count,
+        status.admin_count,
+        format_size(status.db_size),
+        status.pool.size.saturating_sub(status.pool.idle),
+        status.pool.max,
+        status.pool.idle,
+        websocket,
+        last_refresh,
+        group_buys,
+        status.error_count
+    )
+}
+
+/// 將時間長度格式化為「1 天 2 小時」「3 小時 4 分」「5 分」
+fn format_duration(duration: std::time::Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    let (days, hours, minutes) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+    if days > 0 {
+        format!("{} 天 {} 小時", days, hours)
+    } else if hours > 0 {
+        format!("{} 小時 {} 分", hours, minutes)
+    } else {
+        format!("{} 分", minutes)
+    }
+}
+
+/// 產生延遲統計訊息
+fn format_perf_summary(summary: &[crate::metrics::LatencySummary]) -> String {
+    if summary.is_empty() {
+        return "最近一小時沒有任何延遲紀錄。".to_string();
+    }
+
+    let mut message = String::from("### ⏱️ 最近一小時延遲統計\n\n");
+    message.push_str("| 類型 | 名稱 | 次數 | p50 | p95 |\n");
+    message.push_str("|---|---|---:|---:|---:|\n");
+    for s in summary {
+        message.push_str(&format!(
+            "| {} | `{}` | {} | {:.1} ms | {:.1} ms |\n",
+            s.kind, s.name, s.count, s.p50_ms, s.p95_ms
+        ));
+    }
+    message
+}
+
+/// 處理團購維護指令
+async fn handle_gb_command(state: Arc<RwLock<AppState>>, args: &[&str]) -> String {
+    let ["repair", channel_id] = args else {
+        return "用法：`gb repair <頻道 ID>`".to_string();
+    };
+
+    let app_state = state.read().await;
+    match crate::handlers::repair_channel(&app_state, channel_id).await {
+        Ok(report) => crate::handlers::format_repair_report(channel_id, &report),
+        Err(e) => {
+            error!("修復團購貼文失敗: {}", e);
+            format!("❌ 修復團購貼文失敗: {}", e)
+        }
+    }
+}
+
+/// 處理 API token 管理指令
+async fn handle_token_command(database: &Database, args: &[&str], username: &str) -> String {
+    const USAGE: &str = "用法：`token create <名稱> <read-only|sticker-admin|gb-admin>`、`token list`、`token revoke <id>`、`token logs <id>`";
+
+    match args {
+        ["create", name, scope] => {
+            let Some(scope) = ApiTokenScope::from_string(scope) else {
+                return format!("❌ 未知的權限範圍: `{}`\n\n{}", scope, USAGE);
+            };
+            match database.create_api_token(name, scope, username).await {
+                Ok((token, secret)) => {
+                    info!(
+                        "{} 建立了 API token {} ({})",
+                        username, token.id, token.scope
+                    );
+                    format!(
+                        "### 🔑 API token 已建立\n\n- **ID**: `{}`\n- **名稱**: {}\n- **權限**: `{}`\n\n```\n{}\n```\n\n⚠️ Token 只會顯示這一次，請妥善保存。使用時以 `Authorization: Bearer <token>` header 傳送。",
+                        token.id, token.name, token.scope, secret
+                    )
+                }
+                Err(e) => {
+                    error!("建立 API token 失敗: {}", e);
+                    format!("❌ 建立 API token 失敗: {}", e)
+                }
+            }
+        }
+        ["list"] => match database.list_api_tokens().await {
+            Ok(tokens) if tokens.is_empty() => "目前沒有任何 API token。".to_string(),
+            Ok(tokens) => {
+                let mut message = String::from("### 🔑 API token 列表\n\n");
+                message.push_str("| ID | 名稱 | 權限 | 建立者 | 建立時間 | 最後使用 | 狀態 |\n");
+                message.push_str("|---|---|---|---|---|---|---|\n");
+                for token in tokens {
+                    let last_used = token
+                        .last_used_at
+                        .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                        .unwrap_or_else(|| "-".to_string());
+                    let status = match token.revoked_at {
+                        Some(t) => format!("已撤銷（{}）", t.format("%Y-%m-%d %H:%M")),
+                        None => "使用中".to_string(),
+                    };
+                    message.push_str(&format!(
+                        "| `{}` | {} | `{}` | {} | {} | {} | {} |\n",
+                        token.id,
+                        token.name,
+                        token.scope,
+                        token.created_by,
+                        token.created_at.format("%Y-%m-%d %H:%M"),
+                        last_used,
+                        status
+                    ));
+                }
+                message
+            }
+            Err(e) => {
+                error!("取得 API token 列表失敗: {}", e);
+                format!("❌ 取得 API token 列表失敗: {}", e)
+            }
+        },
+        ["revoke", id] => match database.revoke_api_token(id).await {
+            Ok(true) => {
+                info!("{} 撤銷了 API token {}", username, id);
+                format!("✅ 已撤銷 API token `{}`", id)
+            }
+            Ok(false) => format!("❓ 找不到使用中的 API token `{}`", id),
+            Err(e) => {
+                error!("撤銷 API token 失敗: {}", e);
+                format!("❌ 撤銷 API token 失敗: {}", e)
+            }
+        },
+        ["logs", id] => match database.get_api_token_logs(id, 20).await {
+            Ok(logs) if logs.is_empty() => format!("API token `{}` 沒有請求紀錄。", id),
+            Ok(logs) => {
+                let mut message = format!("### 📜 API token `{}` 最近的請求\n\n", id);
+                for log in logs {
+                    message.push_str(&format!(
+                        "- {} `{} {}` → {}\n",
+                        log.created_at.format("%Y-%m-%d %H:%M:%S"),
+                        log.method,
+                        log.path,
+                        log.status
+                    ));
+                }
+                message
+            }
+            Err(e) => {
+                error!("取得 API token 請求紀錄失敗: {}", e);
+                format!("❌ 取得 API token 請求紀錄失敗: {}", e)
+            }
+        },
+        _ => USAGE.to_string(),
+    }
+}
+
+/// `add stickers` 附加檔案的大小上限
+const MAX_STICKER_UPLOAD_BYTES: u64 = 5 * 1024 * 1024;
+
+/// 處理新增貼圖指令
+async fn handle_add_command(
+    sticker_db: &StickerDatabase,
+    client: &MattermostClient,
+    args: &[&str],
+    file_ids: &[String],
+    username: &str,
+) -> String {
+    const USAGE: &str = "用法：`add sticker <分類> <名稱> <圖片網址>`，或 `add stickers <分類>` 並附上 CSV／JSON 檔案";
+
+    match args {
+        ["sticker", category, name @ .., image_url] if !name.is_empty() => {
+            if !crate::sticker::is_valid_image_url(image_url) {
+                return format!("❌ 不是有效的圖片網址: `{}`", image_url);
+            }
+            let sticker = Sticker {
+                name: name.join(" "),
+                image_url: image_url.to_string(),
+                category: category.to_string(),
+            };
+            match sticker_db
+                .add_custom(std::slice::from_ref(&sticker), username)
+                .await
+            {
+                Ok(0) => format!("❓ 已經有使用這個網址的貼圖: {}", image_url),
+                Ok(_) => {
+                    info!("{} 新增了貼圖 {}", username, sticker.get_display_name());
+                    format!("✅ 已新增貼圖 {}", sticker.get_display_name())
+                }
+                Err(e) => {
+                    error!("新增貼圖失敗: {}", e);
+                    format!("❌ 新增貼圖失敗: {}", e)
+                }
+            }
+        }
+        ["stickers", category] => {
+            if file_ids.is_empty() {
+                return format!("❌ 請在訊息附上 CSV 或 JSON 檔案\n\n{}", USAGE);
+            }
+            let mut message = format!("### 📥 匯入貼圖到「{}」\n\n", category);
+            for file_id in file_ids {
+                let line = match import_sticker_file(
+                    sticker_db, client, file_id, category, username,
+                )
+                .await
+                {
+                    Ok((filename, report)) => format!("- `{}`：{}\n", filename, report),
+                    Err(e) => {
+                        error!("匯入貼圖檔案失敗: {:#}", e);
+                        format!("- ❌ {:#}\n", e)
+                    }
+                };
+                message.push_str(&line);
+            }
+            message
+        }
+        _ => USAGE.to_string(),
+    }
+}
+
+/// 下載私訊附加的貼圖檔案並新增其中的貼圖，回傳檔名與匯入結果
+async fn import_sticker_file(
+    sticker_db: &StickerDatabase,
+    client: &MattermostClient,
+    file_id: &str,
+    category: &str,
+    username: &str,
+) -> Result<(String, String)> {
+    let info = client.get_file_info(file_id).await?;
+    if info.size > MAX_STICKER_UPLOAD_BYTES {
+        anyhow::bail!("{} 超過 {} MB", info.name, MAX_STICKER_UPLOAD_BYTES >> 20);
+    }
+    let data = client.download_file(file_id).await?;
+    let content =
+        String::from_utf8(data).with_context(|| format!("{} 不是 UTF-8 文字檔", info.name))?;
+    let content = content.trim_start_matches('\u{feff}');
+
+    let (stickers, invalid): (Vec<Sticker>, Vec<Sticker>) = sticker_db
+        .load_upload(&info.name, content, category)?
+        .into_iter()
+        .partition(|s| crate::sticker::is_valid_image_url(&s.image_url));
+    let added = sticker_db.add_custom(&stickers, username).await?;
+    info!(
+        "{} 從 {} 匯入了 {} 張貼圖到 {}",
+        username, info.name, added, category
+    );
+
+    let mut report = format!("新增 {} 張", added);
+    if stickers.len() > added {
+        report.push_str(&format!("，{} 張網址已存在", stickers.len() - added));
+    }
+    if !invalid.is_empty() {
+        report.push_str(&format!("，{} 張網址無效", invalid.len()));
+    }
+    Ok((info.name, report))
+}
+
+/// 確認刪除的關鍵字
+const CONFIRM_KEYWORDS: [&str; 2] = ["confirm", "確認"];
+
+/// 以 hash 找出要修改的貼圖，找不到時回傳要回覆的訊息
+async fn find_sticker_for_edit(
+    sticker_db: &StickerDatabase,
+    hash: &str,
+) -> std::result::Result<Sticker, String> {
+    match sticker_db.find_by_hash(hash).await {
+        Ok(Some(sticker)) => Ok(sticker),
+        Ok(None) => Err(format!("❓ 找不到 hash 為 `{}` 的貼圖", hash)),
+        Err(e) => {
+            error!("取得貼圖失敗: {}", e);
+            Err(format!("❌ 取得貼圖失敗: {}", e))
+        }
+    }
+}
+
+/// 配置來源中的貼圖刪除或改名後，重新載入配置時會還原，回覆時提醒
+async fn config_sticker_note(sticker_db: &StickerDatabase, sticker: &Sticker) -> &'static str {
+    match sticker_db.is_custom(&sticker.image_url).await {
+        Ok(true) => "",
+        Ok(false) => {
+            "\n\n⚠️ 這張貼圖來自配置的貼圖來源，`reload` 或重新啟動後會還原，請一併修改來源。"
+        }
+        Err(e) => {
+            warn!("無法確認貼圖來源: {}", e);
+            ""
+        }
+    }
+}
+
+/// 處理刪除貼圖指令：第一次回覆貼圖預覽，加上 `confirm` 後才刪除
+async fn handle_remove_command(
+    sticker_db: &StickerDatabase,
+    args: &[&str],
+    username: &str,
+) -> String {
+    const USAGE: &str = "用法：`remove sticker <hash>`";
+
+    let (hash, confirmed) = match args {
+        ["sticker", hash] => (*hash, false),
+        ["sticker", hash, keyword] if CONFIRM_KEYWORDS.contains(keyword) => (*hash, true),
+        _ => return USAGE.to_string(),
+    };
+    let sticker = match find_sticker_for_edit(sticker_db, hash).await {
+        Ok(sticker) => sticker,
+        Err(message) => return message,
+    };
+
+    if !confirmed {
+        return format!(
+            "### 🗑️ 確認刪除貼圖\n\n{}\n\n![{}]({})\n\n確定要刪除請輸入 `remove sticker {} confirm`",
+            sticker.get_display_name(),
+            sticker.name,
+            sticker.image_url,
+            hash
+        );
+    }
+
+    let note = config_sticker_note(sticker_db, &sticker).await;
+    match sticker_db.remove(&sticker.image_url).await {
+        Ok(true) => {
+            info!("{} 刪除了貼圖 {}", username, sticker.get_display_name());
+            format!("✅ 已刪除貼圖 {}{}", sticker.get_display_name(), note)
+        }
+        Ok(false) => format!("❓ 找不到 hash 為 `{}` 的貼圖", hash),
+        Err(e) => {
+            error!("刪除貼圖失敗: {}", e);
+            format!("❌ 刪除貼圖失敗: {}", e)
+        }
+    }
+}
+
+/// 處理修改貼圖名稱指令
+async fn handle_rename_command(
+    sticker_db: &StickerDatabase,
+    args: &[&str],
+    username: &str,
+) -> String {
+    const USAGE: &str = "用法：`rename sticker <hash> <新名稱>`";
+
+    let ["sticker", hash, name @ ..] = args else {
+        return USAGE.to_string();
+    };
+    if name.is_empty() {
+        return USAGE.to_string();
+    }
+    let name = name.join(" ");
+    let sticker = match find_sticker_for_edit(sticker_db, hash).await {
+        Ok(sticker) => sticker,
+        Err(message) => return message,
+    };
+
+    let note = config_sticker_note(sticker_db, &sticker).await;
+    match sticker_db.rename(&sticker.image_url, &name).await {
+        Ok(true) => {
+            info!(
+                "{} 把貼圖 {} 改名為 {}",
+                username,
+                sticker.get_display_name(),
+                name
+            );
+            let renamed = Sticker { name, ..sticker };
+            format!("✅ 已改名為 {}{}", renamed.get_display_name(), note)
+        }
+        Ok(false) => format!("❓ 找不到 hash 為 `{}` 的貼圖", hash),
+        Err(e) => {
+            error!("修改貼圖名稱失敗: {}", e);
+            format!("❌ 修改貼圖名稱失敗: {}", e)
+        }
+    }
+}
+
+/// `stats stickers` 預設的統計天數
+const STICKER_STATS_DEFAULT_DAYS: i64 = 7;
+
+/// `stats stickers` 預設列出的貼圖與使用者數量
+const STICKER_STATS_DEFAULT_TOP: i64 = 10;
+
+/// `stats stickers` 的統計範圍
+#[derive(Debug, PartialEq)]
+struct StickerStatsQuery {
+    days: i64,
+    top: i64,
+}
+
+/// 解析 `stats stickers [天數] [數量]` 的參數
+fn parse_sticker_stats_args(args: &[&str]) -> Option<StickerStatsQuery> {
+    let parse = |arg: Option<&&str>, default: i64, max: i64| match arg {
+        None => Some(default),
+        Some(arg) => arg.parse().ok().filter(|n| (1..=max).contains(n)),
+    };
+    if args.len() > 2 {
+        return None;
+    }
+    Some(StickerStatsQuery {
+        days: parse(args.first(), STICKER_STATS_DEFAULT_DAYS, 365)?,
+        top: parse(args.get(1), STICKER_STATS_DEFAULT_TOP, 50)?,
+    })
+}
+
+/// 貼圖使用統計
+struct StickerUsageStats {
+    days: i64,
+    top: Vec<(Sticker, i64)>,
+    categories: Vec<(String, i64)>,
+    /// 使用者名稱與發送次數
+    users: Vec<(String, i64)>,
+}
+
+/// 處理使用統計指令
+async fn handle_stats_command(
+    database: &Database,
+    client: &MattermostClient,
+    args: &[&str],
+) -> String {
+    const USAGE: &str = "用法：`stats stickers [天數] [數量]`，預設統計最近 7 天、列出前 10 名（天數最多 365、數量最多 50）";
+
+    let ["stickers" | "sticker" | "貼圖", rest @ ..] = args else {
+        return USAGE.to_string();
+    };
+    let Some(query) = parse_sticker_stats_args(rest) else {
+        return USAGE.to_string();
+    };
+
+    let since = chrono::Utc::now() - chrono::Duration::days(query.days);
+    let result = async {
+        let top = database.get_trending_stickers(since, query.top).await?;
+        let categories = database.get_sticker_usage_by_category(since).await?;
+        let users = database.get_sticker_usage_by_user(since, query.top).await?;
+        anyhow::Ok((top, categories, users))
+    }
+    .await;
+    let (top, categories, users) = match result {
+        Ok(v) => v,
+        Err(e) => {
+            error!("查詢貼圖使用統計失敗: {}", e);
+            return format!("❌ 查詢貼圖使用統計失敗: {}", e);
+        }
+    };
+
+    // 以使用者名稱顯示，查詢失敗時顯示 user_id
+    let mut named_users = Vec::with_capacity(users.len());
+    for (user_id, uses) in users {
+        let name = match client.get_user(&user_id).await {
+            Ok(user) => format!("@{}", user.username),
+            Err(e) => {
+                warn!("無法取得使用者 {} 的資訊: {}", user_id, e);
+                format!("`{}`", user_id)
+            }
+        };
+        named_users.push((name, uses));
+    }
+
+    format_sticker_usage_stats(&StickerUsageStats {
+        days: query.days,
+        top,
+        categories,
+        users: named_users,
+    })
+}
+
+fn format_sticker_usage_stats(stats: &StickerUsageStats) -> String {
+    let mut message = format!("### 📈 貼圖使用統計（最近 {} 天）\n\n", stats.days);
+    if stats.top.is_empty() && stats.users.is_empty() {
+        message.push_str("⚠️ 這段期間沒有任何貼圖發送紀錄。\n");
+        return message;
+    }
+
+    message.push_str("#### 最常發送的貼圖\n\n");
+    for (i, (sticker, uses)) in stats.top.iter().enumerate() {
+        message.push_str(&format!(
+            "{}. **{}**（{}）：{} 次\n",
+            i + 1,
+            sticker.name,
+            sticker.category,
+            uses
+        ));
+    }
+
+    message.push_str("\n#### 各分類發送次數\n\n");
+    for (category, uses) in &stats.categories {
+        message.push_str(&format!("- **{}**：{} 次\n", category, uses));
+    }
+
+    message.push_str("\n#### 發送最多的使用者\n\n");
+    for (i, (user, uses)) in stats.users.iter().enumerate() {
+        message.push_str(&format!("{}. {}：{} 次\n", i + 1, user, uses));
+    }
+
+    message
+}
+
+/// HTTP 貼圖來源最後一次讀取的狀態，沒有 HTTP 來源時為空字串
+fn format_source_statuses(sources: &[StickerSourceRecord]) -> String {
+    if sources.is_empty() {
+        return String::new();
+    }
+    let time = |t: chrono::DateTime<chrono::Utc>| {
+        t.with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M")
+            .to_string()
+    };
+
+    let mut message = String::from("\n#### HTTP 來源：\n\n");
+    for source in sources {
+        let outcome = match source.status {
+            StickerSourceStatus::Updated => format!("✅ 已更新，{} 張", source.sticker_count),
+            StickerSourceStatus::NotModified => {
+                format!("⏸️ 沒有變更，{} 張", source.sticker_count)
+            }
+            StickerSourceStatus::Failed => format!(
+                "❌ 失敗：{}",
+                source.error.as_deref().unwrap_or("未知的錯誤")
+            ),
+        };
+        let changed = source
+            .changed_at
+            .map(|t| format!("，內容更新於 {}", time(t)))
+            .unwrap_or_default();
+        message.push_str(&format!(
+            "- **{}** `{}`：{}（檢查於 {}{}）\n",
+            source.category,
+            source.url,
+            outcome,
+            time(source.checked_at),
+            changed
+        ));
+    }
+    message
+}
+
 /// 處理貼圖統計資訊
 async fn handle_sticker_stats(state: Arc<RwLock<AppState>>) -> String {
     let app_state = state.read().await;
@@ -480,5 +1468,295 @@ async fn handle_sticker_stats(state: Arc<RwLock<AppState>>) -> String {
         }
     }
 
+    match sticker_db.source_statuses().await {
+        Ok(sources) => message.push_str(&format_source_statuses(&sources)),
+        Err(e) => warn!("無法取得貼圖來源狀態: {}", e),
+    }
+
     message
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{GroupBuyStatus, PoolStatus};
+    use std::time::Duration;
+
+    #[test]
+    fn test_format_status() {
+        let mut status = BotStatus {
+            sticker_count: 42,
+            admin_count: 2,
+            db_size: Some(3 * 1024 * 1024),
+            pool: PoolStatus {
+                size: 3,
+                idle: 2,
+                max: 5,
+            },
+            websocket_uptime: Some(Duration::from_secs(26 * 60 * 60 + 5 * 60)),
+            last_source_refresh: None,
+            group_buys: vec![(GroupBuyStatus::Active, 3), (GroupBuyStatus::Closed, 1)],
+            error_count: 7,
+        };
+
+        let message = format_status(&status);
+        assert!(message.contains("- **貼圖數量**: 42 張\n"));
+        assert!(message.contains("- **資料庫大小**: 3.0 MB\n"));
+        assert!(message.contains("- **連接池**: 1/5 使用中（閒置 2）\n"));
+        assert!(message.contains("- **WebSocket**: 🟢 已連線 1 天 2 小時\n"));
+        assert!(message.contains("- **貼圖來源更新**: 尚未載入\n"));
+        assert!(message.contains("- **團購**: 進行中 3 個、已截止 1 個\n"));
+        assert!(message.contains("- **啟動後錯誤**: 7 次\n"));
+
+        status.websocket_uptime = None;
+        status.group_buys.clear();
+        let message = format_status(&status);
+        assert!(message.contains("- **WebSocket**: 🔴 未連線\n"));
+        assert!(message.contains("- **團購**: 0 個\n"));
+    }
+
+    #[test]
+    fn test_parse_sticker_stats_args() {
+        assert_eq!(
+            parse_sticker_stats_args(&[]),
+            Some(StickerStatsQuery { days: 7, top: 10 })
+        );
+        assert_eq!(
+            parse_sticker_stats_args(&["30", "5"]),
+            Some(StickerStatsQuery { days: 30, top: 5 })
+        );
+        assert_eq!(parse_sticker_stats_args(&["0"]), None);
+        assert_eq!(parse_sticker_stats_args(&["7", "100"]), None);
+        assert_eq!(parse_sticker_stats_args(&["week"]), None);
+        assert_eq!(parse_sticker_stats_args(&["7", "5", "extra"]), None);
+    }
+
+    #[test]
+    fn test_format_sticker_usage_stats() {
+        let sticker = Sticker {
+            name: "cat".to_string(),
+            image_url: "https://example.com/cat.png".to_string(),
+            category: "animal".to_string(),
+        };
+        let message = format_sticker_usage_stats(&StickerUsageStats {
+            days: 7,
+            top: vec![(sticker, 3)],
+            categories: vec![("animal".to_string(), 3)],
+            users: vec![("@alice".to_string(), 2), ("`u2`".to_string(), 1)],
+        });
+        assert!(message.starts_with("### 📈 貼圖使用統計（最近 7 天）"));
+        assert!(message.contains("1. **cat**（animal）：3 次"));
+        assert!(message.contains("- **animal**：3 次"));
+        assert!(message.contains("2. `u2`：1 次"));
+
+        let empty = format_sticker_usage_stats(&StickerUsageStats {
+            days: 30,
+            top: Vec::new(),
+            categories: Vec::new(),
+            users: Vec::new(),
+        });
+        assert!(empty.contains("這段期間沒有任何貼圖發送紀錄"));
+    }
+
+    #[test]
+    fn test_format_source_statuses() {
+        assert_eq!(format_source_statuses(&[]), "");
+
+        let source = StickerSourceRecord {
+            category: "動物".to_string(),
+            url: "https://example.com/animals.csv".to_string(),
+            etag: None,
+            last_modified: None,
+            content: None,
+            status: StickerSourceStatus::NotModified,
+            sticker_count: 12,
+            error: None,
+            checked_at: chrono::Utc::now(),
+            changed_at: Some(chrono::Utc::now()),
+        };
+        let failed = StickerSourceRecord {
+            url: "https://example.com/broken.csv".to_string(),
+            status: StickerSourceStatus::Failed,
+            error: Some("HTTP 500".to_string()),
+            changed_at: None,
+            ..source.clone()
+        };
+        let message = format_source_statuses(&[source, failed]);
+        assert!(message.contains("#### HTTP 來源"));
+        assert!(
+            message.contains("- **動物** `https://example.com/animals.csv`：⏸️ 沒有變更，12 張")
+        );
+        assert!(message.contains("內容更新於"));
+        assert!(message.contains("`https://example.com/broken.csv`：❌ 失敗：HTTP 500（檢查於"));
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_secs(59)), "0 分");
+        assert_eq!(
+            format_duration(Duration::from_secs(3 * 3600 + 4 * 60)),
+            "3 小時 4 分"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_sticker_commands() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/api/v4/files/file-1/info")
+            .with_status(200)
+            .with_body(r#"{"id":"file-1","name":"animals.csv","extension":"csv","size":100}"#)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/api/v4/files/file-1")
+            .with_status(200)
+            .with_body(
+                "\u{feff}名稱,圖片\n貓,https://example.com/cat.png\n狗,https://example.com/dog.png\n壞掉,not-a-url\n",
+            )
+            .create_async()
+            .await;
+        let client = MattermostClient::new(server.url(), "test_token".to_string()).unwrap();
+        let database = crate::test_utils::utils::setup_db().await;
+        let sticker_db = StickerDatabase::new(database.clone());
+
+        let message = handle_add_command(
+            &sticker_db,
+            &client,
+            &["sticker", "動物", "胖", "貓", "https://example.com/cat.png"],
+            &[],
+            "admin",
+        )
+        .await;
+        assert!(message.starts_with("✅ 已新增貼圖 [動物] 胖 貓 ("));
+        assert!(
+            handle_add_command(
+                &sticker_db,
+                &client,
+                &["sticker", "動物", "貓", "cat.png"],
+                &[],
+                "admin"
+            )
+            .await
+            .starts_with("❌")
+        );
+
+        let message = handle_add_command(
+            &sticker_db,
+            &client,
+            &["stickers", "動物"],
+            &["file-1".to_string()],
+            "admin",
+        )
+        .await;
+        assert!(message.contains("- `animals.csv`：新增 1 張，1 張網址已存在，1 張網址無效\n"));
+        assert_eq!(database.count_stickers().await.unwrap(), 2);
+
+        assert!(
+            handle_add_command(&sticker_db, &client, &["stickers", "動物"], &[], "admin")
+                .await
+                .starts_with("❌ 請在訊息附上")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_remove_and_rename_sticker_commands() {
+        let database = crate::test_utils::utils::setup_db().await;
+        let sticker_db = StickerDatabase::new(database.clone());
+        let cat = Sticker {
+            name: "貓".to_string(),
+            image_url: "https://example.com/cat.png".to_string(),
+            category: "動物".to_string(),
+        };
+        database.replace_stickers(&[cat.clone()]).await.unwrap();
+        let hash = cat.get_url_hash();
+
+        let message = handle_rename_command(
+            &sticker_db,
+            &["sticker", hash.as_str(), "胖", "貓"],
+            "admin",
+        )
+        .await;
+        assert!(message.starts_with("✅ 已改名為 [動物] 胖 貓 ("));
+        // 來自配置的貼圖提醒重新載入後會還原
+        assert!(message.contains("`reload` 或重新啟動後會還原"));
+
+        // 沒有確認時只回覆預覽，不刪除
+        let message =
+            handle_remove_command(&sticker_db, &["sticker", hash.as_str()], "admin").await;
+        assert!(message.contains(&format!("`remove sticker {} confirm`", hash)));
+        assert_eq!(database.count_stickers().await.unwrap(), 1);
+
+        let message =
+            handle_remove_command(&sticker_db, &["sticker", hash.as_str(), "確認"], "admin").await;
+        assert!(message.starts_with("✅ 已刪除貼圖 [動物] 胖 貓"));
+        assert_eq!(database.count_stickers().await.unwrap(), 0);
+
+        assert!(
+            handle_remove_command(&sticker_db, &["sticker", hash.as_str(), "confirm"], "admin")
+                .await
+                .starts_with("❓ 找不到")
+        );
+        assert!(
+            handle_rename_command(&sticker_db, &["sticker", hash.as_str()], "admin")
+                .await
+                .starts_with("用法")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_selftest_reports_missing_permissions() {
+        let mut server = mockito::Server::new_async().await;
+        let _me = server
+            .mock("GET", "/api/v4/users/me")
+            .with_body(r#"{"id":"bot","username":"leko-bot"}"#)
+            .create_async()
+            .await;
+        let _user = server
+            .mock("GET", "/api/v4/users/u1")
+            .with_body(r#"{"id":"u1","username":"alice"}"#)
+            .create_async()
+            .await;
+        let _channel = server
+            .mock("GET", "/api/v4/channels/sandbox")
+            .with_body(r#"{"id":"sandbox","type":"O"}"#)
+            .create_async()
+            .await;
+        let _create = server
+            .mock("POST", "/api/v4/posts")
+            .with_status(201)
+            .with_body(r#"{"id":"p1"}"#)
+            .create_async()
+            .await;
+        let _update = server
+            .mock("PUT", "/api/v4/posts/p1")
+            .with_body(r#"{"id":"p1"}"#)
+            .create_async()
+            .await;
+        let _delete = server
+            .mock("DELETE", "/api/v4/posts/p1")
+            .with_status(403)
+            .with_body(r#"{"message":"permission denied"}"#)
+            .create_async()
+            .await;
+        let _ephemeral = server
+            .mock("POST", "/api/v4/posts/ephemeral")
+            .with_status(201)
+            .with_body("{}")
+            .create_async()
+            .await;
+
+        let client = MattermostClient::new(server.url(), "test_token".to_string()).unwrap();
+        let results = run_selftest(&client, "sandbox", "u1").await;
+
+        let outcome = |name: &str| &results.iter().find(|(n, _)| *n == name).unwrap().1;
+        assert_eq!(outcome("發文"), &CheckOutcome::Passed);
+        assert_eq!(outcome("編輯貼文"), &CheckOutcome::Passed);
+        assert!(matches!(outcome("刪除貼文"), CheckOutcome::Failed(_)));
+        assert_eq!(outcome("臨時訊息"), &CheckOutcome::Passed);
+        assert!(matches!(outcome("開啟對話框"), CheckOutcome::Skipped(_)));
+
+        let message = format_selftest("sandbox", &results);
+        assert!(message.contains("🔴 1 個項目失敗"));
+    }
+}